@@ -54,6 +54,136 @@ kind = "stderr"
 # prot = "tls" # or prot = "tcp"
 # host = "localhost"
 # port = 23456
+
+## Memory usage of the application, pollable under memory pressure
+# [[files]]
+# kind = "memory"
+
+## Append-only, hash-chained log in a host file
+# [[files]]
+# kind = "appendlog"
+# path = "/var/log/enarx/audit.log"
+
+## Read-only dataset in a host file, verified against its digest
+# [[files]]
+# kind = "dataset"
+# path = "/var/lib/enarx/embeddings.bin"
+# digest = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+
+## Resource limits
+# [limits]
+# module_size = 100000000
+# code_size = 200000000
+# tables = 16
+# table_elements = 100000
+# instances = 16
+# memory_size = 1073741824
+# memory_pressure = 80
+# threads = 4
+# cpu_quota = 100
+# io_bandwidth = 10000000
+
+## Process attributes
+# [process]
+# cwd = "/app"
+# umask = 0o022
+
+## Personality presented to the application
+# [compat]
+# sysname = "Linux"
+# nodename = "localhost.localdomain"
+# release = "5.6.0"
+# machine = "x86_64"
+# page_size = 4096
+# nprocs = 1
+
+## Sockets, which the application may open at runtime
+# [network]
+# resolve = true
+# [[network.allow]]
+# prot = "udp"
+# addr = "10.0.0.53"
+# port = 53
+# [[network.allow]]
+# prot = "tcp"
+# addr = "10.0.0.0/8"
+
+## Handle every connection of a listen socket in a fresh instance
+# [isolation]
+# per_connection = "ingest"
+
+## Deterministic execution for reproducible results
+# [determinism]
+# warn = false
+
+## Deny the wall time of the host to the application, serving only the monotonic clock
+# [clock]
+# realtime = false
+
+## In-memory temporary directory at /tmp
+# [tmp]
+# size = "256MiB"
+
+## Module precompiled by `enarx precompile`, which the Keep uses instead of compiling
+# [precompiled]
+# digest = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+
+## Build provenance required of the WASM module
+# [provenance]
+# keys = ["04a1b2..."]
+# builders = ["https://github.com/org/repo/.github/workflows/release.yml"]
+# repositories = ["https://github.com/org/repo"]
+
+## Register with a rendezvous service to be discovered by peer Keeps
+# [rendezvous]
+# url = "https://rendezvous.example.com"
+# endpoints = ["10.0.0.2:8443"]
+
+## Allow an observer WASM module supplied by the operator to monitor the application
+# [sidecar]
+# stats = true
+# log = true
+
+## Capabilities enabled only in attested production Keeps
+# [capabilities.payments]
+# production = true
+# min_tcb.snp = { bootloader = 3, tee = 0, snp = 8, microcode = 115 }
+
+## Configuration values of the `wasi:config` interface
+# [config]
+# log_level = "info"
+
+## Sealed key-value store of the `wasi:keyvalue` interface in a host directory
+# [keyvalue]
+# path = "/var/lib/enarx/store"
+
+## Quarantine of freed heap allocations of the Keep to detect writes after free
+# [heap]
+# quarantine = "16MiB"
+
+## Mirror the decrypted data of streams in debug Keeps, e.g. to `enarx run --tap`
+# [tap]
+# files = ["stream"]
+# protocol = "http"
+# redact = ["Authorization:", "Cookie:"]
+
+## Inject latency, short reads and writes and resets into streams of debug Keeps
+# [chaos]
+# files = ["stream"]
+# latency = 50
+# jitter = 20
+# fragment = 10
+# reset = 1
+
+## Stacks of the threads running the application, e.g. for deep recursion
+# [stack]
+# size = "8MiB"
+# guard = "64KiB"
+# tls = "exec"
+
+## Spin for 20 microseconds before blocking in a wait, e.g. for latency-sensitive servers
+# [wait]
+# spin = 20
 "#;
 
 const fn default_tcp_port() -> u16 {
@@ -68,6 +198,59 @@ fn default_addr() -> String {
     "::".into()
 }
 
+const fn default_stack_size() -> u64 {
+    2 * 1024 * 1024
+}
+
+const fn default_stack_guard() -> u64 {
+    4096
+}
+
+const fn default_confidential() -> bool {
+    true
+}
+
+const fn default_realtime() -> bool {
+    true
+}
+
+/// Parses a size in bytes with an optional `B`, `KiB`, `MiB` or `GiB` unit
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (n, unit) = size.split_at(
+        size.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(size.len()),
+    );
+    let shift = match unit.trim_start() {
+        "" | "B" => 0,
+        "KiB" => 10,
+        "MiB" => 20,
+        "GiB" => 30,
+        _ => return None,
+    };
+    n.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// Deserializes a size in bytes given as an integer or a string with a unit, e.g. `"256MiB"`
+fn deserialize_size<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(n) => Ok(n),
+        Size::Text(text) => {
+            parse_size(&text).ok_or_else(|| D::Error::custom(format!("invalid size `{text}`")))
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
 /// Name assigned to a file descriptor
 ///
@@ -151,6 +334,75 @@ pub struct Config {
     /// The environment variables to provide to the application
     #[serde(default)]
     pub env: HashMap<String, String>,
+
+    /// Resource limits enforced on the application
+    #[serde(default)]
+    pub limits: Limits,
+
+    /// Capabilities of the application, which are enabled depending on the attestation claims
+    #[serde(default)]
+    pub capabilities: HashMap<String, Capability>,
+
+    /// Process attributes of the application
+    #[serde(default)]
+    pub process: Process,
+
+    /// Personality presented to the application
+    #[serde(default)]
+    pub compat: Compat,
+
+    /// Isolation of the connections handled by the application
+    #[serde(default)]
+    pub isolation: Isolation,
+
+    /// Sockets, which the application may open at runtime
+    #[serde(default)]
+    pub network: Network,
+
+    /// Build provenance required of the application
+    pub provenance: Option<Provenance>,
+
+    /// Rendezvous service for the discovery of peer Keeps
+    pub rendezvous: Option<Rendezvous>,
+
+    /// Observer WASM module permitted to run alongside the application
+    pub sidecar: Option<Sidecar>,
+
+    /// Deterministic execution of the application
+    pub determinism: Option<Determinism>,
+
+    /// Clocks available to the application
+    #[serde(default)]
+    pub clock: Clock,
+
+    /// In-memory temporary directory of the application
+    pub tmp: Option<Tmp>,
+
+    /// Precompiled module artifact trusted by the Keep
+    pub precompiled: Option<Precompiled>,
+
+    /// Configuration values provided to the application by the `wasi:config` interface
+    #[serde(default)]
+    pub config: HashMap<String, String>,
+
+    /// Sealed key-value store provided to the application by the `wasi:keyvalue` interface
+    pub keyvalue: Option<KeyValue>,
+
+    /// Hardening of the heap of the Keep
+    #[serde(default)]
+    pub heap: Heap,
+
+    /// Tap mirroring the decrypted stream data of debug Keeps
+    pub tap: Option<Tap>,
+
+    /// Faults injected into the streams of debug Keeps
+    pub chaos: Option<Chaos>,
+
+    /// Stacks of the threads running the application
+    pub stack: Option<Stack>,
+
+    /// Spinning of blocking waits of the Keep, overriding the default of the backend
+    pub wait: Option<Wait>,
 }
 
 impl Default for Config {
@@ -166,10 +418,458 @@ impl Default for Config {
             args: vec![],
             files,
             steward: None, // TODO: Default to a deployed Steward instance
+            limits: Default::default(),
+            capabilities: HashMap::new(),
+            process: Default::default(),
+            compat: Default::default(),
+            isolation: Default::default(),
+            network: Default::default(),
+            provenance: None,
+            rendezvous: None,
+            sidecar: None,
+            determinism: None,
+            clock: Default::default(),
+            tmp: None,
+            precompiled: None,
+            config: HashMap::new(),
+            keyvalue: None,
+            heap: Default::default(),
+            tap: None,
+            chaos: None,
+            stack: None,
+            wait: None,
         }
     }
 }
 
+/// Process attributes of the WASM application
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Process {
+    /// Absolute path of the working directory, exported in the `PWD` environment variable
+    pub cwd: Option<String>,
+
+    /// File mode creation mask
+    pub umask: Option<u32>,
+}
+
+/// Personality of the system presented to the WASM application
+///
+/// Values, which are not set, fall back to the defaults of the runtime, never to the values
+/// of the host.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Compat {
+    /// Operating system name reported by `uname`
+    pub sysname: Option<String>,
+
+    /// Host name reported by `uname`
+    pub nodename: Option<String>,
+
+    /// Operating system release reported by `uname`
+    pub release: Option<String>,
+
+    /// Operating system version reported by `uname`
+    pub version: Option<String>,
+
+    /// Hardware identifier reported by `uname`
+    pub machine: Option<String>,
+
+    /// Page size in bytes
+    pub page_size: Option<u32>,
+
+    /// Number of online processors
+    pub nprocs: Option<u32>,
+}
+
+/// Isolation of the connections handled by the WASM application
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Isolation {
+    /// Name of a listen file descriptor, every accepted connection of which is handled by a
+    /// fresh instance of the application
+    pub per_connection: Option<FileName>,
+}
+
+/// Deterministic execution of the WASM application
+///
+/// NaN results of floating-point operations are canonicalized and the WASM module is checked for
+/// constructs, whose behavior depends on the host or on the scheduling of threads.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Determinism {
+    /// Only warn about nondeterministic constructs instead of refusing the WASM module
+    #[serde(default)]
+    pub warn: bool,
+}
+
+/// Clocks of the WASM application
+///
+/// Denying the wall time restricts the application to the monotonic clock, which counts from the
+/// start of the Keep, so the host cannot use the wall time as a timing channel.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Clock {
+    /// Whether the application may read the wall time of the host, i.e. `CLOCK_REALTIME`
+    #[serde(default = "default_realtime")]
+    pub realtime: bool,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            realtime: default_realtime(),
+        }
+    }
+}
+
+/// Build provenance policy of the WASM application
+///
+/// The WASM module is only executed, if the package contains a provenance statement of its
+/// digest, which is signed by one of the `keys` and matches the allowed builders and repositories.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Provenance {
+    /// Hex-encoded uncompressed SEC1 ECDSA P-256 public keys of the trusted signers
+    pub keys: Vec<String>,
+
+    /// Allowed builder identities, any builder is allowed, if empty
+    #[serde(default)]
+    pub builders: Vec<String>,
+
+    /// Allowed source repositories, any repository is allowed, if empty
+    #[serde(default)]
+    pub repositories: Vec<String>,
+}
+
+/// Sockets, which the WASM application may open at runtime in addition to its pre-opened ones
+///
+/// Without any allowed endpoints, the application can neither connect nor send to any address.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Network {
+    /// Whether the application may resolve host names
+    #[serde(default)]
+    pub resolve: bool,
+
+    /// Endpoints, which the application may connect or send to
+    #[serde(default)]
+    pub allow: Vec<Endpoint>,
+}
+
+/// Transport protocol of an [`Endpoint`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Protocol {
+    /// Outgoing TCP connections
+    #[serde(rename = "tcp")]
+    Tcp,
+
+    /// UDP datagrams
+    #[serde(rename = "udp")]
+    Udp,
+}
+
+/// Endpoints, which the WASM application may reach with a protocol
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Endpoint {
+    /// Transport protocol
+    pub prot: Protocol,
+
+    /// IP address or network in CIDR notation, e.g. `10.0.0.0/8`, or any address, if not set
+    pub addr: Option<String>,
+
+    /// Port or any port, if not set
+    pub port: Option<u16>,
+}
+
+/// Rendezvous service, with which the Keep registers its attested identity and endpoints
+///
+/// Peer Keeps query the service for the endpoints of Keeps running a WASM module of a given
+/// digest, e.g. the other nodes of a confidential cluster.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rendezvous {
+    /// URL of the rendezvous service
+    pub url: Url,
+
+    /// Endpoints `host:port`, at which the Keep is reachable by its peers
+    #[serde(default)]
+    pub endpoints: Vec<String>,
+}
+
+/// Permissions of an observer WASM module, which the operator runs alongside the application
+///
+/// The sidecar has neither access to the secrets, the environment and the files of the
+/// application nor to the network. Without this section, no sidecar is run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sidecar {
+    /// Whether the sidecar may read the memory usage statistics of the application
+    #[serde(default)]
+    pub stats: bool,
+
+    /// Whether the sidecar may read the standard output and error of the application
+    #[serde(default)]
+    pub log: bool,
+
+    /// Digest `sha256:<hex>` of the only sidecar module permitted
+    pub digest: Option<String>,
+}
+
+/// In-memory temporary directory, which the WASM application finds at `/tmp`
+///
+/// The files are stored in the memory of the Keep, never on the host, and are discarded together
+/// with the instance of the application.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tmp {
+    /// Maximum total size of all files in bytes, e.g. `268435456` or `"256MiB"`
+    #[serde(deserialize_with = "deserialize_size")]
+    pub size: u64,
+}
+
+/// Hardening of the heap of the Keep, which is used by the runtime, not by the WASM application
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Heap {
+    /// Maximum total size of freed allocations held in the quarantine in bytes, e.g. `16777216`
+    /// or `"16MiB"`, which is disabled by `0`
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub quarantine: u64,
+}
+
+/// Tap mirroring the data of the streams of the WASM application after decryption
+///
+/// The tap is only enabled in Keeps, which allow debugging, so it cannot leak the data of
+/// production Keeps to the host.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tap {
+    /// Names of the `listen` and `connect` file descriptors to tap, all if empty
+    #[serde(default)]
+    pub files: Vec<String>,
+
+    /// Name of the protocol of the data, which packet analyzers dissect it as, e.g. `"http"`
+    pub protocol: Option<String>,
+
+    /// Case-insensitive prefixes of lines, whose remainder is redacted, e.g. `"Authorization:"`
+    #[serde(default)]
+    pub redact: Vec<String>,
+}
+
+/// Faults injected into the streams of the WASM application, e.g. to test its resilience
+///
+/// The faults are injected inside of the Keep after decryption, where tools on the host cannot
+/// reach. They are only injected in Keeps, which allow debugging.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Chaos {
+    /// Names of the `listen` and `connect` file descriptors to inject faults into, all if empty
+    #[serde(default)]
+    pub files: Vec<String>,
+
+    /// Latency added to every read and write in milliseconds
+    #[serde(default)]
+    pub latency: u32,
+
+    /// Maximum random latency added on top of `latency` in milliseconds
+    #[serde(default)]
+    pub jitter: u32,
+
+    /// Percentage of reads and writes transferring only a random part of the data
+    #[serde(default)]
+    pub fragment: u8,
+
+    /// Percentage of reads and writes resetting the stream
+    #[serde(default)]
+    pub reset: u8,
+
+    /// Seed of the random faults to reproduce a run, random if not set
+    pub seed: Option<u64>,
+}
+
+/// Stacks of the threads running the WASM application
+///
+/// Without it, the application runs on the stack the shim allocated for the exec.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Stack {
+    /// Size of the stack of every thread in bytes, e.g. `8388608` or `"8MiB"`
+    #[serde(default = "default_stack_size", deserialize_with = "deserialize_size")]
+    pub size: u64,
+
+    /// Size of the inaccessible guard below every stack in bytes, e.g. `65536` or `"64KiB"`
+    #[serde(default = "default_stack_guard", deserialize_with = "deserialize_size")]
+    pub guard: u64,
+
+    /// Source of the stacks and the thread-local storage of the threads
+    #[serde(default)]
+    pub tls: StackTls,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self {
+            size: default_stack_size(),
+            guard: default_stack_guard(),
+            tls: Default::default(),
+        }
+    }
+}
+
+/// Source of the stacks and the thread-local storage of the threads running the WASM application
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StackTls {
+    /// The C library maps the stack, the guard and the thread-local storage of every new thread
+    #[default]
+    #[serde(rename = "libc")]
+    Libc,
+
+    /// The exec maps the stacks and the guards and reuses them for new threads, and the C library
+    /// places the thread-local storage at the top of the stack
+    #[serde(rename = "exec")]
+    Exec,
+}
+
+/// Spin-then-block strategy of blocking waits of the Keep, e.g. `poll_oneoff` of the application
+///
+/// A blocking wait first polls without blocking for a spin window, since an exit to the host
+/// costs less than blocking a host thread and waking it up again. The number of polls adapts to
+/// how often spinning ends a wait, so an idle Keep quickly stops spinning.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Wait {
+    /// Spin window in microseconds, which disables spinning, if `0`
+    pub spin: u32,
+}
+
+/// Key-value store of the WASM application, which is sealed to the Keep
+///
+/// The values are stored in files in a directory of the host, encrypted with keys derived from
+/// the sealing key of the Keep, so only Keeps with the same identity can read them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyValue {
+    /// Path of the directory on the host storing the values, which is created if it does not exist
+    pub path: String,
+}
+
+/// Precompiled module artifact, which the Keep deserializes instead of compiling the WASM module
+///
+/// The host provides the artifact produced by `enarx precompile`. It is only used, if its digest
+/// matches, otherwise the WASM module is compiled in the Keep.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Precompiled {
+    /// Digest `sha256:<hex>` of the artifact
+    pub digest: String,
+}
+
+/// Attestation claims required to enable a capability of the WASM application
+///
+/// A capability is enabled, if the Keep satisfies all of the requirements.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Capability {
+    /// Require a hardware TEE with debugging disabled
+    #[serde(default)]
+    pub production: bool,
+
+    /// Require a minimum TCB version of the platform
+    pub min_tcb: Option<MinTcb>,
+}
+
+/// Minimum TCB versions per TEE technology
+///
+/// A Keep running on a technology without a minimum does not satisfy the requirement.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MinTcb {
+    /// Minimum TCB version of AMD SEV-SNP
+    pub snp: Option<SnpTcb>,
+
+    /// Minimum TCB version of Intel SGX
+    pub sgx: Option<SgxTcb>,
+}
+
+/// An AMD SEV-SNP TCB version
+///
+/// Every component must be at least the minimum.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnpTcb {
+    /// SVN of the PSP bootloader
+    pub bootloader: u8,
+
+    /// SVN of the PSP operating system
+    pub tee: u8,
+
+    /// SVN of the SNP firmware
+    pub snp: u8,
+
+    /// Lowest current patch level of all the cores
+    pub microcode: u8,
+}
+
+/// An Intel SGX TCB version
+///
+/// Every component must be at least the minimum.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SgxTcb {
+    /// Components of the CPU security version
+    pub cpusvn: [u8; 16],
+
+    /// Security version of the provisioning certification enclave
+    pub pcesvn: u16,
+}
+
+/// Resource limits of the WASM application
+///
+/// Limits, which are not set, fall back to the defaults of the runtime.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// Maximum size of the WASM module in bytes
+    pub module_size: Option<u64>,
+
+    /// Maximum size of the compiled code of the WASM module in bytes
+    pub code_size: Option<u64>,
+
+    /// Maximum number of tables
+    pub tables: Option<u32>,
+
+    /// Maximum number of elements in a single table
+    pub table_elements: Option<u32>,
+
+    /// Maximum number of module instances
+    pub instances: Option<u32>,
+
+    /// Maximum total size of all linear memories in bytes
+    pub memory_size: Option<u64>,
+
+    /// Percentage of `memory_size`, above which the application is notified of memory pressure
+    ///
+    /// Defaults to 80. The notifications are delivered on `memory` files.
+    pub memory_pressure: Option<u8>,
+
+    /// Maximum number of threads running concurrently, which the WASM application spawned
+    ///
+    /// Spawning threads is refused, if not set. Requires `memory_size`.
+    pub threads: Option<u32>,
+
+    /// Maximum CPU usage of the Keep in percent of a single CPU
+    ///
+    /// Enforced by the host in the cgroup of the Keep.
+    pub cpu_quota: Option<u32>,
+
+    /// Maximum read and write bandwidth of the Keep on every block device in bytes per second
+    ///
+    /// Enforced by the host in the cgroup of the Keep.
+    pub io_bandwidth: Option<u64>,
+}
+
 /// `/dev/null` file descriptor
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -178,12 +878,94 @@ pub struct NullFile {
     name: Option<FileName>,
 }
 
+/// Memory usage file descriptor
+///
+/// Reads return the current, peak and maximum memory usage of the application. The file
+/// descriptor becomes readable, when the memory usage grows above the `memory_pressure`
+/// threshold of the limits.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryFile {
+    /// Name assigned to the file descriptor
+    name: Option<FileName>,
+}
+
+/// Append-only log file descriptor
+///
+/// Every write is framed as a record, hashed into a chain and appended to a host file, which
+/// the Keep can neither read back nor truncate.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppendLogFile {
+    /// Name assigned to the file descriptor
+    name: Option<FileName>,
+
+    /// Path of the host file, which is created if it does not exist
+    pub path: String,
+}
+
+/// Read-only dataset file descriptor
+///
+/// The host file is opened read-only by the host and shared by all Keeps on the host. Its data is
+/// not confidential, but every chunk read by the Keep is verified against the `digest`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatasetFile {
+    /// Name assigned to the file descriptor
+    name: Option<FileName>,
+
+    /// Path of the host file
+    pub path: String,
+
+    /// Digest `sha256:<hex>` of the dataset as printed by `enarx dataset`
+    pub digest: String,
+}
+
+/// Stream file descriptor connected to a helper Keep
+///
+/// The host spawns a Keep of the `nil` backend running the module at `path`, whose standard input
+/// and output are connected to the stream. The helper Keep has no TEE and no network, so it only
+/// communicates through the Keep spawning it, which verifies its module against the `digest`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HelperFile {
+    /// Name assigned to the file descriptor
+    name: Option<FileName>,
+
+    /// Path of the Wasm module of the helper on the host
+    pub path: String,
+
+    /// Digest `sha256:<hex>` of the Wasm module of the helper
+    pub digest: String,
+}
+
 /// Standard I/O file descriptor
-#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct StdioFile {
     /// Name assigned to the file descriptor
     name: Option<FileName>,
+
+    /// Whether the data of the file descriptor is confidential
+    ///
+    /// Data of non-confidential file descriptors may be spliced on the host, bypassing the Keep.
+    #[serde(default = "default_confidential")]
+    pub confidential: bool,
+
+    /// Size in bytes of every read request issued to the host
+    ///
+    /// Hides the sizes of the reads of the application from the host.
+    pub pad: Option<u32>,
+}
+
+impl Default for StdioFile {
+    fn default() -> Self {
+        Self {
+            name: None,
+            confidential: default_confidential(),
+            pad: None,
+        }
+    }
 }
 
 /// File descriptor of a listen socket
@@ -203,6 +985,13 @@ pub enum ListenFile {
         /// Port to listen on
         #[serde(default = "default_tls_port")]
         port: u16,
+
+        /// Whether the data of the connections is confidential
+        ///
+        /// The host terminates TLS for non-confidential listen sockets and passes the plaintext
+        /// to the Keep.
+        #[serde(default = "default_confidential")]
+        confidential: bool,
     },
 
     /// TCP listen socket
@@ -251,6 +1040,17 @@ pub enum ConnectFile {
         /// Port to connect to
         #[serde(default = "default_tcp_port")]
         port: u16,
+
+        /// Whether the data of the stream is confidential
+        ///
+        /// Data of non-confidential streams may be spliced on the host, bypassing the Keep.
+        #[serde(default = "default_confidential")]
+        confidential: bool,
+
+        /// Size in bytes of every read request issued to the host
+        ///
+        /// Hides the sizes of the reads of the application from the host.
+        pad: Option<u32>,
     },
 }
 
@@ -281,6 +1081,22 @@ pub enum File {
     /// File descriptor of a stream socket
     #[serde(rename = "connect")]
     Connect(ConnectFile),
+
+    /// File descriptor of the memory usage of the application
+    #[serde(rename = "memory")]
+    Memory(MemoryFile),
+
+    /// File descriptor of an append-only log
+    #[serde(rename = "appendlog")]
+    AppendLog(AppendLogFile),
+
+    /// File descriptor of a read-only dataset
+    #[serde(rename = "dataset")]
+    Dataset(DatasetFile),
+
+    /// File descriptor of a stream to a helper Keep
+    #[serde(rename = "helper")]
+    Helper(HelperFile),
 }
 
 impl File {
@@ -288,13 +1104,57 @@ impl File {
     pub fn name(&self) -> &str {
         match self {
             Self::Null(NullFile { name }) => name.as_deref().unwrap_or("null"),
-            Self::Stdin(StdioFile { name }) => name.as_deref().unwrap_or("stdin"),
-            Self::Stdout(StdioFile { name }) => name.as_deref().unwrap_or("stdout"),
-            Self::Stderr(StdioFile { name }) => name.as_deref().unwrap_or("stderr"),
+            Self::Stdin(StdioFile { name, .. }) => name.as_deref().unwrap_or("stdin"),
+            Self::Stdout(StdioFile { name, .. }) => name.as_deref().unwrap_or("stdout"),
+            Self::Stderr(StdioFile { name, .. }) => name.as_deref().unwrap_or("stderr"),
             Self::Listen(ListenFile::Tls { name, .. }) => name,
             Self::Listen(ListenFile::Tcp { name, .. }) => name,
             Self::Connect(ConnectFile::Tls { name, host, .. }) => name.as_deref().unwrap_or(host),
             Self::Connect(ConnectFile::Tcp { name, host, .. }) => name.as_deref().unwrap_or(host),
+            Self::Memory(MemoryFile { name }) => name.as_deref().unwrap_or("memory"),
+            Self::AppendLog(AppendLogFile { name, .. }) => name.as_deref().unwrap_or("appendlog"),
+            Self::Dataset(DatasetFile { name, .. }) => name.as_deref().unwrap_or("dataset"),
+            Self::Helper(HelperFile { name, .. }) => name.as_deref().unwrap_or("helper"),
+        }
+    }
+
+    /// Whether the data of the file descriptor is confidential
+    ///
+    /// TLS streams, TCP listen sockets, memory files, append-only logs and `/dev/null` are always
+    /// confidential. Datasets are never written and every read is verified, so they are treated
+    /// as confidential as well. Streams to helper Keeps are never confidential, since the helper
+    /// runs without a TEE.
+    pub fn confidential(&self) -> bool {
+        match self {
+            Self::Stdin(StdioFile { confidential, .. })
+            | Self::Stdout(StdioFile { confidential, .. })
+            | Self::Stderr(StdioFile { confidential, .. })
+            | Self::Listen(ListenFile::Tls { confidential, .. })
+            | Self::Connect(ConnectFile::Tcp { confidential, .. }) => *confidential,
+            Self::Null(..)
+            | Self::Listen(ListenFile::Tcp { .. })
+            | Self::Connect(ConnectFile::Tls { .. })
+            | Self::Memory(..)
+            | Self::AppendLog(..)
+            | Self::Dataset(..) => true,
+            Self::Helper(..) => false,
+        }
+    }
+
+    /// Size in bytes of every read request of the file descriptor issued to the host, if padded
+    pub fn pad(&self) -> Option<u32> {
+        match self {
+            Self::Stdin(StdioFile { pad, .. })
+            | Self::Stdout(StdioFile { pad, .. })
+            | Self::Stderr(StdioFile { pad, .. })
+            | Self::Connect(ConnectFile::Tcp { pad, .. }) => *pad,
+            Self::Null(..)
+            | Self::Listen(..)
+            | Self::Connect(ConnectFile::Tls { .. })
+            | Self::Memory(..)
+            | Self::AppendLog(..)
+            | Self::Dataset(..)
+            | Self::Helper(..) => None,
         }
     }
 }
@@ -326,6 +1186,23 @@ mod test {
         kind = "connect"
         host = "example.com"
         prot = "tls"
+
+        [[files]]
+        kind = "memory"
+
+        [[files]]
+        kind = "appendlog"
+        path = "audit.log"
+
+        [[files]]
+        kind = "dataset"
+        path = "geo.bin"
+        digest = "sha256:00"
+
+        [[files]]
+        kind = "helper"
+        path = "ocr.wasm"
+        digest = "sha256:11"
     "#;
 
     #[test]
@@ -349,6 +1226,21 @@ mod test {
                     port: default_tls_port(),
                     host: "example.com".into(),
                 }),
+                File::Memory(Default::default()),
+                File::AppendLog(AppendLogFile {
+                    name: None,
+                    path: "audit.log".into(),
+                }),
+                File::Dataset(DatasetFile {
+                    name: None,
+                    path: "geo.bin".into(),
+                    digest: "sha256:00".into(),
+                }),
+                File::Helper(HelperFile {
+                    name: None,
+                    path: "ocr.wasm".into(),
+                    digest: "sha256:11".into(),
+                }),
             ]
         );
 
@@ -360,7 +1252,18 @@ mod test {
         let cfg: Config = toml::from_str(CONFIG).unwrap();
 
         assert_eq!(
-            vec!["stdin", "X", "stdout", "null", "stderr", "example.com"],
+            vec![
+                "stdin",
+                "X",
+                "stdout",
+                "null",
+                "stderr",
+                "example.com",
+                "memory",
+                "appendlog",
+                "dataset",
+                "helper"
+            ],
             cfg.files.iter().map(|f| f.name()).collect::<Vec<_>>()
         );
     }
@@ -381,6 +1284,524 @@ mod test {
         );
     }
 
+    #[test]
+    fn limits() {
+        const CONFIG: &str = r#"
+        [limits]
+        module_size = 1000
+        tables = 2
+        memory_size = 65536
+        memory_pressure = 50
+        threads = 8
+        cpu_quota = 150
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.limits,
+            Limits {
+                module_size: Some(1000),
+                tables: Some(2),
+                memory_size: Some(65536),
+                memory_pressure: Some(50),
+                threads: Some(8),
+                cpu_quota: Some(150),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            toml::from_str::<Config>("").unwrap().limits,
+            Limits::default()
+        );
+
+        const INVALID: &str = r#"
+        [limits]
+        module = 1000
+        "#;
+        assert!(toml::from_str::<Config>(INVALID).is_err());
+    }
+
+    #[test]
+    fn process() {
+        const CONFIG: &str = r#"
+        [process]
+        cwd = "/app"
+        umask = 0o027
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.process,
+            Process {
+                cwd: Some("/app".into()),
+                umask: Some(0o027),
+            }
+        );
+        assert_eq!(
+            toml::from_str::<Config>("").unwrap().process,
+            Process::default()
+        );
+    }
+
+    #[test]
+    fn compat() {
+        const CONFIG: &str = r#"
+        [compat]
+        release = "6.1.0"
+        page_size = 16384
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.compat,
+            Compat {
+                release: Some("6.1.0".into()),
+                page_size: Some(16384),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            toml::from_str::<Config>("").unwrap().compat,
+            Compat::default()
+        );
+
+        const INVALID: &str = r#"
+        [compat]
+        domainname = "example.com"
+        "#;
+        assert!(toml::from_str::<Config>(INVALID).is_err());
+    }
+
+    #[test]
+    fn isolation() {
+        const CONFIG: &str = r#"
+        [isolation]
+        per_connection = "ingest"
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.isolation,
+            Isolation {
+                per_connection: Some("ingest".try_into().unwrap()),
+            }
+        );
+        assert_eq!(
+            toml::from_str::<Config>("").unwrap().isolation,
+            Isolation::default()
+        );
+
+        const INVALID: &str = r#"
+        [isolation]
+        per_request = "ingest"
+        "#;
+        assert!(toml::from_str::<Config>(INVALID).is_err());
+    }
+
+    #[test]
+    fn determinism() {
+        let cfg: Config = toml::from_str("[determinism]").unwrap();
+        assert_eq!(cfg.determinism, Some(Determinism { warn: false }));
+
+        let cfg: Config = toml::from_str("[determinism]\nwarn = true").unwrap();
+        assert_eq!(cfg.determinism, Some(Determinism { warn: true }));
+
+        assert_eq!(toml::from_str::<Config>("").unwrap().determinism, None);
+        assert!(toml::from_str::<Config>("[determinism]\nenforce = true").is_err());
+    }
+
+    #[test]
+    fn clock() {
+        let cfg: Config = toml::from_str("[clock]\nrealtime = false").unwrap();
+        assert_eq!(cfg.clock, Clock { realtime: false });
+        assert_eq!(
+            toml::from_str::<Config>("[clock]").unwrap().clock,
+            Clock::default()
+        );
+        assert!(toml::from_str::<Config>("").unwrap().clock.realtime);
+        assert!(toml::from_str::<Config>("[clock]\nprocess = false").is_err());
+    }
+
+    #[test]
+    fn provenance() {
+        const CONFIG: &str = r#"
+        [provenance]
+        keys = ["04abcd"]
+        repositories = ["https://github.com/enarx/enarx"]
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.provenance,
+            Some(Provenance {
+                keys: vec!["04abcd".into()],
+                builders: vec![],
+                repositories: vec!["https://github.com/enarx/enarx".into()],
+            })
+        );
+        assert_eq!(toml::from_str::<Config>("").unwrap().provenance, None);
+
+        const INVALID: &str = r#"
+        [provenance]
+        builders = ["ci"]
+        "#;
+        assert!(toml::from_str::<Config>(INVALID).is_err());
+    }
+
+    #[test]
+    fn rendezvous() {
+        const CONFIG: &str = r#"
+        [rendezvous]
+        url = "https://rendezvous.example.com"
+        endpoints = ["10.0.0.2:8443"]
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.rendezvous,
+            Some(Rendezvous {
+                url: "https://rendezvous.example.com".parse().unwrap(),
+                endpoints: vec!["10.0.0.2:8443".into()],
+            })
+        );
+        assert_eq!(toml::from_str::<Config>("").unwrap().rendezvous, None);
+    }
+
+    #[test]
+    fn sidecar() {
+        const CONFIG: &str = r#"
+        [sidecar]
+        log = true
+        digest = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.sidecar,
+            Some(Sidecar {
+                stats: false,
+                log: true,
+                digest: Some(
+                    "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                        .into()
+                ),
+            })
+        );
+        assert_eq!(toml::from_str::<Config>("").unwrap().sidecar, None);
+        assert!(toml::from_str::<Config>("[sidecar]\nnetwork = true").is_err());
+    }
+
+    #[test]
+    fn tmp() {
+        let cfg: Config = toml::from_str("[tmp]\nsize = \"256MiB\"").unwrap();
+        assert_eq!(cfg.tmp, Some(Tmp { size: 256 << 20 }));
+        let cfg: Config = toml::from_str("[tmp]\nsize = 4096").unwrap();
+        assert_eq!(cfg.tmp, Some(Tmp { size: 4096 }));
+        let cfg: Config = toml::from_str("[tmp]\nsize = \"2 GiB\"").unwrap();
+        assert_eq!(cfg.tmp, Some(Tmp { size: 2 << 30 }));
+        assert_eq!(toml::from_str::<Config>("").unwrap().tmp, None);
+
+        for invalid in ["\"256MB\"", "\"MiB\"", "\"-1\"", "\"99999999999GiB\""] {
+            assert!(toml::from_str::<Config>(&format!("[tmp]\nsize = {invalid}")).is_err());
+        }
+        assert!(toml::from_str::<Config>("[tmp]").is_err());
+    }
+
+    #[test]
+    fn heap() {
+        let cfg: Config = toml::from_str("[heap]\nquarantine = \"16MiB\"").unwrap();
+        assert_eq!(
+            cfg.heap,
+            Heap {
+                quarantine: 16 << 20
+            }
+        );
+        assert_eq!(
+            toml::from_str::<Config>("[heap]").unwrap().heap,
+            Heap::default()
+        );
+        assert_eq!(toml::from_str::<Config>("").unwrap().heap.quarantine, 0);
+        assert!(toml::from_str::<Config>("[heap]\nquarantine = \"16MB\"").is_err());
+    }
+
+    #[test]
+    fn stack() {
+        let cfg: Config =
+            toml::from_str("[stack]\nsize = \"8MiB\"\nguard = 65536\ntls = \"exec\"").unwrap();
+        assert_eq!(
+            cfg.stack,
+            Some(Stack {
+                size: 8 << 20,
+                guard: 64 << 10,
+                tls: StackTls::Exec,
+            })
+        );
+        assert_eq!(
+            toml::from_str::<Config>("[stack]").unwrap().stack,
+            Some(Stack::default())
+        );
+        assert_eq!(toml::from_str::<Config>("").unwrap().stack, None);
+        assert!(toml::from_str::<Config>("[stack]\ntls = \"heap\"").is_err());
+    }
+
+    #[test]
+    fn wait() {
+        let cfg: Config = toml::from_str("[wait]\nspin = 20").unwrap();
+        assert_eq!(cfg.wait, Some(Wait { spin: 20 }));
+        assert_eq!(toml::from_str::<Config>("").unwrap().wait, None);
+        assert!(toml::from_str::<Config>("[wait]").is_err());
+        assert!(toml::from_str::<Config>("[wait]\nspin = -1").is_err());
+    }
+
+    #[test]
+    fn tap() {
+        let cfg: Config = toml::from_str(
+            r#"
+            [tap]
+            files = ["stream"]
+            protocol = "http"
+            redact = ["Authorization:"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.tap,
+            Some(Tap {
+                files: vec!["stream".into()],
+                protocol: Some("http".into()),
+                redact: vec!["Authorization:".into()],
+            })
+        );
+        assert_eq!(
+            toml::from_str::<Config>("[tap]").unwrap().tap,
+            Some(Tap::default())
+        );
+        assert_eq!(toml::from_str::<Config>("").unwrap().tap, None);
+    }
+
+    #[test]
+    fn chaos() {
+        let cfg: Config = toml::from_str(
+            r#"
+            [chaos]
+            files = ["stream"]
+            latency = 50
+            jitter = 20
+            fragment = 10
+            reset = 1
+            seed = 42
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.chaos,
+            Some(Chaos {
+                files: vec!["stream".into()],
+                latency: 50,
+                jitter: 20,
+                fragment: 10,
+                reset: 1,
+                seed: Some(42),
+            })
+        );
+        assert_eq!(
+            toml::from_str::<Config>("[chaos]").unwrap().chaos,
+            Some(Chaos::default())
+        );
+        assert_eq!(toml::from_str::<Config>("").unwrap().chaos, None);
+        assert!(toml::from_str::<Config>("[chaos]\nloss = 1").is_err());
+    }
+
+    #[test]
+    fn precompiled() {
+        let cfg: Config = toml::from_str("[precompiled]\ndigest = \"sha256:00\"").unwrap();
+        assert_eq!(
+            cfg.precompiled,
+            Some(Precompiled {
+                digest: "sha256:00".into()
+            })
+        );
+        assert_eq!(toml::from_str::<Config>("").unwrap().precompiled, None);
+        assert!(toml::from_str::<Config>("[precompiled]").is_err());
+    }
+
+    #[test]
+    fn cloud() {
+        const CONFIG: &str = r#"
+        [config]
+        log_level = "debug"
+        endpoint = "https://example.com"
+
+        [keyvalue]
+        path = "/var/lib/enarx/store"
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.config,
+            HashMap::from([
+                ("log_level".into(), "debug".into()),
+                ("endpoint".into(), "https://example.com".into()),
+            ])
+        );
+        assert_eq!(
+            cfg.keyvalue,
+            Some(KeyValue {
+                path: "/var/lib/enarx/store".into()
+            })
+        );
+
+        let cfg = toml::from_str::<Config>("").unwrap();
+        assert!(cfg.config.is_empty());
+        assert_eq!(cfg.keyvalue, None);
+        assert!(toml::from_str::<Config>("[keyvalue]").is_err());
+        assert!(toml::from_str::<Config>("[config]\nretries = 3").is_err());
+    }
+
+    #[test]
+    fn network() {
+        const CONFIG: &str = r#"
+        [network]
+        resolve = true
+
+        [[network.allow]]
+        prot = "udp"
+        addr = "10.0.0.53"
+        port = 53
+
+        [[network.allow]]
+        prot = "tcp"
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.network,
+            Network {
+                resolve: true,
+                allow: vec![
+                    Endpoint {
+                        prot: Protocol::Udp,
+                        addr: Some("10.0.0.53".into()),
+                        port: Some(53),
+                    },
+                    Endpoint {
+                        prot: Protocol::Tcp,
+                        addr: None,
+                        port: None,
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            toml::from_str::<Config>("").unwrap().network,
+            Network::default()
+        );
+        assert!(toml::from_str::<Config>("[[network.allow]]\nprot = \"icmp\"").is_err());
+    }
+
+    #[test]
+    fn pad() {
+        const CONFIG: &str = r#"
+        [[files]]
+        kind = "stdin"
+        pad = 4096
+
+        [[files]]
+        kind = "connect"
+        prot = "tcp"
+        host = "example.com"
+        pad = 512
+
+        [[files]]
+        kind = "stdout"
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.files.iter().map(File::pad).collect::<Vec<_>>(),
+            vec![Some(4096), Some(512), None]
+        );
+
+        const INVALID: &str = r#"
+        [[files]]
+        kind = "connect"
+        prot = "tls"
+        host = "example.com"
+        pad = 512
+        "#;
+        assert!(toml::from_str::<Config>(INVALID).is_err());
+    }
+
+    #[test]
+    fn confidential() {
+        const CONFIG: &str = r#"
+        [[files]]
+        name = "public"
+        kind = "listen"
+        port = 8443
+        confidential = false
+
+        [[files]]
+        name = "private"
+        kind = "listen"
+        port = 9443
+
+        [[files]]
+        name = "plain"
+        kind = "listen"
+        prot = "tcp"
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.files.iter().map(File::confidential).collect::<Vec<_>>(),
+            vec![false, true, true]
+        );
+
+        const INVALID: &str = r#"
+        [[files]]
+        name = "plain"
+        kind = "listen"
+        prot = "tcp"
+        confidential = false
+        "#;
+        assert!(toml::from_str::<Config>(INVALID).is_err());
+    }
+
+    #[test]
+    fn capabilities() {
+        const CONFIG: &str = r#"
+        [capabilities.payments]
+        production = true
+        min_tcb.snp = { bootloader = 3, snp = 8 }
+
+        [capabilities.debug]
+        "#;
+
+        let cfg: Config = toml::from_str(CONFIG).unwrap();
+        assert_eq!(
+            cfg.capabilities["payments"],
+            Capability {
+                production: true,
+                min_tcb: Some(MinTcb {
+                    snp: Some(SnpTcb {
+                        bootloader: 3,
+                        snp: 8,
+                        ..Default::default()
+                    }),
+                    sgx: None,
+                }),
+            }
+        );
+        assert_eq!(cfg.capabilities["debug"], Capability::default());
+
+        const INVALID: &str = r#"
+        [capabilities.payments]
+        min_tcb.tdx = {}
+        "#;
+        assert!(toml::from_str::<Config>(INVALID).is_err());
+    }
+
     #[test]
     fn check_template() {
         let cfg_str = CONFIG_TEMPLATE
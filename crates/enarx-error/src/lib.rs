@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Enarx error taxonomy
+//!
+#![doc = include_str!("../README.md")]
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![warn(rust_2018_idioms)]
+
+use std::fmt;
+
+/// Class of an Enarx error
+///
+/// Each class maps to a distinct process exit code, so that scripts and orchestrators
+/// can branch on the class of a failure. The exit codes follow `sysexits.h`.
+/// Errors, which are not classified, result in an exit code of `1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Invalid configuration, e.g. a malformed `Enarx.toml` or an exceeded limit
+    Config,
+    /// The platform does not support the requested Keep
+    Platform,
+    /// Attestation of the Keep failed
+    Attestation,
+    /// The workload trapped
+    Trap,
+    /// Host I/O failed, e.g. reading the package or setting up the network
+    Io,
+    /// The host ran out of TEE resources shared by all Keeps, e.g. EPC pages or SEV ASIDs,
+    /// so launching the Keep may succeed later
+    Resource,
+}
+
+impl ErrorKind {
+    /// All error classes
+    pub const ALL: [Self; 6] = [
+        Self::Config,
+        Self::Platform,
+        Self::Attestation,
+        Self::Trap,
+        Self::Io,
+        Self::Resource,
+    ];
+
+    /// The process exit code of the error class
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::Config => 78,      // EX_CONFIG
+            Self::Platform => 69,    // EX_UNAVAILABLE
+            Self::Attestation => 77, // EX_NOPERM
+            Self::Trap => 70,        // EX_SOFTWARE
+            Self::Io => 74,          // EX_IOERR
+            Self::Resource => 75,    // EX_TEMPFAIL
+        }
+    }
+
+    /// The machine-readable name of the error class
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Config => "config",
+            Self::Platform => "platform",
+            Self::Attestation => "attestation",
+            Self::Trap => "trap",
+            Self::Io => "io",
+            Self::Resource => "resource",
+        }
+    }
+
+    /// Returns the class of `err`, if it has been classified
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.downcast_ref::<Self>().copied()
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Config => "configuration error",
+            Self::Platform => "platform unsupported",
+            Self::Attestation => "attestation failure",
+            Self::Trap => "workload trap",
+            Self::Io => "host I/O error",
+            Self::Resource => "TEE resources exhausted",
+        })
+    }
+}
+
+/// Classification of errors
+pub trait Classify<T> {
+    /// Classifies the error as `kind`, unless it has been classified already
+    fn classify(self, kind: ErrorKind) -> anyhow::Result<T>;
+}
+
+impl<T> Classify<T> for anyhow::Result<T> {
+    fn classify(self, kind: ErrorKind) -> anyhow::Result<T> {
+        self.map_err(|err| {
+            if ErrorKind::of(&err).is_some() {
+                err
+            } else {
+                err.context(kind)
+            }
+        })
+    }
+}
+
+/// Exit of the workload with a non-zero status
+///
+/// The status is returned as the process exit code instead of the one of an error class.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Exit(pub i32);
+
+impl Exit {
+    /// Returns the exit of the workload, which caused `err`, if any
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.downcast_ref::<Self>().copied()
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "workload exited with status {}", self.0)
+    }
+}
+
+impl std::error::Error for Exit {}
+
+/// Returns the process exit code for `err`
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    match Exit::of(err) {
+        Some(Exit(status)) => status,
+        None => ErrorKind::of(err).map_or(1, ErrorKind::exit_code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::anyhow;
+
+    #[test]
+    fn exit_codes() {
+        let err = Err::<(), _>(anyhow!("invalid")).classify(ErrorKind::Config);
+        let err = err.classify(ErrorKind::Io).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::Config));
+        assert_eq!(exit_code(&err), 78);
+
+        assert_eq!(exit_code(&anyhow::Error::new(Exit(3))), 3);
+        assert_eq!(exit_code(&anyhow!("unclassified")), 1);
+
+        let mut codes: Vec<_> = ErrorKind::ALL.iter().map(|k| k.exit_code()).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), ErrorKind::ALL.len());
+    }
+}
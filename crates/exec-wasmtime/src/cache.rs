@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Precompiled Wasm module artifacts, which are shared through the host cache
+//!
+//! An artifact consists of a header followed by the serialized module:
+//!
+//! | Offset | Size | Contents                                     |
+//! |--------|------|----------------------------------------------|
+//! | 0      | 8    | Magic `ENARXWC` and format version `2`       |
+//! | 8      | 8    | CPU fingerprint of the compiling host, in LE |
+//! | 16     | 32   | SHA-256 digest of the Wasm module            |
+//! | 48     | 32   | SHA-256 digest of the serialized module      |
+//! | 80     | ..   | Serialized module                            |
+//!
+//! The CPU fingerprint is a bit mask of the CPU features used by Cranelift. Artifacts compiled
+//! on a host with different features are rejected, so that a cache shared by a fleet of
+//! different hosts never serves code, which the CPU of the Keep cannot execute correctly.
+
+use crate::runtime::WASMTIME_CONFIG;
+
+use anyhow::{ensure, Context};
+use sha2::{Digest, Sha256};
+use wasmtime::{Engine, Module};
+
+/// Magic of the artifact format, the last byte is the format version
+const MAGIC: [u8; 8] = *b"ENARXWC\x02";
+
+const FINGERPRINT_SIZE: usize = 8;
+const DIGEST_SIZE: usize = 32;
+const HEADER_SIZE: usize = MAGIC.len() + FINGERPRINT_SIZE + 2 * DIGEST_SIZE;
+
+/// CPU features used by Cranelift, in the order of their bits in the CPU fingerprint
+#[cfg(target_arch = "x86_64")]
+macro_rules! cranelift_features {
+    ($($feature:tt),*) => {
+        [$(is_x86_feature_detected!($feature)),*]
+    };
+}
+
+/// Returns the CPU fingerprint, a bit mask of the CPU features used by Cranelift
+#[cfg(target_arch = "x86_64")]
+pub fn cpu_fingerprint() -> u64 {
+    cranelift_features!(
+        "sse3",
+        "ssse3",
+        "sse4.1",
+        "sse4.2",
+        "popcnt",
+        "avx",
+        "avx2",
+        "fma",
+        "bmi1",
+        "bmi2",
+        "lzcnt",
+        "avx512f",
+        "avx512dq",
+        "avx512vl",
+        "avx512bitalg",
+        "avx512vbmi"
+    )
+    .iter()
+    .enumerate()
+    .fold(0, |fingerprint, (bit, &detected)| {
+        fingerprint | u64::from(detected) << bit
+    })
+}
+
+/// Returns the CPU fingerprint, a bit mask of the CPU features used by Cranelift
+#[cfg(not(target_arch = "x86_64"))]
+pub fn cpu_fingerprint() -> u64 {
+    0
+}
+
+/// Compiles `webasm` into an artifact
+///
+/// The module is compiled for `target`, if specified, and for the host otherwise.
+pub fn precompile(webasm: &[u8], target: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let mut config = WASMTIME_CONFIG.clone();
+    if let Some(target) = target {
+        config
+            .target(target)
+            .with_context(|| format!("unsupported compilation target `{target}`"))?;
+    }
+    let engine = Engine::new(&config).context("failed to create compilation engine")?;
+    let module = engine
+        .precompile_module(webasm)
+        .context("failed to compile Wasm module")?;
+
+    let mut artifact = Vec::with_capacity(HEADER_SIZE + module.len());
+    artifact.extend_from_slice(&MAGIC);
+    artifact.extend_from_slice(&cpu_fingerprint().to_le_bytes());
+    artifact.extend_from_slice(&Sha256::digest(webasm));
+    artifact.extend_from_slice(&Sha256::digest(&module));
+    artifact.extend(module);
+    Ok(artifact)
+}
+
+/// Verifies the integrity of `artifact` and returns the serialized module
+///
+/// If `webasm` is specified, the artifact must have been compiled from it.
+/// The artifact must have been compiled on a host with the same [`cpu_fingerprint`].
+pub fn verify_artifact<'a>(artifact: &'a [u8], webasm: Option<&[u8]>) -> anyhow::Result<&'a [u8]> {
+    ensure!(
+        artifact.len() >= HEADER_SIZE && artifact[..MAGIC.len()] == MAGIC,
+        "invalid artifact header"
+    );
+    let (fingerprint, rest) = artifact[MAGIC.len()..].split_at(FINGERPRINT_SIZE);
+    let fingerprint = u64::from_le_bytes(fingerprint.try_into().unwrap());
+    ensure!(
+        fingerprint == cpu_fingerprint(),
+        "artifact was compiled for CPU features {fingerprint:#x}, but the CPU has {:#x}",
+        cpu_fingerprint()
+    );
+    let (source, rest) = rest.split_at(DIGEST_SIZE);
+    let (checksum, module) = rest.split_at(DIGEST_SIZE);
+    if let Some(webasm) = webasm {
+        ensure!(
+            source == Sha256::digest(webasm).as_slice(),
+            "artifact was compiled from a different Wasm module"
+        );
+    }
+    ensure!(
+        checksum == Sha256::digest(module).as_slice(),
+        "artifact checksum mismatch"
+    );
+    Ok(module)
+}
+
+/// Verifies, that the digest of `artifact` is `expected`, i.e. `sha256:<hex>`
+pub(crate) fn check_digest(artifact: &[u8], expected: &str) -> anyhow::Result<()> {
+    let digest = format!("sha256:{}", hex::encode(Sha256::digest(artifact)));
+    ensure!(
+        digest == expected,
+        "artifact digest `{digest}` does not match `{expected}`"
+    );
+    Ok(())
+}
+
+/// Loads the module of `artifact` compiled from `webasm`
+pub(crate) fn load(engine: &Engine, artifact: &[u8], webasm: &[u8]) -> anyhow::Result<Module> {
+    let module = verify_artifact(artifact, Some(webasm))?;
+    // SAFETY: The integrity of the artifact was verified above and the caller checked, that the
+    // artifact is bound to the package or that the Keep runs without a TEE.
+    unsafe { Module::deserialize(engine, module) }.context("failed to deserialize Wasm module")
+}
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read-only datasets, which are shared by all Keeps on a host
+//!
+//! A large read-only dataset, e.g. embeddings or geo data, is not part of the package, but a host
+//! file, which the host opens read-only and passes to the Keep. All Keeps on the host read the
+//! same pages of the host page cache, so the dataset is not copied into the memory of every Keep.
+//! The data is not confidential, but its integrity is verified inside every Keep.
+//!
+//! The dataset is split into chunks of [`CHUNK_SIZE`] bytes, the last one possibly shorter. Its
+//! digest is the SHA-256 hash of the concatenated SHA-256 hashes of the chunks. When the Keep
+//! opens the dataset, it hashes all chunks once and compares the result to the digest of the
+//! Enarx.toml. Afterwards every chunk is verified against its hash, whenever it is read from the
+//! host, so the host cannot change the dataset after it was opened.
+
+use std::fs::File;
+use std::io::{self, Read, Seek};
+
+use anyhow::{bail, Context};
+use sha2::{Digest, Sha256};
+
+/// Size of a chunk of a dataset in bytes
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+const DIGEST_PREFIX: &str = "sha256:";
+
+/// Reads up to `buf.len()` bytes at `offset` of `file`, fewer only at the end of the file
+fn read_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<usize> {
+    #[cfg(unix)]
+    use std::os::unix::fs::FileExt;
+    #[cfg(windows)]
+    use std::os::windows::fs::FileExt;
+
+    let mut n = 0;
+    while !buf.is_empty() {
+        #[cfg(unix)]
+        let read = file.read_at(buf, offset);
+        #[cfg(windows)]
+        let read = file.seek_read(buf, offset);
+        match read {
+            Ok(0) => break,
+            Ok(read) => {
+                n += read;
+                offset += read as u64;
+                buf = &mut buf[read..];
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Returns the hashes of the chunks of the data read from `reader`
+fn chunks(mut reader: impl Read) -> io::Result<(Vec<[u8; 32]>, u64)> {
+    let mut hashes = vec![];
+    let mut size = 0;
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let mut len = 0;
+        while len < buf.len() {
+            match reader.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if len == 0 {
+            return Ok((hashes, size));
+        }
+        hashes.push(Sha256::digest(&buf[..len]).into());
+        size += len as u64;
+        if len < buf.len() {
+            return Ok((hashes, size));
+        }
+    }
+}
+
+/// Returns the digest `sha256:<hex>` of the hashes of the chunks of a dataset
+fn root(hashes: &[[u8; 32]]) -> String {
+    let mut root = Sha256::new();
+    for hash in hashes {
+        root.update(hash);
+    }
+    format!("{DIGEST_PREFIX}{}", hex::encode(root.finalize()))
+}
+
+/// Returns the digest `sha256:<hex>` of the dataset read from `reader`
+pub fn dataset_digest(reader: impl Read) -> io::Result<String> {
+    chunks(reader).map(|(hashes, _)| root(&hashes))
+}
+
+/// A dataset in a host file, whose chunks are verified on every read
+#[derive(Debug)]
+pub(crate) struct Dataset {
+    file: File,
+    size: u64,
+    hashes: Vec<[u8; 32]>,
+}
+
+impl Dataset {
+    /// Hashes the chunks of the dataset in `file` and verifies them against `digest`
+    pub(crate) fn open(file: File, digest: &str) -> anyhow::Result<Self> {
+        if !digest.starts_with(DIGEST_PREFIX) {
+            bail!("unsupported dataset digest `{digest}`, expected `{DIGEST_PREFIX}<hex>`");
+        }
+        let (hashes, size) = (&file)
+            .rewind()
+            .and_then(|()| chunks(&file))
+            .context("failed to read dataset")?;
+        let actual = root(&hashes);
+        if actual != digest {
+            bail!("dataset digest `{actual}` does not match `{digest}`");
+        }
+        Ok(Self { file, size, hashes })
+    }
+
+    /// Returns the size of the dataset in bytes
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Reads the chunk `index` from the host into `buf` and verifies it
+    ///
+    /// Returns the length of the chunk. Fails, if the chunk does not match its hash.
+    pub(crate) fn chunk(&self, index: usize, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let hash = self
+            .hashes
+            .get(index)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        let offset = index as u64 * CHUNK_SIZE as u64;
+        let len = (self.size - offset).min(CHUNK_SIZE as u64) as usize;
+        buf.resize(len, 0);
+        let n = read_at(&self.file, buf, offset)?;
+        if n != len || Sha256::digest(&buf[..])[..] != hash[..] {
+            buf.clear();
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("chunk {index} of the dataset was modified by the host"),
+            ));
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    #[test]
+    fn verify() {
+        let mut data = vec![0x5a; CHUNK_SIZE + 3];
+        data[CHUNK_SIZE] = 1;
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&data).unwrap();
+        file.rewind().unwrap();
+
+        let digest = dataset_digest(&data[..]).unwrap();
+        assert_eq!(
+            dataset_digest(&b""[..]).unwrap(),
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert!(Dataset::open(file.try_clone().unwrap(), "sha256:00").is_err());
+
+        let dataset = Dataset::open(file.try_clone().unwrap(), &digest).unwrap();
+        assert_eq!(dataset.size(), data.len() as u64);
+        let mut buf = vec![];
+        assert_eq!(dataset.chunk(1, &mut buf).unwrap(), 3);
+        assert_eq!(buf, [1, 0x5a, 0x5a]);
+        assert_eq!(dataset.chunk(0, &mut buf).unwrap(), CHUNK_SIZE);
+        assert!(dataset.chunk(2, &mut buf).is_err());
+
+        // The host modifies the dataset after it was opened
+        file.seek(io::SeekFrom::Start(CHUNK_SIZE as u64 + 1))
+            .unwrap();
+        file.write_all(&[0]).unwrap();
+        assert!(dataset.chunk(1, &mut buf).is_err());
+        assert!(buf.is_empty());
+        assert_eq!(dataset.chunk(0, &mut buf).unwrap(), CHUNK_SIZE);
+    }
+}
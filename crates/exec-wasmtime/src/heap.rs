@@ -0,0 +1,467 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hardened heap of the Keep
+//!
+//! Heap corruption inside the Keep is otherwise only noticed, when it crashes the runtime at an
+//! unrelated place. [`Hardened`] wraps the system allocator and guards every allocation with a
+//! header and a trailing canary, which are bound to a random secret, the address and the size of
+//! the allocation. Both are checked, when the allocation is freed, and the Keep is aborted, if
+//! they were overwritten, e.g. by a buffer overflow.
+//!
+//! Freed allocations may additionally be kept in a quarantine of a limited size, which poisons
+//! them and delays their reuse. An allocation leaving the quarantine is checked to be still
+//! poisoned, which detects writes after free.
+//!
+//! The number of allocated bytes is accounted and reported to the sidecar alongside the memory
+//! usage of the workload.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Write;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Size of the header of an allocation, which holds its size and canary
+const HEADER: usize = 16;
+
+/// Size of the canary trailing an allocation
+const TRAILER: usize = 8;
+
+/// Byte freed allocations in the quarantine are filled with
+const POISON: u8 = 0xdf;
+
+/// Maximum number of allocations in the quarantine
+const QUARANTINE_SLOTS: usize = 1024;
+
+/// The hardened heap of the Keep, whose global allocator is [`Heap`]
+pub static HEAP: Hardened = Hardened::new();
+
+/// Global allocator of `enarx-exec-wasmtime` allocating from [`HEAP`]
+#[derive(Debug)]
+pub struct Heap;
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        HEAP.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        HEAP.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        HEAP.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        HEAP.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Usage of the heap in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Bytes currently allocated
+    pub allocated: usize,
+    /// Maximum number of bytes allocated at once
+    pub peak: usize,
+    /// Bytes of freed allocations held in the quarantine
+    pub quarantined: usize,
+}
+
+/// An allocation held in the quarantine
+#[derive(Clone, Copy)]
+struct Slot {
+    /// Address of the allocation
+    ptr: usize,
+    /// Size of the allocation
+    size: usize,
+    /// Alignment of the allocation
+    align: usize,
+}
+
+/// FIFO of freed allocations, whose reuse is delayed
+struct Quarantine {
+    slots: [Slot; QUARANTINE_SLOTS],
+    head: usize,
+    len: usize,
+    bytes: usize,
+}
+
+impl Quarantine {
+    /// Removes and returns the oldest allocation, if any
+    fn pop(&mut self) -> Option<Slot> {
+        if self.len == 0 {
+            return None;
+        }
+        let slot = self.slots[self.head];
+        self.head = (self.head + 1) % QUARANTINE_SLOTS;
+        self.len -= 1;
+        self.bytes -= slot.size;
+        Some(slot)
+    }
+
+    /// Adds `slot` as the newest allocation, which must fit
+    fn push(&mut self, slot: Slot) {
+        self.slots[(self.head + self.len) % QUARANTINE_SLOTS] = slot;
+        self.len += 1;
+        self.bytes += slot.size;
+    }
+}
+
+/// Allocator guarding every allocation with canaries
+pub struct Hardened {
+    secret: AtomicU64,
+    active: AtomicBool,
+    allocated: AtomicUsize,
+    peak: AtomicUsize,
+    limit: AtomicUsize,
+    quarantine: Mutex<Quarantine>,
+}
+
+impl Hardened {
+    /// Creates an allocator without a quarantine
+    pub const fn new() -> Self {
+        const EMPTY: Slot = Slot {
+            ptr: 0,
+            size: 0,
+            align: 1,
+        };
+        Self {
+            secret: AtomicU64::new(0),
+            active: AtomicBool::new(false),
+            allocated: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            limit: AtomicUsize::new(0),
+            quarantine: Mutex::new(Quarantine {
+                slots: [EMPTY; QUARANTINE_SLOTS],
+                head: 0,
+                len: 0,
+                bytes: 0,
+            }),
+        }
+    }
+
+    /// Returns whether the allocator has allocated memory, i.e. it is in use
+    pub fn active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Holds up to `bytes` of freed allocations in the quarantine, which is disabled by `0`
+    pub fn set_quarantine(&self, bytes: usize) {
+        self.limit.store(bytes, Ordering::Relaxed);
+        Self::evict(&mut self.lock(), bytes, 0);
+    }
+
+    /// Returns the usage of the heap
+    pub fn usage(&self) -> Usage {
+        Usage {
+            allocated: self.allocated.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            quarantined: self.lock().bytes,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Quarantine> {
+        self.quarantine.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the random secret of the canaries
+    fn secret(&self) -> u64 {
+        match self.secret.load(Ordering::Relaxed) {
+            0 => {
+                let mut secret = [0; 8];
+                // The address of the allocator is a weak fallback, if there is no randomness
+                let secret = 1 | match getrandom::getrandom(&mut secret) {
+                    Ok(()) => u64::from_ne_bytes(secret),
+                    Err(_) => self as *const Self as u64 ^ 0x9e37_79b9_7f4a_7c15,
+                };
+                match self
+                    .secret
+                    .compare_exchange(0, secret, Ordering::Relaxed, Ordering::Relaxed)
+                {
+                    Ok(_) => secret,
+                    Err(current) => current,
+                }
+            }
+            secret => secret,
+        }
+    }
+
+    /// Returns the canary of the allocation of `size` bytes at `ptr`
+    fn canary(&self, ptr: *const u8, size: usize) -> u64 {
+        (self.secret() ^ ptr as u64).rotate_left(size as u32 % 64) ^ size as u64
+    }
+
+    /// Returns the layout of the underlying allocation of `layout` and the offset of the
+    /// allocation in it
+    fn outer(layout: Layout) -> Option<(Layout, usize)> {
+        let align = layout.align().max(HEADER);
+        let size = align.checked_add(layout.size())?.checked_add(TRAILER)?;
+        Layout::from_size_align(size, align)
+            .ok()
+            .map(|outer| (outer, align))
+    }
+
+    /// Writes the header and the trailing canary of the allocation of `size` bytes at `ptr`
+    unsafe fn guard(&self, ptr: *mut u8, size: usize) {
+        let canary = self.canary(ptr, size);
+        ptr.sub(HEADER).cast::<u64>().write(size as u64);
+        ptr.sub(HEADER / 2).cast::<u64>().write(canary);
+        ptr.add(size).cast::<u64>().write_unaligned(!canary);
+    }
+
+    /// Aborts the Keep, if the header or the trailing canary of the allocation of `size` bytes
+    /// at `ptr` were overwritten
+    unsafe fn check(&self, ptr: *const u8, size: usize) {
+        let canary = self.canary(ptr, size);
+        if ptr.sub(HEADER).cast::<u64>().read() != size as u64
+            || ptr.sub(HEADER / 2).cast::<u64>().read() != canary
+        {
+            Self::corrupted("header", ptr);
+        }
+        if ptr.add(size).cast::<u64>().read_unaligned() != !canary {
+            Self::corrupted("trailing canary", ptr);
+        }
+    }
+
+    /// Allocates memory for `layout` from the system allocator, which is zeroed, if `zeroed`
+    unsafe fn allocate(&self, layout: Layout, zeroed: bool) -> *mut u8 {
+        let (outer, offset) = match Self::outer(layout) {
+            Some(outer) => outer,
+            None => return ptr::null_mut(),
+        };
+        let base = if zeroed {
+            System.alloc_zeroed(outer)
+        } else {
+            System.alloc(outer)
+        };
+        if base.is_null() {
+            return base;
+        }
+        let ptr = base.add(offset);
+        self.guard(ptr, layout.size());
+        self.active.store(true, Ordering::Relaxed);
+        self.account(0, layout.size());
+        ptr
+    }
+
+    /// Accounts an allocation of `old` bytes, which now has `new` bytes
+    fn account(&self, old: usize, new: usize) {
+        if new >= old {
+            let allocated = self.allocated.fetch_add(new - old, Ordering::Relaxed) + new - old;
+            self.peak.fetch_max(allocated, Ordering::Relaxed);
+        } else {
+            self.allocated.fetch_sub(old - new, Ordering::Relaxed);
+        }
+    }
+
+    /// Aborts the Keep due to heap corruption
+    #[cold]
+    fn corrupted(what: &str, ptr: *const u8) -> ! {
+        let _ = writeln!(
+            std::io::stderr(),
+            "heap corruption detected: {what} of the allocation at {ptr:p}"
+        );
+        std::process::abort()
+    }
+
+    /// Frees the oldest allocations in `quarantine`, until at most `bytes` in `slots` are left
+    fn evict(quarantine: &mut Quarantine, bytes: usize, slots: usize) {
+        while quarantine.bytes > bytes || quarantine.len > slots {
+            let Slot { ptr, size, align } = match quarantine.pop() {
+                Some(slot) => slot,
+                None => return,
+            };
+            let ptr = ptr as *mut u8;
+            // SAFETY: the allocation was made by `alloc` with this size and alignment
+            unsafe {
+                let data = std::slice::from_raw_parts(ptr, size);
+                if data.iter().any(|byte| *byte != POISON) {
+                    Self::corrupted("write after free", ptr);
+                }
+                let (outer, offset) = Self::outer(Layout::from_size_align_unchecked(size, align))
+                    .expect("layout was valid on allocation");
+                System.dealloc(ptr.sub(offset), outer);
+            }
+        }
+    }
+}
+
+impl Default for Hardened {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for Hardened {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout, false)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout, true)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let size = layout.size();
+        self.check(ptr, size);
+        self.account(size, 0);
+
+        let limit = self.limit.load(Ordering::Relaxed);
+        if size > 0 && size <= limit {
+            ptr.write_bytes(POISON, size);
+            let slot = Slot {
+                ptr: ptr as usize,
+                size,
+                align: layout.align(),
+            };
+            let mut quarantine = self.lock();
+            Self::evict(&mut quarantine, limit - size, QUARANTINE_SLOTS - 1);
+            quarantine.push(slot);
+            return;
+        }
+
+        let (outer, offset) = Self::outer(layout).expect("layout was valid on allocation");
+        System.dealloc(ptr.sub(offset), outer);
+    }
+
+    /// Resizes the allocation in place or moves it, which bypasses the quarantine
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let size = layout.size();
+        self.check(ptr, size);
+
+        let (outer, offset) = Self::outer(layout).expect("layout was valid on allocation");
+        let new = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_outer = match Self::outer(new) {
+            Some((new_outer, _)) => new_outer,
+            None => return ptr::null_mut(),
+        };
+        // The offset only depends on the alignment, so the allocation keeps it
+        let base = System.realloc(ptr.sub(offset), outer, new_outer.size());
+        if base.is_null() {
+            return base;
+        }
+        let ptr = base.add(offset);
+        self.guard(ptr, new_size);
+        self.account(size, new_size);
+        ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounting() {
+        let heap = Hardened::new();
+        assert!(!heap.active());
+
+        let small = Layout::from_size_align(24, 8).unwrap();
+        let large = Layout::from_size_align(4096, 4096).unwrap();
+        unsafe {
+            let a = heap.alloc(small);
+            let b = heap.alloc(large);
+            assert_eq!(a as usize % 8, 0);
+            assert_eq!(b as usize % 4096, 0);
+            a.write_bytes(1, small.size());
+            b.write_bytes(2, large.size());
+            assert!(heap.active());
+            assert_eq!(heap.usage().allocated, 24 + 4096);
+
+            heap.dealloc(a, small);
+            heap.dealloc(b, large);
+        }
+        assert_eq!(
+            heap.usage(),
+            Usage {
+                allocated: 0,
+                peak: 24 + 4096,
+                quarantined: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn quarantine() {
+        let heap = Hardened::new();
+        heap.set_quarantine(100);
+
+        let layout = Layout::from_size_align(40, 8).unwrap();
+        unsafe {
+            let a = heap.alloc(layout);
+            let b = heap.alloc(layout);
+            let c = heap.alloc(layout);
+            heap.dealloc(a, layout);
+            let freed = std::slice::from_raw_parts(a, 40);
+            assert!(freed.iter().all(|byte| *byte == POISON));
+            heap.dealloc(b, layout);
+            assert_eq!(heap.usage().quarantined, 80);
+
+            // The oldest allocation leaves the quarantine to make room
+            heap.dealloc(c, layout);
+            assert_eq!(heap.usage().quarantined, 80);
+        }
+
+        heap.set_quarantine(0);
+        assert_eq!(heap.usage().quarantined, 0);
+
+        // Allocations larger than the quarantine are freed right away
+        heap.set_quarantine(16);
+        unsafe {
+            let a = heap.alloc(layout);
+            heap.dealloc(a, layout);
+        }
+        assert_eq!(heap.usage().quarantined, 0);
+    }
+
+    #[test]
+    fn realloc() {
+        let heap = Hardened::new();
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        unsafe {
+            let a = heap.alloc_zeroed(layout);
+            assert!(std::slice::from_raw_parts(a, 16)
+                .iter()
+                .all(|byte| *byte == 0));
+            a.write_bytes(7, 16);
+
+            // The data is kept and the canaries are rewritten for the new size
+            let a = heap.realloc(a, layout, 4096);
+            assert!(std::slice::from_raw_parts(a, 16)
+                .iter()
+                .all(|byte| *byte == 7));
+            assert_eq!(a.sub(HEADER).cast::<u64>().read(), 4096);
+            assert_eq!(heap.usage().allocated, 4096);
+
+            let grown = Layout::from_size_align(4096, 8).unwrap();
+            let a = heap.realloc(a, grown, 8);
+            assert!(std::slice::from_raw_parts(a, 8)
+                .iter()
+                .all(|byte| *byte == 7));
+            assert_eq!(heap.usage().allocated, 8);
+
+            heap.dealloc(a, Layout::from_size_align(8, 8).unwrap());
+        }
+        assert_eq!(heap.usage().allocated, 0);
+        assert_eq!(heap.usage().peak, 4096);
+    }
+
+    #[test]
+    fn canaries() {
+        let heap = Hardened::new();
+        let layout = Layout::from_size_align(13, 1).unwrap();
+        unsafe {
+            let a = heap.alloc(layout);
+            let b = heap.alloc(layout);
+            assert_ne!(
+                a.add(13).cast::<u64>().read_unaligned(),
+                b.add(13).cast::<u64>().read_unaligned()
+            );
+            assert_eq!(a.sub(HEADER).cast::<u64>().read(), 13);
+            heap.dealloc(a, layout);
+            heap.dealloc(b, layout);
+        }
+    }
+}
@@ -6,13 +6,27 @@
 #![deny(clippy::all)]
 #![warn(rust_2018_idioms)]
 
+mod cache;
+mod dataset;
+mod heap;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+mod provenance;
 mod runtime;
+mod task;
 mod workload;
 
+pub use cache::{cpu_fingerprint, precompile, verify_artifact};
+pub use dataset::dataset_digest;
+pub use heap::{Hardened, Heap, Usage, HEAP};
+pub use provenance::{Provenance, Statement, PACKAGE_PROVENANCE};
+pub use runtime::Reexec;
 pub use workload::{Package, Workload, PACKAGE_CONFIG, PACKAGE_ENTRYPOINT};
 
 use runtime::Runtime;
 
+use std::collections::HashMap;
+
 /// The Arguments
 // NOTE: `repr(C)` is required, otherwise `toml` serialization fails with `values must be emitted before tables`
 #[derive(Debug)]
@@ -21,11 +35,19 @@ use runtime::Runtime;
 pub struct Args {
     /// Package
     pub package: Package,
+
+    /// Secrets resolved by the host, which are provided to the application as environment variables
+    #[cfg_attr(unix, serde(default))]
+    pub secrets: HashMap<String, String>,
+
+    /// Relaunch requested by the workload of the previous Keep, whose config the package must have
+    #[cfg_attr(unix, serde(default))]
+    pub reexec: Option<Reexec>,
 }
 
 /// Execute
 pub fn execute_with_args(args: Args) -> anyhow::Result<()> {
-    Runtime::execute(args.package).map(|_| ())
+    Runtime::execute(args.package, args.secrets, args.reexec).map(|_| ())
 }
 
 /// Execute
@@ -34,6 +56,7 @@ pub fn execute_with_args(args: Args) -> anyhow::Result<()> {
 #[cfg(unix)]
 pub fn execute() -> anyhow::Result<()> {
     use anyhow::Context;
+    use enarx_error::{Classify, ErrorKind};
     use std::io::Read;
     use std::mem::forget;
     use std::os::unix::io::FromRawFd;
@@ -46,12 +69,15 @@ pub fn execute() -> anyhow::Result<()> {
 
     let mut args = String::new();
     host.read_to_string(&mut args)
-        .context("failed to read arguments")?;
+        .context("failed to read arguments")
+        .classify(ErrorKind::Io)?;
 
     // The FD is managed by the host or its parent.
     forget(host);
 
-    let args = toml::from_str::<Args>(&args).context("failed to decode arguments")?;
+    let args = toml::from_str::<Args>(&args)
+        .context("failed to decode arguments")
+        .classify(ErrorKind::Config)?;
 
     execute_with_args(args)?;
 
@@ -67,6 +93,8 @@ mod test {
     use std::os::unix::prelude::AsRawFd;
 
     use anyhow::Context;
+    use enarx_error::{exit_code, ErrorKind, Exit};
+    use sha2::{Digest, Sha256};
     use tempfile::tempfile;
     use wasmtime::Val;
 
@@ -104,17 +132,289 @@ mod test {
       (data (i32.const 0) "Hello, world!\0a")
     )"#;
 
+    const SPLICE_WAT: &str = r#"(module
+      (import "enarx" "splice"
+        (func $splice (param i32 i32 i64) (result i64)))
+      (func (export "") (result i64 i64)
+        (call $splice (i32.const 0) (i32.const 1) (i64.const 16))
+        (call $splice (i32.const 0) (i32.const 99) (i64.const 16))
+      )
+    )"#;
+
+    const CLOCK_SKEWS_WAT: &str = r#"(module
+      (import "enarx" "clock_skews" (func $clock_skews (result i64)))
+      (func (export "") (result i64) (call $clock_skews))
+    )"#;
+
+    const CLOCK_TRUSTED_WAT: &str = r#"(module
+      (import "enarx" "clock_trusted" (func $clock_trusted (result i32)))
+      (func (export "") (result i32) (call $clock_trusted))
+    )"#;
+
+    const TTY_WAT: &str = r#"(module
+      (import "enarx" "tty_winsize" (func $tty_winsize (param i32) (result i64)))
+      (import "enarx" "tty_raw" (func $tty_raw (param i32 i32) (result i64)))
+      (func (export "") (result i64 i64 i64)
+        (call $tty_winsize (i32.const 0))
+        (call $tty_raw (i32.const 0) (i32.const 0))
+        (call $tty_winsize (i32.const 99))
+      )
+    )"#;
+
+    const CAPABILITY_WAT: &str = r#"(module
+      (import "enarx" "capability" (func $capability (param i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "debugproduction")
+      (func (export "") (result i32 i32 i32)
+        (call $capability (i32.const 0) (i32.const 5))
+        (call $capability (i32.const 5) (i32.const 10))
+        (call $capability (i32.const 0) (i32.const 4))
+      )
+    )"#;
+
+    const ATTEST_WAT: &str = r#"(module
+      (import "enarx" "attest" (func $attest (param i32 i32 i32 i32) (result i32)))
+      (import "enarx" "attestation_format" (func $attestation_format (result i32)))
+      (import "enarx" "attestation_header" (func $attestation_header (param i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "") (result i32 i32 i32 i32)
+        (call $attest (i32.const 0) (i32.const 64) (i32.const 64) (i32.const 4096))
+        (call $attest (i32.const 0) (i32.const 65) (i32.const 64) (i32.const 4096))
+        (call $attestation_format)
+        (call $attestation_header (i32.const 0) (i32.const 100) (i32.const 128) (i32.const 4096))
+      )
+    )"#;
+
+    const CLOUD_WAT: &str = r#"(module
+      (import "wasi:config/runtime" "get" (func $config_get (param i32 i32 i32 i32) (result i32)))
+      (import "wasi:keyvalue/store" "open" (func $open (param i32 i32) (result i32)))
+      (import "wasi:keyvalue/store" "set" (func $set (param i32 i32 i32 i32 i32) (result i32)))
+      (import "wasi:keyvalue/store" "get" (func $get (param i32 i32 i32 i32 i32) (result i32)))
+      (import "wasi:keyvalue/store" "exists" (func $exists (param i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "portusers")
+      (func (export "") (result i32 i32 i32 i32 i32 i32)
+        (local $bucket i32)
+        (call $config_get (i32.const 0) (i32.const 4) (i32.const 64) (i32.const 16))
+        (i32.load (i32.const 64))
+        (local.set $bucket (call $open (i32.const 4) (i32.const 5)))
+        (local.get $bucket)
+        (call $set (local.get $bucket) (i32.const 0) (i32.const 4) (i32.const 64) (i32.const 4))
+        (call $get (local.get $bucket) (i32.const 0) (i32.const 4) (i32.const 128) (i32.const 16))
+        (call $exists (local.get $bucket) (i32.const 4) (i32.const 5))
+      )
+    )"#;
+
+    const CPU_FEATURES_WAT: &str = r#"(module
+      (import "enarx" "cpu_features" (func $cpu_features (result i64)))
+      (func (export "") (result i64) (call $cpu_features))
+    )"#;
+
+    const DETERMINISM_WAT: &str = r#"(module
+      (memory (export "memory") 1)
+      (global $x (mut f32) (f32.const -1))
+      (func (export "") (result i32)
+        (i32.reinterpret_f32 (f32.sqrt (global.get $x)))
+      )
+    )"#;
+
+    const NETWORK_WAT: &str = r#"(module
+      (import "enarx" "resolve" (func $resolve (param i32 i32 i32 i32) (result i32)))
+      (import "enarx" "tcp_connect" (func $tcp_connect (param i32 i32) (result i32)))
+      (import "enarx" "udp_bind" (func $udp_bind (param i32 i32) (result i32)))
+      (import "enarx" "udp_send" (func $udp_send (param i32 i32 i32 i32 i32) (result i32)))
+      (import "enarx" "udp_recv" (func $udp_recv (param i32 i32 i32 i32 i32 i64) (result i32)))
+      (import "enarx" "udp_close" (func $udp_close (param i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "127.0.0.1:0")
+      (data (i32.const 16) "127.0.0.1:9")
+      (data (i32.const 32) "localhost")
+      (func (export "") (result i32 i32 i32 i32 i32 i32 i32)
+        (call $resolve (i32.const 32) (i32.const 9) (i32.const 256) (i32.const 256))
+        (call $tcp_connect (i32.const 16) (i32.const 11))
+        (call $udp_bind (i32.const 0) (i32.const 11))
+        (call $udp_send (i32.const 0) (i32.const 64) (i32.const 4) (i32.const 16) (i32.const 11))
+        (call $udp_recv (i32.const 0) (i32.const 256) (i32.const 16) (i32.const 512) (i32.const 64) (i64.const 0))
+        (call $udp_close (i32.const 0))
+        (call $udp_close (i32.const 0))
+      )
+    )"#;
+
+    #[cfg(feature = "plugins")]
+    const PLUGIN_WAT: &str = r#"(module
+      (import "test" "answer" (func $answer (result i32)))
+      (func (export "") (result i32) (call $answer))
+    )"#;
+
+    const RESILIENCE_WAT: &str = r#"(module
+      (import "enarx" "token_bucket" (func $token_bucket (param i32 i32 i64 i64 i64) (result i64)))
+      (import "enarx" "concurrency_acquire" (func $concurrency_acquire (param i32 i32 i32) (result i32)))
+      (import "enarx" "concurrency_release" (func $concurrency_release (param i32 i32) (result i32)))
+      (import "enarx" "circuit_allow" (func $circuit_allow (param i32 i32 i64) (result i32)))
+      (import "enarx" "circuit_report" (func $circuit_report (param i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "api")
+      (func (export "") (result i32 i32 i32 i32 i32 i32 i32 i32)
+        (i32.wrap_i64 (call $token_bucket (i32.const 0) (i32.const 3) (i64.const 1) (i64.const 0) (i64.const 1)))
+        (i32.wrap_i64 (call $token_bucket (i32.const 0) (i32.const 3) (i64.const 1) (i64.const 0) (i64.const 1)))
+        (call $concurrency_acquire (i32.const 0) (i32.const 3) (i32.const 1))
+        (call $concurrency_acquire (i32.const 0) (i32.const 3) (i32.const 1))
+        (call $concurrency_release (i32.const 0) (i32.const 3))
+        (call $circuit_report (i32.const 0) (i32.const 3) (i32.const 0) (i32.const 1))
+        (call $circuit_allow (i32.const 0) (i32.const 3) (i64.const 60000))
+        (call $circuit_allow (i32.const 0) (i32.const 257) (i64.const 60000))
+      )
+    )"#;
+
+    const THREADS_WAT: &str = r#"(module
+      (import "env" "memory" (memory 1 1 shared))
+      (import "wasi" "thread-spawn" (func $thread_spawn (param i32) (result i32)))
+      (func (export "wasi_thread_start") (param $tid i32) (param $arg i32)
+        (i32.atomic.store (i32.const 0) (local.get $arg))
+      )
+      (func (export "") (result i32 i32)
+        (local $tid i32)
+        (local.set $tid (call $thread_spawn (i32.const 42)))
+        (if (i32.gt_s (local.get $tid) (i32.const 0))
+          (then (loop $wait
+            (br_if $wait (i32.eqz (i32.atomic.load (i32.const 0))))
+          ))
+        )
+        (local.get $tid)
+        (i32.atomic.load (i32.const 0))
+      )
+    )"#;
+
+    const TMP_WAT: &str = r#"(module
+      (import "wasi_snapshot_preview1" "fd_prestat_dir_name"
+        (func $fd_prestat_dir_name (param i32 i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "path_open"
+        (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "x")
+      (data (i32.const 8) "01234567")
+      (data (i32.const 40) "\08\00\00\00\08\00\00\00")
+      (func (export "") (result i32 i32 i32 i32 i32)
+        ;; preopened directories start at fd 3, even with a single file
+        (call $fd_prestat_dir_name (i32.const 3) (i32.const 16) (i32.const 4))
+        (i32.load (i32.const 16))
+        (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 1)
+          (i64.const 0x1fffffff) (i64.const 0x1fffffff) (i32.const 0) (i32.const 32))
+        (call $fd_write (i32.load (i32.const 32)) (i32.const 40) (i32.const 1) (i32.const 48))
+        (i32.load (i32.const 48))
+      )
+    )"#;
+
+    const TMPFILE_WAT: &str = r#"(module
+      (import "enarx" "tmpfile_open" (func $tmpfile_open (param i32) (result i32)))
+      (import "enarx" "tmpfile_link" (func $tmpfile_link (param i32 i32 i32 i32) (result i32)))
+      (import "enarx" "path_rename_noreplace"
+        (func $path_rename_noreplace (param i32 i32 i32 i32 i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 0) "a.new")
+      (data (i32.const 8) "a")
+      (data (i32.const 16) "data")
+      (data (i32.const 32) "\10\00\00\00\04\00\00\00")
+      (func (export "") (result i32 i32 i32 i32 i32 i32)
+        (local $fd i32)
+        (local.set $fd (call $tmpfile_open (i32.const 3)))
+        (call $fd_write (local.get $fd) (i32.const 32) (i32.const 1) (i32.const 48))
+        (call $tmpfile_link (local.get $fd) (i32.const 3) (i32.const 0) (i32.const 5))
+        ;; hard links are not supported
+        (call $tmpfile_link (local.get $fd) (i32.const 3) (i32.const 8) (i32.const 1))
+        (call $path_rename_noreplace
+          (i32.const 3) (i32.const 0) (i32.const 5) (i32.const 3) (i32.const 8) (i32.const 1))
+        (call $path_rename_noreplace
+          (i32.const 3) (i32.const 8) (i32.const 1) (i32.const 3) (i32.const 8) (i32.const 1))
+        ;; the null file is not in the temporary directory
+        (call $tmpfile_open (i32.const 0))
+      )
+    )"#;
+
+    const TRAP_WAT: &str = r#"(module
+      (func (export "") unreachable)
+    )"#;
+
+    const EXIT_3_WAT: &str = r#"(module
+      (import "wasi_snapshot_preview1" "proc_exit"
+        (func $__wasi_proc_exit (param i32)))
+      (func (export "") (call $__wasi_proc_exit (i32.const 3)))
+    )"#;
+
+    const TWO_TABLES_WAT: &str = r#"(module
+      (table 10 funcref)
+      (table 20 funcref)
+      (func (export "") (result i32) i32.const 1)
+    )"#;
+
     pub fn run(wasm: &[u8]) -> anyhow::Result<Vec<Val>> {
+        run_with_conf(wasm, None)
+    }
+
+    pub fn run_with_conf(wasm: &[u8], conf: Option<&str>) -> anyhow::Result<Vec<Val>> {
+        run_with(wasm, conf, None)
+    }
+
+    pub fn run_with(
+        wasm: &[u8],
+        conf: Option<&str>,
+        artifact: Option<&[u8]>,
+    ) -> anyhow::Result<Vec<Val>> {
         let mut file = tempfile().context("failed to create module file")?;
         file.write(wasm).context("failed to write module to file")?;
         file.rewind().context("failed to rewind file")?;
-        Runtime::execute(Package::Local {
-            #[cfg(unix)]
-            wasm: file.as_raw_fd(),
-            #[cfg(windows)]
-            wasm: file,
-            conf: None,
-        })
+
+        let conf = if let Some(conf) = conf {
+            let mut conf_file = tempfile().context("failed to create config file")?;
+            conf_file
+                .write(conf.as_bytes())
+                .context("failed to write config to file")?;
+            conf_file.rewind().context("failed to rewind file")?;
+            Some(conf_file)
+        } else {
+            None
+        };
+
+        let cache = if let Some(artifact) = artifact {
+            let mut cache_file = tempfile().context("failed to create artifact file")?;
+            cache_file
+                .write(artifact)
+                .context("failed to write artifact to file")?;
+            cache_file.rewind().context("failed to rewind file")?;
+            Some(cache_file)
+        } else {
+            None
+        };
+
+        Runtime::execute(
+            Package::Local {
+                #[cfg(unix)]
+                wasm: file.as_raw_fd(),
+                #[cfg(windows)]
+                wasm: file,
+                #[cfg(unix)]
+                conf: conf.as_ref().map(AsRawFd::as_raw_fd),
+                #[cfg(windows)]
+                conf,
+                #[cfg(unix)]
+                cache: cache.as_ref().map(AsRawFd::as_raw_fd),
+                #[cfg(windows)]
+                cache,
+                provenance: None,
+                sidecar: None,
+                datasets: Default::default(),
+                tap: None,
+                channel: None,
+                offload: Default::default(),
+                helpers: Default::default(),
+            },
+            Default::default(),
+            None,
+        )
     }
 
     #[test]
@@ -149,4 +449,485 @@ mod test {
         // TODO/FIXME: we need a way to configure WASI stdout so we can capture
         // and check it here...
     }
+
+    #[test]
+    fn workload_run_limits() {
+        let bytes = wat::parse_str(TWO_TABLES_WAT).expect("error parsing wat");
+
+        let results: Vec<i32> =
+            run_with_conf(&bytes, Some("[limits]\ntables = 2\ntable_elements = 20"))
+                .unwrap()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect();
+        assert_eq!(results, vec![1]);
+
+        for conf in [
+            "[limits]\nmodule_size = 10",
+            "[limits]\ntables = 1",
+            "[limits]\ntable_elements = 15",
+            "[limits]\ncode_size = 1",
+        ] {
+            let err = run_with_conf(&bytes, Some(conf)).unwrap_err();
+            assert!(format!("{err:#}").contains("exceeds the limit"), "{err:#}");
+            assert_eq!(ErrorKind::of(&err), Some(ErrorKind::Config));
+        }
+    }
+
+    #[test]
+    fn workload_run_process() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+
+        let results: Vec<i32> = run_with_conf(&bytes, Some("[process]\ncwd = \"/app\""))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![1]);
+
+        for conf in [
+            "[process]\ncwd = \"app\"",
+            "[process]\numask = 0o7777",
+            "[process]\ncwd = \"/app\"\n[env]\nPWD = \"/\"",
+        ] {
+            let err = run_with_conf(&bytes, Some(conf)).unwrap_err();
+            assert_eq!(ErrorKind::of(&err), Some(ErrorKind::Config), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn workload_run_precompiled() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let artifact = precompile(&bytes, None).unwrap();
+        assert!(verify_artifact(&artifact, Some(&bytes)).is_ok());
+
+        let other = wat::parse_str(TWO_TABLES_WAT).expect("error parsing wat");
+        assert!(verify_artifact(&artifact, Some(&other)).is_err());
+
+        let mut corrupted = artifact.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        assert!(verify_artifact(&corrupted, None).is_err());
+        assert!(verify_artifact(&artifact[..16], None).is_err());
+
+        // Artifacts compiled for other CPU features are rejected
+        let mut foreign = artifact.clone();
+        foreign[8] ^= 0x80;
+        assert!(verify_artifact(&foreign, None).is_err());
+
+        // Artifacts bound to the package config must match its digest
+        let digest = format!("sha256:{}", hex::encode(Sha256::digest(&artifact)));
+        assert!(cache::check_digest(&artifact, &digest).is_ok());
+        assert!(cache::check_digest(&corrupted, &digest).is_err());
+        for digest in [&digest, "sha256:00"] {
+            let conf = format!("[precompiled]\ndigest = \"{digest}\"");
+            let results: Vec<i32> = run_with(&bytes, Some(&conf), Some(&artifact))
+                .unwrap()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect();
+            assert_eq!(results, vec![1]);
+        }
+
+        // Invalid artifacts are ignored and the module is compiled instead
+        for artifact in [&artifact, &corrupted, &foreign] {
+            let results: Vec<i32> = run_with(&bytes, None, Some(artifact))
+                .unwrap()
+                .iter()
+                .map(wasmtime::Val::unwrap_i32)
+                .collect();
+            assert_eq!(results, vec![1]);
+        }
+    }
+
+    #[test]
+    fn workload_run_error_kind() {
+        let bytes = wat::parse_str(RETURN_1_WAT).expect("error parsing wat");
+        let err = run_with_conf(&bytes, Some("args = 1")).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::Config));
+        assert_eq!(exit_code(&err), 78);
+
+        let bytes = wat::parse_str(TRAP_WAT).expect("error parsing wat");
+        let err = run(&bytes).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::Trap));
+        assert_eq!(exit_code(&err), 70);
+
+        // A non-zero exit status of the workload is not an error class
+        let bytes = wat::parse_str(EXIT_3_WAT).expect("error parsing wat");
+        let err = run(&bytes).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), None);
+        assert_eq!(Exit::of(&err), Some(Exit(3)));
+        assert_eq!(exit_code(&err), 3);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn workload_run_splice() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(SPLICE_WAT).expect("error parsing wat");
+        let errno = |errno| -i64::from(u16::from(errno));
+
+        let results: Vec<i64> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i64)
+            .collect();
+        assert_eq!(results, vec![errno(Errno::Perm), errno(Errno::Badf)]);
+
+        // Both file descriptors have to be non-confidential
+        let conf = r#"
+[[files]]
+kind = "stdin"
+
+[[files]]
+kind = "stdout"
+confidential = false
+"#;
+        let results: Vec<i64> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i64)
+            .collect();
+        assert_eq!(results, vec![errno(Errno::Perm), errno(Errno::Badf)]);
+    }
+
+    #[test]
+    fn workload_run_clock_skews() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(CLOCK_SKEWS_WAT).expect("error parsing wat");
+
+        // Outside of a Keep there is no trusted clock
+        let results: Vec<i64> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i64)
+            .collect();
+        assert_eq!(results, vec![-i64::from(u16::from(Errno::Notsup))]);
+
+        let bytes = wat::parse_str(CLOCK_TRUSTED_WAT).expect("error parsing wat");
+        let results: Vec<i32> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![-i32::from(u16::from(Errno::Notsup))]);
+    }
+
+    #[test]
+    fn workload_run_capability() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(CAPABILITY_WAT).expect("error parsing wat");
+
+        // Outside of a Keep only capabilities without requirements are enabled
+        let conf = "[capabilities.debug]\n[capabilities.production]\nproduction = true";
+        let results: Vec<i32> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![1, 0, -i32::from(u16::from(Errno::Noent))]);
+    }
+
+    #[test]
+    fn workload_run_attest() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(ATTEST_WAT).expect("error parsing wat");
+
+        // outside of a Keep, there is no attestation evidence
+        let results: Vec<i32> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                -i32::from(u16::from(Errno::Notsup)),
+                -i32::from(u16::from(Errno::Inval)),
+                0,
+                -i32::from(u16::from(Errno::Notsup)),
+            ]
+        );
+    }
+
+    #[test]
+    fn workload_run_cloud() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(CLOUD_WAT).expect("error parsing wat");
+
+        // Without a key-value store, the config value is read and all store functions fail
+        let conf = "[config]\nport = \"8080\"";
+        let results: Vec<i32> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        let notcapable = -i32::from(u16::from(Errno::Notcapable));
+        assert_eq!(
+            results,
+            vec![
+                4,
+                i32::from_le_bytes(*b"8080"),
+                notcapable,
+                notcapable,
+                notcapable,
+                notcapable
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let conf = format!(
+            "[config]\nport = \"8080\"\n[keyvalue]\npath = '{}'",
+            dir.path().display()
+        );
+        let results: Vec<i32> = run_with_conf(&bytes, Some(&conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![4, i32::from_le_bytes(*b"8080"), 0, 0, 4, 0]);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn workload_run_cpu_features() {
+        let bytes = wat::parse_str(CPU_FEATURES_WAT).expect("error parsing wat");
+
+        let results: Vec<i64> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i64)
+            .collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0] & !0b111, 0);
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(results[0] & 1 != 0, is_x86_feature_detected!("aes"));
+    }
+
+    #[test]
+    fn workload_run_determinism() {
+        let bytes = wat::parse_str(DETERMINISM_WAT).expect("error parsing wat");
+
+        // The growth of the memory depends on the host without a limit
+        let err = run_with_conf(&bytes, Some("[determinism]")).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::Config), "{err:#}");
+
+        // NaN results are canonical
+        let conf = "[determinism]\n[limits]\nmemory_size = 1048576";
+        let results: Vec<i32> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![0x7fc0_0000]);
+    }
+
+    #[test]
+    fn workload_run_network() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(NETWORK_WAT).expect("error parsing wat");
+
+        // without a network policy, only ephemeral UDP sockets can be bound
+        let results: Vec<i32> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                -i32::from(u16::from(Errno::Acces)),
+                -i32::from(u16::from(Errno::Acces)),
+                0,
+                -i32::from(u16::from(Errno::Acces)),
+                -i32::from(u16::from(Errno::Again)),
+                0,
+                -i32::from(u16::from(Errno::Badf)),
+            ]
+        );
+    }
+
+    #[cfg(feature = "plugins")]
+    #[test]
+    fn workload_run_plugin() {
+        fn add_to_linker(linker: &mut wasmtime::Linker<plugin::Ctx>) -> anyhow::Result<()> {
+            linker.func_wrap("test", "answer", || 42)?;
+            Ok(())
+        }
+
+        plugin::register("test", add_to_linker).unwrap();
+        assert!(plugin::register("test", add_to_linker).is_err());
+
+        let bytes = wat::parse_str(PLUGIN_WAT).expect("error parsing wat");
+        let results: Vec<i32> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![42]);
+    }
+
+    #[test]
+    fn workload_run_resilience() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(RESILIENCE_WAT).expect("error parsing wat");
+
+        let results: Vec<i32> = run(&bytes)
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                0,
+                -i32::from(u16::from(Errno::Again)),
+                0,
+                -i32::from(u16::from(Errno::Again)),
+                0,
+                1,
+                1,
+                -i32::from(u16::from(Errno::Nametoolong)),
+            ]
+        );
+    }
+
+    #[test]
+    fn workload_run_threads() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(THREADS_WAT).expect("error parsing wat");
+
+        let conf = r#"
+[limits]
+memory_size = 1048576
+threads = 1
+"#;
+        let results: Vec<i32> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![1, 42]);
+
+        // Shared memories are only accepted, if the workload may spawn threads
+        assert!(run(&bytes).is_err());
+
+        let conf = r#"
+[limits]
+memory_size = 1048576
+threads = 0
+"#;
+        let results: Vec<i32> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![-i32::from(u16::from(Errno::Again)), 0]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn workload_run_tmp() {
+        let bytes = wat::parse_str(TMP_WAT).expect("error parsing wat");
+
+        // The quota leaves room for the file and 4 bytes of data
+        let conf = r#"
+[[files]]
+kind = "null"
+
+[tmp]
+size = 260
+"#;
+        let results: Vec<i32> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(results, vec![0, i32::from_le_bytes(*b"/tmp"), 0, 0, 4]);
+
+        // Without a temporary directory, fd 3 is not preopened
+        let conf = "[[files]]\nkind = \"null\"";
+        let results: Vec<i32> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_ne!(results[0], 0);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn workload_run_tmpfile() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(TMPFILE_WAT).expect("error parsing wat");
+        let errno = |errno| -i32::from(u16::from(errno));
+
+        let conf = r#"
+[[files]]
+kind = "null"
+
+[tmp]
+size = "1MiB"
+"#;
+        let results: Vec<i32> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i32)
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                0,
+                0,
+                errno(Errno::Notsup),
+                0,
+                errno(Errno::Exist),
+                errno(Errno::Xdev)
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn workload_run_tty() {
+        use wasi_common::snapshots::preview_1::types::Errno;
+
+        let bytes = wat::parse_str(TTY_WAT).expect("error parsing wat");
+        let errno = |errno| -i64::from(u16::from(errno));
+
+        // The null file is not a terminal
+        let conf = r#"
+[[files]]
+kind = "null"
+"#;
+        let results: Vec<i64> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i64)
+            .collect();
+        assert_eq!(
+            results,
+            vec![errno(Errno::Notty), errno(Errno::Notty), errno(Errno::Badf)]
+        );
+
+        // Disabling the raw mode of a terminal, which is not in raw mode, is a no-op
+        let conf = r#"
+[[files]]
+kind = "stdin"
+"#;
+        let results: Vec<i64> = run_with_conf(&bytes, Some(conf))
+            .unwrap()
+            .iter()
+            .map(wasmtime::Val::unwrap_i64)
+            .collect();
+        assert_eq!(results[1..], [0, errno(Errno::Badf)]);
+    }
 }
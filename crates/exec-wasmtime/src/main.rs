@@ -5,10 +5,15 @@
 #![deny(clippy::all)]
 #![warn(rust_2018_idioms)]
 
-use enarx_exec_wasmtime::execute;
+use enarx_error::{exit_code, Exit};
+use enarx_exec_wasmtime::{execute, Heap};
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
 
+/// Heap guarded by canaries
+#[global_allocator]
+static ALLOCATOR: Heap = Heap;
+
 /// Set FSBASE
 ///
 /// Overwrite the only location in musl, which uses the `arch_prctl` syscall
@@ -35,11 +40,17 @@ pub extern "C" fn __set_thread_area(p: *mut core::ffi::c_void) -> core::ffi::c_i
     rax as _
 }
 
-fn main() -> anyhow::Result<()> {
+fn main() {
     tracing_subscriber::registry()
         .with(fmt::layer())
         .with(EnvFilter::from_default_env())
         .init();
 
-    execute()
+    if let Err(e) = execute() {
+        // The workload reports the reason of its exit itself
+        if Exit::of(&e).is_none() {
+            eprintln!("Error: {e:?}");
+        }
+        std::process::exit(exit_code(&e));
+    }
 }
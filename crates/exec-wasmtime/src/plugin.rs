@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host function plugins of custom builds
+//!
+//! Downstream builds of exec-wasmtime can link additional host functions into the workload,
+//! e.g. stubs to submit GPU jobs or clients of a proprietary HSM, without forking the linker
+//! setup. A build registers its plugins with [`register`] before executing the workload.
+//!
+//! Plugins are added to the linker after the WASI and `enarx` functions. The linker does not
+//! allow shadowing, so a plugin defining an import, which is already defined, fails the execution.
+
+use std::sync::Mutex;
+
+use anyhow::{bail, Context};
+use wasmtime::Linker;
+
+pub use crate::runtime::Ctx;
+pub use wasmtime;
+
+/// Adds the host functions of a plugin to `linker`
+pub type AddToLinker = fn(&mut Linker<Ctx>) -> anyhow::Result<()>;
+
+/// A registered plugin
+struct Plugin {
+    name: &'static str,
+    add_to_linker: AddToLinker,
+}
+
+/// The registered plugins in the order of registration
+static PLUGINS: Mutex<Vec<Plugin>> = Mutex::new(Vec::new());
+
+/// Registers the plugin `name`, whose functions are added to the linker with `add_to_linker`
+///
+/// Fails, if a plugin with the same name is already registered.
+pub fn register(name: &'static str, add_to_linker: AddToLinker) -> anyhow::Result<()> {
+    let mut plugins = PLUGINS.lock().unwrap();
+    if plugins.iter().any(|plugin| plugin.name == name) {
+        bail!("plugin `{name}` is already registered");
+    }
+    plugins.push(Plugin {
+        name,
+        add_to_linker,
+    });
+    Ok(())
+}
+
+/// Adds the functions of all registered plugins to `linker`
+pub(crate) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    for plugin in PLUGINS.lock().unwrap().iter() {
+        (plugin.add_to_linker)(linker)
+            .with_context(|| format!("failed to add plugin `{}`", plugin.name))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,269 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Build provenance of Wasm modules
+//!
+//! A provenance statement names the builder, source repository and commit of a Wasm module
+//! together with its digest. It is signed by the build pipeline and published with the package
+//! as [`PACKAGE_PROVENANCE`]. The Keep verifies it against the `provenance` policy of the
+//! Enarx.toml before the module is compiled, so only modules of approved pipelines are run.
+//!
+//! The signature covers the exact bytes of the JSON encoded statement, which are embedded as a
+//! string, so verification does not depend on a canonical JSON encoding.
+
+use anyhow::{anyhow, ensure, Context};
+use drawbridge_client::types::TreeName;
+use enarx_config::Provenance as Policy;
+use once_cell::sync::Lazy;
+use ring::rand::SystemRandom;
+use ring::signature::{
+    EcdsaKeyPair, KeyPair, UnparsedPublicKey, ECDSA_P256_SHA256_ASN1,
+    ECDSA_P256_SHA256_ASN1_SIGNING,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+use ureq::serde_json;
+
+/// Name of package provenance file
+pub static PACKAGE_PROVENANCE: Lazy<TreeName> = Lazy::new(|| "provenance.json".parse().unwrap());
+
+/// Prefix of the digest of a statement
+const DIGEST_PREFIX: &str = "sha256:";
+
+/// Origin of a Wasm module
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Statement {
+    /// Identity of the builder, e.g. the URL of a CI workflow
+    pub builder: String,
+
+    /// Source repository the module was built from
+    pub repository: String,
+
+    /// Commit of the source repository
+    pub commit: String,
+
+    /// Digest of the Wasm module in the form `sha256:<hex>`
+    pub digest: String,
+}
+
+impl Statement {
+    /// Creates a statement of `webasm`
+    pub fn new(
+        builder: impl Into<String>,
+        repository: impl Into<String>,
+        commit: impl Into<String>,
+        webasm: &[u8],
+    ) -> Self {
+        Self {
+            builder: builder.into(),
+            repository: repository.into(),
+            commit: commit.into(),
+            digest: format!("{DIGEST_PREFIX}{}", hex::encode(Sha256::digest(webasm))),
+        }
+    }
+}
+
+/// A signed provenance statement
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Provenance {
+    /// JSON encoded [`Statement`]
+    pub payload: String,
+
+    /// Hex-encoded uncompressed SEC1 ECDSA P-256 public key of the signer
+    pub key: String,
+
+    /// Hex-encoded ASN.1 ECDSA P-256 SHA-256 signature of `payload`
+    pub signature: String,
+}
+
+impl Provenance {
+    /// Signs `statement` with the ECDSA P-256 PKCS#8 key `pkcs8`
+    pub fn sign(statement: &Statement, pkcs8: &[u8]) -> anyhow::Result<Self> {
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8)
+            .map_err(|e| anyhow!("invalid ECDSA P-256 key: {e}"))?;
+        let payload =
+            serde_json::to_string(statement).context("failed to encode provenance statement")?;
+        let signature = key
+            .sign(&SystemRandom::new(), payload.as_bytes())
+            .map_err(|_| anyhow!("failed to sign provenance statement"))?;
+        Ok(Self {
+            payload,
+            key: hex::encode(key.public_key()),
+            signature: hex::encode(signature),
+        })
+    }
+
+    /// Verifies the provenance of `webasm` against `policy` and returns the statement
+    pub fn verify(&self, webasm: &[u8], policy: &Policy) -> anyhow::Result<Statement> {
+        ensure!(
+            policy
+                .keys
+                .iter()
+                .any(|key| key.eq_ignore_ascii_case(&self.key)),
+            "provenance signer `{}` is not trusted",
+            self.key
+        );
+        let key = hex::decode(&self.key).context("failed to decode provenance signer")?;
+        let signature =
+            hex::decode(&self.signature).context("failed to decode provenance signature")?;
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, key)
+            .verify(self.payload.as_bytes(), &signature)
+            .map_err(|_| anyhow!("invalid provenance signature"))?;
+
+        let statement: Statement =
+            serde_json::from_str(&self.payload).context("failed to decode provenance statement")?;
+        let digest = statement
+            .digest
+            .strip_prefix(DIGEST_PREFIX)
+            .and_then(|digest| hex::decode(digest).ok())
+            .with_context(|| format!("unsupported provenance digest `{}`", statement.digest))?;
+        ensure!(
+            digest == Sha256::digest(webasm).as_slice(),
+            "provenance digest does not match the Wasm module"
+        );
+        ensure!(
+            policy.builders.is_empty() || policy.builders.contains(&statement.builder),
+            "builder `{}` is not allowed",
+            statement.builder
+        );
+        ensure!(
+            policy.repositories.is_empty() || policy.repositories.contains(&statement.repository),
+            "repository `{}` is not allowed",
+            statement.repository
+        );
+        Ok(statement)
+    }
+}
+
+/// Checks, that `provenance` of `webasm` satisfies `policy`, if any
+pub(crate) fn check(
+    policy: Option<&Policy>,
+    provenance: Option<&Provenance>,
+    webasm: &[u8],
+) -> anyhow::Result<()> {
+    let policy = match policy {
+        Some(policy) => policy,
+        None => return Ok(()),
+    };
+    let statement = provenance
+        .context("package has no build provenance")?
+        .verify(webasm, policy)
+        .context("failed to verify build provenance")?;
+    info!(
+        builder = statement.builder,
+        repository = statement.repository,
+        commit = statement.commit,
+        "verified build provenance"
+    );
+    Ok(())
+}
+
+impl TryFrom<&[u8]> for Provenance {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        serde_json::from_slice(value).context("failed to decode provenance")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WASM: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn key() -> Vec<u8> {
+        EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new())
+            .unwrap()
+            .as_ref()
+            .to_vec()
+    }
+
+    fn signed(pkcs8: &[u8]) -> Provenance {
+        let statement = Statement::new(
+            "https://ci.example.com/release",
+            "https://github.com/enarx/enarx",
+            "0123456789abcdef",
+            WASM,
+        );
+        Provenance::sign(&statement, pkcs8).unwrap()
+    }
+
+    #[test]
+    fn verify() {
+        let provenance = signed(&key());
+        let policy = Policy {
+            keys: vec![provenance.key.to_uppercase()],
+            ..Default::default()
+        };
+        let statement = provenance.verify(WASM, &policy).unwrap();
+        assert_eq!(statement.commit, "0123456789abcdef");
+
+        let encoded = serde_json::to_vec(&provenance).unwrap();
+        let decoded = Provenance::try_from(encoded.as_slice()).unwrap();
+        assert_eq!(decoded, provenance);
+
+        // The module must match the digest
+        assert!(provenance.verify(b"\0asm\x01\0\0\x01", &policy).is_err());
+
+        // The builder and repository must be allowed
+        let restricted = Policy {
+            builders: vec!["https://ci.example.com/release".into()],
+            repositories: vec!["https://github.com/enarx/enarx".into()],
+            ..policy.clone()
+        };
+        assert!(provenance.verify(WASM, &restricted).is_ok());
+        let restricted = Policy {
+            repositories: vec!["https://github.com/enarx/other".into()],
+            ..policy.clone()
+        };
+        assert!(provenance.verify(WASM, &restricted).is_err());
+    }
+
+    #[test]
+    fn required() {
+        let provenance = signed(&key());
+        let policy = Policy {
+            keys: vec![provenance.key.clone()],
+            ..Default::default()
+        };
+        assert!(check(None, None, WASM).is_ok());
+        assert!(check(Some(&policy), Some(&provenance), WASM).is_ok());
+        assert!(check(Some(&policy), None, WASM).is_err());
+    }
+
+    #[test]
+    fn untrusted() {
+        let provenance = signed(&key());
+        let other = signed(&key());
+
+        // The signer must be trusted
+        let policy = Policy {
+            keys: vec![other.key.clone()],
+            ..Default::default()
+        };
+        assert!(provenance.verify(WASM, &policy).is_err());
+
+        // The signature must be of the trusted signer
+        let forged = Provenance {
+            key: other.key,
+            ..provenance.clone()
+        };
+        assert!(forged.verify(WASM, &policy).is_err());
+
+        // The payload must be signed
+        let policy = Policy {
+            keys: vec![provenance.key.clone()],
+            ..Default::default()
+        };
+        let tampered = Provenance {
+            payload: provenance
+                .payload
+                .replace("0123456789abcdef", "fedcba9876543210"),
+            ..provenance
+        };
+        assert!(tampered.verify(WASM, &policy).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Attestation evidence of the Keep for the workload
+//!
+//! The workload imports `attest` from the `enarx` module to prove to a remote relying party,
+//! that it runs in a Keep. The data of the workload, e.g. a hash of a public key or a nonce of
+//! the relying party, is bound to fresh evidence, i.e. an SGX quote or an SNP attestation report,
+//! which the shim obtains with the `GETATT` sallyport call. The report data of the evidence is
+//! the SHA-384 hash of [`WORKLOAD_PREFIX`] followed by the data, so the workload cannot forge
+//! evidence of the key of the Keep, whose report data is a hash of the key.
+//!
+//! For per-request freshness, a workload serving HTTP imports `attestation_header` to answer the
+//! challenge of a client with an `Enarx-Attestation` response header, which binds the challenge
+//! to fresh evidence without a separate attestation channel.
+
+use super::errno::negate;
+use super::identity::{Evidence, Platform, Technology};
+use super::keys::{memory, read, write};
+use super::Ctx;
+
+use anyhow::Context;
+use base64ct::{Base64, Encoding};
+use sha2::{Digest, Sha384, Sha512};
+use tracing::warn;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+
+/// Size of the report data bound to the evidence
+pub const REPORT_DATA_SIZE: usize = 64;
+
+/// Prefix of the data of the workload in the hash of the report data of `attest`
+pub const WORKLOAD_PREFIX: &[u8] = b"enarx-workload";
+
+/// No attestation, the Keep does not run in a hardware TEE
+pub const NONE: i32 = 0;
+
+/// SEV-SNP attestation report
+pub const SNP: i32 = 1;
+
+/// SGX quote
+pub const SGX: i32 = 2;
+
+/// Returns the SHA-384 hash of [`WORKLOAD_PREFIX`] followed by the up to [`REPORT_DATA_SIZE`]
+/// bytes of `data` of the workload
+fn workload_hash(data: &[u8]) -> Result<Vec<u8>, Errno> {
+    if data.len() > REPORT_DATA_SIZE {
+        return Err(Errno::Inval);
+    }
+    let hash = Sha384::new()
+        .chain_update(WORKLOAD_PREFIX)
+        .chain_update(data)
+        .finalize();
+    Ok(hash.to_vec())
+}
+
+/// Returns fresh evidence of the Keep bound to `data`, which is zero-padded to
+/// [`REPORT_DATA_SIZE`] bytes
+fn evidence(data: &[u8]) -> Result<Evidence, Errno> {
+    if data.len() > REPORT_DATA_SIZE {
+        return Err(Errno::Inval);
+    }
+    let mut report_data = [0; REPORT_DATA_SIZE];
+    report_data[..data.len()].copy_from_slice(data);
+
+    let platform = Platform::get().map_err(|e| {
+        warn!("failed to query platform: {e}");
+        Errno::Io
+    })?;
+    if platform.technology() == Technology::Kvm {
+        return Err(Errno::Notsup);
+    }
+    platform.attest(&report_data).map_err(|e| {
+        warn!("failed to attest Keep: {e}");
+        Errno::Io
+    })
+}
+
+/// Encodes `evidence` of `technology` as the value of an `Enarx-Attestation` header
+///
+/// The value is a structured field dictionary as defined by RFC 8941, e.g.
+/// `format=snp, evidence=:<base64>:`.
+fn encode_header(technology: Technology, evidence: &[u8]) -> String {
+    format!(
+        "format={}, evidence=:{}:",
+        technology.attester().name(),
+        Base64::encode_string(evidence)
+    )
+}
+
+/// Returns the value of an `Enarx-Attestation` header with fresh evidence bound to the SHA-512
+/// hash of the client `challenge`
+fn header(challenge: &[u8]) -> Result<String, Errno> {
+    let evidence = evidence(&Sha512::digest(challenge))?;
+    Ok(encode_header(evidence.attester().technology(), &evidence))
+}
+
+/// Returns the format of the evidence returned by `attest`
+fn attestation_format() -> i32 {
+    match Platform::get() {
+        Ok(platform) => platform.attester().format(),
+        Err(_) => NONE,
+    }
+}
+
+/// Binds the report data at `data` of `data_len` bytes to fresh attestation evidence of the
+/// Keep, writes the evidence to `buf` of `len` bytes and returns its length or the negated
+/// WASI errno
+///
+/// Fails with `ERRNO_INVAL`, if the report data exceeds 64 bytes, with `ERRNO_NOTSUP`, if the
+/// Keep does not run in a hardware TEE, and with `ERRNO_RANGE`, if the evidence does not fit
+/// into `buf`.
+fn attest(mut caller: Caller<'_, Ctx>, data: u32, data_len: u32, buf: u32, len: u32) -> i32 {
+    let res = read(&mut caller, data, data_len)
+        .and_then(|data| workload_hash(&data))
+        .and_then(|hash| evidence(&hash))
+        .and_then(|evidence| Ok((memory(&mut caller)?, evidence)));
+    negate(res.and_then(|(memory, evidence)| write(&mut caller, memory, buf, len, &evidence)))
+}
+
+/// Binds the SHA-512 hash of the client challenge at `challenge` of `challenge_len` bytes to
+/// fresh attestation evidence of the Keep, writes the value of an `Enarx-Attestation` HTTP
+/// response header to `buf` of `len` bytes and returns its length or the negated WASI errno
+///
+/// Fails with `ERRNO_NOTSUP`, if the Keep does not run in a hardware TEE, and with
+/// `ERRNO_RANGE`, if the header value does not fit into `buf`.
+fn attestation_header(
+    mut caller: Caller<'_, Ctx>,
+    challenge: u32,
+    challenge_len: u32,
+    buf: u32,
+    len: u32,
+) -> i32 {
+    let res = read(&mut caller, challenge, challenge_len)
+        .and_then(|challenge| header(&challenge))
+        .and_then(|header| Ok((memory(&mut caller)?, header)));
+    negate(res.and_then(|(memory, header)| write(&mut caller, memory, buf, len, header.as_bytes())))
+}
+
+/// Adds the `enarx` `attest`, `attestation_format` and `attestation_header` functions to `linker`
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "attest", attest)
+        .context("failed to add `enarx::attest`")?;
+    linker
+        .func_wrap("enarx", "attestation_format", attestation_format)
+        .context("failed to add `enarx::attestation_format`")?;
+    linker
+        .func_wrap("enarx", "attestation_header", attestation_header)
+        .context("failed to add `enarx::attestation_header`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_data() {
+        assert_eq!(evidence(&[0; REPORT_DATA_SIZE + 1]), Err(Errno::Inval));
+        assert_eq!(workload_hash(&[0; REPORT_DATA_SIZE + 1]), Err(Errno::Inval));
+    }
+
+    #[test]
+    fn workload_prefix() {
+        let hash = Sha384::digest(b"enarx-workloadnonce");
+        assert_eq!(workload_hash(b"nonce"), Ok(hash.to_vec()));
+    }
+
+    #[test]
+    fn header_encoding() {
+        assert_eq!(
+            encode_header(Technology::Snp, b"report"),
+            "format=snp, evidence=:cmVwb3J0:"
+        );
+        assert_eq!(
+            encode_header(Technology::Sgx, &[]),
+            "format=sgx, evidence=::"
+        );
+    }
+}
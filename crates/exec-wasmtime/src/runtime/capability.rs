@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Capabilities of the workload gated by attestation claims
+//!
+//! The Enarx.toml declares the claims required for each capability of the workload.
+//! The workload imports `capability` from the `enarx` module to check, whether a capability
+//! is enabled, so the same workload can run in development and production with risky
+//! features disabled outside of real TEEs.
+
+use super::errno::negate;
+use super::identity::{Claims, Tcb, Technology};
+use super::keys::memory;
+use super::Ctx;
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use enarx_config::{Capability, MinTcb};
+use tracing::{info, warn};
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+
+/// Returns the reason, why `claims` do not satisfy the requirements of `capability`
+fn unsatisfied(capability: &Capability, claims: &Claims) -> Option<&'static str> {
+    if capability.production && claims.technology == Technology::Kvm {
+        return Some("Keep does not run in a hardware TEE");
+    }
+    if capability.production && claims.debug {
+        return Some("Keep allows debugging");
+    }
+
+    match (&capability.min_tcb, &claims.tcb) {
+        (None, _) => None,
+        (Some(MinTcb { snp: Some(min), .. }), Some(Tcb::Snp(tcb))) => {
+            let ok = tcb.bootloader >= min.bootloader
+                && tcb.tee >= min.tee
+                && tcb.snp >= min.snp
+                && tcb.microcode >= min.microcode;
+            (!ok).then_some("SNP TCB version is below the minimum")
+        }
+        (Some(MinTcb { sgx: Some(min), .. }), Some(Tcb::Sgx(tcb))) => {
+            let ok = tcb.pcesvn >= min.pcesvn
+                && tcb
+                    .cpusvn
+                    .iter()
+                    .zip(min.cpusvn)
+                    .all(|(&svn, min)| svn >= min);
+            (!ok).then_some("SGX TCB version is below the minimum")
+        }
+        (Some(_), _) => Some("no minimum TCB version for the TEE technology"),
+    }
+}
+
+/// The enabled state of the capabilities declared in the Enarx.toml
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities(HashMap<String, bool>);
+
+impl Capabilities {
+    /// Evaluates the requirements of `capabilities` against the claims of the Keep
+    ///
+    /// The Keep is only attested, if there are any capabilities.
+    pub fn new(capabilities: HashMap<String, Capability>) -> anyhow::Result<Self> {
+        if capabilities.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let claims = Claims::get().context("failed to get attestation claims")?;
+        Ok(Self::with_claims(capabilities, &claims))
+    }
+
+    fn with_claims(capabilities: HashMap<String, Capability>, claims: &Claims) -> Self {
+        let enabled = capabilities
+            .into_iter()
+            .map(
+                |(name, capability)| match unsatisfied(&capability, claims) {
+                    None => {
+                        info!(capability = name, "capability enabled");
+                        (name, true)
+                    }
+                    Some(reason) => {
+                        warn!(capability = name, "capability disabled: {reason}");
+                        (name, false)
+                    }
+                },
+            )
+            .collect();
+        Self(enabled)
+    }
+
+    /// Returns whether the capability `name` is enabled
+    fn enabled(&self, name: &str) -> Result<bool, Errno> {
+        self.0.get(name).copied().ok_or(Errno::Noent)
+    }
+}
+
+/// Returns `1`, if the capability named by the UTF-8 string at `name` of `len` bytes
+/// is enabled, `0`, if it is disabled, or the negated WASI errno
+///
+/// Fails with `ERRNO_NOENT`, if the capability is not declared in the Enarx.toml.
+fn capability(mut caller: Caller<'_, Ctx>, name: u32, len: u32) -> i32 {
+    negate(memory(&mut caller).and_then(|memory| {
+        let (data, ctx) = memory.data_and_store_mut(&mut caller);
+        data.get(name as usize..)
+            .and_then(|data| data.get(..len as usize))
+            .ok_or(Errno::Fault)
+            .and_then(|name| std::str::from_utf8(name).map_err(|_| Errno::Ilseq))
+            .and_then(|name| ctx.capabilities.enabled(name))
+            .map(i32::from)
+    }))
+}
+
+/// Adds the `enarx` `capability` function to `linker`
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "capability", capability)
+        .context("failed to add `enarx::capability`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use enarx_config::{SgxTcb, SnpTcb};
+
+    #[test]
+    fn evaluate() {
+        let snp = Claims {
+            technology: Technology::Snp,
+            debug: false,
+            tcb: Some(Tcb::Snp(SnpTcb {
+                bootloader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115,
+            })),
+        };
+        let sgx = Claims {
+            technology: Technology::Sgx,
+            debug: true,
+            tcb: Some(Tcb::Sgx(SgxTcb {
+                cpusvn: [1; 16],
+                pcesvn: 11,
+            })),
+        };
+        let kvm = Claims {
+            technology: Technology::Kvm,
+            debug: true,
+            tcb: None,
+        };
+
+        let any = Capability::default();
+        let production = Capability {
+            production: true,
+            min_tcb: None,
+        };
+        let min_tcb = |bootloader, cpusvn| Capability {
+            production: false,
+            min_tcb: Some(MinTcb {
+                snp: Some(SnpTcb {
+                    bootloader,
+                    ..Default::default()
+                }),
+                sgx: Some(SgxTcb { cpusvn, pcesvn: 11 }),
+            }),
+        };
+
+        assert_eq!(unsatisfied(&any, &kvm), None);
+        assert_eq!(unsatisfied(&production, &snp), None);
+        assert!(unsatisfied(&production, &sgx).is_some());
+        assert!(unsatisfied(&production, &kvm).is_some());
+
+        assert_eq!(unsatisfied(&min_tcb(3, [1; 16]), &snp), None);
+        assert!(unsatisfied(&min_tcb(4, [1; 16]), &snp).is_some());
+        assert_eq!(unsatisfied(&min_tcb(3, [1; 16]), &sgx), None);
+        let mut cpusvn = [0; 16];
+        cpusvn[15] = 2;
+        assert!(unsatisfied(&min_tcb(3, cpusvn), &sgx).is_some());
+        assert!(unsatisfied(&min_tcb(0, [0; 16]), &kvm).is_some());
+
+        // A missing minimum for the technology fails closed
+        let snp_only = Capability {
+            production: false,
+            min_tcb: Some(MinTcb {
+                snp: Some(SnpTcb::default()),
+                sgx: None,
+            }),
+        };
+        assert!(unsatisfied(&snp_only, &sgx).is_some());
+
+        let capabilities = Capabilities::with_claims(
+            [("any".into(), any), ("production".into(), production)].into(),
+            &kvm,
+        );
+        assert_eq!(capabilities.enabled("any"), Ok(true));
+        assert_eq!(capabilities.enabled("production"), Ok(false));
+        assert_eq!(capabilities.enabled("other"), Err(Errno::Noent));
+    }
+}
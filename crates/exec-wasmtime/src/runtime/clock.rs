@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Detection of skew between the trusted clock of the Keep and the host wall time
+//!
+//! The shim compares the wall time provided by the host with its trusted monotonic clock
+//! and counts the detected skews. The workload imports `clock_skews` from the `enarx` module
+//! to check the counter, before it relies on the wall time, e.g. for certificate validation.
+//!
+//! The counter is only as trustworthy as the clock of the shim. The workload imports
+//! `clock_trusted` to check, whether the host can influence it: the KVM shim only has a trusted
+//! clock with the SNP Secure TSC and otherwise derives it from a TSC frequency provided by the
+//! host. The SGX shim has no trusted clock and detects no skews at all, so both functions fail
+//! with `ERRNO_NOTSUP` in SGX Keeps.
+//!
+//! Workloads, whose threat model forbids timing channels to the host, can be denied the wall time
+//! with `realtime = false` in the `[clock]` of the Enarx.toml. The WASI clock functions then only
+//! serve the monotonic clock, which counts from the start of the Keep.
+
+use super::errno::{from_io, negate};
+use super::identity::Platform;
+use super::keys::memory;
+use super::Ctx;
+
+use std::io;
+use std::time::Duration;
+
+use anyhow::Context;
+use tracing::warn;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+
+/// WASI clock ID of the wall time
+const REALTIME: i32 = 0;
+
+/// WASI clock ID of the monotonic clock
+const MONOTONIC: i32 = 1;
+
+/// Returns the number of detected clock skews or the negated WASI errno
+///
+/// Fails with `ERRNO_NOTSUP`, if the platform cannot detect clock skews.
+fn clock_skews() -> i64 {
+    negate(match Platform::clock_skews() {
+        Ok(Some(skews)) => Ok(skews.count.try_into().unwrap_or(i64::MAX)),
+        Ok(None) => Err(Errno::Notsup),
+        Err(e) => Err(from_io(&e)),
+    })
+}
+
+/// Returns `1`, if the host cannot influence the clock detecting the skews, `0`, if it can, or
+/// the negated WASI errno
+///
+/// Fails with `ERRNO_NOTSUP`, if the platform cannot detect clock skews.
+fn clock_trusted() -> i32 {
+    negate(match Platform::clock_skews() {
+        Ok(Some(skews)) => Ok(skews.trusted.into()),
+        Ok(None) => Err(Errno::Notsup),
+        Err(e) => Err(from_io(&e)),
+    })
+}
+
+/// Adds the `enarx` `clock_skews` and `clock_trusted` functions to `linker`
+pub fn add_to_linker<T>(linker: &mut Linker<T>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "clock_skews", clock_skews)
+        .context("failed to add `enarx::clock_skews`")?;
+    linker
+        .func_wrap("enarx", "clock_trusted", clock_trusted)
+        .context("failed to add `enarx::clock_trusted`")?;
+    Ok(())
+}
+
+/// Returns the resolution of the clock `id` in nanoseconds of the Keep without wall time
+fn resolution(ctx: &Ctx, id: i32) -> Result<u64, Errno> {
+    match id {
+        REALTIME => Err(Errno::Notcapable),
+        MONOTONIC => Ok(ctx.wasi.clocks.monotonic.resolution().as_nanos() as u64),
+        _ => Err(Errno::Badf),
+    }
+}
+
+/// Returns the time of the clock `id` in nanoseconds of the Keep without wall time
+fn now(ctx: &Ctx, id: i32, precision: u64) -> Result<u64, Errno> {
+    match id {
+        REALTIME => Err(Errno::Notcapable),
+        MONOTONIC => {
+            let clocks = &ctx.wasi.clocks;
+            let now = clocks.monotonic.now(Duration::from_nanos(precision));
+            Ok((now - clocks.creation_time).as_nanos() as u64)
+        }
+        _ => Err(Errno::Badf),
+    }
+}
+
+/// Writes the result of a WASI clock function to `ptr` and returns the WASI errno
+fn write(caller: &mut Caller<'_, Ctx>, ptr: i32, result: Result<u64, Errno>) -> i32 {
+    let result = result.and_then(|value| {
+        memory(caller)?
+            .write(&mut *caller, ptr as u32 as usize, &value.to_le_bytes())
+            .map_err(|_| Errno::Fault)
+    });
+    u16::from(result.err().unwrap_or(Errno::Success)).into()
+}
+
+/// The WASI `clock_res_get` function of a Keep without wall time
+fn clock_res_get(mut caller: Caller<'_, Ctx>, id: i32, ptr: i32) -> i32 {
+    let result = resolution(caller.data(), id);
+    write(&mut caller, ptr, result)
+}
+
+/// The WASI `clock_time_get` function of a Keep without wall time
+fn clock_time_get(mut caller: Caller<'_, Ctx>, id: i32, precision: i64, ptr: i32) -> i32 {
+    let result = now(caller.data(), id, precision as u64);
+    write(&mut caller, ptr, result)
+}
+
+/// Denies the wall time to the workload by replacing the WASI clock functions in `linker`
+///
+/// Reading `CLOCK_REALTIME` fails with `ERRNO_NOTCAPABLE`, so the workload can handle it.
+/// Timers of `poll_oneoff` only support the monotonic clock anyway.
+pub fn deny_realtime(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker.allow_shadowing(true);
+    linker
+        .func_wrap("wasi_snapshot_preview1", "clock_res_get", clock_res_get)
+        .context("failed to replace `clock_res_get`")?;
+    linker
+        .func_wrap("wasi_snapshot_preview1", "clock_time_get", clock_time_get)
+        .context("failed to replace `clock_time_get`")?;
+    linker.allow_shadowing(false);
+    Ok(())
+}
+
+/// Warns about clock skews detected during the execution of the workload
+pub fn report() -> io::Result<()> {
+    if let Some(skews) = Platform::clock_skews()?.filter(|skews| skews.count > 0) {
+        warn!(
+            clock_skews = skews.count,
+            trusted = skews.trusted,
+            "skew between the trusted clock and the host wall time detected"
+        );
+    }
+    Ok(())
+}
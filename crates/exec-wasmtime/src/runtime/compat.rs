@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Personality of the system presented to the workload
+//!
+//! The Enarx.toml declares the `uname` fields, page size and other values describing the system,
+//! which fall back to fixed defaults instead of the values of the host. The workload imports
+//! `compat` from the `enarx` module to look up a value by name.
+
+use super::errno::negate;
+use super::keys::memory;
+use super::Ctx;
+
+use anyhow::{ensure, Context};
+use enarx_config::Compat;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+
+/// Maximum length of a `uname` field without the terminating NUL
+const UTSNAME_LEN: usize = 64;
+
+/// The resolved personality of the Keep
+#[derive(Clone, Debug)]
+pub struct Personality {
+    sysname: String,
+    nodename: String,
+    release: String,
+    version: String,
+    machine: String,
+    page_size: u32,
+    nprocs: u32,
+}
+
+impl Default for Personality {
+    fn default() -> Self {
+        // Keep in sync with the `uname` stub of the shims
+        Self {
+            sysname: "Linux".into(),
+            nodename: "localhost.localdomain".into(),
+            release: "5.6.0".into(),
+            version: "#1".into(),
+            machine: "x86_64".into(),
+            page_size: 4096,
+            nprocs: 1,
+        }
+    }
+}
+
+impl Personality {
+    /// Checks `compat` and resolves it with the defaults
+    pub fn new(compat: Compat) -> anyhow::Result<Self> {
+        let default = Self::default();
+        let uname = |name: &str, value: Option<String>, default: String| {
+            let value = value.unwrap_or(default);
+            ensure!(
+                value.len() <= UTSNAME_LEN,
+                "`{name}` exceeds {UTSNAME_LEN} bytes"
+            );
+            ensure!(!value.contains('\0'), "`{name}` contains a NUL byte");
+            Ok(value)
+        };
+
+        let page_size = compat.page_size.unwrap_or(default.page_size);
+        ensure!(
+            page_size.is_power_of_two(),
+            "page size `{page_size}` is not a power of two"
+        );
+        let nprocs = compat.nprocs.unwrap_or(default.nprocs);
+        ensure!(nprocs > 0, "number of processors must not be zero");
+
+        Ok(Self {
+            sysname: uname("sysname", compat.sysname, default.sysname)?,
+            nodename: uname("nodename", compat.nodename, default.nodename)?,
+            release: uname("release", compat.release, default.release)?,
+            version: uname("version", compat.version, default.version)?,
+            machine: uname("machine", compat.machine, default.machine)?,
+            page_size,
+            nprocs,
+        })
+    }
+
+    /// Returns the value named `name` as a string
+    fn get(&self, name: &str) -> Result<String, Errno> {
+        Ok(match name {
+            "sysname" => self.sysname.clone(),
+            "nodename" => self.nodename.clone(),
+            "release" => self.release.clone(),
+            "version" => self.version.clone(),
+            "machine" => self.machine.clone(),
+            "page_size" => self.page_size.to_string(),
+            "nprocs" => self.nprocs.to_string(),
+            _ => return Err(Errno::Noent),
+        })
+    }
+}
+
+/// Writes the value named by the UTF-8 string at `name` of `len` bytes to `buf` of `size` bytes
+/// and returns the length of the complete value or the negated WASI errno
+///
+/// The value is truncated to `size` bytes. Fails with `ERRNO_NOENT`, if the name is unknown.
+fn compat(mut caller: Caller<'_, Ctx>, name: u32, len: u32, buf: u32, size: u32) -> i32 {
+    negate(memory(&mut caller).and_then(|memory| {
+        let (data, ctx) = memory.data_and_store_mut(&mut caller);
+
+        let value = data
+            .get(name as usize..)
+            .and_then(|data| data.get(..len as usize))
+            .ok_or(Errno::Fault)
+            .and_then(|name| std::str::from_utf8(name).map_err(|_| Errno::Ilseq))
+            .and_then(|name| ctx.personality.get(name))?;
+        let buf = data
+            .get_mut(buf as usize..)
+            .and_then(|data| data.get_mut(..size as usize))
+            .ok_or(Errno::Fault)?;
+        let n = buf.len().min(value.len());
+        buf[..n].copy_from_slice(&value.as_bytes()[..n]);
+        i32::try_from(value.len()).map_err(|_| Errno::Overflow)
+    }))
+}
+
+/// Adds the `enarx` `compat` function to `linker`
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "compat", compat)
+        .context("failed to add `enarx::compat`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let personality = Personality::new(Compat {
+            release: Some("6.1.0".into()),
+            nprocs: Some(4),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(personality.get("sysname").unwrap(), "Linux");
+        assert_eq!(personality.get("release").unwrap(), "6.1.0");
+        assert_eq!(personality.get("page_size").unwrap(), "4096");
+        assert_eq!(personality.get("nprocs").unwrap(), "4");
+        assert_eq!(personality.get("domainname"), Err(Errno::Noent));
+    }
+
+    #[test]
+    fn check() {
+        let invalid = [
+            Compat {
+                nodename: Some("n".repeat(UTSNAME_LEN + 1)),
+                ..Default::default()
+            },
+            Compat {
+                release: Some("6.1\0".into()),
+                ..Default::default()
+            },
+            Compat {
+                page_size: Some(12288),
+                ..Default::default()
+            },
+            Compat {
+                nprocs: Some(0),
+                ..Default::default()
+            },
+        ];
+        for compat in invalid {
+            assert!(Personality::new(compat).is_err());
+        }
+        assert!(Personality::new(Compat {
+            nodename: Some("n".repeat(UTSNAME_LEN)),
+            ..Default::default()
+        })
+        .is_ok());
+    }
+}
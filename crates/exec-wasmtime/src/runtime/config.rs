@@ -0,0 +1,98 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Configuration values of the `wasi:config` interface
+//!
+//! The workload imports `get` and `get_all` from the `wasi:config/runtime` module to read the
+//! values of the `config` table of the Enarx.toml, so workloads written against the WASI cloud
+//! interfaces need not be configured with environment variables. The values are measured as part
+//! of the package config and shared by all instances of the workload.
+
+use super::errno::negate;
+use super::keys::{memory, read_str, write};
+use super::Ctx;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Context;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+
+/// Configuration values of the workload
+#[derive(Clone, Debug, Default)]
+pub struct Values(Arc<HashMap<String, String>>);
+
+impl Values {
+    /// Creates the configuration values `values`
+    pub fn new(values: HashMap<String, String>) -> Self {
+        Self(Arc::new(values))
+    }
+
+    /// Returns the value of `key`
+    fn get(&self, key: &str) -> Result<&str, Errno> {
+        self.0.get(key).map(String::as_str).ok_or(Errno::Noent)
+    }
+
+    /// Returns all keys and values sorted by key, each terminated by a NUL byte
+    fn all(&self) -> Vec<u8> {
+        let mut values: Vec<_> = self.0.iter().collect();
+        values.sort_unstable();
+        let mut all = vec![];
+        for (key, value) in values {
+            all.extend(key.as_bytes());
+            all.push(0);
+            all.extend(value.as_bytes());
+            all.push(0);
+        }
+        all
+    }
+}
+
+/// Writes the value of the UTF-8 key at `key` of `key_len` bytes to `buf` of `len` bytes and
+/// returns its length or the negated WASI errno
+///
+/// Fails with `ERRNO_NOENT`, if there is no such value, and with `ERRNO_RANGE`, if the value does
+/// not fit into `buf`.
+fn get(mut caller: Caller<'_, Ctx>, key: u32, key_len: u32, buf: u32, len: u32) -> i32 {
+    let res = read_str(&mut caller, key, key_len)
+        .and_then(|key| caller.data().config.get(&key).map(str::to_owned))
+        .and_then(|value| Ok((memory(&mut caller)?, value)));
+    negate(res.and_then(|(memory, value)| write(&mut caller, memory, buf, len, value.as_bytes())))
+}
+
+/// Writes all keys and values, each terminated by a NUL byte, to `buf` of `len` bytes and
+/// returns their length or the negated WASI errno
+///
+/// Fails with `ERRNO_RANGE`, if they do not fit into `buf`.
+fn get_all(mut caller: Caller<'_, Ctx>, buf: u32, len: u32) -> i32 {
+    let all = caller.data().config.all();
+    negate(memory(&mut caller).and_then(|memory| write(&mut caller, memory, buf, len, &all)))
+}
+
+/// Adds the `wasi:config/runtime` `get` and `get_all` functions to `linker`
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("wasi:config/runtime", "get", get)
+        .context("failed to add `wasi:config/runtime::get`")?;
+    linker
+        .func_wrap("wasi:config/runtime", "get_all", get_all)
+        .context("failed to add `wasi:config/runtime::get_all`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let values = Values::new(HashMap::from([
+            ("port".into(), "8080".into()),
+            ("host".into(), "example.com".into()),
+        ]));
+        assert_eq!(values.get("port"), Ok("8080"));
+        assert_eq!(values.get("user"), Err(Errno::Noent));
+        assert_eq!(values.all(), b"host\0example.com\0port\08080\0");
+        assert_eq!(Values::default().all(), b"");
+    }
+}
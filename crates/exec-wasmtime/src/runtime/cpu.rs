@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Detection of the cryptographic acceleration of the CPU
+//!
+//! Wasm cannot execute AES, SHA or carry-less multiplication instructions directly.
+//! The workload imports `cpu_features` from the `enarx` module to check, which of them the
+//! CPU of the Keep implements, e.g. to prefer AES-GCM over ChaCha20-Poly1305 only with AES-NI.
+
+use anyhow::Context;
+use wasmtime::Linker;
+
+/// AES-NI
+pub const AES: i64 = 1 << 0;
+
+/// SHA extensions
+pub const SHA: i64 = 1 << 1;
+
+/// Carry-less multiplication
+pub const PCLMUL: i64 = 1 << 2;
+
+/// Returns the cryptographic acceleration features of the CPU as a bit mask
+#[cfg(target_arch = "x86_64")]
+pub fn features() -> i64 {
+    let mut features = 0;
+    if is_x86_feature_detected!("aes") {
+        features |= AES;
+    }
+    if is_x86_feature_detected!("sha") {
+        features |= SHA;
+    }
+    if is_x86_feature_detected!("pclmulqdq") {
+        features |= PCLMUL;
+    }
+    features
+}
+
+/// Returns the cryptographic acceleration features of the CPU as a bit mask
+#[cfg(not(target_arch = "x86_64"))]
+pub fn features() -> i64 {
+    0
+}
+
+/// Adds the `enarx` `cpu_features` function to `linker`
+pub fn add_to_linker<T>(linker: &mut Linker<T>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "cpu_features", features)
+        .context("failed to add `enarx::cpu_features`")?;
+    Ok(())
+}
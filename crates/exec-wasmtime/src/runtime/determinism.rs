@@ -0,0 +1,164 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Deterministic execution of the Wasm workload
+//!
+//! If the Enarx.toml contains a `[determinism]` section, NaN results of floating-point operations
+//! are canonicalized, so that their bit patterns do not depend on the CPU, and the module is
+//! checked for constructs, whose behavior depends on the host or on the scheduling of threads,
+//! before it is compiled. The module is refused, if it has any, unless `warn` is set.
+
+use super::limits::{Import, Reader, Resizable};
+
+use anyhow::{bail, ensure, Context};
+use enarx_config::{Determinism, Limits};
+use tracing::warn;
+
+const CUSTOM_SECTION: u8 = 0;
+const IMPORT_SECTION: u8 = 2;
+const MEMORY_SECTION: u8 = 5;
+
+/// Memory type flag of a shared memory
+const SHARED: u8 = 0x02;
+
+/// Adjusts the wasmtime `config` for deterministic execution
+pub(super) fn configure(config: &mut wasmtime::Config) {
+    config.cranelift_nan_canonicalization(true);
+}
+
+/// Returns the nondeterministic constructs of the Wasm module `webasm` run with `limits`
+///
+/// Only the sections preceding the code are parsed, the module is validated by wasmtime later.
+/// Relaxed SIMD instructions are rejected by wasmtime anyway, but are reported here, if the
+/// `target_features` section emitted by LLVM declares them.
+fn findings(webasm: &[u8], limits: &Limits) -> anyhow::Result<Vec<String>> {
+    let mut findings = vec![];
+    if limits.threads.is_some() {
+        findings.push("threads are scheduled nondeterministically".into());
+    }
+
+    let mut reader = Reader(webasm);
+    ensure!(reader.bytes(4)? == b"\0asm", "invalid Wasm module magic");
+    if reader.bytes(4)? != [1, 0, 0, 0] {
+        // Not a core module, leave it to wasmtime
+        return Ok(findings);
+    }
+
+    let mut memories = vec![];
+    while !reader.0.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.len()?;
+        let mut section = Reader(reader.bytes(len)?);
+        match id {
+            CUSTOM_SECTION if section.name()? == b"target_features" => {
+                for _ in 0..section.leb128()? {
+                    let prefix = section.byte()?;
+                    if section.name()? == b"relaxed-simd" && prefix != b'-' {
+                        findings.push(
+                            "relaxed SIMD instructions have implementation-defined results".into(),
+                        );
+                    }
+                }
+            }
+            IMPORT_SECTION => {
+                for _ in 0..section.leb128()? {
+                    if let Import::Memory(memory) = section.import()? {
+                        memories.push(memory);
+                    }
+                }
+            }
+            MEMORY_SECTION => {
+                for _ in 0..section.leb128()? {
+                    memories.push(section.limits()?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (index, Resizable { flags, max, .. }) in memories.into_iter().enumerate() {
+        if flags & SHARED != 0 {
+            findings.push(format!(
+                "memory {index} is shared, so threads access it in a nondeterministic order"
+            ));
+        } else if max.is_none() && limits.memory_size.is_none() {
+            findings.push(format!(
+                "memory {index} has no maximum size, so whether it grows depends on the memory of the host without a `memory_size` limit"
+            ));
+        }
+    }
+    Ok(findings)
+}
+
+/// Checks the Wasm module `webasm` run with `limits` for nondeterministic constructs
+pub(crate) fn check(
+    webasm: &[u8],
+    determinism: &Determinism,
+    limits: &Limits,
+) -> anyhow::Result<()> {
+    let findings = findings(webasm, limits).context("failed to parse Wasm module")?;
+    if findings.is_empty() {
+        return Ok(());
+    }
+    if !determinism.warn {
+        bail!("Wasm module is not deterministic: {}", findings.join(", "));
+    }
+    for finding in findings {
+        warn!("Wasm module is not deterministic: {finding}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_wat(wat: &str, limits: &Limits) -> anyhow::Result<()> {
+        let webasm = wat::parse_str(wat).unwrap();
+        check(&webasm, &Determinism::default(), limits)
+    }
+
+    #[test]
+    fn memories() {
+        let limits = Limits::default();
+        check_wat("(module (memory 1 2))", &limits).unwrap();
+        check_wat(r#"(module (import "env" "memory" (memory 1 2)))"#, &limits).unwrap();
+        assert!(check_wat("(module (memory 1))", &limits).is_err());
+        assert!(check_wat(r#"(module (import "env" "memory" (memory 1)))"#, &limits).is_err());
+
+        // The limit bounds the growth of memories without a maximum
+        let limits = Limits {
+            memory_size: Some(1 << 20),
+            ..Default::default()
+        };
+        check_wat("(module (memory 1))", &limits).unwrap();
+        assert!(check_wat("(module (memory 1 2 shared))", &limits).is_err());
+
+        let webasm = wat::parse_str("(module (memory 1))").unwrap();
+        let warn = Determinism { warn: true };
+        check(&webasm, &warn, &Limits::default()).unwrap();
+    }
+
+    #[test]
+    fn threads() {
+        let limits = Limits {
+            memory_size: Some(1 << 20),
+            threads: Some(2),
+            ..Default::default()
+        };
+        assert!(check_wat("(module)", &limits).is_err());
+    }
+
+    #[test]
+    fn relaxed_simd() {
+        const FEATURES: &[u8] = b"\x0ftarget_features\x02\x2b\x04simd\x2b\x0crelaxed-simd";
+        let mut webasm = b"\0asm\x01\0\0\0\0".to_vec();
+        webasm.push(FEATURES.len() as u8);
+        webasm.extend_from_slice(FEATURES);
+        assert!(check(&webasm, &Determinism::default(), &Limits::default()).is_err());
+
+        // Disallowed features are not used
+        let len = webasm.len();
+        webasm[len - 14] = b'-';
+        check(&webasm, &Determinism::default(), &Limits::default()).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! WASI errnos of the host errors of the workload
+//!
+//! Within a Keep, host errors carry the Linux errnos of the shim, which are translated to WASI
+//! errnos by [`sallyport::errno`] like those of the shims, so the workload sees the same errno for
+//! the same failure on every backend. Elsewhere, host errors are translated by WASI itself and by
+//! their kind, if WASI does not know them.
+//!
+//! The host functions of the runtime follow the conventions of [`sallyport::errno`]: they fail
+//! with `ERRNO_NOTCAPABLE`, if the package config does not grant the operation, and with
+//! `ERRNO_NOTSUP`, if the platform does not support it.
+
+use std::io;
+
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasi_common::Error;
+
+/// Returns the WASI errno of the Linux errno `errno`
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub fn from_raw(errno: i32) -> Errno {
+    Errno::try_from(sallyport::errno::to_wasi(errno)).unwrap_or(Errno::Io)
+}
+
+/// Returns the WASI errno of the OS errno `errno`
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+pub fn from_raw(errno: i32) -> Errno {
+    let error = io::Error::from_raw_os_error(errno);
+    let kind = error.kind();
+    Errno::try_from(error).unwrap_or_else(|_| from_kind(kind))
+}
+
+/// Returns the WASI errno of the host error `error`
+pub fn from_io(error: &io::Error) -> Errno {
+    error
+        .raw_os_error()
+        .map_or_else(|| from_kind(error.kind()), from_raw)
+}
+
+/// Returns the WASI errno of the error `error` of a WASI file or directory
+pub fn from_error(error: Error) -> Errno {
+    match error.downcast_ref::<io::Error>() {
+        Some(error) => from_io(error),
+        None => Errno::try_from(error).unwrap_or(Errno::Io),
+    }
+}
+
+/// Returns `res` or the negated WASI errno
+pub fn negate<T: From<i32>>(res: Result<T, Errno>) -> T {
+    res.unwrap_or_else(|errno| T::from(-i32::from(u16::from(errno))))
+}
+
+/// Returns the WASI errno of the host errors of `kind` without an OS errno
+pub fn from_kind(kind: io::ErrorKind) -> Errno {
+    match kind {
+        io::ErrorKind::NotFound => Errno::Noent,
+        io::ErrorKind::PermissionDenied => Errno::Acces,
+        io::ErrorKind::ConnectionRefused => Errno::Connrefused,
+        io::ErrorKind::ConnectionReset => Errno::Connreset,
+        io::ErrorKind::ConnectionAborted => Errno::Connaborted,
+        io::ErrorKind::NotConnected => Errno::Notconn,
+        io::ErrorKind::AddrInUse => Errno::Addrinuse,
+        io::ErrorKind::AddrNotAvailable => Errno::Addrnotavail,
+        io::ErrorKind::BrokenPipe => Errno::Pipe,
+        io::ErrorKind::AlreadyExists => Errno::Exist,
+        io::ErrorKind::WouldBlock => Errno::Again,
+        io::ErrorKind::InvalidInput => Errno::Inval,
+        io::ErrorKind::InvalidData => Errno::Ilseq,
+        io::ErrorKind::TimedOut => Errno::Timedout,
+        io::ErrorKind::Interrupted => Errno::Intr,
+        io::ErrorKind::Unsupported => Errno::Notsup,
+        io::ErrorKind::OutOfMemory => Errno::Nomem,
+        _ => Errno::Io,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conformance() {
+        for (error, errno) in [
+            (io::Error::from(io::ErrorKind::NotFound), Errno::Noent),
+            (io::Error::from(io::ErrorKind::WouldBlock), Errno::Again),
+            (io::Error::from(io::ErrorKind::TimedOut), Errno::Timedout),
+            (io::Error::from(io::ErrorKind::Unsupported), Errno::Notsup),
+            (io::Error::from(io::ErrorKind::Other), Errno::Io),
+            (
+                io::Error::new(io::ErrorKind::AlreadyExists, "key"),
+                Errno::Exist,
+            ),
+        ] {
+            assert_eq!(from_io(&error), errno, "{error}");
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn conformance_keep() {
+        for (errno, wasi) in [
+            (libc::ENOENT, Errno::Noent),
+            (libc::EPERM, Errno::Perm),
+            (libc::EACCES, Errno::Acces),
+            (libc::EAGAIN, Errno::Again),
+            (libc::ENOSYS, Errno::Nosys),
+            (libc::EOPNOTSUPP, Errno::Notsup),
+            (libc::ENOTTY, Errno::Notty),
+            (libc::ENOSPC, Errno::Nospc),
+            (libc::EHOSTUNREACH, Errno::Hostunreach),
+            (libc::EBADFD, Errno::Badf),
+        ] {
+            let error = io::Error::from_raw_os_error(errno);
+            assert_eq!(from_io(&error), wasi, "{error}");
+        }
+    }
+}
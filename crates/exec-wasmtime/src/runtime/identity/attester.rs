@@ -0,0 +1,373 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Technology-specific attestation of the Keep.
+//!
+//! Every technology, which can run a Keep, implements [`Attester`]: it knows the format of its
+//! evidence, how the public key of the Keep is bound to it and which claims and telemetry it
+//! carries. The shim reports the technology of the Keep with the size of its evidence in the
+//! `GETATT` sallyport call, by which [`Platform`](super::Platform) discovers the attester at
+//! runtime. Supporting a new technology, e.g. TDX or CCA, only takes a new attester in
+//! [`ATTESTERS`].
+
+use super::super::attestation::{NONE, REPORT_DATA_SIZE, SGX, SNP};
+use super::claims::{Claims, CpuModel, Tcb, Telemetry};
+use super::platform::Technology;
+
+use std::fmt::Debug;
+use std::ops::Deref;
+
+use anyhow::{ensure, Context};
+use const_oid::db::rfc5912::{SECP_256_R_1, SECP_384_R_1};
+use const_oid::ObjectIdentifier;
+use enarx_config::{SgxTcb, SnpTcb};
+use sha2::{Digest, Sha256, Sha384};
+use x509_cert::der::asn1::OctetStringRef;
+use x509_cert::der::{AnyRef, Decode};
+
+/// Offset of the guest policy in the SNP attestation report
+pub(super) const SNP_POLICY: usize = 0x08;
+
+/// Guest policy bit allowing debugging
+const SNP_POLICY_DEBUG: u64 = 1 << 19;
+
+/// Offset of the reported TCB version in the SNP attestation report
+pub(super) const SNP_REPORTED_TCB: usize = 0x180;
+
+/// Offset of the CPUID family, model and stepping in the SNP attestation report
+pub(super) const SNP_CPUID: usize = 0x188;
+
+/// First version of the SNP attestation report with the CPUID fields
+const SNP_CPUID_VERSION: u32 = 3;
+
+/// Offset of the PCE SVN in the SGX quote header
+pub(super) const SGX_PCESVN: usize = 10;
+
+/// Offset of the CPU SVN in the SGX quote, i.e. of the report body
+pub(super) const SGX_CPUSVN: usize = 48;
+
+/// Offset of the attribute flags in the SGX quote
+const SGX_ATTRIBUTES: usize = SGX_CPUSVN + 48;
+
+/// Attribute flag of a debug enclave
+const SGX_ATTRIBUTES_DEBUG: u64 = 1 << 1;
+
+/// The attesters of all supported technologies
+pub static ATTESTERS: [&dyn Attester; 3] = [&Kvm, &Snp, &Sgx];
+
+/// Returns the attester of the technology `id` reported by the shim, if it is supported
+pub fn find(id: usize) -> Option<&'static dyn Attester> {
+    ATTESTERS
+        .iter()
+        .copied()
+        .find(|attester| attester.id() == id)
+}
+
+/// Attestation of the Keep by the technology running it
+pub trait Attester: Debug + Sync {
+    /// Identifier of the technology in the `GETATT` sallyport call
+    fn id(&self) -> usize;
+
+    /// The technology of the attester
+    fn technology(&self) -> Technology;
+
+    /// OID of the CSR extension carrying the evidence
+    fn oid(&self) -> ObjectIdentifier;
+
+    /// Format of the evidence returned to the workload by `enarx::attest`
+    fn format(&self) -> i32;
+
+    /// Name of the format in the `Enarx-Attestation` header
+    fn name(&self) -> &'static str;
+
+    /// Curve of the private key of the Keep
+    fn curve(&self) -> ObjectIdentifier {
+        SECP_256_R_1
+    }
+
+    /// Returns the report data binding the DER public key `key` and the `nonce` of the verifier
+    /// to the evidence
+    fn report_data(&self, key: &[u8], nonce: &[u8]) -> [u8; REPORT_DATA_SIZE] {
+        let mut report_data = [0; REPORT_DATA_SIZE];
+        let hash = Sha256::new()
+            .chain_update(key)
+            .chain_update(nonce)
+            .finalize();
+        report_data[..hash.len()].copy_from_slice(&hash);
+        report_data
+    }
+
+    /// Extracts the claims of `evidence`
+    fn claims(&self, evidence: &[u8]) -> anyhow::Result<Claims>;
+
+    /// Extracts the telemetry of `evidence`
+    ///
+    /// `cpu` is the CPU model of CPUID, which is used, if the evidence does not identify the CPU
+    /// model itself.
+    fn telemetry(&self, _evidence: &[u8], cpu: Option<CpuModel>) -> anyhow::Result<Telemetry> {
+        Ok(Telemetry {
+            cpu,
+            ..Default::default()
+        })
+    }
+}
+
+impl Technology {
+    /// Returns the attester of the technology
+    pub fn attester(self) -> &'static dyn Attester {
+        ATTESTERS
+            .iter()
+            .copied()
+            .find(|attester| attester.technology() == self)
+            .expect("every technology has an attester")
+    }
+}
+
+/// Attestation evidence of the Keep
+#[derive(Debug)]
+pub struct Evidence {
+    attester: &'static dyn Attester,
+    bytes: Vec<u8>,
+}
+
+impl Evidence {
+    pub fn new(attester: &'static dyn Attester, bytes: Vec<u8>) -> Self {
+        Self { attester, bytes }
+    }
+
+    /// Returns the attester, which produced the evidence
+    pub fn attester(&self) -> &'static dyn Attester {
+        self.attester
+    }
+
+    /// Extracts the claims of the evidence
+    pub fn claims(&self) -> anyhow::Result<Claims> {
+        self.attester.claims(&self.bytes)
+    }
+
+    /// Extracts the telemetry of the evidence
+    ///
+    /// `signature` is the processor signature of CPUID leaf 1, which is used, if the evidence
+    /// does not identify the CPU model itself.
+    pub fn telemetry(&self, signature: Option<u32>) -> anyhow::Result<Telemetry> {
+        self.attester
+            .telemetry(&self.bytes, signature.map(CpuModel::from_signature))
+    }
+}
+
+impl Deref for Evidence {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// A Keep without a hardware TEE
+#[derive(Debug)]
+pub struct Kvm;
+
+impl Attester for Kvm {
+    fn id(&self) -> usize {
+        0
+    }
+
+    fn technology(&self) -> Technology {
+        Technology::Kvm
+    }
+
+    fn oid(&self) -> ObjectIdentifier {
+        ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.1.1")
+    }
+
+    fn format(&self) -> i32 {
+        NONE
+    }
+
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn claims(&self, _evidence: &[u8]) -> anyhow::Result<Claims> {
+        Ok(Claims {
+            technology: Technology::Kvm,
+            debug: true,
+            tcb: None,
+        })
+    }
+}
+
+/// AMD SEV-SNP, whose evidence is the VCEK certificate followed by the attestation report
+#[derive(Debug)]
+pub struct Snp;
+
+impl Snp {
+    /// Extracts the SNP attestation report from the `evidence`
+    fn report(evidence: &[u8]) -> anyhow::Result<&[u8]> {
+        // The report follows the VCEK certificate
+        let report = AnyRef::from_der(evidence)
+            .and_then(|any| {
+                any.sequence(|reader| {
+                    AnyRef::decode(reader)?;
+                    OctetStringRef::decode(reader)
+                })
+            })
+            .context("failed to decode SNP evidence")?
+            .as_bytes();
+        ensure!(report.len() >= SNP_CPUID + 3, "SNP report is truncated");
+        Ok(report)
+    }
+}
+
+impl Attester for Snp {
+    fn id(&self) -> usize {
+        1
+    }
+
+    fn technology(&self) -> Technology {
+        Technology::Snp
+    }
+
+    fn oid(&self) -> ObjectIdentifier {
+        ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.1.3")
+    }
+
+    fn format(&self) -> i32 {
+        SNP
+    }
+
+    fn name(&self) -> &'static str {
+        "snp"
+    }
+
+    fn curve(&self) -> ObjectIdentifier {
+        SECP_384_R_1
+    }
+
+    fn report_data(&self, key: &[u8], nonce: &[u8]) -> [u8; REPORT_DATA_SIZE] {
+        let mut report_data = [0; REPORT_DATA_SIZE];
+        let hash = Sha384::new()
+            .chain_update(key)
+            .chain_update(nonce)
+            .finalize();
+        report_data[..hash.len()].copy_from_slice(&hash);
+        report_data
+    }
+
+    fn claims(&self, evidence: &[u8]) -> anyhow::Result<Claims> {
+        let report = Self::report(evidence)?;
+        let policy = u64::from_le_bytes(report[SNP_POLICY..][..8].try_into()?);
+        let tcb = &report[SNP_REPORTED_TCB..][..8];
+        Ok(Claims {
+            technology: Technology::Snp,
+            debug: policy & SNP_POLICY_DEBUG != 0,
+            tcb: Some(Tcb::Snp(SnpTcb {
+                bootloader: tcb[0],
+                tee: tcb[1],
+                snp: tcb[6],
+                microcode: tcb[7],
+            })),
+        })
+    }
+
+    fn telemetry(&self, evidence: &[u8], cpu: Option<CpuModel>) -> anyhow::Result<Telemetry> {
+        let report = Self::report(evidence)?;
+        let version = u32::from_le_bytes(report[..4].try_into()?);
+        let cpu = match &report[SNP_CPUID..][..3] {
+            [family, model, stepping] if version >= SNP_CPUID_VERSION && *family != 0 => {
+                Some(CpuModel {
+                    family: *family,
+                    model: *model,
+                    stepping: *stepping,
+                })
+            }
+            _ => cpu,
+        };
+        Ok(Telemetry {
+            cpu,
+            microcode: Some(report[SNP_REPORTED_TCB + 7]),
+            cpusvn: None,
+        })
+    }
+}
+
+/// Intel SGX, whose evidence is the quote of the enclave
+#[derive(Debug)]
+pub struct Sgx;
+
+impl Sgx {
+    /// Checks, that the `evidence` is a complete SGX quote
+    fn quote(evidence: &[u8]) -> anyhow::Result<&[u8]> {
+        ensure!(
+            evidence.len() >= SGX_ATTRIBUTES + 8,
+            "SGX quote is truncated"
+        );
+        Ok(evidence)
+    }
+}
+
+impl Attester for Sgx {
+    fn id(&self) -> usize {
+        2
+    }
+
+    fn technology(&self) -> Technology {
+        Technology::Sgx
+    }
+
+    fn oid(&self) -> ObjectIdentifier {
+        ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.1.2")
+    }
+
+    fn format(&self) -> i32 {
+        SGX
+    }
+
+    fn name(&self) -> &'static str {
+        "sgx"
+    }
+
+    fn claims(&self, evidence: &[u8]) -> anyhow::Result<Claims> {
+        let quote = Self::quote(evidence)?;
+        let flags = u64::from_le_bytes(quote[SGX_ATTRIBUTES..][..8].try_into()?);
+        Ok(Claims {
+            technology: Technology::Sgx,
+            debug: flags & SGX_ATTRIBUTES_DEBUG != 0,
+            tcb: Some(Tcb::Sgx(SgxTcb {
+                cpusvn: quote[SGX_CPUSVN..][..16].try_into()?,
+                pcesvn: u16::from_le_bytes(quote[SGX_PCESVN..][..2].try_into()?),
+            })),
+        })
+    }
+
+    fn telemetry(&self, evidence: &[u8], cpu: Option<CpuModel>) -> anyhow::Result<Telemetry> {
+        let quote = Self::quote(evidence)?;
+        Ok(Telemetry {
+            cpu,
+            microcode: None,
+            cpusvn: Some(quote[SGX_CPUSVN..][..16].try_into()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attesters() {
+        for technology in [Technology::Kvm, Technology::Snp, Technology::Sgx] {
+            let attester = technology.attester();
+            assert_eq!(attester.technology(), technology);
+            assert_eq!(find(attester.id()).unwrap().technology(), technology);
+        }
+        assert!(find(ATTESTERS.len()).is_none());
+
+        let snp = Snp.report_data(b"key", b"nonce");
+        assert_ne!(snp[..48], [0; 48]);
+        assert_eq!(snp[48..], [0; 16]);
+        let sgx = Sgx.report_data(b"key", b"nonce");
+        assert_ne!(sgx[..32], [0; 32]);
+        assert_eq!(sgx[32..], [0; 32]);
+        assert_eq!(Kvm.report_data(b"key", b"nonce"), sgx);
+    }
+}
@@ -0,0 +1,165 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Attestation ceremony over a file or serial channel
+//!
+//! Keeps in air-gapped environments cannot reach a Steward over HTTPS. With a `file` Steward
+//! URL, the host opens the file or serial device at its path and passes it to the Keep, which
+//! completes the attestation in a ceremony with the operator:
+//!
+//! 1. The operator writes a fresh nonce of 16 to 64 bytes to the channel as a PEM block labeled
+//!    `ENARX NONCE`.
+//! 2. The Keep writes its certificate signing request bound to the nonce as a PEM block labeled
+//!    `CERTIFICATE REQUEST`.
+//! 3. The operator carries the request to the verifier and writes the returned certificate chain
+//!    to the channel as a PEM block labeled `PKI PATH`, i.e. the body of the response of a
+//!    Steward.
+//!
+//! Lines outside of the expected blocks are skipped. The Keep waits for the data at the end of
+//! a regular file to be appended, so the ceremony works with a file on removable media as well.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context};
+use x509_cert::der::pem::{self, LineEnding};
+use x509_cert::der::{Decode, Encode};
+use x509_cert::PkiPath;
+
+/// PEM label of the nonce of the operator
+pub const NONCE: &str = "ENARX NONCE";
+
+/// PEM label of the certificate signing request of the Keep
+pub const REQUEST: &str = "CERTIFICATE REQUEST";
+
+/// PEM label of the certificate chain of the verifier
+pub const PKI_PATH: &str = "PKI PATH";
+
+/// Minimum size of the nonce in bytes
+const MIN_NONCE_SIZE: usize = 16;
+
+/// Maximum size of the nonce in bytes, i.e. the size of the report data of the evidence
+const MAX_NONCE_SIZE: usize = 64;
+
+/// Maximum size of a PEM block in bytes
+const MAX_BLOCK_SIZE: usize = 1 << 20;
+
+/// Interval of polling the channel for more data
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reads the next complete line of `reader` into `line`, waiting for it to be written
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> anyhow::Result<()> {
+    line.clear();
+    while !line.ends_with('\n') {
+        if reader.read_line(line)? == 0 {
+            thread::sleep(POLL_INTERVAL);
+        }
+        ensure!(line.len() <= MAX_BLOCK_SIZE, "line exceeds the size limit");
+    }
+    Ok(())
+}
+
+/// Reads the next PEM block labeled `label` of `reader` and returns its DER encoding
+fn read_block(reader: &mut impl BufRead, label: &str) -> anyhow::Result<Vec<u8>> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+
+    let mut line = String::new();
+    while line.trim_end() != begin {
+        read_line(reader, &mut line)?;
+    }
+    let mut block = format!("{begin}\n");
+    while line.trim_end() != end {
+        read_line(reader, &mut line)?;
+        block += line.trim_end();
+        block += "\n";
+        ensure!(
+            block.len() <= MAX_BLOCK_SIZE,
+            "`{label}` exceeds the size limit"
+        );
+    }
+
+    let (_, der) = pem::decode_vec(block.as_bytes())
+        .map_err(|e| anyhow!("failed to decode `{label}`: {e}"))?;
+    Ok(der)
+}
+
+/// Completes the ceremony over `channel` with the certificate signing request, which `csr`
+/// makes for the nonce of the operator, and returns the certificate chain of the verifier
+pub fn ceremony(
+    channel: &File,
+    csr: impl FnOnce(Option<&[u8]>) -> anyhow::Result<Vec<u8>>,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut reader = BufReader::new(channel);
+
+    let nonce = read_block(&mut reader, NONCE).context("failed to read nonce")?;
+    ensure!(
+        (MIN_NONCE_SIZE..=MAX_NONCE_SIZE).contains(&nonce.len()),
+        "nonce of {} bytes is not between {MIN_NONCE_SIZE} and {MAX_NONCE_SIZE} bytes",
+        nonce.len()
+    );
+
+    let req = pem::encode_string(REQUEST, LineEnding::LF, &csr(Some(&nonce))?)
+        .map_err(|e| anyhow!("failed to encode certificate signing request: {e}"))?;
+    let mut writer = channel;
+    writer
+        .write_all(req.as_bytes())
+        .and_then(|()| writer.flush())
+        .context("failed to write certificate signing request")?;
+
+    let path = read_block(&mut reader, PKI_PATH).context("failed to read certificate chain")?;
+    let path = PkiPath::from_der(&path).context("failed to decode certificate chain")?;
+    path.iter().rev().map(|c| Ok(c.to_vec()?)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Seek};
+
+    use tempfile::tempfile;
+
+    #[test]
+    fn lines() {
+        let mut reader = "skipped\r\n-----BEGIN ENARX NONCE-----\r\nAAECAwQFBgcICQoLDA0ODw==\r\n-----END ENARX NONCE-----\r\n".as_bytes();
+        assert_eq!(
+            read_block(&mut reader, NONCE).unwrap(),
+            (0..16).collect::<Vec<u8>>()
+        );
+    }
+
+    #[test]
+    fn ceremony() {
+        let mut channel = tempfile().unwrap();
+        let nonce = pem::encode_string(NONCE, LineEnding::LF, &[7; 32]).unwrap();
+        let path = pem::encode_string(PKI_PATH, LineEnding::LF, &[0x30, 0x00]).unwrap();
+        channel
+            .write_all(format!("{nonce}{path}").as_bytes())
+            .unwrap();
+        channel.rewind().unwrap();
+
+        let chain = super::ceremony(&channel, |nonce| {
+            assert_eq!(nonce, Some(&[7; 32][..]));
+            Ok(b"request".to_vec())
+        })
+        .unwrap();
+        assert!(chain.is_empty());
+
+        // The request follows the data read by the Keep
+        let mut data = String::new();
+        channel.rewind().unwrap();
+        channel.read_to_string(&mut data).unwrap();
+        let (label, req) = pem::decode_vec(data[nonce.len() + path.len()..].as_bytes()).unwrap();
+        assert_eq!(label, REQUEST);
+        assert_eq!(req, b"request");
+
+        // Nonces must be fresh enough
+        let mut channel = tempfile().unwrap();
+        let nonce = pem::encode_string(NONCE, LineEnding::LF, &[7; 8]).unwrap();
+        channel.write_all(nonce.as_bytes()).unwrap();
+        channel.rewind().unwrap();
+        assert!(super::ceremony(&channel, |_| unreachable!()).is_err());
+    }
+}
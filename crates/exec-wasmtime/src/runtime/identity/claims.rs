@@ -0,0 +1,428 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Attestation claims of the Keep.
+//!
+//! Besides the claims evaluated by the Keep itself, the evidence carries [`Telemetry`] of the
+//! hardware, so relying parties can apply fleet policies without parsing the raw evidence, and
+//! the [`Preopen`]s of the configuration, so they can tell the data the Keep only reads from the
+//! mounts it can modify.
+
+use super::platform::{Platform, Technology};
+
+use anyhow::{anyhow, Context};
+use const_oid::ObjectIdentifier;
+use enarx_config::{Config, File, ListenFile, SgxTcb, SnpTcb};
+use x509_cert::der::asn1::{OctetStringRef, Utf8StringRef};
+use x509_cert::der::{AnyRef, Encode, Tag};
+
+/// The TCB version of the platform
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tcb {
+    Snp(SnpTcb),
+    Sgx(SgxTcb),
+}
+
+/// The claims of the attestation evidence of the Keep
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Claims {
+    pub technology: Technology,
+    pub debug: bool,
+    pub tcb: Option<Tcb>,
+}
+
+impl Claims {
+    /// Attests the Keep and extracts the claims of the evidence
+    pub fn get() -> anyhow::Result<Self> {
+        let platform = Platform::get().context("failed to query platform")?;
+        let evidence = platform.attest(&[0; 64]).context("failed to attest Keep")?;
+        evidence.claims()
+    }
+}
+
+/// The CPU model as identified by CPUID leaf 1
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuModel {
+    /// Display family, e.g. `0x19` for AMD Milan and Genoa
+    pub family: u8,
+    /// Display model, e.g. `0x01` for AMD Milan and `0x11` for AMD Genoa
+    pub model: u8,
+    /// Stepping of the model
+    pub stepping: u8,
+}
+
+impl CpuModel {
+    /// Decodes the processor signature in EAX of CPUID leaf 1
+    pub fn from_signature(eax: u32) -> Self {
+        let base_family = (eax >> 8 & 0xf) as u8;
+        let mut family = base_family;
+        let mut model = (eax >> 4 & 0xf) as u8;
+        if base_family == 0xf {
+            family += (eax >> 20) as u8;
+        }
+        if base_family == 0x6 || base_family == 0xf {
+            model |= ((eax >> 16 & 0xf) as u8) << 4;
+        }
+
+        Self {
+            family,
+            model,
+            stepping: (eax & 0xf) as u8,
+        }
+    }
+
+    /// Returns the processor signature of the CPU running the Keep
+    #[cfg(target_arch = "x86_64")]
+    pub fn signature() -> Option<u32> {
+        // Safety: CPUID leaf 1 is supported by every x86_64 CPU and emulated by the shims
+        Some(unsafe { core::arch::x86_64::__cpuid(1) }.eax)
+    }
+
+    /// Returns the processor signature of the CPU running the Keep
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn signature() -> Option<u32> {
+        None
+    }
+}
+
+/// Platform details of the hardware running the Keep
+///
+/// The details are claimed by the host, but relying parties can verify them against the
+/// evidence: the microcode and the CPUSVN are part of the signed report, the SNP CPU model is
+/// part of the signed report or validated by the firmware and bound to the product of the VCEK,
+/// and the SGX CPU model is bound to the FMSPC of the PCK certificate.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Telemetry {
+    pub cpu: Option<CpuModel>,
+    /// Microcode patch level reported by SNP
+    pub microcode: Option<u8>,
+    /// Components of the SGX CPU security version
+    pub cpusvn: Option<[u8; 16]>,
+}
+
+impl Telemetry {
+    const CPU_FAMILY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.2.1");
+    const CPU_MODEL: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.2.2");
+    const CPU_STEPPING: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.2.3");
+    const MICROCODE: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.2.4");
+    const CPUSVN: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.2.5");
+
+    /// Encodes every telemetry claim as the DER value of its own OID
+    ///
+    /// The CPU model and the microcode are encoded as `INTEGER`, the CPUSVN as
+    /// `SEQUENCE OF INTEGER` with one element per component.
+    pub fn encode(&self) -> anyhow::Result<Vec<(ObjectIdentifier, Vec<u8>)>> {
+        let mut claims = vec![];
+        if let Some(cpu) = self.cpu {
+            claims.push((Self::CPU_FAMILY, cpu.family.to_vec()?));
+            claims.push((Self::CPU_MODEL, cpu.model.to_vec()?));
+            claims.push((Self::CPU_STEPPING, cpu.stepping.to_vec()?));
+        }
+        if let Some(microcode) = self.microcode {
+            claims.push((Self::MICROCODE, microcode.to_vec()?));
+        }
+        if let Some(cpusvn) = self.cpusvn {
+            claims.push((Self::CPUSVN, cpusvn.to_vec()?));
+        }
+        Ok(claims)
+    }
+}
+
+/// Prefix of the SHA-256 digests of the config
+const DIGEST_PREFIX: &str = "sha256:";
+
+/// Parses the SHA-256 `digest` of a `kind` file descriptor in the `sha256:<hex>` format
+fn parse_digest(kind: &str, digest: &str) -> anyhow::Result<[u8; 32]> {
+    digest
+        .strip_prefix(DIGEST_PREFIX)
+        .and_then(|digest| hex::decode(digest).ok())
+        .and_then(|digest| digest.try_into().ok())
+        .ok_or_else(|| {
+            anyhow!("unsupported {kind} digest `{digest}`, expected `{DIGEST_PREFIX}<hex>`")
+        })
+}
+
+/// A preopened file or directory of the configuration
+///
+/// Read-only preopens carry the SHA-256 digest of their content, which the Keep verifies, while
+/// writable preopens are marked as such and carry no digest, since their content changes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preopen {
+    /// Kind of the preopen, i.e. `config`, `dataset`, `appendlog`, `offload`, `helper`, `tmp` or
+    /// `keyvalue`
+    pub kind: &'static str,
+    /// Name of the file descriptor or path of the directory
+    pub name: String,
+    pub writable: bool,
+    pub digest: Option<[u8; 32]>,
+}
+
+impl Preopen {
+    const OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.3.1");
+
+    /// Lists the preopens of `config`, whose raw bytes have the digest `config_digest`
+    pub fn list(config_digest: Option<[u8; 32]>, config: &Config) -> anyhow::Result<Vec<Self>> {
+        let mut preopens = vec![];
+        if let Some(digest) = config_digest {
+            preopens.push(Self {
+                kind: "config",
+                name: crate::PACKAGE_CONFIG.to_string(),
+                writable: false,
+                digest: Some(digest),
+            });
+        }
+        for file in &config.files {
+            match file {
+                File::Dataset(dataset) => preopens.push(Self {
+                    kind: "dataset",
+                    name: file.name().into(),
+                    writable: false,
+                    digest: Some(parse_digest("dataset", &dataset.digest)?),
+                }),
+                // The helper runs without a TEE and exchanges data with the workload in plaintext
+                File::Helper(helper) => preopens.push(Self {
+                    kind: "helper",
+                    name: file.name().into(),
+                    writable: true,
+                    digest: Some(parse_digest("helper", &helper.digest)?),
+                }),
+                File::AppendLog(..) => preopens.push(Self {
+                    kind: "appendlog",
+                    name: file.name().into(),
+                    writable: true,
+                    digest: None,
+                }),
+                // The host terminates TLS and observes the plaintext of the connections
+                File::Listen(ListenFile::Tls {
+                    confidential: false,
+                    ..
+                }) => preopens.push(Self {
+                    kind: "offload",
+                    name: file.name().into(),
+                    writable: true,
+                    digest: None,
+                }),
+                _ => {}
+            }
+        }
+        if config.tmp.is_some() {
+            preopens.push(Self {
+                kind: "tmp",
+                name: super::super::TMP.into(),
+                writable: true,
+                digest: None,
+            });
+        }
+        if let Some(keyvalue) = &config.keyvalue {
+            preopens.push(Self {
+                kind: "keyvalue",
+                name: keyvalue.path.clone(),
+                writable: true,
+                digest: None,
+            });
+        }
+        Ok(preopens)
+    }
+
+    /// Encodes the `preopens` as the DER value of their OID
+    ///
+    /// The value is a `SEQUENCE OF SEQUENCE { kind UTF8String, name UTF8String, writable BOOLEAN,
+    /// digest OCTET STRING OPTIONAL }`.
+    pub fn encode(preopens: &[Self]) -> anyhow::Result<(ObjectIdentifier, Vec<u8>)> {
+        let mut seq = vec![];
+        for preopen in preopens {
+            let mut fields = Utf8StringRef::new(preopen.kind)?.to_vec()?;
+            fields.extend(Utf8StringRef::new(&preopen.name)?.to_vec()?);
+            fields.extend(preopen.writable.to_vec()?);
+            if let Some(digest) = &preopen.digest {
+                fields.extend(OctetStringRef::new(digest)?.to_vec()?);
+            }
+            seq.extend(AnyRef::new(Tag::Sequence, &fields)?.to_vec()?);
+        }
+        Ok((Self::OID, AnyRef::new(Tag::Sequence, &seq)?.to_vec()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::attester::{
+        Attester, Kvm, Sgx, Snp, SGX_CPUSVN, SGX_PCESVN, SNP_CPUID, SNP_POLICY, SNP_REPORTED_TCB,
+    };
+
+    use x509_cert::der::Encode;
+
+    #[test]
+    fn parse() {
+        let kvm = Kvm.claims(&[]).unwrap();
+        assert!(kvm.debug);
+        assert_eq!(kvm.tcb, None);
+
+        let mut report = vec![0u8; 0x4a0];
+        report[SNP_POLICY + 2] = 0x08; // debugging allowed
+        report[SNP_REPORTED_TCB..][..8].copy_from_slice(&[3, 0, 0, 0, 0, 0, 8, 115]);
+        let report = OctetStringRef::new(&report).unwrap().to_vec().unwrap();
+        let vcek = OctetStringRef::new(b"vcek").unwrap().to_vec().unwrap();
+        let mut evidence = vec![0x30, 0x82];
+        evidence.extend(((vcek.len() + report.len()) as u16).to_be_bytes());
+        evidence.extend(vcek);
+        evidence.extend(report);
+
+        let snp = Snp.claims(&evidence).unwrap();
+        assert!(snp.debug);
+        assert_eq!(
+            snp.tcb,
+            Some(Tcb::Snp(SnpTcb {
+                bootloader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115,
+            }))
+        );
+        assert!(Snp.claims(&evidence[..100]).is_err());
+
+        let mut quote = vec![0u8; 1024];
+        quote[SGX_PCESVN] = 11;
+        quote[SGX_CPUSVN] = 2;
+        let sgx = Sgx.claims(&quote).unwrap();
+        assert!(!sgx.debug);
+        let mut cpusvn = [0; 16];
+        cpusvn[0] = 2;
+        assert_eq!(sgx.tcb, Some(Tcb::Sgx(SgxTcb { cpusvn, pcesvn: 11 })));
+        assert!(Sgx.claims(&quote[..64]).is_err());
+    }
+
+    #[test]
+    fn telemetry() {
+        // AMD Milan, Intel Ice Lake SP and an old Intel Pentium 4
+        let milan = CpuModel::from_signature(0x00a00f11);
+        assert_eq!(
+            milan,
+            CpuModel {
+                family: 0x19,
+                model: 0x01,
+                stepping: 1,
+            }
+        );
+        assert_eq!(CpuModel::from_signature(0x000606a6).model, 0x6a);
+        assert_eq!(CpuModel::from_signature(0x00000f29).family, 0xf);
+
+        let kvm = Kvm.telemetry(&[], Some(milan)).unwrap();
+        assert_eq!(kvm.cpu, Some(milan));
+        assert_eq!(kvm.microcode, None);
+        assert_eq!(kvm.cpusvn, None);
+        assert_eq!(kvm.encode().unwrap().len(), 3);
+
+        let snp_evidence = |version: u32| {
+            let mut report = vec![0u8; 0x4a0];
+            report[..4].copy_from_slice(&version.to_le_bytes());
+            report[SNP_REPORTED_TCB..][..8].copy_from_slice(&[3, 0, 0, 0, 0, 0, 8, 115]);
+            report[SNP_CPUID..][..3].copy_from_slice(&[0x19, 0x11, 1]);
+            let report = OctetStringRef::new(&report).unwrap().to_vec().unwrap();
+            let vcek = OctetStringRef::new(b"vcek").unwrap().to_vec().unwrap();
+            let mut evidence = vec![0x30, 0x82];
+            evidence.extend(((vcek.len() + report.len()) as u16).to_be_bytes());
+            evidence.extend(vcek);
+            evidence.extend(report);
+            evidence
+        };
+
+        // The CPU model of the report takes precedence over CPUID
+        let snp = Snp.telemetry(&snp_evidence(3), Some(milan)).unwrap();
+        assert_eq!(snp.cpu.map(|cpu| cpu.model), Some(0x11));
+        assert_eq!(snp.microcode, Some(115));
+        let snp = Snp.telemetry(&snp_evidence(2), Some(milan)).unwrap();
+        assert_eq!(snp.cpu, Some(milan));
+        let claims = snp.encode().unwrap();
+        assert_eq!(claims.len(), 4);
+        assert_eq!(claims[3], (Telemetry::MICROCODE, vec![0x02, 0x01, 115]));
+
+        let mut quote = vec![0u8; 1024];
+        quote[SGX_CPUSVN + 15] = 7;
+        let sgx = Sgx.telemetry(&quote, None).unwrap();
+        assert_eq!(sgx.cpu, None);
+        let mut cpusvn = [0; 16];
+        cpusvn[15] = 7;
+        assert_eq!(sgx.cpusvn, Some(cpusvn));
+        let claims = sgx.encode().unwrap();
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims[0].0, Telemetry::CPUSVN);
+        assert_eq!(&claims[0].1[..2], &[0x30, 16 * 3]);
+        assert_eq!(&claims[0].1[claims[0].1.len() - 3..], &[0x02, 0x01, 7]);
+    }
+
+    #[test]
+    fn preopens() {
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [[files]]
+            kind = "stdin"
+
+            [[files]]
+            kind = "dataset"
+            name = "geo"
+            path = "geo.bin"
+            digest = "{DIGEST_PREFIX}{}"
+
+            [[files]]
+            kind = "appendlog"
+            name = "audit"
+            path = "audit.log"
+
+            [[files]]
+            kind = "listen"
+            name = "public"
+            confidential = false
+
+            [[files]]
+            kind = "helper"
+            name = "ocr"
+            path = "ocr.wasm"
+            digest = "{DIGEST_PREFIX}{}"
+
+            [tmp]
+            size = 4096
+            "#,
+            "ab".repeat(32),
+            "cd".repeat(32)
+        ))
+        .unwrap();
+
+        let preopens = Preopen::list(Some([1; 32]), &config).unwrap();
+        assert_eq!(
+            preopens
+                .iter()
+                .map(|p| (p.kind, p.name.as_str(), p.writable, p.digest))
+                .collect::<Vec<_>>(),
+            vec![
+                ("config", "Enarx.toml", false, Some([1; 32])),
+                ("dataset", "geo", false, Some([0xab; 32])),
+                ("appendlog", "audit", true, None),
+                ("offload", "public", true, None),
+                ("helper", "ocr", true, Some([0xcd; 32])),
+                ("tmp", "/tmp", true, None),
+            ]
+        );
+        assert_eq!(Preopen::list(None, &config).unwrap().len(), 5);
+
+        let (oid, der) = Preopen::encode(&preopens[2..3]).unwrap();
+        assert_eq!(oid, Preopen::OID);
+        let mut expected = vec![0x30, 23, 0x30, 21, 0x0c, 9];
+        expected.extend(b"appendlog");
+        expected.extend([0x0c, 5]);
+        expected.extend(b"audit");
+        expected.extend([0x01, 1, 0xff]);
+        assert_eq!(der, expected);
+
+        let config: Config = toml::from_str(
+            r#"
+            [[files]]
+            kind = "dataset"
+            path = "geo.bin"
+            digest = "sha256:00"
+            "#,
+        )
+        .unwrap();
+        assert!(Preopen::list(None, &config).is_err());
+    }
+}
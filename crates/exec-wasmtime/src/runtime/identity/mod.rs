@@ -2,24 +2,31 @@
 
 //! Functionality for establishing keep identity.
 
+mod attester;
+mod channel;
+mod claims;
 mod pki;
 mod platform;
 
+pub(super) use attester::Evidence;
+use claims::CpuModel;
+pub(super) use claims::Preopen;
+pub(super) use claims::{Claims, Tcb};
 use pki::PrivateKeyInfoExt;
-use platform::{Platform, Technology};
+pub(super) use platform::{Platform, Technology};
 
+use std::fs::File;
+use std::sync::Mutex;
 use std::time::Duration;
 
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 use const_oid::db::rfc5280::{
     ID_CE_BASIC_CONSTRAINTS, ID_CE_EXT_KEY_USAGE, ID_CE_KEY_USAGE, ID_KP_CLIENT_AUTH,
     ID_KP_SERVER_AUTH,
 };
-use const_oid::db::rfc5912::{SECP_256_R_1, SECP_384_R_1};
-use const_oid::AssociatedOid;
+use const_oid::{AssociatedOid, ObjectIdentifier};
 use getrandom::getrandom;
 use pkcs8::PrivateKeyInfo;
-use sha2::{Digest, Sha256, Sha384};
 use url::Url;
 use x509_cert::attr::Attribute;
 use x509_cert::der::asn1::{BitStringRef, UIntRef};
@@ -62,48 +69,102 @@ fn csr(pki: &PrivateKeyInfo<'_>, exts: Vec<Extension<'_>>) -> anyhow::Result<Vec
     Ok(req.to_vec()?)
 }
 
-/// Generates a new private key and corresponding CSR
-pub fn generate() -> anyhow::Result<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+/// Generates a new private key of the Keep on the curve of the platform
+pub fn generate() -> anyhow::Result<Zeroizing<Vec<u8>>> {
     let platform = Platform::get()?;
-    let cert_algo = match platform.technology() {
-        Technology::Snp => SECP_384_R_1,
-        Technology::Sgx => SECP_256_R_1,
-        Technology::Kvm => SECP_256_R_1,
-    };
 
     // Generate a keypair.
-    let raw = PrivateKeyInfo::generate(cert_algo)?;
-    let pki = PrivateKeyInfo::from_der(raw.as_ref())?;
-    let der = pki.public_key().unwrap().to_vec().unwrap();
-
-    let mut key_hash = [0u8; 64];
-    match platform.technology() {
-        Technology::Snp => {
-            let hash = Sha384::digest(der);
-            key_hash[..48].copy_from_slice(&hash);
-        }
-        _ => {
-            let hash = Sha256::digest(der);
-            key_hash[..32].copy_from_slice(&hash);
-        }
-    };
+    PrivateKeyInfo::generate(platform.attester().curve())
+}
 
-    let attestation_report = platform.attest(&key_hash)?;
+/// Generates a new private key on the curve `oid`
+///
+/// Returns the DER encoding of the `PrivateKeyInfo` type.
+pub fn generate_key(oid: ObjectIdentifier) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+    PrivateKeyInfo::generate(oid)
+}
+
+/// Makes a CSR of the DER encoded private key `key`, which is bound to fresh attestation evidence
+///
+/// The report data of the evidence is the hash of the public key followed by the `nonce` of the
+/// verifier, if any. The CSR claims the `preopens` of the configuration of the Keep.
+pub fn request(
+    platform: &Platform,
+    key: &[u8],
+    preopens: &[Preopen],
+    nonce: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    let pki = PrivateKeyInfo::from_der(key)?;
+    let der = pki.public_key()?.to_vec()?;
+
+    let report_data = platform
+        .attester()
+        .report_data(&der, nonce.unwrap_or_default());
+    let evidence = platform.attest(&report_data)?;
+
+    // Claim the platform details of the report as distinct extensions.
+    let telemetry = evidence.telemetry(CpuModel::signature())?.encode()?;
+    let (preopens_oid, preopens) = Preopen::encode(preopens)?;
 
     // Create extensions.
-    let ext = vec![Extension {
-        extn_id: platform.technology().into(),
+    let mut ext = vec![Extension {
+        extn_id: evidence.attester().oid(),
         critical: false,
-        extn_value: &attestation_report,
+        extn_value: &evidence,
     }];
+    ext.extend(telemetry.iter().map(|(oid, value)| Extension {
+        extn_id: *oid,
+        critical: false,
+        extn_value: value,
+    }));
+    ext.push(Extension {
+        extn_id: preopens_oid,
+        critical: false,
+        extn_value: &preopens,
+    });
 
     // Make a certificate signing request.
-    let req = csr(&pki, ext)?;
+    csr(&pki, ext)
+}
+
+/// Transport of certificate signing requests to the Steward
+#[derive(Debug)]
+pub enum Transport {
+    /// Request to the Steward at the HTTPS URL
+    Https(Url),
 
-    Ok((raw, req))
+    /// Ceremony with the operator over the file or serial channel opened by the host
+    Channel(Mutex<File>),
+}
+
+impl Transport {
+    /// Returns the transport to the Steward at `url`
+    ///
+    /// `channel` is the file or serial channel, which the host opened for a `file` URL.
+    pub fn new(url: Url, channel: Option<File>) -> anyhow::Result<Self> {
+        match url.scheme() {
+            "https" => Ok(Self::Https(url)),
+            "file" => channel
+                .map(|channel| Self::Channel(Mutex::new(channel)))
+                .ok_or_else(|| anyhow!("the host did not open the attestation channel `{url}`")),
+            _ => bail!("refusing to use an unencrypted steward url"),
+        }
+    }
+
+    /// Sends the CSR, which `csr` makes for the nonce of the verifier, if any, and returns the
+    /// certificate chain
+    pub fn attest(
+        &self,
+        csr: impl FnOnce(Option<&[u8]>) -> anyhow::Result<Vec<u8>>,
+    ) -> anyhow::Result<Vec<Vec<u8>>> {
+        match self {
+            Self::Https(url) => steward(url, csr(None)?),
+            Self::Channel(channel) => channel::ceremony(&channel.lock().unwrap(), csr),
+        }
+    }
 }
 
-pub fn steward(url: &Url, csr: impl AsRef<[u8]>) -> anyhow::Result<Vec<Vec<u8>>> {
+fn steward(url: &Url, csr: impl AsRef<[u8]>) -> anyhow::Result<Vec<Vec<u8>>> {
     if url.scheme() != "https" {
         bail!("refusing to use an unencrypted steward url");
     }
@@ -2,10 +2,11 @@
 
 //! Platform-specific functionality.
 
-use std::io::{ErrorKind, Result};
+use super::attester::{Attester, Evidence, Kvm};
 
-use const_oid::ObjectIdentifier;
+use std::io::{ErrorKind, Result};
 
+/// The technology running the Keep, see [`Attester`] for its specifics
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Technology {
     Kvm,
@@ -13,38 +14,38 @@ pub enum Technology {
     Sgx,
 }
 
-impl Technology {
-    const KVM: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.1.1");
-    const SGX: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.1.2");
-    const SNP: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.58270.1.3");
-}
-
-impl From<Technology> for ObjectIdentifier {
-    fn from(value: Technology) -> Self {
-        match value {
-            Technology::Kvm => Technology::KVM,
-            Technology::Snp => Technology::SNP,
-            Technology::Sgx => Technology::SGX,
-        }
-    }
+/// The clock skews detected by the shim
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ClockSkews {
+    /// Number of detected skews
+    pub count: u64,
+    /// Whether the host cannot influence the trusted clock of the shim
+    pub trusted: bool,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct Platform {
-    technology: Technology,
+    attester: &'static dyn Attester,
+    /// Whether a shim reported the technology, which is assumed to be KVM otherwise
+    reported: bool,
     report_size: usize,
-    #[allow(dead_code)]
     key_size: usize,
 }
 
 impl Platform {
     #[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
-    fn get_att(_nonce: Option<&[u8]>, _buf: Option<&mut [u8]>) -> Result<(Technology, usize)> {
-        Ok((Technology::Kvm, 0))
+    fn get_att(
+        _nonce: Option<&[u8]>,
+        _buf: Option<&mut [u8]>,
+    ) -> Result<(Option<&'static dyn Attester>, usize)> {
+        Ok((None, 0))
     }
 
     #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-    fn get_att(nonce: Option<&[u8]>, mut buf: Option<&mut [u8]>) -> Result<(Technology, usize)> {
+    fn get_att(
+        nonce: Option<&[u8]>,
+        mut buf: Option<&mut [u8]>,
+    ) -> Result<(Option<&'static dyn Attester>, usize)> {
         use sallyport::item::enarxcall::SYS_GETATT;
         use std::arch::asm;
         use std::ptr::{null, null_mut};
@@ -71,13 +72,11 @@ impl Platform {
         }
 
         match (rax, rdx) {
-            (ENOSYS | EPERM, ..) => Ok((Technology::Kvm, 0)),
+            (ENOSYS | EPERM, ..) => Ok((None, 0)),
             (n, ..) if n < 0 => Err(std::io::Error::from_raw_os_error(-n as i32)),
-            (n, t) => match t {
-                0 => Ok((Technology::Kvm, n as _)),
-                1 => Ok((Technology::Snp, n as _)),
-                2 => Ok((Technology::Sgx, n as _)),
-                _ => Err(ErrorKind::Other.into()),
+            (n, t) => match super::attester::find(t) {
+                Some(attester) => Ok((Some(attester), n as _)),
+                None => Err(ErrorKind::Other.into()),
             },
         }
     }
@@ -120,22 +119,117 @@ impl Platform {
         }
     }
 
+    /// Returns the clock skews detected by the shim.
+    ///
+    /// Returns `None`, if the platform cannot detect clock skews, e.g. in SGX Keeps.
+    #[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+    pub fn clock_skews() -> Result<Option<ClockSkews>> {
+        Ok(None)
+    }
+
+    /// Returns the clock skews detected by the shim.
+    ///
+    /// Returns `None`, if the platform cannot detect clock skews, e.g. in SGX Keeps.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    pub fn clock_skews() -> Result<Option<ClockSkews>> {
+        use sallyport::item::enarxcall::SYS_GETSKEW;
+        use std::arch::asm;
+
+        const ENOSYS: isize = -(libc::ENOSYS as isize);
+        const EOPNOTSUPP: isize = -(libc::EOPNOTSUPP as isize);
+        const EPERM: isize = -(libc::EPERM as isize);
+
+        let mut rax: isize;
+        let mut rdx: usize;
+
+        unsafe {
+            asm!(
+            "syscall",
+            lateout("rax") rax,
+            lateout("rdx") rdx,
+            in("rax") SYS_GETSKEW,
+            lateout("rcx") _, // clobbered
+            lateout("r11") _, // clobbered
+            )
+        }
+
+        match (rax, rdx) {
+            (ENOSYS | EOPNOTSUPP | EPERM, ..) => Ok(None),
+            (n, ..) if n < 0 => Err(std::io::Error::from_raw_os_error(-n as i32)),
+            (n, trusted) => Ok(Some(ClockSkews {
+                count: n as _,
+                trusted: trusted == 1,
+            })),
+        }
+    }
+
+    /// Sets the spin window of blocking waits of the shim to `window` nanoseconds.
+    ///
+    /// Returns `false`, if the platform does not spin, e.g. without a shim.
+    #[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+    pub fn set_spin_wait(_window: u64) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Sets the spin window of blocking waits of the shim to `window` nanoseconds.
+    ///
+    /// Returns `false`, if the platform does not spin, e.g. without a shim.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    pub fn set_spin_wait(window: u64) -> Result<bool> {
+        use sallyport::item::enarxcall::SYS_SETSPIN;
+        use std::arch::asm;
+
+        const ENOSYS: isize = -(libc::ENOSYS as isize);
+        const EPERM: isize = -(libc::EPERM as isize);
+
+        let mut rax: isize;
+
+        unsafe {
+            asm!(
+            "syscall",
+            inlateout("rax") SYS_SETSPIN as isize => rax,
+            in("rdi") window,
+            lateout("rcx") _, // clobbered
+            lateout("r11") _, // clobbered
+            )
+        }
+
+        match rax {
+            ENOSYS | EPERM => Ok(false),
+            n if n < 0 => Err(std::io::Error::from_raw_os_error(-n as i32)),
+            _ => Ok(true),
+        }
+    }
+
     pub fn get() -> Result<Self> {
-        let (technology, report_size) = Self::get_att(None, None)?;
+        let (attester, report_size) = Self::get_att(None, None)?;
         let key_size = Self::get_key(None)?;
 
         Ok(Self {
-            technology,
+            attester: attester.unwrap_or(&Kvm),
+            reported: attester.is_some(),
             report_size,
             key_size,
         })
     }
 
     pub fn technology(&self) -> Technology {
-        self.technology
+        self.attester.technology()
+    }
+
+    /// Returns, whether the Keep may run in a hardware TEE
+    ///
+    /// This fails closed: without a shim reporting the technology, e.g. with the nil backend,
+    /// the Keep is treated as running in a TEE.
+    pub fn is_tee(&self) -> bool {
+        !self.reported || self.technology() != Technology::Kvm
+    }
+
+    /// Returns the attester of the technology running the Keep
+    pub fn attester(&self) -> &'static dyn Attester {
+        self.attester
     }
 
-    #[allow(dead_code)]
     pub fn key(&self) -> Result<Vec<u8>> {
         let mut buf = vec![0; self.key_size];
 
@@ -147,7 +241,7 @@ impl Platform {
         Ok(buf)
     }
 
-    pub fn attest(&self, nonce: &[u8]) -> Result<Vec<u8>> {
+    pub fn attest(&self, nonce: &[u8]) -> Result<Evidence> {
         let mut buf = vec![0; self.report_size];
 
         let (_, size) = Self::get_att(Some(nonce), Some(&mut buf))?;
@@ -156,7 +250,7 @@ impl Platform {
         }
 
         buf.truncate(size);
-        Ok(buf)
+        Ok(Evidence::new(self.attester, buf))
     }
 }
 
@@ -164,8 +258,11 @@ impl Platform {
 fn test() {
     let platform = Platform::get().unwrap();
     assert_eq!(platform.technology(), Technology::Kvm);
+    assert!(platform.is_tee());
     assert_eq!(platform.report_size, 0);
     assert_eq!(platform.key_size, 0);
     let report = platform.attest(b"00000000").unwrap();
     assert!(report.is_empty());
+    assert_eq!(Platform::clock_skews().unwrap(), None);
+    assert!(!Platform::set_spin_wait(20_000).unwrap());
 }
@@ -0,0 +1,248 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Append-only, hash-chained logs in host files
+//!
+//! Every write to an `appendlog` file descriptor is appended to a host file as a record of a
+//! little-endian `u32` length, the data and the head of a hash chain, which is the SHA-256 hash
+//! of the previous head, the length and the data. The Keep opens the host file write-only in
+//! append mode, so it can neither read it back nor truncate it, and a verifier recomputing the
+//! chain detects modified, dropped or reordered records. The chain starts from
+//! [`HEAD_SIZE`] zero bytes in every run of the Keep.
+//!
+//! The workload imports `appendlog_head` from the `enarx` module to obtain the current head,
+//! e.g. to bind it to attestation evidence with `attest`.
+
+use super::super::errno::negate;
+use super::super::keys::{memory, write};
+use super::super::Ctx;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, IoSlice, Write};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use enarx_config::AppendLogFile;
+use sha2::{Digest, Sha256};
+use wasi_common::file::{FdFlags, FileCaps, FileType};
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasi_common::{Error, WasiFile};
+use wasmtime::{Caller, Linker};
+
+/// Size of the head of a chain in bytes
+pub const HEAD_SIZE: usize = 32;
+
+/// A hash chain of the records appended to a host file
+pub(crate) struct Chain {
+    state: Mutex<(File, [u8; HEAD_SIZE])>,
+}
+
+impl Chain {
+    fn new(file: File) -> Self {
+        Self {
+            state: Mutex::new((file, [0; HEAD_SIZE])),
+        }
+    }
+
+    /// Returns the current head of the chain
+    pub(crate) fn head(&self) -> [u8; HEAD_SIZE] {
+        self.state.lock().unwrap().1
+    }
+
+    /// Appends `data` as a record to the host file and returns the new head
+    ///
+    /// The head is only advanced, if the whole record was written.
+    fn append(&self, data: &[u8]) -> io::Result<[u8; HEAD_SIZE]> {
+        let len = u32::try_from(data.len())
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?
+            .to_le_bytes();
+
+        let mut state = self.state.lock().unwrap();
+        let (file, head) = &mut *state;
+        let next: [u8; HEAD_SIZE] = Sha256::new()
+            .chain_update(*head)
+            .chain_update(len)
+            .chain_update(data)
+            .finalize()
+            .into();
+
+        let mut record = Vec::with_capacity(len.len() + data.len() + HEAD_SIZE);
+        record.extend(len);
+        record.extend(data);
+        record.extend(next);
+        file.write_all(&record)?;
+
+        *head = next;
+        Ok(next)
+    }
+}
+
+/// The chains of the `appendlog` files of the workload by path
+///
+/// The host files are opened once and shared by all instances of the workload.
+pub(crate) struct Chains(HashMap<String, Arc<Chain>>);
+
+impl Chains {
+    /// Opens the host files of `files`, creating them if they do not exist
+    pub(crate) fn open<'a>(
+        files: impl IntoIterator<Item = &'a AppendLogFile>,
+    ) -> anyhow::Result<Self> {
+        let mut chains = HashMap::new();
+        for AppendLogFile { path, .. } in files {
+            if chains.contains_key(path) {
+                continue;
+            }
+            let file = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .with_context(|| format!("failed to open append-only log `{path}`"))?;
+            chains.insert(path.clone(), Arc::new(Chain::new(file)));
+        }
+        Ok(Self(chains))
+    }
+
+    /// Returns a new file descriptor of the chain of `conf`
+    pub(crate) fn file(&self, conf: &AppendLogFile) -> anyhow::Result<AppendLog> {
+        self.0
+            .get(&conf.path)
+            .cloned()
+            .map(AppendLog)
+            .with_context(|| format!("append-only log `{}` is not open", conf.path))
+    }
+}
+
+/// The WASI file descriptors of `appendlog` files
+#[derive(Default)]
+pub struct AppendLogs(HashMap<u32, Arc<Chain>>);
+
+impl AppendLogs {
+    /// Marks the WASI file descriptor `fd` as `file`, if it is an `appendlog` file
+    pub fn insert(&mut self, fd: u32, file: &dyn WasiFile) {
+        if let Some(AppendLog(chain)) = file.as_any().downcast_ref::<AppendLog>() {
+            self.0.insert(fd, chain.clone());
+        }
+    }
+
+    /// Returns the chain of the WASI file descriptor `fd`
+    fn resolve(&self, fd: u32) -> Result<&Chain, Errno> {
+        self.0.get(&fd).map(|chain| &**chain).ok_or(Errno::Badf)
+    }
+}
+
+/// A WasiFile appending every write as a record to a [`Chain`]
+pub(crate) struct AppendLog(Arc<Chain>);
+
+impl AppendLog {
+    /// Returns the capabilities of the file descriptor, which exclude reading and truncation
+    pub(crate) fn caps() -> FileCaps {
+        FileCaps::WRITE | FileCaps::DATASYNC | FileCaps::SYNC | FileCaps::POLL_READWRITE
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for AppendLog {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::APPEND)
+    }
+
+    async fn datasync(&mut self) -> Result<(), Error> {
+        self.0.state.lock().unwrap().0.sync_data()?;
+        Ok(())
+    }
+
+    async fn sync(&mut self) -> Result<(), Error> {
+        self.0.state.lock().unwrap().0.sync_all()?;
+        Ok(())
+    }
+
+    async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        let mut data = Vec::with_capacity(bufs.iter().map(|buf| buf.len()).sum());
+        for buf in bufs {
+            data.extend_from_slice(buf);
+        }
+        if data.is_empty() {
+            return Ok(0);
+        }
+        self.0.append(&data)?;
+        Ok(data.len() as _)
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Writes the head of the chain of the `appendlog` file descriptor `fd` to `buf` of `len` bytes
+/// and returns its length or the negated WASI errno
+///
+/// Fails with `ERRNO_BADF`, if `fd` is not an `appendlog` file descriptor, and with
+/// `ERRNO_RANGE`, if the head does not fit into `buf`.
+fn appendlog_head(mut caller: Caller<'_, Ctx>, fd: u32, buf: u32, len: u32) -> i32 {
+    let Ctx {
+        wasi, appendlog, ..
+    } = caller.data_mut();
+    let head = if wasi.table().contains_key(fd) {
+        appendlog.resolve(fd).map(Chain::head)
+    } else {
+        Err(Errno::Badf)
+    };
+    let res = head.and_then(|head| Ok((memory(&mut caller)?, head)));
+    negate(res.and_then(|(memory, head)| write(&mut caller, memory, buf, len, &head)))
+}
+
+/// Adds the `enarx` `appendlog_head` function to `linker`
+pub(in crate::runtime) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "appendlog_head", appendlog_head)
+        .context("failed to add `enarx::appendlog_head`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn append() {
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let conf: AppendLogFile = toml::from_str(&format!("path = '{}'", path.display())).unwrap();
+        let chains = Chains::open([&conf, &conf]).unwrap();
+        assert_eq!(chains.0.len(), 1);
+
+        let chain = chains.file(&conf).unwrap().0;
+        assert_eq!(chain.head(), [0; HEAD_SIZE]);
+        let first = chain.append(b"first").unwrap();
+        let second = chain.append(b"second").unwrap();
+        assert_eq!(chain.head(), second);
+
+        // The records are framed and chained from a zero head
+        let mut expected = vec![];
+        let mut head = [0; HEAD_SIZE];
+        for (data, next) in [(&b"first"[..], first), (b"second", second)] {
+            let len = (data.len() as u32).to_le_bytes();
+            head = Sha256::new()
+                .chain_update(head)
+                .chain_update(len)
+                .chain_update(data)
+                .finalize()
+                .into();
+            assert_eq!(head, next);
+            expected.extend(len);
+            expected.extend(data);
+            expected.extend(head);
+        }
+        assert_eq!(fs::read(&path).unwrap(), expected);
+    }
+}
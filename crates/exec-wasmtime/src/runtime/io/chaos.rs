@@ -0,0 +1,337 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Faults injected into the streams of debug Keeps
+//!
+//! Network emulators on the host, e.g. `tc netem`, only see the encrypted data of in-Keep TLS, so
+//! they can delay and drop packets, but cannot make the workload observe short reads and writes
+//! or resets at a certain point of its protocol. For the `listen` and `connect` file descriptors
+//! selected by the `[chaos]` of the Enarx.toml, reads and writes of the workload are delayed,
+//! shortened and failed with `ECONNRESET` after decryption instead.
+//!
+//! Streams deliver their data in order, so the workload can only observe reordering between
+//! streams, which the random `jitter` of the latency causes. The faults are pseudo-random and
+//! reproducible with a `seed`. They are only injected in Keeps, which allow debugging.
+
+use super::tap::debuggable;
+
+use std::any::Any;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::ensure;
+use enarx_config::{Chaos as ChaosConf, File as FileConf};
+use tracing::warn;
+use wasi_common::file::{FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags};
+use wasi_common::{Error, WasiFile};
+
+/// Increment of the state of the SplitMix64 generator
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The faults of the Keep shared by all file descriptors with faults
+pub struct Chaos {
+    files: Vec<String>,
+    latency: u64,
+    jitter: u64,
+    fragment: u8,
+    reset: u8,
+    state: AtomicU64,
+}
+
+impl Chaos {
+    /// Creates the faults of `conf`
+    ///
+    /// Returns `None`, if the Keep does not allow debugging.
+    pub fn open(conf: &ChaosConf) -> anyhow::Result<Option<Self>> {
+        if !debuggable() {
+            warn!("the chaos is disabled, since the Keep does not allow debugging");
+            return Ok(None);
+        }
+        let seed = match conf.seed {
+            Some(seed) => seed,
+            None => {
+                let mut seed = [0; 8];
+                getrandom::getrandom(&mut seed)?;
+                u64::from_ne_bytes(seed)
+            }
+        };
+        warn!(seed, "faults are injected into the streams of the workload");
+        Self::new(conf, seed).map(Some)
+    }
+
+    fn new(conf: &ChaosConf, seed: u64) -> anyhow::Result<Self> {
+        ensure!(conf.fragment <= 100, "`fragment` exceeds 100 percent");
+        ensure!(conf.reset <= 100, "`reset` exceeds 100 percent");
+        Ok(Self {
+            files: conf.files.clone(),
+            latency: conf.latency.into(),
+            jitter: conf.jitter.into(),
+            fragment: conf.fragment,
+            reset: conf.reset,
+            state: AtomicU64::new(seed),
+        })
+    }
+
+    /// Returns whether faults are injected into the file descriptor `conf`
+    pub fn affects(&self, conf: &FileConf) -> bool {
+        matches!(conf, FileConf::Listen(..) | FileConf::Connect(..))
+            && (self.files.is_empty() || self.files.iter().any(|name| name == conf.name()))
+    }
+
+    /// Returns the next pseudo-random number of the SplitMix64 generator
+    fn random(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(GOLDEN_GAMMA, Ordering::Relaxed)
+            .wrapping_add(GOLDEN_GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns `true` with a probability of `percent` percent
+    fn chance(&self, percent: u8) -> bool {
+        percent > 0 && self.random() % 100 < u64::from(percent)
+    }
+
+    /// Returns the latency of the next read or write
+    fn delay(&self) -> Duration {
+        let jitter = match self.jitter {
+            0 => 0,
+            jitter => self.random() % (jitter + 1),
+        };
+        Duration::from_millis(self.latency + jitter)
+    }
+
+    /// Returns the shorter number of bytes of the next read or write of `len` bytes by chance
+    fn fragment(&self, len: usize) -> Option<usize> {
+        if len > 1 && self.chance(self.fragment) {
+            Some(1 + (self.random() % (len as u64 - 1)) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the error of a reset stream
+fn reset() -> Error {
+    io::Error::from_raw_os_error(libc::ECONNRESET).into()
+}
+
+/// A stream, into which the faults of [`Chaos`] are injected
+pub struct Chaotic {
+    file: Box<dyn WasiFile>,
+    chaos: Arc<Chaos>,
+    reset: bool,
+}
+
+impl Chaotic {
+    /// Wraps `file` to inject the faults of `chaos`
+    pub fn new(file: Box<dyn WasiFile>, chaos: Arc<Chaos>) -> Self {
+        Self {
+            file,
+            chaos,
+            reset: false,
+        }
+    }
+
+    /// Delays the next read or write and resets the stream by chance
+    async fn inject(&mut self) -> Result<(), Error> {
+        if !self.reset && self.chaos.chance(self.chaos.reset) {
+            self.reset = true;
+            let _ = self.file.sock_shutdown(SdFlags::RD | SdFlags::WR).await;
+        }
+        if self.reset {
+            return Err(reset());
+        }
+        let delay = self.chaos.delay();
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        Ok(())
+    }
+
+    /// Returns the index and the shorter length of the first non-empty buffer of `lens` to
+    /// fragment the next read or write by chance
+    fn fragment(&self, mut lens: impl Iterator<Item = usize>) -> Option<(usize, usize)> {
+        let (i, len) = lens.enumerate().find(|(_, len)| *len > 0)?;
+        self.chaos.fragment(len).map(|len| (i, len))
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Chaotic {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd<'_>> {
+        self.file.pollable()
+    }
+
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.file.pollable()
+    }
+
+    fn isatty(&mut self) -> bool {
+        self.file.isatty()
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        self.file.get_filetype().await
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        self.file.get_fdflags().await
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        self.file.set_fdflags(fdflags).await
+    }
+
+    async fn get_filestat(&mut self) -> Result<Filestat, Error> {
+        self.file.get_filestat().await
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.inject().await?;
+        match self.fragment(bufs.iter().map(|buf| buf.len())) {
+            Some((i, len)) => {
+                let mut buf = [IoSliceMut::new(&mut bufs[i][..len])];
+                self.file.read_vectored(&mut buf).await
+            }
+            None => self.file.read_vectored(bufs).await,
+        }
+    }
+
+    async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        self.inject().await?;
+        match self.fragment(bufs.iter().map(|buf| buf.len())) {
+            Some((i, len)) => {
+                self.file
+                    .write_vectored(&[IoSlice::new(&bufs[i][..len])])
+                    .await
+            }
+            None => self.file.write_vectored(bufs).await,
+        }
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.file.peek(buf).await
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.file.num_ready_bytes().await
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        self.file.readable().await
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        self.file.writable().await
+    }
+
+    async fn sock_accept(&mut self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        let stream = self.file.sock_accept(fdflags).await?;
+        Ok(Box::new(Self::new(stream, self.chaos.clone())))
+    }
+
+    async fn sock_recv<'a>(
+        &mut self,
+        ri_data: &mut [IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        if ri_flags.contains(RiFlags::RECV_PEEK) {
+            return self.file.sock_recv(ri_data, ri_flags).await;
+        }
+        self.inject().await?;
+        match self.fragment(ri_data.iter().map(|buf| buf.len())) {
+            Some((i, len)) => {
+                let mut buf = [IoSliceMut::new(&mut ri_data[i][..len])];
+                self.file.sock_recv(&mut buf, ri_flags).await
+            }
+            None => self.file.sock_recv(ri_data, ri_flags).await,
+        }
+    }
+
+    async fn sock_send<'a>(
+        &mut self,
+        si_data: &[IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        self.inject().await?;
+        match self.fragment(si_data.iter().map(|buf| buf.len())) {
+            Some((i, len)) => {
+                self.file
+                    .sock_send(&[IoSlice::new(&si_data[i][..len])], si_flags)
+                    .await
+            }
+            None => self.file.sock_send(si_data, si_flags).await,
+        }
+    }
+
+    async fn sock_shutdown(&mut self, how: SdFlags) -> Result<(), Error> {
+        self.file.sock_shutdown(how).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faults() {
+        let conf = ChaosConf {
+            latency: 5,
+            jitter: 10,
+            fragment: 50,
+            ..Default::default()
+        };
+        let chaos = Chaos::new(&conf, 42).unwrap();
+        for _ in 0..100 {
+            let delay = chaos.delay();
+            assert!(delay >= Duration::from_millis(5) && delay <= Duration::from_millis(15));
+            assert!(chaos.fragment(8).map_or(true, |len| (1..8).contains(&len)));
+            assert_eq!(chaos.fragment(1), None);
+            assert!(!chaos.chance(0));
+            assert!(chaos.chance(100));
+        }
+
+        // Faults are reproducible with the same seed
+        let other = Chaos::new(&conf, 42).unwrap();
+        let chaos = Chaos::new(&conf, 42).unwrap();
+        assert!((0..16).all(|_| chaos.random() == other.random()));
+
+        // Reads and writes are fragmented by chance
+        assert!((0..100).any(|_| chaos.fragment(1024).is_some()));
+        assert!((0..100).any(|_| chaos.fragment(1024).is_none()));
+
+        let conf = ChaosConf {
+            reset: 101,
+            ..Default::default()
+        };
+        assert!(Chaos::new(&conf, 0).is_err());
+    }
+
+    #[test]
+    fn affects() {
+        let chaos = Chaos::new(&ChaosConf::default(), 0).unwrap();
+        let connect: FileConf = toml::from_str(
+            "kind = 'connect'\nname = 'db'\nprot = 'tcp'\nhost = 'localhost'\nport = 5432",
+        )
+        .unwrap();
+        assert!(chaos.affects(&connect));
+        assert!(!chaos.affects(&FileConf::Stdin(Default::default())));
+
+        let conf = ChaosConf {
+            files: vec!["other".into()],
+            ..Default::default()
+        };
+        assert!(!Chaos::new(&conf, 0).unwrap().affects(&connect));
+    }
+}
@@ -0,0 +1,234 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read-only file descriptors of the datasets of the workload
+//!
+//! Every read of a `dataset` file descriptor is served from a verified chunk of the
+//! [`Dataset`](crate::dataset::Dataset). Only the last chunk read is held in the memory of the
+//! Keep per file descriptor, the dataset itself stays in the host page cache.
+
+use crate::dataset::{Dataset, CHUNK_SIZE};
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{IoSliceMut, SeekFrom};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use enarx_config::DatasetFile;
+use wasi_common::file::{FdFlags, FileCaps, FileType, Filestat};
+use wasi_common::{Error, ErrorExt, WasiFile};
+
+/// The datasets of the workload by path
+///
+/// The datasets are verified once and shared by all instances of the workload.
+#[derive(Default)]
+pub(crate) struct Datasets(HashMap<String, Arc<Dataset>>);
+
+impl Datasets {
+    /// Verifies the datasets of `files` in the host files `opened` by the host
+    pub(crate) fn open<'a>(
+        files: impl IntoIterator<Item = &'a DatasetFile>,
+        mut opened: HashMap<String, fs::File>,
+    ) -> anyhow::Result<Self> {
+        let mut datasets = HashMap::new();
+        for DatasetFile { path, digest, .. } in files {
+            if datasets.contains_key(path) {
+                continue;
+            }
+            let file = opened
+                .remove(path)
+                .ok_or_else(|| anyhow!("dataset `{path}` was not opened by the host"))?;
+            let dataset = Dataset::open(file, digest)
+                .with_context(|| format!("failed to verify dataset `{path}`"))?;
+            datasets.insert(path.clone(), Arc::new(dataset));
+        }
+        Ok(Self(datasets))
+    }
+
+    /// Returns a new file descriptor of the dataset of `conf`
+    pub(crate) fn file(&self, conf: &DatasetFile) -> anyhow::Result<DatasetReader> {
+        self.0
+            .get(&conf.path)
+            .cloned()
+            .map(DatasetReader::new)
+            .with_context(|| format!("dataset `{}` is not open", conf.path))
+    }
+}
+
+/// A WasiFile reading verified chunks of a [`Dataset`]
+pub(crate) struct DatasetReader {
+    dataset: Arc<Dataset>,
+    pos: u64,
+    /// Index and data of the last chunk read
+    chunk: Option<(usize, Vec<u8>)>,
+}
+
+impl DatasetReader {
+    fn new(dataset: Arc<Dataset>) -> Self {
+        Self {
+            dataset,
+            pos: 0,
+            chunk: None,
+        }
+    }
+
+    /// Returns the capabilities of the file descriptor, which exclude writing
+    pub(crate) fn caps() -> FileCaps {
+        FileCaps::READ
+            | FileCaps::SEEK
+            | FileCaps::TELL
+            | FileCaps::FILESTAT_GET
+            | FileCaps::POLL_READWRITE
+    }
+
+    /// Copies the data at `offset` into `buf` and returns the number of bytes copied
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+        if offset >= self.dataset.size() || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = (offset / CHUNK_SIZE as u64) as usize;
+        let data = match &mut self.chunk {
+            Some((cached, data)) if *cached == index => data,
+            chunk => {
+                let mut data = chunk.take().map(|(_, data)| data).unwrap_or_default();
+                self.dataset
+                    .chunk(index, &mut data)
+                    .map_err(|e| Error::io().context(e))?;
+                &mut chunk.insert((index, data)).1
+            }
+        };
+        let start = (offset % CHUNK_SIZE as u64) as usize;
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for DatasetReader {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::empty())
+    }
+
+    async fn get_filestat(&mut self) -> Result<Filestat, Error> {
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: FileType::RegularFile,
+            nlink: 1,
+            size: self.dataset.size(),
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        let n = self.read_vectored_at(bufs, self.pos).await?;
+        self.pos += n;
+        Ok(n)
+    }
+
+    async fn read_vectored_at<'a>(
+        &mut self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let mut total = 0;
+        for buf in bufs.iter_mut() {
+            let mut done = 0;
+            while done < buf.len() {
+                match self.read_at(&mut buf[done..], offset + total + done as u64)? {
+                    0 => return Ok(total + done as u64),
+                    n => done += n,
+                }
+            }
+            total += done as u64;
+        }
+        Ok(total)
+    }
+
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.dataset.size().checked_add_signed(delta),
+        };
+        self.pos = pos.ok_or_else(Error::invalid_argument)?;
+        Ok(self.pos)
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.read_vectored_at(&mut [IoSliceMut::new(buf)], self.pos)
+            .await
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(self.dataset.size().saturating_sub(self.pos))
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dataset::dataset_digest;
+
+    use std::io::{Seek, Write};
+
+    #[test]
+    fn read() {
+        let data: Vec<u8> = (0..CHUNK_SIZE + 10).map(|i| i as u8).collect();
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&data).unwrap();
+        file.rewind().unwrap();
+
+        let conf: DatasetFile = toml::from_str(&format!(
+            "path = 'geo.bin'\ndigest = '{}'",
+            dataset_digest(&data[..]).unwrap()
+        ))
+        .unwrap();
+        assert!(Datasets::open([&conf], HashMap::new()).is_err());
+
+        let datasets =
+            Datasets::open([&conf, &conf], HashMap::from([("geo.bin".into(), file)])).unwrap();
+        let mut reader = datasets.file(&conf).unwrap();
+        wiggle::run_in_dummy_executor(async {
+            // A read across the chunk boundary
+            let mut buf = [0; 20];
+            let n = reader
+                .read_vectored_at(&mut [IoSliceMut::new(&mut buf)], CHUNK_SIZE as u64 - 10)
+                .await
+                .unwrap();
+            assert_eq!(n, 20);
+            assert_eq!(buf[..], data[CHUNK_SIZE - 10..]);
+
+            assert_eq!(
+                reader.seek(SeekFrom::End(-4)).await.unwrap(),
+                CHUNK_SIZE as u64 + 6
+            );
+            let mut buf = [0; 8];
+            let n = reader
+                .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+                .await
+                .unwrap();
+            assert_eq!(n, 4);
+            assert_eq!(buf[..4], data[CHUNK_SIZE + 6..]);
+            assert_eq!(reader.num_ready_bytes().await.unwrap(), 0);
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Readiness signals, which the workload can poll
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+
+use io_lifetimes::{AsFd, BorrowedFd};
+use wasi_common::Error;
+
+/// Readiness signal backed by an `eventfd`
+#[derive(Debug)]
+pub struct Event(File);
+
+impl Event {
+    pub fn new() -> io::Result<Self> {
+        // SAFETY: eventfd() returns a new file descriptor owned by the caller or fails
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd is a valid file descriptor, which is not owned by anything else
+        Ok(Self(unsafe { File::from_raw_fd(fd) }))
+    }
+
+    /// Marks the event ready
+    pub fn set(&self) {
+        let _ = (&self.0).write(&1u64.to_ne_bytes());
+    }
+
+    /// Marks the event not ready
+    pub fn clear(&self) {
+        let _ = (&self.0).read(&mut [0; 8]);
+    }
+
+    /// Blocks, until the event is ready
+    pub fn wait(&self) -> Result<(), Error> {
+        let mut fds = libc::pollfd {
+            fd: self.0.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: fds is a valid pollfd array of one element
+        match unsafe { libc::poll(&mut fds, 1, -1) } {
+            n if n >= 0 => Ok(()),
+            _ => match io::Error::last_os_error() {
+                e if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+                e => Err(e.into()),
+            },
+        }
+    }
+}
+
+impl AsFd for Event {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
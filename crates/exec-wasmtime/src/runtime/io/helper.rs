@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Streams to the helper Keeps of the workload
+//!
+//! For every `helper` file descriptor, the host spawns a Keep of the `nil` backend running the
+//! Wasm module at its `path`, whose standard input and output are connected to a stream. The
+//! host passes the module together with the stream to the Keep, which verifies the module against
+//! the `digest` of the config and claims it in its preopens, so relying parties can tell, which
+//! helper the Keep vouches for. The helper Keep has no network, all its traffic passes through
+//! the workload.
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, ensure};
+use enarx_config::HelperFile;
+use sha2::{Digest, Sha256};
+use wasi_common::file::FileCaps;
+use wasi_common::WasiFile;
+
+/// The streams to the helper Keeps of the workload by name
+#[derive(Debug, Default)]
+pub(crate) struct Helpers(Mutex<HashMap<String, fs::File>>);
+
+impl Helpers {
+    /// Verifies the Wasm modules of the helpers of `files` passed by the host in `spawned`
+    ///
+    /// `files` are the names of the `helper` file descriptors with their configuration, `spawned`
+    /// are the modules of the helper Keeps with the streams connected to them by name.
+    pub(crate) fn open<'a>(
+        files: impl IntoIterator<Item = (&'a str, &'a HelperFile)>,
+        mut spawned: HashMap<String, (Vec<u8>, fs::File)>,
+    ) -> anyhow::Result<Self> {
+        let mut streams = HashMap::new();
+        for (name, HelperFile { path, digest, .. }) in files {
+            let (webasm, stream) = spawned
+                .remove(name)
+                .ok_or_else(|| anyhow!("helper `{name}` was not spawned by the host"))?;
+            let actual = format!("sha256:{}", hex::encode(Sha256::digest(webasm)));
+            ensure!(
+                &actual == digest,
+                "helper `{name}` module `{path}` digest `{actual}` does not match `{digest}`"
+            );
+            streams.insert(name.to_string(), stream);
+        }
+        Ok(Self(Mutex::new(streams)))
+    }
+
+    /// Takes the stream to the helper Keep of the `helper` file descriptor `name`
+    #[cfg(unix)]
+    pub(crate) fn file(&self, name: &str) -> anyhow::Result<(Box<dyn WasiFile>, FileCaps)> {
+        use std::os::unix::io::OwnedFd;
+        use std::os::unix::net::UnixStream;
+
+        let stream = self
+            .0
+            .lock()
+            .unwrap()
+            .remove(name)
+            .ok_or_else(|| anyhow!("the stream to helper `{name}` is already open"))?;
+        let stream = UnixStream::from(OwnedFd::from(stream));
+        let stream = cap_std::os::unix::net::UnixStream::from_std(stream);
+        Ok((
+            wasmtime_wasi::net::Socket::from(stream).into(),
+            FileCaps::FILESTAT_GET
+                | FileCaps::FDSTAT_SET_FLAGS
+                | FileCaps::POLL_READWRITE
+                | FileCaps::READ
+                | FileCaps::WRITE,
+        ))
+    }
+
+    /// Takes the stream to the helper Keep of the `helper` file descriptor `name`
+    #[cfg(not(unix))]
+    pub(crate) fn file(&self, name: &str) -> anyhow::Result<(Box<dyn WasiFile>, FileCaps)> {
+        Err(anyhow!("helper `{name}` is not supported on this platform"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempfile;
+
+    #[test]
+    fn verify() {
+        let conf: HelperFile = toml::from_str(&format!(
+            r#"
+            path = "ocr.wasm"
+            digest = "sha256:{}"
+            "#,
+            hex::encode(Sha256::digest(b"module"))
+        ))
+        .unwrap();
+        let spawned = |webasm: &[u8]| {
+            HashMap::from([("ocr".to_string(), (webasm.to_vec(), tempfile().unwrap()))])
+        };
+
+        let helpers = Helpers::open([("ocr", &conf)], spawned(b"module")).unwrap();
+        assert!(helpers.0.lock().unwrap().contains_key("ocr"));
+
+        assert!(Helpers::open([("ocr", &conf)], spawned(b"other")).is_err());
+        assert!(Helpers::open([("ocr", &conf)], HashMap::new()).is_err());
+    }
+}
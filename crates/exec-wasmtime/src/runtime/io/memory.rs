@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! A WasiFile reporting the memory usage of the workload
+//!
+//! Every read returns a single line `size=<bytes> peak=<bytes>`, followed by ` limit=<bytes>` if
+//! the memory is limited and by ` heap=<bytes> heap_peak=<bytes>` if the hardened heap of the
+//! Keep is in use. The file is readable, when the memory usage grew above the pressure
+//! threshold or a growth was denied since the last read, so the workload can poll it to shed
+//! load before `memory.grow` fails.
+//!
+//! The observer variant read by a sidecar neither consumes these notifications nor is pollable.
+
+use super::super::limits::Memory;
+use crate::heap::HEAP;
+
+use std::any::Any;
+use std::io::{IoSliceMut, Write};
+use std::sync::Arc;
+
+use io_lifetimes::AsFd;
+use wasi_common::file::{FdFlags, FileType};
+use wasi_common::{Error, WasiFile};
+
+pub(crate) struct MemoryFile {
+    memory: Arc<Memory>,
+    observer: bool,
+}
+
+impl MemoryFile {
+    pub(crate) fn new(memory: Arc<Memory>) -> Self {
+        Self {
+            memory,
+            observer: false,
+        }
+    }
+
+    /// Returns a file, which reports the memory usage without clearing the pressure event
+    pub(crate) fn observer(memory: Arc<Memory>) -> Self {
+        Self {
+            memory,
+            observer: true,
+        }
+    }
+
+    fn report(&self) -> String {
+        let usage = self.memory.usage();
+        let mut report = format!("size={} peak={}", usage.size, usage.peak);
+        if let Some(limit) = usage.limit {
+            report += &format!(" limit={limit}");
+        }
+        if HEAP.active() {
+            let heap = HEAP.usage();
+            report += &format!(" heap={} heap_peak={}", heap.allocated, heap.peak);
+        }
+        report + "\n"
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for MemoryFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd<'_>> {
+        if self.observer {
+            None
+        } else {
+            Some(self.memory.event().as_fd())
+        }
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::NONBLOCK)
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        if !self.observer {
+            self.memory.event().clear();
+        }
+        let report = self.report();
+        let mut report = report.as_bytes();
+        let mut n = 0;
+        for buf in bufs {
+            n += (&mut **buf).write(report)?;
+            report = &report[report.len().min(buf.len())..];
+        }
+        Ok(n as _)
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(self.report().len() as _)
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use enarx_config::Limits;
+
+    #[test]
+    fn report() {
+        let memory = Memory::new(&Limits::default()).unwrap();
+        assert_eq!(MemoryFile::new(memory).report(), "size=0 peak=0\n");
+
+        let limits = Limits {
+            memory_size: Some(65536),
+            ..Default::default()
+        };
+        let memory = Memory::new(&limits).unwrap();
+        assert_eq!(
+            MemoryFile::new(memory).report(),
+            "size=0 peak=0 limit=65536\n"
+        );
+    }
+}
@@ -2,7 +2,24 @@
 
 //! I/O functionality for keeps
 
+pub mod appendlog;
+pub mod chaos;
+pub mod dataset;
+#[cfg(target_os = "linux")]
+pub mod event;
+pub mod helper;
+#[cfg(target_os = "linux")]
+pub mod memory;
 pub mod null;
+#[cfg(target_os = "linux")]
+pub mod pad;
+#[cfg(target_os = "linux")]
+pub mod splice;
+pub mod tap;
+#[cfg(unix)]
+pub mod tmp;
+#[cfg(unix)]
+pub mod tty;
 
 use wasi_common::file::FileCaps;
 use wasi_common::WasiFile;
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Padded reads of confidential file descriptors
+//!
+//! The host observes the size of every read request of the Keep, which may depend on secret
+//! data, e.g. the length of a message read after its length prefix. For file descriptors with a
+//! `pad` size in the Enarx.toml, every read request issued to the host is exactly `pad` bytes and
+//! the data exceeding the read of the workload is buffered inside the Keep.
+//!
+//! While data is buffered, the file is polled via an `eventfd`, which is always readable, since
+//! the host file descriptor might not have any data left.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, IoSlice, IoSliceMut, Read};
+use std::os::unix::io::FromRawFd;
+
+use anyhow::ensure;
+use io_lifetimes::AsFd;
+use wasi_common::file::{FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags};
+use wasi_common::{Error, WasiFile};
+
+/// Maximum size of a padded read request
+pub const MAX_PAD: u32 = 64 * 1024;
+
+/// A file, which only issues read requests of a fixed size to the host
+pub struct Padded {
+    file: Box<dyn WasiFile>,
+    /// Data read from the host, but not yet by the workload
+    buf: VecDeque<u8>,
+    /// Scratch space of a read request
+    chunk: Vec<u8>,
+    /// Always readable `eventfd` signaling buffered data
+    ready: File,
+}
+
+impl Padded {
+    /// Wraps `file` to read `pad` bytes from the host at a time
+    pub fn new(file: Box<dyn WasiFile>, pad: u32) -> anyhow::Result<Self> {
+        ensure!(
+            pad > 0 && pad <= MAX_PAD,
+            "pad size `{pad}` is not between 1 and {MAX_PAD}"
+        );
+
+        // SAFETY: eventfd() returns a new file descriptor owned by the caller or fails
+        let fd = unsafe { libc::eventfd(1, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(Self {
+            file,
+            buf: VecDeque::new(),
+            chunk: vec![0; pad as _],
+            // SAFETY: fd is a valid file descriptor, which is not owned by anything else
+            ready: unsafe { File::from_raw_fd(fd) },
+        })
+    }
+
+    /// Issues one read request of `pad` bytes to the host, unless data is buffered
+    async fn fill(&mut self) -> Result<(), Error> {
+        if self.buf.is_empty() {
+            let n = self
+                .file
+                .read_vectored(&mut [IoSliceMut::new(&mut self.chunk)])
+                .await?;
+            self.buf.extend(&self.chunk[..n as usize]);
+        }
+        Ok(())
+    }
+
+    /// Copies buffered data to `buf` without consuming it
+    fn peek_buffered(&self, buf: &mut [u8]) -> u64 {
+        let n = buf.len().min(self.buf.len());
+        for (dst, src) in buf.iter_mut().zip(self.buf.iter()) {
+            *dst = *src;
+        }
+        n as _
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Padded {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd<'_>> {
+        if self.buf.is_empty() {
+            self.file.pollable()
+        } else {
+            Some(self.ready.as_fd())
+        }
+    }
+
+    fn isatty(&mut self) -> bool {
+        self.file.isatty()
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        self.file.get_filetype().await
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        self.file.get_fdflags().await
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        self.file.set_fdflags(fdflags).await
+    }
+
+    async fn get_filestat(&mut self) -> Result<Filestat, Error> {
+        self.file.get_filestat().await
+    }
+
+    async fn datasync(&mut self) -> Result<(), Error> {
+        self.file.datasync().await
+    }
+
+    async fn sync(&mut self) -> Result<(), Error> {
+        self.file.sync().await
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
+        self.fill().await?;
+        Ok(self.buf.read_vectored(bufs)? as _)
+    }
+
+    async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        self.file.write_vectored(bufs).await
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> Result<u64, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.fill().await?;
+        Ok(self.peek_buffered(buf))
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        let ready = self.file.num_ready_bytes().await?;
+        Ok(ready + self.buf.len() as u64)
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        if self.buf.is_empty() {
+            self.file.readable().await
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        self.file.writable().await
+    }
+
+    async fn sock_recv<'a>(
+        &mut self,
+        ri_data: &mut [IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        if ri_flags.contains(RiFlags::RECV_PEEK) {
+            if let Some(buf) = ri_data.iter_mut().find(|buf| !buf.is_empty()) {
+                self.fill().await?;
+                return Ok((self.peek_buffered(buf), RoFlags::empty()));
+            }
+            return Ok((0, RoFlags::empty()));
+        }
+
+        let mut data = vec![0; ri_data.iter().map(|buf| buf.len()).sum()];
+        let mut total = 0;
+        while total < data.len() {
+            let n = self
+                .read_vectored(&mut [IoSliceMut::new(&mut data[total..])])
+                .await? as usize;
+            total += n;
+            if n == 0 || !ri_flags.contains(RiFlags::RECV_WAITALL) {
+                break;
+            }
+        }
+
+        let mut rest = &data[..total];
+        for buf in ri_data.iter_mut() {
+            let n = buf.len().min(rest.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            rest = &rest[n..];
+        }
+        Ok((total as _, RoFlags::empty()))
+    }
+
+    async fn sock_send<'a>(
+        &mut self,
+        si_data: &[IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        self.file.sock_send(si_data, si_flags).await
+    }
+
+    async fn sock_shutdown(&mut self, how: SdFlags) -> Result<(), Error> {
+        self.file.sock_shutdown(how).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    /// A file recording the sizes of the read requests
+    struct Source {
+        data: VecDeque<u8>,
+        requests: Arc<Mutex<Vec<usize>>>,
+    }
+
+    #[wiggle::async_trait]
+    impl WasiFile for Source {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        async fn get_filetype(&mut self) -> Result<FileType, Error> {
+            Ok(FileType::Pipe)
+        }
+
+        async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+            let len = bufs.iter().map(|buf| buf.len()).sum();
+            self.requests.lock().unwrap().push(len);
+            Ok(self.data.read_vectored(bufs)? as _)
+        }
+    }
+
+    fn padded(data: &[u8], pad: u32) -> (Padded, Arc<Mutex<Vec<usize>>>) {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let source = Source {
+            data: data.iter().copied().collect(),
+            requests: requests.clone(),
+        };
+        (Padded::new(Box::new(source), pad).unwrap(), requests)
+    }
+
+    fn read(file: &mut Padded, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        let n = wiggle::run_in_dummy_executor(file.read_vectored(&mut [IoSliceMut::new(&mut buf)]))
+            .unwrap()
+            .unwrap();
+        buf.truncate(n as _);
+        buf
+    }
+
+    #[test]
+    fn requests() {
+        let (mut file, requests) = padded(b"\x05hello world", 8);
+        assert_eq!(read(&mut file, 1), b"\x05");
+        assert!(file.pollable().is_some());
+        assert_eq!(read(&mut file, 5), b"hello");
+        assert_eq!(read(&mut file, 100), b"\x20w");
+        assert_eq!(read(&mut file, 100), b"orld");
+        assert_eq!(read(&mut file, 100), b"");
+
+        // The host only observes reads of the pad size
+        assert_eq!(*requests.lock().unwrap(), vec![8, 8, 8]);
+    }
+
+    #[test]
+    fn peek() {
+        let (mut file, requests) = padded(b"secret", 4);
+        let mut buf = [0; 2];
+        let n = wiggle::run_in_dummy_executor(file.peek(&mut buf))
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..n as usize], b"se");
+        assert_eq!(
+            wiggle::run_in_dummy_executor(file.num_ready_bytes())
+                .unwrap()
+                .unwrap(),
+            4
+        );
+
+        let mut head = [0; 3];
+        let mut tail = [0; 3];
+        let (n, _) = wiggle::run_in_dummy_executor(file.sock_recv(
+            &mut [IoSliceMut::new(&mut head), IoSliceMut::new(&mut tail)],
+            RiFlags::RECV_WAITALL,
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(n, 6);
+        assert_eq!((&head, &tail), (b"sec", b"ret"));
+        assert_eq!(*requests.lock().unwrap(), vec![4, 4]);
+    }
+
+    #[test]
+    fn invalid() {
+        for pad in [0, MAX_PAD + 1] {
+            let source = Source {
+                data: VecDeque::new(),
+                requests: Default::default(),
+            };
+            assert!(Padded::new(Box::new(source), pad).is_err());
+        }
+    }
+}
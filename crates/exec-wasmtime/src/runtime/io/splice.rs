@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host-side splicing of data between non-confidential file descriptors
+//!
+//! The workload imports `splice` from the `enarx` module to copy data between two file
+//! descriptors, which are marked as non-confidential in the Enarx.toml, without routing
+//! the data through the Keep.
+
+use super::super::errno::{from_io, negate};
+use super::super::Ctx;
+
+use std::collections::HashMap;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr::null_mut;
+
+use anyhow::Context;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasi_common::WasiFile;
+use wasmtime::{Caller, Linker};
+
+/// A host file descriptor and the identity of the file it referred to, when it was registered
+///
+/// The identity guards against the host file descriptor being closed by the workload and
+/// reused for a confidential file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct HostFd {
+    fd: RawFd,
+    dev: libc::dev_t,
+    ino: libc::ino_t,
+}
+
+impl HostFd {
+    fn stat(fd: RawFd) -> io::Result<Self> {
+        let mut stat = MaybeUninit::<libc::stat>::uninit();
+        if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stat = unsafe { stat.assume_init() };
+        Ok(Self {
+            fd,
+            dev: stat.st_dev,
+            ino: stat.st_ino,
+        })
+    }
+}
+
+/// The WASI file descriptors, which may be spliced on the host
+#[derive(Debug, Default)]
+pub struct Splice(HashMap<u32, HostFd>);
+
+impl Splice {
+    /// Marks the WASI file descriptor `fd` referring to `file` as spliceable
+    pub fn insert(&mut self, fd: u32, file: &dyn WasiFile) -> anyhow::Result<()> {
+        let raw = file
+            .pollable()
+            .context("file descriptor is not backed by a host file descriptor")?
+            .as_raw_fd();
+        let host = HostFd::stat(raw).context("failed to query host file descriptor")?;
+        self.0.insert(fd, host);
+        Ok(())
+    }
+
+    /// Returns the host file descriptor of the WASI file descriptor `fd`
+    fn resolve(&self, fd: u32) -> Result<RawFd, Errno> {
+        let host = self.0.get(&fd).ok_or(Errno::Perm)?;
+        match HostFd::stat(host.fd) {
+            Ok(current) if current == *host => Ok(host.fd),
+            _ => Err(Errno::Badf),
+        }
+    }
+}
+
+/// Copies up to `len` bytes from `fd_in` to `fd_out` on the host
+///
+/// Returns the number of bytes copied or the negated WASI errno.
+fn splice(mut caller: Caller<'_, Ctx>, fd_in: u32, fd_out: u32, len: u64) -> i64 {
+    let Ctx { wasi, splice, .. } = caller.data_mut();
+    let table = wasi.table();
+    let open = table.contains_key(fd_in) && table.contains_key(fd_out);
+
+    let copy = || {
+        if !open {
+            return Err(Errno::Badf);
+        }
+        let fd_in = splice.resolve(fd_in)?;
+        let fd_out = splice.resolve(fd_out)?;
+        let len = len.try_into().unwrap_or(usize::MAX);
+        match unsafe { libc::sendfile(fd_out, fd_in, null_mut(), len) } {
+            n if n >= 0 => Ok(n as i64),
+            _ => Err(from_io(&io::Error::last_os_error())),
+        }
+    };
+    negate(copy())
+}
+
+/// Adds the `enarx` `splice` function to `linker`
+pub(in crate::runtime) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "splice", splice)
+        .context("failed to add `enarx::splice`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+
+    use wasmtime_wasi::stdio::stdout;
+
+    #[test]
+    fn resolve() {
+        let file = tempfile::tempfile().unwrap();
+        let mut splice = Splice::default();
+        splice.insert(3, &stdout()).unwrap();
+        splice.0.insert(4, HostFd::stat(file.as_raw_fd()).unwrap());
+
+        assert_eq!(splice.resolve(3), Ok(libc::STDOUT_FILENO));
+        assert_eq!(splice.resolve(4), Ok(file.as_raw_fd()));
+        assert_eq!(splice.resolve(5), Err(Errno::Perm));
+
+        // A reused host file descriptor is rejected
+        drop(file);
+        let _other = File::open("/").unwrap();
+        assert_eq!(splice.resolve(4), Err(Errno::Badf));
+    }
+}
@@ -0,0 +1,445 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tap mirroring the decrypted data of the streams of debug Keeps
+//!
+//! With in-Keep TLS, the data on the wire is encrypted, so developers cannot see what their
+//! workload actually sent and received. For the `listen` and `connect` file descriptors selected
+//! by the `[tap]` of the Enarx.toml, every read and write of the workload is mirrored after
+//! decryption, either to a pcapng file opened by the host with `enarx run --tap` or to the log.
+//!
+//! The pcapng file has a single interface with the `LINKTYPE_WIRESHARK_UPPER_PDU` link type, so
+//! Wireshark dissects the data as the configured protocol. Every packet is flagged inbound or
+//! outbound and carries the name of the file descriptor as a comment.
+//!
+//! The remainders of lines starting with one of the `redact` prefixes are replaced by `*` before
+//! the data leaves the Keep. The tap is only enabled in Keeps, which allow debugging.
+
+use super::super::identity::Claims;
+
+use std::any::Any;
+use std::fs::File;
+use std::io::{self, IoSlice, IoSliceMut, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use enarx_config::{File as FileConf, Tap as TapConf};
+use tracing::{info, warn};
+use wasi_common::file::{FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags};
+use wasi_common::{Error, WasiFile};
+
+/// Link type of Wireshark's exported PDUs, which name the dissector of the data
+const LINKTYPE_WIRESHARK_UPPER_PDU: u16 = 252;
+
+/// Tag of the exported PDU naming the protocol of the data
+const EXP_PDU_TAG_PROTO_NAME: u16 = 12;
+
+/// Direction of the data relative to the workload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    /// Returns the pcapng `epb_flags` of the direction
+    fn flags(self) -> u32 {
+        match self {
+            Self::In => 1,
+            Self::Out => 2,
+        }
+    }
+}
+
+/// Returns `data` padded with zeros to a multiple of 4 bytes
+fn pad4(data: &[u8]) -> Vec<u8> {
+    let mut padded = data.to_vec();
+    padded.resize((data.len() + 3) & !3, 0);
+    padded
+}
+
+/// Returns a pcapng block of `typ` with `body`, which is padded to a multiple of 4 bytes
+fn block(typ: u32, body: &[u8]) -> Vec<u8> {
+    let body = pad4(body);
+    let len = (body.len() + 12) as u32;
+    let mut block = Vec::with_capacity(len as _);
+    block.extend(typ.to_le_bytes());
+    block.extend(len.to_le_bytes());
+    block.extend(body);
+    block.extend(len.to_le_bytes());
+    block
+}
+
+/// Appends the pcapng option `code` with `value` to `options`
+fn option(options: &mut Vec<u8>, code: u16, value: &[u8]) {
+    options.extend(code.to_le_bytes());
+    options.extend((value.len() as u16).to_le_bytes());
+    options.extend(pad4(value));
+}
+
+/// Replaces the remainders of the lines of `data` starting with one of `prefixes` by `*`
+fn redact(data: &mut [u8], prefixes: &[Vec<u8>]) {
+    let mut start = 0;
+    while start < data.len() {
+        let end = data[start..]
+            .iter()
+            .position(|b| *b == b'\n' || *b == b'\r')
+            .map_or(data.len(), |n| start + n);
+        let line = &data[start..end];
+        let trimmed = line
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(line.len());
+        for prefix in prefixes {
+            let from = start + trimmed + prefix.len();
+            if from <= end && data[start + trimmed..from].eq_ignore_ascii_case(prefix) {
+                data[from..end].fill(b'*');
+                break;
+            }
+        }
+        start = end + 1;
+    }
+}
+
+/// Destination of the mirrored data
+enum Output {
+    Log,
+    Pcapng(Mutex<File>),
+}
+
+/// The tap of the Keep shared by all tapped file descriptors
+pub struct Tap {
+    files: Vec<String>,
+    protocol: String,
+    redact: Vec<Vec<u8>>,
+    output: Output,
+}
+
+impl Tap {
+    /// Creates the tap of `conf`, which writes to the pcapng file `pcapng` or to the log
+    ///
+    /// Returns `None`, if the Keep does not allow debugging.
+    pub fn open(conf: &TapConf, pcapng: Option<File>) -> anyhow::Result<Option<Self>> {
+        if !debuggable() {
+            warn!("the tap is disabled, since the Keep does not allow debugging");
+            return Ok(None);
+        }
+        Self::new(conf, pcapng).map(Some)
+    }
+
+    fn new(conf: &TapConf, pcapng: Option<File>) -> anyhow::Result<Self> {
+        let output = match pcapng {
+            Some(mut file) => {
+                let mut shb = vec![];
+                shb.extend(0x1a2b_3c4d_u32.to_le_bytes());
+                shb.extend(1_u16.to_le_bytes());
+                shb.extend(0_u16.to_le_bytes());
+                shb.extend((-1_i64).to_le_bytes());
+                let mut idb = vec![];
+                idb.extend(LINKTYPE_WIRESHARK_UPPER_PDU.to_le_bytes());
+                idb.extend(0_u16.to_le_bytes());
+                idb.extend(0_u32.to_le_bytes());
+                file.write_all(&block(0x0a0d_0d0a, &shb))?;
+                file.write_all(&block(1, &idb))?;
+                Output::Pcapng(Mutex::new(file))
+            }
+            None => Output::Log,
+        };
+        warn!("the decrypted data of the tapped streams leaves the Keep");
+        Ok(Self {
+            files: conf.files.clone(),
+            protocol: conf.protocol.clone().unwrap_or_else(|| "data".into()),
+            redact: conf
+                .redact
+                .iter()
+                .map(|prefix| prefix.as_bytes().to_vec())
+                .collect(),
+            output,
+        })
+    }
+
+    /// Returns whether the file descriptor `conf` is tapped
+    pub fn taps(&self, conf: &FileConf) -> bool {
+        matches!(conf, FileConf::Listen(..) | FileConf::Connect(..))
+            && (self.files.is_empty() || self.files.iter().any(|name| name == conf.name()))
+    }
+
+    /// Returns the pcapng enhanced packet block of `data` of the file descriptor `name`
+    fn packet(&self, name: &str, direction: Direction, data: &[u8]) -> Vec<u8> {
+        let mut pdu = vec![];
+        let proto = pad4(self.protocol.as_bytes());
+        pdu.extend(EXP_PDU_TAG_PROTO_NAME.to_be_bytes());
+        pdu.extend((proto.len() as u16).to_be_bytes());
+        pdu.extend(proto);
+        pdu.extend([0; 4]);
+        pdu.extend(data);
+
+        let micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_micros() as u64);
+        let mut epb = vec![];
+        epb.extend(0_u32.to_le_bytes());
+        epb.extend(((micros >> 32) as u32).to_le_bytes());
+        epb.extend((micros as u32).to_le_bytes());
+        epb.extend((pdu.len() as u32).to_le_bytes());
+        epb.extend((pdu.len() as u32).to_le_bytes());
+        epb.extend(pad4(&pdu));
+        option(&mut epb, 1, name.as_bytes());
+        option(&mut epb, 2, &direction.flags().to_le_bytes());
+        option(&mut epb, 0, &[]);
+        block(6, &epb)
+    }
+
+    /// Mirrors `data` read or written by the workload on the file descriptor `name`
+    fn record(&self, name: &str, direction: Direction, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let mut data = data.to_vec();
+        redact(&mut data, &self.redact);
+        match &self.output {
+            Output::Log => {
+                let direction = match direction {
+                    Direction::In => "in",
+                    Direction::Out => "out",
+                };
+                info!(
+                    fd = name,
+                    direction,
+                    len = data.len(),
+                    "tap: {}",
+                    data.escape_ascii()
+                );
+            }
+            Output::Pcapng(file) => {
+                let packet = self.packet(name, direction, &data);
+                if let Err(e) = file.lock().unwrap().write_all(&packet) {
+                    warn!("failed to write to the tap: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether the Keep allows debugging, so the tap cannot leak confidential data
+///
+/// Without a shim, i.e. with the `nil` backend, there is no TEE and the host can read all data
+/// anyway. Otherwise the attestation claims must allow debugging.
+pub(super) fn debuggable() -> bool {
+    match Claims::get() {
+        Ok(claims) => claims.debug,
+        Err(e) => e.chain().any(|e| {
+            e.downcast_ref::<io::Error>()
+                .and_then(io::Error::raw_os_error)
+                == Some(libc::ENOSYS)
+        }),
+    }
+}
+
+/// A file, whose data is mirrored to the [`Tap`]
+pub struct Tapped {
+    file: Box<dyn WasiFile>,
+    tap: Arc<Tap>,
+    name: String,
+}
+
+impl Tapped {
+    /// Wraps `file` of the file descriptor `name` to mirror its data to `tap`
+    pub fn new(file: Box<dyn WasiFile>, tap: Arc<Tap>, name: impl Into<String>) -> Self {
+        Self {
+            file,
+            tap,
+            name: name.into(),
+        }
+    }
+
+    /// Mirrors the first `n` bytes of `bufs` read by the workload
+    fn read(&self, bufs: &[IoSliceMut<'_>], n: u64) {
+        let mut data = Vec::with_capacity(n as _);
+        for buf in bufs {
+            let rest = n as usize - data.len();
+            data.extend(&buf[..buf.len().min(rest)]);
+        }
+        self.tap.record(&self.name, Direction::In, &data);
+    }
+
+    /// Mirrors the first `n` bytes of `bufs` written by the workload
+    fn write(&self, bufs: &[IoSlice<'_>], n: u64) {
+        let mut data = Vec::with_capacity(n as _);
+        for buf in bufs {
+            let rest = n as usize - data.len();
+            data.extend(&buf[..buf.len().min(rest)]);
+        }
+        self.tap.record(&self.name, Direction::Out, &data);
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Tapped {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd<'_>> {
+        self.file.pollable()
+    }
+
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.file.pollable()
+    }
+
+    fn isatty(&mut self) -> bool {
+        self.file.isatty()
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        self.file.get_filetype().await
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        self.file.get_fdflags().await
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        self.file.set_fdflags(fdflags).await
+    }
+
+    async fn get_filestat(&mut self) -> Result<Filestat, Error> {
+        self.file.get_filestat().await
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        let n = self.file.read_vectored(bufs).await?;
+        self.read(bufs, n);
+        Ok(n)
+    }
+
+    async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        let n = self.file.write_vectored(bufs).await?;
+        self.write(bufs, n);
+        Ok(n)
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.file.peek(buf).await
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.file.num_ready_bytes().await
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        self.file.readable().await
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        self.file.writable().await
+    }
+
+    async fn sock_accept(&mut self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        let stream = self.file.sock_accept(fdflags).await?;
+        Ok(Box::new(Self::new(stream, self.tap.clone(), &self.name)))
+    }
+
+    async fn sock_recv<'a>(
+        &mut self,
+        ri_data: &mut [IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        let (n, flags) = self.file.sock_recv(ri_data, ri_flags).await?;
+        if !ri_flags.contains(RiFlags::RECV_PEEK) {
+            self.read(ri_data, n);
+        }
+        Ok((n, flags))
+    }
+
+    async fn sock_send<'a>(
+        &mut self,
+        si_data: &[IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        let n = self.file.sock_send(si_data, si_flags).await?;
+        self.write(si_data, n);
+        Ok(n)
+    }
+
+    async fn sock_shutdown(&mut self, how: SdFlags) -> Result<(), Error> {
+        self.file.sock_shutdown(how).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Seek};
+
+    #[test]
+    fn redaction() {
+        let prefixes = [b"authorization:".to_vec(), b"cookie:".to_vec()];
+        let mut data =
+            b"GET / HTTP/1.1\r\nAuthorization: Bearer abc\r\n  cookie: id=1\r\nAccept: */*\r\n\r\n"
+                .to_vec();
+        redact(&mut data, &prefixes);
+        assert_eq!(
+            data,
+            b"GET / HTTP/1.1\r\nAuthorization:***********\r\n  cookie:*****\r\nAccept: */*\r\n\r\n"
+        );
+
+        let mut data = b"Cookie".to_vec();
+        redact(&mut data, &prefixes);
+        assert_eq!(data, b"Cookie");
+    }
+
+    #[test]
+    fn pcapng() {
+        let conf = TapConf {
+            files: vec!["api".into()],
+            protocol: Some("http".into()),
+            redact: vec!["Authorization:".into()],
+        };
+        let mut file = tempfile::tempfile().unwrap();
+        let tap = Tap::new(&conf, Some(file.try_clone().unwrap())).unwrap();
+        tap.record("api", Direction::Out, b"Authorization: x\r\n");
+
+        let mut pcapng = vec![];
+        file.rewind().unwrap();
+        file.read_to_end(&mut pcapng).unwrap();
+
+        // Section header and interface description blocks
+        assert_eq!(pcapng[..4], 0x0a0d_0d0a_u32.to_le_bytes());
+        assert_eq!(pcapng[8..12], 0x1a2b_3c4d_u32.to_le_bytes());
+        let idb = &pcapng[28..];
+        assert_eq!(idb[..4], 1_u32.to_le_bytes());
+        assert_eq!(idb[8..10], LINKTYPE_WIRESHARK_UPPER_PDU.to_le_bytes());
+
+        // Enhanced packet block with the exported PDU of the redacted data
+        let epb = &idb[20..];
+        assert_eq!(epb[..4], 6_u32.to_le_bytes());
+        let len = u32::from_le_bytes(epb[4..8].try_into().unwrap()) as usize;
+        assert_eq!(epb.len(), len);
+        assert_eq!(epb[len - 4..], epb[4..8]);
+        let captured = u32::from_le_bytes(epb[20..24].try_into().unwrap()) as usize;
+        let pdu = &epb[28..][..captured];
+        assert_eq!(pdu[..4], [0, 12, 0, 4]);
+        assert_eq!(pdu[4..8], *b"http");
+        assert_eq!(pdu[8..12], [0; 4]);
+        assert_eq!(pdu[12..], *b"Authorization:**\r\n");
+    }
+
+    #[test]
+    fn taps() {
+        let tap = Tap::new(&TapConf::default(), None).unwrap();
+        let listen: FileConf =
+            toml::from_str("kind = 'listen'\nname = 'api'\nprot = 'tls'\nport = 443").unwrap();
+        assert!(tap.taps(&listen));
+        assert!(!tap.taps(&FileConf::Stdout(Default::default())));
+
+        let conf = TapConf {
+            files: vec!["other".into()],
+            ..Default::default()
+        };
+        assert!(!Tap::new(&conf, None).unwrap().taps(&listen));
+    }
+}
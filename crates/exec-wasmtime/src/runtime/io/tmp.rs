@@ -0,0 +1,1031 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! An in-memory temporary directory with a size quota
+//!
+//! The files are kept in the memory of the Keep and never reach the host. Symbolic and hard links
+//! are not supported.
+//!
+//! For crash-safe atomic updates, the workload imports `tmpfile_open`, `tmpfile_link` and
+//! `path_rename_noreplace` from the `enarx` module, akin to `O_TMPFILE`, `linkat` and `renameat2`
+//! with `RENAME_NOREPLACE`. An unnamed file is written and materialized under its final name
+//! only once complete, and a file is moved into place without replacing an existing one.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::super::errno::{self, negate};
+use super::super::keys::memory;
+use super::super::Ctx;
+
+use anyhow::Context;
+use wasi_common::dir::{ReaddirCursor, ReaddirEntity};
+use wasi_common::file::{FdFlags, FileCaps, FileType, Filestat, OFlags};
+use wasi_common::snapshots::preview_1::types::{self, Errno};
+use wasi_common::snapshots::preview_1::wasi_snapshot_preview1::WasiSnapshotPreview1;
+use wasi_common::{Error, ErrorExt, SystemTimeSpec, WasiCtx, WasiDir, WasiFile};
+use wasmtime::{Caller, Linker};
+
+/// Inode of the root directory
+const ROOT: u64 = 1;
+
+/// Bytes charged against the quota for every file and directory
+const NODE_SIZE: u64 = 256;
+
+/// Maximum length of a file name in bytes
+const NAME_MAX: usize = 255;
+
+/// Device ID of all files, which identifies the file descriptors of temporary directories
+const DEVICE: u64 = u64::from_be_bytes(*b"enarxtmp");
+
+enum Node {
+    Dir {
+        parent: u64,
+        entries: BTreeMap<String, u64>,
+    },
+    File {
+        data: Vec<u8>,
+        /// The file is still reachable by its name
+        linked: bool,
+        /// The file was created without a name and may be linked once
+        tmpfile: bool,
+        /// Number of open handles
+        open: usize,
+    },
+}
+
+struct Fs {
+    nodes: HashMap<u64, Node>,
+    next: u64,
+    /// Bytes charged against the quota
+    used: u64,
+    size: u64,
+}
+
+impl Fs {
+    fn node(&self, ino: u64) -> Result<&Node, Error> {
+        self.nodes.get(&ino).ok_or_else(Error::not_found)
+    }
+
+    fn entries(&self, ino: u64) -> Result<&BTreeMap<String, u64>, Error> {
+        match self.node(ino)? {
+            Node::Dir { entries, .. } => Ok(entries),
+            Node::File { .. } => Err(Error::not_dir()),
+        }
+    }
+
+    fn entries_mut(&mut self, ino: u64) -> Result<&mut BTreeMap<String, u64>, Error> {
+        match self.nodes.get_mut(&ino).ok_or_else(Error::not_found)? {
+            Node::Dir { entries, .. } => Ok(entries),
+            Node::File { .. } => Err(Error::not_dir()),
+        }
+    }
+
+    fn data_mut(&mut self, ino: u64) -> Result<&mut Vec<u8>, Error> {
+        match self.nodes.get_mut(&ino).ok_or_else(Error::not_found)? {
+            Node::File { data, .. } => Ok(data),
+            Node::Dir { .. } => Err(errno::from_raw(libc::EISDIR).into()),
+        }
+    }
+
+    /// Charges `n` bytes against the quota
+    fn charge(&mut self, n: u64) -> Result<(), Error> {
+        match self.used.checked_add(n) {
+            Some(used) if used <= self.size => {
+                self.used = used;
+                Ok(())
+            }
+            _ => Err(errno::from_raw(libc::ENOSPC).into()),
+        }
+    }
+
+    /// Resolves `path` relative to the directory `base`
+    fn walk(&self, base: u64, path: &str) -> Result<u64, Error> {
+        if path.starts_with('/') {
+            return Err(Error::perm());
+        }
+        let mut ino = base;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            ino = match name {
+                "." => {
+                    self.entries(ino)?;
+                    ino
+                }
+                ".." => match self.node(ino)? {
+                    Node::Dir { .. } if ino == ROOT => return Err(Error::perm()),
+                    Node::Dir { parent, .. } => *parent,
+                    Node::File { .. } => return Err(Error::not_dir()),
+                },
+                name => *self.entries(ino)?.get(name).ok_or_else(Error::not_found)?,
+            };
+        }
+        Ok(ino)
+    }
+
+    /// Resolves the parent directory of `path` relative to the directory `base`
+    ///
+    /// Returns the inode of the parent directory and the last component of `path`.
+    fn split<'a>(&self, base: u64, path: &'a str) -> Result<(u64, &'a str), Error> {
+        if path.starts_with('/') {
+            return Err(Error::perm());
+        }
+        let path = path.trim_end_matches('/');
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        match name {
+            "" => return Err(Error::not_found()),
+            "." | ".." => return Err(Error::exist()),
+            name if name.len() > NAME_MAX => return Err(Error::name_too_long()),
+            _ => {}
+        }
+        let parent = self.walk(base, dir)?;
+        self.entries(parent)?;
+        Ok((parent, name))
+    }
+
+    /// Allocates the inode of the new `node`
+    fn alloc(&mut self, node: Node) -> Result<u64, Error> {
+        self.charge(NODE_SIZE)?;
+        let ino = self.next;
+        self.next += 1;
+        self.nodes.insert(ino, node);
+        Ok(ino)
+    }
+
+    /// Inserts `node` as `name` into the directory `parent`
+    fn insert(&mut self, parent: u64, name: &str, node: Node) -> Result<u64, Error> {
+        if self.entries(parent)?.contains_key(name) {
+            return Err(Error::exist());
+        }
+        let ino = self.alloc(node)?;
+        self.entries_mut(parent)?.insert(name.into(), ino);
+        Ok(ino)
+    }
+
+    /// Creates an unnamed file in the directory `dir`, which is freed, unless it is linked
+    /// before it is closed
+    fn tmpfile(&mut self, dir: u64) -> Result<u64, Error> {
+        self.entries(dir)?;
+        self.alloc(Node::File {
+            data: Vec::new(),
+            linked: false,
+            tmpfile: true,
+            open: 0,
+        })
+    }
+
+    /// Links the unnamed file `ino` as `path` relative to the directory `base`
+    fn link(&mut self, ino: u64, base: u64, path: &str) -> Result<(), Error> {
+        match self.node(ino)? {
+            Node::File {
+                tmpfile: true,
+                linked: false,
+                ..
+            } => {}
+            Node::File { linked: false, .. } => return Err(Error::not_found()),
+            // Hard links are not supported
+            Node::File { .. } => return Err(Error::not_supported()),
+            Node::Dir { .. } => return Err(Error::perm()),
+        }
+        let (parent, name) = self.split(base, path)?;
+        if self.entries(parent)?.contains_key(name) {
+            return Err(Error::exist());
+        }
+        self.entries_mut(parent)?.insert(name.into(), ino);
+        if let Some(Node::File {
+            linked, tmpfile, ..
+        }) = self.nodes.get_mut(&ino)
+        {
+            *linked = true;
+            *tmpfile = false;
+        }
+        Ok(())
+    }
+
+    /// Renames `path` relative to the directory `base` to `dest_path` relative to the directory
+    /// `dest_base`
+    ///
+    /// With `noreplace`, fails if `dest_path` exists, otherwise replaces it atomically.
+    fn rename(
+        &mut self,
+        base: u64,
+        path: &str,
+        dest_base: u64,
+        dest_path: &str,
+        noreplace: bool,
+    ) -> Result<(), Error> {
+        let (src_parent, src_name) = self.split(base, path)?;
+        let ino = *self
+            .entries(src_parent)?
+            .get(src_name)
+            .ok_or_else(Error::not_found)?;
+        let (dest_parent, dest_name) = self.split(dest_base, dest_path)?;
+        let is_dir = matches!(self.node(ino)?, Node::Dir { .. });
+
+        if is_dir {
+            // A directory must not be moved into itself
+            let mut ancestor = dest_parent;
+            while ancestor != ROOT {
+                if ancestor == ino {
+                    return Err(Error::invalid_argument());
+                }
+                ancestor = match self.node(ancestor)? {
+                    Node::Dir { parent, .. } => *parent,
+                    Node::File { .. } => return Err(Error::not_dir()),
+                };
+            }
+        }
+
+        if let Some(&dest) = self.entries(dest_parent)?.get(dest_name) {
+            if noreplace {
+                return Err(Error::exist());
+            }
+            if dest == ino {
+                return Ok(());
+            }
+            match (is_dir, self.node(dest)?) {
+                (true, Node::Dir { entries, .. }) if !entries.is_empty() => {
+                    return Err(errno::from_raw(libc::ENOTEMPTY).into())
+                }
+                (true, Node::File { .. }) => return Err(Error::not_dir()),
+                (false, Node::Dir { .. }) => return Err(errno::from_raw(libc::EISDIR).into()),
+                _ => {}
+            }
+            self.unlink(dest_parent, dest_name)?;
+        }
+
+        self.entries_mut(src_parent)?.remove(src_name);
+        self.entries_mut(dest_parent)?.insert(dest_name.into(), ino);
+        if let Some(Node::Dir { parent, .. }) = self.nodes.get_mut(&ino) {
+            *parent = dest_parent;
+        }
+        Ok(())
+    }
+
+    /// Frees the file `ino`, if it is neither linked nor open anymore
+    fn release(&mut self, ino: u64) {
+        if let Some(Node::File {
+            linked: false,
+            open: 0,
+            data,
+            ..
+        }) = self.nodes.get(&ino)
+        {
+            self.used -= data.len() as u64 + NODE_SIZE;
+            self.nodes.remove(&ino);
+        }
+    }
+
+    /// Removes the entry `name` of the directory `parent`
+    fn unlink(&mut self, parent: u64, name: &str) -> Result<(), Error> {
+        let ino = self.entries_mut(parent)?.remove(name).unwrap();
+        match self.nodes.get_mut(&ino) {
+            Some(Node::File { linked, .. }) => {
+                *linked = false;
+                self.release(ino);
+            }
+            Some(Node::Dir { .. }) => {
+                self.used -= NODE_SIZE;
+                self.nodes.remove(&ino);
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Sets the size of the file `ino` to `len` bytes
+    fn resize(&mut self, ino: u64, len: u64) -> Result<(), Error> {
+        let old = self.data_mut(ino)?.len() as u64;
+        if len > old {
+            self.charge(len - old)?;
+        } else {
+            self.used -= old - len;
+        }
+        let len: usize = len.try_into().map_err(|_| Error::overflow())?;
+        let data = self.data_mut(ino)?;
+        data.reserve_exact(len.saturating_sub(data.len()));
+        data.resize(len, 0);
+        if len < data.capacity() / 2 {
+            data.shrink_to_fit();
+        }
+        Ok(())
+    }
+
+    /// Reads from the file `ino` at `offset` into `bufs`
+    fn read(&self, ino: u64, offset: u64, bufs: &mut [IoSliceMut<'_>]) -> Result<u64, Error> {
+        let data = match self.node(ino)? {
+            Node::File { data, .. } => data,
+            Node::Dir { .. } => return Err(errno::from_raw(libc::EISDIR).into()),
+        };
+        let mut offset = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(data.len());
+        let mut n = 0;
+        for buf in bufs {
+            let len = buf.len().min(data.len() - offset);
+            buf[..len].copy_from_slice(&data[offset..][..len]);
+            offset += len;
+            n += len;
+        }
+        Ok(n as _)
+    }
+
+    /// Writes `bufs` to the file `ino` at `offset`
+    ///
+    /// Writes as many bytes as the quota permits.
+    fn write(&mut self, ino: u64, offset: u64, bufs: &[IoSlice<'_>]) -> Result<u64, Error> {
+        let total = bufs.iter().map(|buf| buf.len() as u64).sum::<u64>();
+        if total == 0 {
+            return Ok(0);
+        }
+        let old = self.data_mut(ino)?.len() as u64;
+        let end = offset
+            .checked_add(total)
+            .ok_or_else(Error::overflow)?
+            .min(old + (self.size - self.used));
+        if end <= offset {
+            return Err(errno::from_raw(libc::ENOSPC).into());
+        }
+        if end > old {
+            self.resize(ino, end)?;
+        }
+
+        let data = self.data_mut(ino)?;
+        let (offset, end) = (offset as usize, end as usize);
+        let mut pos = offset;
+        for buf in bufs {
+            let len = buf.len().min(end - pos);
+            data[pos..][..len].copy_from_slice(&buf[..len]);
+            pos += len;
+        }
+        Ok((pos - offset) as _)
+    }
+
+    fn filestat(&self, ino: u64) -> Result<Filestat, Error> {
+        let (filetype, nlink, size) = match self.node(ino)? {
+            Node::Dir { .. } => (FileType::Directory, 1, 0),
+            Node::File { data, linked, .. } => {
+                (FileType::RegularFile, *linked as u64, data.len() as u64)
+            }
+        };
+        Ok(Filestat {
+            device_id: DEVICE,
+            inode: ino,
+            filetype,
+            nlink,
+            size,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+}
+
+/// An in-memory temporary directory
+///
+/// Clones share the same files.
+#[derive(Clone)]
+pub struct Tmp(Arc<Mutex<Fs>>);
+
+impl Tmp {
+    /// Creates an empty directory, whose files may use up to `size` bytes of memory
+    pub fn new(size: u64) -> Self {
+        let root = Node::Dir {
+            parent: ROOT,
+            entries: BTreeMap::new(),
+        };
+        Self(Arc::new(Mutex::new(Fs {
+            nodes: HashMap::from([(ROOT, root)]),
+            next: ROOT + 1,
+            used: 0,
+            size,
+        })))
+    }
+
+    /// Returns the root directory to be preopened
+    pub fn root(&self) -> Box<dyn WasiDir> {
+        Box::new(Dir {
+            fs: self.0.clone(),
+            ino: ROOT,
+        })
+    }
+
+    fn fs(&self) -> MutexGuard<'_, Fs> {
+        self.0.lock().unwrap()
+    }
+
+    /// Opens a new unnamed file for reading and writing in the directory `dir`
+    fn tmpfile(&self, dir: u64) -> Result<Box<dyn WasiFile>, Error> {
+        let mut fs = self.fs();
+        let ino = fs.tmpfile(dir)?;
+        if let Some(Node::File { open, .. }) = fs.nodes.get_mut(&ino) {
+            *open += 1;
+        }
+        Ok(Box::new(File {
+            fs: self.0.clone(),
+            ino,
+            pos: 0,
+            append: false,
+            read: true,
+            write: true,
+        }))
+    }
+}
+
+struct Dir {
+    fs: Arc<Mutex<Fs>>,
+    ino: u64,
+}
+
+impl Dir {
+    fn fs(&self) -> MutexGuard<'_, Fs> {
+        self.fs.lock().unwrap()
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiDir for Dir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn open_file(
+        &self,
+        _symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<Box<dyn WasiFile>, Error> {
+        let mut fs = self.fs();
+        let ino = match fs.walk(self.ino, path) {
+            Ok(..) if oflags.contains(OFlags::CREATE | OFlags::EXCLUSIVE) => {
+                return Err(Error::exist())
+            }
+            Ok(ino) => {
+                if let Node::Dir { .. } = fs.node(ino)? {
+                    return Err(errno::from_raw(libc::EISDIR).into());
+                }
+                if oflags.contains(OFlags::TRUNCATE) {
+                    fs.resize(ino, 0)?;
+                }
+                ino
+            }
+            Err(..) if oflags.contains(OFlags::CREATE) => {
+                let (parent, name) = fs.split(self.ino, path)?;
+                let file = Node::File {
+                    data: Vec::new(),
+                    linked: true,
+                    tmpfile: false,
+                    open: 0,
+                };
+                fs.insert(parent, name, file)?
+            }
+            Err(e) => return Err(e),
+        };
+        if let Some(Node::File { open, .. }) = fs.nodes.get_mut(&ino) {
+            *open += 1;
+        }
+        Ok(Box::new(File {
+            fs: self.fs.clone(),
+            ino,
+            pos: 0,
+            append: fdflags.contains(FdFlags::APPEND),
+            read,
+            write,
+        }))
+    }
+
+    async fn open_dir(&self, _symlink_follow: bool, path: &str) -> Result<Box<dyn WasiDir>, Error> {
+        let fs = self.fs();
+        let ino = fs.walk(self.ino, path)?;
+        fs.entries(ino)?;
+        Ok(Box::new(Dir {
+            fs: self.fs.clone(),
+            ino,
+        }))
+    }
+
+    async fn create_dir(&self, path: &str) -> Result<(), Error> {
+        let mut fs = self.fs();
+        let (parent, name) = fs.split(self.ino, path)?;
+        let dir = Node::Dir {
+            parent,
+            entries: BTreeMap::new(),
+        };
+        fs.insert(parent, name, dir)?;
+        Ok(())
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        let fs = self.fs();
+        let parent = match fs.node(self.ino)? {
+            Node::Dir { parent, .. } => *parent,
+            Node::File { .. } => return Err(Error::not_dir()),
+        };
+        let mut entries = vec![
+            (".".to_string(), self.ino, FileType::Directory),
+            ("..".to_string(), parent, FileType::Directory),
+        ];
+        for (name, &ino) in fs.entries(self.ino)? {
+            entries.push((name.clone(), ino, fs.filestat(ino)?.filetype));
+        }
+        let entries = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, inode, filetype))| {
+                Ok(ReaddirEntity {
+                    next: ReaddirCursor::from(i as u64 + 1),
+                    inode,
+                    name,
+                    filetype,
+                })
+            })
+            .skip(u64::from(cursor) as usize);
+        Ok(Box::new(entries))
+    }
+
+    async fn symlink(&self, _old_path: &str, _new_path: &str) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<(), Error> {
+        let mut fs = self.fs();
+        let (parent, name) = fs.split(self.ino, path)?;
+        let ino = *fs.entries(parent)?.get(name).ok_or_else(Error::not_found)?;
+        if !fs.entries(ino)?.is_empty() {
+            return Err(errno::from_raw(libc::ENOTEMPTY).into());
+        }
+        fs.unlink(parent, name)
+    }
+
+    async fn unlink_file(&self, path: &str) -> Result<(), Error> {
+        let mut fs = self.fs();
+        let (parent, name) = fs.split(self.ino, path)?;
+        let ino = *fs.entries(parent)?.get(name).ok_or_else(Error::not_found)?;
+        if let Node::Dir { .. } = fs.node(ino)? {
+            return Err(errno::from_raw(libc::EISDIR).into());
+        }
+        fs.unlink(parent, name)
+    }
+
+    async fn read_link(&self, path: &str) -> Result<PathBuf, Error> {
+        self.fs().walk(self.ino, path)?;
+        Err(Error::invalid_argument())
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.fs().filestat(self.ino)
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        _follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        let fs = self.fs();
+        fs.filestat(fs.walk(self.ino, path)?)
+    }
+
+    async fn rename(
+        &self,
+        path: &str,
+        dest_dir: &dyn WasiDir,
+        dest_path: &str,
+    ) -> Result<(), Error> {
+        let dest_dir = match dest_dir.as_any().downcast_ref::<Self>() {
+            Some(dir) if Arc::ptr_eq(&dir.fs, &self.fs) => dir,
+            _ => return Err(Error::not_supported()),
+        };
+
+        self.fs()
+            .rename(self.ino, path, dest_dir.ino, dest_path, false)
+    }
+
+    async fn hard_link(
+        &self,
+        _path: &str,
+        _target_dir: &dyn WasiDir,
+        _target_path: &str,
+    ) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+
+    async fn set_times(
+        &self,
+        path: &str,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+        _follow_symlinks: bool,
+    ) -> Result<(), Error> {
+        self.fs().walk(self.ino, path)?;
+        Ok(())
+    }
+}
+
+struct File {
+    fs: Arc<Mutex<Fs>>,
+    ino: u64,
+    pos: u64,
+    append: bool,
+    read: bool,
+    write: bool,
+}
+
+impl File {
+    fn fs(&self) -> MutexGuard<'_, Fs> {
+        self.fs.lock().unwrap()
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let mut fs = self.fs();
+        if let Some(Node::File { open, .. }) = fs.nodes.get_mut(&self.ino) {
+            *open -= 1;
+        }
+        fs.release(self.ino);
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for File {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        Ok(if self.append {
+            FdFlags::APPEND
+        } else {
+            FdFlags::empty()
+        })
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        self.append = fdflags.contains(FdFlags::APPEND);
+        Ok(())
+    }
+
+    async fn get_filestat(&mut self) -> Result<Filestat, Error> {
+        self.fs().filestat(self.ino)
+    }
+
+    async fn set_filestat_size(&mut self, size: u64) -> Result<(), Error> {
+        if !self.write {
+            return Err(Error::badf());
+        }
+        self.fs().resize(self.ino, size)
+    }
+
+    async fn allocate(&mut self, offset: u64, len: u64) -> Result<(), Error> {
+        if !self.write {
+            return Err(Error::badf());
+        }
+        let end = offset.checked_add(len).ok_or_else(Error::overflow)?;
+        let mut fs = self.fs();
+        if end > fs.filestat(self.ino)?.size {
+            fs.resize(self.ino, end)?;
+        }
+        Ok(())
+    }
+
+    async fn set_times(
+        &mut self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        let n = self.read_vectored_at(bufs, self.pos).await?;
+        self.pos += n;
+        Ok(n)
+    }
+
+    async fn read_vectored_at<'a>(
+        &mut self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        if !self.read {
+            return Err(Error::badf());
+        }
+        self.fs().read(self.ino, offset, bufs)
+    }
+
+    async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        if self.append {
+            let size = self.fs().filestat(self.ino)?.size;
+            self.pos = size;
+        }
+        let n = self.write_vectored_at(bufs, self.pos).await?;
+        self.pos += n;
+        Ok(n)
+    }
+
+    async fn write_vectored_at<'a>(
+        &mut self,
+        bufs: &[IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        if !self.write {
+            return Err(Error::badf());
+        }
+        self.fs().write(self.ino, offset, bufs)
+    }
+
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.fs().filestat(self.ino)?.size.checked_add_signed(delta),
+        };
+        self.pos = pos.ok_or_else(Error::invalid_argument)?;
+        Ok(self.pos)
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.read_vectored_at(&mut [IoSliceMut::new(buf)], self.pos)
+            .await
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        let size = self.fs().filestat(self.ino)?.size;
+        Ok(size.saturating_sub(self.pos))
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Returns the inode of the file or directory of a temporary directory open at `fd`
+///
+/// Fails with `ERRNO_XDEV` for any other file descriptor.
+fn inode(wasi: &mut WasiCtx, fd: u32) -> Result<u64, Errno> {
+    let stat = wiggle::run_in_dummy_executor(wasi.fd_filestat_get(types::Fd::from(fd)))
+        .map_err(|_| Errno::Io)?
+        .map_err(errno::from_error)?;
+    if stat.dev != DEVICE {
+        return Err(Errno::Xdev);
+    }
+    Ok(stat.ino)
+}
+
+/// Returns the temporary directory of the workload
+fn tmp(ctx: &Ctx) -> Result<Tmp, Errno> {
+    ctx.tmp.clone().ok_or(Errno::Xdev)
+}
+
+/// Reads the UTF-8 path at `path` of `len` bytes from the memory of the workload
+fn read_path(caller: &mut Caller<'_, Ctx>, path: u32, len: u32) -> Result<String, Errno> {
+    let memory = memory(caller)?;
+    let path = memory
+        .data(&caller)
+        .get(path as usize..)
+        .and_then(|data| data.get(..len as usize))
+        .ok_or(Errno::Fault)?;
+    String::from_utf8(path.to_vec()).map_err(|_| Errno::Ilseq)
+}
+
+/// Opens a new unnamed file for reading and writing in the temporary directory open at `dirfd`
+/// and returns its WASI file descriptor or the negated WASI errno
+///
+/// The file is freed on close, unless it was linked with `tmpfile_link`. Fails with
+/// `ERRNO_XDEV`, if `dirfd` is not a temporary directory.
+fn tmpfile_open(mut caller: Caller<'_, Ctx>, dirfd: u32) -> i32 {
+    negate((|| {
+        let ctx = caller.data_mut();
+        let dir = inode(&mut ctx.wasi, dirfd)?;
+        let file = tmp(ctx)?.tmpfile(dir).map_err(errno::from_error)?;
+        let fd = (3..)
+            .find(|fd| !ctx.wasi.table().contains_key(*fd))
+            .ok_or(Errno::Nfile)?;
+        ctx.wasi.insert_file(fd, file, FileCaps::all());
+        i32::try_from(fd).map_err(|_| Errno::Nfile)
+    })())
+}
+
+/// Links the unnamed file open at `fd` as the path at `path` of `path_len` bytes relative to the
+/// directory open at `dirfd` and returns `0` or the negated WASI errno
+///
+/// Fails with `ERRNO_EXIST`, if the path exists, with `ERRNO_NOENT`, if the file was unlinked, and
+/// with `ERRNO_NOTSUP`, if the file is already linked, as hard links are not supported.
+fn tmpfile_link(mut caller: Caller<'_, Ctx>, fd: u32, dirfd: u32, path: u32, path_len: u32) -> i32 {
+    negate((|| {
+        let path = read_path(&mut caller, path, path_len)?;
+        let ctx = caller.data_mut();
+        let ino = inode(&mut ctx.wasi, fd)?;
+        let dir = inode(&mut ctx.wasi, dirfd)?;
+        tmp(ctx)?
+            .fs()
+            .link(ino, dir, &path)
+            .map_err(errno::from_error)?;
+        Ok(0)
+    })())
+}
+
+/// Renames the path at `path` of `path_len` bytes relative to the directory open at `fd` to the
+/// path at `new_path` of `new_path_len` bytes relative to the directory open at `new_fd` and
+/// returns `0` or the negated WASI errno
+///
+/// Fails with `ERRNO_EXIST` instead of replacing an existing path.
+fn path_rename_noreplace(
+    mut caller: Caller<'_, Ctx>,
+    fd: u32,
+    path: u32,
+    path_len: u32,
+    new_fd: u32,
+    new_path: u32,
+    new_path_len: u32,
+) -> i32 {
+    negate((|| {
+        let path = read_path(&mut caller, path, path_len)?;
+        let new_path = read_path(&mut caller, new_path, new_path_len)?;
+        let ctx = caller.data_mut();
+        let dir = inode(&mut ctx.wasi, fd)?;
+        let new_dir = inode(&mut ctx.wasi, new_fd)?;
+        tmp(ctx)?
+            .fs()
+            .rename(dir, &path, new_dir, &new_path, true)
+            .map_err(errno::from_error)?;
+        Ok(0)
+    })())
+}
+
+/// Adds the `enarx` `tmpfile_open`, `tmpfile_link` and `path_rename_noreplace` functions to
+/// `linker`
+pub(in crate::runtime) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "tmpfile_open", tmpfile_open)
+        .context("failed to add `enarx::tmpfile_open`")?;
+    linker
+        .func_wrap("enarx", "tmpfile_link", tmpfile_link)
+        .context("failed to add `enarx::tmpfile_link`")?;
+    linker
+        .func_wrap("enarx", "path_rename_noreplace", path_rename_noreplace)
+        .context("failed to add `enarx::path_rename_noreplace`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future::Future;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        wiggle::run_in_dummy_executor(future).unwrap()
+    }
+
+    fn open(dir: &dyn WasiDir, path: &str, oflags: OFlags) -> Result<Box<dyn WasiFile>, Error> {
+        block_on(dir.open_file(false, path, oflags, true, true, FdFlags::empty()))
+    }
+
+    fn read(file: &mut dyn WasiFile) -> Vec<u8> {
+        let mut buf = [0; 64];
+        let n = block_on(file.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)).unwrap();
+        buf[..n as usize].to_vec()
+    }
+
+    fn names(dir: &dyn WasiDir) -> Vec<String> {
+        block_on(dir.readdir(ReaddirCursor::from(0)))
+            .unwrap()
+            .map(|entry| entry.unwrap().name)
+            .collect()
+    }
+
+    #[test]
+    fn files() {
+        let tmp = Tmp::new(1 << 20);
+        let root = tmp.root();
+        assert!(open(root.as_ref(), "a", OFlags::empty()).is_err());
+
+        let mut file = open(root.as_ref(), "a", OFlags::CREATE).unwrap();
+        let n = block_on(file.write_vectored(&[IoSlice::new(b"hello"), IoSlice::new(b" world")]));
+        assert_eq!(n.unwrap(), 11);
+        assert_eq!(read(file.as_mut()), b"hello world");
+        assert!(open(root.as_ref(), "a", OFlags::CREATE | OFlags::EXCLUSIVE).is_err());
+
+        let mut again = open(root.as_ref(), "./a", OFlags::TRUNCATE).unwrap();
+        assert_eq!(read(again.as_mut()), b"");
+        assert_eq!(block_on(file.seek(SeekFrom::End(0))).unwrap(), 0);
+
+        // unlinked files stay readable until closed
+        block_on(file.write_vectored(&[IoSlice::new(b"bye")])).unwrap();
+        block_on(root.unlink_file("a")).unwrap();
+        assert!(open(root.as_ref(), "a", OFlags::empty()).is_err());
+        assert_eq!(read(file.as_mut()), b"bye");
+        drop((file, again));
+        assert_eq!(tmp.0.lock().unwrap().used, 0);
+    }
+
+    #[test]
+    fn dirs() {
+        let root = Tmp::new(1 << 20).root();
+        block_on(root.create_dir("d")).unwrap();
+        block_on(root.create_dir("d/e/")).unwrap();
+        assert!(block_on(root.create_dir("d")).is_err());
+        assert!(block_on(root.create_dir("x/y")).is_err());
+        open(root.as_ref(), "d/e/f", OFlags::CREATE).unwrap();
+
+        let d = block_on(root.open_dir(false, "d")).unwrap();
+        assert_eq!(names(d.as_ref()), [".", "..", "e"]);
+        assert!(block_on(d.open_dir(false, "../..")).is_err());
+        assert!(block_on(root.open_dir(false, "/")).is_err());
+        assert!(block_on(root.open_dir(false, "d/e/f")).is_err());
+        assert!(open(root.as_ref(), "d", OFlags::empty()).is_err());
+
+        assert!(block_on(root.remove_dir("d/e")).is_err());
+        assert!(block_on(root.unlink_file("d/e")).is_err());
+        block_on(root.rename("d/e/f", d.as_ref(), "g")).unwrap();
+        assert!(block_on(root.rename("d", d.as_ref(), "e/d")).is_err());
+        block_on(root.remove_dir("d/e")).unwrap();
+        assert_eq!(names(d.as_ref()), [".", "..", "g"]);
+
+        let stat = block_on(root.get_path_filestat("d/g", false)).unwrap();
+        assert_eq!(stat.filetype, FileType::RegularFile);
+        assert_eq!(stat.size, 0);
+    }
+
+    #[test]
+    fn quota() {
+        let tmp = Tmp::new(NODE_SIZE + 8);
+        let root = tmp.root();
+        let mut file = open(root.as_ref(), "a", OFlags::CREATE).unwrap();
+        assert!(block_on(root.create_dir("d")).is_err());
+
+        // partial write up to the quota
+        let n = block_on(file.write_vectored(&[IoSlice::new(b"0123456789")]));
+        assert_eq!(n.unwrap(), 8);
+        assert!(block_on(file.write_vectored(&[IoSlice::new(b"!")])).is_err());
+        assert!(block_on(file.set_filestat_size(9)).is_err());
+        assert!(block_on(file.write_vectored_at(&[IoSlice::new(b"!")], 20)).is_err());
+
+        // overwriting does not need more space
+        let n = block_on(file.write_vectored_at(&[IoSlice::new(b"ab")], 0));
+        assert_eq!(n.unwrap(), 2);
+        assert_eq!(read(file.as_mut()), b"ab234567");
+
+        block_on(file.set_filestat_size(4)).unwrap();
+        block_on(file.write_vectored_at(&[IoSlice::new(b"wxyz")], 4)).unwrap();
+        assert_eq!(read(file.as_mut()), b"ab23wxyz");
+    }
+
+    #[test]
+    fn tmpfile() {
+        let tmp = Tmp::new(1 << 20);
+        let root = tmp.root();
+        block_on(root.create_dir("d")).unwrap();
+        let d = block_on(root.get_path_filestat("d", false)).unwrap().inode;
+
+        // unnamed files are freed on close
+        let file = tmp.tmpfile(d).unwrap();
+        assert_eq!(names(root.as_ref()), [".", "..", "d"]);
+        drop(file);
+        assert_eq!(tmp.0.lock().unwrap().used, NODE_SIZE);
+
+        let mut file = tmp.tmpfile(d).unwrap();
+        block_on(file.write_vectored(&[IoSlice::new(b"new")])).unwrap();
+        let ino = block_on(file.get_filestat()).unwrap().inode;
+        assert_eq!(block_on(file.get_filestat()).unwrap().nlink, 0);
+        open(root.as_ref(), "d/config", OFlags::CREATE).unwrap();
+        assert!(tmp.fs().link(ino, ROOT, "d/config").is_err());
+        tmp.fs().link(ino, ROOT, "d/config.new").unwrap();
+        assert!(tmp.fs().link(ino, ROOT, "d/again").is_err());
+        drop(file);
+
+        // the complete file replaces the old one only without `noreplace`
+        assert!(tmp
+            .fs()
+            .rename(ROOT, "d/config.new", d, "config", true)
+            .is_err());
+        tmp.fs()
+            .rename(ROOT, "d/config.new", d, "config", false)
+            .unwrap();
+        let mut config = open(root.as_ref(), "d/config", OFlags::empty()).unwrap();
+        assert_eq!(read(config.as_mut()), b"new");
+        assert_eq!(names(root.as_ref()), [".", "..", "d"]);
+        assert!(tmp.fs().rename(ROOT, "d/config", ROOT, "d2", true).is_ok());
+
+        // named files cannot be linked
+        let ino = block_on(config.get_filestat()).unwrap().inode;
+        assert!(tmp.fs().link(ino, ROOT, "other").is_err());
+        assert!(tmp.tmpfile(ino).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Terminal control of the standard I/O file descriptors
+//!
+//! The standard I/O file descriptors of the Keep are connected to the controlling terminal
+//! of `enarx run`, if any. WASI has no notion of terminals, so the workload imports
+//! `tty_winsize` and `tty_raw` from the `enarx` module to query the window size and to
+//! toggle the raw mode of the terminal. Window size changes are observed by querying
+//! the window size again, as signals are not delivered to the workload.
+
+use super::super::errno::{from_io, negate};
+use super::super::Ctx;
+
+use std::collections::HashMap;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::io::RawFd;
+
+use anyhow::Context;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+
+/// The WASI file descriptors connected to the standard I/O of the host
+#[derive(Default)]
+pub struct Tty {
+    fds: HashMap<u32, RawFd>,
+    saved: HashMap<RawFd, libc::termios>,
+}
+
+impl Tty {
+    /// Marks the WASI file descriptor `fd` as connected to the host file descriptor `raw`
+    pub fn insert(&mut self, fd: u32, raw: RawFd) {
+        self.fds.insert(fd, raw);
+    }
+
+    /// Returns the host file descriptor of the WASI file descriptor `fd`
+    fn resolve(&self, fd: u32) -> Result<RawFd, Errno> {
+        self.fds.get(&fd).copied().ok_or(Errno::Notty)
+    }
+
+    /// Returns the window size of the terminal `raw` as `rows << 16 | columns`
+    fn winsize(raw: RawFd) -> Result<i64, Errno> {
+        let mut ws = MaybeUninit::<libc::winsize>::uninit();
+        if unsafe { libc::ioctl(raw, libc::TIOCGWINSZ, ws.as_mut_ptr()) } != 0 {
+            return Err(from_io(&io::Error::last_os_error()));
+        }
+        let ws = unsafe { ws.assume_init() };
+        Ok(i64::from(ws.ws_row) << 16 | i64::from(ws.ws_col))
+    }
+
+    /// Enables or disables the raw mode of the terminal `raw`
+    ///
+    /// The settings of the terminal are saved, when the raw mode is enabled,
+    /// and restored, when it is disabled.
+    fn set_raw(&mut self, raw: RawFd, enable: bool) -> Result<(), Errno> {
+        let termios = if enable {
+            let saved = match self.saved.get(&raw) {
+                Some(saved) => *saved,
+                None => {
+                    let mut termios = MaybeUninit::<libc::termios>::uninit();
+                    if unsafe { libc::tcgetattr(raw, termios.as_mut_ptr()) } != 0 {
+                        return Err(from_io(&io::Error::last_os_error()));
+                    }
+                    let termios = unsafe { termios.assume_init() };
+                    self.saved.insert(raw, termios);
+                    termios
+                }
+            };
+            let mut termios = saved;
+            unsafe { libc::cfmakeraw(&mut termios) };
+            termios
+        } else {
+            match self.saved.remove(&raw) {
+                Some(saved) => saved,
+                None => return Ok(()),
+            }
+        };
+
+        if unsafe { libc::tcsetattr(raw, libc::TCSANOW, &termios) } != 0 {
+            return Err(from_io(&io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Tty {
+    fn drop(&mut self) {
+        // Leave the terminal as it was found
+        for (raw, termios) in self.saved.drain() {
+            unsafe { libc::tcsetattr(raw, libc::TCSANOW, &termios) };
+        }
+    }
+}
+
+/// Returns the window size of the terminal `fd` as `rows << 16 | columns`
+/// or the negated WASI errno
+fn tty_winsize(mut caller: Caller<'_, Ctx>, fd: u32) -> i64 {
+    let Ctx { wasi, tty, .. } = caller.data_mut();
+    if !wasi.table().contains_key(fd) {
+        return negate(Err(Errno::Badf));
+    }
+    negate(tty.resolve(fd).and_then(Tty::winsize))
+}
+
+/// Enables the raw mode of the terminal `fd`, if `enable` is not `0`,
+/// and disables it otherwise
+///
+/// Returns `0` or the negated WASI errno.
+fn tty_raw(mut caller: Caller<'_, Ctx>, fd: u32, enable: u32) -> i64 {
+    let Ctx { wasi, tty, .. } = caller.data_mut();
+    if !wasi.table().contains_key(fd) {
+        return negate(Err(Errno::Badf));
+    }
+    negate(
+        tty.resolve(fd)
+            .and_then(|raw| tty.set_raw(raw, enable != 0))
+            .map(|()| 0),
+    )
+}
+
+/// Adds the `enarx` `tty_winsize` and `tty_raw` functions to `linker`
+pub(in crate::runtime) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "tty_winsize", tty_winsize)
+        .context("failed to add `enarx::tty_winsize`")?;
+    linker
+        .func_wrap("enarx", "tty_raw", tty_raw)
+        .context("failed to add `enarx::tty_raw`")?;
+    Ok(())
+}
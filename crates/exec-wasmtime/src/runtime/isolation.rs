@@ -0,0 +1,314 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Instance-per-connection isolation of network services
+//!
+//! Every connection accepted on the listen file named in the `[isolation]` section is handled by
+//! a fresh instance of the module, which is pre-instantiated once at startup. Each instance has
+//! its own store and thereby freshly zeroed memory, which is released after the connection.
+//!
+//! To verify, that the isolation engages, [`Stats`] count the instances, their instantiation
+//! latency and the memory released with them. After every connection, a random canary is written
+//! to the end of the initial memory of the instance. Finding it in the memory of the next
+//! instance means, that state leaked between connections. A sidecar permitted to read `stats`
+//! reads the statistics from fd 5.
+
+use super::net::accept;
+use super::Ctx;
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Context};
+use enarx_config::{File, Isolation};
+use getrandom::getrandom;
+use tracing::{debug, error, info, warn};
+use wasi_common::file::FileCaps;
+use wasi_common::WasiFile;
+use wasmtime::{InstancePre, Memory, Store};
+
+/// Maximum number of consecutive failures to accept a connection before giving up
+const MAX_ACCEPT_FAILURES: usize = 64;
+
+/// Canary written to the memory of every instance after its connection
+type Canary = [u8; 16];
+
+/// Counters of the instances of the connections
+#[derive(Copy, Clone, Debug, Default)]
+struct Counters {
+    /// Number of instances
+    instances: u64,
+    /// Number of instances, which failed to handle their connection
+    failed: u64,
+    /// Total instantiation latency
+    instantiation: Duration,
+    /// Maximum instantiation latency
+    instantiation_max: Duration,
+    /// Total size of the linear memory released with the instances in bytes
+    recycled: u64,
+    /// Number of instances, which found the canary of a previous instance
+    residues: u64,
+}
+
+/// Statistics of the instance-per-connection isolation
+#[derive(Debug, Default)]
+pub(crate) struct Stats(Mutex<Counters>);
+
+impl Stats {
+    /// Records an instance, which was instantiated in `latency`
+    fn instantiated(&self, latency: Duration) {
+        let mut counters = self.0.lock().unwrap();
+        counters.instances += 1;
+        counters.instantiation += latency;
+        counters.instantiation_max = counters.instantiation_max.max(latency);
+    }
+
+    /// Records an instance, which released `recycled` bytes of memory and either `handled` its
+    /// connection or failed
+    fn released(&self, recycled: u64, handled: bool) {
+        let mut counters = self.0.lock().unwrap();
+        counters.recycled += recycled;
+        counters.failed += u64::from(!handled);
+    }
+
+    /// Records an instance, which found the canary of a previous instance
+    fn residue(&self) {
+        self.0.lock().unwrap().residues += 1;
+    }
+
+    /// Returns a single line `instances=<n> failed=<n> instantiate_avg_us=<n>
+    /// instantiate_max_us=<n> recycled=<bytes> residues=<n>`
+    pub(crate) fn report(&self) -> String {
+        let counters = *self.0.lock().unwrap();
+        let avg = match counters.instances {
+            0 => 0,
+            n => counters.instantiation.as_micros() / u128::from(n),
+        };
+        format!(
+            "instances={} failed={} instantiate_avg_us={avg} instantiate_max_us={} recycled={} residues={}\n",
+            counters.instances,
+            counters.failed,
+            counters.instantiation_max.as_micros(),
+            counters.recycled,
+            counters.residues,
+        )
+    }
+}
+
+/// Checks the `isolation` of the `files` and returns the index of the listen file to serve,
+/// if any
+pub(super) fn check(isolation: &Isolation, files: &[File]) -> anyhow::Result<Option<usize>> {
+    let name = match &isolation.per_connection {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+
+    let mut listen = None;
+    for (fd, file) in files.iter().enumerate() {
+        match file {
+            File::Listen(..) if file.name() == &**name => listen = Some(fd),
+            File::Null(..)
+            | File::Stdin(..)
+            | File::Stdout(..)
+            | File::Stderr(..)
+            | File::Memory(..)
+            | File::AppendLog(..)
+            | File::Dataset(..) => {}
+            _ => bail!(
+                "`{}` cannot be shared by the instances of the connections of `{}`",
+                file.name(),
+                &**name
+            ),
+        }
+    }
+    ensure!(listen.is_some(), "`{}` is not a listen file", &**name);
+    Ok(listen)
+}
+
+/// Handles every connection accepted on `listener` in a fresh instance of `pre` and records them
+/// in `stats`
+///
+/// `store` creates the store of an instance, which gets the accepted connection as its listen
+/// file. Failing instances only fail their connection.
+pub(super) fn serve(
+    pre: &InstancePre<Ctx>,
+    mut listener: Box<dyn WasiFile>,
+    stats: &Stats,
+    mut store: impl FnMut((Box<dyn WasiFile>, FileCaps)) -> anyhow::Result<Store<Ctx>>,
+) -> anyhow::Result<()> {
+    let mut canary = Canary::default();
+    getrandom(&mut canary).context("failed to generate isolation canary")?;
+    let mut failures = 0;
+    loop {
+        let conn = match accept(listener.as_mut()) {
+            Ok(conn) => conn,
+            Err(e) if failures < MAX_ACCEPT_FAILURES => {
+                failures += 1;
+                warn!("failed to accept connection: {e:#}");
+                continue;
+            }
+            Err(e) => return Err(e).context("failed to accept connections"),
+        };
+        failures = 0;
+
+        let mut wstore = store(conn)?;
+        if let Err(e) = handle(pre, &mut wstore, &canary, stats) {
+            warn!("failed to handle connection: {e:#}");
+        }
+        // Release the memory of the instance, before accepting the next connection
+        drop(wstore);
+        info!("connection closed");
+    }
+}
+
+/// Returns the offset of the canary at the end of the initial `memory` of an instance
+fn canary_offset(memory: &Memory, wstore: &Store<Ctx>) -> Option<usize> {
+    memory
+        .data_size(wstore)
+        .checked_sub(Canary::default().len())
+}
+
+/// Runs a fresh instance of `pre` in `wstore` to handle its connection and records it in
+/// `stats`
+///
+/// Before the instance runs, its memory is checked for the `canary` of a previous instance,
+/// which is written to it afterwards.
+fn handle(
+    pre: &InstancePre<Ctx>,
+    wstore: &mut Store<Ctx>,
+    canary: &Canary,
+    stats: &Stats,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let instance = pre
+        .instantiate(&mut *wstore)
+        .context("failed to instantiate module")?;
+    let latency = start.elapsed();
+    stats.instantiated(latency);
+    debug!("instantiated module in {}us", latency.as_micros());
+
+    let memory = instance.get_memory(&mut *wstore, "memory");
+    let offset = memory.and_then(|memory| canary_offset(&memory, &*wstore));
+    if let (Some(memory), Some(offset)) = (memory, offset) {
+        if memory.data(&*wstore)[offset..].starts_with(canary) {
+            stats.residue();
+            error!("the memory of a fresh instance holds state of a previous connection");
+        }
+    }
+
+    let res = instance
+        .get_typed_func::<(), (), _>(&mut *wstore, "_start")
+        .context("failed to get `_start` function")
+        .and_then(|func| match func.call(&mut *wstore, ()) {
+            Ok(()) => Ok(()),
+            Err(e) if e.i32_exit_status() == Some(0) => Ok(()),
+            Err(e) => Err(anyhow::Error::from(e).context("failed to execute `_start` function")),
+        });
+
+    let recycled = match memory {
+        Some(memory) => {
+            if let Some(offset) = offset {
+                memory.data_mut(&mut *wstore)[offset..][..canary.len()].copy_from_slice(canary);
+            }
+            memory.data_size(&*wstore) as u64
+        }
+        None => 0,
+    };
+    stats.released(recycled, res.is_ok());
+    res
+}
+
+/// Reports the [`Stats`] of the instance-per-connection isolation to a sidecar
+#[cfg(target_os = "linux")]
+pub(crate) struct StatsFile(pub(crate) std::sync::Arc<Stats>);
+
+#[cfg(target_os = "linux")]
+#[wiggle::async_trait]
+impl WasiFile for StatsFile {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    async fn get_filetype(&mut self) -> Result<wasi_common::file::FileType, wasi_common::Error> {
+        Ok(wasi_common::file::FileType::CharacterDevice)
+    }
+
+    async fn read_vectored<'a>(
+        &mut self,
+        bufs: &mut [std::io::IoSliceMut<'a>],
+    ) -> Result<u64, wasi_common::Error> {
+        use std::io::Write;
+
+        let report = self.0.report();
+        let mut report = report.as_bytes();
+        let mut n = 0;
+        for buf in bufs {
+            n += (&mut **buf).write(report)?;
+            report = &report[report.len().min(buf.len())..];
+        }
+        Ok(n as _)
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, wasi_common::Error> {
+        Ok(self.0.report().len() as _)
+    }
+
+    async fn readable(&self) -> Result<(), wasi_common::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use enarx_config::{ListenFile, StdioFile};
+
+    fn listen(name: &str) -> File {
+        File::Listen(ListenFile::Tcp {
+            name: name.try_into().unwrap(),
+            addr: "::".into(),
+            port: 8080,
+        })
+    }
+
+    #[test]
+    fn check_files() {
+        let isolation = Isolation {
+            per_connection: Some("ingest".try_into().unwrap()),
+        };
+        let files = [
+            File::Stdin(StdioFile::default()),
+            listen("ingest"),
+            File::Stderr(StdioFile::default()),
+        ];
+
+        assert_eq!(check(&Isolation::default(), &files).unwrap(), None);
+        assert_eq!(check(&isolation, &files).unwrap(), Some(1));
+
+        // The other files cannot be opened anew for every instance
+        assert!(check(&isolation, &[listen("ingest"), listen("admin")]).is_err());
+
+        // The file to serve has to be a listen file
+        assert!(check(&isolation, &[File::Stdin(StdioFile::default())]).is_err());
+        assert!(check(&isolation, &[listen("admin")]).is_err());
+    }
+
+    #[test]
+    fn stats() {
+        let stats = Stats::default();
+        assert_eq!(
+            stats.report(),
+            "instances=0 failed=0 instantiate_avg_us=0 instantiate_max_us=0 recycled=0 residues=0\n"
+        );
+
+        stats.instantiated(Duration::from_micros(100));
+        stats.released(65536, true);
+        stats.instantiated(Duration::from_micros(300));
+        stats.released(131072, false);
+        stats.residue();
+        assert_eq!(
+            stats.report(),
+            "instances=2 failed=1 instantiate_avg_us=200 instantiate_max_us=300 recycled=196608 residues=1\n"
+        );
+    }
+}
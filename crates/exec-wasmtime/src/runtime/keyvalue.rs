@@ -0,0 +1,394 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sealed key-value store of the `wasi:keyvalue` interface
+//!
+//! The workload imports `open`, `get`, `set`, `delete` and `exists` from the `wasi:keyvalue/store`
+//! module to store values in the directory of the `keyvalue` table of the Enarx.toml on the host.
+//! Every value is stored in its own file, which is named after the HMAC of its key and contains a
+//! random nonce and the value encrypted with ChaCha20-Poly1305 and the key as associated data. The
+//! keys of each bucket are derived from the sealing key of the Keep and the name of the bucket,
+//! so the host can neither read the values nor swap them between keys or buckets, and identical
+//! Keeps share the store.
+//!
+//! Without a sealing key, e.g. on KVM, a random secret generated at startup is used instead, so
+//! the values are lost with the Keep.
+
+use super::errno::{self, negate};
+use super::identity::Platform;
+use super::keys::{memory, read, read_str, write};
+use super::Ctx;
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use enarx_config::KeyValue;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use tracing::warn;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+use zeroize::Zeroizing;
+
+/// Maximum number of open buckets of an instance
+pub const MAX_BUCKETS: usize = 64;
+
+/// Maximum length of the name of a bucket
+pub const MAX_NAME_LEN: usize = 256;
+
+/// Salt of the key derivation
+const SALT: &[u8] = b"enarx keyvalue";
+
+/// Store of the values of the workload in a directory of the host
+pub struct Store {
+    dir: PathBuf,
+    secret: Zeroizing<Vec<u8>>,
+    rng: SystemRandom,
+}
+
+impl Store {
+    /// Opens the store of `conf`, creating its directory if it does not exist
+    pub fn open(conf: &KeyValue) -> anyhow::Result<Self> {
+        fs::create_dir_all(&conf.path)
+            .with_context(|| format!("failed to create key-value store `{}`", conf.path))?;
+
+        let key = Platform::get()
+            .and_then(|platform| platform.key())
+            .context("failed to get sealing key")?;
+        let mut secret = Zeroizing::new(key);
+        if secret.is_empty() {
+            warn!("no sealing key, the values of the key-value store are lost with the Keep");
+            secret.resize(32, 0);
+            SystemRandom::new()
+                .fill(&mut secret)
+                .map_err(|_| anyhow!("failed to generate key-value store secret"))?;
+        }
+        Ok(Self::new(conf.path.clone().into(), secret))
+    }
+
+    fn new(dir: PathBuf, secret: Zeroizing<Vec<u8>>) -> Self {
+        Self {
+            dir,
+            secret,
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Returns the bucket named `name`
+    fn bucket(self: &Arc<Self>, name: &str) -> Bucket {
+        let prk = Salt::new(HKDF_SHA256, SALT).extract(&self.secret);
+        let key = prk
+            .expand(&[&b"value"[..], name.as_bytes()], &CHACHA20_POLY1305)
+            .expect("value key length is valid");
+        let names = prk
+            .expand(&[&b"name"[..], name.as_bytes()], hmac::HMAC_SHA256)
+            .expect("name key length is valid");
+        Bucket {
+            store: self.clone(),
+            key: LessSafeKey::new(UnboundKey::from(key)),
+            names: hmac::Key::from(names),
+        }
+    }
+}
+
+/// A bucket of the store with its own keys
+struct Bucket {
+    store: Arc<Store>,
+    key: LessSafeKey,
+    names: hmac::Key,
+}
+
+impl Bucket {
+    /// Returns the path of the file of the value of `key`
+    fn path(&self, key: &str) -> PathBuf {
+        let name = hmac::sign(&self.names, key.as_bytes());
+        self.store.dir.join(hex::encode(name))
+    }
+
+    /// Returns the value of `key`
+    ///
+    /// Fails with `ERRNO_NOENT`, if there is no such value, and with `ERRNO_IO`, if the value was
+    /// modified.
+    fn get(&self, key: &str) -> Result<Vec<u8>, Errno> {
+        let sealed = fs::read(self.path(key)).map_err(|e| errno::from_io(&e))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(Errno::Io);
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| Errno::Io)?;
+        let mut value = sealed.to_vec();
+        let len = self
+            .key
+            .open_in_place(nonce, Aad::from(key.as_bytes()), &mut value)
+            .map_err(|_| {
+                warn!("value of the key-value store was modified on the host");
+                Errno::Io
+            })?
+            .len();
+        value.truncate(len);
+        Ok(value)
+    }
+
+    /// Replaces the value of `key` with `value` atomically
+    fn set(&self, key: &str, value: &[u8]) -> Result<(), Errno> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.store.rng.fill(&mut nonce).map_err(|_| Errno::Io)?;
+
+        let mut sealed = value.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| Errno::Io)?;
+
+        // The unique nonce also names the temporary file
+        let path = self.path(key);
+        let tmp = path.with_extension(hex::encode(nonce));
+        let mut file = nonce.to_vec();
+        file.extend(sealed);
+        fs::write(&tmp, file)
+            .and_then(|()| fs::rename(&tmp, path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&tmp);
+                errno::from_io(&e)
+            })
+    }
+
+    /// Removes the value of `key`, if any
+    fn delete(&self, key: &str) -> Result<(), Errno> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(errno::from_io(&e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether `key` has a value
+    fn exists(&self, key: &str) -> Result<bool, Errno> {
+        self.path(key).try_exists().map_err(|e| errno::from_io(&e))
+    }
+}
+
+/// The open buckets of an instance
+#[derive(Default)]
+pub struct Buckets {
+    store: Option<Arc<Store>>,
+    open: Vec<Bucket>,
+}
+
+impl Buckets {
+    /// Creates the buckets of an instance using `store`
+    pub fn new(store: Option<Arc<Store>>) -> Self {
+        Self {
+            store,
+            open: vec![],
+        }
+    }
+
+    /// Opens the bucket `name` and returns its handle
+    fn open(&mut self, name: &str) -> Result<i32, Errno> {
+        let store = self.store.as_ref().ok_or(Errno::Notcapable)?;
+        if self.open.len() >= MAX_BUCKETS {
+            return Err(Errno::Nfile);
+        }
+        self.open.push(store.bucket(name));
+        Ok(self.open.len() as i32 - 1)
+    }
+
+    /// Returns the bucket of `handle`
+    fn get(&self, handle: i32) -> Result<&Bucket, Errno> {
+        if self.store.is_none() {
+            return Err(Errno::Notcapable);
+        }
+        usize::try_from(handle)
+            .ok()
+            .and_then(|handle| self.open.get(handle))
+            .ok_or(Errno::Badf)
+    }
+}
+
+/// Opens the bucket with the UTF-8 name at `name` of `name_len` bytes and returns its handle or
+/// the negated WASI errno
+///
+/// Fails with `ERRNO_NOTCAPABLE`, if the workload has no key-value store, and with `ERRNO_NFILE`,
+/// if too many buckets are open.
+fn open(mut caller: Caller<'_, Ctx>, name: u32, name_len: u32) -> i32 {
+    if name_len as usize > MAX_NAME_LEN {
+        return negate(Err(Errno::Nametoolong));
+    }
+    negate(
+        read_str(&mut caller, name, name_len)
+            .and_then(|name| caller.data_mut().keyvalue.open(&name)),
+    )
+}
+
+/// Writes the value of the UTF-8 key at `key` of `key_len` bytes in `bucket` to `buf` of `len`
+/// bytes and returns its length or the negated WASI errno
+///
+/// Fails with `ERRNO_NOENT`, if there is no such value, with `ERRNO_IO`, if the value was
+/// modified, and with `ERRNO_RANGE`, if the value does not fit into `buf`.
+fn get(
+    mut caller: Caller<'_, Ctx>,
+    bucket: i32,
+    key: u32,
+    key_len: u32,
+    buf: u32,
+    len: u32,
+) -> i32 {
+    let res = read_str(&mut caller, key, key_len)
+        .and_then(|key| caller.data().keyvalue.get(bucket)?.get(&key))
+        .and_then(|value| Ok((memory(&mut caller)?, Zeroizing::new(value))));
+    negate(res.and_then(|(memory, value)| write(&mut caller, memory, buf, len, &value)))
+}
+
+/// Sets the value of the UTF-8 key at `key` of `key_len` bytes in `bucket` to the `value_len`
+/// bytes at `value` and returns 0 or the negated WASI errno
+fn set(
+    mut caller: Caller<'_, Ctx>,
+    bucket: i32,
+    key: u32,
+    key_len: u32,
+    value: u32,
+    value_len: u32,
+) -> i32 {
+    negate((|| {
+        let key = read_str(&mut caller, key, key_len)?;
+        let value = Zeroizing::new(read(&mut caller, value, value_len)?);
+        caller.data().keyvalue.get(bucket)?.set(&key, &value)?;
+        Ok(0)
+    })())
+}
+
+/// Removes the value of the UTF-8 key at `key` of `key_len` bytes from `bucket` and returns 0
+/// or the negated WASI errno
+fn delete(mut caller: Caller<'_, Ctx>, bucket: i32, key: u32, key_len: u32) -> i32 {
+    negate(
+        read_str(&mut caller, key, key_len)
+            .and_then(|key| caller.data().keyvalue.get(bucket)?.delete(&key))
+            .map(|()| 0),
+    )
+}
+
+/// Returns 1, if the UTF-8 key at `key` of `key_len` bytes has a value in `bucket`, 0, if it
+/// has none, or the negated WASI errno
+fn exists(mut caller: Caller<'_, Ctx>, bucket: i32, key: u32, key_len: u32) -> i32 {
+    negate(
+        read_str(&mut caller, key, key_len)
+            .and_then(|key| caller.data().keyvalue.get(bucket)?.exists(&key))
+            .map(i32::from),
+    )
+}
+
+/// Adds the `wasi:keyvalue/store` `open`, `get`, `set`, `delete` and `exists` functions to
+/// `linker`
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("wasi:keyvalue/store", "open", open)
+        .context("failed to add `wasi:keyvalue/store::open`")?;
+    linker
+        .func_wrap("wasi:keyvalue/store", "get", get)
+        .context("failed to add `wasi:keyvalue/store::get`")?;
+    linker
+        .func_wrap("wasi:keyvalue/store", "set", set)
+        .context("failed to add `wasi:keyvalue/store::set`")?;
+    linker
+        .func_wrap("wasi:keyvalue/store", "delete", delete)
+        .context("failed to add `wasi:keyvalue/store::delete`")?;
+    linker
+        .func_wrap("wasi:keyvalue/store", "exists", exists)
+        .context("failed to add `wasi:keyvalue/store::exists`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(dir: &tempfile::TempDir, secret: u8) -> Arc<Store> {
+        Arc::new(Store::new(
+            dir.path().into(),
+            Zeroizing::new(vec![secret; 32]),
+        ))
+    }
+
+    #[test]
+    fn roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let bucket = store(&dir, 0x42).bucket("users");
+        assert_eq!(bucket.get("alice"), Err(Errno::Noent));
+        assert_eq!(bucket.exists("alice"), Ok(false));
+        assert_eq!(bucket.delete("alice"), Ok(()));
+
+        bucket.set("alice", b"admin").unwrap();
+        assert_eq!(bucket.get("alice").unwrap(), b"admin");
+        assert_eq!(bucket.exists("alice"), Ok(true));
+        bucket.set("alice", b"user").unwrap();
+        assert_eq!(bucket.get("alice").unwrap(), b"user");
+
+        // Neither the key nor the value reach the host in plaintext
+        let files: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let file = files[0].as_ref().unwrap();
+        assert!(!file.file_name().to_str().unwrap().contains("alice"));
+        let sealed = fs::read(file.path()).unwrap();
+        assert!(!sealed.windows(4).any(|window| window == b"user"));
+
+        bucket.delete("alice").unwrap();
+        assert_eq!(bucket.get("alice"), Err(Errno::Noent));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn sealing() {
+        let dir = tempfile::tempdir().unwrap();
+        let users = store(&dir, 0x42).bucket("users");
+        users.set("alice", b"admin").unwrap();
+        users.set("bob", b"user").unwrap();
+
+        // Identical Keeps share the values, other Keeps and buckets do not
+        assert_eq!(
+            store(&dir, 0x42).bucket("users").get("alice").unwrap(),
+            b"admin"
+        );
+        assert_eq!(
+            store(&dir, 0x24).bucket("users").get("alice"),
+            Err(Errno::Noent)
+        );
+        assert_eq!(
+            store(&dir, 0x42).bucket("groups").get("alice"),
+            Err(Errno::Noent)
+        );
+
+        // Values swapped or modified by the host are rejected
+        fs::copy(users.path("bob"), users.path("alice")).unwrap();
+        assert_eq!(users.get("alice"), Err(Errno::Io));
+        let mut sealed = fs::read(users.path("bob")).unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        fs::write(users.path("bob"), sealed).unwrap();
+        assert_eq!(users.get("bob"), Err(Errno::Io));
+    }
+
+    #[test]
+    fn buckets() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buckets = Buckets::default();
+        assert_eq!(buckets.open("users").err(), Some(Errno::Notcapable));
+        assert_eq!(buckets.get(0).err(), Some(Errno::Notcapable));
+
+        let mut buckets = Buckets::new(Some(store(&dir, 0x42)));
+        assert_eq!(buckets.open("users"), Ok(0));
+        assert_eq!(buckets.open("groups"), Ok(1));
+        assert!(buckets.get(1).is_ok());
+        assert_eq!(buckets.get(2).err(), Some(Errno::Badf));
+        assert_eq!(buckets.get(-1).err(), Some(Errno::Badf));
+        for _ in 2..MAX_BUCKETS {
+            buckets.open("users").unwrap();
+        }
+        assert_eq!(buckets.open("users"), Err(Errno::Nfile));
+    }
+}
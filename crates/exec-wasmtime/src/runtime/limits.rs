@@ -0,0 +1,581 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Enforcement of the resource limits of the Wasm workload
+
+#[cfg(target_os = "linux")]
+use super::io::event::Event;
+
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, ensure, Context};
+use enarx_config::Limits;
+use tracing::warn;
+use wasmtime::{Module, ResourceLimiter, StoreLimits, StoreLimitsBuilder};
+
+const IMPORT_SECTION: u8 = 2;
+const TABLE_SECTION: u8 = 4;
+const CODE_SECTION: u8 = 10;
+
+/// Default percentage of the memory limit, above which the workload is under memory pressure
+const DEFAULT_MEMORY_PRESSURE: u8 = 80;
+
+/// Resizable limits of a table or memory type
+pub(super) struct Resizable {
+    pub flags: u8,
+    pub min: u64,
+    pub max: Option<u64>,
+}
+
+/// Description of an import, of which only tables and memories are parsed
+pub(super) enum Import {
+    Table(Resizable),
+    Memory(Resizable),
+    Other,
+}
+
+/// Minimal reader of the Wasm binary format
+pub(super) struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn byte(&mut self) -> anyhow::Result<u8> {
+        let (&byte, rest) = self
+            .0
+            .split_first()
+            .context("unexpected end of Wasm module")?;
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    pub fn bytes(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        ensure!(n <= self.0.len(), "unexpected end of Wasm module");
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    pub fn leb128(&mut self) -> anyhow::Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("invalid LEB128 integer in Wasm module")
+    }
+
+    pub fn len(&mut self) -> anyhow::Result<usize> {
+        self.leb128()?
+            .try_into()
+            .context("invalid length in Wasm module")
+    }
+
+    pub fn name(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = self.len()?;
+        self.bytes(len)
+    }
+
+    /// Reads resizable limits
+    pub fn limits(&mut self) -> anyhow::Result<Resizable> {
+        let flags = self.byte()?;
+        let min = self.leb128()?;
+        let max = if flags & 0x01 != 0 {
+            Some(self.leb128()?)
+        } else {
+            None
+        };
+        Ok(Resizable { flags, min, max })
+    }
+
+    /// Reads an entry of the import section
+    pub fn import(&mut self) -> anyhow::Result<Import> {
+        self.name()?;
+        self.name()?;
+        match self.byte()? {
+            0x00 => {
+                self.leb128()?;
+            }
+            0x01 => {
+                self.byte()?;
+                return self.limits().map(Import::Table);
+            }
+            0x02 => return self.limits().map(Import::Memory),
+            0x03 => {
+                self.bytes(2)?;
+            }
+            0x04 => {
+                self.byte()?;
+                self.leb128()?;
+            }
+            kind => bail!("invalid import kind `{kind}` in Wasm module"),
+        }
+        Ok(Import::Other)
+    }
+}
+
+/// Sizes of a core Wasm module, which are limited before compiling it
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Sections {
+    /// Initial sizes of all tables imported or defined by the module
+    tables: Vec<u64>,
+    /// Size of the code section in bytes
+    code: u64,
+}
+
+/// Parses the sizes of a core Wasm module up to its code section
+///
+/// The module is validated by wasmtime later.
+fn sections(webasm: &[u8]) -> anyhow::Result<Sections> {
+    let mut reader = Reader(webasm);
+    ensure!(reader.bytes(4)? == b"\0asm", "invalid Wasm module magic");
+    if reader.bytes(4)? != [1, 0, 0, 0] {
+        // Not a core module, leave it to wasmtime
+        return Ok(Sections::default());
+    }
+
+    let mut sections = Sections::default();
+    while !reader.0.is_empty() {
+        let id = reader.byte()?;
+        let len = reader.len()?;
+        let mut section = Reader(reader.bytes(len)?);
+        match id {
+            IMPORT_SECTION => {
+                for _ in 0..section.leb128()? {
+                    if let Import::Table(table) = section.import()? {
+                        sections.tables.push(table.min);
+                    }
+                }
+            }
+            TABLE_SECTION => {
+                for _ in 0..section.leb128()? {
+                    section.byte()?;
+                    sections.tables.push(section.limits()?.min);
+                }
+            }
+            CODE_SECTION => {
+                sections.code = len as u64;
+                // Only the data and custom sections follow the code section
+                break;
+            }
+            _ => {}
+        }
+    }
+    Ok(sections)
+}
+
+/// Checks the Wasm module against the limits before compiling it
+pub(crate) fn check_module(webasm: &[u8], limits: &Limits) -> anyhow::Result<()> {
+    if let Some(max) = limits.module_size {
+        let size = webasm.len() as u64;
+        ensure!(
+            size <= max,
+            "Wasm module size of `{size}` exceeds the limit of `{max}`"
+        );
+    }
+
+    if limits.tables.is_none() && limits.table_elements.is_none() && limits.code_size.is_none() {
+        return Ok(());
+    }
+    let Sections { tables, code } = sections(webasm).context("failed to parse Wasm module")?;
+
+    // The compiled code is checked again by `check_compiled`, but compiling an oversized code
+    // section may already exhaust the memory of the Keep
+    if let Some(max) = limits.code_size {
+        ensure!(
+            code <= max,
+            "Wasm module code section size of `{code}` exceeds the limit of `{max}`"
+        );
+    }
+    if let Some(max) = limits.tables {
+        let count = tables.len();
+        ensure!(
+            count <= max as usize,
+            "Wasm module table count of `{count}` exceeds the limit of `{max}`"
+        );
+    }
+    if let Some(max) = limits.table_elements {
+        if let Some(size) = tables.into_iter().max() {
+            ensure!(
+                size <= max.into(),
+                "Wasm module table size of `{size}` exceeds the limit of `{max}`"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks the compiled Wasm module against the limits
+pub(crate) fn check_compiled(module: &Module, limits: &Limits) -> anyhow::Result<()> {
+    if let Some(max) = limits.code_size {
+        let size = module.image_range().len() as u64;
+        ensure!(
+            size <= max,
+            "compiled Wasm code size of `{size}` exceeds the limit of `{max}`"
+        );
+    }
+    Ok(())
+}
+
+/// Returns the store limits enforced at runtime
+fn store_limits(limits: &Limits) -> StoreLimits {
+    let mut builder = StoreLimitsBuilder::new();
+    if let Some(max) = limits.tables {
+        builder = builder.tables(max as usize);
+    }
+    if let Some(max) = limits.table_elements {
+        builder = builder.table_elements(max);
+    }
+    if let Some(max) = limits.instances {
+        builder = builder.instances(max as usize);
+    }
+    builder.build()
+}
+
+/// Memory usage of the linear memories of the workload in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Usage {
+    pub size: u64,
+    /// High-water mark of `size`
+    pub peak: u64,
+    pub limit: Option<u64>,
+}
+
+/// Cause of a failed growth of a linear memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Exhaustion {
+    /// The configured memory limit was reached
+    Limit,
+    /// The heap of the Keep is exhausted (`ENOMEM`)
+    Heap,
+    /// The host refused to commit memory to the Keep (`EAGAIN`)
+    Host,
+    /// The host did not commit the memory it reported as committed (`EFAULT`)
+    Commit,
+    /// Any other failure
+    Other,
+}
+
+impl Exhaustion {
+    /// Classifies the error of a failed growth by the errno reported by the Keep
+    fn new(error: &anyhow::Error) -> Self {
+        let errno = error.chain().find_map(|e| {
+            #[cfg(unix)]
+            if let Some(errno) = e.downcast_ref::<rustix::io::Errno>() {
+                return Some(errno.raw_os_error());
+            }
+            e.downcast_ref::<io::Error>()?.raw_os_error()
+        });
+        match errno {
+            Some(libc::ENOMEM) => Self::Heap,
+            Some(libc::EAGAIN) => Self::Host,
+            Some(libc::EFAULT) => Self::Commit,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl fmt::Display for Exhaustion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Limit => "memory limit reached",
+            Self::Heap => "heap exhausted",
+            Self::Host => "host refused to commit memory",
+            Self::Commit => "host failed to commit memory",
+            Self::Other => "memory growth failed",
+        })
+    }
+}
+
+/// Memory usage of all stores of the workload, which signals memory pressure
+#[derive(Debug)]
+pub(crate) struct Memory {
+    usage: Mutex<Usage>,
+    /// Cause of the last failed growth
+    exhaustion: Mutex<Option<Exhaustion>>,
+    /// Size, above which the workload is under memory pressure
+    threshold: Option<u64>,
+    /// Signaled on every growth above `threshold` and every denied growth
+    #[cfg(target_os = "linux")]
+    event: Event,
+}
+
+impl Memory {
+    pub(crate) fn new(limits: &Limits) -> anyhow::Result<Arc<Self>> {
+        let pressure = limits.memory_pressure.unwrap_or(DEFAULT_MEMORY_PRESSURE);
+        ensure!(
+            pressure <= 100,
+            "memory pressure of `{pressure}` is not a percentage"
+        );
+        let threshold = limits
+            .memory_size
+            .map(|max| (u128::from(max) * u128::from(pressure) / 100) as u64);
+
+        Ok(Arc::new(Self {
+            usage: Mutex::new(Usage {
+                limit: limits.memory_size,
+                ..Default::default()
+            }),
+            exhaustion: Mutex::new(None),
+            threshold,
+            #[cfg(target_os = "linux")]
+            event: Event::new().context("failed to create memory pressure event")?,
+        }))
+    }
+
+    /// Returns the current memory usage
+    pub(crate) fn usage(&self) -> Usage {
+        *self.usage.lock().unwrap()
+    }
+
+    /// Returns the cause of the last failed growth
+    pub(crate) fn exhaustion(&self) -> Option<Exhaustion> {
+        *self.exhaustion.lock().unwrap()
+    }
+
+    /// Returns the diagnostic of an out of memory Keep, if a growth failed
+    pub(crate) fn diagnostic(&self) -> Option<String> {
+        let exhaustion = self.exhaustion()?;
+        let usage = self.usage();
+        let size = usage
+            .limit
+            .map_or_else(|| "unlimited".into(), |limit| format!("{limit} bytes"));
+        Some(format!(
+            "Keep out of memory ({exhaustion}), configured size {size}, peak usage {} bytes",
+            usage.peak
+        ))
+    }
+
+    /// Records the failed growth of a linear memory
+    fn exhausted(&self, exhaustion: Exhaustion) {
+        *self.exhaustion.lock().unwrap() = Some(exhaustion);
+        if let Some(diagnostic) = self.diagnostic() {
+            warn!("{diagnostic}");
+        }
+    }
+
+    /// Returns the event signaling memory pressure
+    #[cfg(target_os = "linux")]
+    pub(crate) fn event(&self) -> &Event {
+        &self.event
+    }
+
+    fn notify(&self) {
+        #[cfg(target_os = "linux")]
+        self.event.set();
+    }
+
+    /// Accounts `delta` more bytes, if the limit permits it
+    fn grow(&self, delta: u64) -> bool {
+        let mut usage = self.usage.lock().unwrap();
+        let size = usage.size.saturating_add(delta);
+        if matches!(usage.limit, Some(limit) if size > limit) {
+            drop(usage);
+            self.notify();
+            self.exhausted(Exhaustion::Limit);
+            return false;
+        }
+        usage.size = size;
+        usage.peak = usage.peak.max(size);
+        if matches!(self.threshold, Some(threshold) if size > threshold) {
+            self.notify();
+        }
+        true
+    }
+
+    /// Accounts `delta` bytes less
+    fn shrink(&self, delta: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.size = usage.size.saturating_sub(delta);
+    }
+}
+
+/// Resource limiter of a store, which accounts its linear memories in the [`Memory`] of the
+/// workload
+pub(crate) struct Limiter {
+    limits: StoreLimits,
+    memory: Arc<Memory>,
+    /// Size of the linear memories of the store, which is released when the store is dropped
+    size: u64,
+    /// Size of the last permitted growth, which is reverted if the growth fails
+    growth: u64,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: &Limits, memory: Arc<Memory>) -> Self {
+        Self {
+            limits: store_limits(limits),
+            memory,
+            size: 0,
+            growth: 0,
+        }
+    }
+}
+
+impl ResourceLimiter for Limiter {
+    fn memory_growing(&mut self, current: usize, desired: usize, maximum: Option<usize>) -> bool {
+        self.growth = 0;
+        if !self.limits.memory_growing(current, desired, maximum) {
+            return false;
+        }
+        let delta = desired.saturating_sub(current) as u64;
+        if !self.memory.grow(delta) {
+            return false;
+        }
+        self.size += delta;
+        self.growth = delta;
+        true
+    }
+
+    fn memory_grow_failed(&mut self, error: &anyhow::Error) {
+        // Growths denied by the limits are already recorded
+        if self.growth == 0 {
+            return;
+        }
+        self.memory.exhausted(Exhaustion::new(error));
+        self.memory.shrink(self.growth);
+        self.size -= self.growth;
+        self.growth = 0;
+    }
+
+    fn table_growing(&mut self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        self.limits.table_growing(current, desired, maximum)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables()
+    }
+
+    fn memories(&self) -> usize {
+        self.limits.memories()
+    }
+}
+
+impl Drop for Limiter {
+    fn drop(&mut self) {
+        self.memory.shrink(self.size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: usize = 64 * 1024;
+
+    #[test]
+    fn memory() {
+        let limits = Limits {
+            memory_size: Some(10 * PAGE as u64),
+            memory_pressure: Some(50),
+            ..Default::default()
+        };
+        let memory = Memory::new(&limits).unwrap();
+
+        let mut limiter = Limiter::new(&limits, memory.clone());
+        assert!(limiter.memory_growing(0, 4 * PAGE, None));
+        assert!(limiter.memory_growing(4 * PAGE, 8 * PAGE, None));
+        // A failed growth is not accounted
+        assert_eq!(memory.diagnostic(), None);
+        limiter.memory_grow_failed(&anyhow::anyhow!("out of memory"));
+        assert_eq!(memory.exhaustion(), Some(Exhaustion::Other));
+        assert!(limiter.memory_growing(4 * PAGE, 6 * PAGE, None));
+
+        // The limit covers the memories of all stores
+        let mut other = Limiter::new(&limits, memory.clone());
+        assert!(!other.memory_growing(0, 5 * PAGE, None));
+        // A denied growth is not reverted
+        other.memory_grow_failed(&anyhow::anyhow!("Memory maximum size exceeded"));
+        assert_eq!(memory.exhaustion(), Some(Exhaustion::Limit));
+        assert!(other.memory_growing(0, 4 * PAGE, None));
+        assert_eq!(
+            memory.usage(),
+            Usage {
+                size: 10 * PAGE as u64,
+                peak: 10 * PAGE as u64,
+                limit: Some(10 * PAGE as u64),
+            }
+        );
+
+        // Dropped stores release their memory, but not the high-water mark
+        drop(limiter);
+        assert_eq!(memory.usage().size, 4 * PAGE as u64);
+        assert_eq!(memory.usage().peak, 10 * PAGE as u64);
+
+        assert_eq!(
+            memory.diagnostic().unwrap(),
+            "Keep out of memory (memory limit reached), configured size 655360 bytes, \
+             peak usage 655360 bytes"
+        );
+
+        let invalid = Limits {
+            memory_pressure: Some(101),
+            ..Default::default()
+        };
+        assert!(Memory::new(&invalid).is_err());
+    }
+    #[test]
+    fn sections() {
+        let webasm = wat::parse_str(
+            r#"(module
+              (import "env" "table" (table 5 funcref))
+              (table 10 funcref)
+              (func (export "") (result i32) i32.const 1)
+              (data (i32.const 0) "data")
+              (memory 1)
+            )"#,
+        )
+        .unwrap();
+        let sections = super::sections(&webasm).unwrap();
+        assert_eq!(sections.tables, vec![5, 10]);
+        // Vector length, body size, local count, `i32.const 1` and `end`
+        assert_eq!(sections.code, 6);
+
+        let limits = Limits {
+            code_size: Some(6),
+            ..Default::default()
+        };
+        check_module(&webasm, &limits).unwrap();
+        let limits = Limits {
+            code_size: Some(5),
+            ..Default::default()
+        };
+        let err = check_module(&webasm, &limits).unwrap_err();
+        assert!(err.to_string().contains("code section size"), "{err}");
+    }
+
+    #[test]
+    fn exhaustion() {
+        for (errno, exhaustion) in [
+            (libc::ENOMEM, Exhaustion::Heap),
+            (libc::EAGAIN, Exhaustion::Host),
+            (libc::EFAULT, Exhaustion::Commit),
+            (libc::EINVAL, Exhaustion::Other),
+        ] {
+            let error = anyhow::Error::new(io::Error::from_raw_os_error(errno))
+                .context("mmap failed to allocate 0x10000 bytes");
+            assert_eq!(Exhaustion::new(&error), exhaustion);
+
+            #[cfg(unix)]
+            {
+                let error = anyhow::Error::new(rustix::io::Errno::from_raw_os_error(errno));
+                assert_eq!(Exhaustion::new(&error), exhaustion);
+            }
+        }
+
+        let memory = Memory::new(&Limits::default()).unwrap();
+        let mut limiter = Limiter::new(&Limits::default(), memory.clone());
+        assert!(limiter.memory_growing(0, PAGE, None));
+        limiter.memory_grow_failed(&io::Error::from_raw_os_error(libc::ENOMEM).into());
+        assert_eq!(
+            memory.diagnostic().unwrap(),
+            "Keep out of memory (heap exhausted), configured size unlimited, peak usage 65536 bytes"
+        );
+    }
+}
@@ -2,27 +2,89 @@
 
 //! The Enarx Wasm runtime and all related functionality
 
+mod attestation;
+mod capability;
+mod clock;
+mod compat;
+mod config;
+mod cpu;
+mod determinism;
+mod errno;
 mod identity;
 mod io;
+mod isolation;
+mod keys;
+mod keyvalue;
+mod limits;
 mod net;
+mod process;
+mod reexec;
+mod rendezvous;
+mod resilience;
+mod sandbox;
+#[cfg(target_os = "linux")]
+mod sidecar;
+mod stack;
+mod threads;
 
+use self::capability::Capabilities;
+use self::compat::Personality;
+use self::config::Values;
+use self::identity::{Platform, Preopen, Transport};
+use self::io::appendlog::{self, AppendLog, AppendLogs, Chains};
+use self::io::chaos::{Chaos, Chaotic};
+use self::io::dataset::{DatasetReader, Datasets};
+use self::io::helper::Helpers;
+#[cfg(target_os = "linux")]
+use self::io::memory::MemoryFile;
 use self::io::null::Null;
+#[cfg(target_os = "linux")]
+use self::io::pad::Padded;
+#[cfg(target_os = "linux")]
+use self::io::splice::{self, Splice};
 use self::io::stdio_file;
-use self::net::{connect_file, listen_file};
+use self::io::tap::{Tap, Tapped};
+#[cfg(unix)]
+use self::io::tmp::{self, Tmp};
+#[cfg(unix)]
+use self::io::tty::{self, Tty};
+use self::keyvalue::{Buckets, Store};
+use self::limits::{Limiter, Memory};
+use self::net::{connect_file, listen_file, socket, Loopback, Offload, Policy, Sockets};
+use self::rendezvous::Rendezvous;
+use self::resilience::Resilience;
+use self::sandbox::Sandbox;
+#[cfg(target_os = "linux")]
+use self::sidecar::{Log, Sidecar, Tee};
+use self::threads::Threads;
 
+pub use self::reexec::Reexec;
+
+use super::cache;
+use super::heap::HEAP;
+use super::provenance;
+use super::task;
 use super::{Package, Workload};
 
-use anyhow::{bail, Context};
-use enarx_config::{Config, File};
+use std::collections::HashMap;
+use std::iter;
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, bail, Context};
+use enarx_config::{Config, File, Precompiled};
+use enarx_error::{Classify, ErrorKind, Exit};
 use once_cell::sync::Lazy;
+use tracing::{info, warn};
 use wasi_common::file::FileCaps;
 use wasi_common::WasiFile;
-use wasmtime::{AsContextMut, Engine, Linker, Module, Store, Trap, Val};
+use wasmtime::{Engine, Linker, Module, Store, Trap, Val};
 use wasmtime_wasi::stdio::{stderr, stdin, stdout};
-use wasmtime_wasi::{add_to_linker, WasiCtxBuilder};
+use wasmtime_wasi::{add_to_linker, WasiCtx, WasiCtxBuilder};
+use zeroize::Zeroizing;
 
 /// Wasmtime config
-static WASMTIME_CONFIG: Lazy<wasmtime::Config> = Lazy::new(|| {
+pub(crate) static WASMTIME_CONFIG: Lazy<wasmtime::Config> = Lazy::new(|| {
     let mut config = wasmtime::Config::new();
     config.wasm_multi_memory(true);
     config.static_memory_maximum_size(0);
@@ -32,90 +94,693 @@ static WASMTIME_CONFIG: Lazy<wasmtime::Config> = Lazy::new(|| {
     config
 });
 
+/// Path of the temporary directory of the workload
+const TMP: &str = "/tmp";
+
+/// The data associated with the store of the workload
+pub struct Ctx {
+    wasi: WasiCtx,
+    limits: Limiter,
+    capabilities: Capabilities,
+    personality: Personality,
+    steward: Option<Arc<Transport>>,
+    rendezvous: Option<Arc<Rendezvous>>,
+    resilience: Resilience,
+    sockets: Sockets,
+    appendlog: AppendLogs,
+    config: Values,
+    preopens: Arc<Vec<Preopen>>,
+    keyvalue: Buckets,
+    #[cfg(target_os = "linux")]
+    splice: Splice,
+    #[cfg(unix)]
+    tty: Tty,
+    #[cfg(unix)]
+    tmp: Option<Tmp>,
+    #[cfg(target_os = "linux")]
+    log: Option<Arc<Log>>,
+    tap: Option<Arc<Tap>>,
+    chaos: Option<Arc<Chaos>>,
+    threads: Option<Arc<Threads>>,
+    sandbox: Arc<Sandbox>,
+}
+
+/// Checks, whether the Keep may use the precompiled `artifact` provided by the host
+///
+/// Keeps, which may run in a hardware TEE, only use artifacts, whose digest is bound to the
+/// package by the `precompiled` section of the Enarx.toml.
+fn check_trusted(artifact: &[u8], precompiled: Option<&Precompiled>) -> anyhow::Result<()> {
+    match precompiled {
+        Some(precompiled) => cache::check_digest(artifact, &precompiled.digest),
+        None => {
+            let platform = Platform::get().context("failed to query platform")?;
+            if platform.is_tee() {
+                bail!("the digest of artifacts must be specified in the package config of Keeps in a TEE");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Loads the precompiled `artifact` of `webasm` or compiles `webasm`
+fn compile(
+    engine: &Engine,
+    artifact: Option<&[u8]>,
+    webasm: &[u8],
+    precompiled: Option<&Precompiled>,
+) -> anyhow::Result<Module> {
+    let artifact = artifact.map(|artifact| {
+        check_trusted(artifact, precompiled).and_then(|()| cache::load(engine, artifact, webasm))
+    });
+    match artifact {
+        Some(Ok(module)) => return Ok(module),
+        Some(Err(e)) => warn!("ignoring precompiled Wasm module: {e:#}"),
+        None => {}
+    }
+    Module::from_binary(engine, webasm).context("failed to compile Wasm module")
+}
+
 // The Enarx Wasm runtime
 pub struct Runtime;
 
 impl Runtime {
     // Execute an Enarx [Package]
-    pub fn execute(package: Package) -> anyhow::Result<Vec<Val>> {
-        let (prvkey, crtreq) = identity::generate()?;
-
-        let Workload { webasm, config } = package.try_into()?;
+    pub fn execute(
+        package: Package,
+        secrets: HashMap<String, String>,
+        reexec: Option<Reexec>,
+    ) -> anyhow::Result<Vec<Val>> {
+        // Fetch the workload, while the keep identity is generated
+        let (identity, workload) = thread::scope(|s| {
+            let identity = task::spawn(s, identity::generate);
+            let workload = Workload::try_from(package).classify(ErrorKind::Io);
+            (identity.join(), workload)
+        });
+        let prvkey = identity?;
+        let Workload {
+            webasm,
+            config,
+            config_digest,
+            artifact,
+            provenance,
+            sidecar,
+            datasets,
+            tap: pcapng,
+            channel,
+            offload,
+            helpers,
+        } = workload?;
+        if let Some(reexec) = reexec {
+            reexec
+                .check(config_digest.as_ref())
+                .context("refusing to run the relaunched Keep")
+                .classify(ErrorKind::Config)?;
+        }
+        let config = config.unwrap_or_default();
+        let preopens = Preopen::list(config_digest, &config).classify(ErrorKind::Config)?;
         let Config {
             steward,
             args,
             files,
             env,
-        } = config.unwrap_or_default();
+            limits,
+            capabilities,
+            process,
+            compat,
+            isolation,
+            network,
+            provenance: policy,
+            rendezvous,
+            sidecar: permission,
+            determinism,
+            clock: clocks,
+            tmp,
+            precompiled,
+            config: values,
+            keyvalue,
+            heap,
+            tap,
+            chaos,
+            stack,
+            wait,
+        } = config;
+        HEAP.set_quarantine(usize::try_from(heap.quarantine).unwrap_or(usize::MAX));
+        let steward = steward
+            .map(|url| Transport::new(url, channel))
+            .transpose()
+            .classify(ErrorKind::Config)?
+            .map(Arc::new);
+        if let Some(wait) = wait {
+            // Without a shim, the host kernel waits and the window is ignored
+            Platform::set_spin_wait(u64::from(wait.spin) * 1_000)
+                .context("failed to set the spin window of blocking waits")
+                .classify(ErrorKind::Platform)?;
+        }
 
-        let certs = if let Some(url) = steward {
-            identity::steward(&url, crtreq).context("failed to attest to Steward")?
-        } else {
-            identity::selfsigned(&prvkey).context("failed to generate self-signed certificates")?
+        limits::check_module(&webasm, &limits).classify(ErrorKind::Config)?;
+        if let Some(determinism) = &determinism {
+            determinism::check(&webasm, determinism, &limits).classify(ErrorKind::Config)?;
         }
-        .into_iter()
-        .map(rustls::Certificate)
-        .collect::<Vec<_>>();
+        provenance::check(policy.as_ref(), provenance.as_ref(), &webasm)
+            .classify(ErrorKind::Attestation)?;
+        process::check(&process, &env).classify(ErrorKind::Config)?;
+        process::set_umask(&process);
+        let personality = Personality::new(compat).classify(ErrorKind::Config)?;
+        let capabilities = Capabilities::new(capabilities).classify(ErrorKind::Attestation)?;
+        let memory = Memory::new(&limits).classify(ErrorKind::Config)?;
+        #[cfg(not(unix))]
+        if tmp.is_some() {
+            return Err(anyhow!(
+                "temporary directories are not supported on this platform"
+            ))
+            .classify(ErrorKind::Config);
+        }
+        #[cfg(unix)]
+        let tmpfs = tmp.as_ref().map(|conf| Tmp::new(conf.size));
 
-        let engine = Engine::new(&WASMTIME_CONFIG).context("failed to create execution engine")?;
+        let mut wasmtime_config = match limits.threads {
+            Some(..) => threads::config(&limits).classify(ErrorKind::Config)?,
+            None => WASMTIME_CONFIG.clone(),
+        };
+        if determinism.is_some() {
+            determinism::configure(&mut wasmtime_config);
+        }
+        if let Some(stack) = &stack {
+            stack::configure(&mut wasmtime_config, stack).classify(ErrorKind::Config)?;
+        }
+        let engine = Engine::new(&wasmtime_config).context("failed to create execution engine")?;
+
+        // Compile the module, while the Steward attests the keep
+        let (certs, module) = thread::scope(|s| {
+            let module = task::spawn(s, || {
+                compile(&engine, artifact.as_deref(), &webasm, precompiled.as_ref())
+            });
+            let certs = if let Some(transport) = &steward {
+                transport
+                    .attest(|nonce| {
+                        let platform = Platform::get().context("failed to query platform")?;
+                        identity::request(&platform, &prvkey, &preopens, nonce)
+                            .context("failed to make certificate signing request")
+                    })
+                    .context("failed to attest to Steward")
+            } else {
+                identity::selfsigned(&prvkey).context("failed to generate self-signed certificates")
+            }
+            .classify(ErrorKind::Attestation);
+            (certs, module.join())
+        });
+        let certs = certs?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect::<Vec<_>>();
+        let module = module?;
+
+        let sidecar = match (sidecar, permission) {
+            (Some(..), None) => {
+                return Err(anyhow!(
+                    "the package config does not permit a sidecar module"
+                ))
+                .classify(ErrorKind::Config)
+            }
+            (Some(webasm), Some(conf)) => Some((webasm, conf)),
+            (None, _) => None,
+        };
+        #[cfg(not(target_os = "linux"))]
+        if sidecar.is_some() {
+            return Err(anyhow!("sidecars are not supported on this platform"))
+                .classify(ErrorKind::Config);
+        }
+        let stats = isolation
+            .per_connection
+            .is_some()
+            .then(|| Arc::new(isolation::Stats::default()));
+        #[cfg(target_os = "linux")]
+        let sidecar = sidecar
+            .map(|(webasm, conf)| Sidecar::spawn(&conf, &engine, &webasm, &memory, stats.as_ref()))
+            .transpose()
+            .context("failed to start sidecar")
+            .classify(ErrorKind::Config)?;
+        #[cfg(target_os = "linux")]
+        let log = sidecar.as_ref().and_then(Sidecar::log);
+
+        let rendezvous = rendezvous
+            .map(|conf| Rendezvous::register(&conf, &webasm, certs.clone(), &prvkey))
+            .transpose()
+            .context("failed to register with rendezvous service")
+            .classify(ErrorKind::Io)?
+            .map(Arc::new);
 
         let mut linker = Linker::new(&engine);
-        add_to_linker(&mut linker, |s| s).context("failed to setup linker and add WASI")?;
+        add_to_linker(&mut linker, |ctx: &mut Ctx| &mut ctx.wasi)
+            .context("failed to setup linker and add WASI")?;
+        if !clocks.realtime {
+            clock::deny_realtime(&mut linker)?;
+        }
+        appendlog::add_to_linker(&mut linker)?;
+        attestation::add_to_linker(&mut linker)?;
+        clock::add_to_linker(&mut linker)?;
+        capability::add_to_linker(&mut linker)?;
+        compat::add_to_linker(&mut linker)?;
+        config::add_to_linker(&mut linker)?;
+        cpu::add_to_linker(&mut linker)?;
+        keys::add_to_linker(&mut linker)?;
+        keyvalue::add_to_linker(&mut linker)?;
+        rendezvous::add_to_linker(&mut linker)?;
+        reexec::add_to_linker(&mut linker)?;
+        resilience::add_to_linker(&mut linker)?;
+        sandbox::add_to_linker(&mut linker)?;
+        socket::add_to_linker(&mut linker)?;
+        #[cfg(target_os = "linux")]
+        splice::add_to_linker(&mut linker)?;
+        #[cfg(unix)]
+        tty::add_to_linker(&mut linker)?;
+        #[cfg(unix)]
+        tmp::add_to_linker(&mut linker)?;
+        threads::add_to_linker(&mut linker)?;
+        #[cfg(feature = "plugins")]
+        crate::plugin::add_to_linker(&mut linker).classify(ErrorKind::Config)?;
+        threads::define_memory(&mut linker, &module).classify(ErrorKind::Config)?;
+
+        limits::check_compiled(&module, &limits).classify(ErrorKind::Config)?;
+
+        #[cfg(target_os = "linux")]
+        let loopback = Loopback::new(files.iter().filter_map(|file| match file {
+            File::Listen(file) => Some(file),
+            _ => None,
+        }))
+        .context("failed to setup loopback network")
+        .classify(ErrorKind::Io)?;
+        #[cfg(not(target_os = "linux"))]
+        let loopback: Loopback = ();
+        let offload = Offload::new(offload);
+
+        let chains = Arc::new(
+            Chains::open(files.iter().filter_map(|file| match file {
+                File::AppendLog(file) => Some(file),
+                _ => None,
+            }))
+            .classify(ErrorKind::Io)?,
+        );
+        let datasets = Arc::new(
+            Datasets::open(
+                files.iter().filter_map(|file| match file {
+                    File::Dataset(file) => Some(file),
+                    _ => None,
+                }),
+                datasets,
+            )
+            .classify(ErrorKind::Io)?,
+        );
+        let helpers = Helpers::open(
+            files.iter().filter_map(|file| match file {
+                File::Helper(helper) => Some((file.name(), helper)),
+                _ => None,
+            }),
+            helpers,
+        )
+        .classify(ErrorKind::Config)?;
+
+        let store = keyvalue
+            .map(|conf| Store::open(&conf))
+            .transpose()
+            .context("failed to open key-value store")
+            .classify(ErrorKind::Io)?
+            .map(Arc::new);
+        let values = Values::new(values);
+        let preopens = Arc::new(preopens);
+        let tap = tap
+            .map(|conf| Tap::open(&conf, pcapng))
+            .transpose()
+            .context("failed to open tap")
+            .classify(ErrorKind::Io)?
+            .flatten()
+            .map(Arc::new);
+        let chaos = chaos
+            .map(|conf| Chaos::open(&conf))
+            .transpose()
+            .context("failed to inject faults")
+            .classify(ErrorKind::Config)?
+            .flatten()
+            .map(Arc::new);
+
+        let environ = Environ::new(&files, args, env, secrets, process.cwd, tmp.is_some())?;
+        let threads = limits.threads.map(|max| Threads::new(max, stack.clone()));
+        let resilience = Arc::new(resilience::Shared::default());
+        let network = Arc::new(Policy::new(&network).classify(ErrorKind::Config)?);
+        let sandbox = Sandbox::new(&files);
+        let new_store = {
+            let memory = memory.clone();
+            let threads = threads.clone();
+            let sandbox = sandbox.clone();
+            move || {
+                let mut wstore = Store::new(
+                    &engine,
+                    Ctx {
+                        wasi: WasiCtxBuilder::new().build(),
+                        limits: Limiter::new(&limits, memory.clone()),
+                        capabilities: capabilities.clone(),
+                        personality: personality.clone(),
+                        steward: steward.clone(),
+                        rendezvous: rendezvous.clone(),
+                        resilience: Resilience::new(resilience.clone()),
+                        sockets: Sockets::new(network.clone()),
+                        appendlog: Default::default(),
+                        config: values.clone(),
+                        preopens: preopens.clone(),
+                        keyvalue: Buckets::new(store.clone()),
+                        #[cfg(target_os = "linux")]
+                        splice: Default::default(),
+                        #[cfg(unix)]
+                        tty: Default::default(),
+                        #[cfg(unix)]
+                        tmp: None,
+                        #[cfg(target_os = "linux")]
+                        log: log.clone(),
+                        tap: tap.clone(),
+                        chaos: chaos.clone(),
+                        threads: threads.clone(),
+                        sandbox: sandbox.clone(),
+                    },
+                );
+                wstore.limiter(|ctx| &mut ctx.limits);
+                wstore
+            }
+        };
+        sandbox.start(linker.clone(), Box::new(new_store.clone()));
 
-        let mut wstore = Store::new(&engine, WasiCtxBuilder::new().build());
+        if let Some(threads) = &threads {
+            let pre = linker
+                .instantiate_pre(new_store(), &module)
+                .context("failed to link module")?;
+            let new_store = new_store.clone();
+            let files = files.clone();
+            let memory = memory.clone();
+            let chains = chains.clone();
+            let datasets = datasets.clone();
+            let prvkey = prvkey.clone();
+            let environ = environ.clone();
+            #[cfg(unix)]
+            let tmpfs = tmpfs.clone();
+            threads.start(
+                pre,
+                Box::new(move || {
+                    let loopback = Loopback::default();
+                    let mut wstore = new_store();
+                    for (fd, conf) in files.iter().enumerate() {
+                        if threads::shareable(conf) {
+                            let (file, caps) = open_file(
+                                conf,
+                                &loopback,
+                                &Offload::default(),
+                                &Helpers::default(),
+                                &memory,
+                                &chains,
+                                &datasets,
+                                &[],
+                                &prvkey,
+                            )?;
+                            insert_file(wstore.data_mut(), fd, conf, file, caps)?;
+                        }
+                    }
+                    #[cfg(unix)]
+                    preopen_tmp(wstore.data_mut(), tmpfs.clone())?;
+                    environ.push(&mut wstore.data_mut().wasi)?;
+                    Ok(wstore)
+                }),
+            );
+        }
+
+        if let Some(listen) = isolation::check(&isolation, &files).classify(ErrorKind::Config)? {
+            let pre = linker
+                .instantiate_pre(new_store(), &module)
+                .context("failed to link module")?;
+            let (listener, _) = open_file(
+                &files[listen],
+                &loopback,
+                &offload,
+                &helpers,
+                &memory,
+                &chains,
+                &datasets,
+                &certs,
+                &prvkey,
+            )?;
+            let stats = stats.unwrap_or_default();
+            return isolation::serve(&pre, listener, &stats, |conn| {
+                let mut conn = Some(conn);
+                let mut wstore = new_store();
+                for (fd, conf) in files.iter().enumerate() {
+                    let (file, caps) = if fd == listen {
+                        conn.take().unwrap()
+                    } else {
+                        open_file(
+                            conf,
+                            &loopback,
+                            &offload,
+                            &helpers,
+                            &memory,
+                            &chains,
+                            &datasets,
+                            &[],
+                            &prvkey,
+                        )?
+                    };
+                    insert_file(wstore.data_mut(), fd, conf, file, caps)?;
+                }
+                // Every instance starts with an empty temporary directory
+                #[cfg(unix)]
+                preopen_tmp(
+                    wstore.data_mut(),
+                    tmp.as_ref().map(|conf| Tmp::new(conf.size)),
+                )?;
+                environ.push(&mut wstore.data_mut().wasi)?;
+                Ok(wstore)
+            })
+            .map(|()| vec![]);
+        }
 
-        let module =
-            Module::from_binary(&engine, &webasm).context("failed to compile Wasm module")?;
+        let mut wstore = new_store();
         linker
             .module(&mut wstore, "", &module)
             .context("failed to link module")?;
 
-        let mut ctx = wstore.as_context_mut();
-        let ctx = ctx.data_mut();
-
-        let mut names = vec![];
-        for (fd, file) in files.iter().enumerate() {
-            names.push(file.name());
-            let (file, caps): (Box<dyn WasiFile>, _) = match file {
-                File::Null(..) => (Box::new(Null), FileCaps::all()),
-                File::Stdin(..) => stdio_file(stdin()),
-                File::Stdout(..) => stdio_file(stdout()),
-                File::Stderr(..) => stdio_file(stderr()),
-                File::Listen(file) => listen_file(file, certs.clone(), &prvkey)
-                    .context("failed to setup listening socket")?,
-                File::Connect(file) => connect_file(file, certs.clone(), &prvkey)
-                    .context("failed to setup connection stream")?,
-            };
-            let fd = fd.try_into().context("too many open files")?;
-            ctx.insert_file(fd, file, caps);
-        }
-        ctx.push_env("FD_COUNT", &names.len().to_string())
-            .context("failed to set environment variable `FD_COUNT`")?;
-        ctx.push_env("FD_NAMES", &names.join(":"))
-            .context("failed to set environment variable `FD_NAMES`")?;
-
-        for (k, v) in env {
-            ctx.push_env(&k, &v)
-                .context("failed to set environment variable `{k}`")?;
-        }
-
-        ctx.push_arg("main.wasm")
-            .context("failed to push argv[0]")?;
-        for arg in args {
-            ctx.push_arg(&arg).context("failed to push argument")?;
+        for (fd, conf) in files.iter().enumerate() {
+            let (file, caps) = open_file(
+                conf, &loopback, &offload, &helpers, &memory, &chains, &datasets, &certs, &prvkey,
+            )?;
+            insert_file(wstore.data_mut(), fd, conf, file, caps)?;
         }
+        #[cfg(unix)]
+        preopen_tmp(wstore.data_mut(), tmpfs)?;
+        environ.push(&mut wstore.data_mut().wasi)?;
 
         let func = linker
             .get_default(&mut wstore, "")
             .context("failed to get default function")?;
 
         let mut values = vec![Val::null(); func.ty(&wstore).results().len()];
-        if let Err(e) = func.call(wstore, Default::default(), &mut values) {
+        let res = match &stack {
+            Some(stack) => stack::run(stack, || func.call(wstore, Default::default(), &mut values))
+                .classify(ErrorKind::Resource)?,
+            None => func.call(wstore, Default::default(), &mut values),
+        };
+        if let Err(e) = clock::report() {
+            warn!("failed to query clock skews: {e}");
+        }
+        info!(
+            peak_memory = memory.usage().peak,
+            "memory high-water mark of the workload"
+        );
+        if let Err(e) = res {
             match e.downcast_ref::<Trap>().map(Trap::i32_exit_status) {
                 Some(Some(0)) => {} // function exited with a code of 0, treat as success
-                _ => bail!(e.context("failed to execute default function")),
+                Some(Some(status)) => bail!(Exit(status)),
+                _ => {
+                    let e = e.context(ErrorKind::Trap);
+                    let e = match memory.diagnostic() {
+                        Some(diagnostic) => e.context(diagnostic),
+                        None => e,
+                    };
+                    bail!(e.context("failed to execute default function"))
+                }
             }
         };
         Ok(values)
     }
 }
+
+/// Opens the file descriptor described by `conf`
+#[allow(clippy::too_many_arguments)]
+fn open_file(
+    conf: &File,
+    loopback: &Loopback,
+    offload: &Offload,
+    helpers: &Helpers,
+    memory: &Arc<Memory>,
+    chains: &Chains,
+    datasets: &Datasets,
+    certs: &[rustls::Certificate],
+    prvkey: &Zeroizing<Vec<u8>>,
+) -> anyhow::Result<(Box<dyn WasiFile>, FileCaps)> {
+    let (file, caps): (Box<dyn WasiFile>, _) = match conf {
+        File::Null(..) => (Box::new(Null), FileCaps::all()),
+        File::Stdin(..) => stdio_file(stdin()),
+        File::Stdout(..) => stdio_file(stdout()),
+        File::Stderr(..) => stdio_file(stderr()),
+        File::Listen(file) => listen_file(file, loopback, offload, certs.to_vec(), prvkey)
+            .context("failed to setup listening socket")
+            .classify(ErrorKind::Io)?,
+        File::Connect(file) => connect_file(file, loopback, certs.to_vec(), prvkey)
+            .context("failed to setup connection stream")
+            .classify(ErrorKind::Io)?,
+        #[cfg(target_os = "linux")]
+        File::Memory(..) => (Box::new(MemoryFile::new(memory.clone())), FileCaps::all()),
+        #[cfg(not(target_os = "linux"))]
+        File::Memory(..) => {
+            return Err(anyhow!("memory files are not supported on this platform"))
+                .classify(ErrorKind::Config)
+        }
+        File::AppendLog(file) => (Box::new(chains.file(file)?), AppendLog::caps()),
+        File::Dataset(file) => (Box::new(datasets.file(file)?), DatasetReader::caps()),
+        File::Helper(..) => helpers
+            .file(conf.name())
+            .context("failed to setup helper stream")
+            .classify(ErrorKind::Io)?,
+    };
+    let file: Box<dyn WasiFile> = match conf.pad() {
+        Some(..) if !conf.confidential() => {
+            return Err(anyhow!(
+                "`{}` is not confidential and cannot be padded",
+                conf.name()
+            ))
+            .classify(ErrorKind::Config)
+        }
+        #[cfg(target_os = "linux")]
+        Some(pad) => Box::new(
+            Padded::new(file, pad)
+                .with_context(|| format!("failed to pad `{}`", conf.name()))
+                .classify(ErrorKind::Config)?,
+        ),
+        #[cfg(not(target_os = "linux"))]
+        Some(..) => {
+            return Err(anyhow!("padded reads are not supported on this platform"))
+                .classify(ErrorKind::Config)
+        }
+        None => file,
+    };
+    Ok((file, caps))
+}
+
+/// Inserts the opened `file` described by `conf` as the file descriptor `fd` of the workload
+fn insert_file(
+    ctx: &mut Ctx,
+    fd: usize,
+    conf: &File,
+    file: Box<dyn WasiFile>,
+    caps: FileCaps,
+) -> anyhow::Result<()> {
+    let fd = fd.try_into().context("too many open files")?;
+    ctx.appendlog.insert(fd, file.as_ref());
+    #[cfg(target_os = "linux")]
+    if !conf.confidential() {
+        ctx.splice
+            .insert(fd, file.as_ref())
+            .with_context(|| format!("failed to mark `{}` as non-confidential", conf.name()))
+            .classify(ErrorKind::Io)?;
+    }
+    #[cfg(unix)]
+    match conf {
+        File::Stdin(..) => ctx.tty.insert(fd, libc::STDIN_FILENO),
+        File::Stdout(..) => ctx.tty.insert(fd, libc::STDOUT_FILENO),
+        File::Stderr(..) => ctx.tty.insert(fd, libc::STDERR_FILENO),
+        _ => {}
+    }
+    #[cfg(target_os = "linux")]
+    let file: Box<dyn WasiFile> = match (conf, &ctx.log) {
+        (File::Stdout(..) | File::Stderr(..), Some(log)) => Box::new(Tee::new(file, log.clone())),
+        _ => file,
+    };
+    let file: Box<dyn WasiFile> = match &ctx.chaos {
+        Some(chaos) if chaos.affects(conf) => Box::new(Chaotic::new(file, chaos.clone())),
+        _ => file,
+    };
+    let file: Box<dyn WasiFile> = match &ctx.tap {
+        Some(tap) if tap.taps(conf) => Box::new(Tapped::new(file, tap.clone(), conf.name())),
+        _ => file,
+    };
+    ctx.wasi.insert_file(fd, file, caps);
+    Ok(())
+}
+
+/// Preopens the temporary directory `tmp` of the workload at `/tmp`
+///
+/// Must be called after all file descriptors have been inserted, as it takes the next free one
+/// from 3 onwards.
+#[cfg(unix)]
+fn preopen_tmp(ctx: &mut Ctx, tmp: Option<Tmp>) -> anyhow::Result<()> {
+    if let Some(tmp) = tmp {
+        ctx.wasi
+            .push_preopened_dir(tmp.root(), TMP)
+            .context("failed to preopen temporary directory")?;
+        ctx.tmp = Some(tmp);
+    }
+    Ok(())
+}
+
+/// The arguments and environment variables of the workload
+#[derive(Clone)]
+struct Environ {
+    args: Vec<String>,
+    vars: Vec<(String, String)>,
+}
+
+impl Environ {
+    fn new(
+        files: &[File],
+        args: Vec<String>,
+        env: HashMap<String, String>,
+        secrets: HashMap<String, String>,
+        cwd: Option<String>,
+        tmp: bool,
+    ) -> anyhow::Result<Self> {
+        let names: Vec<_> = files.iter().map(File::name).collect();
+        let mut vars = vec![
+            ("FD_COUNT".into(), names.len().to_string()),
+            ("FD_NAMES".into(), names.join(":")),
+        ];
+
+        for (k, v) in secrets {
+            if env.contains_key(&k) {
+                return Err(anyhow!(
+                    "secret `{k}` conflicts with an environment variable of the same name"
+                ))
+                .classify(ErrorKind::Config);
+            }
+            vars.push((k, v));
+        }
+
+        if let Some(cwd) = cwd {
+            vars.push((process::PWD.into(), cwd));
+        }
+
+        if tmp && !env.contains_key("TMPDIR") {
+            vars.push(("TMPDIR".into(), TMP.into()));
+        }
+
+        vars.extend(env);
+
+        let args = iter::once("main.wasm".into()).chain(args).collect();
+        Ok(Self { args, vars })
+    }
+
+    fn push(&self, ctx: &mut WasiCtx) -> anyhow::Result<()> {
+        for (k, v) in &self.vars {
+            ctx.push_env(k, v)
+                .with_context(|| format!("failed to set environment variable `{k}`"))?;
+        }
+        for arg in &self.args {
+            ctx.push_arg(arg).context("failed to push argument")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,498 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Loopback networking inside the Keep
+//!
+//! Listen sockets on a loopback address are never bound on the host. Connect sockets to the port
+//! of such a listener on a loopback address are connected to it through buffers in the memory of
+//! the Keep, so the data never reaches the host. Connect sockets to other loopback ports are
+//! still connected to services on the host. TLS is skipped on these connections, since it is transparent
+//! to the workload anyway.
+//!
+//! The readiness of each endpoint is signaled by an `eventfd`, so the workload can poll the
+//! sockets like any other. The host only observes the wakeups, but never the data.
+
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, IoSlice, IoSliceMut};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use super::super::io::event::Event;
+
+use enarx_config::{ConnectFile, ListenFile};
+use io_lifetimes::AsFd;
+use wasi_common::file::{FdFlags, FileType, RiFlags, RoFlags, SdFlags, SiFlags};
+use wasi_common::{Error, ErrorExt, ErrorKind, WasiFile};
+
+/// Returns whether `host` names the loopback interface
+pub fn is_loopback(host: &str) -> bool {
+    host == "localhost"
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map_or(false, |ip| ip.is_loopback())
+}
+
+/// Data of one direction of a connection
+#[derive(Debug, Default)]
+struct Pipe {
+    buf: VecDeque<u8>,
+    /// The writer shut down
+    eof: bool,
+    /// The reader shut down
+    broken: bool,
+}
+
+/// One direction of a connection with the readiness signal of its reader
+#[derive(Debug)]
+struct Half {
+    pipe: Mutex<Pipe>,
+    event: Event,
+}
+
+impl Half {
+    fn new() -> io::Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            pipe: Default::default(),
+            event: Event::new()?,
+        }))
+    }
+
+    fn push(&self, bufs: &[IoSlice<'_>]) -> Result<u64, Error> {
+        let mut pipe = self.pipe.lock().unwrap();
+        if pipe.broken || pipe.eof {
+            return Err(io::Error::from_raw_os_error(libc::EPIPE).into());
+        }
+        let ready = !pipe.buf.is_empty();
+        for buf in bufs {
+            pipe.buf.extend(buf.iter());
+        }
+        if !ready && !pipe.buf.is_empty() {
+            self.event.set();
+        }
+        Ok(bufs.iter().map(|buf| buf.len() as u64).sum())
+    }
+
+    /// Copies the buffered data to `bufs` and consumes it, if `consume`
+    ///
+    /// Returns `None`, if there is no data yet.
+    fn pull(&self, bufs: &mut [IoSliceMut<'_>], consume: bool) -> Option<u64> {
+        let mut pipe = self.pipe.lock().unwrap();
+        if pipe.buf.is_empty() {
+            return pipe.eof.then_some(0);
+        }
+
+        let mut data = pipe.buf.iter();
+        let mut n = 0;
+        for buf in bufs.iter_mut() {
+            for (dst, src) in buf.iter_mut().zip(&mut data) {
+                *dst = *src;
+                n += 1;
+            }
+        }
+        if consume {
+            pipe.buf.drain(..n);
+            if pipe.buf.is_empty() && !pipe.eof {
+                self.event.clear();
+            }
+        }
+        Some(n as _)
+    }
+
+    fn len(&self) -> usize {
+        self.pipe.lock().unwrap().buf.len()
+    }
+
+    fn shutdown_write(&self) {
+        let mut pipe = self.pipe.lock().unwrap();
+        pipe.eof = true;
+        self.event.set();
+    }
+
+    fn shutdown_read(&self) {
+        let mut pipe = self.pipe.lock().unwrap();
+        pipe.broken = true;
+        pipe.buf.clear();
+    }
+}
+
+/// An endpoint of a loopback connection
+#[derive(Debug)]
+pub struct Stream {
+    rx: Arc<Half>,
+    tx: Arc<Half>,
+    nonblocking: bool,
+}
+
+impl Stream {
+    /// Returns the two endpoints of a new connection
+    fn pair() -> io::Result<(Self, Self)> {
+        let (a, b) = (Half::new()?, Half::new()?);
+        let one = Self {
+            rx: a.clone(),
+            tx: b.clone(),
+            nonblocking: false,
+        };
+        let two = Self {
+            rx: b,
+            tx: a,
+            nonblocking: false,
+        };
+        Ok((one, two))
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.tx.shutdown_write();
+        self.rx.shutdown_read();
+    }
+}
+
+impl From<Stream> for Box<dyn WasiFile> {
+    fn from(value: Stream) -> Self {
+        Box::new(value)
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Stream {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd<'_>> {
+        Some(self.rx.event.as_fd())
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::SocketStream)
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        Ok(if self.nonblocking {
+            FdFlags::NONBLOCK
+        } else {
+            FdFlags::empty()
+        })
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        if fdflags == FdFlags::NONBLOCK {
+            self.nonblocking = true;
+        } else if fdflags.is_empty() {
+            self.nonblocking = false;
+        } else {
+            return Err(Error::invalid_argument().context("cannot set anything else than NONBLOCK"));
+        }
+        Ok(())
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        loop {
+            match self.rx.pull(bufs, true) {
+                Some(n) => return Ok(n),
+                None if self.nonblocking => return Err(ErrorKind::WouldBlk.into()),
+                None => self.rx.event.wait()?,
+            }
+        }
+    }
+
+    async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        self.tx.push(bufs)
+    }
+
+    async fn peek(&mut self, buf: &mut [u8]) -> Result<u64, Error> {
+        Ok(self
+            .rx
+            .pull(&mut [IoSliceMut::new(buf)], false)
+            .unwrap_or(0))
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(self.rx.len() as _)
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        match self.rx.pull(&mut [], false) {
+            Some(_) => Ok(()),
+            None => Err(Error::io()),
+        }
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn sock_recv<'a>(
+        &mut self,
+        ri_data: &mut [IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        let n = if ri_flags == RiFlags::RECV_PEEK {
+            self.rx.pull(ri_data, false).unwrap_or(0)
+        } else if ri_flags.is_empty() {
+            self.read_vectored(ri_data).await?
+        } else {
+            return Err(Error::not_supported());
+        };
+        Ok((n, RoFlags::empty()))
+    }
+
+    async fn sock_send<'a>(
+        &mut self,
+        si_data: &[IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        if si_flags != SiFlags::empty() {
+            return Err(Error::not_supported());
+        }
+        self.write_vectored(si_data).await
+    }
+
+    async fn sock_shutdown(&mut self, how: SdFlags) -> Result<(), Error> {
+        if how.is_empty() || !(SdFlags::RD | SdFlags::WR).contains(how) {
+            return Err(Error::invalid_argument());
+        }
+        if how.contains(SdFlags::RD) {
+            self.rx.shutdown_read();
+        }
+        if how.contains(SdFlags::WR) {
+            self.tx.shutdown_write();
+        }
+        Ok(())
+    }
+}
+
+/// Pending connections of a listener
+#[derive(Debug)]
+struct Backlog {
+    queue: Mutex<VecDeque<Stream>>,
+    event: Event,
+}
+
+/// A listener on a loopback port
+#[derive(Debug)]
+pub struct Listener {
+    backlog: Arc<Backlog>,
+    nonblocking: bool,
+}
+
+impl From<Listener> for Box<dyn WasiFile> {
+    fn from(value: Listener) -> Self {
+        Box::new(value)
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Listener {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd<'_>> {
+        Some(self.backlog.event.as_fd())
+    }
+
+    async fn sock_accept(&mut self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        let mut stream = loop {
+            let mut queue = self.backlog.queue.lock().unwrap();
+            if let Some(stream) = queue.pop_front() {
+                if queue.is_empty() {
+                    self.backlog.event.clear();
+                }
+                break stream;
+            }
+            drop(queue);
+
+            if self.nonblocking {
+                return Err(ErrorKind::WouldBlk.into());
+            }
+            self.backlog.event.wait()?;
+        };
+        stream.set_fdflags(fdflags).await?;
+        Ok(Box::new(stream))
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::SocketStream)
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        Ok(if self.nonblocking {
+            FdFlags::NONBLOCK
+        } else {
+            FdFlags::empty()
+        })
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        if fdflags == FdFlags::NONBLOCK {
+            self.nonblocking = true;
+        } else if fdflags.is_empty() {
+            self.nonblocking = false;
+        } else {
+            return Err(Error::invalid_argument().context("cannot set anything else than NONBLOCK"));
+        }
+        Ok(())
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(1)
+    }
+}
+
+/// The loopback network of the Keep
+#[derive(Debug, Default)]
+pub struct Loopback(HashMap<u16, Arc<Backlog>>);
+
+impl Loopback {
+    /// Creates the loopback network of the listen sockets on a loopback address in `files`
+    ///
+    /// All the listeners exist up front, so connect sockets may precede their listener.
+    pub fn new<'a>(files: impl IntoIterator<Item = &'a ListenFile>) -> io::Result<Self> {
+        let mut ports = HashMap::new();
+        for file in files {
+            let (addr, port) = match file {
+                ListenFile::Tcp { addr, port, .. } | ListenFile::Tls { addr, port, .. } => {
+                    (addr, *port)
+                }
+            };
+            if is_loopback(addr) && !ports.contains_key(&port) {
+                let backlog = Backlog {
+                    queue: Default::default(),
+                    event: Event::new()?,
+                };
+                ports.insert(port, Arc::new(backlog));
+            }
+        }
+        Ok(Self(ports))
+    }
+
+    /// Returns the listener of `file`, if it listens on a loopback address
+    pub fn listen(&self, file: &ListenFile) -> Option<io::Result<Listener>> {
+        let (addr, port) = match file {
+            ListenFile::Tcp { addr, port, .. } | ListenFile::Tls { addr, port, .. } => (addr, port),
+        };
+        if !is_loopback(addr) {
+            return None;
+        }
+        let backlog = self.0.get(port)?.clone();
+        if Arc::strong_count(&backlog) > 2 {
+            return Some(Err(io::ErrorKind::AddrInUse.into()));
+        }
+        Some(Ok(Listener {
+            backlog,
+            nonblocking: false,
+        }))
+    }
+
+    /// Returns a stream connected to the listener of `file`, if it connects to the port of a
+    /// listener on a loopback address
+    pub fn connect(&self, file: &ConnectFile) -> Option<io::Result<Stream>> {
+        let (host, port) = match file {
+            ConnectFile::Tcp { host, port, .. } | ConnectFile::Tls { host, port, .. } => {
+                (host, port)
+            }
+        };
+        if !is_loopback(host) {
+            return None;
+        }
+        let backlog = self.0.get(port)?;
+        Some(Stream::pair().map(|(client, server)| {
+            let mut queue = backlog.queue.lock().unwrap();
+            if queue.is_empty() {
+                backlog.event.set();
+            }
+            queue.push_back(server);
+            client
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future::Future;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        wiggle::run_in_dummy_executor(future).unwrap()
+    }
+
+    fn listen(port: u16) -> ListenFile {
+        toml::from_str(&format!(
+            "name = \"listen\"\nprot = \"tcp\"\naddr = \"127.0.0.1\"\nport = {port}"
+        ))
+        .unwrap()
+    }
+
+    fn connect(host: &str, port: u16) -> ConnectFile {
+        toml::from_str(&format!("prot = \"tls\"\nhost = \"{host}\"\nport = {port}")).unwrap()
+    }
+
+    #[test]
+    fn loopback() {
+        assert!(is_loopback("localhost"));
+        assert!(is_loopback("127.0.0.1"));
+        assert!(is_loopback("127.1.2.3"));
+        assert!(is_loopback("::1"));
+        assert!(is_loopback("[::1]"));
+        assert!(!is_loopback("::"));
+        assert!(!is_loopback("0.0.0.0"));
+        assert!(!is_loopback("example.com"));
+    }
+
+    #[test]
+    fn connect_before_accept() {
+        let file = listen(8080);
+        let net = Loopback::new([&file]).unwrap();
+
+        let mut client = net.connect(&connect("localhost", 8080)).unwrap().unwrap();
+        assert!(net.connect(&connect("example.com", 8080)).is_none());
+        assert!(net.connect(&connect("127.0.0.1", 8081)).is_none());
+
+        let mut listener = net.listen(&file).unwrap().unwrap();
+        assert!(net.listen(&file).unwrap().is_err());
+        block_on(listener.set_fdflags(FdFlags::NONBLOCK)).unwrap();
+        let mut server = block_on(listener.sock_accept(FdFlags::NONBLOCK)).unwrap();
+        assert!(block_on(listener.sock_accept(FdFlags::NONBLOCK)).is_err());
+
+        let mut buf = [0u8; 8];
+        assert!(block_on(server.read_vectored(&mut [IoSliceMut::new(&mut buf)])).is_err());
+        assert!(block_on(server.readable()).is_err());
+
+        let n = block_on(client.write_vectored(&[IoSlice::new(b"ping"), IoSlice::new(b"!")]));
+        assert_eq!(n.unwrap(), 5);
+        assert!(block_on(server.readable()).is_ok());
+        assert_eq!(block_on(server.num_ready_bytes()).unwrap(), 5);
+        assert_eq!(block_on(server.peek(&mut buf)).unwrap(), 5);
+        let n = block_on(server.read_vectored(&mut [IoSliceMut::new(&mut buf[..4])])).unwrap();
+        assert_eq!(&buf[..n as usize], b"ping");
+        let n = block_on(server.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap();
+        assert_eq!(&buf[..n as usize], b"!");
+
+        drop(client);
+        let n = block_on(server.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap();
+        assert_eq!(n, 0);
+        assert!(block_on(server.write_vectored(&[IoSlice::new(b"pong")])).is_err());
+    }
+
+    #[test]
+    fn blocking() {
+        let file = listen(8080);
+        let net = Loopback::new([&file]).unwrap();
+        let mut listener = net.listen(&file).unwrap().unwrap();
+
+        let mut client = net.connect(&connect("::1", 8080)).unwrap().unwrap();
+        let reader = std::thread::spawn(move || {
+            let mut server = block_on(listener.sock_accept(FdFlags::empty())).unwrap();
+            let mut buf = [0u8; 4];
+            let n = block_on(server.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap();
+            buf[..n as usize].to_vec()
+        });
+        block_on(client.write_vectored(&[IoSlice::new(b"pong")])).unwrap();
+        assert_eq!(reader.join().unwrap(), b"pong");
+    }
+}
@@ -2,8 +2,24 @@
 
 //! Networking functionality for keeps
 
+#[cfg(target_os = "linux")]
+pub mod loopback;
+pub mod offload;
+pub mod policy;
+pub mod socket;
+pub mod ticket;
 pub mod tls;
 
+#[cfg(target_os = "linux")]
+pub use loopback::Loopback;
+pub use offload::Offload;
+pub use policy::Policy;
+pub use socket::Sockets;
+
+/// Without loopback network in the Keep, loopback sockets are left to the host
+#[cfg(not(target_os = "linux"))]
+pub type Loopback = ();
+
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::ops::Deref;
 use std::sync::Arc;
@@ -18,7 +34,7 @@ use rustls::cipher_suite::{
 use rustls::kx_group::{SECP256R1, SECP384R1, X25519};
 use rustls::version::TLS13;
 use rustls::{Certificate, PrivateKey, RootCertStore};
-use wasi_common::file::FileCaps;
+use wasi_common::file::{FdFlags, FileCaps};
 use wasi_common::WasiFile;
 use zeroize::Zeroizing;
 
@@ -29,11 +45,20 @@ static DEFAULT_TLS_KX_GROUPS: Lazy<[&'static rustls::SupportedKxGroup; 3]> =
     Lazy::new(|| [&X25519, &SECP384R1, &SECP256R1]);
 
 static DEFAULT_TLS_CIPHER_SUITES: Lazy<[rustls::SupportedCipherSuite; 3]> = Lazy::new(|| {
-    [
-        TLS13_AES_256_GCM_SHA384,
-        TLS13_AES_128_GCM_SHA256,
-        TLS13_CHACHA20_POLY1305_SHA256,
-    ]
+    // Without AES-NI, ChaCha20-Poly1305 is faster and not prone to cache timing attacks
+    if super::cpu::features() & super::cpu::AES != 0 {
+        [
+            TLS13_AES_256_GCM_SHA384,
+            TLS13_AES_128_GCM_SHA256,
+            TLS13_CHACHA20_POLY1305_SHA256,
+        ]
+    } else {
+        [
+            TLS13_CHACHA20_POLY1305_SHA256,
+            TLS13_AES_256_GCM_SHA384,
+            TLS13_AES_128_GCM_SHA256,
+        ]
+    }
 });
 
 static LISTEN_CAPS: Lazy<FileCaps> = Lazy::new(|| {
@@ -50,9 +75,27 @@ static CONNECT_CAPS: Lazy<FileCaps> = Lazy::new(|| {
 
 pub fn listen_file(
     file: &ListenFile,
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))] loopback: &Loopback,
+    offload: &Offload,
     certs: Vec<Certificate>,
     key: &Zeroizing<Vec<u8>>,
 ) -> Result<(Box<dyn WasiFile>, FileCaps)> {
+    if let ListenFile::Tls {
+        name,
+        confidential: false,
+        ..
+    } = file
+    {
+        let tcp = TcpListener::from_std(offload.take(name)?);
+        return Ok((wasmtime_wasi::net::Socket::from(tcp).into(), *LISTEN_CAPS));
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(listener) = loopback.listen(file) {
+        let listener = listener.context("failed to listen on loopback port")?;
+        return Ok((listener.into(), *LISTEN_CAPS));
+    }
+
     let (addr, port) = match file {
         ListenFile::Tcp { addr, port, .. } | ListenFile::Tls { addr, port, .. } => (addr, port),
     };
@@ -60,24 +103,38 @@ pub fn listen_file(
     let tcp = TcpListener::from_std(tcp);
     let file = match file {
         ListenFile::Tcp { .. } => wasmtime_wasi::net::Socket::from(tcp).into(),
-        ListenFile::Tls { .. } => {
-            let cfg = rustls::ServerConfig::builder()
+        ListenFile::Tls { name, .. } => {
+            let mut cfg = rustls::ServerConfig::builder()
                 .with_cipher_suites(DEFAULT_TLS_CIPHER_SUITES.deref())
                 .with_kx_groups(DEFAULT_TLS_KX_GROUPS.deref())
                 .with_protocol_versions(DEFAULT_TLS_PROTOCOL_VERSIONS.deref())?
                 .with_no_client_auth() // TODO: https://github.com/enarx/enarx/issues/1547
                 .with_single_cert(certs, PrivateKey(key.deref().clone()))?;
+            cfg.ticketer = Arc::new(ticket::Ticketer::new(ticket::secret()?, name.deref()));
             tls::Listener::new(tcp, Arc::new(cfg)).into()
         }
     };
     Ok((file, *LISTEN_CAPS))
 }
 
+/// Accepts a connection on the `listener` returned by [`listen_file`]
+pub fn accept(listener: &mut dyn WasiFile) -> Result<(Box<dyn WasiFile>, FileCaps)> {
+    let stream = wiggle::run_in_dummy_executor(listener.sock_accept(FdFlags::empty()))??;
+    Ok((stream, *CONNECT_CAPS))
+}
+
 pub fn connect_file(
     file: &ConnectFile,
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))] loopback: &Loopback,
     certs: Vec<Certificate>,
     key: &Zeroizing<Vec<u8>>,
 ) -> Result<(Box<dyn WasiFile>, FileCaps)> {
+    #[cfg(target_os = "linux")]
+    if let Some(stream) = loopback.connect(file) {
+        let stream = stream.context("failed to connect to loopback endpoint")?;
+        return Ok((stream.into(), *CONNECT_CAPS));
+    }
+
     let (host, port) = match &file {
         ConnectFile::Tcp { host, port, .. } | ConnectFile::Tls { host, port, .. } => (host, port),
     };
@@ -95,25 +152,30 @@ pub fn connect_file(
     let file = match file {
         ConnectFile::Tcp { .. } => wasmtime_wasi::net::Socket::from(tcp).into(),
         ConnectFile::Tls { .. } => {
-            let mut server_roots = RootCertStore::empty();
-            server_roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
-                |ta| {
-                    rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
-                        ta.subject,
-                        ta.spki,
-                        ta.name_constraints,
-                    )
-                },
-            ));
-            let cfg = rustls::ClientConfig::builder()
-                .with_cipher_suites(DEFAULT_TLS_CIPHER_SUITES.deref())
-                .with_kx_groups(DEFAULT_TLS_KX_GROUPS.deref())
-                .with_protocol_versions(DEFAULT_TLS_PROTOCOL_VERSIONS.deref())?
-                .with_root_certificates(server_roots)
-                .with_single_cert(certs, PrivateKey(key.deref().clone()))?;
-
-            tls::Stream::connect(tcp, host, Arc::new(cfg))?.into()
+            tls::Stream::connect(tcp, host, Arc::new(client_config(certs, key)?))?.into()
         }
     };
     Ok((file, *CONNECT_CAPS))
 }
+
+/// Returns the TLS client configuration authenticating the Keep with `certs` and `key`
+pub fn client_config(
+    certs: Vec<Certificate>,
+    key: &Zeroizing<Vec<u8>>,
+) -> Result<rustls::ClientConfig> {
+    let mut server_roots = RootCertStore::empty();
+    server_roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+    let cfg = rustls::ClientConfig::builder()
+        .with_cipher_suites(DEFAULT_TLS_CIPHER_SUITES.deref())
+        .with_kx_groups(DEFAULT_TLS_KX_GROUPS.deref())
+        .with_protocol_versions(DEFAULT_TLS_PROTOCOL_VERSIONS.deref())?
+        .with_root_certificates(server_roots)
+        .with_single_cert(certs, PrivateKey(key.deref().clone()))?;
+    Ok(cfg)
+}
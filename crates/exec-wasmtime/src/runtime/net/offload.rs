@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Listen sockets, whose TLS is terminated by the host
+//!
+//! For a `listen` file descriptor with `prot = "tls"` and `confidential = false`, the host
+//! accepts the connections on the configured address, terminates TLS with the certificate of the
+//! operator and forwards the plaintext to an internal listen socket on its loopback interface.
+//! The host passes the internal listen socket to the Keep, which the workload gets instead of a
+//! TLS listen socket. The Keep claims these listen sockets in its preopens, so relying parties
+//! can tell, which endpoints are observable by the host.
+
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+
+/// The internal listen sockets passed by the host by name
+#[derive(Debug, Default)]
+pub struct Offload(Mutex<HashMap<String, TcpListener>>);
+
+impl Offload {
+    pub fn new(listeners: HashMap<String, TcpListener>) -> Self {
+        Self(Mutex::new(listeners))
+    }
+
+    /// Takes the internal listen socket of the non-confidential `listen` file descriptor `name`
+    pub fn take(&self, name: &str) -> anyhow::Result<TcpListener> {
+        self.0.lock().unwrap().remove(name).ok_or_else(|| {
+            anyhow!(
+                "the host does not terminate TLS of the non-confidential listen socket `{name}`"
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let offload = Offload::new([("public".to_string(), listener)].into());
+        assert!(offload.take("public").is_ok());
+        assert!(offload.take("public").is_err());
+        assert!(offload.take("other").is_err());
+    }
+}
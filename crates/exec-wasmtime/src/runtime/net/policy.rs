@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Network policy of the workload
+//!
+//! The `[network]` section of the Enarx.toml declares, whether the workload may resolve host
+//! names, and the endpoints, which it may connect or send to with the sockets opened at runtime.
+//! Anything else is denied.
+
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{bail, Context, Result};
+use enarx_config::{Network, Protocol};
+
+/// Returns the IPv4 address of an IPv4-mapped IPv6 `addr` or `addr` itself
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+        IpAddr::V4(..) => addr,
+    }
+}
+
+/// IP network of an address and a prefix length
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Net {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Net {
+    /// Parses an IP address or a network in CIDR notation, e.g. `10.0.0.0/8`
+    fn parse(net: &str) -> Result<Self> {
+        let (addr, prefix) = match net.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (net, None),
+        };
+        let addr = canonical(addr.parse().context("invalid IP address")?);
+        let max = match addr {
+            IpAddr::V4(..) => 32,
+            IpAddr::V6(..) => 128,
+        };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().context("invalid prefix length")?,
+            None => max,
+        };
+        if prefix > max {
+            bail!("prefix length {prefix} exceeds {max}");
+        }
+        Ok(Self { addr, prefix })
+    }
+
+    /// Returns, whether the network contains `addr`
+    fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, canonical(addr)) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Endpoints allowed with a protocol
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Rule {
+    prot: Protocol,
+    net: Option<Net>,
+    port: Option<u16>,
+}
+
+/// The network policy of the workload
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    resolve: bool,
+    rules: Vec<Rule>,
+}
+
+impl Policy {
+    /// Parses the `network` section of the Enarx.toml
+    pub fn new(network: &Network) -> Result<Self> {
+        let rules = network
+            .allow
+            .iter()
+            .map(|endpoint| {
+                let net = endpoint
+                    .addr
+                    .as_deref()
+                    .map(|addr| {
+                        Net::parse(addr)
+                            .with_context(|| format!("invalid network endpoint address `{addr}`"))
+                    })
+                    .transpose()?;
+                Ok(Rule {
+                    prot: endpoint.prot,
+                    net,
+                    port: endpoint.port,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            resolve: network.resolve,
+            rules,
+        })
+    }
+
+    /// Returns, whether the workload may resolve host names
+    pub fn resolve(&self) -> bool {
+        self.resolve
+    }
+
+    /// Returns, whether the workload may reach `addr` with `prot`
+    pub fn allows(&self, prot: Protocol, addr: SocketAddr) -> bool {
+        self.rules.iter().any(|rule| {
+            rule.prot == prot
+                && rule.net.map_or(true, |net| net.contains(addr.ip()))
+                && rule.port.map_or(true, |port| port == addr.port())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use enarx_config::Endpoint;
+
+    fn endpoint(prot: Protocol, addr: Option<&str>, port: Option<u16>) -> Endpoint {
+        Endpoint {
+            prot,
+            addr: addr.map(Into::into),
+            port,
+        }
+    }
+
+    #[test]
+    fn allows() {
+        let network = Network {
+            resolve: false,
+            allow: vec![
+                endpoint(Protocol::Udp, Some("10.0.0.53"), Some(53)),
+                endpoint(Protocol::Tcp, Some("10.0.0.0/8"), None),
+                endpoint(Protocol::Tcp, Some("fd00::/8"), Some(443)),
+                endpoint(Protocol::Tcp, None, Some(8443)),
+            ],
+        };
+        let policy = Policy::new(&network).unwrap();
+        let allows = |prot, addr: &str| policy.allows(prot, addr.parse().unwrap());
+
+        assert!(!policy.resolve());
+        assert!(allows(Protocol::Udp, "10.0.0.53:53"));
+        assert!(!allows(Protocol::Udp, "10.0.0.53:54"));
+        assert!(!allows(Protocol::Udp, "10.0.0.54:53"));
+        assert!(allows(Protocol::Tcp, "10.1.2.3:80"));
+        assert!(allows(Protocol::Tcp, "[::ffff:10.1.2.3]:80"));
+        assert!(!allows(Protocol::Tcp, "11.0.0.1:80"));
+        assert!(!allows(Protocol::Udp, "10.1.2.3:80"));
+        assert!(allows(Protocol::Tcp, "[fd12::1]:443"));
+        assert!(!allows(Protocol::Tcp, "[fe80::1]:443"));
+        assert!(allows(Protocol::Tcp, "192.0.2.1:8443"));
+
+        let policy = Policy::default();
+        assert!(!policy.allows(Protocol::Tcp, "10.0.0.1:80".parse().unwrap()));
+    }
+
+    #[test]
+    fn invalid() {
+        for addr in ["10.0.0.0/33", "fd00::/129", "example.com", "10.0.0.0/x"] {
+            let network = Network {
+                resolve: true,
+                allow: vec![endpoint(Protocol::Tcp, Some(addr), None)],
+            };
+            assert!(Policy::new(&network).is_err(), "{addr}");
+        }
+        assert_eq!(
+            Net::parse("0.0.0.0/0").unwrap(),
+            Net {
+                addr: "0.0.0.0".parse().unwrap(),
+                prefix: 0
+            }
+        );
+        assert!(Net::parse("0.0.0.0/0")
+            .unwrap()
+            .contains("192.0.2.1".parse().unwrap()));
+    }
+}
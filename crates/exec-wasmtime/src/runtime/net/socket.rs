@@ -0,0 +1,351 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sockets opened by the workload at runtime
+//!
+//! Beyond the listen and connect sockets pre-opened according to the Enarx.toml, the workload
+//! imports functions modeled on the wasi-sockets proposal from the `enarx` module: `resolve`
+//! resolves host names, `tcp_connect` opens outgoing TCP connections as new WASI file
+//! descriptors, and `udp_bind`, `udp_send`, `udp_recv` and `udp_close` exchange UDP datagrams.
+//! Addresses are passed as text, e.g. `192.0.2.1:53` or `[2001:db8::1]:443`. The sockets are
+//! ordinary sockets of the Keep, whose calls are proxied to the host by the shim, and every
+//! destination is checked against the [`Policy`] of the workload. Datagrams from peers, which
+//! the policy does not allow, are dropped.
+
+use super::super::errno::{self, negate};
+use super::super::keys::{memory, read, read_str, write};
+use super::super::Ctx;
+use super::policy::Policy;
+use super::CONNECT_CAPS;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use enarx_config::Protocol;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+
+/// Maximum number of UDP sockets of an instance
+pub const MAX_UDP_SOCKETS: usize = 64;
+
+/// Minimum size of the buffer receiving the address of the peer of a datagram
+pub const PEER_LEN: u32 = 64;
+
+/// Maximum size of a UDP datagram
+const MAX_DATAGRAM: usize = 65535;
+
+/// The sockets of an instance of the workload
+#[derive(Debug)]
+pub struct Sockets {
+    policy: Arc<Policy>,
+    udp: HashMap<u32, UdpSocket>,
+}
+
+impl Sockets {
+    /// Creates the sockets of a new instance restricted by `policy`
+    pub fn new(policy: Arc<Policy>) -> Self {
+        Self {
+            policy,
+            udp: HashMap::new(),
+        }
+    }
+
+    /// Resolves `host` and returns its IP addresses in order, one per line
+    fn resolve(&self, host: &str) -> Result<String, Errno> {
+        if !self.policy.resolve() {
+            return Err(Errno::Acces);
+        }
+        let addrs = (host, 0).to_socket_addrs().map_err(|_| Errno::Noent)?;
+        let mut ips: Vec<IpAddr> = vec![];
+        for addr in addrs {
+            if !ips.contains(&addr.ip()) {
+                ips.push(addr.ip());
+            }
+        }
+        Ok(ips.iter().map(|ip| format!("{ip}\n")).collect())
+    }
+
+    /// Connects to `addr` with TCP
+    fn connect(&self, addr: SocketAddr) -> Result<TcpStream, Errno> {
+        if !self.policy.allows(Protocol::Tcp, addr) {
+            return Err(Errno::Acces);
+        }
+        TcpStream::connect(addr).map_err(|e| errno::from_io(&e))
+    }
+
+    /// Binds a new UDP socket to the local `addr` and returns its handle
+    ///
+    /// Binding to a fixed port requires the policy to allow UDP for `addr`.
+    fn bind(&mut self, addr: SocketAddr) -> Result<u32, Errno> {
+        if addr.port() != 0 && !self.policy.allows(Protocol::Udp, addr) {
+            return Err(Errno::Acces);
+        }
+        if self.udp.len() >= MAX_UDP_SOCKETS {
+            return Err(Errno::Nfile);
+        }
+        let socket = UdpSocket::bind(addr).map_err(|e| errno::from_io(&e))?;
+        let handle = (0..).find(|handle| !self.udp.contains_key(handle)).unwrap();
+        self.udp.insert(handle, socket);
+        Ok(handle)
+    }
+
+    /// Returns the UDP socket `handle`
+    fn udp(&self, handle: u32) -> Result<&UdpSocket, Errno> {
+        self.udp.get(&handle).ok_or(Errno::Badf)
+    }
+
+    /// Sends the datagram `data` to `addr` on the UDP socket `handle`
+    fn send(&self, handle: u32, data: &[u8], addr: SocketAddr) -> Result<usize, Errno> {
+        let socket = self.udp(handle)?;
+        if !self.policy.allows(Protocol::Udp, addr) {
+            return Err(Errno::Acces);
+        }
+        socket.send_to(data, addr).map_err(|e| errno::from_io(&e))
+    }
+
+    /// Receives a datagram from an allowed peer into `buf` on the UDP socket `handle`
+    ///
+    /// Blocks for at most `timeout_ms` milliseconds per datagram, forever if negative, and not
+    /// at all if zero. Returns the size of the datagram and the address of the peer.
+    fn recv(
+        &self,
+        handle: u32,
+        buf: &mut [u8],
+        timeout_ms: i64,
+    ) -> Result<(usize, SocketAddr), Errno> {
+        let socket = self.udp(handle)?;
+        match u64::try_from(timeout_ms) {
+            Ok(0) => socket.set_nonblocking(true),
+            Ok(ms) => socket
+                .set_nonblocking(false)
+                .and_then(|()| socket.set_read_timeout(Some(Duration::from_millis(ms)))),
+            Err(..) => socket
+                .set_nonblocking(false)
+                .and_then(|()| socket.set_read_timeout(None)),
+        }
+        .map_err(|e| errno::from_io(&e))?;
+
+        loop {
+            // Some hosts report expired read timeouts as timeouts instead of `EAGAIN`
+            let (n, peer) = socket
+                .recv_from(buf)
+                .map_err(|e| match errno::from_io(&e) {
+                    Errno::Timedout => Errno::Again,
+                    other => other,
+                })?;
+            if self.policy.allows(Protocol::Udp, peer) {
+                return Ok((n, peer));
+            }
+        }
+    }
+
+    /// Closes the UDP socket `handle`
+    fn close(&mut self, handle: u32) -> Result<(), Errno> {
+        self.udp.remove(&handle).map(drop).ok_or(Errno::Badf)
+    }
+}
+
+/// Reads the socket address at `ptr` of `len` bytes from the memory of the workload
+fn read_addr(caller: &mut Caller<'_, Ctx>, ptr: u32, len: u32) -> Result<SocketAddr, Errno> {
+    read_str(caller, ptr, len)?
+        .parse()
+        .map_err(|_| Errno::Inval)
+}
+
+/// Resolves the host name at `host` of `host_len` bytes, writes its IP addresses, one per line,
+/// to `buf` of `len` bytes and returns their length or the negated WASI errno
+///
+/// Fails with `ERRNO_ACCES`, if the policy does not allow to resolve host names, and with
+/// `ERRNO_NOENT`, if the host name cannot be resolved.
+fn resolve(mut caller: Caller<'_, Ctx>, host: u32, host_len: u32, buf: u32, len: u32) -> i32 {
+    let res = read_str(&mut caller, host, host_len)
+        .and_then(|host| caller.data().sockets.resolve(&host))
+        .and_then(|ips| Ok((memory(&mut caller)?, ips)));
+    negate(res.and_then(|(memory, ips)| write(&mut caller, memory, buf, len, ips.as_bytes())))
+}
+
+/// Connects to the address at `addr` of `addr_len` bytes with TCP and returns the new WASI file
+/// descriptor of the connection or the negated WASI errno
+///
+/// Fails with `ERRNO_ACCES`, if the policy does not allow the address.
+fn tcp_connect(mut caller: Caller<'_, Ctx>, addr: u32, addr_len: u32) -> i32 {
+    negate((|| {
+        let addr = read_addr(&mut caller, addr, addr_len)?;
+        let stream = caller.data().sockets.connect(addr)?;
+        let stream = cap_std::net::TcpStream::from_std(stream);
+
+        let wasi = &mut caller.data_mut().wasi;
+        let fd = (3..)
+            .find(|fd| !wasi.table().contains_key(*fd))
+            .ok_or(Errno::Nfile)?;
+        wasi.insert_file(
+            fd,
+            wasmtime_wasi::net::Socket::from(stream).into(),
+            *CONNECT_CAPS,
+        );
+        i32::try_from(fd).map_err(|_| Errno::Nfile)
+    })())
+}
+
+/// Binds a new UDP socket to the local address at `addr` of `addr_len` bytes, e.g. `0.0.0.0:0`,
+/// and returns its handle or the negated WASI errno
+///
+/// Fails with `ERRNO_ACCES`, if the port is not `0` and the policy does not allow the address.
+fn udp_bind(mut caller: Caller<'_, Ctx>, addr: u32, addr_len: u32) -> i32 {
+    negate((|| {
+        let addr = read_addr(&mut caller, addr, addr_len)?;
+        let handle = caller.data_mut().sockets.bind(addr)?;
+        Ok(handle as i32)
+    })())
+}
+
+/// Sends the datagram at `buf` of `len` bytes to the address at `addr` of `addr_len` bytes on
+/// the UDP socket `handle` and returns the number of bytes sent or the negated WASI errno
+///
+/// Fails with `ERRNO_ACCES`, if the policy does not allow the address.
+fn udp_send(
+    mut caller: Caller<'_, Ctx>,
+    handle: u32,
+    buf: u32,
+    len: u32,
+    addr: u32,
+    addr_len: u32,
+) -> i32 {
+    negate((|| {
+        let data = read(&mut caller, buf, len)?;
+        let addr = read_addr(&mut caller, addr, addr_len)?;
+        let n = caller.data().sockets.send(handle, &data, addr)?;
+        Ok(n as i32)
+    })())
+}
+
+/// Receives a datagram on the UDP socket `handle` into `buf` of `len` bytes, writes the
+/// NUL-terminated address of its peer to `peer` of `peer_len` bytes and returns the size of the
+/// datagram or the negated WASI errno
+///
+/// Waits for at most `timeout_ms` milliseconds, forever if negative, and fails with
+/// `ERRNO_AGAIN` without a datagram. `peer_len` must be at least 64 bytes. Datagrams exceeding
+/// `buf` are truncated.
+fn udp_recv(
+    mut caller: Caller<'_, Ctx>,
+    handle: u32,
+    buf: u32,
+    len: u32,
+    peer: u32,
+    peer_len: u32,
+    timeout_ms: i64,
+) -> i32 {
+    negate((|| {
+        if peer_len < PEER_LEN {
+            return Err(Errno::Inval);
+        }
+        let memory = memory(&mut caller)?;
+        let mut data = vec![0; (len as usize).min(MAX_DATAGRAM)];
+        let (n, addr) = caller.data().sockets.recv(handle, &mut data, timeout_ms)?;
+        let n = n.min(data.len());
+
+        let mut addr = addr.to_string().into_bytes();
+        addr.push(0);
+        memory
+            .write(&mut caller, buf as usize, &data[..n])
+            .and_then(|()| memory.write(&mut caller, peer as usize, &addr))
+            .map_err(|_| Errno::Fault)?;
+        Ok(n as i32)
+    })())
+}
+
+/// Closes the UDP socket `handle` and returns `0` or the negated WASI errno
+fn udp_close(mut caller: Caller<'_, Ctx>, handle: u32) -> i32 {
+    negate(caller.data_mut().sockets.close(handle).map(|()| 0))
+}
+
+/// Adds the `enarx` socket functions to `linker`
+pub fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "resolve", resolve)
+        .context("failed to add `enarx::resolve`")?;
+    linker
+        .func_wrap("enarx", "tcp_connect", tcp_connect)
+        .context("failed to add `enarx::tcp_connect`")?;
+    linker
+        .func_wrap("enarx", "udp_bind", udp_bind)
+        .context("failed to add `enarx::udp_bind`")?;
+    linker
+        .func_wrap("enarx", "udp_send", udp_send)
+        .context("failed to add `enarx::udp_send`")?;
+    linker
+        .func_wrap("enarx", "udp_recv", udp_recv)
+        .context("failed to add `enarx::udp_recv`")?;
+    linker
+        .func_wrap("enarx", "udp_close", udp_close)
+        .context("failed to add `enarx::udp_close`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use enarx_config::{Endpoint, Network};
+
+    fn sockets(allow: Vec<Endpoint>) -> Sockets {
+        let network = Network {
+            resolve: false,
+            allow,
+        };
+        Sockets::new(Arc::new(Policy::new(&network).unwrap()))
+    }
+
+    #[test]
+    fn udp() {
+        let mut sockets = sockets(vec![Endpoint {
+            prot: Protocol::Udp,
+            addr: Some("127.0.0.1".into()),
+            port: None,
+        }]);
+        let local = "127.0.0.1:0".parse().unwrap();
+        let a = sockets.bind(local).unwrap();
+        let b = sockets.bind(local).unwrap();
+        assert_ne!(a, b);
+        let addr = sockets.udp(b).unwrap().local_addr().unwrap();
+
+        assert_eq!(sockets.send(a, b"ping", addr), Ok(4));
+        let mut buf = [0; 16];
+        let (n, peer) = sockets.recv(b, &mut buf, -1).unwrap();
+        assert_eq!(&buf[..n], b"ping");
+        assert_eq!(peer, sockets.udp(a).unwrap().local_addr().unwrap());
+        assert_eq!(sockets.recv(b, &mut buf, 0), Err(Errno::Again));
+
+        assert_eq!(
+            sockets.send(a, b"ping", "127.0.0.2:53".parse().unwrap()),
+            Err(Errno::Acces)
+        );
+        assert_eq!(sockets.close(a), Ok(()));
+        assert_eq!(sockets.close(a), Err(Errno::Badf));
+        assert_eq!(sockets.send(a, b"ping", addr), Err(Errno::Badf));
+    }
+
+    #[test]
+    fn denied() {
+        let mut sockets = sockets(vec![]);
+        assert_eq!(sockets.resolve("localhost"), Err(Errno::Acces));
+        assert_eq!(
+            sockets.connect("127.0.0.1:80".parse().unwrap()).err(),
+            Some(Errno::Acces)
+        );
+        assert_eq!(
+            sockets.bind("127.0.0.1:5353".parse().unwrap()),
+            Err(Errno::Acces)
+        );
+
+        // datagrams of peers, which are not allowed, are dropped
+        let b = sockets.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = sockets.udp(b).unwrap().local_addr().unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.send_to(b"ping", addr).unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(sockets.recv(b, &mut buf, 100), Err(Errno::Again));
+    }
+}
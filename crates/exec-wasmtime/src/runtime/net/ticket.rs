@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! TLS session tickets sealed to the Keep
+//!
+//! Session tickets let clients resume a TLS session without a full handshake, which saves the
+//! CPU cost of the key exchange and certificate signature for keeps with high connection rates.
+//!
+//! The ticket keys of each listener are derived from the sealing key of the Keep and the name
+//! of the listener, so they never leave the Keep and identical Keeps can resume the sessions of
+//! each other. Keys are rotated every [`ROTATION`] seconds and tickets are accepted for at most
+//! one rotation after their key was retired. The rotation is based on the wall time provided by
+//! the host, which can at most delay the rotation, but never learn the keys.
+//!
+//! Without a sealing key, e.g. on KVM, a random secret generated at startup is used instead.
+
+use super::super::identity::Platform;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use rustls::server::ProducesTickets;
+use zeroize::Zeroizing;
+
+/// Interval of the ticket key rotation in seconds
+pub const ROTATION: u64 = 6 * 60 * 60;
+
+/// Salt of the ticket key derivation
+const SALT: &[u8] = b"enarx tls ticket";
+
+/// Size of the epoch prefix of a ticket
+const EPOCH_LEN: usize = 8;
+
+/// Returns the secret to derive the ticket keys from
+///
+/// This is the sealing key of the Keep or a random secret, if the platform has no sealing key.
+pub fn secret() -> Result<Zeroizing<Vec<u8>>> {
+    let key = Platform::get()
+        .and_then(|platform| platform.key())
+        .context("failed to get sealing key")?;
+    let mut key = Zeroizing::new(key);
+    if key.is_empty() {
+        key.resize(32, 0);
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| anyhow!("failed to generate ticket secret"))?;
+    }
+    Ok(key)
+}
+
+/// Producer of session tickets with rotating keys derived from a secret of the Keep
+pub struct Ticketer {
+    secret: Zeroizing<Vec<u8>>,
+    listener: String,
+    rng: SystemRandom,
+}
+
+impl Ticketer {
+    /// Creates a ticketer of the listener named `listener`
+    pub fn new(secret: Zeroizing<Vec<u8>>, listener: impl Into<String>) -> Self {
+        Self {
+            secret,
+            listener: listener.into(),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Returns the current rotation epoch
+    fn epoch() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() / ROTATION)
+            .unwrap_or_default()
+    }
+
+    /// Derives the ticket key of `epoch`
+    fn key(&self, epoch: u64) -> LessSafeKey {
+        let epoch = epoch.to_le_bytes();
+        let info = [self.listener.as_bytes(), &epoch];
+        let prk = Salt::new(HKDF_SHA256, SALT).extract(&self.secret);
+        let okm = prk
+            .expand(&info, &CHACHA20_POLY1305)
+            .expect("ticket key length is valid");
+        LessSafeKey::new(UnboundKey::from(okm))
+    }
+
+    /// Encrypts `plain` with the key of `epoch`
+    ///
+    /// The ticket consists of the epoch, a random nonce and the sealed plaintext.
+    fn encrypt_at(&self, epoch: u64, plain: &[u8]) -> Option<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).ok()?;
+
+        let mut ticket =
+            Vec::with_capacity(EPOCH_LEN + NONCE_LEN + plain.len() + CHACHA20_POLY1305.tag_len());
+        ticket.extend_from_slice(&epoch.to_le_bytes());
+        ticket.extend_from_slice(&nonce);
+
+        let mut sealed = plain.to_vec();
+        self.key(epoch)
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&ticket[..EPOCH_LEN]),
+                &mut sealed,
+            )
+            .ok()?;
+        ticket.extend_from_slice(&sealed);
+        Some(ticket)
+    }
+
+    /// Decrypts `ticket`, if its key is the one of `now` or the previous epoch
+    fn decrypt_at(&self, now: u64, ticket: &[u8]) -> Option<Vec<u8>> {
+        if ticket.len() < EPOCH_LEN + NONCE_LEN + CHACHA20_POLY1305.tag_len() {
+            return None;
+        }
+        let (aad, rest) = ticket.split_at(EPOCH_LEN);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+
+        let epoch = u64::from_le_bytes(aad.try_into().ok()?);
+        if epoch != now && Some(epoch) != now.checked_sub(1) {
+            return None;
+        }
+
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut plain = sealed.to_vec();
+        let len = self
+            .key(epoch)
+            .open_in_place(nonce, Aad::from(aad), &mut plain)
+            .ok()?
+            .len();
+        plain.truncate(len);
+        Some(plain)
+    }
+}
+
+impl ProducesTickets for Ticketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        ROTATION as _
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.encrypt_at(Self::epoch(), plain)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        self.decrypt_at(Self::epoch(), cipher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener(name: &str) -> Ticketer {
+        Ticketer::new(Zeroizing::new(vec![0x42; 32]), name)
+    }
+
+    #[test]
+    fn roundtrip() {
+        let ticketer = listener("ingest");
+        let ticket = ticketer.encrypt(b"session").unwrap();
+        assert_ne!(&ticket[EPOCH_LEN + NONCE_LEN..], b"session");
+        assert_eq!(ticketer.decrypt(&ticket).unwrap(), b"session");
+
+        // Tickets of identical Keeps are interchangeable
+        assert_eq!(ticket.len(), ticketer.encrypt(b"session").unwrap().len());
+        assert_eq!(listener("ingest").decrypt(&ticket).unwrap(), b"session");
+    }
+
+    #[test]
+    fn rotation() {
+        let ticketer = listener("ingest");
+        let ticket = ticketer.encrypt_at(7, b"session").unwrap();
+        assert_eq!(ticketer.decrypt_at(7, &ticket).unwrap(), b"session");
+        assert_eq!(ticketer.decrypt_at(8, &ticket).unwrap(), b"session");
+        assert_eq!(ticketer.decrypt_at(9, &ticket), None);
+        assert_eq!(ticketer.decrypt_at(6, &ticket), None);
+
+        // The epoch is authenticated
+        let mut forged = ticket.clone();
+        forged[..EPOCH_LEN].copy_from_slice(&8u64.to_le_bytes());
+        assert_eq!(ticketer.decrypt_at(8, &forged), None);
+    }
+
+    #[test]
+    fn isolation() {
+        let ticket = listener("ingest").encrypt(b"session").unwrap();
+        assert_eq!(listener("egress").decrypt(&ticket), None);
+        assert_eq!(
+            Ticketer::new(Zeroizing::new(vec![0x24; 32]), "ingest").decrypt(&ticket),
+            None
+        );
+
+        let mut tampered = ticket.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(listener("ingest").decrypt(&tampered), None);
+        assert_eq!(listener("ingest").decrypt(&ticket[..EPOCH_LEN]), None);
+    }
+}
@@ -6,6 +6,7 @@ use std::any::Any;
 use std::io;
 use std::io::{IoSlice, IoSliceMut, Read, Write};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use cap_std::net::{Shutdown, TcpListener as CapListener, TcpStream as CapStream};
 #[cfg(windows)]
@@ -20,6 +21,15 @@ use wasi_common::{Context, Error, ErrorExt, ErrorKind, WasiFile};
 use wasmtime_wasi::net::get_fd_flags;
 use wasmtime_wasi::net::is_read_write;
 
+/// Maximum time to block on close to deliver the pending TLS records, akin to `SO_LINGER`
+const LINGER: Duration = Duration::from_secs(10);
+
+/// Maximum time to drain the data of the peer on close after the FIN was sent
+///
+/// Closing a socket with unread data resets the connection, which may discard the final
+/// response of the application at the peer.
+const LINGER_READ: Duration = Duration::from_secs(2);
+
 fn errmap(error: io::Error) -> Error {
     match error.kind() {
         io::ErrorKind::WouldBlock => ErrorKind::WouldBlk.into(),
@@ -103,6 +113,8 @@ pub struct Stream {
     tcp: CapStream,
     tls: Connection,
     nonblocking: bool,
+    /// Directions the application has shut down
+    shutdown: SdFlags,
 }
 
 impl From<Stream> for Box<dyn WasiFile> {
@@ -130,6 +142,7 @@ impl Stream {
             tcp,
             tls,
             nonblocking: false, // this is only valid under assumption that this executable has opened the socket
+            shutdown: SdFlags::empty(),
         };
         stream
             .complete_io()
@@ -145,6 +158,69 @@ impl Stream {
         }
         Ok(())
     }
+
+    /// Blocks until all pending TLS records are written to the socket or `LINGER` elapsed
+    ///
+    /// Non-blocking writes may leave records pending, which would be lost on shutdown.
+    fn linger(&mut self) -> io::Result<()> {
+        if !self.tls.wants_write() {
+            return Ok(());
+        }
+
+        self.tcp.set_nonblocking(false)?;
+        let deadline = Instant::now() + LINGER;
+        let res = (|| {
+            while self.tls.wants_write() {
+                let timeout = deadline
+                    .checked_duration_since(Instant::now())
+                    .filter(|timeout| !timeout.is_zero())
+                    .ok_or(io::ErrorKind::TimedOut)?;
+                self.tcp.set_write_timeout(Some(timeout))?;
+                self.tls.write_tls(&mut self.tcp)?;
+            }
+            self.tcp.flush()
+        })();
+        self.tcp.set_write_timeout(None)?;
+        self.tcp.set_nonblocking(self.nonblocking)?;
+        res
+    }
+
+    /// Sends the `close_notify` alert and the FIN to the peer
+    fn shutdown_write(&mut self) -> io::Result<()> {
+        self.tls.send_close_notify();
+        self.linger()?;
+        self.tcp.shutdown(Shutdown::Write)
+    }
+
+    /// Discards the data sent by the peer until it closes the connection or `LINGER_READ` elapsed
+    fn drain(&mut self) -> io::Result<()> {
+        self.tcp.set_nonblocking(false)?;
+        let deadline = Instant::now() + LINGER_READ;
+        let mut buf = [0; 4096];
+        while let Some(timeout) = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|timeout| !timeout.is_zero())
+        {
+            self.tcp.set_read_timeout(Some(timeout))?;
+            if self.tcp.read(&mut buf)? == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Stream {
+    /// Closes the TLS session cleanly, so the peer can tell the end of the stream from a
+    /// truncation, and lingers to deliver the pending data to the peer
+    fn drop(&mut self) {
+        if !self.shutdown.contains(SdFlags::WR) && self.shutdown_write().is_err() {
+            return;
+        }
+        if !self.shutdown.contains(SdFlags::RD) {
+            let _ = self.drain();
+        }
+    }
 }
 
 #[wiggle::async_trait]
@@ -192,6 +268,9 @@ impl WasiFile for Stream {
     }
 
     async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        if self.shutdown.contains(SdFlags::RD) {
+            return Ok(0);
+        }
         loop {
             self.complete_io()?;
             match self.tls.reader().read_vectored(bufs) {
@@ -203,6 +282,9 @@ impl WasiFile for Stream {
     }
 
     async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        if self.shutdown.contains(SdFlags::WR) {
+            return Err(io::Error::from_raw_os_error(libc::EPIPE).into());
+        }
         match self.tls.writer().write_vectored(bufs) {
             Ok(n) => {
                 self.complete_io()?;
@@ -269,16 +351,20 @@ impl WasiFile for Stream {
     }
 
     async fn sock_shutdown(&mut self, how: SdFlags) -> Result<(), Error> {
-        let how = if how == SdFlags::RD | SdFlags::WR {
-            Shutdown::Both
-        } else if how == SdFlags::RD {
-            Shutdown::Read
-        } else if how == SdFlags::WR {
-            Shutdown::Write
-        } else {
+        if how.is_empty() || !(SdFlags::RD | SdFlags::WR).contains(how) {
             return Err(Error::invalid_argument());
-        };
-        self.tcp.shutdown(how)?;
+        }
+
+        // The peer needs a `close_notify` to tell a half-close from a truncation
+        if how.contains(SdFlags::WR) && !self.shutdown.contains(SdFlags::WR) {
+            self.shutdown_write()
+                .map_err(errmap)
+                .context("failed to shut down writing")?;
+        }
+        if how.contains(SdFlags::RD) && !self.shutdown.contains(SdFlags::RD) {
+            self.tcp.shutdown(Shutdown::Read)?;
+        }
+        self.shutdown |= how;
         Ok(())
     }
 }
@@ -328,6 +414,7 @@ impl WasiFile for Listener {
             tcp,
             tls,
             nonblocking: false,
+            shutdown: SdFlags::empty(),
         };
         stream
             .set_fdflags(FdFlags::empty())
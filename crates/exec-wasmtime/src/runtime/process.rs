@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Process attributes of the Wasm workload
+
+use std::collections::HashMap;
+
+use anyhow::ensure;
+use enarx_config::Process;
+
+/// Environment variable holding the working directory
+pub const PWD: &str = "PWD";
+
+/// Checks `process` and its consistency with the environment variables `env`
+pub fn check(process: &Process, env: &HashMap<String, String>) -> anyhow::Result<()> {
+    if let Some(cwd) = &process.cwd {
+        ensure!(
+            cwd.starts_with('/'),
+            "working directory `{cwd}` is not an absolute path"
+        );
+        ensure!(
+            !env.contains_key(PWD),
+            "working directory conflicts with the `{PWD}` environment variable"
+        );
+    }
+    if let Some(umask) = process.umask {
+        ensure!(umask <= 0o777, "umask `{umask:#o}` exceeds `0o777`");
+    }
+    Ok(())
+}
+
+/// Sets the file mode creation mask of `process`
+#[cfg(unix)]
+pub fn set_umask(process: &Process) {
+    if let Some(umask) = process.umask {
+        // SAFETY: umask() always succeeds
+        unsafe { libc::umask(umask as _) };
+    }
+}
+
+/// Sets the file mode creation mask of `process`
+#[cfg(not(unix))]
+pub fn set_umask(_process: &Process) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let env = HashMap::new();
+        let process = |cwd: &str, umask| Process {
+            cwd: Some(cwd.into()),
+            umask: Some(umask),
+        };
+
+        assert!(super::check(&Process::default(), &env).is_ok());
+        assert!(super::check(&process("/app", 0o022), &env).is_ok());
+        assert!(super::check(&process("app", 0o022), &env).is_err());
+        assert!(super::check(&process("/app", 0o1000), &env).is_err());
+
+        let env = HashMap::from([(PWD.into(), "/".into())]);
+        assert!(super::check(&process("/app", 0o022), &env).is_err());
+    }
+}
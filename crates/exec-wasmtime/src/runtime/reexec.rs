@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Relaunch of the Keep with a new configuration requested by the workload
+//!
+//! The workload imports `reexec` from the `enarx:keep` module to update itself: after deciding
+//! on an update by its own policy, it passes the SHA-256 digest of a pre-published Enarx.toml,
+//! e.g. of a new tag of its package. The Keep sends the request to the host over the socket, on
+//! which it received its arguments, and exits. The host relaunches the Keep with the same
+//! arguments and passes the digest back, so the new Keep refuses to run with any other config,
+//! which the host might provide instead. The config digest is claimed in the attestation evidence
+//! of the new Keep as well.
+
+use super::errno::negate;
+use super::keys::read;
+use super::Ctx;
+
+use anyhow::{bail, Context};
+use tracing::{info, warn};
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker, Trap};
+
+/// Prefix of the config digest of a [`Reexec`]
+const SHA256: &str = "sha256:";
+
+/// File descriptor of the socket, on which the Keep received its arguments from the host
+#[cfg(unix)]
+const HOST_FD: std::os::unix::io::RawFd = 3;
+
+/// Relaunch of the Keep requested by the workload
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(unix, derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(unix, serde(deny_unknown_fields))]
+pub struct Reexec {
+    /// SHA-256 digest of the config of the relaunched Keep in the `sha256:<hex>` format
+    pub config: String,
+}
+
+impl Reexec {
+    /// Creates the relaunch with the config of the SHA-256 digest `digest`
+    fn new(digest: &[u8; 32]) -> Self {
+        Self {
+            config: format!("{SHA256}{}", hex::encode(digest)),
+        }
+    }
+
+    /// Checks, whether `digest` of the config of the package matches the requested config
+    pub(super) fn check(&self, digest: Option<&[u8; 32]>) -> anyhow::Result<()> {
+        let requested = self
+            .config
+            .strip_prefix(SHA256)
+            .and_then(|digest| hex::decode(digest).ok())
+            .with_context(|| format!("invalid requested config digest `{}`", self.config))?;
+        match digest {
+            Some(digest) if digest[..] == requested[..] => Ok(()),
+            Some(digest) => bail!(
+                "config digest `{SHA256}{}` does not match the requested `{}`",
+                hex::encode(digest),
+                self.config
+            ),
+            None => bail!("package has no config, but `{}` was requested", self.config),
+        }
+    }
+}
+
+/// Sends `reexec` to the host
+#[cfg(unix)]
+fn request(reexec: &Reexec) -> anyhow::Result<()> {
+    use std::io::Write;
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::FromRawFd;
+    use std::os::unix::net::UnixStream;
+
+    let msg = toml::to_vec(reexec).context("failed to encode relaunch")?;
+    // SAFETY: The FD is managed by the host and is not closed here.
+    let host = ManuallyDrop::new(unsafe { UnixStream::from_raw_fd(HOST_FD) });
+    (&*host)
+        .write_all(&msg)
+        .context("failed to send relaunch to the host")
+}
+
+#[cfg(windows)]
+fn request(_reexec: &Reexec) -> anyhow::Result<()> {
+    bail!("relaunching the Keep is not supported on this platform")
+}
+
+/// Requests the host to relaunch the Keep with the config of the SHA-256 digest at `digest` of
+/// `len` bytes and exits the workload
+///
+/// Returns the negated WASI errno on failure: `ERRNO_INVAL`, if `len` is not 32, and
+/// `ERRNO_NOTSUP`, if the host cannot be reached.
+fn reexec(mut caller: Caller<'_, Ctx>, digest: u32, len: u32) -> Result<i32, Trap> {
+    let digest = match len {
+        32 => read(&mut caller, digest, len),
+        _ => Err(Errno::Inval),
+    };
+    let res = digest.and_then(|digest| {
+        let reexec = Reexec::new(digest[..].try_into().unwrap());
+        request(&reexec).map(|()| reexec).map_err(|e| {
+            warn!("{e:#}");
+            Errno::Notsup
+        })
+    });
+    match res {
+        Ok(reexec) => {
+            info!(config = %reexec.config, "relaunch requested by the workload");
+            Err(Trap::i32_exit(0))
+        }
+        Err(errno) => Ok(negate(Err(errno))),
+    }
+}
+
+/// Adds the `enarx:keep` `reexec` function to `linker`
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx:keep", "reexec", reexec)
+        .context("failed to add `enarx:keep::reexec`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let reexec = Reexec::new(&[0xab; 32]);
+        assert_eq!(reexec.config, format!("sha256:{}", "ab".repeat(32)));
+        assert!(reexec.check(Some(&[0xab; 32])).is_ok());
+        assert!(reexec.check(Some(&[0xcd; 32])).is_err());
+        assert!(reexec.check(None).is_err());
+
+        let reexec = Reexec {
+            config: "sha512:00".into(),
+        };
+        assert!(reexec.check(Some(&[0; 32])).is_err());
+    }
+}
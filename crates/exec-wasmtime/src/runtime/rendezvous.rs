@@ -0,0 +1,251 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Discovery of peer Keeps via a rendezvous service
+//!
+//! If the Enarx.toml configures a `rendezvous` service, the Keep registers the digest of its
+//! Wasm module and its endpoints with `POST {url}/keeps` and deregisters on exit with
+//! `DELETE {url}/keeps/{id}`. The workload imports `peers` from the `enarx` module to query the
+//! endpoints of the Keeps running a Wasm module of a given digest with
+//! `GET {url}/keeps?digest={digest}`, e.g. to bootstrap a confidential cluster.
+//!
+//! All requests authenticate the Keep with its attested certificate chain as TLS client
+//! certificate, so the service only accepts and discloses the endpoints of attested Keeps.
+
+use super::errno::negate;
+use super::keys::{memory, read_str, write};
+use super::net::client_config;
+use super::Ctx;
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, ensure, Context};
+use enarx_config::Rendezvous as Config;
+use rustls::Certificate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use ureq::{Agent, AgentBuilder};
+use url::Url;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+use zeroize::Zeroizing;
+
+/// Prefix of the digest of a Wasm module
+const DIGEST_PREFIX: &str = "sha256:";
+
+/// Registration of a Keep
+#[derive(Serialize)]
+struct Registration<'a> {
+    digest: &'a str,
+    endpoints: &'a [String],
+}
+
+/// Response of the service to a registration
+#[derive(Deserialize)]
+struct Registered {
+    id: String,
+}
+
+/// A Keep registered with the rendezvous service
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Peer {
+    /// Identifier of the registration
+    pub id: String,
+
+    /// Endpoints `host:port`, at which the Keep is reachable
+    pub endpoints: Vec<String>,
+}
+
+/// Returns the digest of `webasm` in the form `sha256:<hex>`
+fn digest(webasm: &[u8]) -> String {
+    format!("{DIGEST_PREFIX}{}", hex::encode(Sha256::digest(webasm)))
+}
+
+/// Returns the URL of the registered Keeps of the service at `url` or the one of the
+/// registration `id`
+fn endpoint(url: &Url, id: Option<&str>) -> anyhow::Result<Url> {
+    let mut keeps = url.clone();
+    keeps
+        .path_segments_mut()
+        .map_err(|()| anyhow!("invalid rendezvous url `{url}`"))?
+        .pop_if_empty()
+        .push("keeps")
+        .extend(id);
+    Ok(keeps)
+}
+
+/// Formats the endpoints of `peers` as one line of space-separated endpoints per peer
+fn format(peers: &[Peer]) -> String {
+    peers
+        .iter()
+        .map(|peer| format!("{}\n", peer.endpoints.join(" ")))
+        .collect()
+}
+
+/// The registration of the Keep with a rendezvous service, which is removed on drop
+pub struct Rendezvous {
+    agent: Agent,
+    url: Url,
+    digest: String,
+    id: String,
+}
+
+impl Rendezvous {
+    /// Registers the Keep running `webasm` with the service of `config`
+    pub fn register(
+        config: &Config,
+        webasm: &[u8],
+        certs: Vec<Certificate>,
+        key: &Zeroizing<Vec<u8>>,
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            config.url.scheme() == "https",
+            "refusing to use an unencrypted rendezvous url"
+        );
+        let tls = client_config(certs, key).context("failed to configure TLS")?;
+        let agent = AgentBuilder::new().tls_config(Arc::new(tls)).build();
+        let digest = digest(webasm);
+
+        let url = endpoint(&config.url, None)?;
+        let Registered { id } = agent
+            .post(url.as_str())
+            .send_json(Registration {
+                digest: &digest,
+                endpoints: &config.endpoints,
+            })
+            .with_context(|| format!("failed to register with `{url}`"))?
+            .into_json()
+            .context("failed to decode registration")?;
+        info!(
+            "registered with rendezvous service `{}` as `{id}`",
+            config.url
+        );
+
+        Ok(Self {
+            agent,
+            url: config.url.clone(),
+            digest,
+            id,
+        })
+    }
+
+    /// Returns the other Keeps running a Wasm module of `digest`, which defaults to the one of
+    /// this Keep
+    pub fn peers(&self, digest: Option<&str>) -> anyhow::Result<Vec<Peer>> {
+        let digest = digest.unwrap_or(&self.digest);
+        ensure!(
+            digest
+                .strip_prefix(DIGEST_PREFIX)
+                .and_then(|digest| hex::decode(digest).ok())
+                .map_or(false, |digest| digest.len() == 32),
+            "unsupported digest `{digest}`"
+        );
+
+        let mut url = endpoint(&self.url, None)?;
+        url.query_pairs_mut().append_pair("digest", digest);
+        let peers: Vec<Peer> = self
+            .agent
+            .get(url.as_str())
+            .call()
+            .with_context(|| format!("failed to query `{url}`"))?
+            .into_json()
+            .context("failed to decode peers")?;
+        Ok(peers
+            .into_iter()
+            .filter(|peer| peer.id != self.id)
+            .collect())
+    }
+}
+
+impl Drop for Rendezvous {
+    fn drop(&mut self) {
+        let res = endpoint(&self.url, Some(&self.id)).and_then(|url| {
+            self.agent
+                .delete(url.as_str())
+                .call()
+                .with_context(|| format!("failed to deregister from `{url}`"))
+        });
+        if let Err(e) = res {
+            warn!("{e:#}");
+        }
+    }
+}
+
+/// Writes the endpoints of the other Keeps running a Wasm module of the digest at `digest` of
+/// `digest_len` bytes in the form `sha256:<hex>` to `buf` of `len` bytes and returns the number
+/// of bytes written or the negated WASI errno
+///
+/// Every peer is written as one line of space-separated endpoints. Without `digest`, the peers
+/// running the Wasm module of this Keep are written. Fails with `ERRNO_NOTCAPABLE`, if no rendezvous
+/// service is configured, and with `ERRNO_RANGE`, if the peers do not fit into `buf`.
+fn peers(mut caller: Caller<'_, Ctx>, digest: u32, digest_len: u32, buf: u32, len: u32) -> i32 {
+    negate(memory(&mut caller).and_then(|memory| {
+        let rendezvous = caller.data().rendezvous.clone().ok_or(Errno::Notcapable)?;
+        let digest = match digest_len {
+            0 => None,
+            _ => Some(read_str(&mut caller, digest, digest_len)?),
+        };
+        let peers = rendezvous.peers(digest.as_deref()).map_err(|e| {
+            warn!("failed to query peers: {e:#}");
+            Errno::Io
+        })?;
+        write(&mut caller, memory, buf, len, format(&peers).as_bytes())
+    }))
+}
+
+/// Adds the `enarx` `peers` function to `linker`
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "peers", peers)
+        .context("failed to add `enarx::peers`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ureq::serde_json;
+
+    #[test]
+    fn urls() {
+        for base in ["https://example.com", "https://example.com/"] {
+            let base = base.parse().unwrap();
+            assert_eq!(
+                endpoint(&base, None).unwrap().as_str(),
+                "https://example.com/keeps"
+            );
+        }
+        let base = "https://example.com/rendezvous/".parse().unwrap();
+        assert_eq!(
+            endpoint(&base, Some("a/b")).unwrap().as_str(),
+            "https://example.com/rendezvous/keeps/a%2Fb"
+        );
+    }
+
+    #[test]
+    fn peers() {
+        assert_eq!(
+            digest(b""),
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        let peers: Vec<Peer> = serde_json::from_str(
+            r#"[
+                {"id": "1", "endpoints": ["10.0.0.1:8443", "[fd00::1]:8443"]},
+                {"id": "2", "endpoints": ["10.0.0.2:8443"], "claims": {}}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            format(&peers),
+            "10.0.0.1:8443 [fd00::1]:8443\n10.0.0.2:8443\n"
+        );
+
+        let config = Config {
+            url: "http://example.com".parse().unwrap(),
+            endpoints: vec![],
+        };
+        assert!(Rendezvous::register(&config, b"", vec![], &Zeroizing::new(vec![])).is_err());
+    }
+}
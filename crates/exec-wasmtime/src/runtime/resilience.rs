@@ -0,0 +1,409 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rate limiting and circuit breaking shared by all instances of the workload
+//!
+//! Workloads instantiated per connection lose their memory with every instance, so they cannot
+//! maintain rate limits or circuit breakers themselves. The workload imports `token_bucket`,
+//! `concurrency_acquire`, `concurrency_release`, `circuit_allow` and `circuit_report` from the
+//! `enarx` module, which operate on named state kept by the runtime for all instances in the Keep.
+//! Concurrency permits still held by an instance are released, when the instance is dropped.
+
+use super::errno::negate;
+use super::keys::memory;
+use super::Ctx;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Linker};
+
+/// Maximum length of the name of a limiter or circuit breaker
+pub const MAX_NAME_LEN: usize = 256;
+
+/// Maximum number of limiters or circuit breakers of each kind
+pub const MAX_ENTRIES: usize = 1024;
+
+/// Requests pass the circuit breaker
+pub const CLOSED: i32 = 0;
+
+/// Requests are rejected by the circuit breaker
+pub const OPEN: i32 = 1;
+
+/// A single trial request passes the circuit breaker
+pub const HALF_OPEN: i32 = 2;
+
+/// Token bucket refilled at a constant rate up to its capacity
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket of `capacity` tokens
+    fn new(now: Instant, capacity: u64) -> Self {
+        Self {
+            tokens: capacity as f64,
+            updated: now,
+        }
+    }
+
+    /// Refills the bucket at `rate` tokens per second up to `capacity` and takes `n` tokens
+    ///
+    /// Returns the number of remaining tokens or `None`, if there are less than `n` tokens.
+    fn take(&mut self, now: Instant, capacity: u64, rate: u64, n: u64) -> Option<u64> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate as f64).min(capacity as f64);
+        self.updated = now;
+
+        if self.tokens < n as f64 {
+            return None;
+        }
+        self.tokens -= n as f64;
+        Some(self.tokens as u64)
+    }
+}
+
+/// Circuit breaker opening after consecutive failures
+#[derive(Debug, Default)]
+struct Breaker {
+    failures: u32,
+    opened: Option<Instant>,
+    trial: bool,
+}
+
+impl Breaker {
+    /// Returns the state of the breaker for a new request
+    ///
+    /// An open breaker becomes half-open after `cooldown` and lets a single trial request pass.
+    fn allow(&mut self, now: Instant, cooldown: Duration) -> i32 {
+        match self.opened {
+            None => CLOSED,
+            Some(opened) if now.saturating_duration_since(opened) < cooldown => OPEN,
+            Some(..) if self.trial => OPEN,
+            Some(..) => {
+                self.trial = true;
+                HALF_OPEN
+            }
+        }
+    }
+
+    /// Records the outcome of a request and returns the new state of the breaker
+    ///
+    /// The breaker opens after `threshold` consecutive failures or a failed trial request
+    /// and closes after any successful request.
+    fn report(&mut self, now: Instant, success: bool, threshold: u32) -> i32 {
+        if success {
+            *self = Self::default();
+            return CLOSED;
+        }
+        self.failures = self.failures.saturating_add(1);
+        if self.trial || self.failures >= threshold {
+            self.opened = Some(now);
+            self.trial = false;
+        }
+        match self.opened {
+            Some(..) => OPEN,
+            None => CLOSED,
+        }
+    }
+}
+
+/// Named limiters and circuit breakers
+#[derive(Debug, Default)]
+struct State {
+    buckets: HashMap<String, TokenBucket>,
+    concurrency: HashMap<String, u32>,
+    breakers: HashMap<String, Breaker>,
+}
+
+/// Returns the entry `name` of `map`, which is inserted with `new`, if there is room for it
+fn entry<'a, T>(
+    map: &'a mut HashMap<String, T>,
+    name: &str,
+    new: impl FnOnce() -> T,
+) -> Result<&'a mut T, Errno> {
+    if !map.contains_key(name) {
+        if map.len() >= MAX_ENTRIES {
+            return Err(Errno::Nospc);
+        }
+        map.insert(name.into(), new());
+    }
+    Ok(map.get_mut(name).unwrap())
+}
+
+/// The state shared by all instances of the workload
+#[derive(Debug, Default)]
+pub struct Shared(Mutex<State>);
+
+/// The view of an instance on the [`Shared`] state, which releases its permits on drop
+#[derive(Debug)]
+pub struct Resilience {
+    shared: Arc<Shared>,
+    held: HashMap<String, u32>,
+}
+
+impl Resilience {
+    /// Creates the view of a new instance on `shared`
+    pub fn new(shared: Arc<Shared>) -> Self {
+        Self {
+            shared,
+            held: HashMap::new(),
+        }
+    }
+
+    /// Takes `n` tokens of the bucket `name` of `capacity` tokens refilled at `rate` per second
+    fn take(&self, name: &str, capacity: u64, rate: u64, n: u64) -> Result<u64, Errno> {
+        let now = Instant::now();
+        let mut state = self.shared.0.lock().unwrap();
+        entry(&mut state.buckets, name, || TokenBucket::new(now, capacity))?
+            .take(now, capacity, rate, n)
+            .ok_or(Errno::Again)
+    }
+
+    /// Acquires a permit of the concurrency limiter `name` of `max` permits
+    fn acquire(&mut self, name: &str, max: u32) -> Result<(), Errno> {
+        let mut state = self.shared.0.lock().unwrap();
+        let used = entry(&mut state.concurrency, name, || 0)?;
+        if *used >= max {
+            return Err(Errno::Again);
+        }
+        *used += 1;
+        *self.held.entry(name.into()).or_default() += 1;
+        Ok(())
+    }
+
+    /// Releases a permit of the concurrency limiter `name` held by this instance
+    fn release(&mut self, name: &str) -> Result<(), Errno> {
+        match self.held.get_mut(name) {
+            Some(held) if *held > 0 => *held -= 1,
+            _ => return Err(Errno::Inval),
+        }
+        let mut state = self.shared.0.lock().unwrap();
+        if let Some(used) = state.concurrency.get_mut(name) {
+            *used = used.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    /// Returns the state of the circuit breaker `name` for a new request
+    fn allow(&self, name: &str, cooldown: Duration) -> Result<i32, Errno> {
+        let mut state = self.shared.0.lock().unwrap();
+        Ok(entry(&mut state.breakers, name, Breaker::default)?.allow(Instant::now(), cooldown))
+    }
+
+    /// Records the outcome of a request at the circuit breaker `name`
+    fn report(&self, name: &str, success: bool, threshold: u32) -> Result<i32, Errno> {
+        let mut state = self.shared.0.lock().unwrap();
+        Ok(entry(&mut state.breakers, name, Breaker::default)?.report(
+            Instant::now(),
+            success,
+            threshold,
+        ))
+    }
+}
+
+impl Drop for Resilience {
+    fn drop(&mut self) {
+        let mut state = self.shared.0.lock().unwrap_or_else(|e| e.into_inner());
+        for (name, held) in self.held.drain() {
+            if let Some(used) = state.concurrency.get_mut(&name) {
+                *used = used.saturating_sub(held);
+            }
+        }
+    }
+}
+
+/// Reads the UTF-8 name at `name` of `len` bytes from the memory of the workload
+fn read_name(caller: &mut Caller<'_, Ctx>, name: u32, len: u32) -> Result<String, Errno> {
+    if len as usize > MAX_NAME_LEN {
+        return Err(Errno::Nametoolong);
+    }
+    let memory = memory(caller)?;
+    let name = memory
+        .data(&caller)
+        .get(name as usize..)
+        .and_then(|data| data.get(..len as usize))
+        .ok_or(Errno::Fault)?;
+    String::from_utf8(name.to_vec()).map_err(|_| Errno::Ilseq)
+}
+
+/// Takes `tokens` tokens of the bucket `name` of `capacity` tokens refilled at `rate` tokens per
+/// second and returns the number of remaining tokens or the negated WASI errno
+///
+/// The bucket is created full on first use. Fails with `ERRNO_AGAIN`, if there are not enough
+/// tokens, and with `ERRNO_NOSPC`, if there are too many buckets.
+fn token_bucket(
+    mut caller: Caller<'_, Ctx>,
+    name: u32,
+    name_len: u32,
+    capacity: i64,
+    rate: i64,
+    tokens: i64,
+) -> i64 {
+    negate((|| {
+        let name = read_name(&mut caller, name, name_len)?;
+        let capacity = u64::try_from(capacity).map_err(|_| Errno::Inval)?;
+        let rate = u64::try_from(rate).map_err(|_| Errno::Inval)?;
+        let tokens = u64::try_from(tokens).map_err(|_| Errno::Inval)?;
+        let remaining = caller
+            .data()
+            .resilience
+            .take(&name, capacity, rate, tokens)?;
+        Ok(i64::try_from(remaining).unwrap_or(i64::MAX))
+    })())
+}
+
+/// Acquires a permit of the concurrency limiter `name` of `max` permits and returns `0` or the
+/// negated WASI errno
+///
+/// Fails with `ERRNO_AGAIN`, if all permits are held.
+fn concurrency_acquire(mut caller: Caller<'_, Ctx>, name: u32, name_len: u32, max: i32) -> i32 {
+    negate((|| {
+        let name = read_name(&mut caller, name, name_len)?;
+        let max = u32::try_from(max).map_err(|_| Errno::Inval)?;
+        caller.data_mut().resilience.acquire(&name, max)?;
+        Ok(0)
+    })())
+}
+
+/// Releases a permit of the concurrency limiter `name` and returns `0` or the negated WASI errno
+///
+/// Fails with `ERRNO_INVAL`, if the instance does not hold a permit.
+fn concurrency_release(mut caller: Caller<'_, Ctx>, name: u32, name_len: u32) -> i32 {
+    negate((|| {
+        let name = read_name(&mut caller, name, name_len)?;
+        caller.data_mut().resilience.release(&name)?;
+        Ok(0)
+    })())
+}
+
+/// Returns the state of the circuit breaker `name` for a new request or the negated WASI errno
+///
+/// An open breaker becomes half-open after `cooldown_ms` milliseconds.
+fn circuit_allow(mut caller: Caller<'_, Ctx>, name: u32, name_len: u32, cooldown_ms: i64) -> i32 {
+    negate((|| {
+        let name = read_name(&mut caller, name, name_len)?;
+        let cooldown = u64::try_from(cooldown_ms).map_err(|_| Errno::Inval)?;
+        caller
+            .data()
+            .resilience
+            .allow(&name, Duration::from_millis(cooldown))
+    })())
+}
+
+/// Records the outcome of a request at the circuit breaker `name`, which opens after `threshold`
+/// consecutive failures, and returns its new state or the negated WASI errno
+fn circuit_report(
+    mut caller: Caller<'_, Ctx>,
+    name: u32,
+    name_len: u32,
+    success: i32,
+    threshold: i32,
+) -> i32 {
+    negate((|| {
+        let name = read_name(&mut caller, name, name_len)?;
+        let threshold = u32::try_from(threshold).map_err(|_| Errno::Inval)?;
+        caller
+            .data()
+            .resilience
+            .report(&name, success != 0, threshold)
+    })())
+}
+
+/// Adds the `enarx` rate limiting and circuit breaking functions to `linker`
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx", "token_bucket", token_bucket)
+        .context("failed to add `enarx::token_bucket`")?;
+    linker
+        .func_wrap("enarx", "concurrency_acquire", concurrency_acquire)
+        .context("failed to add `enarx::concurrency_acquire`")?;
+    linker
+        .func_wrap("enarx", "concurrency_release", concurrency_release)
+        .context("failed to add `enarx::concurrency_release`")?;
+    linker
+        .func_wrap("enarx", "circuit_allow", circuit_allow)
+        .context("failed to add `enarx::circuit_allow`")?;
+    linker
+        .func_wrap("enarx", "circuit_report", circuit_report)
+        .context("failed to add `enarx::circuit_report`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(start, 2);
+        assert_eq!(bucket.take(start, 2, 1, 1), Some(1));
+        assert_eq!(bucket.take(start, 2, 1, 1), Some(0));
+        assert_eq!(bucket.take(start, 2, 1, 1), None);
+
+        // refilled at 1 token per second up to the capacity
+        let later = start + Duration::from_secs(1);
+        assert_eq!(bucket.take(later, 2, 1, 1), Some(0));
+        let later = later + Duration::from_secs(10);
+        assert_eq!(bucket.take(later, 2, 1, 0), Some(2));
+    }
+
+    #[test]
+    fn breaker() {
+        let start = Instant::now();
+        let cooldown = Duration::from_secs(1);
+        let mut breaker = Breaker::default();
+        assert_eq!(breaker.allow(start, cooldown), CLOSED);
+        assert_eq!(breaker.report(start, false, 2), CLOSED);
+        assert_eq!(breaker.report(start, false, 2), OPEN);
+        assert_eq!(breaker.allow(start, cooldown), OPEN);
+
+        // a single trial after the cooldown, which reopens the breaker on failure
+        let later = start + cooldown;
+        assert_eq!(breaker.allow(later, cooldown), HALF_OPEN);
+        assert_eq!(breaker.allow(later, cooldown), OPEN);
+        assert_eq!(breaker.report(later, false, 2), OPEN);
+        assert_eq!(breaker.allow(later, cooldown), OPEN);
+
+        // and closes it on success
+        let later = later + cooldown;
+        assert_eq!(breaker.allow(later, cooldown), HALF_OPEN);
+        assert_eq!(breaker.report(later, true, 2), CLOSED);
+        assert_eq!(breaker.allow(later, cooldown), CLOSED);
+    }
+
+    #[test]
+    fn concurrency() {
+        let shared = Arc::new(Shared::default());
+        let mut first = Resilience::new(shared.clone());
+        let mut second = Resilience::new(shared.clone());
+
+        assert_eq!(first.acquire("db", 2), Ok(()));
+        assert_eq!(first.acquire("db", 2), Ok(()));
+        assert_eq!(second.acquire("db", 2), Err(Errno::Again));
+        assert_eq!(second.release("db"), Err(Errno::Inval));
+
+        assert_eq!(first.release("db"), Ok(()));
+        assert_eq!(second.acquire("db", 2), Ok(()));
+
+        // the permits of a dropped instance are released
+        drop(first);
+        assert_eq!(second.acquire("db", 2), Ok(()));
+        assert_eq!(second.acquire("db", 2), Err(Errno::Again));
+    }
+
+    #[test]
+    fn entries() {
+        let mut map = HashMap::new();
+        for i in 0..MAX_ENTRIES {
+            entry(&mut map, &i.to_string(), || ()).unwrap();
+        }
+        assert!(entry(&mut map, "0", || ()).is_ok());
+        assert_eq!(entry(&mut map, "new", || ()), Err(Errno::Nospc));
+    }
+}
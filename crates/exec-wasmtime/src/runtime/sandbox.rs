@@ -0,0 +1,250 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Child instances of the workload with reduced rights
+//!
+//! The workload imports `sandbox` from the `enarx:keep` module to run an embedded module, e.g. a
+//! plugin or user-supplied code, in a store of its own. The child only gets the rights granted
+//! by the workload in a TOML table:
+//!
+//! - `args`: the arguments of the child
+//! - `files`: the names of the file descriptors of the workload lent to the child as its file
+//!   descriptors `0`, `1`, ... until it exits
+//! - `imports`: the host functions the child may import besides WASI, either a whole module like
+//!   `enarx` or a single function like `enarx::capability`
+//!
+//! The child shares the memory limits of the Keep with the workload, but gets no environment
+//! besides `FD_COUNT` and `FD_NAMES`, no temporary directory and no other file descriptors.
+
+use super::errno::negate;
+use super::keys::read;
+use super::Ctx;
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use enarx_config::File;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use tracing::warn;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, Extern, Instance, Linker, Module, Store, Trap};
+
+/// Import module of the WASI functions, which every child may import
+const WASI: &str = "wasi_snapshot_preview1";
+
+/// Rights granted to a child by the workload
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rights {
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    files: Vec<String>,
+    #[serde(default)]
+    imports: Vec<String>,
+}
+
+impl Rights {
+    /// Returns whether the child may import `name` of `module`
+    fn allows(&self, module: &str, name: &str) -> bool {
+        module == WASI
+            || self
+                .imports
+                .iter()
+                .any(|import| match import.split_once("::") {
+                    Some((m, n)) => m == module && n == name,
+                    None => import == module,
+                })
+    }
+}
+
+/// Creates the store of a child
+type NewStore = Box<dyn Fn() -> Store<Ctx> + Send + Sync>;
+
+struct Spawner {
+    linker: Linker<Ctx>,
+    new_store: NewStore,
+}
+
+/// The children of the workload
+pub(crate) struct Sandbox {
+    /// Names of the file descriptors of the workload
+    names: Vec<String>,
+    spawner: OnceCell<Spawner>,
+}
+
+impl Sandbox {
+    pub(super) fn new(files: &[File]) -> Arc<Self> {
+        Arc::new(Self {
+            names: files.iter().map(|file| file.name().into()).collect(),
+            spawner: OnceCell::new(),
+        })
+    }
+
+    /// Enables running children, which import the host functions of `linker` in a store created
+    /// by `new_store`
+    pub(super) fn start(&self, linker: Linker<Ctx>, new_store: NewStore) {
+        let _ = self.spawner.set(Spawner { linker, new_store });
+    }
+}
+
+/// Runs `module` with `rights` in `child` and returns its exit code
+fn run(
+    spawner: &Spawner,
+    child: &mut Store<Ctx>,
+    module: &Module,
+    rights: &Rights,
+) -> Result<i32, Errno> {
+    let wasi = &mut child.data_mut().wasi;
+    for (k, v) in [
+        ("FD_COUNT", rights.files.len().to_string()),
+        ("FD_NAMES", rights.files.join(":")),
+    ] {
+        wasi.push_env(k, &v).map_err(|_| Errno::Inval)?;
+    }
+    for arg in &rights.args {
+        wasi.push_arg(arg).map_err(|_| Errno::Inval)?;
+    }
+
+    let imports = module
+        .imports()
+        .map(|import| {
+            spawner
+                .linker
+                .get(&mut *child, import.module(), import.name())
+                .ok_or(Errno::Noent)
+        })
+        .collect::<Result<Vec<Extern>, _>>()?;
+    let instance = Instance::new(&mut *child, module, &imports).map_err(|e| {
+        warn!("failed to instantiate child: {e:#}");
+        Errno::Noexec
+    })?;
+    let start = instance
+        .get_func(&mut *child, "_start")
+        .ok_or(Errno::Noexec)?;
+    match start.call(&mut *child, &[], &mut []) {
+        Ok(()) => Ok(0),
+        Err(e) => match e.downcast_ref::<Trap>().and_then(Trap::i32_exit_status) {
+            Some(code) => Ok(code),
+            None => {
+                warn!("child trapped: {e:#}");
+                Err(Errno::Canceled)
+            }
+        },
+    }
+}
+
+/// Runs the module at `module` of `module_len` bytes with the rights in the TOML table at
+/// `rights` of `rights_len` bytes and returns its exit code or the negated WASI errno
+///
+/// Fails with `ERRNO_INVAL`, if the rights are invalid, with `ERRNO_ACCES`, if the module imports
+/// a host function, which is not granted, with `ERRNO_BADF`, if a granted file descriptor is
+/// closed, with `ERRNO_NOEXEC`, if the module cannot be run, and with `ERRNO_CANCELED`, if it
+/// traps.
+fn sandbox(
+    mut caller: Caller<'_, Ctx>,
+    module: u32,
+    module_len: u32,
+    rights: u32,
+    rights_len: u32,
+) -> i32 {
+    let res = (|| {
+        let webasm = read(&mut caller, module, module_len)?;
+        let rights = read(&mut caller, rights, rights_len)?;
+        let rights: Rights = std::str::from_utf8(&rights)
+            .ok()
+            .and_then(|rights| toml::from_str(rights).ok())
+            .ok_or(Errno::Inval)?;
+
+        let sandbox = caller.data().sandbox.clone();
+        let spawner = sandbox.spawner.get().ok_or(Errno::Notsup)?;
+        let module = Module::from_binary(caller.engine(), &webasm).map_err(|e| {
+            warn!("failed to compile child: {e:#}");
+            Errno::Noexec
+        })?;
+        if let Some(import) = module
+            .imports()
+            .find(|import| !rights.allows(import.module(), import.name()))
+        {
+            warn!(
+                "child imports `{}::{}`, which is not granted",
+                import.module(),
+                import.name()
+            );
+            return Err(Errno::Acces);
+        }
+
+        // Lend the granted file descriptors of the workload to the child
+        let table = caller.data_mut().wasi.table();
+        let mut lent = vec![];
+        for name in &rights.files {
+            let entry = sandbox
+                .names
+                .iter()
+                .position(|n| n == name)
+                .and_then(|fd| u32::try_from(fd).ok())
+                .and_then(|fd| Some((fd, table.delete(fd)?)));
+            match entry {
+                Some(entry) => lent.push(entry),
+                None => {
+                    for (fd, entry) in lent {
+                        table.insert_at(fd, entry);
+                    }
+                    return Err(Errno::Badf);
+                }
+            }
+        }
+        let mut child = (spawner.new_store)();
+        let fds: Vec<_> = lent
+            .into_iter()
+            .zip(0..)
+            .map(|((fd, entry), i)| {
+                child.data_mut().wasi.table().insert_at(i, entry);
+                (i, fd)
+            })
+            .collect();
+
+        let code = run(spawner, &mut child, &module, &rights);
+
+        // Return the file descriptors, which the child did not close
+        for (i, fd) in fds {
+            if let Some(entry) = child.data_mut().wasi.table().delete(i) {
+                caller.data_mut().wasi.table().insert_at(fd, entry);
+            }
+        }
+        code
+    })();
+    negate(res)
+}
+
+/// Adds the `enarx:keep` `sandbox` function to `linker`
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("enarx:keep", "sandbox", sandbox)
+        .context("failed to add `enarx:keep::sandbox`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rights() {
+        let rights: Rights = toml::from_str(
+            "args = ['plugin']\nfiles = ['stdout']\nimports = ['enarx::capability', 'enarx:keep']",
+        )
+        .unwrap();
+        assert_eq!(rights.args, ["plugin"]);
+        assert_eq!(rights.files, ["stdout"]);
+
+        assert!(rights.allows(WASI, "fd_write"));
+        assert!(rights.allows("enarx", "capability"));
+        assert!(!rights.allows("enarx", "splice"));
+        assert!(rights.allows("enarx:keep", "reexec"));
+        assert!(!rights.allows("env", "memory"));
+
+        assert!(!Rights::default().allows("enarx", "capability"));
+        assert!(toml::from_str::<Rights>("env = {}").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Observer Wasm module supplied by the operator
+//!
+//! If the Enarx.toml contains a `[sidecar]` section, the operator may run a second Wasm module,
+//! e.g. a monitoring agent, alongside the workload in the same Keep. The sidecar only gets WASI
+//! with the standard output and error of the host. It has no access to the arguments, environment,
+//! secrets, files or network of the workload, nor to the Enarx host functions. If permitted, it
+//! reads the memory usage of the workload from fd 3 and a copy of the standard output and error
+//! of the workload from fd 4. If the workload runs an instance per connection, it also reads the
+//! statistics of the instances from fd 5.
+//!
+//! The copy is buffered in the Keep and the oldest data is dropped, when the buffer is full, so a
+//! slow sidecar never blocks the workload.
+
+use super::io::event::Event;
+use super::io::memory::MemoryFile;
+use super::isolation::{Stats, StatsFile};
+use super::limits::Memory;
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::io::{IoSlice, IoSliceMut, Read};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{ensure, Context};
+use enarx_config::Sidecar as Conf;
+use io_lifetimes::AsFd;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use wasi_common::file::{FdFlags, FileCaps, FileType, Filestat};
+use wasi_common::{Error, WasiFile};
+use wasmtime::{Engine, Linker, Module, Store, Trap, Val};
+use wasmtime_wasi::{add_to_linker, WasiCtxBuilder};
+
+/// File descriptor of the memory usage of the workload
+const STATS_FD: u32 = 3;
+
+/// File descriptor of the copy of the standard output and error of the workload
+const LOG_FD: u32 = 4;
+
+/// File descriptor of the statistics of the instance-per-connection isolation
+const ISOLATION_FD: u32 = 5;
+
+/// Maximum amount of buffered log data in bytes
+const LOG_CAPACITY: usize = 64 * 1024;
+
+/// Time the sidecar is given to drain the log after the workload exited
+const GRACE: Duration = Duration::from_secs(1);
+
+/// Buffered log data
+#[derive(Debug, Default)]
+struct Buffer {
+    data: VecDeque<u8>,
+    /// The workload exited
+    closed: bool,
+}
+
+/// Copy of the standard output and error of the workload with the readiness signal of its reader
+#[derive(Debug)]
+pub(crate) struct Log {
+    buffer: Mutex<Buffer>,
+    capacity: usize,
+    event: Event,
+}
+
+impl Log {
+    fn new(capacity: usize) -> anyhow::Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            buffer: Default::default(),
+            capacity,
+            event: Event::new().context("failed to create sidecar log event")?,
+        }))
+    }
+
+    /// Appends the first `n` bytes of `bufs`, dropping the oldest data exceeding the capacity
+    fn append(&self, bufs: &[IoSlice<'_>], mut n: usize) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.closed {
+            return;
+        }
+        let ready = !buffer.data.is_empty();
+        for buf in bufs {
+            let len = buf.len().min(n);
+            buffer.data.extend(&buf[..len]);
+            n -= len;
+        }
+        let excess = buffer.data.len().saturating_sub(self.capacity);
+        buffer.data.drain(..excess);
+        if !ready && !buffer.data.is_empty() {
+            self.event.set();
+        }
+    }
+
+    /// Signals the end of the log to the reader
+    fn close(&self) {
+        self.buffer.lock().unwrap().closed = true;
+        self.event.set();
+    }
+
+    /// Consumes buffered data into `bufs`
+    ///
+    /// Returns `None`, if there is no data yet.
+    fn pull(&self, bufs: &mut [IoSliceMut<'_>]) -> Option<u64> {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.data.is_empty() {
+            return buffer.closed.then_some(0);
+        }
+        let n = buffer.data.read_vectored(bufs).unwrap();
+        if buffer.data.is_empty() && !buffer.closed {
+            self.event.clear();
+        }
+        Some(n as _)
+    }
+}
+
+/// The standard output or error of the workload, which copies all written data to the [Log]
+pub(crate) struct Tee {
+    file: Box<dyn WasiFile>,
+    log: Arc<Log>,
+}
+
+impl Tee {
+    pub(crate) fn new(file: Box<dyn WasiFile>, log: Arc<Log>) -> Self {
+        Self { file, log }
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for Tee {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd<'_>> {
+        self.file.pollable()
+    }
+
+    fn isatty(&mut self) -> bool {
+        self.file.isatty()
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        self.file.get_filetype().await
+    }
+
+    async fn get_fdflags(&mut self) -> Result<FdFlags, Error> {
+        self.file.get_fdflags().await
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        self.file.set_fdflags(fdflags).await
+    }
+
+    async fn get_filestat(&mut self) -> Result<Filestat, Error> {
+        self.file.get_filestat().await
+    }
+
+    async fn datasync(&mut self) -> Result<(), Error> {
+        self.file.datasync().await
+    }
+
+    async fn sync(&mut self) -> Result<(), Error> {
+        self.file.sync().await
+    }
+
+    async fn write_vectored<'a>(&mut self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        let n = self.file.write_vectored(bufs).await?;
+        self.log.append(bufs, n as _);
+        Ok(n)
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        self.file.writable().await
+    }
+}
+
+/// The reading end of the [Log] inserted into the sidecar
+struct LogReader(Arc<Log>);
+
+#[wiggle::async_trait]
+impl WasiFile for LogReader {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd<'_>> {
+        Some(self.0.event.as_fd())
+    }
+
+    async fn get_filetype(&mut self) -> Result<FileType, Error> {
+        Ok(FileType::Pipe)
+    }
+
+    async fn read_vectored<'a>(&mut self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        loop {
+            if let Some(n) = self.0.pull(bufs) {
+                return Ok(n);
+            }
+            self.0.event.wait()?;
+        }
+    }
+
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(self.0.buffer.lock().unwrap().data.len() as _)
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A sidecar running on its own thread
+///
+/// Dropping it closes the log and waits for the sidecar to exit for a short grace period.
+pub(crate) struct Sidecar {
+    log: Option<Arc<Log>>,
+    done: Receiver<()>,
+}
+
+impl Sidecar {
+    /// Compiles `webasm` and starts it with the permissions of `conf`
+    ///
+    /// `isolation` are the statistics of the instance-per-connection isolation, if it is enabled.
+    pub(crate) fn spawn(
+        conf: &Conf,
+        engine: &Engine,
+        webasm: &[u8],
+        memory: &Arc<Memory>,
+        isolation: Option<&Arc<Stats>>,
+    ) -> anyhow::Result<Self> {
+        if let Some(expected) = &conf.digest {
+            let digest = format!("sha256:{}", hex::encode(Sha256::digest(webasm)));
+            ensure!(
+                &digest == expected,
+                "sidecar module digest `{digest}` does not match `{expected}`"
+            );
+        }
+        let module =
+            Module::from_binary(engine, webasm).context("failed to compile sidecar module")?;
+
+        let mut linker = Linker::new(engine);
+        add_to_linker(&mut linker, |ctx| ctx).context("failed to setup sidecar linker")?;
+
+        let mut wasi = WasiCtxBuilder::new()
+            .inherit_stdout()
+            .inherit_stderr()
+            .build();
+        let caps = FileCaps::READ | FileCaps::FILESTAT_GET | FileCaps::POLL_READWRITE;
+        if conf.stats {
+            let stats = MemoryFile::observer(memory.clone());
+            wasi.insert_file(STATS_FD, Box::new(stats), caps);
+            if let Some(isolation) = isolation {
+                let stats = StatsFile(isolation.clone());
+                wasi.insert_file(ISOLATION_FD, Box::new(stats), caps);
+            }
+        }
+        let log = conf.log.then(|| Log::new(LOG_CAPACITY)).transpose()?;
+        if let Some(log) = &log {
+            wasi.insert_file(LOG_FD, Box::new(LogReader(log.clone())), caps);
+        }
+
+        let mut store = Store::new(engine, wasi);
+        linker
+            .module(&mut store, "", &module)
+            .context("failed to link sidecar module")?;
+        let func = linker
+            .get_default(&mut store, "")
+            .context("failed to get default function of sidecar module")?;
+
+        let (tx, done) = mpsc::channel();
+        thread::Builder::new()
+            .name("sidecar".into())
+            .spawn(move || {
+                let mut values = vec![Val::null(); func.ty(&store).results().len()];
+                if let Err(e) = func.call(&mut store, Default::default(), &mut values) {
+                    if e.downcast_ref::<Trap>().and_then(Trap::i32_exit_status) != Some(0) {
+                        warn!("sidecar failed: {e:#}");
+                    }
+                }
+                let _ = tx.send(());
+            })
+            .context("failed to start sidecar thread")?;
+        Ok(Self { log, done })
+    }
+
+    /// Returns the log, which the standard output and error of the workload are copied to
+    pub(crate) fn log(&self) -> Option<Arc<Log>> {
+        self.log.clone()
+    }
+}
+
+impl Drop for Sidecar {
+    fn drop(&mut self) {
+        if let Some(log) = &self.log {
+            log.close();
+        }
+        let _ = self.done.recv_timeout(GRACE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pull(log: &Log) -> Option<Vec<u8>> {
+        let mut buf = [0; 16];
+        let n = log.pull(&mut [IoSliceMut::new(&mut buf)])?;
+        Some(buf[..n as usize].to_vec())
+    }
+
+    #[test]
+    fn log() {
+        let log = Log::new(4).unwrap();
+        assert_eq!(pull(&log), None);
+
+        log.append(&[IoSlice::new(b"ab"), IoSlice::new(b"cdef")], 5);
+        assert_eq!(pull(&log).as_deref(), Some(&b"bcde"[..]));
+        assert_eq!(pull(&log), None);
+
+        log.append(&[IoSlice::new(b"gh")], 2);
+        log.close();
+        log.append(&[IoSlice::new(b"ij")], 2);
+        assert_eq!(pull(&log).as_deref(), Some(&b"gh"[..]));
+        assert_eq!(pull(&log).as_deref(), Some(&b""[..]));
+    }
+}
@@ -0,0 +1,390 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stacks of the threads running the workload
+//!
+//! The shims start the exec on a stack of a fixed size, which language runtimes with deep
+//! recursion overflow. If the Enarx.toml has a `[stack]`, the default function and the threads
+//! spawned by the workload run on threads created by the exec with the configured stack and guard
+//! size, which the exec passes to the `clone()` of the shim. Half of every stack is available to
+//! Wasm, the other half is left to the runtime and the host functions.
+//!
+//! With `tls = "exec"`, the exec maps the stacks itself and reuses them for new threads, so a
+//! Keep spawning many short-lived threads does not map and unmap memory in the shim for every
+//! thread. The C library then places the thread-local storage at the top of the stack.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{bail, Context};
+use enarx_config::Stack;
+
+/// Size of a page, which the guard size must be a multiple of
+const PAGE_SIZE: u64 = 4096;
+
+/// Minimum size of a stack
+const MIN_STACK_SIZE: u64 = 256 * 1024;
+
+/// Checks `conf` and limits the stack usage of Wasm in `config` to half of the stack
+pub(super) fn configure(config: &mut wasmtime::Config, conf: &Stack) -> anyhow::Result<()> {
+    if conf.size < MIN_STACK_SIZE {
+        bail!("the stack size must be at least {MIN_STACK_SIZE} bytes");
+    }
+    if conf.guard % PAGE_SIZE != 0 {
+        bail!("the stack guard size must be a multiple of {PAGE_SIZE} bytes");
+    }
+    let size = usize::try_from(conf.size).context("the stack size is too large")?;
+    config.max_wasm_stack(size / 2);
+    Ok(())
+}
+
+/// The result of a thread shared with its [`Thread`]
+struct Shared<T> {
+    result: Mutex<Option<thread::Result<T>>>,
+    done: AtomicBool,
+}
+
+impl<T> Shared<T> {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            result: Mutex::new(None),
+            done: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns `f` running on the thread and storing its result
+    fn wrap(self: &Arc<Self>, f: impl FnOnce() -> T + Send) -> impl FnOnce() + Send
+    where
+        T: Send,
+    {
+        let shared = self.clone();
+        move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            *shared.result.lock().unwrap() = Some(result);
+            shared.done.store(true, Ordering::Release);
+        }
+    }
+
+    fn take(&self) -> thread::Result<T> {
+        self.result
+            .lock()
+            .unwrap()
+            .take()
+            .expect("a joined thread has a result")
+    }
+}
+
+/// Runs `f` on a thread with a stack configured by `conf` and returns its result
+///
+/// Panics of `f` are propagated.
+pub(super) fn run<T: Send>(conf: &Stack, f: impl FnOnce() -> T + Send) -> anyhow::Result<T> {
+    let shared = Shared::new();
+    let f: Box<dyn FnOnce() + Send + '_> = Box::new(shared.wrap(f));
+    // SAFETY: the thread is joined before the borrows of `f` end
+    let f: Box<dyn FnOnce() + Send + 'static> = unsafe { std::mem::transmute(f) };
+    let thread = imp::spawn(conf, f).context("failed to create the thread of the workload")?;
+    thread.join();
+    shared
+        .take()
+        .unwrap_or_else(|panic| panic::resume_unwind(panic))
+}
+
+/// A detached thread with a stack configured by [`spawn`]
+pub(super) struct Thread {
+    thread: imp::Thread,
+    shared: Arc<Shared<()>>,
+}
+
+impl Thread {
+    /// Returns whether the thread has finished running its function
+    pub(super) fn is_finished(&self) -> bool {
+        self.shared.done.load(Ordering::Acquire)
+    }
+
+    /// Waits for the thread to exit and releases its stack
+    pub(super) fn join(self) {
+        self.thread.join()
+    }
+}
+
+/// Spawns a thread running `f` with a stack configured by `conf`
+pub(super) fn spawn(conf: &Stack, f: impl FnOnce() + Send + 'static) -> std::io::Result<Thread> {
+    let shared = Shared::new();
+    let thread = imp::spawn(conf, Box::new(shared.wrap(f)))?;
+    Ok(Thread { thread, shared })
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::Stack;
+
+    use std::ffi::c_void;
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::ptr;
+    use std::sync::Mutex;
+
+    use enarx_config::StackTls;
+
+    /// Stacks mapped by the exec, which are free to be reused
+    static STACKS: Mutex<Vec<Mapping>> = Mutex::new(Vec::new());
+
+    /// A stack mapped by the exec, whose lowest `guard` bytes are inaccessible
+    struct Mapping {
+        addr: *mut c_void,
+        len: usize,
+        guard: usize,
+    }
+
+    // SAFETY: the mapping is only accessed by the thread it is the stack of
+    unsafe impl Send for Mapping {}
+
+    impl Mapping {
+        /// Returns a free stack of `size` bytes above a guard of `guard` bytes
+        fn get(size: usize, guard: usize) -> io::Result<Self> {
+            let mut stacks = STACKS.lock().unwrap();
+            if let Some(i) = stacks
+                .iter()
+                .position(|stack| stack.len == size + guard && stack.guard == guard)
+            {
+                return Ok(stacks.swap_remove(i));
+            }
+            drop(stacks);
+
+            let len = size + guard;
+            // Like the C library, map the guard inaccessible and make the stack accessible, as
+            // the SGX shim does not allow removing all access with `mprotect()`, but maps
+            // inaccessible memory read-only, which still faults on a stack overflow.
+            // SAFETY: a new anonymous mapping does not alias any memory
+            let addr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_NONE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+            if addr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let mapping = Self { addr, len, guard };
+            // SAFETY: the stack is part of the mapping
+            let stack = unsafe { addr.cast::<u8>().add(guard).cast() };
+            if unsafe { libc::mprotect(stack, size, libc::PROT_READ | libc::PROT_WRITE) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(mapping)
+        }
+
+        /// Returns the stack to be reused by a new thread
+        fn release(self) {
+            STACKS.lock().unwrap().push(self)
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: the mapping is not the stack of a running thread
+            unsafe { libc::munmap(self.addr, self.len) };
+        }
+    }
+
+    /// Converts the return value of a pthread function to a result
+    fn check(ret: libc::c_int) -> io::Result<()> {
+        match ret {
+            0 => Ok(()),
+            errno => Err(io::Error::from_raw_os_error(errno)),
+        }
+    }
+
+    /// Attributes of a new thread
+    struct Attr(libc::pthread_attr_t);
+
+    impl Attr {
+        fn new() -> io::Result<Self> {
+            let mut attr = MaybeUninit::uninit();
+            // SAFETY: `attr` is initialized on success
+            unsafe {
+                check(libc::pthread_attr_init(attr.as_mut_ptr()))?;
+                Ok(Self(attr.assume_init()))
+            }
+        }
+    }
+
+    impl Drop for Attr {
+        fn drop(&mut self) {
+            // SAFETY: `self.0` was initialized
+            unsafe { libc::pthread_attr_destroy(&mut self.0) };
+        }
+    }
+
+    type Start = Box<dyn FnOnce() + Send + 'static>;
+
+    extern "C" fn start(arg: *mut c_void) -> *mut c_void {
+        // SAFETY: `arg` was leaked by `spawn` for this thread
+        let f = unsafe { Box::from_raw(arg.cast::<Start>()) };
+        f();
+        ptr::null_mut()
+    }
+
+    /// A thread, which is detached, unless it is joined
+    pub(super) struct Thread {
+        id: Option<libc::pthread_t>,
+        stack: Option<Mapping>,
+    }
+
+    impl Thread {
+        pub(super) fn join(mut self) {
+            if let Some(id) = self.id.take() {
+                // SAFETY: the thread was neither joined nor detached
+                unsafe { libc::pthread_join(id, ptr::null_mut()) };
+            }
+            if let Some(stack) = self.stack.take() {
+                stack.release();
+            }
+        }
+    }
+
+    impl Drop for Thread {
+        fn drop(&mut self) {
+            if let Some(id) = self.id.take() {
+                // SAFETY: the thread was neither joined nor detached
+                unsafe { libc::pthread_detach(id) };
+                // The thread may still run on its stack
+                std::mem::forget(self.stack.take());
+            }
+        }
+    }
+
+    pub(super) fn spawn(conf: &Stack, f: Start) -> io::Result<Thread> {
+        let size = usize::try_from(conf.size).map_err(|_| io::ErrorKind::InvalidInput)?;
+        let guard = usize::try_from(conf.guard).map_err(|_| io::ErrorKind::InvalidInput)?;
+
+        let mut attr = Attr::new()?;
+        let stack = match conf.tls {
+            StackTls::Libc => {
+                // SAFETY: `attr` was initialized
+                unsafe {
+                    check(libc::pthread_attr_setstacksize(&mut attr.0, size))?;
+                    check(libc::pthread_attr_setguardsize(&mut attr.0, guard))?;
+                }
+                None
+            }
+            StackTls::Exec => {
+                let stack = Mapping::get(size, guard)?;
+                // SAFETY: the stack is owned by the thread until it is joined
+                unsafe {
+                    check(libc::pthread_attr_setstack(
+                        &mut attr.0,
+                        stack.addr.cast::<u8>().add(guard).cast(),
+                        size,
+                    ))?;
+                }
+                Some(stack)
+            }
+        };
+
+        let arg = Box::into_raw(Box::new(f));
+        let mut id = MaybeUninit::uninit();
+        // SAFETY: `start` takes ownership of `arg`, if the thread is created
+        match check(unsafe { libc::pthread_create(id.as_mut_ptr(), &attr.0, start, arg.cast()) }) {
+            Ok(()) => Ok(Thread {
+                // SAFETY: `id` is initialized on success
+                id: Some(unsafe { id.assume_init() }),
+                stack,
+            }),
+            Err(e) => {
+                // SAFETY: the thread was not created
+                drop(unsafe { Box::from_raw(arg) });
+                if let Some(stack) = stack {
+                    stack.release();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::Stack;
+
+    use std::io;
+    use std::thread::{self, JoinHandle};
+
+    /// A thread, which is detached, unless it is joined
+    pub(super) struct Thread(JoinHandle<()>);
+
+    impl Thread {
+        pub(super) fn join(self) {
+            let _ = self.0.join();
+        }
+    }
+
+    /// Spawns `f` on a thread with a stack of the configured size, the guard and the source of
+    /// the stack are chosen by the platform
+    pub(super) fn spawn(conf: &Stack, f: Box<dyn FnOnce() + Send + 'static>) -> io::Result<Thread> {
+        let size = usize::try_from(conf.size).map_err(|_| io::ErrorKind::InvalidInput)?;
+        thread::Builder::new().stack_size(size).spawn(f).map(Thread)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use enarx_config::StackTls;
+
+    /// Recurses `depth` times with about 1 KiB of stack per call and returns `depth`
+    #[inline(never)]
+    fn recurse(depth: usize) -> usize {
+        let buf = std::hint::black_box([1_u8; 1024]);
+        match depth {
+            0 => 0,
+            depth => recurse(depth - 1) + usize::from(buf[depth % buf.len()]),
+        }
+    }
+
+    #[test]
+    fn stacks() {
+        for tls in [StackTls::Libc, StackTls::Exec] {
+            let conf = Stack {
+                size: 8 << 20,
+                guard: 64 << 10,
+                tls,
+            };
+            // Deeper than the default stack of 2 MiB of Rust threads
+            let depth = 4096;
+            assert_eq!(run(&conf, || recurse(depth)).unwrap(), depth);
+
+            let thread = spawn(&conf, move || assert_eq!(recurse(depth), depth)).unwrap();
+            thread.join();
+        }
+    }
+
+    #[test]
+    fn panics() {
+        let conf = Stack::default();
+        let res = panic::catch_unwind(|| run(&conf, || panic!("boom")));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn config() {
+        let mut config = wasmtime::Config::new();
+        assert!(configure(&mut config, &Stack::default()).is_ok());
+        let conf = Stack {
+            guard: 100,
+            ..Default::default()
+        };
+        assert!(configure(&mut config, &conf).is_err());
+        let conf = Stack {
+            size: 4096,
+            ..Default::default()
+        };
+        assert!(configure(&mut config, &conf).is_err());
+    }
+}
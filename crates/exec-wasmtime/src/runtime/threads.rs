@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! wasi-threads support
+//!
+//! If the Enarx.toml sets `threads` in `[limits]`, the workload may import a shared linear memory
+//! and spawn threads with `thread-spawn` from the `wasi` module. Every thread instantiates the
+//! module anew in its own store, which imports the same shared memory, and calls the exported
+//! `wasi_thread_start(tid, start_arg)` on a thread of the Keep, i.e. through the `clone()` of
+//! the shim.
+//!
+//! Spawned threads get the arguments and environment variables of the workload, but only the
+//! file descriptors, which can be opened more than once, i.e. neither `listen` nor `connect`
+//! sockets. If any thread exits or traps, the whole workload exits.
+//!
+//! With a `[stack]` in the Enarx.toml, the threads run on stacks configured by [`stack`].
+
+use super::errno::negate;
+use super::stack;
+use super::{Ctx, WASMTIME_CONFIG};
+
+use std::process;
+use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{anyhow, Context};
+use enarx_config::{File, Limits, Stack};
+use enarx_error::ErrorKind;
+use once_cell::sync::OnceCell;
+use tracing::error;
+use wasi_common::snapshots::preview_1::types::Errno;
+use wasmtime::{Caller, ExternType, InstancePre, Linker, Module, SharedMemory, Store, Trap};
+
+/// Name of the function exported by the workload, which every spawned thread starts with
+const THREAD_START: &str = "wasi_thread_start";
+
+/// Highest thread ID, as the upper bits are reserved by the wasi-threads proposal
+const MAX_TID: i32 = 0x1fff_ffff;
+
+/// Returns the wasmtime config of a workload, which may spawn threads
+///
+/// Shared memories are allocated statically, so `memory_size` bounds the maximum size of the
+/// shared memory imported by the workload.
+pub(super) fn config(limits: &Limits) -> anyhow::Result<wasmtime::Config> {
+    let bound = limits
+        .memory_size
+        .ok_or_else(|| anyhow!("`threads` requires a `memory_size` limit"))?;
+    let mut config = WASMTIME_CONFIG.clone();
+    config.wasm_threads(true);
+    config.static_memory_maximum_size(bound);
+    Ok(config)
+}
+
+/// Returns whether the file descriptor `conf` can be opened for a spawned thread
+pub(super) fn shareable(conf: &File) -> bool {
+    !matches!(
+        conf,
+        File::Listen(..) | File::Connect(..) | File::Helper(..)
+    )
+}
+
+/// Defines the shared memory imported by `module`, if any
+pub(super) fn define_memory(linker: &mut Linker<Ctx>, module: &Module) -> anyhow::Result<()> {
+    for import in module.imports() {
+        if let ExternType::Memory(ty) = import.ty() {
+            if ty.is_shared() {
+                let memory = SharedMemory::new(module.engine(), ty)
+                    .context("failed to allocate shared memory within the `memory_size` limit")?;
+                linker
+                    .define(import.module(), import.name(), memory)
+                    .context("failed to define shared memory")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Creates the store of a spawned thread
+type NewStore = Box<dyn Fn() -> anyhow::Result<Store<Ctx>> + Send + Sync>;
+
+struct Spawner {
+    pre: InstancePre<Ctx>,
+    new_store: NewStore,
+}
+
+/// The threads spawned by the workload
+pub(crate) struct Threads {
+    /// Maximum number of running spawned threads
+    max: u32,
+    running: AtomicU32,
+    next_tid: AtomicI32,
+    spawner: OnceCell<Spawner>,
+    /// Stacks of the threads, if configured
+    stack: Option<Stack>,
+    /// Threads with configured stacks, which were not joined yet
+    spawned: Mutex<Vec<stack::Thread>>,
+}
+
+impl Threads {
+    pub(super) fn new(max: u32, stack: Option<Stack>) -> Arc<Self> {
+        Arc::new(Self {
+            max,
+            running: AtomicU32::new(0),
+            next_tid: AtomicI32::new(1),
+            spawner: OnceCell::new(),
+            stack,
+            spawned: Mutex::new(vec![]),
+        })
+    }
+
+    /// Enables spawning threads, which instantiate `pre` in a store created by `new_store`
+    pub(super) fn start(&self, pre: InstancePre<Ctx>, new_store: NewStore) {
+        let _ = self.spawner.set(Spawner { pre, new_store });
+    }
+
+    /// Spawns a thread calling `wasi_thread_start` with `start_arg` and returns its ID
+    fn spawn(self: &Arc<Self>, start_arg: i32) -> Result<i32, Errno> {
+        if self.spawner.get().is_none() {
+            return Err(Errno::Notsup);
+        }
+        self.running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.max).then_some(n + 1)
+            })
+            .map_err(|_| Errno::Again)?;
+        let tid = self.next_tid.fetch_add(1, Ordering::SeqCst);
+        if tid > MAX_TID {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            return Err(Errno::Again);
+        }
+
+        let threads = self.clone();
+        let run = move || {
+            threads.run(tid, start_arg);
+            threads.running.fetch_sub(1, Ordering::SeqCst);
+        };
+        let res = match &self.stack {
+            Some(conf) => {
+                let mut spawned = self.spawned.lock().unwrap();
+                // Join the finished threads, so their stacks can be reused
+                let (finished, running): (Vec<_>, Vec<_>) =
+                    spawned.drain(..).partition(stack::Thread::is_finished);
+                *spawned = running;
+                finished.into_iter().for_each(stack::Thread::join);
+                stack::spawn(conf, run).map(|thread| spawned.push(thread))
+            }
+            None => thread::Builder::new()
+                .name(format!("wasm-{tid}"))
+                .spawn(run)
+                .map(drop),
+        };
+        res.map_err(|_| {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Errno::Again
+        })?;
+        Ok(tid)
+    }
+
+    fn run(&self, tid: i32, start_arg: i32) {
+        let spawner = self.spawner.get().unwrap();
+        let res = (spawner.new_store)().and_then(|mut store| {
+            let instance = spawner
+                .pre
+                .instantiate(&mut store)
+                .context("failed to instantiate module")?;
+            instance
+                .get_typed_func::<(i32, i32), (), _>(&mut store, THREAD_START)?
+                .call(&mut store, (tid, start_arg))
+                .map_err(Into::into)
+        });
+        if let Err(e) = res {
+            match e.downcast_ref::<Trap>().and_then(Trap::i32_exit_status) {
+                Some(status) => process::exit(status),
+                None => {
+                    error!("thread {tid} of the workload failed: {e:#}");
+                    process::exit(ErrorKind::Trap.exit_code())
+                }
+            }
+        }
+    }
+}
+
+fn thread_spawn(caller: Caller<'_, Ctx>, start_arg: i32) -> i32 {
+    let res = match &caller.data().threads {
+        Some(threads) => threads.spawn(start_arg),
+        None => Err(Errno::Notcapable),
+    };
+    negate(res)
+}
+
+pub(super) fn add_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    linker
+        .func_wrap("wasi", "thread-spawn", thread_spawn)
+        .context("failed to add `wasi::thread-spawn`")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_requires_memory_size() {
+        assert!(config(&Limits::default()).is_err());
+        let limits = Limits {
+            memory_size: Some(1 << 20),
+            threads: Some(4),
+            ..Default::default()
+        };
+        assert!(config(&limits).is_ok());
+    }
+
+    #[test]
+    fn spawn_before_start() {
+        assert_eq!(Threads::new(1, None).spawn(0), Err(Errno::Notsup));
+    }
+}
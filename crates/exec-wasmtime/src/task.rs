@@ -0,0 +1,89 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Work overlapped with other work on scoped threads
+//!
+//! Not every shim can spawn threads, e.g. `clone` fails in KVM and SEV-SNP Keeps. If no thread
+//! can be spawned, the work runs inline instead, so the Keep only loses the overlap.
+
+use std::panic;
+use std::sync::{Arc, Mutex};
+use std::thread::{Builder, Scope, ScopedJoinHandle};
+
+use tracing::debug;
+
+/// Work running on a scoped thread or its result, if it ran inline
+pub(crate) enum Task<'scope, T> {
+    Spawned(ScopedJoinHandle<'scope, T>),
+    Done(T),
+}
+
+impl<T> Task<'_, T> {
+    /// Returns the result of the work and propagates its panic
+    pub(crate) fn join(self) -> T {
+        match self {
+            Self::Spawned(handle) => handle
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic)),
+            Self::Done(value) => value,
+        }
+    }
+}
+
+/// Runs `f` on a thread of `scope` or inline, if no thread can be spawned
+pub(crate) fn spawn<'scope, T, F>(scope: &'scope Scope<'scope, '_>, f: F) -> Task<'scope, T>
+where
+    F: FnOnce() -> T + Send + 'scope,
+    T: Send + 'scope,
+{
+    spawn_with(Builder::new(), scope, f)
+}
+
+/// Runs `f` on a thread of `scope` built by `builder` or inline, if it cannot be spawned
+fn spawn_with<'scope, T, F>(
+    builder: Builder,
+    scope: &'scope Scope<'scope, '_>,
+    f: F,
+) -> Task<'scope, T>
+where
+    F: FnOnce() -> T + Send + 'scope,
+    T: Send + 'scope,
+{
+    // A failed spawn drops the closure of the thread, so `f` is only lent to it
+    let slot = Arc::new(Mutex::new(Some(f)));
+    let lent = slot.clone();
+    let spawned = builder.spawn_scoped(scope, move || {
+        let f = lent.lock().unwrap().take().unwrap();
+        f()
+    });
+    match spawned {
+        Ok(handle) => Task::Spawned(handle),
+        Err(e) => {
+            debug!("running inline, as no thread can be spawned: {e}");
+            let f = slot.lock().unwrap().take().unwrap();
+            Task::Done(f())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn spawn_or_inline() {
+        let caller = thread::current().id();
+        thread::scope(|s| {
+            let task = spawn(s, || thread::current().id());
+            assert!(matches!(task, Task::Spawned(..)));
+            assert_ne!(task.join(), caller);
+
+            // No stack of this size can be mapped, so spawning the thread fails
+            let builder = Builder::new().stack_size(1 << 62);
+            let task = spawn_with(builder, s, || thread::current().id());
+            assert!(matches!(task, Task::Done(..)));
+            assert_eq!(task.join(), caller);
+        });
+    }
+}
@@ -2,15 +2,22 @@
 
 //! Workload-related functionality and definitions.
 
+use std::collections::HashMap;
 use std::io::Read;
 #[cfg(unix)]
 use std::os::unix::prelude::FromRawFd;
+use std::thread;
+
+use crate::provenance::{Provenance, PACKAGE_PROVENANCE};
+use crate::task::{self, Task};
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use drawbridge_client::types::{Meta, TagEntry, TreeDirectory, TreeEntry, TreeName, TreePath};
 use drawbridge_client::{scope, Client, Entity, Node, Scope};
 use enarx_config::Config;
+use enarx_error::{Classify, ErrorKind};
 use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
 use ureq::serde_json;
 use url::Url;
 
@@ -24,6 +31,8 @@ pub static PACKAGE_CONFIG: Lazy<TreeName> = Lazy::new(|| "Enarx.toml".parse().un
 const MAX_WASM_SIZE: u64 = 100_000_000;
 /// Maximum size of Enarx.toml in bytes
 const MAX_CONF_SIZE: u64 = 1_000_000;
+/// Maximum size of provenance.json in bytes
+const MAX_PROVENANCE_SIZE: u64 = 1_000_000;
 /// Maximum directory size in bytes
 const MAX_DIR_SIZE: u64 = 1_000_000;
 
@@ -48,6 +57,32 @@ pub enum Package {
         wasm: std::os::unix::prelude::RawFd,
         /// Optional open config file descriptor
         conf: Option<std::os::unix::prelude::RawFd>,
+        /// Optional open file descriptor of a precompiled module artifact
+        #[serde(default)]
+        cache: Option<std::os::unix::prelude::RawFd>,
+        /// Optional open file descriptor of the build provenance
+        #[serde(default)]
+        provenance: Option<std::os::unix::prelude::RawFd>,
+        /// Optional open file descriptor of an observer sidecar WASM module
+        #[serde(default)]
+        sidecar: Option<std::os::unix::prelude::RawFd>,
+        /// Open file descriptors of the datasets of the config by path
+        #[serde(default)]
+        datasets: HashMap<String, std::os::unix::prelude::RawFd>,
+        /// Optional open file descriptor of the pcapng file of the tap
+        #[serde(default)]
+        tap: Option<std::os::unix::prelude::RawFd>,
+        /// Optional open file descriptor of the attestation channel of a `file` Steward URL
+        #[serde(default)]
+        channel: Option<std::os::unix::prelude::RawFd>,
+        /// Open file descriptors of the internal listen sockets of the non-confidential `listen`
+        /// file descriptors of the config, whose TLS is terminated by the host, by name
+        #[serde(default)]
+        offload: HashMap<String, std::os::unix::prelude::RawFd>,
+        /// Open file descriptors of the Wasm modules of the helper Keeps spawned by the host and of
+        /// the streams connected to them by the name of their `helper` file descriptor
+        #[serde(default)]
+        helpers: HashMap<String, (std::os::unix::prelude::RawFd, std::os::unix::prelude::RawFd)>,
     },
 
     /// Local package
@@ -57,6 +92,24 @@ pub enum Package {
         wasm: std::fs::File,
         /// Optional open config file
         conf: Option<std::fs::File>,
+        /// Optional open file of a precompiled module artifact
+        cache: Option<std::fs::File>,
+        /// Optional open file of the build provenance
+        provenance: Option<std::fs::File>,
+        /// Optional open file of an observer sidecar WASM module
+        sidecar: Option<std::fs::File>,
+        /// Open files of the datasets of the config by path
+        datasets: HashMap<String, std::fs::File>,
+        /// Optional open pcapng file of the tap
+        tap: Option<std::fs::File>,
+        /// Optional open attestation channel of a `file` Steward URL
+        channel: Option<std::fs::File>,
+        /// Internal listen sockets of the non-confidential `listen` file descriptors of the
+        /// config, whose TLS is terminated by the host, by name
+        offload: HashMap<String, std::net::TcpListener>,
+        /// Open Wasm modules of the helper Keeps spawned by the host and the streams connected to
+        /// them by the name of their `helper` file descriptor
+        helpers: HashMap<String, (std::fs::File, std::fs::File)>,
     },
 }
 
@@ -78,20 +131,19 @@ fn get_wasm(root: Entity<'_, impl Scope, scope::Node>, entry: &TreeEntry) -> Res
     Ok(wasm)
 }
 
-fn get_package(root: Entity<'_, impl Scope, scope::Node>, dir: TreeDirectory) -> Result<Workload> {
-    let webasm = dir
-        .get(&PACKAGE_ENTRYPOINT)
-        .ok_or_else(|| anyhow!("directory does not contain `{}`", *PACKAGE_ENTRYPOINT))
-        .and_then(|e| get_wasm(root.clone(), e).context("failed to get Wasm"))?;
-
-    let entry = if let Some(entry) = dir.get(&PACKAGE_CONFIG) {
-        entry
-    } else {
-        return Ok(Workload {
-            webasm,
-            config: Default::default(),
-        });
-    };
+/// Parses the raw `config` and returns it with its SHA-256 digest
+fn parse_config(config: &[u8]) -> Result<(Config, [u8; 32])> {
+    let digest = Sha256::digest(config).into();
+    toml::from_slice(config)
+        .map(|config| (config, digest))
+        .context("failed to parse config")
+        .classify(ErrorKind::Config)
+}
+
+fn get_config(
+    root: Entity<'_, impl Scope, scope::Node>,
+    entry: &TreeEntry,
+) -> Result<(Config, [u8; 32])> {
     ensure!(
         entry.meta.mime.essence_str() == TOML_MEDIA_TYPE,
         "invalid `{}` media type `{}`",
@@ -106,10 +158,60 @@ fn get_package(root: Entity<'_, impl Scope, scope::Node>, dir: TreeDirectory) ->
         "`{}` metadata does not match directory entry metadata",
         *PACKAGE_CONFIG,
     );
-    let config = toml::from_slice(&config).context("failed to parse config")?;
+    parse_config(&config)
+}
+
+fn get_provenance(
+    root: Entity<'_, impl Scope, scope::Node>,
+    entry: &TreeEntry,
+) -> Result<Provenance> {
+    let (meta, provenance) = Node::new(root, &PACKAGE_PROVENANCE.clone().into())
+        .get_bytes(MAX_PROVENANCE_SIZE)
+        .with_context(|| format!("failed to fetch `{}`", *PACKAGE_PROVENANCE))?;
+    ensure!(
+        meta == entry.meta,
+        "`{}` metadata does not match directory entry metadata",
+        *PACKAGE_PROVENANCE,
+    );
+    Provenance::try_from(provenance.as_slice())
+}
+
+fn get_package(
+    root: Entity<'_, impl Scope + Sync, scope::Node>,
+    dir: TreeDirectory,
+) -> Result<Workload> {
+    let wasm = dir
+        .get(&PACKAGE_ENTRYPOINT)
+        .ok_or_else(|| anyhow!("directory does not contain `{}`", *PACKAGE_ENTRYPOINT))?;
+
+    // Fetch the config and provenance, while the Wasm module is in flight
+    let (webasm, config, provenance) = thread::scope(|s| {
+        let config = dir
+            .get(&PACKAGE_CONFIG)
+            .map(|entry| task::spawn(s, || get_config(root.clone(), entry)));
+        let provenance = dir
+            .get(&PACKAGE_PROVENANCE)
+            .map(|entry| task::spawn(s, || get_provenance(root.clone(), entry)));
+        let webasm = get_wasm(root.clone(), wasm).context("failed to get Wasm");
+        (webasm, config.map(Task::join), provenance.map(Task::join))
+    });
+
+    let (config, config_digest) = match config.transpose()? {
+        Some((config, digest)) => (Some(config), Some(digest)),
+        None => (None, None),
+    };
     Ok(Workload {
-        webasm,
-        config: Some(config),
+        webasm: webasm?,
+        config,
+        config_digest,
+        artifact: None,
+        provenance: provenance.transpose()?,
+        sidecar: None,
+        datasets: Default::default(),
+        tap: None,
+        channel: None,
+        offload: Default::default(),
+        helpers: Default::default(),
     })
 }
 
@@ -120,6 +222,34 @@ pub struct Workload {
 
     /// Enarx keep configuration
     pub config: Option<Config>,
+
+    /// SHA-256 digest of the raw configuration, which is claimed in the attestation evidence
+    pub config_digest: Option<[u8; 32]>,
+
+    /// Precompiled module artifact provided by the host cache
+    pub artifact: Option<Vec<u8>>,
+
+    /// Build provenance of the Wasm module
+    pub provenance: Option<Provenance>,
+
+    /// Observer sidecar Wasm module supplied by the operator
+    pub sidecar: Option<Vec<u8>>,
+
+    /// Host files of the datasets of the config by path
+    pub datasets: HashMap<String, std::fs::File>,
+
+    /// Pcapng file of the tap opened by the host
+    pub tap: Option<std::fs::File>,
+
+    /// Attestation channel of a `file` Steward URL opened by the host
+    pub channel: Option<std::fs::File>,
+
+    /// Internal listen sockets of the listen sockets with TLS terminated by the host by name
+    pub offload: HashMap<String, std::net::TcpListener>,
+
+    /// Wasm modules of the helper Keeps spawned by the host and the streams connected to them by
+    /// the name of their `helper` file descriptor
+    pub helpers: HashMap<String, (Vec<u8>, std::fs::File)>,
 }
 
 impl TryFrom<Package> for Workload {
@@ -151,6 +281,15 @@ impl TryFrom<Package> for Workload {
                         Ok(Workload {
                             webasm,
                             config: None,
+                            config_digest: None,
+                            artifact: None,
+                            provenance: None,
+                            sidecar: None,
+                            datasets: Default::default(),
+                            tap: None,
+                            channel: None,
+                            offload: Default::default(),
+                            helpers: Default::default(),
                         })
                     }
                     TreeDirectory::<()>::TYPE => serde_json::from_reader(rdr)
@@ -175,6 +314,15 @@ impl TryFrom<Package> for Workload {
                                 .map(|webasm| Workload {
                                     webasm,
                                     config: None,
+                                    config_digest: None,
+                                    artifact: None,
+                                    provenance: None,
+                                    sidecar: None,
+                                    datasets: Default::default(),
+                                    tap: None,
+                                    channel: None,
+                                    offload: Default::default(),
+                                    helpers: Default::default(),
                                 })
                                 .context("failed to fetch workload"),
                             TreeDirectory::<()>::TYPE => {
@@ -195,6 +343,14 @@ impl TryFrom<Package> for Workload {
             Package::Local {
                 ref mut wasm,
                 ref mut conf,
+                ref mut cache,
+                ref mut provenance,
+                ref mut sidecar,
+                ref mut datasets,
+                ref mut tap,
+                ref mut channel,
+                ref mut offload,
+                ref mut helpers,
             } => {
                 let mut webasm = Vec::new();
                 // SAFETY: This FD was passed to us by the host and we trust that we have exclusive
@@ -205,7 +361,7 @@ impl TryFrom<Package> for Workload {
                 wasm.read_to_end(&mut webasm)
                     .context("failed to read WASM module")?;
 
-                let config = if let Some(conf) = conf.as_mut() {
+                let (config, config_digest) = if let Some(conf) = conf.as_mut() {
                     // SAFETY: This FD was passed to us by the host and we trust that we have exclusive
                     // access to it.
                     #[cfg(unix)]
@@ -214,12 +370,119 @@ impl TryFrom<Package> for Workload {
                     let mut config = vec![];
                     conf.read_to_end(&mut config)
                         .context("failed to read config")?;
-                    let config = toml::from_slice(&config).context("failed to parse config")?;
-                    Some(config)
+                    let (config, digest) = parse_config(&config)?;
+                    (Some(config), Some(digest))
+                } else {
+                    (None, None)
+                };
+
+                let artifact = if let Some(cache) = cache.as_mut() {
+                    // SAFETY: This FD was passed to us by the host and we trust that we have exclusive
+                    // access to it.
+                    #[cfg(unix)]
+                    let mut cache = unsafe { std::fs::File::from_raw_fd(*cache) };
+
+                    let mut artifact = vec![];
+                    cache
+                        .read_to_end(&mut artifact)
+                        .context("failed to read precompiled module")?;
+                    Some(artifact)
                 } else {
                     None
                 };
-                Ok(Workload { webasm, config })
+                let provenance = if let Some(provenance) = provenance.as_mut() {
+                    // SAFETY: This FD was passed to us by the host and we trust that we have exclusive
+                    // access to it.
+                    #[cfg(unix)]
+                    let mut provenance = unsafe { std::fs::File::from_raw_fd(*provenance) };
+
+                    let mut buf = vec![];
+                    provenance
+                        .read_to_end(&mut buf)
+                        .context("failed to read provenance")?;
+                    Some(Provenance::try_from(buf.as_slice())?)
+                } else {
+                    None
+                };
+                let sidecar = if let Some(sidecar) = sidecar.as_mut() {
+                    // SAFETY: This FD was passed to us by the host and we trust that we have exclusive
+                    // access to it.
+                    #[cfg(unix)]
+                    let mut sidecar = unsafe { std::fs::File::from_raw_fd(*sidecar) };
+
+                    let mut buf = vec![];
+                    sidecar
+                        .read_to_end(&mut buf)
+                        .context("failed to read sidecar module")?;
+                    Some(buf)
+                } else {
+                    None
+                };
+                // SAFETY: These FDs were passed to us by the host and we trust that we have
+                // exclusive access to them.
+                #[cfg(unix)]
+                let datasets = datasets
+                    .drain()
+                    .map(|(path, fd)| (path, unsafe { std::fs::File::from_raw_fd(fd) }))
+                    .collect();
+                #[cfg(windows)]
+                let datasets = std::mem::take(datasets);
+                // SAFETY: This FD was passed to us by the host and we trust that we have exclusive
+                // access to it.
+                #[cfg(unix)]
+                let tap = tap.map(|tap| unsafe { std::fs::File::from_raw_fd(tap) });
+                #[cfg(windows)]
+                let tap = tap.take();
+                // SAFETY: This FD was passed to us by the host and we trust that we have exclusive
+                // access to it.
+                #[cfg(unix)]
+                let channel = channel.map(|channel| unsafe { std::fs::File::from_raw_fd(channel) });
+                #[cfg(windows)]
+                let channel = channel.take();
+                // SAFETY: These FDs were passed to us by the host and we trust that we have
+                // exclusive access to them.
+                #[cfg(unix)]
+                let offload = offload
+                    .drain()
+                    .map(|(name, fd)| (name, unsafe { std::net::TcpListener::from_raw_fd(fd) }))
+                    .collect();
+                #[cfg(windows)]
+                let offload = std::mem::take(offload);
+                let helpers = helpers
+                    .drain()
+                    .map(|(name, (wasm, stream))| {
+                        // SAFETY: These FDs were passed to us by the host and we trust that we have
+                        // exclusive access to them.
+                        #[cfg(unix)]
+                        let (mut wasm, stream) = unsafe {
+                            (
+                                std::fs::File::from_raw_fd(wasm),
+                                std::fs::File::from_raw_fd(stream),
+                            )
+                        };
+                        #[cfg(windows)]
+                        let mut wasm = wasm;
+
+                        let mut webasm = vec![];
+                        wasm.read_to_end(&mut webasm)
+                            .with_context(|| format!("failed to read helper `{name}` module"))?;
+                        Ok((name, (webasm, stream)))
+                    })
+                    .collect::<Result<_>>()?;
+
+                Ok(Workload {
+                    webasm,
+                    config,
+                    config_digest,
+                    artifact,
+                    provenance,
+                    sidecar,
+                    datasets,
+                    tap,
+                    channel,
+                    offload,
+                    helpers,
+                })
             }
         }
     }
@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Exits to the host per iteration of an event loop with and without batching
+//!
+//! Every exit is an enclave or VM transition in a Keep, so the number of exits dominates the
+//! cost of these loops. Run with `cargo bench -p sallyport`.
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn main() {
+    transitions::main()
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+fn main() {}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod transitions {
+    use core::ffi::{c_int, c_ulong, c_void};
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr::NonNull;
+    use std::sync::atomic::AtomicU32;
+    use std::time::Instant;
+
+    use libc::POLLIN;
+    use sallyport::guest::{Handler, Platform, ThreadLocalStorage};
+    use sallyport::item::Block;
+    use sallyport::libc::{off_t, pollfd, timespec, CloneFlags, CLOCK_MONOTONIC};
+    use sallyport::{host, Result};
+
+    const ITERATIONS: usize = 100_000;
+
+    /// Handler counting its exits to the host
+    struct CountingHandler {
+        block: Box<[usize]>,
+        tls: ThreadLocalStorage,
+        exits: usize,
+        clock_after_wait: bool,
+    }
+
+    impl CountingHandler {
+        fn new(clock_after_wait: bool) -> Self {
+            Self {
+                block: vec![0; 4096].into_boxed_slice(),
+                tls: Default::default(),
+                exits: 0,
+                clock_after_wait,
+            }
+        }
+    }
+
+    impl Handler for CountingHandler {
+        fn sally(&mut self) -> Result<()> {
+            self.exits += 1;
+            host::execute(Block::from(&mut self.block[..]))
+        }
+
+        fn block(&self) -> &[usize] {
+            &self.block
+        }
+
+        fn block_mut(&mut self) -> &mut [usize] {
+            &mut self.block
+        }
+
+        fn thread_local_storage(&mut self) -> &mut ThreadLocalStorage {
+            &mut self.tls
+        }
+
+        fn attacked(&mut self) -> ! {
+            panic!("attacked")
+        }
+
+        fn clock_after_wait(&self) -> bool {
+            self.clock_after_wait
+        }
+
+        fn arch_prctl(&mut self, _: &impl Platform, _: c_int, _: c_ulong) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn brk(
+            &mut self,
+            _: &impl Platform,
+            _: Option<NonNull<c_void>>,
+        ) -> Result<NonNull<c_void>> {
+            unimplemented!()
+        }
+
+        fn clone(
+            &mut self,
+            _: CloneFlags,
+            _: NonNull<c_void>,
+            _: Option<&AtomicU32>,
+            _: Option<&AtomicU32>,
+            _: NonNull<c_void>,
+        ) -> Result<c_int> {
+            unimplemented!()
+        }
+
+        fn madvise(
+            &mut self,
+            _: &impl Platform,
+            _: NonNull<c_void>,
+            _: usize,
+            _: c_int,
+        ) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn mmap(
+            &mut self,
+            _: &impl Platform,
+            _: Option<NonNull<c_void>>,
+            _: usize,
+            _: c_int,
+            _: c_int,
+            _: c_int,
+            _: off_t,
+        ) -> Result<NonNull<c_void>> {
+            unimplemented!()
+        }
+
+        fn mprotect(
+            &mut self,
+            _: &impl Platform,
+            _: NonNull<c_void>,
+            _: usize,
+            _: c_int,
+        ) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn munmap(&mut self, _: &impl Platform, _: NonNull<c_void>, _: usize) -> Result<()> {
+            unimplemented!()
+        }
+    }
+
+    /// Runs `f` [`ITERATIONS`] times and reports the exits and the time per iteration
+    fn bench(name: &str, clock_after_wait: bool, mut f: impl FnMut(&mut CountingHandler)) {
+        let mut handler = CountingHandler::new(clock_after_wait);
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            f(&mut handler);
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "{name:<32} {:>5.2} exits/iter {:>8} ns/iter",
+            handler.exits as f64 / ITERATIONS as f64,
+            elapsed.as_nanos() / ITERATIONS as u128,
+        );
+    }
+
+    /// An event loop, which reads the monotonic clock after every wait to expire its timers
+    fn event_loop(handler: &mut CountingHandler, null: &File) {
+        let mut fds = [pollfd {
+            fd: null.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        }];
+        let mut now = timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        handler.poll(&mut fds, -1).unwrap();
+        handler.clock_gettime(CLOCK_MONOTONIC, &mut now).unwrap();
+    }
+
+    pub fn main() {
+        let null = File::open("/dev/null").unwrap();
+
+        bench("poll + clock_gettime", false, |h| event_loop(h, &null));
+        bench("poll + clock_gettime (batched)", true, |h| {
+            event_loop(h, &null)
+        });
+    }
+}
@@ -55,6 +55,11 @@ pub mod note {
     /// The number of sallyport blocks of the shim (u64)
     pub const NUM_BLOCKS: u32 = 0x73677821;
 
+    /// The maximum size of a bounce buffer transfer of the shim (u64)
+    ///
+    /// Shims without this note do not support bounce buffer transfers.
+    pub const BOUNCE_SIZE: u32 = 0x73677826;
+
     /// SGX ELF Notes
     pub mod sgx {
         /// The SGX enclave bits (u8; in powers of 2)
@@ -63,6 +68,9 @@ pub mod note {
         /// The number of pages in an SSA frame (u8)
         pub const SSAP: u32 = 0x73677801;
 
+        /// The maximum SGX enclave bits the shim supports (u8; in powers of 2)
+        pub const MAX_BITS: u32 = 0x73677802;
+
         /// The product identifier (u16)
         pub const PID: u32 = 0x73677810;
 
@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Translation of errnos between the host, the shims and WASI
+//!
+//! The shims and the host speak Linux errnos, the workload speaks WASI errnos. Language runtimes
+//! choose their recovery path by the errno, so every layer reports failures with the errno Linux
+//! reports in the same situation, and all of them distinguish the following cases:
+//!
+//! | Situation                                                        | Linux        | WASI               |
+//! |------------------------------------------------------------------|--------------|--------------------|
+//! | Unknown syscall, enarxcall or `futex` operation                  | `ENOSYS`     | `ERRNO_NOSYS`      |
+//! | Unknown `ioctl` request                                          | `ENOTTY`     | `ERRNO_NOTTY`      |
+//! | Known operation, flag or argument not supported by the platform  | `EOPNOTSUPP` | `ERRNO_NOTSUP`     |
+//! | Operation not granted to the workload by its package config      | `EPERM`      | `ERRNO_NOTCAPABLE` |
+//!
+//! Every Linux errno with a WASI counterpart is translated to it by [`to_wasi`] and back by
+//! [`from_wasi`]. The few Linux errnos without a counterpart are translated to a related WASI
+//! errno, and all unknown errnos to `ERRNO_IO`. `ERRNO_NOTCAPABLE` has no Linux counterpart and
+//! is translated to `EPERM`.
+
+use crate::libc::*;
+use core::ffi::c_int;
+
+/// WASI errnos of the `wasi_snapshot_preview1` module
+pub mod wasi {
+    pub const SUCCESS: u16 = 0;
+    pub const TOOBIG: u16 = 1;
+    pub const ACCES: u16 = 2;
+    pub const ADDRINUSE: u16 = 3;
+    pub const ADDRNOTAVAIL: u16 = 4;
+    pub const AFNOSUPPORT: u16 = 5;
+    pub const AGAIN: u16 = 6;
+    pub const ALREADY: u16 = 7;
+    pub const BADF: u16 = 8;
+    pub const BADMSG: u16 = 9;
+    pub const BUSY: u16 = 10;
+    pub const CANCELED: u16 = 11;
+    pub const CHILD: u16 = 12;
+    pub const CONNABORTED: u16 = 13;
+    pub const CONNREFUSED: u16 = 14;
+    pub const CONNRESET: u16 = 15;
+    pub const DEADLK: u16 = 16;
+    pub const DESTADDRREQ: u16 = 17;
+    pub const DOM: u16 = 18;
+    pub const DQUOT: u16 = 19;
+    pub const EXIST: u16 = 20;
+    pub const FAULT: u16 = 21;
+    pub const FBIG: u16 = 22;
+    pub const HOSTUNREACH: u16 = 23;
+    pub const IDRM: u16 = 24;
+    pub const ILSEQ: u16 = 25;
+    pub const INPROGRESS: u16 = 26;
+    pub const INTR: u16 = 27;
+    pub const INVAL: u16 = 28;
+    pub const IO: u16 = 29;
+    pub const ISCONN: u16 = 30;
+    pub const ISDIR: u16 = 31;
+    pub const LOOP: u16 = 32;
+    pub const MFILE: u16 = 33;
+    pub const MLINK: u16 = 34;
+    pub const MSGSIZE: u16 = 35;
+    pub const MULTIHOP: u16 = 36;
+    pub const NAMETOOLONG: u16 = 37;
+    pub const NETDOWN: u16 = 38;
+    pub const NETRESET: u16 = 39;
+    pub const NETUNREACH: u16 = 40;
+    pub const NFILE: u16 = 41;
+    pub const NOBUFS: u16 = 42;
+    pub const NODEV: u16 = 43;
+    pub const NOENT: u16 = 44;
+    pub const NOEXEC: u16 = 45;
+    pub const NOLCK: u16 = 46;
+    pub const NOLINK: u16 = 47;
+    pub const NOMEM: u16 = 48;
+    pub const NOMSG: u16 = 49;
+    pub const NOPROTOOPT: u16 = 50;
+    pub const NOSPC: u16 = 51;
+    pub const NOSYS: u16 = 52;
+    pub const NOTCONN: u16 = 53;
+    pub const NOTDIR: u16 = 54;
+    pub const NOTEMPTY: u16 = 55;
+    pub const NOTRECOVERABLE: u16 = 56;
+    pub const NOTSOCK: u16 = 57;
+    pub const NOTSUP: u16 = 58;
+    pub const NOTTY: u16 = 59;
+    pub const NXIO: u16 = 60;
+    pub const OVERFLOW: u16 = 61;
+    pub const OWNERDEAD: u16 = 62;
+    pub const PERM: u16 = 63;
+    pub const PIPE: u16 = 64;
+    pub const PROTO: u16 = 65;
+    pub const PROTONOSUPPORT: u16 = 66;
+    pub const PROTOTYPE: u16 = 67;
+    pub const RANGE: u16 = 68;
+    pub const ROFS: u16 = 69;
+    pub const SPIPE: u16 = 70;
+    pub const SRCH: u16 = 71;
+    pub const STALE: u16 = 72;
+    pub const TIMEDOUT: u16 = 73;
+    pub const TXTBSY: u16 = 74;
+    pub const XDEV: u16 = 75;
+    pub const NOTCAPABLE: u16 = 76;
+}
+
+/// Linux errnos with their WASI counterparts
+const ERRNOS: [(c_int, u16); 75] = [
+    (E2BIG, wasi::TOOBIG),
+    (EACCES, wasi::ACCES),
+    (EADDRINUSE, wasi::ADDRINUSE),
+    (EADDRNOTAVAIL, wasi::ADDRNOTAVAIL),
+    (EAFNOSUPPORT, wasi::AFNOSUPPORT),
+    (EAGAIN, wasi::AGAIN),
+    (EALREADY, wasi::ALREADY),
+    (EBADF, wasi::BADF),
+    (EBADMSG, wasi::BADMSG),
+    (EBUSY, wasi::BUSY),
+    (ECANCELED, wasi::CANCELED),
+    (ECHILD, wasi::CHILD),
+    (ECONNABORTED, wasi::CONNABORTED),
+    (ECONNREFUSED, wasi::CONNREFUSED),
+    (ECONNRESET, wasi::CONNRESET),
+    (EDEADLK, wasi::DEADLK),
+    (EDESTADDRREQ, wasi::DESTADDRREQ),
+    (EDOM, wasi::DOM),
+    (EDQUOT, wasi::DQUOT),
+    (EEXIST, wasi::EXIST),
+    (EFAULT, wasi::FAULT),
+    (EFBIG, wasi::FBIG),
+    (EHOSTUNREACH, wasi::HOSTUNREACH),
+    (EIDRM, wasi::IDRM),
+    (EILSEQ, wasi::ILSEQ),
+    (EINPROGRESS, wasi::INPROGRESS),
+    (EINTR, wasi::INTR),
+    (EINVAL, wasi::INVAL),
+    (EIO, wasi::IO),
+    (EISCONN, wasi::ISCONN),
+    (EISDIR, wasi::ISDIR),
+    (ELOOP, wasi::LOOP),
+    (EMFILE, wasi::MFILE),
+    (EMLINK, wasi::MLINK),
+    (EMSGSIZE, wasi::MSGSIZE),
+    (EMULTIHOP, wasi::MULTIHOP),
+    (ENAMETOOLONG, wasi::NAMETOOLONG),
+    (ENETDOWN, wasi::NETDOWN),
+    (ENETRESET, wasi::NETRESET),
+    (ENETUNREACH, wasi::NETUNREACH),
+    (ENFILE, wasi::NFILE),
+    (ENOBUFS, wasi::NOBUFS),
+    (ENODEV, wasi::NODEV),
+    (ENOENT, wasi::NOENT),
+    (ENOEXEC, wasi::NOEXEC),
+    (ENOLCK, wasi::NOLCK),
+    (ENOLINK, wasi::NOLINK),
+    (ENOMEM, wasi::NOMEM),
+    (ENOMSG, wasi::NOMSG),
+    (ENOPROTOOPT, wasi::NOPROTOOPT),
+    (ENOSPC, wasi::NOSPC),
+    (ENOSYS, wasi::NOSYS),
+    (ENOTCONN, wasi::NOTCONN),
+    (ENOTDIR, wasi::NOTDIR),
+    (ENOTEMPTY, wasi::NOTEMPTY),
+    (ENOTRECOVERABLE, wasi::NOTRECOVERABLE),
+    (ENOTSOCK, wasi::NOTSOCK),
+    (EOPNOTSUPP, wasi::NOTSUP),
+    (ENOTTY, wasi::NOTTY),
+    (ENXIO, wasi::NXIO),
+    (EOVERFLOW, wasi::OVERFLOW),
+    (EOWNERDEAD, wasi::OWNERDEAD),
+    (EPERM, wasi::PERM),
+    (EPIPE, wasi::PIPE),
+    (EPROTO, wasi::PROTO),
+    (EPROTONOSUPPORT, wasi::PROTONOSUPPORT),
+    (EPROTOTYPE, wasi::PROTOTYPE),
+    (ERANGE, wasi::RANGE),
+    (EROFS, wasi::ROFS),
+    (ESPIPE, wasi::SPIPE),
+    (ESRCH, wasi::SRCH),
+    (ESTALE, wasi::STALE),
+    (ETIMEDOUT, wasi::TIMEDOUT),
+    (ETXTBSY, wasi::TXTBSY),
+    (EXDEV, wasi::XDEV),
+];
+
+/// Linux errnos without WASI counterpart with the related WASI errno they are translated to
+const RELATED: [(c_int, u16); 5] = [
+    (EBADFD, wasi::BADF),
+    (EHOSTDOWN, wasi::HOSTUNREACH),
+    (EPFNOSUPPORT, wasi::AFNOSUPPORT),
+    (ESHUTDOWN, wasi::PIPE),
+    (ESOCKTNOSUPPORT, wasi::PROTONOSUPPORT),
+];
+
+/// Translates the Linux errno `errno` to the WASI errno
+///
+/// Unknown errnos are translated to `ERRNO_IO`.
+pub fn to_wasi(errno: c_int) -> u16 {
+    ERRNOS
+        .iter()
+        .chain(&RELATED)
+        .find(|(linux, _)| *linux == errno)
+        .map_or(wasi::IO, |(_, wasi)| *wasi)
+}
+
+/// Translates the WASI errno `errno` to the Linux errno
+///
+/// `ERRNO_NOTCAPABLE` is translated to `EPERM` and unknown errnos to `EIO`.
+pub fn from_wasi(errno: u16) -> c_int {
+    if errno == wasi::NOTCAPABLE {
+        return EPERM;
+    }
+    ERRNOS
+        .iter()
+        .find(|(_, wasi)| *wasi == errno)
+        .map_or(EIO, |(linux, _)| *linux)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exhaustive() {
+        // Every WASI errno but `ERRNO_SUCCESS` and `ERRNO_NOTCAPABLE` has a Linux counterpart
+        let mut wasi: Vec<_> = ERRNOS.iter().map(|(_, wasi)| *wasi).collect();
+        wasi.sort_unstable();
+        assert_eq!(wasi, (wasi::TOOBIG..wasi::NOTCAPABLE).collect::<Vec<_>>());
+
+        let mut linux: Vec<_> = ERRNOS.iter().chain(&RELATED).map(|(e, _)| *e).collect();
+        linux.sort_unstable();
+        linux.dedup();
+        assert_eq!(linux.len(), ERRNOS.len() + RELATED.len());
+    }
+
+    #[test]
+    fn round_trip() {
+        for (linux, wasi) in ERRNOS {
+            assert_eq!(to_wasi(linux), wasi);
+            assert_eq!(from_wasi(wasi), linux);
+        }
+        for (linux, wasi) in RELATED {
+            assert_eq!(to_wasi(linux), wasi);
+            assert_ne!(from_wasi(wasi), linux);
+        }
+    }
+
+    #[test]
+    fn conventions() {
+        assert_eq!(to_wasi(ENOSYS), wasi::NOSYS);
+        assert_eq!(to_wasi(ENOTTY), wasi::NOTTY);
+        assert_eq!(to_wasi(EOPNOTSUPP), wasi::NOTSUP);
+        assert_eq!(to_wasi(ENOTSUP), wasi::NOTSUP);
+        assert_eq!(to_wasi(EWOULDBLOCK), wasi::AGAIN);
+        assert_eq!(from_wasi(wasi::NOTCAPABLE), EPERM);
+
+        assert_eq!(to_wasi(0), wasi::IO);
+        assert_eq!(to_wasi(4095), wasi::IO);
+        assert_eq!(from_wasi(wasi::SUCCESS), EIO);
+        assert_eq!(from_wasi(u16::MAX), EIO);
+    }
+}
@@ -50,6 +50,65 @@ impl<'a, T: PassthroughAlloc> Alloc<'a> for T {
     }
 }
 
+/// Read from a host file descriptor into a bounce buffer.
+pub struct BounceRead {
+    /// Host file descriptor.
+    pub fd: c_int,
+    /// Guest physical address of the bounce buffer.
+    pub addr: usize,
+    /// Number of bytes to read at most.
+    pub count: usize,
+}
+
+impl PassthroughAlloc for BounceRead {
+    const NUM: Number = Number::BounceRead;
+
+    type Argv = Argv<3>;
+    type Ret = usize;
+
+    fn stage(self) -> Self::Argv {
+        Argv([self.fd as _, self.addr, self.count])
+    }
+}
+
+/// Write to a host file descriptor from a bounce buffer.
+pub struct BounceWrite {
+    /// Host file descriptor.
+    pub fd: c_int,
+    /// Guest physical address of the bounce buffer.
+    pub addr: usize,
+    /// Number of bytes to write at most.
+    pub count: usize,
+}
+
+impl PassthroughAlloc for BounceWrite {
+    const NUM: Number = Number::BounceWrite;
+
+    type Argv = Argv<3>;
+    type Ret = usize;
+
+    fn stage(self) -> Self::Argv {
+        Argv([self.fd as _, self.addr, self.count])
+    }
+}
+
+/// Register a [`TimePage`](crate::item::enarxcall::time::TimePage) to be updated by the host.
+pub struct RegisterTimePage {
+    /// Guest physical address of the page.
+    pub addr: usize,
+}
+
+impl PassthroughAlloc for RegisterTimePage {
+    const NUM: Number = Number::RegisterTimePage;
+
+    type Argv = Argv<1>;
+    type Ret = ();
+
+    fn stage(self) -> Self::Argv {
+        Argv([self.addr])
+    }
+}
+
 /// Request an additional memory region.
 pub struct BalloonMemory {
     /// Page size expressed as an exponent of 2.
@@ -86,6 +145,21 @@ impl PassthroughAlloc for GetSgxQuoteSize {
     }
 }
 
+/// Get the end of the heap added to an SGX enclave without EDMM
+#[repr(transparent)]
+pub struct GetSgxStaticHeap;
+
+impl PassthroughAlloc for GetSgxStaticHeap {
+    const NUM: Number = Number::GetSgxStaticHeap;
+
+    type Argv = Argv<0>;
+    type Ret = usize;
+
+    fn stage(self) -> Self::Argv {
+        Argv([])
+    }
+}
+
 /// Get number of memory slots available for ballooning from the host.
 #[repr(transparent)]
 pub struct MemInfo;
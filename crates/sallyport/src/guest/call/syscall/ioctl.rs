@@ -6,8 +6,8 @@ use crate::guest::alloc::{Allocator, Collect, Collector, InOut, Output};
 use crate::guest::call::alloc::kind;
 use crate::guest::call::{MaybeAlloc, UnstagedMaybeAlloc};
 use crate::libc::{
-    self, SYS_ioctl, EBADFD, EINVAL, ENOTTY, FIONBIO, FIONREAD, STDERR_FILENO, STDIN_FILENO,
-    STDOUT_FILENO, TIOCGWINSZ,
+    self, SYS_ioctl, EBADFD, EINVAL, FIONBIO, FIONREAD, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO,
+    TCGETS, TCSETS, TCSETSF, TCSETSW, TIOCGWINSZ,
 };
 use crate::{Result, NULL};
 
@@ -25,10 +25,11 @@ impl<'a> MaybeAlloc<'a, kind::Syscall> for Ioctl<'a> {
     #[inline]
     fn stage(self) -> Result<UnstagedMaybeAlloc<'a, kind::Syscall, Self::Alloc>> {
         match (self.fd, self.request) {
-            (STDIN_FILENO | STDOUT_FILENO | STDERR_FILENO, TIOCGWINSZ) => {
-                // the keep has no tty
-                Ok(UnstagedMaybeAlloc::Stub(Err(ENOTTY)))
-            }
+            // terminal settings and window size of the controlling terminal of the host
+            (
+                STDIN_FILENO | STDOUT_FILENO | STDERR_FILENO,
+                TIOCGWINSZ | TCGETS | TCSETS | TCSETSW | TCSETSF,
+            ) => Ok(UnstagedMaybeAlloc::Alloc(AllocIoctl(self))),
             (STDIN_FILENO | STDOUT_FILENO | STDERR_FILENO, _) => {
                 Ok(UnstagedMaybeAlloc::Stub(Err(EINVAL)))
             }
@@ -22,6 +22,7 @@ mod nanosleep;
 mod open;
 mod passthrough;
 mod poll;
+mod pread64;
 mod read;
 mod readv;
 mod recv;
@@ -40,7 +41,7 @@ pub use accept4::*;
 pub use alloc::*;
 pub use bind::*;
 pub(crate) use clock_getres::*;
-pub(crate) use clock_gettime::*;
+pub use clock_gettime::*;
 pub use connect::*;
 pub use epoll_ctl::*;
 pub use epoll_pwait::EpollPwait;
@@ -52,6 +53,7 @@ pub use nanosleep::*;
 pub use open::*;
 pub use passthrough::*;
 pub use poll::*;
+pub use pread64::*;
 pub use read::*;
 pub use readv::Readv;
 pub use recv::*;
@@ -4,12 +4,12 @@ use super::super::types::Argv;
 use super::Alloc;
 use crate::guest::alloc::{Allocator, Collector};
 use crate::libc::{
-    SYS_close, SYS_dup, SYS_dup2, SYS_dup3, SYS_epoll_create1, SYS_eventfd2, SYS_exit,
-    SYS_exit_group, SYS_listen, SYS_socket, SYS_sync,
+    mode_t, SYS_close, SYS_dup, SYS_dup2, SYS_dup3, SYS_epoll_create1, SYS_eventfd2, SYS_exit,
+    SYS_exit_group, SYS_listen, SYS_sendfile, SYS_shutdown, SYS_socket, SYS_sync, SYS_umask,
 };
-use crate::Result;
+use crate::{Result, NULL};
 
-use core::ffi::{c_int, c_long};
+use core::ffi::{c_int, c_long, c_size_t};
 
 /// Trait implemented by allocatable syscalls, which are passed through directly to the host and do
 /// not require custom handling logic.
@@ -222,6 +222,45 @@ unsafe impl PassthroughAlloc for Listen {
     }
 }
 
+/// Copies data between two file descriptors on the host, without passing it through the guest.
+///
+/// The offset argument is always `NULL`, i.e. data is read from the current file offset of `in_fd`.
+pub struct Sendfile {
+    pub out_fd: c_int,
+    pub in_fd: c_int,
+    pub count: c_size_t,
+}
+
+unsafe impl PassthroughAlloc for Sendfile {
+    const NUM: c_long = SYS_sendfile;
+
+    type Argv = Argv<4>;
+    type Ret = c_size_t;
+
+    fn stage(self) -> Self::Argv {
+        Argv([self.out_fd as _, self.in_fd as _, NULL, self.count])
+    }
+}
+
+/// Shuts down the receptions and/or transmissions of a socket.
+///
+/// Shutting down transmissions sends a FIN to the peer, while the socket can still receive.
+pub struct Shutdown {
+    pub sockfd: c_int,
+    pub how: c_int,
+}
+
+unsafe impl PassthroughAlloc for Shutdown {
+    const NUM: c_long = SYS_shutdown;
+
+    type Argv = Argv<2>;
+    type Ret = ();
+
+    fn stage(self) -> Self::Argv {
+        Argv([self.sockfd as _, self.how as _])
+    }
+}
+
 pub struct Socket {
     pub domain: c_int,
     pub typ: c_int,
@@ -251,3 +290,18 @@ unsafe impl PassthroughAlloc for Sync {
         Argv([])
     }
 }
+
+pub struct Umask {
+    pub mask: mode_t,
+}
+
+unsafe impl PassthroughAlloc for Umask {
+    const NUM: c_long = SYS_umask;
+
+    type Argv = Argv<1>;
+    type Ret = c_int;
+
+    fn stage(self) -> Self::Argv {
+        Argv([self.mask as _])
+    }
+}
@@ -0,0 +1,308 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Detection of skew between a trusted monotonic clock and the host-provided wall time
+//! and validation of host-updated timestamp pages.
+
+use crate::item::enarxcall::time::Timestamps;
+use crate::libc::timespec;
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Nanoseconds per second.
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// Baseline value, which marks the absence of a baseline.
+const UNSET: u64 = u64::MAX;
+
+/// Detects skew between a trusted monotonic clock and the host-provided wall time.
+///
+/// The detector compares the time elapsed on the trusted clock with the wall time elapsed
+/// according to the host since the last baseline. The wall time is expected to advance at the
+/// pace of the trusted clock, with a tolerance of a fixed `threshold` plus a relative `drift`
+/// accounting for the inaccuracy of the trusted clock frequency.
+///
+/// Every detected skew increments the skew counter and establishes a new baseline, so that a
+/// single manipulation of the host clock is counted once.
+///
+/// The detector is shared by all threads. Observations racing with another observation
+/// are skipped.
+#[derive(Debug)]
+pub struct SkewDetector {
+    threshold: u64,
+    drift: u64,
+    busy: AtomicBool,
+    trusted: AtomicU64,
+    host: AtomicU64,
+    skews: AtomicU64,
+}
+
+/// A detected clock skew.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Skew {
+    /// Nanoseconds elapsed on the trusted clock since the last baseline.
+    pub trusted: u64,
+
+    /// Nanoseconds of wall time elapsed according to the host since the last baseline.
+    ///
+    /// Negative, if the host clock was set back.
+    pub host: i128,
+
+    /// Number of skews detected so far, including this one.
+    pub count: u64,
+}
+
+impl Skew {
+    /// Nanoseconds the host clock is ahead of the trusted clock.
+    ///
+    /// Negative, if the host clock is behind.
+    #[inline]
+    pub fn skew(&self) -> i128 {
+        self.host - self.trusted as i128
+    }
+}
+
+impl SkewDetector {
+    /// Creates a detector tolerating an absolute skew of `threshold` nanoseconds
+    /// plus `drift` nanoseconds per second elapsed on the trusted clock.
+    pub const fn new(threshold: u64, drift: u64) -> Self {
+        Self {
+            threshold,
+            drift,
+            busy: AtomicBool::new(false),
+            trusted: AtomicU64::new(UNSET),
+            host: AtomicU64::new(UNSET),
+            skews: AtomicU64::new(0),
+        }
+    }
+
+    /// Observes the trusted monotonic time `trusted` and the host wall time `host`,
+    /// both in nanoseconds, and returns the skew, if it exceeds the tolerance.
+    pub fn observe(&self, trusted: u64, host: u64) -> Option<Skew> {
+        if self.busy.swap(true, Ordering::Acquire) {
+            return None;
+        }
+
+        let base_trusted = self.trusted.load(Ordering::Relaxed);
+        let base_host = self.host.load(Ordering::Relaxed);
+
+        let skew = if base_trusted == UNSET || trusted < base_trusted {
+            None
+        } else {
+            let elapsed = trusted - base_trusted;
+            let host = host as i128 - base_host as i128;
+            let tolerance =
+                self.threshold as i128 + (elapsed / NSEC_PER_SEC) as i128 * self.drift as i128;
+
+            if (host - elapsed as i128).abs() <= tolerance {
+                // Keep the baseline to detect a gradual skew
+                self.busy.store(false, Ordering::Release);
+                return None;
+            }
+
+            let count = self.skews.fetch_add(1, Ordering::Relaxed) + 1;
+            Some(Skew {
+                trusted: elapsed,
+                host,
+                count,
+            })
+        };
+
+        self.trusted.store(trusted, Ordering::Relaxed);
+        self.host.store(host, Ordering::Relaxed);
+        self.busy.store(false, Ordering::Release);
+        skew
+    }
+
+    /// Returns the number of skews detected so far.
+    #[inline]
+    pub fn skews(&self) -> u64 {
+        self.skews.load(Ordering::Relaxed)
+    }
+}
+
+/// Validates snapshots of a host-updated [`TimePage`](crate::item::enarxcall::time::TimePage)
+/// against a trusted monotonic clock.
+///
+/// The host monotonic time read from the page has to advance at the pace of the trusted clock
+/// since the last baseline, with a tolerance of a fixed `threshold` plus a relative `drift`.
+/// A baseline is established by [`rebase`](Self::rebase) with a host monotonic time obtained
+/// by an exit, so a page lagging behind or running ahead of the host is rejected and the
+/// caller has to fall back to an exit.
+///
+/// Monotonic times returned by the validator never go backwards.
+#[derive(Debug)]
+pub struct TimeValidator {
+    threshold: u64,
+    drift: u64,
+    busy: AtomicBool,
+    trusted: AtomicU64,
+    monotonic: AtomicU64,
+    last: AtomicU64,
+}
+
+impl TimeValidator {
+    /// Creates a validator tolerating a deviation of `threshold` nanoseconds
+    /// plus `drift` nanoseconds per second elapsed on the trusted clock.
+    pub const fn new(threshold: u64, drift: u64) -> Self {
+        Self {
+            threshold,
+            drift,
+            busy: AtomicBool::new(false),
+            trusted: AtomicU64::new(UNSET),
+            monotonic: AtomicU64::new(UNSET),
+            last: AtomicU64::new(0),
+        }
+    }
+
+    /// Establishes a new baseline of the trusted monotonic time `trusted` and the host
+    /// monotonic time `monotonic`, both in nanoseconds, and returns `monotonic` clamped
+    /// to the last returned monotonic time.
+    pub fn rebase(&self, trusted: u64, monotonic: u64) -> u64 {
+        if !self.busy.swap(true, Ordering::Acquire) {
+            self.trusted.store(trusted, Ordering::Relaxed);
+            self.monotonic.store(monotonic, Ordering::Relaxed);
+            self.busy.store(false, Ordering::Release);
+        }
+        self.clamp(monotonic)
+    }
+
+    /// Validates the snapshot `time` read at the trusted monotonic time `trusted`.
+    ///
+    /// Returns `None`, if there is no baseline, the snapshot deviates from the trusted clock
+    /// beyond the tolerance or the validation races with a rebase.
+    pub fn validate(&self, trusted: u64, time: Timestamps) -> Option<Timestamps> {
+        if self.busy.swap(true, Ordering::Acquire) {
+            return None;
+        }
+
+        let base_trusted = self.trusted.load(Ordering::Relaxed);
+        let base_monotonic = self.monotonic.load(Ordering::Relaxed);
+        self.busy.store(false, Ordering::Release);
+
+        if base_trusted == UNSET || trusted < base_trusted {
+            return None;
+        }
+
+        let elapsed = trusted - base_trusted;
+        let host = time.monotonic as i128 - base_monotonic as i128;
+        let tolerance =
+            self.threshold as i128 + (elapsed / NSEC_PER_SEC) as i128 * self.drift as i128;
+
+        if (host - elapsed as i128).abs() > tolerance {
+            return None;
+        }
+
+        Some(Timestamps {
+            realtime: time.realtime,
+            monotonic: self.clamp(time.monotonic),
+        })
+    }
+
+    /// Returns the maximum of `monotonic` and the last returned monotonic time.
+    #[inline]
+    fn clamp(&self, monotonic: u64) -> u64 {
+        self.last
+            .fetch_max(monotonic, Ordering::Relaxed)
+            .max(monotonic)
+    }
+}
+
+/// Converts `tp` to nanoseconds, if it is not negative.
+#[inline]
+pub fn timespec_nanos(tp: &timespec) -> Option<u64> {
+    let sec = u64::try_from(tp.tv_sec).ok()?;
+    let nsec = u64::try_from(tp.tv_nsec).ok()?;
+    sec.checked_mul(NSEC_PER_SEC)?.checked_add(nsec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: u64 = NSEC_PER_SEC;
+
+    #[test]
+    fn observe() {
+        // 1s absolute, 1ms per second relative tolerance
+        let detector = SkewDetector::new(SEC, SEC / 1000);
+
+        // The first observation establishes the baseline
+        assert_eq!(detector.observe(100 * SEC, 1_000 * SEC), None);
+
+        // Within the tolerance
+        assert_eq!(detector.observe(110 * SEC, 1_010 * SEC + SEC / 2), None);
+        assert_eq!(detector.observe(1_100 * SEC, 2_001 * SEC + SEC / 2), None);
+
+        // Host clock set back
+        let skew = detector.observe(1_101 * SEC, 1_000 * SEC).unwrap();
+        assert_eq!(skew.trusted, 1_001 * SEC);
+        assert_eq!(skew.host, 0);
+        assert_eq!(skew.skew(), -(1_001 * SEC as i128));
+        assert_eq!(skew.count, 1);
+
+        // New baseline
+        assert_eq!(detector.observe(1_102 * SEC, 1_001 * SEC), None);
+
+        // Host clock set forward
+        let skew = detector.observe(1_103 * SEC, 5_000 * SEC).unwrap();
+        assert_eq!(skew.skew(), 3_999 * SEC as i128 - SEC as i128);
+        assert_eq!(skew.count, 2);
+        assert_eq!(detector.skews(), 2);
+    }
+
+    #[test]
+    fn validate() {
+        // 1ms absolute, 1ms per second relative tolerance
+        let validator = TimeValidator::new(SEC / 1000, SEC / 1000);
+        let time = |monotonic| Timestamps {
+            realtime: 1_000 * SEC + monotonic,
+            monotonic,
+        };
+
+        // Without a baseline every snapshot is rejected
+        assert_eq!(validator.validate(100 * SEC, time(10 * SEC)), None);
+
+        assert_eq!(validator.rebase(100 * SEC, 10 * SEC), 10 * SEC);
+
+        // Within the tolerance
+        assert_eq!(
+            validator.validate(101 * SEC, time(11 * SEC)),
+            Some(time(11 * SEC))
+        );
+        assert_eq!(
+            validator.validate(110 * SEC, time(20 * SEC + SEC / 200)),
+            Some(time(20 * SEC + SEC / 200))
+        );
+
+        // Stale page
+        assert_eq!(validator.validate(111 * SEC, time(20 * SEC)), None);
+
+        // Page running ahead
+        assert_eq!(validator.validate(111 * SEC, time(30 * SEC)), None);
+
+        // Monotonic times never go backwards
+        assert_eq!(validator.rebase(112 * SEC, 15 * SEC), 20 * SEC + SEC / 200);
+        assert_eq!(
+            validator.validate(112 * SEC + SEC / 2000, time(15 * SEC)),
+            Some(Timestamps {
+                realtime: 1_015 * SEC,
+                monotonic: 20 * SEC + SEC / 200,
+            })
+        );
+    }
+
+    #[test]
+    fn nanos() {
+        let tp = timespec {
+            tv_sec: 2,
+            tv_nsec: 3,
+        };
+        assert_eq!(timespec_nanos(&tp), Some(2 * SEC + 3));
+
+        let tp = timespec {
+            tv_sec: -1,
+            tv_nsec: 0,
+        };
+        assert_eq!(timespec_nanos(&tp), None);
+    }
+}
@@ -3,22 +3,27 @@
 use super::alloc::{Alloc, Allocator, Collect, Commit, Committer};
 use super::call::kind;
 use super::syscall::types::{MremapFlags, SockaddrInput, SockaddrOutput, SockoptInput};
-use super::{enarxcall, gdbcall, syscall, Call, Platform, ThreadLocalStorage, SIGRTMAX};
+use super::{
+    enarxcall, gdbcall, syscall, timespec_nanos, zeroize_words, Call, Platform, SpinWait,
+    ThreadLocalStorage, CALIBRATION_EXITS, SIGRTMAX,
+};
 use crate::item::enarxcall::sgx;
 use crate::item::syscall::sigaction;
 use crate::libc::{
-    clockid_t, epoll_event, gid_t, mode_t, off_t, pid_t, pollfd, sigset_t, stack_t, stat, timespec,
-    uid_t, utsname, CloneFlags, Ioctl, SYS_accept, SYS_accept4, SYS_arch_prctl, SYS_bind, SYS_brk,
-    SYS_clock_getres, SYS_clock_gettime, SYS_clone, SYS_close, SYS_connect, SYS_dup, SYS_dup2,
-    SYS_dup3, SYS_epoll_create1, SYS_epoll_ctl, SYS_epoll_pwait, SYS_epoll_wait, SYS_eventfd2,
-    SYS_exit, SYS_exit_group, SYS_fcntl, SYS_fstat, SYS_futex, SYS_getegid, SYS_geteuid,
-    SYS_getgid, SYS_getpid, SYS_getrandom, SYS_getsockname, SYS_getuid, SYS_ioctl, SYS_listen,
-    SYS_madvise, SYS_mmap, SYS_mprotect, SYS_mremap, SYS_munmap, SYS_nanosleep, SYS_open, SYS_poll,
-    SYS_read, SYS_readlink, SYS_readv, SYS_recvfrom, SYS_rt_sigaction, SYS_rt_sigprocmask,
-    SYS_sendto, SYS_set_tid_address, SYS_setsockopt, SYS_sigaltstack, SYS_socket, SYS_sync,
-    SYS_uname, SYS_write, SYS_writev, CLOCK_MONOTONIC, EFAULT, EINVAL, ENOSYS, ENOTSUP, FIONBIO,
-    FIONREAD, FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAIT_BITSET, FUTEX_WAKE, MAP_ANONYMOUS,
-    MAP_PRIVATE, MREMAP_DONTUNMAP, MREMAP_FIXED, MREMAP_MAYMOVE, PROT_EXEC, PROT_READ, PROT_WRITE,
+    clockid_t, epoll_event, gid_t, mode_t, off_t, pid_t, pollfd, sigset_t, stack_t, stat, termios,
+    timespec, uid_t, utsname, winsize, CloneFlags, Ioctl, SYS_accept, SYS_accept4, SYS_arch_prctl,
+    SYS_bind, SYS_brk, SYS_clock_getres, SYS_clock_gettime, SYS_clone, SYS_close, SYS_connect,
+    SYS_dup, SYS_dup2, SYS_dup3, SYS_epoll_create1, SYS_epoll_ctl, SYS_epoll_pwait, SYS_epoll_wait,
+    SYS_eventfd2, SYS_exit, SYS_exit_group, SYS_fcntl, SYS_fstat, SYS_futex, SYS_getegid,
+    SYS_geteuid, SYS_getgid, SYS_getpid, SYS_getrandom, SYS_getsockname, SYS_getuid, SYS_ioctl,
+    SYS_listen, SYS_madvise, SYS_mmap, SYS_mprotect, SYS_mremap, SYS_munmap, SYS_nanosleep,
+    SYS_open, SYS_poll, SYS_pread64, SYS_read, SYS_readlink, SYS_readv, SYS_recvfrom,
+    SYS_rt_sigaction, SYS_rt_sigprocmask, SYS_sendfile, SYS_sendto, SYS_set_tid_address,
+    SYS_setsockopt, SYS_shutdown, SYS_sigaltstack, SYS_socket, SYS_sync, SYS_umask, SYS_uname,
+    SYS_write, SYS_writev, CLOCK_MONOTONIC, CLOCK_REALTIME, EFAULT, EINVAL, ENOMEM, ENOSYS, ENOTTY,
+    EOPNOTSUPP, FIONBIO, FIONREAD, FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAIT_BITSET, FUTEX_WAKE,
+    MAP_ANONYMOUS, MAP_PRIVATE, MREMAP_DONTUNMAP, MREMAP_FIXED, MREMAP_MAYMOVE, PROT_EXEC,
+    PROT_READ, PROT_WRITE, TCGETS, TCSETS, TCSETSF, TCSETSW, TIOCGWINSZ,
 };
 use crate::{item, Result};
 
@@ -51,8 +56,14 @@ pub trait Handler {
     /// - [`syscall::Write`]
     /// - [`gdbcall::Read`]
     /// - [`gdbcall::Write`]
+    ///
+    /// Tuples of up to 4 calls are queued into the block together and executed by the host
+    /// in order in a single exit, e.g. a `writev` followed by a `clock_gettime`. Fails with
+    /// `ENOMEM`, if they do not fit into the block together.
     #[inline]
     fn execute<'a, K: kind::Kind, T: Call<'a, K>>(&mut self, call: T) -> Result<T::Collected> {
+        // The time of the last wait is only current until the next exit
+        self.thread_local_storage().wait_time = None;
         let mut alloc = Alloc::new(self.block_mut()).stage();
         let ((call, len), mut end_ref) =
             alloc.reserve_input(|alloc| alloc.section(|alloc| call.stage(alloc)))?;
@@ -76,6 +87,105 @@ pub trait Handler {
         Ok(call.collect(&alloc))
     }
 
+    /// Zeroizes sensitive guest state before the keep is torn down.
+    ///
+    /// The default implementation zeroizes the untrusted sallyport block, so the host
+    /// can verify the scrubbing by checking that nothing follows the `exit_group` request.
+    /// Implementations should additionally zeroize any key material and other sensitive
+    /// memory, which would otherwise survive in reclaimed pages.
+    #[inline]
+    fn scrub(&mut self) {
+        zeroize_words(self.block_mut());
+    }
+
+    /// Checks the wall time `host` provided by the host against a trusted clock.
+    ///
+    /// Implementations with access to a trusted monotonic clock should feed both times
+    /// into a [`SkewDetector`](super::SkewDetector) and report detected skews.
+    /// The default implementation does nothing.
+    #[inline]
+    fn check_clock(&mut self, _host: &timespec) {}
+
+    /// Returns the spin-then-block strategy of blocking waits.
+    ///
+    /// The default implementation returns `None`, so waits block right away.
+    #[inline]
+    fn spin_wait(&self) -> Option<&'static SpinWait> {
+        None
+    }
+
+    /// Returns whether blocking waits read the monotonic clock in their exit.
+    ///
+    /// Event loops read the monotonic clock right after every wait to expire their timers.
+    /// If enabled, the exit of a blocking [`poll`](Handler::poll),
+    /// [`epoll_wait`](Handler::epoll_wait) or [`epoll_pwait`](Handler::epoll_pwait) also reads
+    /// [`CLOCK_MONOTONIC`], and a [`clock_gettime`](Handler::clock_gettime) of it right after the
+    /// wait returns that time without an exit, as if the thread was descheduled between both.
+    ///
+    /// The default implementation returns `false`.
+    #[inline]
+    fn clock_after_wait(&self) -> bool {
+        false
+    }
+
+    /// Measures the cost of an exit on the host monotonic clock and calibrates
+    /// [`spin_wait`](Handler::spin_wait) with it.
+    ///
+    /// Shims call this once at startup. Every measured `clock_gettime` is an exit, even if the
+    /// shim reads the clock without one otherwise. The host can skew the measurement, but like
+    /// delaying any exit, that only changes for how long the Keep spins.
+    fn calibrate_spin_wait(&mut self) {
+        let spin = match self.spin_wait() {
+            Some(spin) => spin,
+            None => return,
+        };
+
+        let mut start = None;
+        let mut elapsed = 0;
+        for _ in 0..=CALIBRATION_EXITS {
+            let mut tp = timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            let ret = self.execute(syscall::ClockGettime {
+                clockid: CLOCK_MONOTONIC,
+                tp: &mut tp,
+            });
+            if !matches!(ret, Ok(Ok(()))) {
+                return;
+            }
+            let now = match timespec_nanos(&tp) {
+                Some(now) => now,
+                None => return,
+            };
+            match start {
+                None => start = Some(now),
+                Some(start) => elapsed = now.saturating_sub(start),
+            }
+        }
+        spin.calibrate(elapsed / CALIBRATION_EXITS);
+    }
+
+    /// Polls by `poll` without blocking for the number of polls of
+    /// [`spin_wait`](Handler::spin_wait) and returns the first result, which is ready or failed.
+    ///
+    /// Returns `None`, if the caller has to block.
+    #[inline]
+    fn spin(&mut self, mut poll: impl FnMut(&mut Self) -> Result<c_int>) -> Option<Result<c_int>> {
+        let spin = self.spin_wait()?;
+        for _ in 0..spin.polls() {
+            match poll(self) {
+                Ok(0) => {}
+                ret => {
+                    spin.record(true);
+                    return Some(ret);
+                }
+            }
+        }
+        spin.record(false);
+        None
+    }
+
     /// Loops infinitely trying to exit.
     #[inline]
     fn attacked(&mut self) -> ! {
@@ -134,9 +244,23 @@ pub trait Handler {
     }
 
     /// Executes [`clock_gettime`](https://man7.org/linux/man-pages/man2/clock_gettime.2.html) syscall akin to [`libc::clock_gettime`].
+    ///
+    /// The wall time returned by the host for [`CLOCK_REALTIME`] is passed to
+    /// [`check_clock`](Handler::check_clock). [`CLOCK_MONOTONIC`] right after a blocking wait
+    /// is served without an exit, see [`clock_after_wait`](Handler::clock_after_wait).
     #[inline]
     fn clock_gettime(&mut self, clockid: clockid_t, tp: &mut timespec) -> Result<()> {
-        self.execute(syscall::ClockGettime { clockid, tp })?
+        if clockid == CLOCK_MONOTONIC {
+            if let Some(now) = self.thread_local_storage().wait_time.take() {
+                *tp = now;
+                return Ok(());
+            }
+        }
+        self.execute(syscall::ClockGettime { clockid, tp })??;
+        if clockid == CLOCK_REALTIME {
+            self.check_clock(tp);
+        }
+        Ok(())
     }
 
     /// Executes [`clone`](https://man7.org/linux/man-pages/man2/clone.2.html) syscall akin to [`libc::clone`].
@@ -201,6 +325,8 @@ pub trait Handler {
     }
 
     /// Executes [`epoll_wait`](https://man7.org/linux/man-pages/man2/epoll_wait.2.html) syscall akin to [`libc::epoll_wait`].
+    ///
+    /// A blocking wait [`spin`](Handler::spin)s first.
     #[inline]
     fn epoll_wait(
         &mut self,
@@ -208,6 +334,43 @@ pub trait Handler {
         events: &mut [epoll_event],
         timeout: c_int,
     ) -> Result<c_int> {
+        if timeout != 0 {
+            let spun = self.spin(|h| {
+                h.execute(syscall::EpollWait {
+                    epfd,
+                    events: &mut *events,
+                    timeout: 0,
+                })?
+                .unwrap_or_else(|| h.attacked())
+            });
+            if let Some(ret) = spun {
+                return ret;
+            }
+        }
+        if self.clock_after_wait() {
+            let mut now = timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            let clock = syscall::ClockGettime {
+                clockid: CLOCK_MONOTONIC,
+                tp: &mut now,
+            };
+            let wait = syscall::EpollWait {
+                epfd,
+                events: &mut *events,
+                timeout,
+            };
+            match self.execute((wait, clock)) {
+                Ok((ret, clock)) => {
+                    self.thread_local_storage().wait_time = clock.ok().map(|()| now);
+                    return ret.unwrap_or_else(|| self.attacked());
+                }
+                // The wait does not leave room for the clock in the block
+                Err(ENOMEM) => {}
+                Err(e) => return Err(e),
+            }
+        }
         self.execute(syscall::EpollWait {
             epfd,
             events,
@@ -217,6 +380,8 @@ pub trait Handler {
     }
 
     /// Executes [`epoll_pwait`](https://man7.org/linux/man-pages/man2/epoll_pwait.2.html) syscall akin to [`libc::epoll_pwait`].
+    ///
+    /// A blocking wait [`spin`](Handler::spin)s first.
     #[inline]
     fn epoll_pwait(
         &mut self,
@@ -225,6 +390,45 @@ pub trait Handler {
         timeout: c_int,
         sigmask: &sigset_t,
     ) -> Result<c_int> {
+        if timeout != 0 {
+            let spun = self.spin(|h| {
+                h.execute(syscall::EpollPwait {
+                    epfd,
+                    events: &mut *events,
+                    timeout: 0,
+                    sigmask,
+                })?
+                .unwrap_or_else(|| h.attacked())
+            });
+            if let Some(ret) = spun {
+                return ret;
+            }
+        }
+        if self.clock_after_wait() {
+            let mut now = timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            let clock = syscall::ClockGettime {
+                clockid: CLOCK_MONOTONIC,
+                tp: &mut now,
+            };
+            let wait = syscall::EpollPwait {
+                epfd,
+                events: &mut *events,
+                timeout,
+                sigmask,
+            };
+            match self.execute((wait, clock)) {
+                Ok((ret, clock)) => {
+                    self.thread_local_storage().wait_time = clock.ok().map(|()| now);
+                    return ret.unwrap_or_else(|| self.attacked());
+                }
+                // The wait does not leave room for the clock in the block
+                Err(ENOMEM) => {}
+                Err(e) => return Err(e),
+            }
+        }
         self.execute(syscall::EpollPwait {
             epfd,
             events,
@@ -248,8 +452,11 @@ pub trait Handler {
     }
 
     /// Executes [`exit_group`](https://man7.org/linux/man-pages/man2/exit_group.2.html).
+    ///
+    /// [`scrub`](Handler::scrub) is called before the keep teardown is requested from the host.
     #[inline]
     fn exit_group(&mut self, status: c_int) -> Result<()> {
+        self.scrub();
         self.execute(syscall::ExitGroup { status })??;
         self.attacked()
     }
@@ -304,7 +511,7 @@ pub trait Handler {
             }
             FUTEX_WAIT_BITSET => {
                 if val3 != !0u32 {
-                    return Err(ENOTSUP);
+                    return Err(EOPNOTSUPP);
                 }
 
                 while uaddr.load(Ordering::Relaxed) == val {
@@ -318,7 +525,7 @@ pub trait Handler {
                 // For now return 1 or 0 in the error case.
                 self.unpark().map(|_| 1).or(Ok(0))
             }
-            _ => Err(ENOTSUP),
+            _ => Err(ENOSYS),
         }
     }
 
@@ -473,7 +680,7 @@ pub trait Handler {
 
                 Ok(NonNull::new(new_slice.as_ptr() as *mut _).unwrap())
             }
-            _ => Err(ENOTSUP),
+            _ => Err(EOPNOTSUPP),
         }
     }
 
@@ -503,12 +710,56 @@ pub trait Handler {
     }
 
     /// Executes [`poll`](https://man7.org/linux/man-pages/man2/poll.2.html) syscall akin to [`libc::poll`].
+    ///
+    /// A blocking wait [`spin`](Handler::spin)s first.
     #[inline]
     fn poll(&mut self, fds: &mut [pollfd], timeout: c_int) -> Result<c_int> {
+        if timeout != 0 {
+            let spun = self.spin(|h| {
+                h.execute(syscall::Poll {
+                    fds: &mut *fds,
+                    timeout: 0,
+                })?
+                .unwrap_or_else(|| h.attacked())
+            });
+            if let Some(ret) = spun {
+                return ret;
+            }
+        }
+        if self.clock_after_wait() {
+            let mut now = timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            let clock = syscall::ClockGettime {
+                clockid: CLOCK_MONOTONIC,
+                tp: &mut now,
+            };
+            let wait = syscall::Poll {
+                fds: &mut *fds,
+                timeout,
+            };
+            match self.execute((wait, clock)) {
+                Ok((ret, clock)) => {
+                    self.thread_local_storage().wait_time = clock.ok().map(|()| now);
+                    return ret.unwrap_or_else(|| self.attacked());
+                }
+                // The wait does not leave room for the clock in the block
+                Err(ENOMEM) => {}
+                Err(e) => return Err(e),
+            }
+        }
         self.execute(syscall::Poll { fds, timeout })?
             .unwrap_or_else(|| self.attacked())
     }
 
+    /// Executes [`pread64`](https://man7.org/linux/man-pages/man2/pread64.2.html) syscall akin to [`libc::pread64`].
+    #[inline]
+    fn pread64(&mut self, fd: c_int, buf: &mut [u8], offset: off_t) -> Result<c_size_t> {
+        self.execute(syscall::Pread64 { fd, buf, offset })?
+            .unwrap_or_else(|| self.attacked())
+    }
+
     /// Executes [`read`](https://man7.org/linux/man-pages/man2/read.2.html) syscall akin to [`libc::read`].
     #[inline]
     fn read(&mut self, fd: c_int, buf: &mut [u8]) -> Result<c_size_t> {
@@ -610,6 +861,19 @@ pub trait Handler {
             .unwrap_or_else(|| self.attacked())
     }
 
+    /// Executes [`sendfile`](https://man7.org/linux/man-pages/man2/sendfile.2.html) syscall akin to [`libc::sendfile`]
+    /// with a `NULL` offset.
+    ///
+    /// The data is copied by the host and never passes through the guest.
+    #[inline]
+    fn sendfile(&mut self, out_fd: c_int, in_fd: c_int, count: c_size_t) -> Result<c_size_t> {
+        self.execute(syscall::Sendfile {
+            out_fd,
+            in_fd,
+            count,
+        })?
+    }
+
     /// Executes [`sendto`](https://man7.org/linux/man-pages/man2/sendto.2.html) syscall akin to [`libc::sendto`].
     #[inline]
     fn sendto<'a>(
@@ -651,6 +915,12 @@ pub trait Handler {
         self.execute(syscall::SetTidAddress { tidptr })
     }
 
+    /// Executes [`shutdown`](https://man7.org/linux/man-pages/man2/shutdown.2.html) syscall akin to [`libc::shutdown`].
+    #[inline]
+    fn shutdown(&mut self, sockfd: c_int, how: c_int) -> Result<()> {
+        self.execute(syscall::Shutdown { sockfd, how })?
+    }
+
     /// Executes [`sigaltstack`](https://man7.org/linux/man-pages/man2/sigaltstack.2.html) syscall akin to [`libc::sigaltstack`].
     #[inline]
     fn sigaltstack(&mut self, ss: Option<&stack_t>, old_ss: Option<&mut stack_t>) -> Result<()> {
@@ -673,6 +943,14 @@ pub trait Handler {
         self.execute(syscall::Sync)?
     }
 
+    /// Executes [`umask`](https://man7.org/linux/man-pages/man2/umask.2.html) syscall akin to [`libc::umask`].
+    ///
+    /// The mask applies to the files created by the host on behalf of the guest.
+    #[inline]
+    fn umask(&mut self, mask: mode_t) -> Result<mode_t> {
+        self.execute(syscall::Umask { mask })?.map(|ret| ret as _)
+    }
+
     /// Executes [`uname`](https://man7.org/linux/man-pages/man2/uname.2.html) syscall akin to [`libc::uname`].
     #[inline]
     fn uname(&mut self, buf: &mut utsname) -> Result<()> {
@@ -713,6 +991,10 @@ pub trait Handler {
         registers: [usize; 7],
     ) -> Result<[usize; 2]> {
         let [num, argv @ ..] = registers;
+        if num != SYS_clock_gettime as _ {
+            // The time of the last wait is only current right after it
+            self.thread_local_storage().wait_time = None;
+        }
         #[allow(non_upper_case_globals)]
         match (num as _, argv) {
             (SYS_accept, [sockfd, addr, addrlen, ..]) => {
@@ -830,7 +1112,7 @@ pub trait Handler {
                         }
                     }
                     FUTEX_WAKE => None,
-                    _ => return Err(ENOTSUP),
+                    _ => return Err(ENOSYS),
                 };
 
                 let uaddr = platform.validate_mut(uaddr)?;
@@ -862,7 +1144,21 @@ pub trait Handler {
                                 size_of::<c_int>(),
                             ))
                         })?,
-                        _ => return Err(ENOTSUP),
+                        TIOCGWINSZ => platform.validate_mut::<winsize>(argp).map(|argp| {
+                            Some(slice::from_raw_parts_mut(
+                                argp as *mut _ as _,
+                                size_of::<winsize>(),
+                            ))
+                        })?,
+                        TCGETS | TCSETS | TCSETSW | TCSETSF => {
+                            platform.validate_mut::<termios>(argp).map(|argp| {
+                                Some(slice::from_raw_parts_mut(
+                                    argp as *mut _ as _,
+                                    size_of::<termios>(),
+                                ))
+                            })?
+                        }
+                        _ => return Err(ENOTTY),
                     }
                 };
                 self.ioctl(fd as _, request as _, argp)
@@ -944,6 +1240,10 @@ pub trait Handler {
                 let fds = platform.validate_slice_mut(fds, nfds)?;
                 self.poll(fds, timeout as _).map(|ret| [ret as _, 0])
             }
+            (SYS_pread64, [fd, buf, count, offset, ..]) => {
+                let buf = platform.validate_slice_mut(buf, count)?;
+                self.pread64(fd as _, buf, offset as _).map(|ret| [ret, 0])
+            }
             (SYS_read, [fd, buf, count, ..]) => {
                 let buf = platform.validate_slice_mut(buf, count)?;
                 self.read(fd as _, buf).map(|ret| [ret, 0])
@@ -999,6 +1299,14 @@ pub trait Handler {
                 self.rt_sigprocmask(how as _, set, oldset, sigsetsize as _)
                     .map(|_| [0, 0])
             }
+            (SYS_sendfile, [out_fd, in_fd, offset, count, ..]) => {
+                if offset != 0 {
+                    // Offsets in guest memory are not supported
+                    return Err(EOPNOTSUPP);
+                }
+                self.sendfile(out_fd as _, in_fd as _, count)
+                    .map(|ret| [ret, 0])
+            }
             (SYS_sendto, [sockfd, buf, len, flags, dest_addr, addrlen]) => {
                 let buf = platform.validate_slice(buf, len)?;
                 if dest_addr == 0 {
@@ -1035,10 +1343,14 @@ pub trait Handler {
                 };
                 self.sigaltstack(ss, old_ss).map(|_| [0, 0])
             }
+            (SYS_shutdown, [sockfd, how, ..]) => {
+                self.shutdown(sockfd as _, how as _).map(|_| [0, 0])
+            }
             (SYS_socket, [domain, typ, protocol, ..]) => self
                 .socket(domain as _, typ as _, protocol as _)
                 .map(|ret| [ret as _, 0]),
             (SYS_sync, ..) => self.sync().map(|_| [0, 0]),
+            (SYS_umask, [mask, ..]) => self.umask(mask as _).map(|ret| [ret as _, 0]),
             (SYS_uname, [buf, ..]) => {
                 let buf = platform.validate_mut(buf)?;
                 self.uname(buf).map(|_| [0, 0])
@@ -1123,6 +1435,33 @@ pub trait Handler {
         })?
     }
 
+    /// Reads at most `count` bytes from the host file descriptor `fd` into the bounce buffer at
+    /// the guest physical address `addr` and returns the number of bytes read.
+    ///
+    /// Fails with `ENOSYS`, if the host does not support bounce buffers.
+    #[inline]
+    fn bounce_read(&mut self, fd: c_int, addr: usize, count: usize) -> Result<usize> {
+        self.execute(enarxcall::BounceRead { fd, addr, count })?
+    }
+
+    /// Writes at most `count` bytes from the bounce buffer at the guest physical address `addr`
+    /// to the host file descriptor `fd` and returns the number of bytes written.
+    ///
+    /// Fails with `ENOSYS`, if the host does not support bounce buffers.
+    #[inline]
+    fn bounce_write(&mut self, fd: c_int, addr: usize, count: usize) -> Result<usize> {
+        self.execute(enarxcall::BounceWrite { fd, addr, count })?
+    }
+
+    /// Registers the [`TimePage`](crate::item::enarxcall::time::TimePage) at the guest physical
+    /// address `addr`, which the host updates from then on.
+    ///
+    /// Fails with `ENOSYS`, if the host does not support timestamp pages.
+    #[inline]
+    fn register_time_page(&mut self, addr: usize) -> Result<()> {
+        self.execute(enarxcall::RegisterTimePage { addr })?
+    }
+
     /// Execute `cpuid` instruction storing the result in `result`.
     #[inline]
     fn cpuid(&mut self, leaf: u32, sub_leaf: u32, result: &mut CpuidResult) -> Result<()> {
@@ -1146,6 +1485,15 @@ pub trait Handler {
         self.execute(enarxcall::GetSgxQuoteSize)?
     }
 
+    /// Requests the end of the heap, which the host added to the SGX enclave at build time, from
+    /// the host.
+    ///
+    /// Returns `0`, if the heap is added on demand with EDMM.
+    #[inline]
+    fn get_sgx_static_heap(&mut self) -> Result<usize> {
+        self.execute(enarxcall::GetSgxStaticHeap)?
+    }
+
     /// Requests [SGX `TargetInfo`](sgx::TargetInfo) from the host.
     #[inline]
     fn get_sgx_target_info(&mut self, info: &mut sgx::TargetInfo) -> Result<()> {
@@ -55,11 +55,17 @@
 pub mod alloc;
 pub mod call;
 
+mod clock;
 mod handler;
 mod platform;
+mod scrub;
 mod tls;
+mod wait;
 
 pub use call::{enarxcall, gdbcall, syscall, Call};
+pub use clock::*;
 pub use handler::*;
 pub use platform::*;
+pub use scrub::*;
 pub use tls::*;
+pub use wait::*;
@@ -0,0 +1,29 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Scrubbing of sensitive guest memory.
+
+use core::ptr::write_volatile;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Overwrites `buf` with zeroes.
+///
+/// Unlike a plain `fill(0)`, the writes are volatile and are therefore never
+/// optimized away, even if `buf` is not read afterwards.
+#[inline]
+pub fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe { write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Overwrites `buf` with zeroes.
+///
+/// See [`zeroize`].
+#[inline]
+pub fn zeroize_words(buf: &mut [usize]) {
+    for word in buf.iter_mut() {
+        unsafe { write_volatile(word, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
@@ -1,6 +1,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::item::syscall::sigaction;
+use crate::libc::timespec;
 
 use core::ffi::c_int;
 
@@ -9,6 +10,9 @@ pub(super) const SIGRTMAX: c_int = 64;
 /// Thread-local storage shared between [`Handler`](super::Handler) instances.
 pub struct ThreadLocalStorage {
     pub(super) actions: [Option<sigaction>; SIGRTMAX as _],
+    /// Monotonic time read in the exit of the last blocking wait, see
+    /// [`clock_after_wait`](super::Handler::clock_after_wait).
+    pub(super) wait_time: Option<timespec>,
 }
 
 impl ThreadLocalStorage {
@@ -16,6 +20,7 @@ impl ThreadLocalStorage {
     pub const fn new() -> Self {
         Self {
             actions: [None; SIGRTMAX as _],
+            wait_time: None,
         }
     }
 }
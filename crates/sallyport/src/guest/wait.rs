@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Adaptive spin-then-block strategy of blocking waits for readiness.
+
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Maximum number of nonblocking polls of a single wait.
+pub const MAX_SPIN_POLLS: u32 = 256;
+
+/// Number of exits measured by [`Handler::calibrate_spin_wait`](super::Handler::calibrate_spin_wait).
+pub const CALIBRATION_EXITS: u64 = 16;
+
+/// Adaptive spin-then-block strategy of blocking waits, e.g. `poll` and `epoll_wait`.
+///
+/// Blocking a host thread and waking it up again costs more than a few exits, so a blocking
+/// wait first polls without blocking for a spin window, before it blocks. The window in
+/// nanoseconds is divided by the cost of an exit measured at startup, which differs widely
+/// between the backends, to get the maximum number of nonblocking polls.
+///
+/// The number of polls adapts to the workload: it doubles up to the maximum, whenever spinning
+/// ends a wait, and halves down to a single poll, whenever it does not, so an idle Keep does
+/// not burn CPU. Without a calibration or with an empty window, waits block right away.
+///
+/// The strategy is shared by all threads.
+#[derive(Debug)]
+pub struct SpinWait {
+    window: AtomicU64,
+    exit: AtomicU64,
+    polls: AtomicU32,
+}
+
+impl SpinWait {
+    /// Creates a strategy spinning for `window` nanoseconds, once calibrated.
+    pub const fn new(window: u64) -> Self {
+        Self {
+            window: AtomicU64::new(window),
+            exit: AtomicU64::new(0),
+            polls: AtomicU32::new(0),
+        }
+    }
+
+    /// Sets the spin window to `window` nanoseconds, which disables spinning, if `0`.
+    pub fn set_window(&self, window: u64) {
+        self.window.store(window, Ordering::Relaxed);
+        self.polls.store(self.max_polls(), Ordering::Relaxed);
+    }
+
+    /// Calibrates the strategy with the cost of an exit of `exit` nanoseconds.
+    pub fn calibrate(&self, exit: u64) {
+        self.exit.store(exit.max(1), Ordering::Relaxed);
+        self.polls.store(self.max_polls(), Ordering::Relaxed);
+    }
+
+    /// Returns the maximum number of nonblocking polls of a wait.
+    pub fn max_polls(&self) -> u32 {
+        match self.exit.load(Ordering::Relaxed) {
+            0 => 0,
+            exit => (self.window.load(Ordering::Relaxed) / exit).min(MAX_SPIN_POLLS.into()) as u32,
+        }
+    }
+
+    /// Returns the number of nonblocking polls of the next wait.
+    #[inline]
+    pub fn polls(&self) -> u32 {
+        self.polls.load(Ordering::Relaxed)
+    }
+
+    /// Adapts the number of polls to whether spinning ended the last wait.
+    pub fn record(&self, ready: bool) {
+        let max = self.max_polls();
+        let _ = self
+            .polls
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |polls| {
+                let polls = if ready {
+                    polls.saturating_mul(2)
+                } else {
+                    polls / 2
+                };
+                Some(polls.max(1).min(max))
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adapt() {
+        let spin = SpinWait::new(50_000);
+
+        // Waits block right away without a calibration
+        assert_eq!(spin.max_polls(), 0);
+        spin.record(true);
+        assert_eq!(spin.polls(), 0);
+
+        spin.calibrate(2_000);
+        assert_eq!(spin.max_polls(), 25);
+        assert_eq!(spin.polls(), 25);
+
+        // Idle waits fall back to a single poll
+        for _ in 0..8 {
+            spin.record(false);
+        }
+        assert_eq!(spin.polls(), 1);
+
+        // Busy waits spin up to the window again
+        spin.record(true);
+        spin.record(true);
+        assert_eq!(spin.polls(), 4);
+        for _ in 0..8 {
+            spin.record(true);
+        }
+        assert_eq!(spin.polls(), 25);
+
+        // Cheap exits are capped
+        spin.calibrate(0);
+        assert_eq!(spin.max_polls(), MAX_SPIN_POLLS);
+
+        spin.set_window(0);
+        assert_eq!(spin.polls(), 0);
+        spin.record(false);
+        assert_eq!(spin.polls(), 0);
+    }
+}
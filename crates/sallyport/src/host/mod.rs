@@ -7,7 +7,7 @@ mod enarxcall;
 #[cfg(not(miri))]
 mod syscall;
 
-use crate::item::Item;
+use crate::item::{Item, Kind};
 use crate::libc::{EFAULT, EOVERFLOW};
 use crate::Result;
 
@@ -98,6 +98,32 @@ pub fn deref_aligned_slice<T>(data: &mut [u8], offset: usize, len: usize) -> Res
     deref_aligned(data, offset, len).map(|ptr| slice_from_raw_parts_mut(ptr, len))
 }
 
+/// Returns whether the guest has scrubbed `block`, i.e. whether nothing
+/// but zeroes follow the end of the items within it.
+///
+/// Guests scrub the block before requesting the keep teardown via `exit_group`,
+/// which allows the host to verify the completion of the scrubbing.
+pub fn is_scrubbed(block: &[usize]) -> bool {
+    let mut rest = block;
+    loop {
+        match rest {
+            [_, kind, tail @ ..] if *kind == Kind::End as usize => {
+                return tail.iter().all(|word| *word == 0)
+            }
+            [size, _, tail @ ..] => {
+                if *size % size_of::<usize>() != 0 {
+                    return false;
+                }
+                match tail.get(*size / size_of::<usize>()..) {
+                    Some(tail) => rest = tail,
+                    None => return false,
+                }
+            }
+            _ => return true,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -481,4 +507,25 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn is_scrubbed() {
+        let syscall = size_of::<Syscall>();
+        let words = syscall / size_of::<usize>();
+
+        let mut block = [0usize; 32];
+        assert!(super::is_scrubbed(&block));
+
+        block[0] = syscall;
+        block[1] = Kind::Syscall as _;
+        block[2] = libc::SYS_exit_group as _;
+        block[2 + words + 1] = Kind::End as _;
+        assert!(super::is_scrubbed(&block));
+
+        block[31] = 0xdead;
+        assert!(!super::is_scrubbed(&block));
+
+        block[0] = usize::MAX - 7;
+        assert!(!super::is_scrubbed(&block));
+    }
 }
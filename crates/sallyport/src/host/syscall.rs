@@ -492,6 +492,17 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
             .execute()
         }
 
+        item::Syscall {
+            num,
+            argv: [mask, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_umask as _ => Syscall {
+            num: libc::SYS_umask,
+            argv: [*mask],
+            ret: [ret],
+        }
+        .execute(),
+
         item::Syscall {
             num,
             argv: [fd, buf_offset, count, ..],
@@ -506,6 +517,20 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
             .execute();
         }
 
+        item::Syscall {
+            num,
+            argv: [fd, buf_offset, count, offset, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_pread64 as _ => {
+            let buf = deref::<u8>(data, *buf_offset, *count)?;
+            Syscall {
+                num: libc::SYS_pread64,
+                argv: [*fd, buf as _, *count, *offset],
+                ret: [ret],
+            }
+            .execute();
+        }
+
         item::Syscall {
             num,
             argv: [sockfd, buf_offset, len, flags, src_addr_offset, addrlen_offset],
@@ -525,6 +550,17 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
             .execute();
         }
 
+        item::Syscall {
+            num,
+            argv: [out_fd, in_fd, _, count, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_sendfile as _ => Syscall {
+            num: libc::SYS_sendfile,
+            argv: [*out_fd, *in_fd, null_mut::<libc::off_t>() as _, *count],
+            ret: [ret],
+        }
+        .execute(),
+
         item::Syscall {
             num,
             argv: [sockfd, buf_offset, len, flags, dest_addr_offset, addrlen],
@@ -569,6 +605,17 @@ pub(super) unsafe fn execute(call: &mut item::Syscall, data: &mut [u8]) -> Resul
             .execute();
         }
 
+        item::Syscall {
+            num,
+            argv: [sockfd, how, ..],
+            ret: [ret, ..],
+        } if *num == libc::SYS_shutdown as _ => Syscall {
+            num: libc::SYS_shutdown,
+            argv: [*sockfd, *how],
+            ret: [ret],
+        }
+        .execute(),
+
         item::Syscall {
             num,
             argv: [domain, typ, protocol, ..],
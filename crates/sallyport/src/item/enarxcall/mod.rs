@@ -4,6 +4,7 @@
 
 pub mod sev;
 pub mod sgx;
+pub mod time;
 
 use core::mem::size_of;
 
@@ -19,6 +20,22 @@ pub const SYS_GETATT: i64 = 0xEA01;
 #[allow(dead_code)]
 pub const SYS_GETKEY: i64 = 0xEA02;
 
+/// `get_clock_skews` syscall number used by the shim.
+///
+/// Returns the number of skews detected between the trusted clock of the shim
+/// and the wall time provided by the host in `rax` and `1` in `rdx`, if the host cannot
+/// influence the trusted clock, e.g. with the SNP Secure TSC, or `0` otherwise.
+/// Shims without a trusted clock, like the SGX shim, fail with `EOPNOTSUPP`.
+#[allow(dead_code)]
+pub const SYS_GETSKEW: i64 = 0xEA03;
+
+/// `set_spin_wait` syscall number used by the shim.
+///
+/// Sets the spin window of blocking waits of the shim to the nanoseconds passed in the first
+/// argument, overriding the default of the backend.
+#[allow(dead_code)]
+pub const SYS_SETSPIN: i64 = 0xEA04;
+
 /// Payload of an [`Item`](super::Item) of [`Kind::Enarxcall`](super::Kind::Enarxcall).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(C, align(8))]
@@ -83,6 +100,18 @@ pub enum Number {
 
     /// Spawn a new thread
     Spawn = 0x13,
+
+    /// Read from a file descriptor into a bounce buffer in guest memory.
+    BounceRead = 0x14,
+
+    /// Write to a file descriptor from a bounce buffer in guest memory.
+    BounceWrite = 0x15,
+
+    /// SGX static heap request call number.
+    GetSgxStaticHeap = 0x16,
+
+    /// Register a [`TimePage`](time::TimePage) in guest memory to be updated by the host.
+    RegisterTimePage = 0x17,
 }
 
 #[cfg(test)]
@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Timestamp page updated by the host.
+
+use core::hint::spin_loop;
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+
+/// Interval, in which the host updates the [`TimePage`], in nanoseconds.
+pub const UPDATE_INTERVAL: u64 = 1_000_000;
+
+/// Number of attempts to read a consistent snapshot of the [`TimePage`].
+const READ_ATTEMPTS: usize = 16;
+
+/// Page of guest memory shared with the host, which writes its clocks to it every
+/// [`UPDATE_INTERVAL`].
+///
+/// The page is a sequence lock: the host increments `seq` to an odd value before and to an even
+/// value after updating the timestamps. A `seq` of 0 marks a page, which was never written.
+/// The contents are untrusted and have to be validated by the guest.
+#[derive(Debug, Default)]
+#[repr(C, align(4096))]
+pub struct TimePage {
+    seq: AtomicU64,
+    realtime: AtomicU64,
+    monotonic: AtomicU64,
+}
+
+/// Snapshot of a [`TimePage`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timestamps {
+    /// `CLOCK_REALTIME` of the host in nanoseconds.
+    pub realtime: u64,
+
+    /// `CLOCK_MONOTONIC` of the host in nanoseconds.
+    pub monotonic: u64,
+}
+
+impl TimePage {
+    /// Writes the timestamps `time`, only called by the single writer on the host.
+    #[inline]
+    pub fn write(&self, time: Timestamps) {
+        let seq = self.seq.load(Ordering::Relaxed) & !1;
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.realtime.store(time.realtime, Ordering::Relaxed);
+        self.monotonic.store(time.monotonic, Ordering::Relaxed);
+        self.seq
+            .store(seq.wrapping_add(2).max(2), Ordering::Release);
+    }
+
+    /// Reads a consistent snapshot of the timestamps.
+    ///
+    /// Returns `None`, if the page was never written or no consistent snapshot could be read,
+    /// because the host kept updating it.
+    #[inline]
+    pub fn read(&self) -> Option<Timestamps> {
+        for _ in 0..READ_ATTEMPTS {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq == 0 {
+                return None;
+            }
+            if seq % 2 == 0 {
+                let time = Timestamps {
+                    realtime: self.realtime.load(Ordering::Relaxed),
+                    monotonic: self.monotonic.load(Ordering::Relaxed),
+                };
+                fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == seq {
+                    return Some(time);
+                }
+            }
+            spin_loop();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::mem::{align_of, size_of};
+
+    #[test]
+    fn layout() {
+        assert_eq!(size_of::<TimePage>(), 4096);
+        assert_eq!(align_of::<TimePage>(), 4096);
+    }
+
+    #[test]
+    fn read_write() {
+        let page = TimePage::default();
+        assert_eq!(page.read(), None);
+
+        let time = Timestamps {
+            realtime: 1_700_000_000_000_000_000,
+            monotonic: 42,
+        };
+        page.write(time);
+        assert_eq!(page.read(), Some(time));
+
+        // A write in progress is never read
+        page.seq.store(3, Ordering::Relaxed);
+        assert_eq!(page.read(), None);
+        page.write(time);
+        assert_eq!(page.seq.load(Ordering::Relaxed), 4);
+        assert_eq!(page.read(), Some(time));
+    }
+}
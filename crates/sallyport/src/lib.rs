@@ -14,6 +14,7 @@
 #![feature(slice_ptr_len)]
 
 pub mod elf;
+pub mod errno;
 pub mod guest;
 pub mod host;
 pub mod item;
@@ -53,5 +54,21 @@ pub const REQUIRES: [u8; VERSION.len() + 1] = {
     value
 };
 
+/// Exit status in `r8` of an SGX shim, which panicked and exited the enclave for good.
+///
+/// `r9` holds the FNV-1a hash of the source file of the panic and `r10` its line in the upper
+/// and its column in the lower 32 bits. Regular exit statuses are zero-extended 32-bit values.
+pub const SGX_PANIC: u64 = 0x7061_6e69_6321_0000;
+
 /// I/O port used to trigger an exit to the host (`#VMEXIT`) for KVM driven shims.
 pub const KVM_SYSCALL_TRIGGER_PORT: u16 = 0xFF;
+
+/// I/O port used by KVM driven shims to hand a block to the host without waiting for it.
+///
+/// The shim halts after the write and the host signals the completion of the block
+/// by injecting [`KVM_SYSCALL_COMPLETION_VECTOR`].
+pub const KVM_SYSCALL_DOORBELL_PORT: u16 = 0xFE;
+
+/// Interrupt vector injected by the host on completion of a block posted to
+/// [`KVM_SYSCALL_DOORBELL_PORT`].
+pub const KVM_SYSCALL_COMPLETION_VECTOR: u8 = 0x40;
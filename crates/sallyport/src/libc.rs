@@ -15,10 +15,11 @@
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
 
-use core::ffi::{c_char, c_int, c_long, c_short, c_size_t, c_uint, c_ulong, c_void};
+use core::ffi::{c_char, c_int, c_long, c_short, c_size_t, c_uint, c_ulong, c_ushort, c_void};
 
 pub type blkcnt_t = i64;
 pub type blksize_t = i64;
+pub type cc_t = u8;
 pub type clockid_t = i32;
 pub type dev_t = u64;
 pub type gid_t = u32;
@@ -32,6 +33,7 @@ pub type pid_t = i32;
 pub type sa_family_t = u16;
 pub type socklen_t = u32;
 pub type suseconds_t = i64;
+pub type tcflag_t = u32;
 pub type time_t = i64;
 pub type uid_t = u32;
 pub type Ioctl = i32;
@@ -149,6 +151,18 @@ pub struct stat {
     __unused: [c_long; 3],
 }
 
+/// The kernel `termios` structure used by the `TCGETS` and `TCSETS*` ioctls.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct termios {
+    pub c_iflag: tcflag_t,
+    pub c_oflag: tcflag_t,
+    pub c_cflag: tcflag_t,
+    pub c_lflag: tcflag_t,
+    pub c_line: cc_t,
+    pub c_cc: [cc_t; 19],
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct timespec {
@@ -163,6 +177,15 @@ pub struct timeval {
     pub tv_usec: suseconds_t,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct winsize {
+    pub ws_row: c_ushort,
+    pub ws_col: c_ushort,
+    pub ws_xpixel: c_ushort,
+    pub ws_ypixel: c_ushort,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct utsname {
@@ -175,6 +198,7 @@ pub struct utsname {
 }
 
 pub const AF_INET: c_int = 2;
+pub const CLOCK_REALTIME: clockid_t = 0;
 pub const CLOCK_MONOTONIC: clockid_t = 1;
 pub const CLONE_VM: c_uint = 0x00000100;
 pub const CLONE_FS: c_uint = 0x00000200;
@@ -200,22 +224,88 @@ pub const CLONE_NEWUSER: c_uint = 0x10000000;
 pub const CLONE_NEWPID: c_uint = 0x20000000;
 pub const CLONE_NEWNET: c_uint = 0x40000000;
 pub const CLONE_IO: c_uint = 0x80000000;
+pub const E2BIG: c_int = 7;
 pub const EACCES: c_int = 13;
+pub const EADDRINUSE: c_int = 98;
+pub const EADDRNOTAVAIL: c_int = 99;
+pub const EAFNOSUPPORT: c_int = 97;
 pub const EAGAIN: c_int = 11;
+pub const EALREADY: c_int = 114;
 pub const EBADF: c_int = 9;
 pub const EBADFD: c_int = 77;
+pub const EBADMSG: c_int = 74;
+pub const EBUSY: c_int = 16;
+pub const ECANCELED: c_int = 125;
+pub const ECHILD: c_int = 10;
+pub const ECONNABORTED: c_int = 103;
+pub const ECONNREFUSED: c_int = 111;
+pub const ECONNRESET: c_int = 104;
+pub const EDEADLK: c_int = 35;
+pub const EDESTADDRREQ: c_int = 89;
+pub const EDOM: c_int = 33;
+pub const EDQUOT: c_int = 122;
+pub const EEXIST: c_int = 17;
 pub const EFAULT: c_int = 14;
+pub const EFBIG: c_int = 27;
+pub const EHOSTDOWN: c_int = 112;
+pub const EHOSTUNREACH: c_int = 113;
+pub const EIDRM: c_int = 43;
+pub const EILSEQ: c_int = 84;
+pub const EINPROGRESS: c_int = 115;
 pub const EINTR: c_int = 4;
 pub const EINVAL: c_int = 22;
 pub const EIO: c_int = 5;
+pub const EISCONN: c_int = 106;
+pub const EISDIR: c_int = 21;
+pub const ELOOP: c_int = 40;
+pub const EMFILE: c_int = 24;
+pub const EMLINK: c_int = 31;
 pub const EMSGSIZE: c_int = 90;
+pub const EMULTIHOP: c_int = 72;
+pub const ENAMETOOLONG: c_int = 36;
+pub const ENETDOWN: c_int = 100;
+pub const ENETRESET: c_int = 102;
+pub const ENETUNREACH: c_int = 101;
+pub const ENFILE: c_int = 23;
+pub const ENOBUFS: c_int = 105;
+pub const ENODEV: c_int = 19;
 pub const ENOENT: c_int = 2;
+pub const ENOEXEC: c_int = 8;
+pub const ENOLCK: c_int = 37;
+pub const ENOLINK: c_int = 67;
 pub const ENOMEM: c_int = 12;
+pub const ENOMSG: c_int = 42;
+pub const ENOPROTOOPT: c_int = 92;
+pub const ENOSPC: c_int = 28;
 pub const ENOSYS: c_int = 38;
+pub const ENOTCONN: c_int = 107;
+pub const ENOTDIR: c_int = 20;
+pub const ENOTEMPTY: c_int = 39;
+pub const ENOTRECOVERABLE: c_int = 131;
+pub const ENOTSOCK: c_int = 88;
 pub const ENOTSUP: c_int = 95;
 pub const ENOTTY: c_int = 25;
+pub const ENXIO: c_int = 6;
+pub const EOPNOTSUPP: c_int = ENOTSUP;
 pub const EOVERFLOW: c_int = 75;
+pub const EOWNERDEAD: c_int = 130;
 pub const EPERM: c_int = 1;
+pub const EPFNOSUPPORT: c_int = 96;
+pub const EPIPE: c_int = 32;
+pub const EPROTO: c_int = 71;
+pub const EPROTONOSUPPORT: c_int = 93;
+pub const EPROTOTYPE: c_int = 91;
+pub const ERANGE: c_int = 34;
+pub const EROFS: c_int = 30;
+pub const ESHUTDOWN: c_int = 108;
+pub const ESOCKTNOSUPPORT: c_int = 94;
+pub const ESPIPE: c_int = 29;
+pub const ESRCH: c_int = 3;
+pub const ESTALE: c_int = 116;
+pub const ETIMEDOUT: c_int = 110;
+pub const ETXTBSY: c_int = 26;
+pub const EWOULDBLOCK: c_int = EAGAIN;
+pub const EXDEV: c_int = 18;
 pub const F_GETFD: c_int = 1;
 pub const F_GETFL: c_int = 3;
 pub const F_SETFD: c_int = 2;
@@ -239,6 +329,7 @@ pub const FUTEX_LOCK_PI2: c_int = 13;
 pub const FUTEX_PRIVATE_FLAG: c_int = 128;
 pub const GRND_NONBLOCK: c_uint = 1;
 pub const GRND_RANDOM: c_uint = 2;
+pub const MADV_DONTNEED: c_int = 4;
 pub const MAP_ANONYMOUS: c_int = 32;
 pub const MAP_PRIVATE: c_int = 2;
 pub const MREMAP_DONTUNMAP: c_int = 4;
@@ -303,6 +394,7 @@ pub const SYS_munmap: c_long = 11;
 pub const SYS_nanosleep: c_long = 35;
 pub const SYS_open: c_long = 2;
 pub const SYS_poll: c_long = 7;
+pub const SYS_pread64: c_long = 17;
 pub const SYS_read: c_long = 0;
 pub const SYS_readlink: c_long = 89;
 pub const SYS_readv: c_long = 19;
@@ -310,14 +402,21 @@ pub const SYS_recvfrom: c_long = 45;
 pub const SYS_rt_sigaction: c_long = 13;
 pub const SYS_rt_sigprocmask: c_long = 14;
 pub const SYS_set_tid_address: c_long = 218;
+pub const SYS_sendfile: c_long = 40;
 pub const SYS_sendto: c_long = 44;
 pub const SYS_setsockopt: c_long = 54;
+pub const SYS_shutdown: c_long = 48;
 pub const SYS_sigaltstack: c_long = 131;
 pub const SYS_socket: c_long = 41;
 pub const SYS_sync: c_long = 162;
+pub const SYS_umask: c_long = 95;
 pub const SYS_uname: c_long = 63;
 pub const SYS_write: c_long = 1;
 pub const SYS_writev: c_long = 20;
+pub const TCGETS: Ioctl = 0x5401;
+pub const TCSETS: Ioctl = 0x5402;
+pub const TCSETSW: Ioctl = 0x5403;
+pub const TCSETSF: Ioctl = 0x5404;
 pub const TIOCGWINSZ: Ioctl = 0x5413;
 
 bitflags::bitflags! {
@@ -0,0 +1,284 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fault injection into the host side of the `sallyport` block
+//!
+//! The host of a Keep is untrusted, so the guest side, which the shims rely on, must fail
+//! safely on any reply. [`Injector`] executes the block like [`host::execute`], but randomly
+//! misbehaves like an adversarial host would. The tests assert, that the guest only ever
+//! returns results within bounds, an error or detects the attack.
+
+use super::{TestHandler, TestPlatform};
+
+use core::ffi::c_int;
+use core::iter::once;
+use core::sync::atomic::AtomicU32;
+use libc::{sockaddr_in, CLOCK_MONOTONIC, FUTEX_WAIT, O_RDONLY};
+use std::fs::File;
+use std::mem::size_of;
+use std::net::UdpSocket;
+use std::os::unix::io::AsRawFd;
+use std::panic::resume_unwind;
+use std::thread;
+use std::time::Duration;
+
+use sallyport::guest::syscall::types::SockaddrOutput;
+use sallyport::guest::Handler;
+use sallyport::item::enarxcall::Number;
+use sallyport::item::{Block, Item};
+use sallyport::libc::timespec;
+use sallyport::{host, Result};
+
+/// Panic payload of [`Handler::attacked`] of the [`TestHandler`]
+pub struct Attacked;
+
+/// Unwinds with [`Attacked`] without invoking the panic hook
+pub fn attacked() -> ! {
+    resume_unwind(Box::new(Attacked))
+}
+
+/// Probabilities of the adversarial behaviors of the host
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Faults {
+    /// Stop executing the block before an item, leaving its reply truncated
+    pub truncate: f64,
+    /// Reply with out-of-range return values and corrupted data
+    pub out_of_range: f64,
+    /// Delay the completion of an item
+    pub delay: f64,
+    /// Complete a park request without waiting
+    pub spurious: f64,
+}
+
+impl Faults {
+    /// All adversarial behaviors with probability `p`
+    pub const fn all(p: f64) -> Self {
+        Self {
+            truncate: p,
+            out_of_range: p,
+            delay: p,
+            spurious: p,
+        }
+    }
+}
+
+/// Adversarial executor of the `sallyport` block
+#[derive(Debug)]
+pub struct Injector {
+    faults: Faults,
+    state: u64,
+}
+
+impl Injector {
+    /// Creates an injector of `faults` reproducible by `seed`
+    pub fn new(faults: Faults, seed: u64) -> Self {
+        Self {
+            faults,
+            // xorshift must not be seeded with zero
+            state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+        }
+    }
+
+    /// Returns the next number of the xorshift64* sequence
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns `true` with probability `p`
+    fn happens(&mut self, p: f64) -> bool {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64 <= p && p > 0.0
+    }
+
+    /// Returns a return value just or far out of the range of `bound`
+    fn out_of_range(&mut self, bound: usize) -> usize {
+        match self.next() % 4 {
+            0 => bound.wrapping_add(1),
+            1 => bound.wrapping_add(self.next() as usize % 4096),
+            2 => usize::MAX >> 1,
+            _ => self.next() as _,
+        }
+    }
+
+    /// Corrupts a random byte of `data`
+    fn corrupt(&mut self, data: &mut [u8]) {
+        if !data.is_empty() {
+            let i = self.next() as usize % data.len();
+            data[i] = self.next() as _;
+        }
+    }
+
+    /// Executes the items of `block` with faults injected
+    pub fn execute(&mut self, block: Block<'_>) -> Result<()> {
+        for item in block {
+            if self.happens(self.faults.delay) {
+                thread::sleep(Duration::from_micros(self.next() % 1000));
+            }
+            if self.happens(self.faults.truncate) {
+                return Ok(());
+            }
+
+            match item {
+                Item::Enarxcall(call, _)
+                    if call.num == Number::Park && self.happens(self.faults.spurious) =>
+                {
+                    call.ret = 0;
+                }
+                Item::Syscall(call, data) => {
+                    host::execute(once(Item::Syscall(call, data)))?;
+                    if self.happens(self.faults.out_of_range) {
+                        call.ret[0] = self.out_of_range(call.argv[2].max(data.len()));
+                        self.corrupt(data);
+                    }
+                }
+                Item::Enarxcall(call, data) => {
+                    host::execute(once(Item::Enarxcall(call, data)))?;
+                    if self.happens(self.faults.out_of_range) {
+                        call.ret = self.out_of_range(data.len());
+                    }
+                }
+                item => host::execute(once(item))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `f` with a handler injecting `faults` for each of `seeds`
+///
+/// Fails, if `f` panics for any other reason than a detected attack.
+fn run_faulty<const N: usize, F>(seeds: u64, faults: Faults, block: [usize; N], f: F)
+where
+    F: FnOnce(&mut TestPlatform, &mut TestHandler<N>) + Sync + Send + Copy + 'static,
+{
+    for seed in 0..seeds {
+        let res = thread::Builder::new()
+            .name(format!("seed {}", seed))
+            .spawn(move || {
+                let mut platform = TestPlatform;
+                let mut handler = TestHandler {
+                    block,
+                    tls: Default::default(),
+                    faults: Some(Injector::new(faults, seed)),
+                    exits: 0,
+                    clock_after_wait: false,
+                };
+                f(&mut platform, &mut handler);
+            })
+            .expect(&format!("couldn't spawn seed {} thread", seed))
+            .join();
+        if let Err(e) = res {
+            assert!(e.is::<Attacked>(), "seed {} failed unsafely", seed);
+        }
+    }
+}
+
+#[test]
+fn disabled() {
+    run_faulty(8, Faults::default(), [0xff; 16], |_, handler| {
+        let file = File::open("/dev/zero").unwrap();
+        let mut buf = [0xffu8; 16];
+        assert_eq!(handler.read(file.as_raw_fd(), &mut buf), Ok(buf.len()));
+        assert_eq!(buf, [0; 16]);
+    })
+}
+
+#[test]
+fn read() {
+    run_faulty(256, Faults::all(0.3), [0xff; 16], |_, handler| {
+        let file = File::open("/dev/zero").unwrap();
+        let mut buf = [0u8; 16];
+        if let Ok(n) = handler.read(file.as_raw_fd(), &mut buf) {
+            assert!(n <= buf.len());
+        }
+    })
+}
+
+#[test]
+fn readv() {
+    run_faulty(256, Faults::all(0.3), [0xff; 16], |_, handler| {
+        let file = File::open("/dev/zero").unwrap();
+        let mut one = [0u8; 3];
+        let mut two = [0u8; 5];
+        if let Ok(n) = handler.readv(file.as_raw_fd(), &mut [&mut one[..], &mut two[..]]) {
+            assert!(n <= one.len() + two.len());
+        }
+    })
+}
+
+#[test]
+fn write() {
+    run_faulty(256, Faults::all(0.3), [0xff; 16], |_, handler| {
+        let file = File::create("/dev/null").unwrap();
+        const BUF: &[u8] = b"fault";
+        if let Ok(n) = handler.write(file.as_raw_fd(), BUF) {
+            assert!(n <= BUF.len());
+        }
+    })
+}
+
+#[test]
+fn open() {
+    run_faulty(256, Faults::all(0.3), [0xff; 16], |_, handler| {
+        let _ = handler
+            .open(b"/dev/null\0", O_RDONLY, None)
+            .map(|fd| handler.close(fd));
+    })
+}
+
+#[test]
+fn clock_gettime() {
+    run_faulty(256, Faults::all(0.3), [0xff; 16], |_, handler| {
+        let mut tp = timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let _ = handler.clock_gettime(CLOCK_MONOTONIC, &mut tp);
+    })
+}
+
+#[test]
+fn getsockname() {
+    run_faulty(256, Faults::all(0.3), [0xff; 32], |_, handler| {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        // The address may be truncated, but must never overflow its buffer
+        let mut buf = [0u8; 2 * size_of::<sockaddr_in>()];
+        let (addr, canary) = buf.split_at_mut(size_of::<sockaddr_in>());
+        let mut addrlen = addr.len() as _;
+        let _ = handler.getsockname(sock.as_raw_fd(), SockaddrOutput::new(addr, &mut addrlen));
+        assert!(canary.iter().all(|&b| b == 0));
+    })
+}
+
+#[test]
+fn futex() {
+    // Spurious wakeups must not complete a wait on an unchanged futex word
+    run_faulty(256, Faults::all(0.3), [0xff; 16], |_, handler| {
+        let mut uaddr = AtomicU32::new(1);
+        assert!(handler
+            .futex(&mut uaddr, FUTEX_WAIT, 1, None, None, 0)
+            .is_err());
+    });
+    run_faulty(
+        16,
+        Faults {
+            spurious: 0.9,
+            ..Default::default()
+        },
+        [0xff; 16],
+        |_, handler| {
+            let mut uaddr = AtomicU32::new(1);
+            assert!(handler
+                .futex(&mut uaddr, FUTEX_WAIT, 1, None, None, 0)
+                .is_err());
+        },
+    );
+}
+
+#[test]
+fn park() {
+    run_faulty(256, Faults::all(0.3), [0xff; 16], |_, handler| {
+        let _: Result<c_int> = handler.park(0, None);
+    })
+}
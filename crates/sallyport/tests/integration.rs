@@ -3,6 +3,7 @@
 #![feature(c_size_t)]
 
 mod enarxcall;
+mod fault;
 mod gdbcall;
 mod syscall;
 
@@ -24,6 +25,11 @@ use sallyport::{host, Result};
 pub struct TestHandler<const N: usize> {
     block: [usize; N],
     tls: ThreadLocalStorage,
+    faults: Option<fault::Injector>,
+    /// Number of exits to the host
+    exits: usize,
+    /// Whether blocking waits read the monotonic clock in their exit
+    clock_after_wait: bool,
 }
 
 pub struct TestPlatform;
@@ -60,7 +66,12 @@ impl Platform for TestPlatform {
 
 impl<const N: usize> Handler for TestHandler<N> {
     fn sally(&mut self) -> Result<()> {
-        host::execute(Block::from(self.block_mut()))
+        self.exits += 1;
+        let block = Block::from(self.block.as_mut_slice());
+        match &mut self.faults {
+            Some(faults) => faults.execute(block),
+            None => host::execute(block),
+        }
     }
 
     fn block(&self) -> &[usize] {
@@ -75,6 +86,14 @@ impl<const N: usize> Handler for TestHandler<N> {
         &mut self.tls
     }
 
+    fn attacked(&mut self) -> ! {
+        fault::attacked()
+    }
+
+    fn clock_after_wait(&self) -> bool {
+        self.clock_after_wait
+    }
+
     fn arch_prctl(
         &mut self,
         _platform: &impl Platform,
@@ -158,6 +177,9 @@ where
                 let mut handler = TestHandler {
                     block: block.clone(),
                     tls: Default::default(),
+                    faults: None,
+                    exits: 0,
+                    clock_after_wait: false,
                 };
                 f(i, &mut platform, &mut handler);
             })
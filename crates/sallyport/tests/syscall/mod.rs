@@ -6,21 +6,22 @@ use core::ffi::{c_char, c_int};
 use libc::{
     self, in_addr, iovec, pollfd, sockaddr, sockaddr_in, timespec, timeval, utsname, SYS_accept,
     SYS_accept4, SYS_bind, SYS_clock_getres, SYS_clock_gettime, SYS_close, SYS_fcntl, SYS_fstat,
-    SYS_getegid, SYS_geteuid, SYS_getgid, SYS_getpid, SYS_getrandom, SYS_getsockname, SYS_listen,
-    SYS_mremap, SYS_nanosleep, SYS_open, SYS_poll, SYS_read, SYS_readlink, SYS_readv, SYS_recvfrom,
-    SYS_rt_sigaction, SYS_rt_sigprocmask, SYS_sendto, SYS_set_tid_address, SYS_setsockopt,
-    SYS_sigaltstack, SYS_socket, SYS_uname, SYS_write, SYS_writev, AF_INET, CLOCK_MONOTONIC,
-    CLOCK_REALTIME, EACCES, EBADF, EBADFD, EINVAL, ENOENT, ENOSYS, ENOTSUP, F_GETFD, F_GETFL,
-    F_SETFD, F_SETFL, GRND_RANDOM, MREMAP_DONTUNMAP, MREMAP_FIXED, MREMAP_MAYMOVE, MSG_NOSIGNAL,
-    O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_WRONLY, SIGCHLD, SIG_BLOCK, SOCK_CLOEXEC, SOCK_STREAM,
-    SOL_SOCKET, SO_RCVTIMEO, SO_REUSEADDR, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO,
+    SYS_getegid, SYS_geteuid, SYS_getgid, SYS_getpid, SYS_getrandom, SYS_getsockname, SYS_ioctl,
+    SYS_listen, SYS_mremap, SYS_nanosleep, SYS_open, SYS_poll, SYS_pread64, SYS_read, SYS_readlink,
+    SYS_readv, SYS_recvfrom, SYS_rt_sigaction, SYS_rt_sigprocmask, SYS_sendfile, SYS_sendto,
+    SYS_set_tid_address, SYS_setsockopt, SYS_shutdown, SYS_sigaltstack, SYS_socket, SYS_umask,
+    SYS_uname, SYS_write, SYS_writev, AF_INET, CLOCK_MONOTONIC, CLOCK_REALTIME, EACCES, EBADF,
+    EBADFD, EINVAL, ENOENT, ENOSYS, ENOTSUP, ENOTTY, FIONCLEX, F_GETFD, F_GETFL, F_SETFD, F_SETFL,
+    GRND_RANDOM, MREMAP_DONTUNMAP, MREMAP_FIXED, MREMAP_MAYMOVE, MSG_NOSIGNAL, O_APPEND, O_CREAT,
+    O_RDONLY, O_RDWR, O_WRONLY, SHUT_WR, SIGCHLD, SIG_BLOCK, SOCK_CLOEXEC, SOCK_STREAM, SOL_SOCKET,
+    SO_RCVTIMEO, SO_REUSEADDR, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO, TCGETS, TIOCGWINSZ,
 };
 use std::env::temp_dir;
 use std::ffi::CString;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, Write};
 use std::mem::{size_of, transmute};
-use std::net::{TcpListener, UdpSocket};
+use std::net::{TcpListener, TcpStream, UdpSocket};
 use std::os::unix::io::IntoRawFd;
 use std::os::unix::prelude::AsRawFd;
 use std::ptr::{null_mut, NonNull};
@@ -507,6 +508,75 @@ fn getrandom() {
     });
 }
 
+#[test]
+#[serial]
+#[cfg_attr(miri, ignore)]
+fn ioctl() {
+    run_test(2, [0xff; 32], move |i, platform, handler| {
+        let expected = |fd| {
+            if unsafe { libc::isatty(fd) } == 1 {
+                Ok(0)
+            } else {
+                Err(ENOTTY)
+            }
+        };
+
+        if i % 2 == 0 {
+            let mut termios = [0u8; size_of::<sallyport::libc::termios>()];
+            assert_eq!(
+                handler.ioctl(STDIN_FILENO, TCGETS as _, Some(&mut termios)),
+                expected(STDIN_FILENO)
+            );
+            assert_eq!(
+                handler.ioctl(STDIN_FILENO, FIONCLEX as _, None),
+                Err(EINVAL)
+            );
+        } else {
+            let mut winsize = libc::winsize {
+                ws_row: 0,
+                ws_col: 0,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+            assert_eq!(
+                unsafe {
+                    handler.syscall(
+                        platform,
+                        [
+                            SYS_ioctl as _,
+                            STDOUT_FILENO as _,
+                            TIOCGWINSZ as _,
+                            &mut winsize as *mut _ as _,
+                            0,
+                            0,
+                            0,
+                        ],
+                    )
+                },
+                expected(STDOUT_FILENO).map(|ret| [ret as _, 0])
+            );
+            // Unknown requests fail like on Linux
+            assert_eq!(
+                unsafe {
+                    handler.syscall(
+                        platform,
+                        [
+                            SYS_ioctl as _,
+                            STDOUT_FILENO as _,
+                            0xdead,
+                            &mut winsize as *mut _ as _,
+                            0,
+                            0,
+                            0,
+                        ],
+                    )
+                },
+                Err(ENOTTY)
+            );
+        }
+    });
+}
+
 #[test]
 fn mremap() {
     let mem = [0u8; 4096];
@@ -819,6 +889,178 @@ fn read() {
     });
 }
 
+#[test]
+#[serial]
+fn pread64() {
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        const EXPECTED: &str = "pread64";
+        let path = temp_dir().join(format!("sallyport-test-pread64-{}", i));
+        write!(&mut File::create(&path).unwrap(), "skip{}", EXPECTED).unwrap();
+
+        let mut buf = [0u8; EXPECTED.len()];
+
+        let file = File::open(&path).unwrap();
+        if i % 2 == 0 {
+            assert_eq!(
+                handler.pread64(file.as_raw_fd(), &mut buf, 4),
+                if cfg!(not(miri)) {
+                    Ok(EXPECTED.len())
+                } else {
+                    Err(ENOSYS)
+                }
+            );
+        } else {
+            assert_eq!(
+                unsafe {
+                    handler.syscall(
+                        platform,
+                        [
+                            SYS_pread64 as _,
+                            file.as_raw_fd() as _,
+                            buf.as_mut_ptr() as _,
+                            EXPECTED.len(),
+                            4,
+                            0,
+                            0,
+                        ],
+                    )
+                },
+                if cfg!(not(miri)) {
+                    Ok([EXPECTED.len(), 0])
+                } else {
+                    Err(ENOSYS)
+                }
+            );
+        }
+        if cfg!(not(miri)) {
+            assert_eq!(buf, EXPECTED.as_bytes());
+        }
+    });
+}
+
+#[test]
+#[serial]
+fn execute_tuple() {
+    run_test(1, [0xff; 64], move |_, _, handler| {
+        let path = temp_dir().join("sallyport-test-execute-tuple");
+        write!(&mut File::create(&path).unwrap(), "rd0").unwrap();
+
+        // Differently typed calls exit to the host once as a tuple
+        let null = OpenOptions::new().write(true).open("/dev/null").unwrap();
+        let file = File::open(&path).unwrap();
+        let mut buf = [0u8; 3];
+        let exits = handler.exits;
+        let (write, read) = handler
+            .execute((
+                syscall::Write {
+                    fd: null.as_raw_fd(),
+                    buf: b"batch",
+                },
+                syscall::Read {
+                    fd: file.as_raw_fd(),
+                    buf: &mut buf,
+                },
+            ))
+            .unwrap();
+        assert_eq!(write, Some(Ok(5)));
+        assert_eq!(read, Some(Ok(3)));
+        assert_eq!(handler.exits - exits, 1);
+        assert_eq!(buf, b"rd0");
+    });
+}
+
+#[test]
+#[serial]
+fn clock_after_wait() {
+    run_test(1, [0xff; 64], move |_, platform, handler| {
+        let null = dev_null();
+        let mut fds = [pollfd {
+            fd: null.as_raw_fd(),
+            events: libc::POLLOUT,
+            revents: 0,
+        }];
+        let mut start = unsafe { mem::zeroed::<timespec>() };
+        assert_eq!(
+            unsafe { libc::clock_gettime(CLOCK_MONOTONIC, &mut start as *mut _) },
+            0
+        );
+
+        // The wait and the clock exit to the host separately
+        let mut tp = unsafe { mem::zeroed::<timespec>() };
+        let exits = handler.exits;
+        assert_eq!(
+            handler.poll(unsafe { transmute::<_, &mut [_; 1]>(&mut fds) }, -1),
+            Ok(1)
+        );
+        assert_eq!(
+            handler.clock_gettime(CLOCK_MONOTONIC, unsafe { transmute(&mut tp) }),
+            Ok(())
+        );
+        assert_eq!(handler.exits - exits, 2);
+
+        // The clock is read in the exit of the wait
+        handler.clock_after_wait = true;
+        let exits = handler.exits;
+        let poll = [
+            SYS_poll as _,
+            fds.as_mut_ptr() as _,
+            1,
+            -1 as c_int as _,
+            0,
+            0,
+            0,
+        ];
+        assert_eq!(unsafe { handler.syscall(platform, poll) }, Ok([1, 0]));
+        let now = [
+            SYS_clock_gettime as _,
+            CLOCK_MONOTONIC as _,
+            &mut tp as *mut _ as _,
+            0,
+            0,
+            0,
+            0,
+        ];
+        assert_eq!(unsafe { handler.syscall(platform, now) }, Ok([0, 0]));
+        assert_eq!(handler.exits - exits, 1);
+        assert!((tp.tv_sec, tp.tv_nsec) >= (start.tv_sec, start.tv_nsec));
+
+        // Only right after the wait
+        let exits = handler.exits;
+        assert_eq!(unsafe { handler.syscall(platform, poll) }, Ok([1, 0]));
+        let pid = [SYS_getpid as _, 0, 0, 0, 0, 0, 0];
+        assert!(unsafe { handler.syscall(platform, pid) }.is_ok());
+        assert_eq!(unsafe { handler.syscall(platform, now) }, Ok([0, 0]));
+        assert_eq!(handler.exits - exits, 2);
+
+        // Also, if the calls are executed directly
+        let exits = handler.exits;
+        assert_eq!(
+            handler.poll(unsafe { transmute::<_, &mut [_; 1]>(&mut fds) }, -1),
+            Ok(1)
+        );
+        assert!(handler.getpid().is_ok());
+        assert_eq!(
+            handler.clock_gettime(CLOCK_MONOTONIC, unsafe { transmute(&mut tp) }),
+            Ok(())
+        );
+        assert_eq!(handler.exits - exits, 2);
+
+        // Waits, which leave no room for the clock in the block, exit without it
+        let mut fds = [fds[0]; 48];
+        let exits = handler.exits;
+        assert_eq!(
+            handler.poll(unsafe { transmute::<_, &mut [_; 48]>(&mut fds) }, -1),
+            Ok(48)
+        );
+        assert_eq!(handler.exits - exits, 1);
+        assert_eq!(
+            handler.clock_gettime(CLOCK_MONOTONIC, unsafe { transmute(&mut tp) }),
+            Ok(())
+        );
+        assert_eq!(handler.exits - exits, 2);
+    });
+}
+
 #[test]
 fn readlink() {
     run_test(2, [0xff; 16], move |i, platform, handler| {
@@ -1457,6 +1699,83 @@ fn send() {
     });
 }
 
+#[test]
+#[serial]
+fn sendfile() {
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        const EXPECTED: &str = "sendfile";
+        let in_path = temp_dir().join("sallyport-test-sendfile-in");
+        let out_path = temp_dir().join("sallyport-test-sendfile-out");
+        write!(&mut File::create(&in_path).unwrap(), "{}", EXPECTED).unwrap();
+
+        let in_file = File::open(&in_path).unwrap();
+        let mut out_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&out_path)
+            .unwrap();
+
+        if i % 2 == 0 {
+            assert_eq!(
+                handler.sendfile(out_file.as_raw_fd(), in_file.as_raw_fd(), EXPECTED.len()),
+                if cfg!(not(miri)) {
+                    Ok(EXPECTED.len())
+                } else {
+                    Err(ENOSYS)
+                }
+            );
+        } else {
+            let mut offset: libc::off_t = 0;
+            assert_eq!(
+                unsafe {
+                    handler.syscall(
+                        platform,
+                        [
+                            SYS_sendfile as _,
+                            out_file.as_raw_fd() as _,
+                            in_file.as_raw_fd() as _,
+                            &mut offset as *mut _ as _,
+                            EXPECTED.len(),
+                            0,
+                            0,
+                        ],
+                    )
+                },
+                Err(ENOTSUP)
+            );
+            assert_eq!(
+                unsafe {
+                    handler.syscall(
+                        platform,
+                        [
+                            SYS_sendfile as _,
+                            out_file.as_raw_fd() as _,
+                            in_file.as_raw_fd() as _,
+                            0,
+                            EXPECTED.len(),
+                            0,
+                            0,
+                        ],
+                    )
+                },
+                if cfg!(not(miri)) {
+                    Ok([EXPECTED.len(), 0])
+                } else {
+                    Err(ENOSYS)
+                }
+            );
+        }
+        if cfg!(not(miri)) {
+            let mut got = String::new();
+            out_file.rewind().unwrap();
+            out_file.read_to_string(&mut got).unwrap();
+            assert_eq!(got, EXPECTED);
+        }
+    })
+}
+
 #[test]
 #[serial]
 #[cfg_attr(miri, ignore)]
@@ -1542,6 +1861,52 @@ fn set_tid_address() {
     });
 }
 
+#[test]
+#[serial]
+#[cfg_attr(miri, ignore)]
+fn shutdown() {
+    const EXPECTED: &str = "shutdown";
+
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("couldn't bind to address");
+        let mut client =
+            TcpStream::connect(listener.local_addr().unwrap()).expect("couldn't connect");
+        let mut server = listener.accept().expect("couldn't accept connection").0;
+
+        if i % 2 == 0 {
+            assert_eq!(handler.shutdown(server.as_raw_fd(), SHUT_WR), Ok(()));
+        } else {
+            assert_eq!(
+                unsafe {
+                    handler.syscall(
+                        platform,
+                        [
+                            SYS_shutdown as _,
+                            server.as_raw_fd() as _,
+                            SHUT_WR as _,
+                            0,
+                            0,
+                            0,
+                            0,
+                        ],
+                    )
+                },
+                Ok([0, 0])
+            );
+        }
+
+        // The client sees the FIN, but the connection is only half-closed
+        let mut buf = vec![];
+        assert_eq!(client.read_to_end(&mut buf).expect("couldn't read"), 0);
+        client
+            .write_all(EXPECTED.as_bytes())
+            .expect("couldn't write data");
+        drop(client);
+        server.read_to_end(&mut buf).expect("couldn't read");
+        assert_eq!(buf, EXPECTED.as_bytes());
+    });
+}
+
 #[test]
 fn sigaltstack() {
     run_test(2, [0xff; 16], move |i, platform, handler| {
@@ -1585,6 +1950,27 @@ fn sync_read_close() {
     });
 }
 
+#[test]
+#[serial]
+fn umask() {
+    run_test(2, [0xff; 16], move |i, platform, handler| {
+        let orig = unsafe { libc::umask(0o022) };
+        let ret = if i % 2 == 0 {
+            handler.umask(0o077).map(|ret| ret as usize)
+        } else {
+            unsafe { handler.syscall(platform, [SYS_umask as _, 0o077, 0, 0, 0, 0, 0]) }
+                .map(|[ret, _]| ret)
+        };
+        if cfg!(not(miri)) {
+            assert_eq!(ret, Ok(0o022));
+            assert_eq!(unsafe { libc::umask(orig) }, 0o077);
+        } else {
+            assert_eq!(ret, Err(ENOSYS));
+            unsafe { libc::umask(orig) };
+        }
+    });
+}
+
 #[test]
 fn uname() {
     run_test(2, [0xff; 16], move |i, platform, handler| {
@@ -0,0 +1,138 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Trusted monotonic clock and detection of host clock skew
+//!
+//! The trusted clock is the TSC of the vCPU. With the SNP Secure TSC, the host can neither
+//! offset nor scale the TSC and its frequency is read from the `GUEST_TSC_FREQ` MSR, so the
+//! clock is trusted. Otherwise the frequency is taken from CPUID leaves provided by the host,
+//! which can make the clock run at any pace, so the detected skews are labeled untrusted.
+
+use crate::print;
+use crate::snp::cpuid_page::cpuid;
+use crate::snp::snp_active;
+
+use core::arch::x86_64::_rdtsc;
+
+use sallyport::guest::{timespec_nanos, SkewDetector, TimeValidator, NSEC_PER_SEC};
+use sallyport::item::enarxcall::time::UPDATE_INTERVAL;
+use sallyport::libc::timespec;
+use spinning::Lazy;
+use x86_64::registers::model_specific::Msr;
+
+/// Tolerated absolute skew of the host wall time in nanoseconds
+const SKEW_THRESHOLD: u64 = 2 * NSEC_PER_SEC;
+
+/// Tolerated drift of the trusted clock in nanoseconds per second (0.1%)
+const SKEW_DRIFT: u64 = NSEC_PER_SEC / 1000;
+
+/// The detector of skew between the trusted clock and the host wall time
+pub static SKEW_DETECTOR: SkewDetector = SkewDetector::new(SKEW_THRESHOLD, SKEW_DRIFT);
+
+/// Tolerated deviation of the timestamp page from the trusted clock in nanoseconds
+const TIME_PAGE_THRESHOLD: u64 = 10 * UPDATE_INTERVAL;
+
+/// The validator of the timestamp page shared with the host
+pub static TIME_VALIDATOR: TimeValidator = TimeValidator::new(TIME_PAGE_THRESHOLD, SKEW_DRIFT);
+
+/// The `SEV_STATUS` MSR
+const SEV_STATUS: Msr = Msr::new(0xC001_0131);
+
+/// The Secure TSC bit of the `SEV_STATUS` MSR
+const SEV_STATUS_SECURE_TSC: u64 = 1 << 11;
+
+/// The `GUEST_TSC_FREQ` MSR with the TSC frequency of a Secure TSC guest in MHz in bits 17:0
+const GUEST_TSC_FREQ: Msr = Msr::new(0xC001_0134);
+
+/// The frequency of the TSC
+#[derive(Clone, Copy, Debug)]
+struct TscFreq {
+    /// Frequency in kHz
+    khz: u64,
+
+    /// Whether the TSC and its frequency are protected from the host by the SNP Secure TSC
+    secure: bool,
+}
+
+/// The TSC frequency, if it can be determined
+static TSC_FREQ: Lazy<Option<TscFreq>> = Lazy::new(|| {
+    if snp_active() && unsafe { SEV_STATUS.read() } & SEV_STATUS_SECURE_TSC != 0 {
+        let mhz = unsafe { GUEST_TSC_FREQ.read() } & 0x3_ffff;
+        if mhz != 0 {
+            return Some(TscFreq {
+                khz: mhz * 1000,
+                secure: true,
+            });
+        }
+    }
+
+    host_tsc_khz().map(|khz| TscFreq { khz, secure: false })
+});
+
+/// Returns the TSC frequency in kHz of the CPUID leaves provided by the host, if any
+fn host_tsc_khz() -> Option<u64> {
+    let max_leaf = cpuid(0).eax;
+
+    // Time Stamp Counter and Nominal Core Crystal Clock Information Leaf
+    if max_leaf >= 0x15 {
+        let res = cpuid(0x15);
+        if res.eax != 0 && res.ebx != 0 && res.ecx != 0 {
+            return Some(res.ecx as u64 * res.ebx as u64 / res.eax as u64 / 1000);
+        }
+    }
+
+    // Processor Frequency Information Leaf
+    if max_leaf >= 0x16 {
+        let res = cpuid(0x16);
+        if res.eax != 0 {
+            return Some(res.eax as u64 * 1000);
+        }
+    }
+
+    // Hypervisor timing information leaf
+    if cpuid(0x4000_0000).eax >= 0x4000_0010 {
+        let res = cpuid(0x4000_0010);
+        if res.eax != 0 {
+            return Some(res.eax as u64);
+        }
+    }
+
+    None
+}
+
+/// Returns the time of the trusted monotonic clock in nanoseconds
+///
+/// The trusted clock is derived from the TSC of the vCPU, which is not affected
+/// by changes of the host wall time. Returns `None`, if the TSC frequency is unknown.
+pub fn trusted_time() -> Option<u64> {
+    let khz = (*TSC_FREQ)?.khz;
+    let tsc = unsafe { _rdtsc() };
+    Some((tsc as u128 * 1_000_000 / khz as u128) as u64)
+}
+
+/// Compares the wall time `host` provided by the host against the trusted clock
+///
+/// Detected skews are counted in [`SKEW_DETECTOR`] and reported on the host stderr.
+pub fn check(host: &timespec) {
+    let (trusted, host) = match (trusted_time(), timespec_nanos(host)) {
+        (Some(trusted), Some(host)) => (trusted, host),
+        _ => return,
+    };
+
+    if let Some(skew) = SKEW_DETECTOR.observe(trusted, host) {
+        print::_eprint(format_args!(
+            "WARNING: clock skew detected: trusted_elapsed_ns={} host_elapsed_ns={} skew_ns={} count={}\n",
+            skew.trusted,
+            skew.host,
+            skew.skew(),
+            skew.count
+        ));
+    }
+}
+
+/// Returns the number of detected clock skews and whether the trusted clock is protected from
+/// the host by the SNP Secure TSC
+///
+/// Returns `None`, if there is no trusted clock to detect skews.
+pub fn skews() -> Option<(u64, bool)> {
+    (*TSC_FREQ).map(|freq| (SKEW_DETECTOR.skews(), freq.secure))
+}
@@ -4,6 +4,7 @@
 
 use crate::addr::ShimPhysAddr;
 use crate::allocator::ALLOCATOR;
+use crate::hostcall::calibrate_spin_wait;
 use crate::random::random;
 use crate::shim_stack::init_stack_with_guard;
 use crate::snp::cpuid;
@@ -196,6 +197,8 @@ pub fn execute_exec() -> ! {
 
     let (entry, sp_handle) = crt0setup(*EXEC_VIRT_ADDR.read(), stack.slice, header);
 
+    calibrate_spin_wait();
+
     #[cfg(feature = "gdb")]
     unsafe {
         use core::arch::asm;
@@ -2,33 +2,43 @@
 
 //! Host <-> Shim Communication
 
+use crate::addr::ShimPhysUnencryptedAddr;
 use crate::allocator::ALLOCATOR;
 use crate::debug::_enarx_asm_triple_fault;
 use crate::eprintln;
 use crate::exec::{BRK_LINE, NEXT_MMAP_RWLOCK};
+use crate::interrupts::{sallyport_doorbell, SALLYPORT_COMPLETED};
 use crate::paging::SHIM_PAGETABLE;
 use crate::snp::attestation::asn1_encode_report_vcek;
 use crate::snp::ghcb::{GHCB, GHCB_EXT, SNP_ATTESTATION_LEN_MAX, SNP_KEY_LEN};
+use crate::snp::secrets_page::SECRETS;
 use crate::snp::snp_active;
-use crate::spin::{RacyCell, RwLocked};
+use crate::spin::{Locked, RacyCell, RwLocked};
 
 use const_default::ConstDefault;
+use core::alloc::Layout;
 use core::ffi::{c_int, c_size_t, c_ulong, c_void};
 use core::ops::{Deref, DerefMut};
-use core::ptr::NonNull;
+use core::ptr::{self, NonNull};
 use core::slice;
 use core::sync::atomic::AtomicU32;
 
-use sallyport::guest::{self, Handler, Platform, ThreadLocalStorage};
+use nbytes::bytes;
+use sallyport::guest::{
+    self, timespec_nanos, Handler, Platform, SpinWait, ThreadLocalStorage, NSEC_PER_SEC,
+};
 use sallyport::item::enarxcall::sev::TECH;
+use sallyport::item::enarxcall::time::TimePage;
 use sallyport::item::syscall;
 use sallyport::libc::{
-    off_t, CloneFlags, EAGAIN, EFAULT, EINVAL, EIO, EMSGSIZE, ENOMEM, ENOSYS, MAP_ANONYMOUS,
-    MAP_PRIVATE, PROT_EXEC, PROT_WRITE,
+    clockid_t, off_t, timespec, CloneFlags, CLOCK_MONOTONIC, CLOCK_REALTIME, EAGAIN, EFAULT,
+    EINVAL, EIO, EMSGSIZE, ENOMEM, ENOSYS, EOPNOTSUPP, MAP_ANONYMOUS, MAP_PRIVATE, PROT_EXEC,
+    PROT_WRITE,
 };
 use sallyport::util::ptr::is_aligned_non_null;
-use sallyport::{libc, KVM_SYSCALL_TRIGGER_PORT};
+use sallyport::{libc, KVM_SYSCALL_DOORBELL_PORT, KVM_SYSCALL_TRIGGER_PORT};
 use spinning::Lazy;
+use x86_64::instructions::interrupts;
 use x86_64::instructions::port::Port;
 use x86_64::instructions::segmentation::{Segment64, FS, GS};
 use x86_64::instructions::tlb::flush_all;
@@ -40,6 +50,12 @@ pub const BLOCK_SIZE: usize = 69632;
 /// The number of sallyport blocks
 pub const NUM_BLOCKS: usize = 2;
 
+/// The maximum size of a bounce buffer transfer
+///
+/// Reads and writes exceeding [`BLOCK_SIZE`] are transferred via a bounce buffer of this size
+/// shared with the host, instead of being split into many sallyport block round trips.
+pub const BOUNCE_SIZE: usize = bytes![4; MiB];
+
 const BLOCK_SIZE_USIZE: usize = BLOCK_SIZE / core::mem::size_of::<usize>();
 
 /// Global TLS for the SHIM
@@ -66,6 +82,100 @@ pub static SNP_VCEK: Lazy<Result<&[u8], c_int>> = Lazy::new(|| {
     }
 });
 
+/// Bounce buffer shared with the host
+struct BounceBuffer {
+    buf: &'static mut [u8],
+    /// Guest physical address of `buf`
+    phys: usize,
+}
+
+impl BounceBuffer {
+    /// Allocates the bounce buffer and shares it with the host
+    fn new() -> Option<Self> {
+        const PAGE_SIZE: usize = Page::<Size4KiB>::SIZE as usize;
+
+        // Restrict the number of pages per page state change request
+        const SHARE_PAGES: usize = 128;
+
+        let layout = Layout::from_size_align(BOUNCE_SIZE, PAGE_SIZE).ok()?;
+        let ptr = ALLOCATOR.lock().try_alloc(layout)?.as_ptr();
+
+        if snp_active() {
+            for offset in (0..BOUNCE_SIZE).step_by(SHARE_PAGES * PAGE_SIZE) {
+                let npages = (BOUNCE_SIZE - offset).min(SHARE_PAGES * PAGE_SIZE) / PAGE_SIZE;
+                GHCB.set_memory_shared(VirtAddr::from_ptr(ptr) + offset, npages);
+            }
+        }
+
+        // The shim memory is linearly mapped, so the buffer is physically contiguous
+        let phys = ShimPhysUnencryptedAddr::try_from(ptr as *const u8).ok()?;
+
+        Some(Self {
+            // Safety: the memory was just allocated and is never freed
+            buf: unsafe { slice::from_raw_parts_mut(ptr, BOUNCE_SIZE) },
+            phys: phys.raw().raw() as _,
+        })
+    }
+}
+
+/// The bounce buffer, allocated on the first transfer exceeding [`BLOCK_SIZE`]
+///
+/// `None`, if it could not be allocated or the host does not support bounce buffers.
+static BOUNCE_BUFFER: Lazy<Locked<Option<BounceBuffer>>> =
+    Lazy::new(|| Locked::new(BounceBuffer::new()));
+
+/// Allocates the timestamp page and shares it with the host
+///
+/// Returns the page and its guest physical address.
+fn new_time_page() -> Option<(&'static TimePage, usize)> {
+    let ptr = ALLOCATOR
+        .lock()
+        .try_alloc(Layout::new::<TimePage>())?
+        .as_ptr();
+
+    if snp_active() {
+        GHCB.set_memory_shared(VirtAddr::from_ptr(ptr), 1);
+    }
+
+    let phys = ShimPhysUnencryptedAddr::try_from(ptr as *const u8).ok()?;
+
+    let page = ptr.cast::<TimePage>();
+    // Safety: the memory was just allocated, is never freed and only accessed atomically
+    let page = unsafe {
+        ptr::write(page, TimePage::default());
+        &*page
+    };
+    Some((page, phys.raw().raw() as _))
+}
+
+/// The timestamp page, registered with the host on the first `clock_gettime`
+///
+/// The inner `None` marks a page, which could not be allocated or is not supported by the host.
+static TIME_PAGE: Locked<Option<Option<&'static TimePage>>> = Locked::new(None);
+
+/// Default spin window of blocking waits in nanoseconds
+///
+/// Exits of KVM and SEV-SNP guests are cheap compared to waking a blocked vCPU thread.
+const SPIN_WINDOW: u64 = 50_000;
+
+/// The spin-then-block strategy of blocking waits of the exec
+pub static SPIN_WAIT: SpinWait = SpinWait::new(SPIN_WINDOW);
+
+/// Calibrates [`SPIN_WAIT`] with the cost of an exit
+pub fn calibrate_spin_wait() {
+    if let Some(mut host_call) = HostCall::try_new(SHIM_LOCAL_STORAGE.write().deref_mut()) {
+        host_call.calibrate_spin_wait();
+    }
+}
+
+/// Converts `nanos` to a `timespec`
+fn nanos_timespec(nanos: u64) -> timespec {
+    timespec {
+        tv_sec: (nanos / NSEC_PER_SEC) as _,
+        tv_nsec: (nanos % NSEC_PER_SEC) as _,
+    }
+}
+
 /// Host file descriptor
 #[derive(Copy, Clone)]
 pub struct HostFd(c_int);
@@ -207,9 +317,10 @@ impl<'a> HostCall<'a> {
 
         let user_buf = platform.validate_slice_mut::<u8>(buf, buf_len)?;
 
-        let u = GHCB_EXT.get_key(1, 0).map_err(|_| EIO)?;
+        let mut u = GHCB_EXT.get_key(1, 0).map_err(|_| EIO)?;
 
         user_buf[0..SNP_KEY_LEN].copy_from_slice(&u);
+        guest::zeroize(&mut u);
 
         Ok(SNP_KEY_LEN)
     }
@@ -261,6 +372,68 @@ impl<'a> HostCall<'a> {
 
         Ok([len, TECH])
     }
+
+    /// Reads from `fd` via the bounce buffer
+    ///
+    /// Returns `None`, if the bounce buffer is unavailable and the read has to fall back to
+    /// the sallyport block.
+    fn read_bounced(&mut self, fd: c_int, buf: &mut [u8]) -> Option<sallyport::Result<usize>> {
+        let mut bounce = BOUNCE_BUFFER.lock();
+        let BounceBuffer {
+            buf: bounce_buf,
+            phys,
+        } = bounce.as_mut()?;
+        let count = buf.len().min(bounce_buf.len());
+
+        match self.bounce_read(fd, *phys, count) {
+            Err(ENOSYS | EFAULT) => {
+                bounce.take();
+                None
+            }
+            Err(e) => Some(Err(e)),
+            // be careful with `n` as it is untrusted
+            Ok(n) if n > count => Some(Err(EIO)),
+            Ok(n) => {
+                buf[..n].copy_from_slice(&bounce_buf[..n]);
+                Some(Ok(n))
+            }
+        }
+    }
+
+    /// Writes to `fd` via the bounce buffer
+    ///
+    /// Returns `None`, if the bounce buffer is unavailable and the write has to fall back to
+    /// the sallyport block.
+    fn write_bounced(&mut self, fd: c_int, buf: &[u8]) -> Option<sallyport::Result<usize>> {
+        let mut bounce = BOUNCE_BUFFER.lock();
+        let BounceBuffer {
+            buf: bounce_buf,
+            phys,
+        } = bounce.as_mut()?;
+        let count = buf.len().min(bounce_buf.len());
+        bounce_buf[..count].copy_from_slice(&buf[..count]);
+
+        match self.bounce_write(fd, *phys, count) {
+            Err(ENOSYS | EFAULT) => {
+                bounce.take();
+                None
+            }
+            Err(e) => Some(Err(e)),
+            // be careful with `n` as it is untrusted
+            Ok(n) if n > count => Some(Err(EIO)),
+            Ok(n) => Some(Ok(n)),
+        }
+    }
+
+    /// Returns the timestamp page, registering it with the host on the first call
+    fn time_page(&mut self) -> Option<&'static TimePage> {
+        let mut time_page = TIME_PAGE.lock();
+        *time_page.get_or_insert_with(|| {
+            let (page, phys) = new_time_page()?;
+            self.register_time_page(phys).ok()?;
+            Some(page)
+        })
+    }
 }
 
 impl Handler for HostCall<'_> {
@@ -268,29 +441,39 @@ impl Handler for HostCall<'_> {
     ///
     /// Returns the contents of the shared memory reply status, the host might have
     /// written.
+    ///
+    /// Without SEV-SNP and once the IDT is loaded, the block is posted to the doorbell port
+    /// and the vCPU halts until the host injects the completion interrupt.
     fn sally(&mut self) -> Result<(), c_int> {
-        if !snp_active() {
-            let mut port = Port::<u16>::new(KVM_SYSCALL_TRIGGER_PORT);
+        // prevent earlier writes from being moved beyond this point
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::Release);
 
-            // prevent earlier writes from being moved beyond this point
-            core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::Release);
+        if snp_active() {
+            GHCB.do_io_out(KVM_SYSCALL_TRIGGER_PORT, self.block_guard.block_index);
+        } else if sallyport_doorbell() {
+            SALLYPORT_COMPLETED.store(false, core::sync::atomic::Ordering::Relaxed);
 
+            let mut port = Port::<u16>::new(KVM_SYSCALL_DOORBELL_PORT);
             unsafe {
                 // Safety: this I/O port does not violate memory safety
                 port.write(self.block_guard.block_index);
             }
 
-            // prevent later reads from being moved before this point
-            core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::Acquire);
+            while !SALLYPORT_COMPLETED.load(core::sync::atomic::Ordering::Acquire) {
+                // `sti; hlt` cannot miss the interrupt, because `sti` delays it by one instruction
+                interrupts::enable_and_hlt();
+                interrupts::disable();
+            }
         } else {
-            // prevent earlier writes from being moved beyond this point
-            core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::Release);
-
-            GHCB.do_io_out(KVM_SYSCALL_TRIGGER_PORT, self.block_guard.block_index);
-
-            // prevent later reads from being moved before this point
-            core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::Acquire);
+            let mut port = Port::<u16>::new(KVM_SYSCALL_TRIGGER_PORT);
+            unsafe {
+                // Safety: this I/O port does not violate memory safety
+                port.write(self.block_guard.block_index);
+            }
         }
+
+        // prevent later reads from being moved before this point
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::Acquire);
         Ok(())
     }
 
@@ -309,6 +492,103 @@ impl Handler for HostCall<'_> {
         self.tls
     }
 
+    fn read(&mut self, fd: c_int, buf: &mut [u8]) -> sallyport::Result<c_size_t> {
+        if buf.len() > BLOCK_SIZE {
+            if let Some(ret) = self.read_bounced(fd, buf) {
+                return ret;
+            }
+        }
+        self.execute(guest::syscall::Read { fd, buf })?
+            .unwrap_or_else(|| self.attacked())
+    }
+
+    fn write(&mut self, fd: c_int, buf: &[u8]) -> sallyport::Result<c_size_t> {
+        if buf.len() > BLOCK_SIZE {
+            if let Some(ret) = self.write_bounced(fd, buf) {
+                return ret;
+            }
+        }
+        self.execute(guest::syscall::Write { fd, buf })?
+            .unwrap_or_else(|| self.attacked())
+    }
+
+    fn scrub(&mut self) {
+        guest::zeroize_words(self.block_mut());
+
+        // The VM private communication keys are not needed anymore
+        if snp_active() {
+            SECRETS.zeroize_vmpcks();
+        }
+    }
+
+    #[inline]
+    fn check_clock(&mut self, host: &libc::timespec) {
+        crate::clock::check(host)
+    }
+
+    #[inline]
+    fn spin_wait(&self) -> Option<&'static SpinWait> {
+        Some(&SPIN_WAIT)
+    }
+
+    /// Reads `CLOCK_REALTIME` and `CLOCK_MONOTONIC` from the timestamp page without an exit,
+    /// if it passes the validation against the trusted clock
+    ///
+    /// Otherwise the host monotonic time is fetched in the same exit as the requested clock
+    /// to establish a new baseline for the validation.
+    fn clock_gettime(&mut self, clockid: clockid_t, tp: &mut timespec) -> sallyport::Result<()> {
+        let trusted = match crate::clock::trusted_time() {
+            Some(trusted) if clockid == CLOCK_REALTIME || clockid == CLOCK_MONOTONIC => trusted,
+            _ => return self.execute(guest::syscall::ClockGettime { clockid, tp })?,
+        };
+
+        let time = self
+            .time_page()
+            .and_then(TimePage::read)
+            .and_then(|time| crate::clock::TIME_VALIDATOR.validate(trusted, time));
+
+        if let Some(time) = time {
+            if clockid == CLOCK_MONOTONIC {
+                *tp = nanos_timespec(time.monotonic);
+            } else {
+                *tp = nanos_timespec(time.realtime);
+                self.check_clock(tp);
+            }
+            return Ok(());
+        }
+
+        let monotonic = if clockid == CLOCK_MONOTONIC {
+            self.execute(guest::syscall::ClockGettime { clockid, tp })??;
+            *tp
+        } else {
+            let mut monotonic = timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            let (ret, monotonic_ret) = self.execute((
+                guest::syscall::ClockGettime { clockid, tp },
+                guest::syscall::ClockGettime {
+                    clockid: CLOCK_MONOTONIC,
+                    tp: &mut monotonic,
+                },
+            ))?;
+            ret?;
+            self.check_clock(tp);
+            if monotonic_ret.is_err() {
+                return Ok(());
+            }
+            monotonic
+        };
+
+        if let Some(nanos) = timespec_nanos(&monotonic) {
+            let nanos = crate::clock::TIME_VALIDATOR.rebase(trusted, nanos);
+            if clockid == CLOCK_MONOTONIC {
+                *tp = nanos_timespec(nanos);
+            }
+        }
+        Ok(())
+    }
+
     fn arch_prctl(
         &mut self,
         platform: &impl Platform,
@@ -431,7 +711,8 @@ impl Handler for HostCall<'_> {
         _ctid: Option<&AtomicU32>,
         _tls: NonNull<c_void>,
     ) -> sallyport::Result<c_int> {
-        Err(ENOSYS)
+        // Threads are not supported by this shim yet
+        Err(EOPNOTSUPP)
     }
 
     fn madvise(
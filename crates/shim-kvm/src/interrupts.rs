@@ -9,13 +9,18 @@ use crate::eprintln;
 #[cfg(feature = "dbg")]
 use crate::hostcall::shim_exit;
 use crate::snp::cpuid_count;
+use crate::snp::ghcb::GHCB;
+use crate::snp::ioio::{self, Port, IOIO_REP, IOIO_STR};
 
 use core::arch::asm;
 use core::fmt;
 use core::mem::size_of;
 use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, Ordering};
 
+use sallyport::KVM_SYSCALL_COMPLETION_VECTOR;
 use spinning::Lazy;
+use x86_64::registers::rflags::RFlags;
 use x86_64::structures::idt::InterruptDescriptorTable;
 use x86_64::VirtAddr;
 use xsave::XSave;
@@ -235,11 +240,99 @@ declare_interrupt!(
                     stack_frame.instruction_pointer += 2u64
                 }
             }
+            0x7B => {
+                // VMEXIT_IOIO
+
+                unsafe { emulate_ioio(stack_frame.as_mut()) }
+            }
             _ => panic!("Unhandled #VC: {:x?}", error_code),
         }
     }
 );
 
+/// Emulate the port I/O instruction at the instruction pointer of `frame` via the GHCB
+///
+/// String instructions are forwarded to the host one element at a time.
+///
+/// # Safety
+///
+/// The instruction pointer, and `RSI` or `RDI` of string instructions, have to point to
+/// valid memory.
+#[cfg_attr(coverage, no_coverage)]
+unsafe fn emulate_ioio(frame: &mut ExtendedInterruptStackFrameValue) {
+    let rip = frame.instruction_pointer;
+    let bytes = core::slice::from_raw_parts(rip.as_ptr::<u8>(), ioio::MAX_INSN_LEN);
+    let insn = ioio::decode(bytes)
+        .unwrap_or_else(|| panic!("Unhandled #VC: IOIO instruction {:x?} at {:?}", bytes, rip));
+
+    let port = match insn.port {
+        Port::Imm(port) => port as u16,
+        Port::Dx => frame.rdx as u16,
+    };
+    // transfer single elements via RAX
+    let exit_info_1 = insn.exit_info1(port) & !(IOIO_STR | IOIO_REP);
+    let data_mask = insn.data_mask();
+
+    if insn.string {
+        let addr_mask = insn.addr_mask();
+        let size = insn.size as u64;
+        let step = if RFlags::from_bits_truncate(frame.cpu_flags).contains(RFlags::DIRECTION_FLAG) {
+            size.wrapping_neg()
+        } else {
+            size
+        };
+        let mut count = if insn.rep { frame.rcx & addr_mask } else { 1 };
+
+        while count > 0 {
+            if insn.input {
+                let value = GHCB.do_io(exit_info_1, 0);
+                let dst = (frame.rdi & addr_mask) as *mut u8;
+                dst.copy_from_nonoverlapping(value.to_le_bytes().as_ptr(), insn.size as _);
+                frame.rdi = frame.rdi & !addr_mask | (frame.rdi.wrapping_add(step) & addr_mask);
+            } else {
+                let mut value = [0u8; 8];
+                let src = (frame.rsi & addr_mask) as *const u8;
+                src.copy_to_nonoverlapping(value.as_mut_ptr(), insn.size as _);
+                GHCB.do_io(exit_info_1, u64::from_le_bytes(value));
+                frame.rsi = frame.rsi & !addr_mask | (frame.rsi.wrapping_add(step) & addr_mask);
+            }
+            count -= 1;
+            if insn.rep {
+                frame.rcx = frame.rcx & !addr_mask | count;
+            }
+        }
+    } else if insn.input {
+        let value = GHCB.do_io(exit_info_1, 0) & data_mask;
+        frame.rax = match insn.size {
+            // writing a 32-bit register clears the upper half
+            4 => value,
+            _ => frame.rax & !data_mask | value,
+        };
+    } else {
+        GHCB.do_io(exit_info_1, frame.rax & data_mask);
+    }
+
+    frame.instruction_pointer += insn.len as u64;
+}
+
+/// Set by the host, when it has completed the last block posted to the doorbell
+pub static SALLYPORT_COMPLETED: AtomicBool = AtomicBool::new(false);
+
+/// Set, when the IDT with the completion handler is loaded
+static SALLYPORT_DOORBELL: AtomicBool = AtomicBool::new(false);
+
+declare_interrupt!(
+    fn sallyport_completion_handler(_stack_frame: &mut ExtendedInterruptStackFrame) {
+        SALLYPORT_COMPLETED.store(true, Ordering::Release);
+    }
+);
+
+/// Returns true, if the host can signal block completion with an interrupt
+#[inline]
+pub fn sallyport_doorbell() -> bool {
+    SALLYPORT_DOORBELL.load(Ordering::Relaxed)
+}
+
 /// The global IDT
 pub static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     let mut idt = InterruptDescriptorTable::new();
@@ -249,6 +342,9 @@ pub static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
             .set_handler_addr(virt)
             .set_stack_index(0);
 
+        let virt = VirtAddr::new_unsafe(sallyport_completion_handler as usize as u64);
+        idt[KVM_SYSCALL_COMPLETION_VECTOR as usize].set_handler_addr(virt);
+
         #[cfg(feature = "dbg")]
         debug::idt_add_debug_exception_handlers(&mut idt);
     }
@@ -261,6 +357,7 @@ pub fn init() {
     #[cfg(debug_assertions)]
     eprintln!("interrupts::init");
     IDT.load();
+    SALLYPORT_DOORBELL.store(true, Ordering::Relaxed);
 }
 
 #[cfg(feature = "dbg")]
@@ -25,6 +25,7 @@ pub mod print;
 
 pub mod addr;
 pub mod allocator;
+pub mod clock;
 pub mod debug;
 pub mod exec;
 pub mod gdb;
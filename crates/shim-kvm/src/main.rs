@@ -17,7 +17,7 @@ extern crate rcrt1;
 use enarx_shim_kvm::addr::SHIM_VIRT_OFFSET;
 use enarx_shim_kvm::exec;
 use enarx_shim_kvm::gdt;
-use enarx_shim_kvm::hostcall::BLOCK_SIZE;
+use enarx_shim_kvm::hostcall::{BLOCK_SIZE, BOUNCE_SIZE};
 use enarx_shim_kvm::interrupts;
 use enarx_shim_kvm::pagetables::{unmap_identity, PDPT, PDT_C000_0000, PML4T, PT_FFE0_0000};
 use enarx_shim_kvm::print::enable_printing;
@@ -48,6 +48,7 @@ noted! {
     static NOTE_ENARX_SALLYPORT<note::NAME, note::REQUIRES, [u8; REQUIRES.len()]> = REQUIRES;
 
     static NOTE_BLOCK_SIZE<note::NAME, note::BLOCK_SIZE, u64> = BLOCK_SIZE as u64;
+    static NOTE_BOUNCE_SIZE<note::NAME, note::BOUNCE_SIZE, u64> = BOUNCE_SIZE as u64;
 
     static NOTE_SVN<note::NAME, note::snp::SVN, u32> = 1;
     static NOTE_POLICY<note::NAME, note::snp::POLICY, u64> = POLICY;
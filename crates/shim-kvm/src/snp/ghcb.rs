@@ -7,6 +7,7 @@
 
 use crate::addr::SHIM_VIRT_OFFSET;
 use crate::pagetables::{clear_c_bit_address_range, smash};
+use crate::snp::ioio::IOIO_DATA_16;
 use crate::snp::secrets_page::SECRETS;
 use crate::snp::{pvalidate, ByteSized, PvalidateSize};
 use crate::spin::{Locked, RacyCell, RwLocked};
@@ -434,32 +435,37 @@ impl RwLocked<GhcbHandle<'_>> {
     /// GHCB IOIO_PROT
     #[cfg_attr(coverage, no_coverage)]
     pub fn do_io_out(&self, portnumber: u16, value: u16) {
-        const IOIO_TYPE_OUT: u64 = 0;
-        const IOIO_DATA_16: u64 = 1 << 5;
+        self.do_io(
+            IOIO_DATA_16 | ((portnumber as u64).checked_shl(16).unwrap()),
+            value as _,
+        );
+    }
+
+    /// GHCB IOIO_PROT for a single, non-string port access described by `exit_info_1`
+    ///
+    /// Passes `rax` to the hypervisor and returns the `rax` of the hypervisor,
+    /// which contains the value read by an `IN`.
+    #[cfg_attr(coverage, no_coverage)]
+    pub fn do_io(&self, exit_info_1: u64, rax: u64) -> u64 {
         const SVM_EXIT_IOIO_PROT: u64 = 0x7B;
 
         let mut this = self.write();
 
         this.invalidate();
 
-        this.ghcb.save_area.rax = value as _;
+        this.ghcb.save_area.rax = rax;
         let offset: usize = ptr::addr_of!(this.ghcb.save_area.rax) as _;
         this.set_offset_valid(offset);
 
         unsafe {
-            if this
-                .vmgexit(
-                    SVM_EXIT_IOIO_PROT,
-                    IOIO_DATA_16 | IOIO_TYPE_OUT | ((portnumber as u64).checked_shl(16).unwrap()),
-                    0,
-                )
-                .is_err()
-            {
+            if this.vmgexit(SVM_EXIT_IOIO_PROT, exit_info_1, 0).is_err() {
                 crate::debug::_early_debug_panic(4, 0x10);
             }
 
             // FIXME: check error codes
         }
+
+        this.ghcb.save_area.rax
     }
 
     /// turn physical pages to decrypted / shared
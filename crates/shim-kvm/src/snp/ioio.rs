@@ -0,0 +1,316 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Decoder of the port I/O instructions intercepted with a #VC `VMEXIT_IOIO`
+//!
+//! With SEV-ES and SEV-SNP the hypervisor can't read the instruction and the registers of the
+//! guest, so the #VC handler has to decode the instruction and forward the access via the GHCB.
+
+/// Maximum length of an x86 instruction
+pub const MAX_INSN_LEN: usize = 15;
+
+/// `IN`, `INS`
+pub const IOIO_TYPE_IN: u64 = 1 << 0;
+/// `INS`, `OUTS`
+pub const IOIO_STR: u64 = 1 << 2;
+/// `REP` prefix
+pub const IOIO_REP: u64 = 1 << 3;
+/// 8-bit operand
+pub const IOIO_DATA_8: u64 = 1 << 4;
+/// 16-bit operand
+pub const IOIO_DATA_16: u64 = 1 << 5;
+/// 32-bit operand
+pub const IOIO_DATA_32: u64 = 1 << 6;
+/// 32-bit address size
+pub const IOIO_ADDR_32: u64 = 1 << 8;
+/// 64-bit address size
+pub const IOIO_ADDR_64: u64 = 1 << 9;
+
+/// The port operand of a port I/O instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Port {
+    /// 8-bit immediate
+    Imm(u8),
+    /// `DX` register
+    Dx,
+}
+
+/// A decoded `IN`, `OUT`, `INS` or `OUTS` instruction in 64-bit mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoInsn {
+    /// The port operand
+    pub port: Port,
+    /// Operand size in bytes: 1, 2 or 4
+    pub size: u8,
+    /// Reads from the port
+    pub input: bool,
+    /// `INS` or `OUTS`
+    pub string: bool,
+    /// `REP` prefixed string instruction
+    pub rep: bool,
+    /// `RCX`, `RSI` and `RDI` are used as 32-bit registers
+    pub addr32: bool,
+    /// Length of the instruction in bytes
+    pub len: usize,
+}
+
+impl IoInsn {
+    /// The `VMEXIT_IOIO` exit information 1 for an access to `port`, as defined in
+    /// AMD Programmer's Manual Vol. 2, section "IOIO Intercept Information"
+    pub fn exit_info1(&self, port: u16) -> u64 {
+        let mut info = u64::from(port) << 16;
+        if self.input {
+            info |= IOIO_TYPE_IN;
+        }
+        if self.string {
+            info |= IOIO_STR;
+        }
+        if self.rep {
+            info |= IOIO_REP;
+        }
+        info |= match self.size {
+            1 => IOIO_DATA_8,
+            2 => IOIO_DATA_16,
+            _ => IOIO_DATA_32,
+        };
+        info | if self.addr32 {
+            IOIO_ADDR_32
+        } else {
+            IOIO_ADDR_64
+        }
+    }
+
+    /// Mask of the bits of the operand in `RAX`
+    pub fn data_mask(&self) -> u64 {
+        u64::MAX >> (64 - 8 * u32::from(self.size))
+    }
+
+    /// Mask of the bits of `RCX`, `RSI` and `RDI` used by string instructions
+    pub fn addr_mask(&self) -> u64 {
+        if self.addr32 {
+            u32::MAX as u64
+        } else {
+            u64::MAX
+        }
+    }
+}
+
+/// Decodes the port I/O instruction at the start of `bytes`
+///
+/// Returns `None`, if `bytes` does not start with a port I/O instruction.
+pub fn decode(bytes: &[u8]) -> Option<IoInsn> {
+    let mut opsize16 = false;
+    let mut addr32 = false;
+    let mut rep = false;
+
+    for (i, &byte) in bytes.iter().enumerate().take(MAX_INSN_LEN) {
+        let (input, string, port) = match byte {
+            // operand size override
+            0x66 => {
+                opsize16 = true;
+                continue;
+            }
+            // address size override
+            0x67 => {
+                addr32 = true;
+                continue;
+            }
+            // REP, REPNE, which is treated as REP by INS and OUTS
+            0xF2 | 0xF3 => {
+                rep = true;
+                continue;
+            }
+            // segment overrides, LOCK
+            0x26 | 0x2E | 0x36 | 0x3E | 0x64 | 0x65 | 0xF0 => continue,
+            // REX, which must directly precede the opcode and does not change the operand size
+            0x40..=0x4F => match bytes.get(i + 1) {
+                Some(0x6C..=0x6F | 0xE4..=0xE7 | 0xEC..=0xEF) => continue,
+                _ => return None,
+            },
+            0x6C | 0x6D => (true, true, Port::Dx),
+            0x6E | 0x6F => (false, true, Port::Dx),
+            0xE4 | 0xE5 => (true, false, Port::Imm(*bytes.get(i + 1)?)),
+            0xE6 | 0xE7 => (false, false, Port::Imm(*bytes.get(i + 1)?)),
+            0xEC | 0xED => (true, false, Port::Dx),
+            0xEE | 0xEF => (false, false, Port::Dx),
+            _ => return None,
+        };
+
+        let size = match (byte & 1, opsize16) {
+            (0, _) => 1,
+            (_, true) => 2,
+            (_, false) => 4,
+        };
+        let len = i + 1 + usize::from(matches!(port, Port::Imm(_)));
+        if len > MAX_INSN_LEN {
+            return None;
+        }
+
+        return Some(IoInsn {
+            port,
+            size,
+            input,
+            string,
+            rep: rep && string,
+            addr32,
+            len,
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn insn(port: Port, size: u8, input: bool, len: usize) -> IoInsn {
+        IoInsn {
+            port,
+            size,
+            input,
+            string: false,
+            rep: false,
+            addr32: false,
+            len,
+        }
+    }
+
+    fn string(size: u8, input: bool, rep: bool, addr32: bool, len: usize) -> IoInsn {
+        IoInsn {
+            port: Port::Dx,
+            size,
+            input,
+            string: true,
+            rep,
+            addr32,
+            len,
+        }
+    }
+
+    #[test]
+    fn corpus() {
+        let corpus: &[(&[u8], IoInsn)] = &[
+            // in al, 0x60
+            (&[0xE4, 0x60], insn(Port::Imm(0x60), 1, true, 2)),
+            // in ax, 0x60
+            (&[0x66, 0xE5, 0x60], insn(Port::Imm(0x60), 2, true, 3)),
+            // in eax, 0x60
+            (&[0xE5, 0x60], insn(Port::Imm(0x60), 4, true, 2)),
+            // out 0x80, al
+            (&[0xE6, 0x80], insn(Port::Imm(0x80), 1, false, 2)),
+            // out 0x80, ax
+            (&[0x66, 0xE7, 0x80], insn(Port::Imm(0x80), 2, false, 3)),
+            // out 0x80, eax
+            (&[0xE7, 0x80], insn(Port::Imm(0x80), 4, false, 2)),
+            // in al, dx
+            (&[0xEC], insn(Port::Dx, 1, true, 1)),
+            // in ax, dx
+            (&[0x66, 0xED], insn(Port::Dx, 2, true, 2)),
+            // in eax, dx
+            (&[0xED], insn(Port::Dx, 4, true, 1)),
+            // out dx, al
+            (&[0xEE], insn(Port::Dx, 1, false, 1)),
+            // out dx, ax
+            (&[0x66, 0xEF], insn(Port::Dx, 2, false, 2)),
+            // out dx, eax
+            (&[0xEF], insn(Port::Dx, 4, false, 1)),
+            // rex.w out dx, eax
+            (&[0x48, 0xEF], insn(Port::Dx, 4, false, 2)),
+            // insb
+            (&[0x6C], string(1, true, false, false, 1)),
+            // insw
+            (&[0x66, 0x6D], string(2, true, false, false, 2)),
+            // insd
+            (&[0x6D], string(4, true, false, false, 1)),
+            // outsb
+            (&[0x6E], string(1, false, false, false, 1)),
+            // outsw
+            (&[0x66, 0x6F], string(2, false, false, false, 2)),
+            // outsd
+            (&[0x6F], string(4, false, false, false, 1)),
+            // rep insb
+            (&[0xF3, 0x6C], string(1, true, true, false, 2)),
+            // rep insw
+            (&[0x66, 0xF3, 0x6D], string(2, true, true, false, 3)),
+            // rep insd, with 32-bit addresses
+            (&[0x67, 0xF3, 0x6D], string(4, true, true, true, 3)),
+            // rep outsb
+            (&[0xF3, 0x6E], string(1, false, true, false, 2)),
+            // rep outsw
+            (&[0xF3, 0x66, 0x6F], string(2, false, true, false, 3)),
+            // rep outsd
+            (&[0xF3, 0x6F], string(4, false, true, false, 2)),
+            // rep outsb with a segment override
+            (&[0xF3, 0x2E, 0x6E], string(1, false, true, false, 3)),
+            // rep ignored for non-string instructions
+            (&[0xF3, 0xEE], insn(Port::Dx, 1, false, 2)),
+        ];
+
+        for (bytes, expected) in corpus {
+            assert_eq!(decode(bytes).as_ref(), Some(expected), "{bytes:x?}");
+        }
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        // out dx, al; nop
+        assert_eq!(decode(&[0xEE, 0x90]), Some(insn(Port::Dx, 1, false, 1)));
+    }
+
+    #[test]
+    fn invalid() {
+        let corpus: &[&[u8]] = &[
+            &[],
+            // nop
+            &[0x90],
+            // cpuid
+            &[0x0F, 0xA2],
+            // truncated in al, imm8
+            &[0xE4],
+            // prefixes only
+            &[0x66, 0xF3],
+            // rex not directly preceding the opcode
+            &[0x48, 0x66, 0xEF],
+            // too long
+            &[0x66; MAX_INSN_LEN],
+        ];
+
+        for bytes in corpus {
+            assert_eq!(decode(bytes), None, "{bytes:x?}");
+        }
+        assert_eq!(
+            decode(&[[0x66; MAX_INSN_LEN - 1].as_slice(), &[0xEF]].concat()).map(|i| i.len),
+            Some(MAX_INSN_LEN)
+        );
+        assert_eq!(
+            decode(&[[0x66; MAX_INSN_LEN - 1].as_slice(), &[0xE5, 0x60]].concat()),
+            None
+        );
+    }
+
+    #[test]
+    fn exit_info1() {
+        let out_dx_ax = decode(&[0x66, 0xEF]).unwrap();
+        assert_eq!(
+            out_dx_ax.exit_info1(0x1234),
+            0x1234 << 16 | IOIO_DATA_16 | IOIO_ADDR_64
+        );
+        assert_eq!(out_dx_ax.data_mask(), 0xffff);
+
+        let rep_insd = decode(&[0x67, 0xF3, 0x6D]).unwrap();
+        assert_eq!(
+            rep_insd.exit_info1(0x3f8),
+            0x3f8 << 16 | IOIO_TYPE_IN | IOIO_STR | IOIO_REP | IOIO_DATA_32 | IOIO_ADDR_32
+        );
+        assert_eq!(rep_insd.data_mask(), 0xffff_ffff);
+        assert_eq!(rep_insd.addr_mask(), 0xffff_ffff);
+
+        let in_al = decode(&[0xE4, 0x60]).unwrap();
+        assert_eq!(
+            in_al.exit_info1(0x60),
+            0x60 << 16 | IOIO_TYPE_IN | IOIO_DATA_8 | IOIO_ADDR_64
+        );
+        assert_eq!(in_al.data_mask(), 0xff);
+        assert_eq!(in_al.addr_mask(), u64::MAX);
+    }
+}
@@ -15,6 +15,7 @@ use crate::snp::Error::{FailInput, FailSizeMismatch, Unknown};
 pub mod attestation;
 pub mod cpuid_page;
 pub mod ghcb;
+pub mod ioio;
 pub mod launch;
 pub mod secrets_page;
 
@@ -4,6 +4,7 @@
 
 use crate::spin::{Locked, RacyCell};
 
+use sallyport::guest::zeroize;
 use spinning::Lazy;
 
 /// The SEV-SNP secrets page OS area
@@ -103,6 +104,20 @@ impl Locked<SecretsHandle<'_>> {
         this.secrets.os_area.msg_seqno_0.checked_add(1).unwrap()
     }
 
+    /// zeroize all VM private communication keys
+    pub fn zeroize_vmpcks(&self) {
+        let mut this = self.lock();
+        let secrets = &mut *this.secrets;
+        for key in [
+            &mut secrets.vmpck0,
+            &mut secrets.vmpck1,
+            &mut secrets.vmpck2,
+            &mut secrets.vmpck3,
+        ] {
+            zeroize(key);
+        }
+    }
+
     /// increase message sequence number for VM private communication key for VMPL0
     pub fn inc_msg_seqno_0(&self) {
         let mut this = self.lock();
@@ -10,7 +10,8 @@ use core::mem::size_of;
 
 use sallyport::guest;
 use sallyport::guest::Handler;
-use sallyport::item::enarxcall::{SYS_GETATT, SYS_GETKEY};
+use sallyport::item::enarxcall::{SYS_GETATT, SYS_GETKEY, SYS_GETSKEW, SYS_SETSPIN};
+use sallyport::libc::EOPNOTSUPP;
 #[cfg(feature = "dbg")]
 use sallyport::libc::{SYS_write, STDERR_FILENO, STDOUT_FILENO};
 use spinning::Lazy;
@@ -184,6 +185,39 @@ extern "sysv64" fn syscall_rust(
                 },
             }
         }
+        SYS_GETSKEW => {
+            let ret = crate::clock::skews().ok_or(EOPNOTSUPP);
+
+            #[cfg(feature = "dbg")]
+            eprintln!(
+                "syscall SYS_GETSKEW = {}",
+                ret.map_or_else(|e| -e as usize, |(v, _)| v as usize)
+            );
+
+            match ret {
+                Err(e) => X8664DoubleReturn {
+                    rax: e.checked_neg().unwrap() as _,
+                    // Preserve `rdx` as it is normally not clobbered with a syscall
+                    rdx: orig_rdx as _,
+                },
+                Ok((skews, secure)) => X8664DoubleReturn {
+                    rax: skews as _,
+                    rdx: secure as _,
+                },
+            }
+        }
+        SYS_SETSPIN => {
+            crate::hostcall::SPIN_WAIT.set_window(a as _);
+
+            #[cfg(feature = "dbg")]
+            eprintln!("syscall SYS_SETSPIN = 0");
+
+            X8664DoubleReturn {
+                rax: 0,
+                // Preserve `rdx` as it is normally not clobbered with a syscall
+                rdx: orig_rdx as _,
+            }
+        }
         _ => {
             let ret = unsafe { h.syscall(&usermemscope, [nr, a, b, c, d, e, f]) };
 
@@ -0,0 +1,214 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! CPUID policy of the enclave
+//!
+//! `cpuid` is not allowed in an enclave, so its results are requested from the untrusted host.
+//! Features, which are implemented by every SGX capable CPU, are always reported, so that the
+//! host cannot force the exec layer onto slower software fallbacks. In particular, software AES
+//! is prone to cache timing side channels, which AES-NI is not.
+//!
+//! The cache and topology leaves describe a single package of single-threaded cores, one for each
+//! logical processor the enclave can run threads on, instead of the topology of the host. Thread
+//! pools sized by them do not oversubscribe the TCS pool of the enclave.
+
+use core::arch::x86_64::CpuidResult;
+
+/// Leaf 1 ECX: PCLMULQDQ
+pub const PCLMULQDQ: u32 = 1 << 1;
+
+/// Leaf 1 ECX: SSSE3
+pub const SSSE3: u32 = 1 << 9;
+
+/// Leaf 1 ECX: SSE4.1
+pub const SSE4_1: u32 = 1 << 19;
+
+/// Leaf 1 ECX: SSE4.2
+pub const SSE4_2: u32 = 1 << 20;
+
+/// Leaf 1 ECX: AES-NI
+pub const AES: u32 = 1 << 25;
+
+/// Leaf 1 ECX features implemented by every SGX capable CPU
+const BASELINE_1_ECX: u32 = PCLMULQDQ | SSSE3 | SSE4_1 | SSE4_2 | AES;
+
+/// Leaf 1 EDX: Max APIC IDs reserved field is valid
+pub const HTT: u32 = 1 << 28;
+
+/// Maximum number of logical processors reported, if the enclave can add threads
+///
+/// Without EDMM, the enclave runs on its single TCS built into the shim.
+pub const MAX_LOGICAL_PROCESSORS: u32 = 8;
+
+/// Level type of the SMT level of the extended topology leaves
+const LEVEL_SMT: u32 = 1;
+
+/// Level type of the core level of the extended topology leaves
+const LEVEL_CORE: u32 = 2;
+
+/// Returns the number of bits to shift an x2APIC ID to address `count` logical processors
+fn shift(count: u32) -> u32 {
+    count.next_power_of_two().trailing_zeros()
+}
+
+/// Applies the CPUID policy to the `result` for `leaf` and `sub_leaf` reported by the host
+/// for an enclave running threads on `cpus` logical processors
+///
+/// SHA extensions are only available since some later SGX capable CPUs, so their support
+/// as reported by the host is passed through. A host hiding them only slows hashing down,
+/// because software SHA does not depend on secret data for memory accesses.
+pub fn apply(leaf: u32, sub_leaf: u32, result: &mut CpuidResult, cpus: u32) {
+    let cpus = cpus.clamp(1, MAX_LOGICAL_PROCESSORS);
+    let ids = cpus.next_power_of_two();
+
+    match leaf {
+        1 => {
+            result.ecx |= BASELINE_1_ECX;
+
+            // Initial APIC ID 0 and the addressable IDs of the package
+            result.ebx = (result.ebx & 0xffff) | ids << 16;
+            if cpus > 1 {
+                result.edx |= HTT;
+            } else {
+                result.edx &= !HTT;
+            }
+        }
+
+        // Deterministic cache parameters
+        4 if result.eax & 0x1f != 0 => {
+            let level = (result.eax >> 5) & 0x7;
+
+            // The last level cache is shared by all cores, the others are private
+            let sharing = if level >= 3 { ids } else { 1 };
+            result.eax = (result.eax & 0x3fff) | (sharing - 1) << 14 | (ids - 1) << 26;
+        }
+
+        // Extended topology enumeration
+        0xb | 0x1f => {
+            let (eax, ebx, level) = match sub_leaf {
+                0 => (0, 1, LEVEL_SMT),
+                1 => (shift(cpus), cpus, LEVEL_CORE),
+                _ => (0, 0, 0),
+            };
+            *result = CpuidResult {
+                eax,
+                ebx,
+                ecx: level << 8 | (sub_leaf & 0xff),
+                edx: 0,
+            };
+        }
+
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty() -> CpuidResult {
+        CpuidResult {
+            eax: 0,
+            ebx: 0,
+            ecx: 0,
+            edx: 0,
+        }
+    }
+
+    #[test]
+    fn baseline() {
+        let mut result = empty();
+        apply(1, 0, &mut result, 1);
+        assert_eq!(result.ecx & AES, AES);
+        assert_eq!(result.ecx & PCLMULQDQ, PCLMULQDQ);
+        assert_eq!(result.ecx & SSSE3, SSSE3);
+        assert_eq!(result.eax, 0);
+        assert_eq!(result.edx, 0);
+
+        // Other features are kept
+        let mut result = empty();
+        result.ecx = 1 << 28;
+        apply(1, 0, &mut result, 1);
+        assert_eq!(result.ecx, BASELINE_1_ECX | 1 << 28);
+    }
+
+    #[test]
+    fn topology() {
+        // 128 logical processors reported by the host
+        let mut result = empty();
+        result.ebx = 0x2a80_0800;
+        result.edx = HTT;
+        apply(1, 0, &mut result, 1);
+        assert_eq!(result.ebx, 0x0001_0800);
+        assert_eq!(result.edx & HTT, 0);
+
+        apply(1, 0, &mut result, 6);
+        assert_eq!(result.ebx, 0x0008_0800);
+        assert_eq!(result.edx & HTT, HTT);
+
+        // Capped at the maximum
+        apply(1, 0, &mut result, 128);
+        assert_eq!(result.ebx >> 16, MAX_LOGICAL_PROCESSORS);
+
+        // Extended topology of 6 single-threaded cores
+        let mut result = empty();
+        result.ebx = 2;
+        result.edx = 0x7f;
+        apply(0xb, 0, &mut result, 6);
+        assert_eq!(
+            (result.eax, result.ebx, result.ecx, result.edx),
+            (0, 1, 0x100, 0)
+        );
+
+        apply(0xb, 1, &mut result, 6);
+        assert_eq!(
+            (result.eax, result.ebx, result.ecx, result.edx),
+            (3, 6, 0x201, 0)
+        );
+
+        result.ebx = 128;
+        apply(0x1f, 2, &mut result, 6);
+        assert_eq!(
+            (result.eax, result.ebx, result.ecx, result.edx),
+            (0, 0, 0x2, 0)
+        );
+    }
+
+    #[test]
+    fn caches() {
+        // L1 data cache of 2 threads and L3 cache of 128 threads of 64 cores
+        let l1d = 0x21 | 1 << 14 | 63 << 26;
+        let l3 = 0x63 | 127 << 14 | 63 << 26;
+
+        let mut result = empty();
+        result.eax = l1d | 1 << 8;
+        apply(4, 0, &mut result, 4);
+        assert_eq!(result.eax, 0x21 | 1 << 8 | 3 << 26);
+
+        result.eax = l3;
+        apply(4, 3, &mut result, 4);
+        assert_eq!(result.eax, 0x63 | 3 << 14 | 3 << 26);
+
+        // No more caches
+        let mut result = empty();
+        apply(4, 4, &mut result, 4);
+        assert_eq!(result.eax, 0);
+    }
+
+    #[test]
+    fn passthrough() {
+        // Leaf 7 EBX: SHA extensions
+        const SHA: u32 = 1 << 29;
+
+        let mut result = empty();
+        apply(7, 0, &mut result, 1);
+        assert_eq!(result.ebx & SHA, 0);
+
+        result.ebx = SHA;
+        apply(7, 0, &mut result, 1);
+        assert_eq!(result.ebx, SHA);
+
+        let mut result = empty();
+        apply(0x8000_0001, 0, &mut result, 1);
+        assert_eq!(result.ecx, 0);
+    }
+}
@@ -3,6 +3,7 @@
 use core::arch::asm;
 use core::mem::MaybeUninit;
 
+use sallyport::guest;
 use sgx::enclu::EGETKEY;
 
 /// SGX derived key length in bytes
@@ -11,11 +12,19 @@ pub const SGX_KEY_LEN: usize = 16;
 /// SGX ENCLU[EGETKEY] response
 ///
 /// has to be aligned to 16 bytes as per Intel CPU spec
+///
+/// The key is zeroized on drop.
 #[repr(C, align(16))]
 pub struct Response {
     pub key: [u8; SGX_KEY_LEN],
 }
 
+impl Drop for Response {
+    fn drop(&mut self) {
+        guest::zeroize(&mut self.key);
+    }
+}
+
 /// SGX ENCLU[EGETKEY] request
 ///
 /// has to be aligned to 512 bytes as per Intel CPU spec
@@ -23,19 +23,21 @@ macro_rules! debugln {
     };
 }
 
+pub(crate) mod cpuid;
 pub(crate) mod gdb;
 pub(crate) mod key;
+pub(crate) mod tls;
 pub(crate) mod usermem;
 
 use crate::handler::usermem::UserMemScope;
 use crate::heap::Heap;
 use crate::thread::{
-    NewThread, NewThreadFromRegisters, Tcb, Tcs, ThreadMem, NEW_THREAD_QUEUE, THREADS_FREE,
-    THREAD_ID_CNT,
+    NewThread, NewThreadFromRegisters, QueuedThread, Tcb, Tcs, ThreadMem, NEW_THREAD_QUEUE,
+    PARKED_THREADS, THREAD_ID_CNT,
 };
 use crate::{
-    shim_address, CSSA_0_STACK_SIZE, CSSA_1_PLUS_STACK_SIZE, DEBUG, ENARX_EXEC_END,
-    ENARX_EXEC_START, ENCL_SIZE, NUM_SSA,
+    encl_size, shim_address, CSSA_0_STACK_SIZE, CSSA_1_PLUS_STACK_SIZE, DEBUG, ENARX_EXEC_END,
+    ENARX_EXEC_START, NUM_SSA,
 };
 use core::arch::asm;
 use core::arch::x86_64::CpuidResult;
@@ -43,37 +45,79 @@ use core::ffi::{c_int, c_size_t, c_ulong, c_void};
 use core::fmt::Write;
 use core::mem::size_of;
 use core::ptr::read_unaligned;
-use core::ptr::NonNull;
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::ptr::{addr_of_mut, NonNull};
+use core::slice;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 
 use mmledger::Access;
 use primordial::{Address, Offset, Page};
-use sallyport::guest::{self, Handler as _, Platform, ThreadLocalStorage};
+use sallyport::guest::{self, Handler as _, Platform, SpinWait, ThreadLocalStorage};
 use sallyport::item::enarxcall::sgx::{Report, ReportData, TargetInfo, TECH};
-use sallyport::item::enarxcall::{SYS_GETATT, SYS_GETKEY};
+use sallyport::item::enarxcall::{SYS_GETATT, SYS_GETKEY, SYS_GETSKEW, SYS_SETSPIN};
 use sallyport::libc::{
-    off_t, pid_t, CloneFlags, SYS_clock_gettime, EACCES, EAGAIN, EINVAL, EIO, EMSGSIZE, ENOMEM,
-    ENOSYS, ENOTSUP, MAP_ANONYMOUS, MAP_PRIVATE, PROT_EXEC, PROT_READ, PROT_WRITE, STDERR_FILENO,
+    off_t, pid_t, CloneFlags, SYS_clock_gettime, EACCES, EAGAIN, EFAULT, EINVAL, EIO, EMSGSIZE,
+    ENOMEM, EOPNOTSUPP, MADV_DONTNEED, MAP_ANONYMOUS, MAP_PRIVATE, PROT_EXEC, PROT_READ,
+    PROT_WRITE, STDERR_FILENO,
 };
 use sgx::page::{Class, Flags};
-use sgx::ssa::StateSaveArea;
 use sgx::ssa::Vector;
-use spinning::{Lazy, RwLock};
+use sgx::ssa::{GenPurposeRegs, StateSaveArea};
+use spinning::{Lazy, RwLock, RwLockWriteGuard};
 use x86_64::addr::VirtAddr;
 use x86_64::structures::paging::Page as PageAddr;
+use xsave::XSave;
 
 // Opcode constants, details in Volume 2 of the Intel 64 and IA-32 Architectures Software
 // Developer's Manual
 const OP_SYSCALL: u16 = 0x050f;
 const OP_CPUID: u16 = 0xa20f;
 
+/// Default spin window of blocking waits in nanoseconds
+///
+/// Exits of enclaves cost more than exits of VMs, but a woken thread has to enter the enclave
+/// again as well, so spinning pays off for longer.
+const SPIN_WINDOW: u64 = 100_000;
+
+/// The spin-then-block strategy of blocking waits of the exec
+pub static SPIN_WAIT: SpinWait = SpinWait::new(SPIN_WINDOW);
+
+/// Whether [`SPIN_WAIT`] was calibrated by the first syscall of the exec
+///
+/// The shim can only exit from the exception handler, not at startup.
+static SPIN_WAIT_CALIBRATED: AtomicBool = AtomicBool::new(false);
+
 /// The keep heap
 pub static HEAP: Lazy<RwLock<Heap>> = Lazy::new(|| {
-    let start = unsafe { &ENARX_EXEC_END as *const _ } as usize;
-    let end = shim_address() + ENCL_SIZE;
-    RwLock::new(Heap::new(Address::new(start), Address::new(end)))
+    let end = shim_address() + encl_size();
+    RwLock::new(Heap::new(Address::new(heap_start()), Address::new(end)))
 });
 
+/// End of the heap added by the host at build time, if the CPU lacks EDMM
+///
+/// It is `0`, until the host is asked on the first use of the heap, and
+/// `usize::MAX` with EDMM.
+static STATIC_HEAP_END: AtomicUsize = AtomicUsize::new(0);
+
+fn heap_start() -> usize {
+    unsafe { &ENARX_EXEC_END as *const _ as usize }
+}
+
+/// Whether the heap was added at build time, because the CPU lacks EDMM
+///
+/// The pages of a static heap are never removed from the enclave, and their
+/// permissions in the EPCM cannot be changed, so only the host page tables
+/// are updated.
+fn is_static_heap() -> bool {
+    !matches!(STATIC_HEAP_END.load(Ordering::Relaxed), 0 | usize::MAX)
+}
+
+/// Zero the pages of a static heap, which are either unmeasured or reused.
+fn zero_pages(addr: Address<usize, Page>, length: Offset<usize, Page>) {
+    // Safety: the pages of the static heap are part of the enclave and are
+    // made writable by the host before.
+    unsafe { core::ptr::write_bytes(addr.raw() as *mut u8, 0, length.bytes()) };
+}
+
 // For `Handler::mmap_guest()`
 static ZERO: Page = Page::zeroed();
 
@@ -167,15 +211,54 @@ impl guest::Handler for Handler<'_> {
         &mut self.tcb.tls
     }
 
+    fn spin_wait(&self) -> Option<&'static SpinWait> {
+        Some(&SPIN_WAIT)
+    }
+
+    fn clock_after_wait(&self) -> bool {
+        // Every exit is an enclave transition
+        true
+    }
+
+    fn scrub(&mut self) {
+        guest::zeroize_words(self.block);
+
+        // The register state of the exiting thread
+        unsafe {
+            guest::zeroize(slice::from_raw_parts_mut(
+                addr_of_mut!(self.ssa.xsave).cast(),
+                size_of::<XSave>(),
+            ));
+            guest::zeroize(slice::from_raw_parts_mut(
+                addr_of_mut!(self.ssa.gpr).cast(),
+                size_of::<GenPurposeRegs>(),
+            ));
+        }
+
+        // The thread control block
+        self.tcb.return_to_main = Default::default();
+        self.tcb.tls = ThreadLocalStorage::new();
+    }
+
     fn arch_prctl(
         &mut self,
-        _platform: &impl Platform,
-        _code: c_int,
-        _addr: c_ulong,
+        platform: &impl Platform,
+        code: c_int,
+        addr: c_ulong,
     ) -> sallyport::Result<()> {
         let tid = self.tcb.tid;
-        debugln!(self, "[{tid}] arch_prctl should have never been called");
-        Err(ENOSYS)
+        let ret = tls::Request::decode(code, addr).and_then(|req| match req {
+            tls::Request::Set(segment, base) => {
+                segment.set_base(&mut self.ssa.gpr, base);
+                Ok(())
+            }
+            tls::Request::Get(segment, addr) => {
+                *platform.validate_mut::<u64>(addr)? = segment.base(&self.ssa.gpr);
+                Ok(())
+            }
+        });
+        debugln!(self, "[{tid}] arch_prctl({code:#x}, {addr:#x}) = {ret:?}");
+        ret
     }
 
     fn brk(
@@ -188,17 +271,25 @@ impl guest::Handler for Handler<'_> {
                 .unwrap_or(0),
         );
 
-        let mut heap = HEAP.write();
+        let mut heap = self.heap();
         let max = heap.brk_max();
         let addr = heap.brk(addr);
 
-        if addr > max {
+        if addr > max && is_static_heap() {
+            self.mprotect_host(
+                NonNull::new(max.raw() as *mut _).unwrap(),
+                addr.raw() - max.raw(),
+                PROT_READ | PROT_WRITE,
+            )?;
+            zero_pages(max, addr - max);
+        } else if addr > max {
             self.mmap_host(
                 NonNull::new(max.raw() as *mut _).unwrap(),
                 addr.raw() - max.raw(),
                 PROT_READ | PROT_WRITE,
             )?;
-            self.mmap_guest(max, addr - max, Flags::READ | Flags::WRITE);
+            self.mmap_guest(max, addr - max, Flags::READ | Flags::WRITE)
+                .unwrap_or_else(|_| self.attacked());
         }
 
         Ok(NonNull::new(addr.raw() as *mut _).unwrap())
@@ -226,7 +317,7 @@ impl guest::Handler for Handler<'_> {
                 | CloneFlags::CHILD_CLEARTID
                 | CloneFlags::DETACHED
         {
-            return Err(ENOTSUP);
+            return Err(EOPNOTSUPP);
         }
 
         let clear_on_exit = clear_on_exit.ok_or(EINVAL)?;
@@ -247,26 +338,41 @@ impl guest::Handler for Handler<'_> {
         regs.rsp = stack.as_ptr() as _;
         regs.fsbase = tls.as_ptr() as _;
 
-        let mut threads_free_guard = THREADS_FREE.write();
+        // Reserve a slot in the queue, before a parked thread or new thread memory is taken,
+        // so a full queue fails the clone instead of losing either.
+        let mut queue = NEW_THREAD_QUEUE.write();
+        if queue.is_full() {
+            debugln!(self, "[{tid}] too many threads waiting to be scheduled");
+            return Err(EAGAIN);
+        }
 
-        let addr = if *threads_free_guard == 0 {
-            debugln!(self, "[{tid}] allocating new thread");
-            self.thread_mem_alloc().map_err(|_| EAGAIN)? as usize
-        } else {
-            *threads_free_guard -= 1;
-            0
-        };
+        let parked = PARKED_THREADS.write().take();
 
-        drop(threads_free_guard);
+        // The affinity is keyed on the TCS address, like the TCS a thread is picked up on
+        let (addr, affinity) = match parked {
+            None => {
+                debugln!(self, "[{tid}] allocating new thread");
+                let tcs = self.thread_mem_alloc().map_err(|_| EAGAIN)? as usize;
+                (tcs, Some(tcs))
+            }
+            Some(tcs) => {
+                debugln!(self, "[{tid}] reusing parked thread {tcs:#x?}");
+                (0, tcs)
+            }
+        };
 
-        NEW_THREAD_QUEUE
-            .write()
-            .push(NewThread::Thread(NewThreadFromRegisters {
-                clear_on_exit: clear_on_exit as *const _ as _,
-                regs,
-                tid: new_tid,
-            }))
+        queue
+            .push(QueuedThread {
+                thread: NewThread::Thread(NewThreadFromRegisters {
+                    clear_on_exit: clear_on_exit as *const _ as _,
+                    regs,
+                    tid: new_tid,
+                }),
+                affinity,
+            })
             .unwrap();
+        // The new thread pops itself from the queue
+        drop(queue);
 
         ptid.store(new_tid as _, Ordering::Relaxed);
 
@@ -310,11 +416,18 @@ impl guest::Handler for Handler<'_> {
     fn madvise(
         &mut self,
         _platform: &impl Platform,
-        _addr: NonNull<c_void>,
-        _length: c_size_t,
-        _advice: c_int,
+        addr: NonNull<c_void>,
+        length: c_size_t,
+        advice: c_int,
     ) -> sallyport::Result<()> {
-        Ok(())
+        // Any other advice is only a hint.
+        if advice != MADV_DONTNEED {
+            return Ok(());
+        }
+
+        let mut heap = self.heap();
+
+        self.discard_unlocked(&mut heap, addr, length)
     }
 
     fn mmap(
@@ -335,7 +448,7 @@ impl guest::Handler for Handler<'_> {
 
         if addr != 0 || len == 0 || fd != -1 || offset != 0 || flags != MAP_PRIVATE | MAP_ANONYMOUS
         {
-            return Err(ENOTSUP);
+            return Err(EOPNOTSUPP);
         }
 
         if prot != 0 && !is_prot_allowed(prot) {
@@ -344,17 +457,53 @@ impl guest::Handler for Handler<'_> {
 
         let length = Offset::from_items((len + Page::SIZE - 1) / Page::SIZE);
         let access = access_from_libc(prot);
-        let mut heap = HEAP.write();
+        let mut heap = self.heap();
 
         if let Some(addr) = heap.mmap(None, length, access) {
             let ret = NonNull::new(addr.raw() as *mut c_void).unwrap();
 
-            if let Err(e) = self.mmap_host(
-                NonNull::new(addr.raw() as *mut _).unwrap(),
-                length.bytes(),
-                PROT_READ | PROT_WRITE,
-            ) {
-                debugln!(self, "[{tid}] ERROR mmap_host() = {e:#?}");
+            // The host refusing to commit the pages is reported as `EAGAIN`, and
+            // the host not having committed all of them as `EFAULT`, so that
+            // both can be told apart from the exhausted heap (`ENOMEM`).
+            let err = if is_static_heap() {
+                match self.mprotect_host(ret, length.bytes(), PROT_READ | PROT_WRITE) {
+                    Err(e) => {
+                        debugln!(self, "[{tid}] ERROR mprotect_host() = {e:#?}");
+                        Some(EAGAIN)
+                    }
+                    Ok(()) => {
+                        zero_pages(addr, length);
+                        None
+                    }
+                }
+            } else {
+                match self.mmap_host(ret, length.bytes(), PROT_READ | PROT_WRITE) {
+                    Err(e) => {
+                        debugln!(self, "[{tid}] ERROR mmap_host() = {e:#?}");
+                        Some(EAGAIN)
+                    }
+                    Ok(()) => match self.mmap_guest(addr, length, flags_from_libc(prot)) {
+                        Err(accepted) => {
+                            debugln!(
+                                self,
+                                "[{tid}] ERROR mmap_guest() accepted {} of {} pages",
+                                accepted.items(),
+                                length.items()
+                            );
+                            // The pages not accepted are not part of the enclave.
+                            if accepted.items() > 0 {
+                                self.remove_pages(addr, accepted);
+                            }
+                            self.munmap_host(ret, length.bytes())
+                                .unwrap_or_else(|_| self.attacked());
+                            Some(EFAULT)
+                        }
+                        Ok(()) => None,
+                    },
+                }
+            };
+
+            if let Some(e) = err {
                 // undo the mmap
                 heap.munmap(addr, length).unwrap_or_else(|e| {
                     panic!(
@@ -366,8 +515,6 @@ impl guest::Handler for Handler<'_> {
                 return Err(e);
             }
 
-            self.mmap_guest(addr, length, flags_from_libc(prot));
-
             // If the previous operations succeeded, the virtual memory area
             // (VMA) is already RW.
             if prot != PROT_READ | PROT_WRITE {
@@ -379,7 +526,7 @@ impl guest::Handler for Handler<'_> {
             }
             Ok(ret)
         } else {
-            debugln!(self, "[{tid}] ERROR heap.mmap() failed!!!!");
+            debugln!(self, "[{tid}] ERROR heap.mmap() failed: heap exhausted");
             Err(ENOMEM)
         }
     }
@@ -391,7 +538,7 @@ impl guest::Handler for Handler<'_> {
         len: c_size_t,
         prot: c_int,
     ) -> sallyport::Result<()> {
-        let mut heap = HEAP.write();
+        let mut heap = self.heap();
 
         self.mprotect_unlocked(&mut heap, addr, len, prot)
     }
@@ -402,7 +549,7 @@ impl guest::Handler for Handler<'_> {
         addr: NonNull<c_void>,
         length: c_size_t,
     ) -> sallyport::Result<()> {
-        let mut heap = HEAP.write();
+        let mut heap = self.heap();
 
         self.munmap_unlocked(&mut heap, addr, length)
     }
@@ -421,6 +568,36 @@ impl guest::Handler for Handler<'_> {
 }
 
 impl<'a> Handler<'a> {
+    /// Lock the heap, which is limited to the pages added at build time, if
+    /// the CPU lacks EDMM.
+    ///
+    /// The host is asked on the first use of the heap. Lying about it only
+    /// crashes the enclave: pages, which were not added, fault on access, and
+    /// pages, which were added, fail to be accepted again.
+    fn heap(&mut self) -> RwLockWriteGuard<'static, Heap> {
+        let mut heap = HEAP.write();
+
+        if STATIC_HEAP_END.load(Ordering::Relaxed) == 0 {
+            let end = match self.get_sgx_static_heap() {
+                Ok(0) | Err(_) => usize::MAX,
+                Ok(end) => {
+                    if end & (Page::SIZE - 1) != 0
+                        || end <= heap_start()
+                        || end > shim_address() + encl_size()
+                    {
+                        self.attacked();
+                    }
+                    debugln!(self, "static heap: {:#x}-{end:#x}", heap_start());
+                    *heap = Heap::new(Address::new(heap_start()), Address::new(end));
+                    end
+                }
+            };
+            STATIC_HEAP_END.store(end, Ordering::Relaxed);
+        }
+
+        heap
+    }
+
     fn new(
         ssa: &'a mut StateSaveArea,
         block: &'a mut [usize],
@@ -464,6 +641,8 @@ impl<'a> Handler<'a> {
             Some(Vector::InvalidOpcode) => match unsafe { read_unaligned(h.ssa.gpr.rip as _) } {
                 OP_SYSCALL => h.handle_syscall(),
                 OP_CPUID => h.handle_cpuid(),
+                // Safety: `rip` points to the code of the exec layer, which raised `#UD`.
+                _ if unsafe { tls::emulate(&mut h.ssa.gpr) } => {}
                 r => {
                     debugln!(h, "unsupported opcode: {:#04x}", r);
                     h.print_ssa_stack_trace();
@@ -492,6 +671,8 @@ impl<'a> Handler<'a> {
                 }
             },
 
+            Some(Vector::Page) if h.handle_page_fault() => {}
+
             #[cfg(feature = "gdb")]
             Some(Vector::Page) => {
                 h.print_ssa_stack_trace();
@@ -509,6 +690,10 @@ impl<'a> Handler<'a> {
         }
     }
 
+    /// Copies the seal key to `buf`
+    ///
+    /// The key never leaves the enclave and is handled without any exit to the host, so it
+    /// cannot influence the exit pattern or the sallyport block.
     fn get_key(
         &mut self,
         platform: &impl Platform,
@@ -607,9 +792,18 @@ impl<'a> Handler<'a> {
             debugln!(self, "[{tid}] syscall {nr} ...");
         }
 
+        if !SPIN_WAIT_CALIBRATED.swap(true, Ordering::Relaxed) {
+            self.calibrate_spin_wait();
+        }
+
         let usermemscope = UserMemScope;
 
         match nr as i64 {
+            SYS_SETSPIN => {
+                SPIN_WAIT.set_window(self.ssa.gpr.rdi);
+                self.ssa.gpr.rax = 0;
+                self.ssa.gpr.rdx = orig_rdx;
+            }
             SYS_GETKEY => {
                 let ret = self.get_key(&usermemscope, self.ssa.gpr.rdi as _, self.ssa.gpr.rsi as _);
                 match ret {
@@ -620,6 +814,8 @@ impl<'a> Handler<'a> {
                     }
                 }
             }
+            // The enclave has no trusted clock, so skews of the host wall time are not detected
+            SYS_GETSKEW => self.ssa.gpr.rax = -EOPNOTSUPP as u64,
             SYS_GETATT => {
                 let ret = self.get_attestation(
                     &usermemscope,
@@ -671,6 +867,20 @@ impl<'a> Handler<'a> {
         }
     }
 
+    /// Returns the number of logical processors the enclave can run threads on
+    ///
+    /// Without EDMM, no TCS can be added to the enclave, so it runs on a single one.
+    fn logical_processors(&mut self) -> u32 {
+        // Ask the host for a static heap, if not done yet
+        drop(self.heap());
+
+        if is_static_heap() {
+            1
+        } else {
+            cpuid::MAX_LOGICAL_PROCESSORS
+        }
+    }
+
     fn handle_cpuid(&mut self) {
         let mut cpuid_result: CpuidResult = CpuidResult {
             eax: 0,
@@ -692,6 +902,13 @@ impl<'a> Handler<'a> {
             &mut cpuid_result,
         )
         .unwrap();
+        let cpus = self.logical_processors();
+        cpuid::apply(
+            self.ssa.gpr.rax as _,
+            self.ssa.gpr.rcx as _,
+            &mut cpuid_result,
+            cpus,
+        );
 
         self.ssa.gpr.rax = cpuid_result.eax.into();
         self.ssa.gpr.rbx = cpuid_result.ebx.into();
@@ -711,12 +928,15 @@ impl<'a> Handler<'a> {
     }
 
     /// Acknowledge pages committed by the host with ENCLS[EAUG].
+    ///
+    /// Fails with the number of accepted pages, if the host did not commit
+    /// all of the pages.
     fn mmap_guest(
         &mut self,
         addr: Address<usize, Page>,
         length: Offset<usize, Page>,
         flags: Flags,
-    ) {
+    ) -> Result<(), Offset<usize, Page>> {
         let zero_virt_addr = VirtAddr::new(ZERO.as_ptr() as u64);
         // # Safety
         //
@@ -733,8 +953,10 @@ impl<'a> Handler<'a> {
             Class::Regular
                 .info(flags)
                 .accept_copy(page_addr, zero_page_addr)
-                .unwrap_or_else(|_| self.attacked());
+                .map_err(|_| Offset::from_items(i))?;
         }
+
+        Ok(())
     }
 
     fn mprotect_unlocked(
@@ -763,22 +985,26 @@ impl<'a> Handler<'a> {
             return Err(ENOMEM);
         }
 
+        self.restore_unlocked(heap, addr, length);
         self.mprotect_host(addr_in, length.bytes(), prot)?;
 
-        for i in 0..pages {
-            let virt_addr = VirtAddr::new((addr.raw() + i * Page::SIZE) as u64);
-            // Safety: The address is guaranteed to be page aligned, because
-            // `addr` was checked to be page aligned and only a multiple of
-            // pages was added.
-            let page_addr = unsafe { PageAddr::from_start_address_unchecked(virt_addr) };
-
-            // TODO: https://github.com/enarx/enarx/issues/1892
-            Class::Regular
-                .info(Flags::READ | Flags::RESTRICTED)
-                .accept(page_addr)
-                .unwrap_or_else(|_| self.attacked());
-
-            Class::Regular.info(flags_from_libc(prot)).extend(page_addr);
+        // The EPCM permissions of a static heap cannot be changed.
+        if !is_static_heap() {
+            for i in 0..pages {
+                let virt_addr = VirtAddr::new((addr.raw() + i * Page::SIZE) as u64);
+                // Safety: The address is guaranteed to be page aligned, because
+                // `addr` was checked to be page aligned and only a multiple of
+                // pages was added.
+                let page_addr = unsafe { PageAddr::from_start_address_unchecked(virt_addr) };
+
+                // TODO: https://github.com/enarx/enarx/issues/1892
+                Class::Regular
+                    .info(Flags::READ | Flags::RESTRICTED)
+                    .accept(page_addr)
+                    .unwrap_or_else(|_| self.attacked());
+
+                Class::Regular.info(flags_from_libc(prot)).extend(page_addr);
+            }
         }
 
         let access = access_from_libc(prot);
@@ -808,6 +1034,8 @@ impl<'a> Handler<'a> {
             return Ok(());
         }
 
+        self.restore_unlocked(heap, addr, length);
+
         // Process the ledger first, before doing anything else, because it can
         // legitly fail when running out of resources.
         if let Err(e) = heap.munmap(addr, length) {
@@ -815,18 +1043,35 @@ impl<'a> Handler<'a> {
             return Err(ENOMEM);
         }
 
-        // On the other hand, failing in any of these operations is expected to
-        // crash the enclave because it is due either to a software bug, or a
-        // malicious host.
+        // The pages of a static heap stay in the enclave, and are zeroed on reuse.
+        if is_static_heap() {
+            return Ok(());
+        }
+
+        self.remove_pages(addr, length);
+        self.munmap_host(addr_in, length.bytes())
+            .unwrap_or_else(|_| self.attacked());
+
+        Ok(())
+    }
+
+    /// Trim the pages of a region, and remove them from the enclave.
+    ///
+    /// Failing in any of these operations is expected to crash the enclave
+    /// because it is due either to a software bug, or a malicious host.
+    fn remove_pages(&mut self, addr: Address<usize, Page>, length: Offset<usize, Page>) {
+        let tid = self.tcb.tid;
+        let addr_in = NonNull::new(addr.raw() as *mut c_void).unwrap();
+
         if let Err(e) = self.modify_sgx_page_type(addr_in, length.bytes(), Class::Trimmed as _) {
             debugln!(
                 self,
-                "[{tid}] ERROR munmap: modify_sgx_page_type FAILED !!! {e:?}"
+                "[{tid}] ERROR remove_pages: modify_sgx_page_type FAILED !!! {e:?}"
             );
             self.attacked();
         }
 
-        for i in 0..pages {
+        for i in 0..length.items() {
             let virt_addr = VirtAddr::new((addr.raw() + i * Page::SIZE) as u64);
             // # Safety
             //
@@ -838,13 +1083,105 @@ impl<'a> Handler<'a> {
                 .accept(page_addr)
                 .unwrap_or_else(|_| self.attacked());
         }
+    }
+
+    /// Release the pages of a region to the host with `madvise(MADV_DONTNEED)`
+    /// semantics.
+    ///
+    /// The pages are removed from the enclave, but stay mapped by the host.
+    /// On the next access the host adds them back, and the page fault handler
+    /// accepts them zeroed and read-write.
+    fn discard_unlocked(
+        &mut self,
+        heap: &mut Heap,
+        addr_in: NonNull<c_void>,
+        length_in: c_size_t,
+    ) -> sallyport::Result<()> {
+        let tid = self.tcb.tid;
+        let addr = addr_in.as_ptr() as usize;
+        let pages = ((length_in + Page::SIZE - 1) & !(Page::SIZE - 1)) / Page::SIZE;
 
+        if addr & 0xfff != 0 {
+            return Err(EINVAL);
+        }
+
+        if pages == 0 {
+            return Ok(());
+        }
+
+        let addr = Address::new(addr);
+        let length = Offset::from_items(pages);
+
+        if heap.contains(addr, length).is_none() {
+            return Err(ENOMEM);
+        }
+
+        // The pages of a static heap cannot be removed from the enclave.
+        if is_static_heap() {
+            zero_pages(addr, length);
+            return Ok(());
+        }
+
+        // Pages discarded before are not part of the enclave and cannot be
+        // trimmed again.
+        self.restore_unlocked(heap, addr, length);
+
+        // Record the discarded pages first, so that a concurrent access of
+        // another thread restores them.
+        if let Err(e) = heap.discard(addr, length) {
+            // Without capacity to track the pages, keep them, but zero them.
+            debugln!(self, "[{tid}] madvise: heap.discard FAILED {e:?}");
+            // Safety: the region is mapped, and `MADV_DONTNEED` is only
+            // used by allocators on writable memory.
+            unsafe { core::ptr::write_bytes(addr.raw() as *mut u8, 0, length.bytes()) };
+            return Ok(());
+        }
+
+        self.remove_pages(addr, length);
         self.munmap_host(addr_in, length.bytes())
             .unwrap_or_else(|_| self.attacked());
+        self.mmap_host(addr_in, length.bytes(), PROT_READ | PROT_WRITE)
+            .unwrap_or_else(|_| self.attacked());
 
+        debugln!(
+            self,
+            "[{tid}] madvise: discarded {} pages, {} pages in total",
+            length.items(),
+            heap.discarded().items()
+        );
         Ok(())
     }
 
+    /// Restore the discarded pages overlapping the given region.
+    fn restore_unlocked(
+        &mut self,
+        heap: &mut Heap,
+        addr: Address<usize, Page>,
+        length: Offset<usize, Page>,
+    ) {
+        while let Some((addr, length)) = heap.restore(addr, length) {
+            self.mmap_guest(addr, length, Flags::READ | Flags::WRITE)
+                .unwrap_or_else(|_| self.attacked());
+        }
+    }
+
+    /// Restore a discarded page on access.
+    ///
+    /// Returns `false`, if the page fault was not caused by a discarded page.
+    fn handle_page_fault(&mut self) -> bool {
+        let addr = self.ssa.misc.exinfo.maddr as usize & !(Page::SIZE - 1);
+        let mut heap = HEAP.write();
+
+        match heap.restore(Address::new(addr), Offset::from_items(1)) {
+            Some((addr, length)) => {
+                self.mmap_guest(addr, length, Flags::READ | Flags::WRITE)
+                    .unwrap_or_else(|_| self.attacked());
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Print a stack trace using the SSA registers.
     fn print_ssa_stack_trace(&mut self) {
         if DEBUG {
@@ -875,8 +1212,9 @@ impl<'a> Handler<'a> {
     /// Print a stack trace with the old `rbp` stack frame pointers
     unsafe fn print_stack_trace(&mut self, rip: u64, mut rbp: u64) {
         // TODO: parse the elf and actually find the text sections.
-        let encl_start = self as *const _ as u64 / ENCL_SIZE as u64 * ENCL_SIZE as u64;
-        let encl_end = encl_start + ENCL_SIZE as u64;
+        let encl_size = encl_size() as u64;
+        let encl_start = self as *const _ as u64 / encl_size * encl_size;
+        let encl_end = encl_start + encl_size;
         let encl_range = encl_start..encl_end;
 
         debugln!(self, "TRACE:");
@@ -914,6 +1252,11 @@ impl<'a> Handler<'a> {
     fn thread_mem_alloc(&mut self) -> sallyport::Result<*const Tcs> {
         let usermemscope = UserMemScope;
 
+        // Without EDMM, no TCS pages can be added to the enclave.
+        if is_static_heap() {
+            return Err(EOPNOTSUPP);
+        }
+
         // Allocate the whole block of memory used for the thread.
         // It is easier to do this in one go and punch holes in it,
         // than to allocate each part separately.
@@ -944,6 +1287,8 @@ impl<'a> Handler<'a> {
         tm.tcs.nssa = NUM_SSA as _;
         // address is relative to the enclave base
         tm.tcs.oentry = self.start - shim_address() as u64;
+        // the GS base of the shim points to the TCB page, which precedes the TCS
+        tm.tcs.ogsbasgx = tcs as u64 - Page::SIZE as u64 - shim_address() as u64;
 
         // unmap stack guard pages, so a stack overflow will cause a page fault
         self.munmap(&usermemscope, stack_end_1, Page::SIZE)
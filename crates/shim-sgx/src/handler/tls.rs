@@ -0,0 +1,314 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Thread-local storage management of the exec layer
+//!
+//! The FS and GS base of the exec layer are part of the register state saved in the SSA,
+//! which is restored on `ERESUME`. `arch_prctl()` reads and updates the saved state, so that
+//! a standard libc can set up its TLS. If the host did not enable the FSGSBASE instructions,
+//! `rdfsbase`, `rdgsbase`, `wrfsbase` and `wrgsbase` raise `#UD` and are emulated on the
+//! saved state as well.
+
+use core::ffi::{c_int, c_ulong};
+
+use sallyport::item::syscall::{ARCH_GET_FS, ARCH_GET_GS, ARCH_SET_FS, ARCH_SET_GS};
+use sallyport::libc::{EINVAL, EPERM};
+use sgx::ssa::GenPurposeRegs;
+
+/// The end of the canonical lower half of the address space
+const TASK_SIZE_MAX: u64 = 1 << 47;
+
+/// The maximum length of an FSGSBASE instruction
+pub const INSN_MAX_LEN: usize = 5;
+
+/// A segment with a base register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// FS
+    Fs,
+    /// GS
+    Gs,
+}
+
+impl Segment {
+    /// Returns the base of the segment in `regs`
+    #[inline]
+    pub fn base(self, regs: &GenPurposeRegs) -> u64 {
+        match self {
+            Self::Fs => regs.fsbase,
+            Self::Gs => regs.gsbase,
+        }
+    }
+
+    /// Sets the base of the segment in `regs` to `base`
+    #[inline]
+    pub fn set_base(self, regs: &mut GenPurposeRegs, base: u64) {
+        match self {
+            Self::Fs => regs.fsbase = base,
+            Self::Gs => regs.gsbase = base,
+        }
+    }
+}
+
+/// A decoded `arch_prctl()` request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Request {
+    /// Set the base of the segment to the value
+    Set(Segment, u64),
+    /// Store the base of the segment at the address
+    Get(Segment, usize),
+}
+
+impl Request {
+    /// Decodes the `arch_prctl()` arguments like Linux does
+    pub fn decode(code: c_int, addr: c_ulong) -> Result<Self, c_int> {
+        let req = match code {
+            ARCH_SET_FS => Self::Set(Segment::Fs, addr as _),
+            ARCH_SET_GS => Self::Set(Segment::Gs, addr as _),
+            ARCH_GET_FS => Self::Get(Segment::Fs, addr as _),
+            ARCH_GET_GS => Self::Get(Segment::Gs, addr as _),
+            _ => return Err(EINVAL),
+        };
+
+        match req {
+            Self::Set(_, base) if base >= TASK_SIZE_MAX => Err(EPERM),
+            req => Ok(req),
+        }
+    }
+}
+
+/// A decoded FSGSBASE instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    /// The segment, whose base is accessed
+    pub segment: Segment,
+    /// Whether the base is written
+    pub write: bool,
+    /// The index of the general purpose register operand
+    pub reg: u8,
+    /// Whether the operand size is 64 bit
+    pub wide: bool,
+    /// The length of the instruction in bytes
+    pub len: u8,
+}
+
+impl Instruction {
+    /// Decodes `rdfsbase`, `rdgsbase`, `wrfsbase` or `wrgsbase` from `code`
+    ///
+    /// The encoding is `F3 [REX] 0F AE /0../3` with a register operand.
+    pub fn decode(code: &[u8; INSN_MAX_LEN]) -> Option<Self> {
+        if code[0] != 0xf3 {
+            return None;
+        }
+
+        let (rex, rest) = match code[1] {
+            rex @ 0x40..=0x4f => (rex, &code[2..]),
+            _ => (0, &code[1..]),
+        };
+
+        let modrm = match rest {
+            [0x0f, 0xae, modrm, ..] if modrm >> 6 == 0b11 => *modrm,
+            _ => return None,
+        };
+
+        let (segment, write) = match (modrm >> 3) & 0b111 {
+            0 => (Segment::Fs, false),
+            1 => (Segment::Gs, false),
+            2 => (Segment::Fs, true),
+            3 => (Segment::Gs, true),
+            _ => return None,
+        };
+
+        Some(Self {
+            segment,
+            write,
+            reg: (rex & 0b1) << 3 | (modrm & 0b111),
+            wide: rex & 0b1000 != 0,
+            len: (INSN_MAX_LEN - rest.len() + 3) as u8,
+        })
+    }
+
+    /// Emulates the instruction on `regs` and skips it
+    ///
+    /// Returns `false` without touching `regs`, if the instruction would raise `#GP`,
+    /// because a non-canonical base is written.
+    pub fn emulate(&self, regs: &mut GenPurposeRegs) -> bool {
+        if self.write {
+            let mut base = *gpr_mut(regs, self.reg);
+            if !self.wide {
+                base &= u32::MAX as u64;
+            }
+            // Sign extension from bit 47 must not change the address
+            if ((base << 16) as i64 >> 16) as u64 != base {
+                return false;
+            }
+            self.segment.set_base(regs, base);
+        } else {
+            let mut base = self.segment.base(regs);
+            if !self.wide {
+                base &= u32::MAX as u64;
+            }
+            *gpr_mut(regs, self.reg) = base;
+        }
+
+        regs.rip += self.len as u64;
+        true
+    }
+}
+
+/// Returns the general purpose register with the index `reg` in the ModRM encoding
+fn gpr_mut(regs: &mut GenPurposeRegs, reg: u8) -> &mut u64 {
+    match reg {
+        0 => &mut regs.rax,
+        1 => &mut regs.rcx,
+        2 => &mut regs.rdx,
+        3 => &mut regs.rbx,
+        4 => &mut regs.rsp,
+        5 => &mut regs.rbp,
+        6 => &mut regs.rsi,
+        7 => &mut regs.rdi,
+        8 => &mut regs.r8,
+        9 => &mut regs.r9,
+        10 => &mut regs.r10,
+        11 => &mut regs.r11,
+        12 => &mut regs.r12,
+        13 => &mut regs.r13,
+        14 => &mut regs.r14,
+        _ => &mut regs.r15,
+    }
+}
+
+/// Emulates the FSGSBASE instruction at `regs.rip`, which raised `#UD`
+///
+/// Returns `false`, if there is no FSGSBASE instruction to emulate.
+///
+/// # Safety
+///
+/// The caller has to ensure `regs.rip` points to readable memory of at least
+/// [`INSN_MAX_LEN`] bytes.
+pub unsafe fn emulate(regs: &mut GenPurposeRegs) -> bool {
+    let code = core::ptr::read_unaligned(regs.rip as *const [u8; INSN_MAX_LEN]);
+    match Instruction::decode(&code) {
+        Some(insn) => insn.emulate(regs),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regs() -> GenPurposeRegs {
+        // Safety: all fields are plain integers
+        unsafe { core::mem::zeroed() }
+    }
+
+    #[test]
+    fn request() {
+        assert_eq!(
+            Request::decode(ARCH_SET_FS, 0x1000),
+            Ok(Request::Set(Segment::Fs, 0x1000))
+        );
+        assert_eq!(
+            Request::decode(ARCH_SET_GS, 0x2000),
+            Ok(Request::Set(Segment::Gs, 0x2000))
+        );
+        assert_eq!(
+            Request::decode(ARCH_GET_FS, 0x3000),
+            Ok(Request::Get(Segment::Fs, 0x3000))
+        );
+        assert_eq!(
+            Request::decode(ARCH_GET_GS, 0x4000),
+            Ok(Request::Get(Segment::Gs, 0x4000))
+        );
+        assert_eq!(Request::decode(ARCH_SET_FS, 1 << 47), Err(EPERM));
+        assert_eq!(Request::decode(0x1005, 0), Err(EINVAL));
+    }
+
+    #[test]
+    fn decode() {
+        let code = |bytes: &[u8]| {
+            let mut code = [0x90; INSN_MAX_LEN];
+            code[..bytes.len()].copy_from_slice(bytes);
+            Instruction::decode(&code)
+        };
+
+        // rdfsbase rax
+        assert_eq!(
+            code(&[0xf3, 0x48, 0x0f, 0xae, 0xc0]),
+            Some(Instruction {
+                segment: Segment::Fs,
+                write: false,
+                reg: 0,
+                wide: true,
+                len: 5,
+            })
+        );
+
+        // wrgsbase r9
+        assert_eq!(
+            code(&[0xf3, 0x49, 0x0f, 0xae, 0xd9]),
+            Some(Instruction {
+                segment: Segment::Gs,
+                write: true,
+                reg: 9,
+                wide: true,
+                len: 5,
+            })
+        );
+
+        // rdgsbase ecx
+        assert_eq!(
+            code(&[0xf3, 0x0f, 0xae, 0xc9]),
+            Some(Instruction {
+                segment: Segment::Gs,
+                write: false,
+                reg: 1,
+                wide: false,
+                len: 4,
+            })
+        );
+
+        // Memory operand: fxsave
+        assert_eq!(code(&[0xf3, 0x0f, 0xae, 0x00]), None);
+        // Other register form: lfence
+        assert_eq!(code(&[0x0f, 0xae, 0xe8]), None);
+        // ptwrite
+        assert_eq!(code(&[0xf3, 0x0f, 0xae, 0xe0]), None);
+    }
+
+    #[test]
+    fn emulate() {
+        let mut regs = regs();
+
+        // wrfsbase rdx
+        let wr = Instruction::decode(&[0xf3, 0x48, 0x0f, 0xae, 0xd2]).unwrap();
+        regs.rdx = 0x7fff_dead_b000;
+        assert!(wr.emulate(&mut regs));
+        assert_eq!(regs.fsbase, 0x7fff_dead_b000);
+        assert_eq!(regs.rip, 5);
+
+        // rdfsbase r15
+        let rd = Instruction::decode(&[0xf3, 0x49, 0x0f, 0xae, 0xc7]).unwrap();
+        assert!(rd.emulate(&mut regs));
+        assert_eq!(regs.r15, 0x7fff_dead_b000);
+        assert_eq!(regs.rip, 10);
+
+        // rdfsbase eax
+        let rd = Instruction::decode(&[0xf3, 0x0f, 0xae, 0xc0, 0x90]).unwrap();
+        regs.rax = u64::MAX;
+        assert!(rd.emulate(&mut regs));
+        assert_eq!(regs.rax, 0xdead_b000);
+        assert_eq!(regs.rip, 14);
+
+        // wrfsbase rdx with a non-canonical address
+        regs.rdx = 0x8000_0000_0000;
+        assert!(!wr.emulate(&mut regs));
+        assert_eq!(regs.fsbase, 0x7fff_dead_b000);
+        assert_eq!(regs.rip, 14);
+
+        // Kernel half addresses are canonical
+        regs.rdx = 0xffff_8000_0000_0000;
+        assert!(wr.emulate(&mut regs));
+        assert_eq!(Segment::Fs.base(&regs), 0xffff_8000_0000_0000);
+    }
+}
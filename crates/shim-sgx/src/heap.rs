@@ -5,6 +5,9 @@
 use mmledger::{Access, Ledger, Region};
 use primordial::{Address, Offset, Page};
 
+/// The maximum number of discarded regions
+const DISCARDED: usize = 512;
+
 /// A heap
 pub struct Heap {
     start: Address<usize, Page>,
@@ -14,6 +17,8 @@ pub struct Heap {
     // FIXME: use a dynamic Ledger
     // https://github.com/enarx/enarx/issues/2264
     ledger: Ledger<8188>,
+    /// Regions released with `madvise(MADV_DONTNEED)`, which are restored on access
+    discarded: Ledger<DISCARDED>,
 }
 
 impl Heap {
@@ -26,6 +31,7 @@ impl Heap {
             brk: start,
             brk_max: start,
             ledger: Ledger::new(region),
+            discarded: Ledger::new(region),
         }
     }
 
@@ -93,6 +99,56 @@ impl Heap {
     ) -> Result<(), mmledger::Error> {
         self.ledger.unmap(addr, length)
     }
+
+    /// Mark a region as discarded.
+    pub fn discard(
+        &mut self,
+        addr: Address<usize, Page>,
+        length: Offset<usize, Page>,
+    ) -> Result<(), mmledger::Error> {
+        self.discarded
+            .map(addr, length, Access::READ | Access::WRITE)
+    }
+
+    /// Take the first discarded region overlapping the given region out of
+    /// the discarded regions, and return it for restoration.
+    ///
+    /// The whole discarded region is returned, if there is no capacity left
+    /// to split it.
+    pub fn restore(
+        &mut self,
+        addr: Address<usize, Page>,
+        length: Offset<usize, Page>,
+    ) -> Option<(Address<usize, Page>, Offset<usize, Page>)> {
+        let end = addr + length;
+        let record = *self
+            .discarded
+            .records()
+            .iter()
+            .find(|record| record.region.start < end && addr < record.region.end)?;
+
+        let whole = (record.region.start, record.region.end - record.region.start);
+        let start = record.region.start.max(addr);
+        let part = (start, record.region.end.min(end) - start);
+
+        let restored = if self.discarded.records().len() < DISCARDED {
+            part
+        } else {
+            whole
+        };
+        self.discarded.unmap(restored.0, restored.1).ok()?;
+        Some(restored)
+    }
+
+    /// Return the number of discarded pages.
+    pub fn discarded(&self) -> Offset<usize, Page> {
+        self.discarded
+            .records()
+            .iter()
+            .fold(Offset::from_items(0), |sum, record| {
+                sum + (record.region.end - record.region.start)
+            })
+    }
 }
 
 #[cfg(test)]
@@ -153,6 +209,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn discard() {
+        let mut heap = Heap::new(Address::new(0), Address::new(BYTES));
+        let page = |n: usize| Address::new(n * Page::SIZE);
+        let pages = Offset::from_items;
+
+        assert_eq!(heap.restore(page(0), pages(PAGES)), None);
+
+        heap.discard(page(8), pages(8)).unwrap();
+        heap.discard(page(32), pages(4)).unwrap();
+        assert_eq!(heap.discarded(), pages(12));
+
+        // A single page is restored from the middle of a region
+        assert_eq!(heap.restore(page(10), pages(1)), Some((page(10), pages(1))));
+        assert_eq!(heap.restore(page(10), pages(1)), None);
+        assert_eq!(heap.discarded(), pages(11));
+
+        // Overlapping regions are restored one after the other
+        assert_eq!(heap.restore(page(0), pages(34)), Some((page(8), pages(2))));
+        assert_eq!(heap.restore(page(0), pages(34)), Some((page(11), pages(5))));
+        assert_eq!(heap.restore(page(0), pages(34)), Some((page(32), pages(2))));
+        assert_eq!(heap.restore(page(0), pages(34)), None);
+        assert_eq!(heap.discarded(), pages(2));
+    }
+
+    #[test]
+    fn discard_full() {
+        let mut heap = Heap::new(Address::new(0), Address::new(DISCARDED * 4 * Page::SIZE));
+        let page = |n: usize| Address::new(n * Page::SIZE);
+        let pages = Offset::from_items;
+
+        for i in 0..DISCARDED {
+            heap.discard(page(i * 4), pages(3)).unwrap();
+        }
+        assert!(heap.discard(page(DISCARDED * 4 - 1), pages(1)).is_err());
+
+        // Without capacity to split a region, it is restored as a whole
+        assert_eq!(heap.restore(page(1), pages(1)), Some((page(0), pages(3))));
+        assert_eq!(heap.restore(page(5), pages(1)), Some((page(5), pages(1))));
+    }
+
     #[test]
     fn mmap_oversubscribe() {
         let mut heap = Heap::new(Address::new(0), Address::new(BYTES));
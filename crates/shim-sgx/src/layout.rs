@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Layout of the enclave
+//!
+//! The shim is loaded at the start of the enclave, followed by the slot of the exec and the
+//! heap up to the end of the enclave. The size of the enclave is a power of two between
+//! `1 << ENCL_SIZE_BITS` and `1 << MAX_ENCL_SIZE_BITS`, which the backend chooses for the
+//! memory limits of the Keep. It writes the number of bits into the `BITS` note of the shim
+//! before the note is measured, so the size is part of MRENCLAVE, and the shim reads the note
+//! back from its own ELF headers.
+
+use crate::{shim_address, ENCL_SIZE_BITS, MAX_ENCL_SIZE_BITS};
+
+use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use goblin::elf::header::header64::Header;
+use goblin::elf::program_header::program_header64::ProgramHeader;
+use goblin::elf::program_header::PT_NOTE;
+use sallyport::elf::note;
+
+/// Size of the header of an ELF note
+const NOTE_HEADER: usize = 12;
+
+/// Returns the descriptor of the first note of `kind` named `name` in the `notes` of a segment
+pub fn find_note<'a>(mut notes: &'a [u8], name: &str, kind: u32) -> Option<&'a [u8]> {
+    let align = |n: usize| (n + 3) & !3;
+    let word = |bytes: &[u8], i: usize| {
+        let bytes = bytes.get(i * 4..i * 4 + 4)?;
+        Some(u32::from_ne_bytes(bytes.try_into().ok()?) as usize)
+    };
+
+    while notes.len() >= NOTE_HEADER {
+        let (namesz, descsz) = (word(notes, 0)?, word(notes, 1)?);
+        let typ = word(notes, 2)? as u32;
+        let desc = NOTE_HEADER.checked_add(align(namesz))?;
+        let next = desc.checked_add(align(descsz))?;
+        let n = notes.get(NOTE_HEADER..NOTE_HEADER + namesz)?;
+        let d = notes.get(desc..desc + descsz)?;
+
+        // The name is terminated by a NUL byte
+        if typ == kind && n.strip_suffix(&[0]) == Some(name.as_bytes()) {
+            return Some(d);
+        }
+        notes = notes.get(next..).unwrap_or_default();
+    }
+    None
+}
+
+/// Returns the number of bits of the size of the enclave in the `BITS` note of the shim
+fn note_bits() -> Option<u8> {
+    let base = shim_address();
+    // SAFETY: the ELF and program headers of the shim are loaded at the start of the enclave
+    unsafe {
+        let header = &*(base as *const Header);
+        let phdrs = core::slice::from_raw_parts(
+            (base + header.e_phoff as usize) as *const ProgramHeader,
+            header.e_phnum.into(),
+        );
+        debug_assert_eq!(usize::from(header.e_phentsize), size_of::<ProgramHeader>());
+        phdrs
+            .iter()
+            .filter(|phdr| phdr.p_type == PT_NOTE)
+            .find_map(|phdr| {
+                let notes = core::slice::from_raw_parts(
+                    (base + phdr.p_vaddr as usize) as *const u8,
+                    phdr.p_memsz as usize,
+                );
+                match find_note(notes, note::NAME, note::sgx::BITS)? {
+                    [bits] => Some(*bits),
+                    _ => None,
+                }
+            })
+    }
+}
+
+/// Returns the size of the enclave
+pub fn encl_size() -> usize {
+    static SIZE: AtomicUsize = AtomicUsize::new(0);
+
+    match SIZE.load(Ordering::Relaxed) {
+        0 => {
+            let bits = match note_bits() {
+                Some(bits) if (ENCL_SIZE_BITS..=MAX_ENCL_SIZE_BITS).contains(&bits) => bits,
+                _ => ENCL_SIZE_BITS,
+            };
+            let size = 1 << bits;
+            SIZE.store(size, Ordering::Relaxed);
+            size
+        }
+        size => size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_note(name: &str, kind: u32, desc: &[u8]) -> Vec<u8> {
+        let mut note = vec![];
+        note.extend((name.len() as u32 + 1).to_ne_bytes());
+        note.extend((desc.len() as u32).to_ne_bytes());
+        note.extend(kind.to_ne_bytes());
+        note.extend(name.as_bytes());
+        note.push(0);
+        note.resize((note.len() + 3) & !3, 0);
+        note.extend(desc);
+        note.resize((note.len() + 3) & !3, 0);
+        note
+    }
+
+    #[test]
+    fn notes() {
+        let mut notes = make_note("other", note::sgx::BITS, &[1]);
+        notes.extend(make_note(note::NAME, note::sgx::SSAP, &[1]));
+        notes.extend(make_note(note::NAME, note::sgx::BITS, &[36]));
+
+        assert_eq!(
+            find_note(&notes, note::NAME, note::sgx::BITS),
+            Some(&[36][..])
+        );
+        assert_eq!(find_note(&notes, note::NAME, note::sgx::MAX_BITS), None);
+
+        // A truncated note is not found
+        assert_eq!(
+            find_note(&notes[..notes.len() - 4], note::NAME, note::sgx::BITS),
+            None
+        );
+    }
+}
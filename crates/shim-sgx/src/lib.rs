@@ -17,6 +17,7 @@
 pub mod entry;
 pub mod handler;
 pub mod heap;
+pub mod layout;
 pub mod thread;
 
 use primordial::Page;
@@ -37,10 +38,15 @@ pub const CSSA_0_STACK_SIZE: usize = 0x800000 - Page::SIZE; // 8MB - TCB
 pub const CSSA_1_PLUS_STACK_SIZE: usize =
     0x800000 - Page::SIZE - NUM_SSA * core::mem::size_of::<StateSaveArea>(); // 8MB - TCS - SSA
 
-/// FIXME: doc
+/// The default and minimum number of bits of the enclave size
 pub const ENCL_SIZE_BITS: u8 = 32;
-/// FIXME: doc
-pub const ENCL_SIZE: usize = 1 << ENCL_SIZE_BITS;
+
+/// The maximum number of bits of the enclave size
+///
+/// The backend may grow the enclave up to this size for the memory limits of the Keep.
+pub const MAX_ENCL_SIZE_BITS: u8 = 40;
+
+pub use layout::encl_size;
 
 const XFRM: Xfrm = Xfrm::from_bits_truncate(
     Xfrm::X87.bits()
@@ -55,13 +61,9 @@ const XFRM: Xfrm = Xfrm::from_bits_truncate(
 pub const ATTR: Attributes = Attributes::new(Features::MODE64BIT, XFRM);
 
 /// Default miscelaneous SSA data selector
-pub const MISC: MiscSelect = {
-    if cfg!(dbg) {
-        MiscSelect::EXINFO
-    } else {
-        MiscSelect::empty()
-    }
-};
+///
+/// The page fault address is required to restore discarded pages.
+pub const MISC: MiscSelect = MiscSelect::EXINFO;
 
 /// The size of the sallyport block
 pub const BLOCK_SIZE: usize = 69632;
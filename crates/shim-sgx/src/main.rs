@@ -20,18 +20,24 @@ use core::mem::MaybeUninit;
 use core::ptr::NonNull;
 
 use enarx_shim_sgx::thread::{
-    LoadRegsExt, NewThread, NewThreadFromRegisters, Tcb, NEW_THREAD_QUEUE, THREADS_FREE,
+    park, LoadRegsExt, NewThread, NewThreadFromRegisters, Tcb, HOST_STATE_OFFSET, NEW_THREAD_QUEUE,
 };
 use enarx_shim_sgx::{
-    entry, handler, shim_address, ATTR, BLOCK_SIZE, CSSA_0_STACK_SIZE, ENARX_EXEC_START,
-    ENARX_SHIM_ADDRESS, ENCL_SIZE, ENCL_SIZE_BITS, MISC,
+    encl_size, entry, handler, shim_address, ATTR, BLOCK_SIZE, CSSA_0_STACK_SIZE, ENARX_EXEC_START,
+    ENARX_SHIM_ADDRESS, ENCL_SIZE_BITS, MAX_ENCL_SIZE_BITS, MISC,
 };
 
 #[panic_handler]
 #[cfg(not(test))]
-#[allow(clippy::empty_loop)]
-fn panic(_info: &core::panic::PanicInfo<'_>) -> ! {
-    loop {}
+fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
+    let (file, line) = match info.location() {
+        Some(location) => (
+            fnv1a(location.file()),
+            u64::from(location.line()) << 32 | u64::from(location.column()),
+        ),
+        None => (0, 0),
+    };
+    unsafe { abort(file, line) }
 }
 
 // ============== REAL CODE HERE ===============
@@ -48,6 +54,7 @@ noted! {
     static NOTE_BLOCK_SIZE<note::NAME, note::BLOCK_SIZE, u64> = BLOCK_SIZE as u64;
 
     static NOTE_BITS<note::NAME, note::sgx::BITS, u8> = ENCL_SIZE_BITS;
+    static NOTE_MAX_BITS<note::NAME, note::sgx::MAX_BITS, u8> = MAX_ENCL_SIZE_BITS;
     static NOTE_SSAP<note::NAME, note::sgx::SSAP, u8> = 1;
 
     static NOTE_PID<note::NAME, note::sgx::PID, u16> = 0;
@@ -126,6 +133,55 @@ extern "sysv64" fn clearp() {
     }
 }
 
+/// Returns the FNV-1a hash of the source file `file`
+fn fnv1a(file: &str) -> u64 {
+    file.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Exit the enclave for good after a panic
+///
+/// The host state saved at the last EENTER of the thread is restored and the host is passed
+/// [`sallyport::SGX_PANIC`] with the location of the panic, `file` and `line`, instead of
+/// spinning inside the enclave.
+///
+/// # Safety
+///
+/// Must only be called by the panic handler. It never returns to the enclave.
+#[naked]
+unsafe extern "sysv64" fn abort(file: u64, line: u64) -> ! {
+    asm!(
+        "mov    r12,    rdi                 ",  // r12 = hash of the file
+        "mov    r13,    rsi                 ",  // r13 = line and column
+        "call   {CLEARX}                    ",  // Clear CPU state
+        "call   {CLEARP}                    ",  // Clear parameter registers
+        "mov    r8,     {PANIC}             ",  // r8 = panic exit status
+        "mov    r9,     r12                 ",  // r9 = hash of the file
+        "mov    r10,    r13                 ",  // r10 = line and column
+        "xor    r12,    r12                 ",  // Clear the callee-saved registers
+        "xor    r13,    r13                 ",
+        "xor    r14,    r14                 ",
+        "xor    r15,    r15                 ",
+
+        // Restore the host state
+        "mov    rsp,    gs:[{HOST}]         ",
+        "mov    rbp,    gs:[{HOST} + 8]     ",
+        "mov    rbx,    gs:[{HOST} + 16]    ",
+
+        // Exit
+        "mov    rax,    {EEXIT}             ",  // rax = EEXIT
+        "enclu                              ",  // Exit enclave
+
+        CLEARX = sym clearx,
+        CLEARP = sym clearp,
+        PANIC = const sallyport::SGX_PANIC,
+        HOST = const HOST_STATE_OFFSET,
+        EEXIT = const sgx::enclu::EEXIT,
+        options(noreturn)
+    )
+}
+
 /// Perform relocation
 ///
 /// # Safety
@@ -227,6 +283,12 @@ pub unsafe extern "sysv64" fn _start() -> ! {
         "4:                                 ",
         "and    r10,    ~0xf                ",  // Align the stack
         "xchg   rsp,    r10                 ",  // Swap r10 and rsp
+
+        // Save the host state for a panic, the GS base points to the TCB page
+        "mov    gs:[{HOST}],        r10     ",  // Host rsp
+        "mov    gs:[{HOST} + 8],    rbp     ",  // Host rbp
+        "mov    gs:[{HOST} + 16],   rbx     ",  // Host exit address
+
         "sub    rsp,    8                   ",  // Align the stack
         "push   r10                         ",  // Store old stack
 
@@ -242,6 +304,7 @@ pub unsafe extern "sysv64" fn _start() -> ! {
         "sub    rcx,    4096                ",  // rcx = TCB
         "call   {CLEARX}                    ",  // Clear CPU state
         "call   {ENTRY}                     ",  // Jump to Rust
+        "mov    eax,    eax                 ",  // Zero-extend the return value
         "push   rax                         ",  // Save return value
         "call   {CLEARX}                    ",  // Clear CPU state
         "call   {CLEARP}                    ",  // Clear parameter registers
@@ -264,6 +327,7 @@ pub unsafe extern "sysv64" fn _start() -> ! {
         ENTRY = sym main,
         EEXIT = const sgx::enclu::EEXIT,
         CSSA_0_STK_TCS_SZ = const CSSA_0_STACK_SIZE + Page::SIZE,
+        HOST = const HOST_STATE_OFFSET,
         options(noreturn)
     )
 }
@@ -283,7 +347,7 @@ unsafe extern "C" fn main(
     let block_start = block.as_ptr() as usize;
     let block_end = block_start + BLOCK_SIZE;
     let shim_start = shim_address();
-    let shim_end = shim_start + ENCL_SIZE;
+    let shim_end = shim_start + encl_size();
 
     if (block_start >= shim_start && block_start < shim_end)
         || (block_end > shim_start && block_end <= shim_end)
@@ -301,7 +365,10 @@ unsafe extern "C" fn main(
                 tcb.assume_init_mut()
             };
 
-            let thread = { NEW_THREAD_QUEUE.write().pop().unwrap() };
+            // The TCS page directly follows the TCB page
+            let tcs = tcb as *const _ as usize + Page::SIZE;
+
+            let thread = { NEW_THREAD_QUEUE.write().pop_for(tcs).unwrap() };
 
             match thread {
                 NewThread::Main => {
@@ -324,8 +391,8 @@ unsafe extern "C" fn main(
                     ret = regs.load_registers(tcb);
                 }
             }
-            // increment the free counter, although it's not yet completely done
-            *THREADS_FREE.write() += 1;
+            // park the thread, although it's not yet completely done
+            park(tcs, ssas);
         }
         1 => {
             // cssa == 0 already initialized the TCB
@@ -13,48 +13,60 @@ use sallyport::libc::pid_t;
 use sgx::ssa::{GenPurposeRegs, StateSaveArea};
 use spinning::{Lazy, RwLock};
 
-/// A constant array to enqueue and dequeue from.
+/// A new thread waiting for a TCS to run on
+#[derive(Clone, Copy, Debug)]
+pub struct QueuedThread<T> {
+    /// The new thread
+    pub thread: T,
+    /// The address of the TCS the thread should preferably run on
+    pub affinity: Option<usize>,
+}
+
+/// A constant array of new threads ordered by affinity and arrival.
 #[derive(Clone, Debug)]
-pub struct ConstVecDequeue<const N: usize, T: Sized + Copy> {
-    records: [Option<T>; N],
-    start: usize,
-    count: usize,
+pub struct ThreadQueue<const N: usize, T: Sized + Copy> {
+    records: [Option<(u64, QueuedThread<T>)>; N],
+    seq: u64,
 }
 
-impl<const N: usize, T: Sized + Copy> Default for ConstVecDequeue<N, T> {
+impl<const N: usize, T: Sized + Copy> Default for ThreadQueue<N, T> {
     fn default() -> Self {
         Self {
             records: [None; N],
-            start: 0,
-            count: 0,
+            seq: 0,
         }
     }
 }
 
-impl<const N: usize, T: Sized + Copy> ConstVecDequeue<N, T> {
-    /// Push a record to the end of the queue.
-    pub fn push(&mut self, val: T) -> Result<(), T> {
-        if self.count >= N {
-            return Err(val); // full
+impl<const N: usize, T: Sized + Copy> ThreadQueue<N, T> {
+    /// Push a thread to the queue.
+    pub fn push(&mut self, val: QueuedThread<T>) -> Result<(), QueuedThread<T>> {
+        match self.records.iter_mut().find(|r| r.is_none()) {
+            None => Err(val), // full
+            Some(record) => {
+                record.replace((self.seq, val));
+                self.seq += 1;
+                Ok(())
+            }
         }
-
-        let end = (self.start + self.count) % N;
-        self.records[end].replace(val);
-        self.count += 1;
-        Ok(())
     }
 
-    /// Pop a record from the front of the queue.
-    pub fn pop(&mut self) -> Option<T> {
-        if self.count == 0 {
-            return None;
-        }
-
-        let record = self.records[self.start].take();
-        self.start = (self.start + 1) % N;
-        self.count -= 1;
+    /// Whether no more threads can be pushed to the queue.
+    pub fn is_full(&self) -> bool {
+        self.records.iter().all(Option::is_some)
+    }
 
-        record
+    /// Pop the next thread to run on the TCS at address `tcs`.
+    ///
+    /// Threads with an affinity for `tcs` are preferred, otherwise the threads are
+    /// picked in the order they were pushed.
+    pub fn pop_for(&mut self, tcs: usize) -> Option<T> {
+        let (_, record) = self
+            .records
+            .iter_mut()
+            .filter_map(|r| r.map(|(seq, val)| ((val.affinity == Some(tcs), !seq), r)))
+            .max_by_key(|(key, _)| *key)?;
+        record.take().map(|(_, val)| val.thread)
     }
 }
 
@@ -79,9 +91,14 @@ pub enum NewThread {
 }
 
 /// Queue of new threads to be picked up
-pub static NEW_THREAD_QUEUE: Lazy<RwLock<ConstVecDequeue<10, NewThread>>> = Lazy::new(|| {
-    let mut queue = ConstVecDequeue::default();
-    queue.push(NewThread::Main).unwrap();
+pub static NEW_THREAD_QUEUE: Lazy<RwLock<ThreadQueue<10, NewThread>>> = Lazy::new(|| {
+    let mut queue = ThreadQueue::default();
+    queue
+        .push(QueuedThread {
+            thread: NewThread::Main,
+            affinity: None,
+        })
+        .unwrap();
     RwLock::new(queue)
 });
 
@@ -101,7 +118,13 @@ pub struct Tcs {
     pub nssa: u32,
     /// Offset in enclave to which control is transferred on EENTER relative to the base of the enclave.
     pub oentry: u64,
-    _reserved2: [u8; 4096 - 5 * 8],
+    /// Asynchronous Exit Pointer, saved by EENTER.
+    pub aep: u64,
+    /// Offset of the FS base, relative to the enclave base. Must be page aligned.
+    pub ofsbasgx: u64,
+    /// Offset of the GS base, relative to the enclave base. Must be page aligned.
+    pub ogsbasgx: u64,
+    _reserved2: [u8; 4096 - 8 * 8],
 }
 
 impl Default for Tcs {
@@ -112,7 +135,10 @@ impl Default for Tcs {
             cssa: 0,
             nssa: 0,
             oentry: 0,
-            _reserved2: [0; 4096 - 5 * 8],
+            aep: 0,
+            ofsbasgx: 0,
+            ogsbasgx: 0,
+            _reserved2: [0; 4096 - 8 * 8],
         }
     }
 }
@@ -151,6 +177,26 @@ pub struct ReturnToMain {
     pub rsp: u64,
 }
 
+/// State of the host at the last EENTER of a thread
+///
+/// It is stored at [`HOST_STATE_OFFSET`] of the TCB page, which the GS base of the shim points
+/// to, so a panicking shim can exit the enclave from anywhere.
+#[derive(Default)]
+#[repr(C)]
+pub struct HostState {
+    /// rsp
+    pub rsp: u64,
+    /// rbp
+    pub rbp: u64,
+    /// rbx, the exit address
+    pub rbx: u64,
+}
+
+/// Offset of the [`HostState`] in the TCB page
+pub const HOST_STATE_OFFSET: usize = Page::SIZE - core::mem::size_of::<HostState>();
+
+const _: () = assert!(core::mem::size_of::<Tcb>() <= HOST_STATE_OFFSET);
+
 /// Thread Control Block
 #[derive(Default)]
 pub struct Tcb {
@@ -167,8 +213,88 @@ pub struct Tcb {
 /// actual thread ID to be used for the next thread
 pub static THREAD_ID_CNT: AtomicI32 = AtomicI32::new(1);
 
-/// number of free threads
-pub static THREADS_FREE: RwLock<usize> = RwLock::new(0);
+/// Number of TCS addresses of parked threads remembered for reuse affinity
+const PARKED_HINTS: usize = 16;
+
+/// Parked threads
+///
+/// A thread is parked after it exited. Its TCS returns to the pool of the host,
+/// while the thread memory stays mapped and accepted, so that it can be reused
+/// by a new thread without allocating and accepting fresh pages.
+#[derive(Debug)]
+pub struct ParkedThreads {
+    count: usize,
+    hints: [usize; PARKED_HINTS],
+    start: usize,
+    len: usize,
+}
+
+impl ParkedThreads {
+    /// Create an empty set of parked threads.
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            hints: [0; PARKED_HINTS],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// Park the thread running on the TCS at address `tcs`.
+    pub fn park(&mut self, tcs: usize) {
+        self.count += 1;
+        if self.len == PARKED_HINTS {
+            // forget the oldest hint
+            self.start = (self.start + 1) % PARKED_HINTS;
+            self.len -= 1;
+        }
+        self.hints[(self.start + self.len) % PARKED_HINTS] = tcs;
+        self.len += 1;
+    }
+
+    /// Take a parked thread for reuse.
+    ///
+    /// Returns `None`, if no thread is parked, otherwise the TCS address of
+    /// the most recently parked thread, if still known. The host reuses the most
+    /// recently returned TCS first, so this is the TCS the new thread will most
+    /// likely run on.
+    pub fn take(&mut self) -> Option<Option<usize>> {
+        if self.count == 0 {
+            return None;
+        }
+        self.count -= 1;
+
+        if self.len == 0 {
+            return Some(None);
+        }
+        self.len -= 1;
+        Some(Some(self.hints[(self.start + self.len) % PARKED_HINTS]))
+    }
+}
+
+impl Default for ParkedThreads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The parked threads
+pub static PARKED_THREADS: RwLock<ParkedThreads> = RwLock::new(ParkedThreads::new());
+
+/// Park the current thread
+///
+/// Clears the register state of the exited thread from the state save areas,
+/// but keeps the stacks and the TCS warm for the next thread.
+///
+/// # Safety
+///
+/// Must only be called on the CSSA 0 stack after the thread has exited.
+pub unsafe fn park(tcs: usize, ssas: &mut [StateSaveArea]) {
+    for ssa in ssas.iter_mut() {
+        core::ptr::write_bytes(&mut ssa.gpr as *mut GenPurposeRegs, 0, 1);
+    }
+    PARKED_THREADS.write().park(tcs);
+}
 
 /// Extend some trait with a method to load registers
 pub trait LoadRegsExt {
@@ -242,25 +368,63 @@ impl LoadRegsExt for GenPurposeRegs {
 
 #[cfg(test)]
 mod test {
-    use super::{ConstVecDequeue, Tcb};
+    use super::{ParkedThreads, QueuedThread, Tcb, ThreadQueue, PARKED_HINTS};
     use core::mem::size_of;
     use primordial::Page;
 
+    fn queued(thread: u32, affinity: Option<usize>) -> QueuedThread<u32> {
+        QueuedThread { thread, affinity }
+    }
+
     #[test]
-    fn test_const_vec_dequeue() {
-        let mut queue = ConstVecDequeue::<3, u32>::default();
+    fn test_thread_queue() {
+        let mut queue = ThreadQueue::<3, u32>::default();
 
-        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.pop_for(0), None);
 
-        queue.push(1).unwrap();
-        queue.push(2).unwrap();
-        queue.push(3).unwrap();
-        assert_eq!(queue.push(4).unwrap_err(), 4);
+        queue.push(queued(1, None)).unwrap();
+        queue.push(queued(2, None)).unwrap();
+        assert!(!queue.is_full());
+        queue.push(queued(3, None)).unwrap();
+        assert!(queue.is_full());
+        assert_eq!(queue.push(queued(4, None)).unwrap_err().thread, 4);
+
+        assert_eq!(queue.pop_for(0), Some(1));
+        assert!(!queue.is_full());
+        assert_eq!(queue.pop_for(0), Some(2));
+        assert_eq!(queue.pop_for(0), Some(3));
+        assert_eq!(queue.pop_for(0), None);
+    }
 
-        assert_eq!(queue.pop(), Some(1));
-        assert_eq!(queue.pop(), Some(2));
-        assert_eq!(queue.pop(), Some(3));
-        assert_eq!(queue.pop(), None);
+    #[test]
+    fn test_thread_queue_affinity() {
+        let mut queue = ThreadQueue::<4, u32>::default();
+
+        queue.push(queued(1, None)).unwrap();
+        queue.push(queued(2, Some(0x2000))).unwrap();
+        queue.push(queued(3, None)).unwrap();
+        queue.push(queued(4, Some(0x1000))).unwrap();
+
+        assert_eq!(queue.pop_for(0x1000), Some(4));
+        assert_eq!(queue.pop_for(0x1000), Some(1));
+        assert_eq!(queue.pop_for(0x2000), Some(2));
+        assert_eq!(queue.pop_for(0x1000), Some(3));
+        assert_eq!(queue.pop_for(0x1000), None);
+    }
+
+    #[test]
+    fn test_parked_threads() {
+        let mut parked = ParkedThreads::new();
+        assert_eq!(parked.take(), None);
+
+        for tcs in 0..PARKED_HINTS + 1 {
+            parked.park(tcs);
+        }
+        for tcs in (1..PARKED_HINTS + 1).rev() {
+            assert_eq!(parked.take(), Some(Some(tcs)));
+        }
+        assert_eq!(parked.take(), Some(None));
+        assert_eq!(parked.take(), None);
     }
 
     #[test]
@@ -23,7 +23,7 @@ struct Segment<'a> {
 pub struct Binary<'a>(&'a [u8], Elf<'a>);
 
 impl<'a> Binary<'a> {
-    fn new(bytes: &'a [u8]) -> Result<Self> {
+    pub(super) fn new(bytes: &'a [u8]) -> Result<Self> {
         let elf = Elf::parse(bytes)?;
 
         if elf.header.e_ident[EI_CLASS] != ELFCLASS64 {
@@ -116,6 +116,15 @@ impl<'a> Binary<'a> {
             .map(|n| n.desc)
     }
 
+    /// Returns the virtual address of the descriptor of a note, if it is loaded
+    pub fn note_vaddr(&self, name: &'a str, kind: u32) -> Option<usize> {
+        let desc = self.notes(name, kind).next()?;
+        let offset = desc.as_ptr() as usize - self.0.as_ptr() as usize;
+        self.headers(PT_LOAD)
+            .find(|phdr| phdr.file_range().contains(&offset))
+            .map(|phdr| phdr.p_vaddr as usize + offset - phdr.p_offset as usize)
+    }
+
     /// Read a note from the note section
     ///
     /// # Safety
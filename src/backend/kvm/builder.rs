@@ -122,6 +122,8 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
             cpu_fds: vec![vcpu_fd],
             regions: builder.regions,
             sallyport_block_size: builder.config.sallyport_block_size,
+            bounce_size: builder.config.bounce_size,
+            time_page: false,
             sallyports: builder.sallyports,
             personality: KvmKeepPersonality(()),
         })))
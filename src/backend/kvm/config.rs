@@ -7,6 +7,7 @@ use sallyport::elf::{self, pf::kvm::SALLYPORT};
 
 pub struct Config {
     pub sallyport_block_size: usize,
+    pub bounce_size: usize,
     pub signatures: Option<Signatures>,
 }
 
@@ -33,8 +34,14 @@ impl super::super::Config for Config {
             unsafe { shim.note::<u64>(elf::note::NAME, elf::note::BLOCK_SIZE) }
                 .ok_or_else(|| anyhow!("KVM shim is missing BLOCK_SIZE"))? as usize;
 
+        // Shims without BOUNCE_SIZE do not support bounce buffer transfers.
+        // Safety: converting 8 bytes into u64 should not produce any unsound behavior.
+        let bounce_size = unsafe { shim.note::<u64>(elf::note::NAME, elf::note::BOUNCE_SIZE) }
+            .unwrap_or(0) as usize;
+
         Ok(Self {
             sallyport_block_size,
+            bounce_size,
             signatures,
         })
     }
@@ -1,5 +1,6 @@
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::backend::probe::linux::dev_access;
 use crate::backend::{probe::x86_64::CpuId, Datum};
 
 use kvm_ioctls::Kvm;
@@ -11,10 +12,17 @@ pub fn dev_kvm() -> Datum {
         name: "Driver".into(),
         pass: dev_kvm.exists(),
         info: Some("/dev/kvm".into()),
-        mesg: None,
+        mesg: (!dev_kvm.exists()).then(|| {
+            "Enable virtualization in the BIOS and load the `kvm_intel` or `kvm_amd` kernel module."
+                .into()
+        }),
     }
 }
 
+pub fn dev_kvm_access() -> Datum {
+    dev_access("/dev/kvm")
+}
+
 pub fn kvm_version() -> Datum {
     let version = Kvm::new().map(|kvm| kvm.get_api_version());
     let (pass, info) = match version {
@@ -19,7 +19,6 @@ impl Region {
         }
     }
 
-    #[allow(dead_code)]
     pub fn as_guest(&self) -> Span<PhysAddr, u64> {
         Span {
             start: PhysAddr::new(self.kvm_region.guest_phys_addr),
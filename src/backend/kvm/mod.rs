@@ -3,7 +3,7 @@
 pub use kvm_bindings::kvm_userspace_memory_region as KvmUserspaceMemoryRegion;
 
 use super::Loader;
-use data::{dev_kvm, kvm_version, CPUIDS};
+use data::{dev_kvm, dev_kvm_access, kvm_version, CPUIDS};
 use mem::Region;
 
 use std::sync::Arc;
@@ -47,12 +47,30 @@ pub struct Keep<P: KeepPersonality> {
     pub vm_fd: VmFd,
     pub cpu_fds: Vec<VcpuFd>,
     pub sallyport_block_size: usize,
+    /// The maximum size of a bounce buffer transfer or 0, if unsupported by the shim
+    pub bounce_size: usize,
+    /// Whether the shim registered a timestamp page
+    pub time_page: bool,
     pub sallyports: Vec<Option<VirtAddr>>,
     pub regions: Vec<Region>,
     pub personality: P,
 }
 
 impl<P: KeepPersonality> Keep<P> {
+    /// Returns the host address of `count` bytes of guest memory at the guest physical address
+    /// `addr`, which have to be contained in a single memory region
+    pub fn guest_virt(&self, addr: usize, count: usize) -> sallyport::Result<VirtAddr> {
+        let end = addr.checked_add(count).ok_or(libc::EFAULT)? as u64;
+        self.regions
+            .iter()
+            .find_map(|region| {
+                let guest = region.as_guest();
+                let offset = (addr as u64).checked_sub(guest.start.as_u64())?;
+                (end <= guest.start.as_u64() + guest.count).then(|| region.as_virt().start + offset)
+            })
+            .ok_or(libc::EFAULT)
+    }
+
     pub fn map(&mut self, pages: Map<perms::ReadWrite>, to: usize) -> std::io::Result<&mut Region> {
         let kvm_region = kvm_userspace_memory_region {
             slot: self.regions.len() as u32,
@@ -102,6 +120,10 @@ impl crate::backend::Backend for Backend {
         vec![]
     }
 
+    fn doctor(&self) -> Vec<super::Datum> {
+        vec![dev_kvm_access()]
+    }
+
     #[inline]
     fn keep(
         &self,
@@ -112,6 +134,15 @@ impl crate::backend::Backend for Backend {
         builder::Builder::load(shim, exec, signatures)
     }
 
+    /// The KVM shim does not support threads yet
+    #[inline]
+    fn capabilities(&self, _shim: &[u8]) -> Result<super::Capabilities> {
+        Ok(super::Capabilities {
+            threads: Some(0),
+            memory_size: None,
+        })
+    }
+
     #[inline]
     fn hash(&self, _shim: &[u8], _exec: &[u8]) -> Result<Vec<u8>> {
         Ok(Vec::new())
@@ -7,20 +7,40 @@ use crate::backend::execute_gdb;
 
 use std::io;
 use std::iter;
-use std::mem::size_of;
+use std::mem::{align_of, size_of};
 use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
 use kvm_ioctls::{VcpuExit, VcpuFd};
 use mmarinus::{perms, Map};
+use sallyport::item::enarxcall::time::{TimePage, Timestamps, UPDATE_INTERVAL};
 use sallyport::item::enarxcall::Payload;
 use sallyport::item::{Block, Item};
-use sallyport::{item, KVM_SYSCALL_TRIGGER_PORT};
+use sallyport::{
+    item, KVM_SYSCALL_COMPLETION_VECTOR, KVM_SYSCALL_DOORBELL_PORT, KVM_SYSCALL_TRIGGER_PORT,
+};
+use tracing::error;
+
+/// Returns the time of the host clock `clockid` in nanoseconds
+fn now(clockid: libc::clockid_t) -> u64 {
+    let mut tp = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Safety: `tp` is a valid timespec struct
+    unsafe { libc::clock_gettime(clockid, &mut tp) };
+    tp.tv_sec as u64 * 1_000_000_000 + tp.tv_nsec as u64
+}
 
 pub struct Thread<P: KeepPersonality> {
     keep: Arc<RwLock<super::Keep<P>>>,
     vcpu_fd: Option<VcpuFd>,
 
+    /// Block posted to the doorbell, which is executed when the vCPU halts
+    posted: Option<usize>,
+
     #[cfg(feature = "gdb")]
     gdb_fd: Option<std::net::TcpStream>,
 }
@@ -40,6 +60,7 @@ impl<P: KeepPersonality + 'static> super::super::Keep for RwLock<super::Keep<P>>
             Some(vcpu_fd) => Ok(Some(Box::new(Thread {
                 keep: self,
                 vcpu_fd: Some(vcpu_fd),
+                posted: None,
 
                 #[cfg(feature = "gdb")]
                 gdb_fd: None,
@@ -88,6 +109,74 @@ impl<P: KeepPersonality> Thread<P> {
         Ok(keep.kvm_fd.get_nr_memslots() - keep.regions.len())
     }
 
+    /// Reads from or writes to `fd` with at most `count` bytes of guest memory at `addr`
+    pub fn bounce(
+        &self,
+        write: bool,
+        fd: usize,
+        addr: usize,
+        count: usize,
+    ) -> sallyport::Result<usize> {
+        let keep = self.keep.read().unwrap();
+
+        if keep.bounce_size == 0 {
+            return Err(libc::ENOSYS);
+        }
+        if count > keep.bounce_size {
+            return Err(libc::EINVAL);
+        }
+        let virt = keep.guest_virt(addr, count)?;
+
+        let ret = unsafe {
+            if write {
+                libc::write(fd as _, virt.as_ptr(), count)
+            } else {
+                libc::read(fd as _, virt.as_mut_ptr(), count)
+            }
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or(libc::EIO));
+        }
+        Ok(ret as _)
+    }
+
+    /// Registers the [`TimePage`] at the guest physical address `addr` and spawns a thread
+    /// updating it every [`UPDATE_INTERVAL`], until the Keep is dropped
+    pub fn register_time_page(&self, addr: usize) -> sallyport::Result<()> {
+        if addr % align_of::<TimePage>() != 0 {
+            return Err(libc::EINVAL);
+        }
+
+        let mut keep = self.keep.write().unwrap();
+        if keep.time_page {
+            return Err(libc::EBUSY);
+        }
+        let virt = keep.guest_virt(addr, size_of::<TimePage>())?;
+        keep.time_page = true;
+
+        let weak = Arc::downgrade(&self.keep);
+        let virt = virt.as_u64() as usize;
+        thread::Builder::new()
+            .name("time-page".into())
+            .spawn(move || {
+                // The memory regions of the guest are mapped as long as the Keep is alive
+                while let Some(keep) = weak.upgrade() {
+                    // Safety: the page is mapped, aligned and only accessed atomically
+                    let page = unsafe { &*(virt as *const TimePage) };
+                    page.write(Timestamps {
+                        realtime: now(libc::CLOCK_REALTIME),
+                        monotonic: now(libc::CLOCK_MONOTONIC),
+                    });
+                    drop(keep);
+                    thread::sleep(Duration::from_nanos(UPDATE_INTERVAL));
+                }
+            })
+            .map_err(|e| e.raw_os_error().unwrap_or(libc::EAGAIN))?;
+        Ok(())
+    }
+
     fn kvm_enarxcall<'a>(
         &mut self,
         enarxcall: &'a mut Payload,
@@ -118,96 +207,174 @@ impl<P: KeepPersonality> Thread<P> {
                 Ok(None)
             }
 
+            item::Enarxcall {
+                num:
+                    num @ (item::enarxcall::Number::BounceRead | item::enarxcall::Number::BounceWrite),
+                argv: [fd, addr, count, ..],
+                ret,
+            } => {
+                let write = *num == item::enarxcall::Number::BounceWrite;
+                *ret = match self.bounce(write, *fd, *addr, *count) {
+                    Ok(n) => n,
+                    Err(e) => -e as usize,
+                };
+                Ok(None)
+            }
+
+            item::Enarxcall {
+                num: item::enarxcall::Number::RegisterTimePage,
+                argv: [addr, ..],
+                ret,
+            } => {
+                *ret = match self.register_time_page(*addr) {
+                    Ok(()) => 0,
+                    Err(e) => -e as usize,
+                };
+                Ok(None)
+            }
+
             _ => return Ok(Some(Item::Enarxcall(enarxcall, data))),
         }
     }
-}
 
-impl<P: KeepPersonality> super::super::Thread for Thread<P> {
-    fn enter(&mut self, _gdblisten: &Option<String>) -> Result<Command> {
-        let vcpu_fd = self.vcpu_fd.as_mut().unwrap();
-        match vcpu_fd.run()? {
-            VcpuExit::IoOut(KVM_SYSCALL_TRIGGER_PORT, data) => {
-                debug_assert_eq!(data.len(), 2);
-                let block_nr = data[0] as usize + ((data[1] as usize) << 8);
-                let block_virt = self.keep.write().unwrap().sallyports[block_nr]
-                    .take()
-                    .unwrap();
-
-                // If some other thread tried to use the same block, the above unwrap would have panicked.
-                let block: Block = unsafe {
-                    std::slice::from_raw_parts_mut(
-                        block_virt.as_mut_ptr::<usize>(),
-                        self.keep.read().unwrap().sallyport_block_size / size_of::<usize>(),
-                    )
-                }
+    /// Executes the items of the sallyport block `block_nr`
+    fn execute(&mut self, block_nr: usize, _gdblisten: &Option<String>) -> Result<Command> {
+        let block_virt = self.keep.write().unwrap().sallyports[block_nr]
+            .take()
+            .unwrap();
+
+        let block_len = self.keep.read().unwrap().sallyport_block_size / size_of::<usize>();
+
+        // If some other thread tried to use the same block, the above unwrap would have panicked.
+        let block: Block =
+            unsafe { std::slice::from_raw_parts_mut(block_virt.as_mut_ptr::<usize>(), block_len) }
                 .into();
 
-                for item in block {
-                    match item {
-                        Item::Gdbcall(_gdbcall, _data) => {
-                            #[cfg(feature = "gdb")]
-                            unsafe {
-                                execute_gdb(
-                                    _gdbcall,
-                                    _data,
-                                    &mut self.gdb_fd,
-                                    _gdblisten.as_ref().unwrap(),
-                                )
-                                .map_err(io::Error::from_raw_os_error)
-                                .context("execute_gdb")?;
-                            }
-                        }
+        let mut exit = None;
+        for item in block {
+            match item {
+                Item::Gdbcall(_gdbcall, _data) => {
+                    #[cfg(feature = "gdb")]
+                    unsafe {
+                        execute_gdb(
+                            _gdbcall,
+                            _data,
+                            &mut self.gdb_fd,
+                            _gdblisten.as_ref().unwrap(),
+                        )
+                        .map_err(io::Error::from_raw_os_error)
+                        .context("execute_gdb")?;
+                    }
+                }
 
-                        Item::Enarxcall(enarxcall, data) => {
-                            if let Some(Item::Enarxcall(enarxcall, data)) =
-                                self.kvm_enarxcall(enarxcall, data)?
-                            {
-                                let mut keep = self.keep.write().unwrap();
-                                sallyport::host::execute(
-                                    keep.personality.enarxcall(enarxcall, data)?.into_iter(),
-                                )
-                                .map_err(io::Error::from_raw_os_error)
-                                .context("sallyport::host::execute")?;
-                            }
-                        }
+                Item::Enarxcall(enarxcall, data) => {
+                    if let Some(Item::Enarxcall(enarxcall, data)) =
+                        self.kvm_enarxcall(enarxcall, data)?
+                    {
+                        let mut keep = self.keep.write().unwrap();
+                        sallyport::host::execute(
+                            keep.personality.enarxcall(enarxcall, data)?.into_iter(),
+                        )
+                        .map_err(io::Error::from_raw_os_error)
+                        .context("sallyport::host::execute")?;
+                    }
+                }
 
-                        // Catch exit and exit_group for a clean shutdown
-                        Item::Syscall(syscall, ..)
-                            if (syscall.num == libc::SYS_exit as usize
-                                || syscall.num == libc::SYS_exit_group as usize) =>
-                        {
-                            if cfg!(feature = "dbg") {
-                                dbg!(&syscall);
-                            }
-                            return Ok(Command::Exit(syscall.argv[0] as _));
-                        }
+                // Catch exit and exit_group for a clean shutdown
+                Item::Syscall(syscall, ..)
+                    if (syscall.num == libc::SYS_exit as usize
+                        || syscall.num == libc::SYS_exit_group as usize) =>
+                {
+                    if cfg!(feature = "dbg") {
+                        dbg!(&syscall);
+                    }
+                    exit = Some((syscall.num, syscall.argv[0]));
+                    break;
+                }
 
-                        Item::Syscall(ref _syscall, ..) => {
-                            #[cfg(feature = "dbg")]
-                            match (
-                                _syscall.num as libc::c_long,
-                                _syscall.argv[1] as libc::c_int,
-                            ) {
-                                (
-                                    libc::SYS_write | libc::SYS_read,
-                                    libc::STDIN_FILENO | libc::STDOUT_FILENO | libc::STDERR_FILENO,
-                                ) => {}
-                                _ => {
-                                    dbg!(&_syscall);
-                                }
-                            }
-
-                            sallyport::host::execute(iter::once(item))
-                                .map_err(io::Error::from_raw_os_error)
-                                .context("sallyport::host::execute")?;
+                Item::Syscall(ref _syscall, ..) => {
+                    #[cfg(feature = "dbg")]
+                    match (
+                        _syscall.num as libc::c_long,
+                        _syscall.argv[1] as libc::c_int,
+                    ) {
+                        (
+                            libc::SYS_write | libc::SYS_read,
+                            libc::STDIN_FILENO | libc::STDOUT_FILENO | libc::STDERR_FILENO,
+                        ) => {}
+                        _ => {
+                            dbg!(&_syscall);
                         }
                     }
+
+                    sallyport::host::execute(iter::once(item))
+                        .map_err(io::Error::from_raw_os_error)
+                        .context("sallyport::host::execute")?;
                 }
+            }
+        }
 
-                self.keep.write().unwrap().sallyports[block_nr].replace(block_virt);
+        if let Some((num, code)) = exit {
+            // Verify the scrubbing of the block as far as observable by the host
+            if num == libc::SYS_exit_group as usize {
+                let block = unsafe {
+                    std::slice::from_raw_parts(block_virt.as_mut_ptr::<usize>(), block_len)
+                };
+                if !sallyport::host::is_scrubbed(block) {
+                    error!("sallyport block was not scrubbed before exit_group({code})");
+                }
+            }
+            return Ok(Command::Exit(code as _));
+        }
+
+        self.keep.write().unwrap().sallyports[block_nr].replace(block_virt);
+        Ok(Command::Continue)
+    }
+
+    /// Signals the completion of the block posted to the doorbell to the shim
+    fn complete(&self) -> Result<()> {
+        let vcpu_fd = self.vcpu_fd.as_ref().unwrap();
+        let mut events = vcpu_fd
+            .get_vcpu_events()
+            .context("failed to get the vCPU events")?;
+        events.interrupt.injected = 1;
+        events.interrupt.nr = KVM_SYSCALL_COMPLETION_VECTOR;
+        events.interrupt.soft = 0;
+        vcpu_fd
+            .set_vcpu_events(&events)
+            .context("failed to inject the sallyport completion interrupt")
+    }
+}
+
+impl<P: KeepPersonality> super::super::Thread for Thread<P> {
+    fn enter(&mut self, gdblisten: &Option<String>) -> Result<Command> {
+        let vcpu_fd = self.vcpu_fd.as_mut().unwrap();
+        match vcpu_fd.run()? {
+            VcpuExit::IoOut(KVM_SYSCALL_TRIGGER_PORT, data) => {
+                debug_assert_eq!(data.len(), 2);
+                let block_nr = data[0] as usize + ((data[1] as usize) << 8);
+                self.execute(block_nr, gdblisten)
+            }
+
+            // The block is executed, when the shim halts to wait for its completion
+            VcpuExit::IoOut(KVM_SYSCALL_DOORBELL_PORT, data) => {
+                debug_assert_eq!(data.len(), 2);
+                let block_nr = data[0] as usize + ((data[1] as usize) << 8);
+                if self.posted.replace(block_nr).is_some() {
+                    bail!("sallyport block posted while another one is pending");
+                }
                 Ok(Command::Continue)
             }
+
+            VcpuExit::Hlt if self.posted.is_some() => {
+                let block_nr = self.posted.take().unwrap();
+                let command = self.execute(block_nr, gdblisten)?;
+                if let Command::Continue = command {
+                    self.complete()?;
+                }
+                Ok(command)
+            }
+
             #[cfg(debug_assertions)]
             reason => bail!(
                 "KVM error: {:?} {:#x?} {:#x?}",
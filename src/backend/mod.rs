@@ -155,6 +155,14 @@ pub trait Backend: Sync + Send {
     /// The tests that show machine configuration support for the backend
     fn config(&self) -> Vec<Datum>;
 
+    /// Further tests that diagnose common setup failures of the backend
+    ///
+    /// These do not affect [`Backend::configured`]. Failing tests explain how to fix the
+    /// problem in [`Datum::mesg`].
+    fn doctor(&self) -> Vec<Datum> {
+        vec![]
+    }
+
     /// Create a keep instance
     fn keep(
         &self,
@@ -176,6 +184,17 @@ pub trait Backend: Sync + Send {
         !self.config().iter().fold(false, |e, d| e | !d.pass)
     }
 
+    /// The capabilities of the Keeps built with `shim`, against which package configs are checked
+    /// before a Keep is built
+    fn capabilities(&self, _shim: &[u8]) -> Result<Capabilities> {
+        Ok(Capabilities::default())
+    }
+
+    /// Size the keep for a workload using up to `size` bytes of memory
+    ///
+    /// Backends with a fixed memory layout ignore it.
+    fn set_memory_size(&self, _size: u64) {}
+
     #[cfg(windows)]
     /// set wasmtime args directly
     fn set_args(&self, _args: Args) {}
@@ -205,6 +224,18 @@ pub struct Datum {
     pub mesg: Option<String>,
 }
 
+/// Features of the Keeps of a backend, which a package config may request
+///
+/// `None` means, that the backend does not limit the feature.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Maximum number of threads, which the workload may spawn
+    pub threads: Option<u32>,
+
+    /// Maximum memory size of the workload in bytes
+    pub memory_size: Option<u64>,
+}
+
 pub trait Keep {
     /// Creates a new thread in the keep.
     fn spawn(self: Arc<Self>) -> Result<Option<Box<dyn Thread>>>;
@@ -282,6 +313,22 @@ pub static BACKENDS: Lazy<Vec<Box<dyn Backend>>> = Lazy::new(|| {
     ]
 });
 
+/// Converts `err` of the TEE into an error classified as [`ErrorKind::Resource`], if `exhausted`
+/// holds for it
+///
+/// TEE resources, like EPC pages or SEV ASIDs, are shared by all Keeps of the host, so a Keep
+/// failing to launch due to their exhaustion may be launched again once other Keeps exited.
+///
+/// [`ErrorKind::Resource`]: enarx_error::ErrorKind::Resource
+#[cfg(enarx_with_shim)]
+fn resource(err: std::io::Error, exhausted: fn(&std::io::Error) -> bool) -> Error {
+    if exhausted(&err) {
+        Error::new(err).context(enarx_error::ErrorKind::Resource)
+    } else {
+        err.into()
+    }
+}
+
 #[cfg(feature = "gdb")]
 pub fn wait_for_gdb_connection(sockaddr: &str) -> std::io::Result<std::net::TcpStream> {
     use std::net::TcpListener;
@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::backend::Datum;
+
+use std::ffi::CStr;
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+
+/// Returns the major and minor version of the running kernel
+pub fn kernel_version() -> Option<(u32, u32)> {
+    let release = fs::read_to_string("/proc/sys/kernel/osrelease").ok()?;
+    let mut numbers = release
+        .trim()
+        .split(|c: char| !c.is_ascii_digit())
+        .map(str::parse);
+    Some((numbers.next()?.ok()?, numbers.next()?.ok()?))
+}
+
+/// Checks the running kernel is at least `major.minor`, suggesting an upgrade otherwise
+pub fn kernel_at_least(major: u32, minor: u32, why: &str) -> Datum {
+    let version = kernel_version();
+
+    Datum {
+        name: format!("Kernel version is at least {major}.{minor}"),
+        pass: version.map_or(false, |v| v >= (major, minor)),
+        info: version.map(|(major, minor)| format!("{major}.{minor}")),
+        mesg: match version {
+            Some(v) if v >= (major, minor) => None,
+            _ => Some(format!(
+                "{why} Upgrade the host to Linux {major}.{minor} or later."
+            )),
+        },
+    }
+}
+
+/// Returns the name of the group `gid`
+fn group_name(gid: u32) -> Option<String> {
+    // Safety: getgrgid returns a pointer to static storage or NULL
+    let group = unsafe { libc::getgrgid(gid) };
+    if group.is_null() {
+        return None;
+    }
+    // Safety: gr_name of a valid group entry is a nul-terminated string
+    let name = unsafe { CStr::from_ptr((*group).gr_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Returns whether the current process is a member of the group `gid`
+fn in_group(gid: u32) -> bool {
+    // Safety: getegid cannot fail
+    if unsafe { libc::getegid() } == gid {
+        return true;
+    }
+
+    // Safety: a zero sized buffer only queries the number of groups
+    let n = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    if n <= 0 {
+        return false;
+    }
+    let mut groups = vec![0; n as usize];
+    // Safety: `groups` has room for `n` group IDs
+    let n = unsafe { libc::getgroups(n, groups.as_mut_ptr()) };
+    n > 0 && groups[..n as usize].contains(&gid)
+}
+
+/// Checks the device node at `path` can be opened for reading and writing
+///
+/// On failure, the [`Datum::mesg`] explains how to gain access, e.g. by joining the group
+/// owning the device node.
+pub fn dev_access(path: &str) -> Datum {
+    let err = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(_) => {
+            return Datum {
+                name: format!("{path} is accessible by user"),
+                pass: true,
+                info: None,
+                mesg: None,
+            }
+        }
+        Err(e) => e,
+    };
+
+    let mesg = match (err.kind(), fs::metadata(path)) {
+        (ErrorKind::NotFound, _) => format!(
+            "{path} does not exist. Check the driver is loaded and supported by the host kernel."
+        ),
+        (ErrorKind::PermissionDenied, Ok(meta)) if meta.gid() != 0 => {
+            let group = group_name(meta.gid()).unwrap_or_else(|| meta.gid().to_string());
+            if in_group(meta.gid()) {
+                format!(
+                    "{path} is owned by the `{group}` group, but it cannot be read and written \
+                     by the group. Allow access with a udev rule setting `MODE=\"0660\"`."
+                )
+            } else {
+                format!(
+                    "{path} is owned by the `{group}` group. Add your user to the group with \
+                     `sudo usermod -aG {group} $USER` and log in again."
+                )
+            }
+        }
+        (ErrorKind::PermissionDenied, _) => format!(
+            "{path} is only accessible by root. Allow access with a udev rule setting \
+             `MODE=\"0666\"` or a `GROUP` your user is a member of."
+        ),
+        _ => format!("{path} cannot be opened: {err}"),
+    };
+
+    Datum {
+        name: format!("{path} is accessible by user"),
+        pass: false,
+        info: Some(err.to_string()),
+        mesg: Some(mesg),
+    }
+}
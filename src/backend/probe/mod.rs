@@ -1,3 +1,4 @@
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod linux;
 pub mod x86_64;
@@ -4,11 +4,11 @@ use super::cpuid_page::CpuidPage;
 use super::snp::firmware::Firmware;
 use super::snp::launch::*;
 
-use super::SnpKeepPersonality;
+use super::{snp, SnpKeepPersonality};
 use crate::backend::kvm::builder::kvm_try_from_builder;
 use crate::backend::kvm::mem::Region;
 use crate::backend::sev::config::Config;
-use crate::backend::ByteSized;
+use crate::backend::{resource, ByteSized};
 
 use std::convert::TryFrom;
 use std::sync::{Arc, RwLock};
@@ -74,7 +74,9 @@ impl TryFrom<super::config::Config> for Builder {
                 .context("Failed to create a virtual machine")?;
 
             let sev = retry(|| Firmware::open().context("Failed to open '/dev/sev'"))?;
-            let launcher = Launcher::new(vm_fd, sev).context("SNP Launcher init failed")?;
+            let launcher = Launcher::new(vm_fd, sev)
+                .map_err(|e| resource(e, snp::Error::exhausted))
+                .context("SNP Launcher init failed")?;
 
             Ok((kvm_fd, launcher))
         })?;
@@ -84,7 +86,10 @@ impl TryFrom<super::config::Config> for Builder {
             ..Default::default()
         };
 
-        let launcher = launcher.start(start).context("SNP Launcher start failed")?;
+        let launcher = launcher
+            .start(start)
+            .map_err(|e| resource(e, snp::Error::exhausted))
+            .context("SNP Launcher start failed")?;
 
         Ok(Builder {
             config,
@@ -196,6 +201,7 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
         } = builder;
 
         let sallyport_block_size = config.sallyport_block_size;
+        let bounce_size = config.bounce_size;
         let signatures = config.signatures.take();
 
         let id_block;
@@ -226,6 +232,8 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
             cpu_fds: vec![vcpu_fd],
             regions,
             sallyport_block_size,
+            bounce_size,
+            time_page: false,
             sallyports,
             personality: SnpKeepPersonality { _sev_fd: sev_fd },
         })))
@@ -1,7 +1,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
-pub use crate::backend::kvm::data::{dev_kvm, kvm_version};
+pub use crate::backend::kvm::data::{dev_kvm, dev_kvm_access, kvm_version};
 
+use crate::backend::probe::linux::dev_access;
 use crate::backend::probe::x86_64::{CpuId, Vendor};
 use crate::backend::Datum;
 
@@ -95,11 +96,16 @@ pub fn has_reasonable_memlock_rlimit() -> Datum {
 }
 
 pub fn dev_sev() -> Datum {
+    let pass = std::path::Path::new("/dev/sev").exists();
+
     Datum {
         name: "Driver".into(),
-        pass: std::path::Path::new("/dev/sev").exists(),
+        pass,
         info: Some("/dev/sev".into()),
-        mesg: None,
+        mesg: (!pass).then(|| {
+            "Enable SEV-SNP in the BIOS and load the `ccp` kernel module of an SEV-SNP enabled host kernel."
+                .into()
+        }),
     }
 }
 
@@ -118,6 +124,14 @@ pub fn sev_enabled_in_kernel() -> Datum {
         }
     }
 
+    if !datum.pass {
+        datum.mesg = Some(
+            "Run a host kernel with SEV-SNP support and enable it with \
+             `options kvm_amd sev=1 sev_snp=1` in /etc/modprobe.d/kvm-amd.conf."
+                .into(),
+        );
+    }
+
     datum
 }
 
@@ -128,7 +142,7 @@ pub fn dev_sev_readable() -> Datum {
         name: "/dev/sev is readable by user".into(),
         pass: opts.is_ok(),
         info: None,
-        mesg: None,
+        mesg: opts.err().and_then(|_| dev_access("/dev/sev").mesg),
     }
 }
 
@@ -139,7 +153,52 @@ pub fn dev_sev_writable() -> Datum {
         name: "/dev/sev is writable by user".into(),
         pass: opts.is_ok(),
         info: None,
-        mesg: None,
+        mesg: opts.err().and_then(|_| dev_access("/dev/sev").mesg),
+    }
+}
+
+pub fn ibpb_enabled() -> Datum {
+    // e.g. "Mitigation: Retpolines, IBPB: conditional, IBRS_FW, STIBP: disabled, RSB filling"
+    let spectre_v2 = std::fs::read_to_string("/sys/devices/system/cpu/vulnerabilities/spectre_v2");
+    let ibpb = spectre_v2.ok().and_then(|val| {
+        val.split(',')
+            .find_map(|m| m.trim().strip_prefix("IBPB: ").map(str::to_string))
+    });
+    let pass = ibpb.as_deref() != Some("disabled");
+
+    Datum {
+        name: "Indirect Branch Prediction Barrier (IBPB) is enabled".into(),
+        pass,
+        info: ibpb,
+        mesg: (!pass).then(|| {
+            "Remove `mitigations=off` and `spectre_v2_user=off` from the kernel command line \
+             to protect keeps from branch target injection."
+                .into()
+        }),
+    }
+}
+
+pub fn ciphertext_hiding() -> Datum {
+    let mod_param = "/sys/module/kvm_amd/parameters/ciphertext_hiding_asids";
+
+    // Kernels without ciphertext hiding support do not have the module parameter
+    let asids = std::fs::read_to_string(mod_param)
+        .ok()
+        .and_then(|val| val.trim().parse::<u32>().ok());
+    let pass = asids != Some(0);
+
+    Datum {
+        name: "Ciphertext hiding is enabled".into(),
+        pass,
+        info: Some(match asids {
+            Some(asids) => format!("{asids} ASIDs"),
+            None => "unsupported by the host kernel".into(),
+        }),
+        mesg: (!pass).then(|| {
+            "Enable ciphertext hiding with `options kvm_amd ciphertext_hiding_asids=<n>` in \
+             /etc/modprobe.d/kvm-amd.conf to keep the host from reading the ciphertext of keep memory."
+                .into()
+        }),
     }
 }
 
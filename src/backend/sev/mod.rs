@@ -16,8 +16,8 @@ use super::kvm::mem::Region;
 use super::kvm::{Keep, KeepPersonality};
 use super::Loader;
 use data::{
-    dev_kvm, dev_sev, dev_sev_readable, dev_sev_writable, has_reasonable_memlock_rlimit,
-    kvm_version, sev_enabled_in_kernel, CPUIDS,
+    ciphertext_hiding, dev_kvm, dev_kvm_access, dev_sev, dev_sev_readable, dev_sev_writable,
+    has_reasonable_memlock_rlimit, ibpb_enabled, kvm_version, sev_enabled_in_kernel, CPUIDS,
 };
 
 use std::io;
@@ -111,6 +111,10 @@ impl super::Backend for Backend {
         ]
     }
 
+    fn doctor(&self) -> Vec<super::Datum> {
+        vec![dev_kvm_access(), ibpb_enabled(), ciphertext_hiding()]
+    }
+
     #[inline]
     fn keep(
         &self,
@@ -121,6 +125,11 @@ impl super::Backend for Backend {
         builder::Builder::load(shim, exec, signatures)
     }
 
+    #[inline]
+    fn capabilities(&self, shim: &[u8]) -> Result<super::Capabilities> {
+        super::kvm::Backend.capabilities(shim)
+    }
+
     #[inline]
     fn hash(&self, shim: &[u8], exec: &[u8]) -> Result<Vec<u8>> {
         hasher::Hasher::load(shim, exec, None)
@@ -259,6 +259,19 @@ impl From<u32> for Indeterminate<Error> {
     }
 }
 
+impl Error {
+    /// Returns whether `err` of a launch command signals the exhaustion of a resource shared by
+    /// all guests, i.e. the firmware ran out of a resource or KVM ran out of ASIDs
+    pub fn exhausted(err: &io::Error) -> bool {
+        match err.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+            Some(Error::ResourceLimit) => true,
+            Some(Error::Io(err)) => Self::exhausted(err),
+            Some(_) => false,
+            None => err.raw_os_error() == Some(libc::EBUSY),
+        }
+    }
+}
+
 /// Information about the SEV platform version.
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
@@ -291,6 +304,8 @@ pub struct Parameters {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     struct A;
     struct B;
     struct C;
@@ -308,4 +323,19 @@ mod tests {
         assert_eq!(B::ID, 2);
         assert_eq!(C::ID, 3);
     }
+
+    #[test]
+    fn exhausted() {
+        let busy = || io::Error::from_raw_os_error(libc::EBUSY);
+        assert!(Error::exhausted(&busy()));
+        assert!(Error::exhausted(
+            &Indeterminate::Known(busy().into()).into()
+        ));
+        assert!(Error::exhausted(&Indeterminate::<Error>::from(23).into()));
+        assert!(!Error::exhausted(&Indeterminate::<Error>::from(12).into()));
+        assert!(!Error::exhausted(&Indeterminate::<Error>::Unknown.into()));
+        assert!(!Error::exhausted(&io::Error::from_raw_os_error(
+            libc::EINVAL
+        )));
+    }
 }
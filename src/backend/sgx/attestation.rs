@@ -6,9 +6,9 @@
 use super::AESM_SOCKET;
 
 use crate::protobuf::aesm_proto::{
-    Request, Request_GetQuoteExRequest, Request_GetQuoteSizeExRequest,
-    Request_GetSupportedAttKeyIDNumRequest, Request_GetSupportedAttKeyIDsRequest,
-    Request_InitQuoteExRequest, Response,
+    Request, Request_GetLaunchTokenRequest, Request_GetQuoteExRequest,
+    Request_GetQuoteSizeExRequest, Request_GetSupportedAttKeyIDNumRequest,
+    Request_GetSupportedAttKeyIDsRequest, Request_InitQuoteExRequest, Response,
 };
 
 use std::io::{Error, ErrorKind, Read, Write};
@@ -24,6 +24,7 @@ const SGX_TI_SIZE: usize = size_of::<TargetInfo>();
 const AESM_REQUEST_TIMEOUT: u32 = 1_000_000;
 const SGX_KEY_ID_SIZE: u32 = 256;
 const SGX_REPORT_SIZE: usize = 432;
+const SGX_LAUNCH_TOKEN_SIZE: usize = 304;
 
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -434,6 +435,51 @@ pub fn get_quote(report: &[u8], akid: Vec<u8>, out_buf: &mut [u8]) -> Result<usi
     Ok(quote.len())
 }
 
+/// Gets the launch token (`EINITTOKEN`) of an enclave from the launch enclave of the AESMD
+pub fn get_launch_token(
+    mrenclave: &[u8],
+    mrsigner: &[u8],
+    attributes: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let mut transaction = AesmTransaction::new();
+
+    let mut msg = Request_GetLaunchTokenRequest::new();
+    msg.set_timeout(AESM_REQUEST_TIMEOUT);
+    msg.set_mr_enclave(mrenclave.to_vec());
+    msg.set_mr_signer(mrsigner.to_vec());
+    msg.set_se_attributes(attributes.to_vec());
+    transaction.set_getLicTokenReq(msg);
+
+    let pb_msg = transaction.request()?;
+
+    let res = pb_msg.get_getLicTokenRes();
+
+    if res.get_errorCode() != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "GetLaunchToken error: {:?}",
+                AesmError::from(res.get_errorCode())
+            ),
+        ));
+    }
+
+    let token = res.get_token();
+
+    if token.len() != SGX_LAUNCH_TOKEN_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "GetLaunchToken: Invalid EINITTOKEN size: {} != {}",
+                token.len(),
+                SGX_LAUNCH_TOKEN_SIZE
+            ),
+        ));
+    }
+
+    Ok(token.to_vec())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -3,6 +3,7 @@
 use super::config::Config;
 use super::ioctls::*;
 
+use std::arch::x86_64::__cpuid_count;
 use std::convert::TryFrom;
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
@@ -13,11 +14,63 @@ use mmarinus::{perms, Map, Shared};
 use primordial::Page;
 use sgx::crypto::{rcrypto::*, *};
 use sgx::page::{Class, Flags, SecInfo};
+use sgx::parameters::MiscSelect;
 use sgx::signature::{Author, Hasher, Signature};
 
-use tracing::trace;
+use tracing::{info, trace, warn};
 
-use crate::backend::ByteSized;
+use crate::backend::{resource, Binary, ByteSized, Capabilities};
+
+/// Default size of the heap added up front without EDMM
+const STATIC_HEAP_SIZE: usize = 256 * 1024 * 1024;
+
+/// Returns whether the CPU supports SGX2, which allows to add pages on demand (EDMM)
+///
+/// `ENARX_SGX_EDMM=0` disables EDMM on SGX2 CPUs, e.g. to test the static heap of SGX1.
+fn edmm() -> bool {
+    if std::env::var_os("ENARX_SGX_EDMM").map_or(false, |edmm| edmm == "0") {
+        return false;
+    }
+
+    // Safety: the SGX backend is only built for x86_64, where CPUID is always available
+    unsafe { __cpuid_count(0x00000012, 0x00000000) }.eax & (1 << 1) != 0
+}
+
+/// Returns the size of the heap added up front without EDMM, which can be set with
+/// `ENARX_SGX_HEAP_SIZE` in bytes
+fn static_heap_size() -> Result<usize> {
+    let size = match std::env::var("ENARX_SGX_HEAP_SIZE") {
+        Ok(size) => size
+            .parse()
+            .with_context(|| format!("invalid ENARX_SGX_HEAP_SIZE `{size}`"))?,
+        Err(_) => STATIC_HEAP_SIZE,
+    };
+    Ok((size + Page::SIZE - 1) / Page::SIZE * Page::SIZE)
+}
+
+/// Returns the capabilities of the enclaves of `shim` on this CPU
+///
+/// Without EDMM, no TCS can be added for new threads and the workload is limited to the heap
+/// added up front.
+pub(super) fn capabilities(shim: &[u8]) -> Result<Capabilities> {
+    let memory_size = super::config::memory_limit(&Binary::new(shim)?)?;
+    Ok(match edmm() {
+        true => Capabilities {
+            threads: None,
+            memory_size: Some(memory_size),
+        },
+        false => Capabilities {
+            threads: Some(0),
+            memory_size: Some(memory_size.min(static_heap_size()? as u64)),
+        },
+    })
+}
+
+/// Returns whether `err` of the SGX driver signals, that no EPC page could be allocated or
+/// reclaimed for the enclave
+fn epc_exhausted(err: &std::io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ENOMEM)
+}
 
 pub struct Builder {
     file: File,
@@ -26,15 +79,39 @@ pub struct Builder {
     mmap: Map<perms::Unknown>,
     perm: Vec<(*const (), usize, SecInfo)>,
     tcsp: Vec<super::Tcs>,
+    /// Whether the enclave is launched with a launch token on the legacy driver
+    legacy: bool,
+    /// Size of the heap added up front, if the CPU lacks EDMM
+    heap: Option<usize>,
 }
 
 impl TryFrom<super::config::Config> for Builder {
     type Error = Error;
 
-    fn try_from(config: super::config::Config) -> Result<Self> {
+    fn try_from(mut config: super::config::Config) -> Result<Self> {
         trace!("parsed config: {:?}", config);
         assert!(config.size.is_power_of_two()); // This is verified by `Config`...
 
+        // Without EDMM, the heap cannot grow on demand and is added up front. SGX1 CPUs also lack
+        // the extended SSA data, which is only needed to restore pages released with EDMM.
+        let heap = match edmm() {
+            true => None,
+            false => {
+                let size = static_heap_size()?;
+                if config.heap + size > config.size {
+                    return Err(anyhow!("the static SGX heap of {size} bytes is too large"));
+                }
+                info!("the CPU lacks SGX2 (EDMM), adding a static heap of {size} bytes");
+
+                // Safety: the SGX backend is only built for x86_64
+                let misc = unsafe { __cpuid_count(0x00000012, 0x00000000) }.ebx;
+                let misc = MiscSelect::from_bits_truncate(misc);
+                config.parameters.misc.data &= misc;
+                config.parameters.misc.mask &= misc;
+                Some(size)
+            }
+        };
+
         // Map the memory for the enclave
         // We map twice as much as we need so that we can naturally align it.
         let map = Map::bytes(config.size * 2)
@@ -53,12 +130,21 @@ impl TryFrom<super::config::Config> for Builder {
             map.addr() + map.size()
         );
 
-        // Open the device.
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open("/dev/sgx_enclave")
-            .context("Failed to open '/dev/sgx_enclave'")?;
+        let legacy = super::legacy::required();
+        let mut file = if legacy {
+            warn!(
+                "launching the enclave with a launch token on the legacy SGX driver, \
+                 which is weaker than Flexible Launch Control"
+            );
+            super::legacy::open(map.addr(), map.size())?
+        } else {
+            // Open the device.
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/sgx_enclave")
+                .context("Failed to open '/dev/sgx_enclave'")?
+        };
 
         // Create the enclave.
         let secs = config
@@ -68,6 +154,7 @@ impl TryFrom<super::config::Config> for Builder {
         let create = Create::new(&secs);
         ENCLAVE_CREATE
             .ioctl(&mut file, &create)
+            .map_err(|e| resource(e, epc_exhausted))
             .context("Failed to create SGX enclave")?;
 
         Ok(Builder {
@@ -77,6 +164,8 @@ impl TryFrom<super::config::Config> for Builder {
             tcsp: Vec::new(),
             cnfg: config,
             file,
+            legacy,
+            heap,
         })
     }
 }
@@ -87,7 +176,7 @@ impl super::super::Mapper for Builder {
 
     fn map(
         &mut self,
-        pages: Map<perms::ReadWrite>,
+        mut pages: Map<perms::ReadWrite>,
         to: usize,
         with: (SecInfo, bool),
     ) -> anyhow::Result<()> {
@@ -96,6 +185,9 @@ impl super::super::Mapper for Builder {
             return Ok(());
         }
 
+        // Write the enclave size into the shim before the pages are measured.
+        self.cnfg.patch(&mut pages, to);
+
         trace!(
             "adding pages: {:016x}-{:016x} {}",
             self.mmap.addr() + to,
@@ -104,10 +196,16 @@ impl super::super::Mapper for Builder {
         );
 
         // Update the enclave.
-        let mut ap = AddPages::new(&pages, to, &with.0, with.1);
-        ENCLAVE_ADD_PAGES
-            .ioctl(&mut self.file, &mut ap)
-            .context("Failed to add pages to SGX enclave")?;
+        if self.legacy {
+            let addr = self.mmap.addr() + to;
+            super::legacy::add_pages(&mut self.file, addr, &pages, &with.0, with.1)?;
+        } else {
+            let mut ap = AddPages::new(&pages, to, &with.0, with.1);
+            ENCLAVE_ADD_PAGES
+                .ioctl(&mut self.file, &mut ap)
+                .map_err(|e| resource(e, epc_exhausted))
+                .context("Failed to add pages to SGX enclave")?;
+        }
 
         // Update the hasher.
         self.hash.load(&pages, to, with.0, with.1).unwrap();
@@ -132,6 +230,22 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
     type Error = Error;
 
     fn try_from(mut builder: Builder) -> Result<Self> {
+        // Add the static heap zeroed and unmeasured, as the shim zeroes it before use
+        let static_heap = match builder.heap {
+            Some(size) => {
+                let pages = Map::bytes(size)
+                    .anywhere()
+                    .anonymously()
+                    .with(perms::ReadWrite)
+                    .context("Failed to map the static heap")?;
+                let si = Class::Regular.info(Flags::READ | Flags::WRITE | Flags::EXECUTE);
+                let offset = builder.cnfg.heap;
+                super::super::Mapper::map(&mut builder, pages, offset, (si, false))?;
+                Some(builder.mmap.addr() + offset + size)
+            }
+            None => None,
+        };
+
         let signature = if let Some(signatures) = builder.cnfg.signatures {
             let sig_blob = signatures.sgx;
 
@@ -141,7 +255,7 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
             let hash = builder.hash.finish();
             let author = Author::new(0, 0);
             let body = builder.cnfg.parameters.body(hash);
-            let key = if let Ok(key) = std::env::var("ENARX_TEST_SGX_KEY_FILE")
+            if let Ok(key) = std::env::var("ENARX_TEST_SGX_KEY_FILE")
                 .map_err(Error::new)
                 .and_then(|test_key_file| File::open(test_key_file).map_err(Error::new))
                 .and_then(|mut f| {
@@ -151,19 +265,42 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
                 })
                 .and_then(|keystr| RS256PrivateKey::from_pem(&keystr).map_err(Error::new))
             {
-                key
+                Signature::new(&key, author, body).context("Failed to create RSA signature")?
             } else {
-                RS256PrivateKey::generate(3).context("Failed to create RSA key")?
-            };
-
-            Signature::new(&key, author, body).context("Failed to create RSA signature")?
+                // Reuse the signature of an identical enclave launched before
+                let dir = super::cache::dir();
+                match dir.as_ref().and_then(|dir| super::cache::load(dir, &body)) {
+                    Some(signature) => signature,
+                    None => {
+                        let key =
+                            RS256PrivateKey::generate(3).context("Failed to create RSA key")?;
+                        let signature = Signature::new(&key, author, body)
+                            .context("Failed to create RSA signature")?;
+                        if let Some(dir) = dir {
+                            if let Err(e) = super::cache::store(&dir, &signature) {
+                                trace!("failed to cache signature: {:#}", e);
+                            }
+                        }
+                        signature
+                    }
+                }
+            }
         };
 
         // Initialize the enclave.
-        let init = Init::new(&signature);
-        ENCLAVE_INIT
-            .ioctl(&mut builder.file, &init)
-            .context("Failed to initialize SGX enclave")?;
+        if builder.legacy {
+            let addr = builder.mmap.addr();
+            let parameters = &builder.cnfg.parameters;
+            super::legacy::init(&mut builder.file, addr, &signature, parameters)?;
+
+            // The enclave is mapped by the legacy driver and the permissions are enforced by the EPCM.
+            builder.perm.clear();
+        } else {
+            let init = Init::new(&signature);
+            ENCLAVE_INIT
+                .ioctl(&mut builder.file, &init)
+                .context("Failed to initialize SGX enclave")?;
+        }
         trace!("enclave initialized");
 
         // Fix up mapped permissions.
@@ -210,6 +347,7 @@ impl TryFrom<Builder> for Arc<dyn super::super::Keep> {
             sallyport_block_size: builder.cnfg.sallyport_block_size,
             mem: builder.mmap,
             tcs: RwLock::new(builder.tcsp),
+            static_heap,
             enclave: Mutex::new(builder.file.try_clone().unwrap()),
         }))
     }
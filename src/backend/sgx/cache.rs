@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cache of self-signed enclave signatures
+//!
+//! Keeps launched without signatures are signed with a freshly generated RSA key, which
+//! dominates the host side cost of the launch. Identical Keeps on the same host have identical
+//! signature bodies, so their signatures are stored and reused across launches.
+//!
+//! The EPC pages themselves cannot be shared, because the CPU adds and measures them for each
+//! enclave. A cached signature cannot subvert the measurement either, since `EINIT` verifies it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use sgx::signature::{Body, Signature};
+use sha2::{Digest, Sha256};
+use tracing::trace;
+
+use crate::backend::ByteSized;
+
+/// Returns the user level cache directory of SGX signatures (`~/.cache/enarx/sgx`)
+pub fn dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("enarx").join("sgx"))
+}
+
+/// Returns the path of the signature of `body` in `dir`
+fn path(dir: &Path, body: &Body) -> PathBuf {
+    let digest = Sha256::digest(body.as_bytes());
+    dir.join(format!("{}.sig", hex::encode(digest)))
+}
+
+/// Loads the cached signature of `body` from `dir`
+pub fn load(dir: &Path, body: &Body) -> Option<Signature> {
+    let path = path(dir, body);
+    let signature = fs::read(&path)
+        .ok()
+        .and_then(|bytes| Signature::from_bytes(&bytes))
+        .filter(|signature| signature.body() == *body)?;
+    trace!("loaded cached signature: {}", path.display());
+    Some(signature)
+}
+
+/// Stores `signature` in `dir`
+///
+/// The signature is written to a temporary file first, so that concurrent launches
+/// never load a partially written signature.
+pub fn store(dir: &Path, signature: &Signature) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create directory `{}`", dir.display()))?;
+
+    let path = path(dir, &signature.body());
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp, signature.as_bytes())
+        .with_context(|| format!("failed to write `{}`", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| {
+        format!(
+            "failed to rename `{}` to `{}`",
+            tmp.display(),
+            path.display()
+        )
+    })?;
+    trace!("stored cached signature: {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut bytes = vec![0u8; Signature::SIZE];
+        bytes[0] = 1;
+        let signature = Signature::from_bytes(&bytes).unwrap();
+        let body = signature.body();
+
+        assert_eq!(load(dir.path(), &body), None);
+        store(dir.path(), &signature).unwrap();
+        assert_eq!(load(dir.path(), &body), Some(signature));
+
+        // A signature of another body is never loaded
+        let other = Body::from_bytes(&[1; Body::SIZE]).unwrap();
+        fs::copy(path(dir.path(), &body), path(dir.path(), &other)).unwrap();
+        assert_eq!(load(dir.path(), &other), None);
+    }
+}
@@ -5,11 +5,68 @@ use std::num::NonZeroU32;
 use crate::backend::Signatures;
 use anyhow::{anyhow, Result};
 use goblin::elf::program_header::{PF_R, PF_W, PF_X};
+use primordial::Page;
 use sallyport::elf;
 use sgx::page::{Class, Flags, SecInfo};
 use sgx::parameters::{Attributes, Masked, Parameters};
 use x86_64::registers::xcontrol::XCr0;
 
+/// Memory of the shim and the runtime in addition to the memory of the workload
+const MEMORY_HEADROOM: usize = 256 * 1024 * 1024;
+
+/// Returns the number of bits of the size of an enclave with the heap at `heap`, which holds
+/// `memory` bytes of the workload
+///
+/// The size is a power of two between `1 << bits` and `1 << max`.
+fn size_bits(bits: u8, max: u8, heap: usize, memory: u64) -> Result<u8> {
+    if memory == 0 {
+        return Ok(bits);
+    }
+
+    let need = usize::try_from(memory)
+        .ok()
+        .and_then(|memory| heap.checked_add(memory))
+        .and_then(|need| need.checked_add(MEMORY_HEADROOM))
+        .and_then(usize::checked_next_power_of_two)
+        .ok_or_else(|| anyhow!("memory size of {memory} bytes is too large"))?;
+    let need = (need.trailing_zeros() as u8).max(bits);
+    if need > max {
+        return Err(anyhow!(
+            "memory size of {memory} bytes exceeds the maximum SGX enclave size of {} bytes",
+            1usize << max
+        ));
+    }
+    Ok(need)
+}
+
+/// Returns the maximum memory size of the workload in an enclave of at most `1 << max` bytes with
+/// the heap at `heap`
+fn max_memory_size(max: u8, heap: usize) -> u64 {
+    (1u64 << max).saturating_sub((heap + MEMORY_HEADROOM) as u64)
+}
+
+/// Returns the offset of the heap of the enclaves of `shim`, which follows the executable slot
+fn heap(shim: &super::super::Binary<'_>) -> Result<usize> {
+    let heap = shim
+        .headers(elf::pt::EXEC)
+        .next()
+        .ok_or_else(|| anyhow!("SGX shim is missing the executable slot"))?
+        .vm_range()
+        .end;
+    Ok((heap + Page::SIZE - 1) / Page::SIZE * Page::SIZE)
+}
+
+/// Returns the maximum memory size of the workload in the enclaves of `shim`
+pub fn memory_limit(shim: &super::super::Binary<'_>) -> Result<u64> {
+    // Shims without MAX_BITS only support the size of their BITS
+    let max_bits: u8 = unsafe {
+        shim.note(elf::note::NAME, elf::note::sgx::MAX_BITS)
+            .or_else(|| shim.note(elf::note::NAME, elf::note::sgx::BITS))
+    }
+    .ok_or_else(|| anyhow!("SGX shim is missing BITS"))?;
+    Ok(max_memory_size(max_bits, heap(shim)?))
+}
+
 #[derive(Debug)]
 pub struct Config {
     pub parameters: Parameters,
@@ -17,6 +74,25 @@ pub struct Config {
     pub ssap: NonZeroU32,
     pub size: usize,
     pub sallyport_block_size: u64,
+    /// Offset of the heap, which follows the executable slot
+    pub heap: usize,
+    /// Number of bits of the enclave size
+    bits: u8,
+    /// Offset of the `BITS` note of the shim
+    bits_note: Option<usize>,
+}
+
+impl Config {
+    /// Writes the enclave size into the `BITS` note of the shim, if it is in `pages` at `to`
+    ///
+    /// The note is measured with the pages, so the size is part of MRENCLAVE.
+    pub fn patch(&self, pages: &mut [u8], to: usize) {
+        if let Some(note) = self.bits_note {
+            if (to..to + pages.len()).contains(&note) {
+                pages[note - to] = self.bits;
+            }
+        }
+    }
 }
 
 impl super::super::Config for Config {
@@ -97,6 +173,15 @@ impl super::super::Config for Config {
             let bits: u8 = shim
                 .note(elf::note::NAME, elf::note::sgx::BITS)
                 .ok_or_else(|| anyhow!("SGX shim is missing BITS"))?;
+            // Shims without MAX_BITS only support the size of their BITS
+            let max_bits: u8 = shim
+                .note(elf::note::NAME, elf::note::sgx::MAX_BITS)
+                .unwrap_or(bits);
+            let bits_note = shim.note_vaddr(elf::note::NAME, elf::note::sgx::BITS);
+
+            let heap = heap(shim)?;
+
+            let bits = size_bits(bits, max_bits, heap, super::memory_size())?;
 
             let sallyport_block_size: u64 = shim
                 .note(elf::note::NAME, elf::note::BLOCK_SIZE)
@@ -107,8 +192,33 @@ impl super::super::Config for Config {
                 size: 1 << bits,
                 ssap,
                 sallyport_block_size,
+                heap,
                 signatures,
+                bits,
+                bits_note,
             })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        let heap = 0x4000_0000;
+
+        assert_eq!(size_bits(32, 40, heap, 0).unwrap(), 32);
+        assert_eq!(size_bits(32, 40, heap, GIB).unwrap(), 32);
+        assert_eq!(size_bits(32, 40, heap, 3 * GIB).unwrap(), 33);
+        assert_eq!(size_bits(32, 40, heap, 100 * GIB).unwrap(), 37);
+        assert!(size_bits(32, 32, heap, 3 * GIB).is_err());
+        assert!(size_bits(32, 40, heap, u64::MAX).is_err());
+
+        let max = max_memory_size(36, heap);
+        assert_eq!(size_bits(32, 36, heap, max).unwrap(), 36);
+        assert!(size_bits(32, 36, heap, max + 1).is_err());
+    }
+}
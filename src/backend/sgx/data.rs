@@ -1,5 +1,6 @@
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::backend::probe::linux::{dev_access, kernel_at_least};
 use crate::backend::probe::x86_64::{CpuId, Vendor};
 use crate::backend::sgx::AESM_SOCKET;
 use crate::backend::Datum;
@@ -8,7 +9,15 @@ use sgx::parameters::{Features, MiscSelect, Xfrm};
 
 use std::arch::x86_64::__cpuid_count;
 use std::fs::File;
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
 use std::path::Path;
+use std::time::Duration;
+
+use url::Url;
+
+/// The configuration of the Intel quote provider library
+const QCNL_CONF: &str = "/etc/sgx_default_qcnl.conf";
 
 fn humanize(mut size: f64) -> (f64, &'static str) {
     let mut iter = 0;
@@ -60,7 +69,11 @@ pub const CPUIDS: &[CpuId] = &[
         name: "  Version 2",
         leaf: 0x00000012,
         subl: 0x00000000,
-        func: |res| (res.eax & (1 << 1) != 0, None),
+        // Without EDMM, Keeps fall back to a static heap
+        func: |res| match res.eax & (1 << 1) != 0 {
+            true => (true, None),
+            false => (true, Some("no, static heap".into())),
+        },
         vend: Some(Vendor::Intel),
     },
     CpuId {
@@ -156,14 +169,28 @@ pub fn epc_size(max: u32) -> Datum {
 }
 
 pub fn dev_sgx_enclave() -> Datum {
+    let pass = File::open("/dev/sgx_enclave").is_ok();
+
     Datum {
         name: "Driver".into(),
-        pass: File::open("/dev/sgx_enclave").is_ok(),
+        pass,
         info: Some("/dev/sgx_enclave".into()),
-        mesg: None,
+        mesg: if pass {
+            None
+        } else {
+            dev_access("/dev/sgx_enclave").mesg
+        },
     }
 }
 
+pub fn kernel_version() -> Datum {
+    kernel_at_least(
+        6,
+        0,
+        "Enarx requires the SGX2 (EDMM) support of the in-kernel SGX driver.",
+    )
+}
+
 pub fn aesm_socket() -> Datum {
     Datum {
         name: "AESM Daemon Socket".into(),
@@ -172,3 +199,127 @@ pub fn aesm_socket() -> Datum {
         mesg: None,
     }
 }
+
+pub fn aesmd_reachable() -> Datum {
+    let conn = UnixStream::connect(AESM_SOCKET);
+    let pass = cfg!(feature = "disable-sgx-attestation") || conn.is_ok();
+
+    Datum {
+        name: "AESM Daemon is reachable".into(),
+        pass,
+        info: conn.err().map(|e| e.to_string()),
+        mesg: (!pass).then(|| {
+            "Install the Intel SGX platform software and start the AESM daemon with \
+             `sudo systemctl enable --now aesmd`."
+                .into()
+        }),
+    }
+}
+
+/// Returns the PCCS URL configured in `conf`
+///
+/// Both the `PCCS_URL=` and the JSON `"pccs_url":` formats of the configuration are supported.
+fn pccs_url(conf: &str) -> Option<Url> {
+    conf.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#') && !line.starts_with("//"))
+        .filter(|line| line.to_ascii_lowercase().contains("pccs_url"))
+        .find_map(|line| {
+            let url = &line[line.find("http")?..];
+            let end = url.find(|c: char| c == '"' || c.is_whitespace());
+            Url::parse(&url[..end.unwrap_or(url.len())]).ok()
+        })
+}
+
+pub fn pccs_reachable() -> Datum {
+    let name = "PCCS is reachable".to_string();
+
+    if cfg!(feature = "disable-sgx-attestation") {
+        return Datum {
+            name,
+            pass: true,
+            info: None,
+            mesg: None,
+        };
+    }
+
+    let url =
+        match std::fs::read_to_string(QCNL_CONF) {
+            Ok(conf) => pccs_url(&conf),
+            Err(e) => return Datum {
+                name,
+                pass: false,
+                info: Some(format!("{QCNL_CONF}: {e}")),
+                mesg: Some(
+                    "Install the Intel DCAP quote provider library (`libsgx-dcap-default-qpl`) \
+                     and configure the PCCS in /etc/sgx_default_qcnl.conf."
+                        .into(),
+                ),
+            },
+        };
+
+    let url = match url {
+        Some(url) => url,
+        None => {
+            return Datum {
+                name,
+                pass: false,
+                info: Some(format!("no `pccs_url` in {QCNL_CONF}")),
+                mesg: Some(format!(
+                    "Set `pccs_url` in {QCNL_CONF} to the URL of the PCCS."
+                )),
+            }
+        }
+    };
+
+    let conn = url
+        .socket_addrs(|| Some(443))
+        .map_err(|e| e.to_string())
+        .and_then(|addrs| {
+            addrs
+                .iter()
+                .find_map(|addr| TcpStream::connect_timeout(addr, Duration::from_secs(3)).ok())
+                .ok_or_else(|| "connection failed".to_string())
+        });
+
+    Datum {
+        name,
+        pass: conn.is_ok(),
+        info: Some(match conn {
+            Ok(_) => url.to_string(),
+            Err(ref e) => format!("{url}: {e}"),
+        }),
+        mesg: conn.err().map(|_| {
+            format!(
+                "Start the PCCS service (`sudo systemctl start pccs`) or set `pccs_url` in \
+                 {QCNL_CONF} to a reachable PCCS."
+            )
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pccs_url_formats() {
+        let legacy = "# PCCS server address\nPCCS_URL=https://localhost:8081/sgx/certification/v3/\nUSE_SECURE_CERT=FALSE\n";
+        assert_eq!(
+            pccs_url(legacy).unwrap().as_str(),
+            "https://localhost:8081/sgx/certification/v3/"
+        );
+
+        let json = r#"{
+          // "pccs_url": "https://unused:8081/",
+          "pccs_url": "https://pccs.example.com:8081/sgx/certification/v4/",
+          "use_secure_cert": true
+        }"#;
+        assert_eq!(
+            pccs_url(json).unwrap().as_str(),
+            "https://pccs.example.com:8081/sgx/certification/v4/"
+        );
+
+        assert!(pccs_url("USE_SECURE_CERT=FALSE").is_none());
+    }
+}
@@ -217,6 +217,15 @@ pub(crate) fn sgx_enarxcall<'a>(
             Ok(None)
         }
 
+        item::Enarxcall {
+            num: item::enarxcall::Number::GetSgxStaticHeap,
+            ret,
+            ..
+        } => {
+            *ret = keep.static_heap.unwrap_or(0);
+            Ok(None)
+        }
+
         item::Enarxcall {
             num: item::enarxcall::Number::MmapHost,
             argv: [addr, len, prot, ..],
@@ -35,10 +35,11 @@ impl super::super::Mapper for Hasher {
     #[inline]
     fn map(
         &mut self,
-        pages: Map<perms::ReadWrite>,
+        mut pages: Map<perms::ReadWrite>,
         to: usize,
         with: (SecInfo, bool),
     ) -> anyhow::Result<()> {
+        self.cnfg.patch(&mut pages, to);
         self.digest.load(&pages, to, with.0, with.1).unwrap();
         Ok(())
     }
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Launch of enclaves on platforms without Flexible Launch Control (FLC)
+//!
+//! Without FLC, EINIT requires a launch token (`EINITTOKEN`) issued by Intel's launch enclave.
+//! The in-kernel SGX driver does not support those platforms, so the enclave is built with the
+//! legacy out-of-tree driver `/dev/isgx` and the launch token is requested from the AESM daemon.
+//!
+//! This is only available with the `sgx-launch-token` feature, as the trust model is weaker:
+//! the launch policy is decided by Intel's launch enclave on behalf of the host rather than by
+//! the platform owner, the launch depends on the AESM daemon of the host and the legacy driver
+//! lacks the hardening and the SGX2 (EDMM) support of the in-kernel driver.
+
+use super::attestation::get_launch_token;
+use super::data::CPUIDS;
+
+use crate::backend::probe::linux::dev_access;
+use crate::backend::{ByteSized, Datum};
+
+use std::arch::x86_64::__cpuid_count;
+use std::fs::{File, OpenOptions};
+use std::marker::PhantomData;
+use std::mem::transmute;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use iocuddle::*;
+use mmarinus::{perms, Map, Shared};
+use primordial::Page;
+use sgx::page::SecInfo;
+use sgx::parameters::{Attributes, Parameters};
+use sgx::signature::Signature;
+use sha2::{Digest, Sha256};
+
+/// Device node of the legacy out-of-tree driver
+pub const DEVICE: &str = "/dev/isgx";
+
+const SGX: Group = Group::new(0xA4);
+
+/// IOCTL identifier for EADD of a single page of the legacy driver
+const ENCLAVE_ADD_PAGE: Ioctl<Write, &AddPage<'_>> = unsafe { SGX.write(0x01) };
+
+/// IOCTL identifier for EINIT with a launch token of the legacy driver
+const ENCLAVE_INIT: Ioctl<Write, &Init<'_>> = unsafe { SGX.write(0x02) };
+
+/// Struct for adding a page to an enclave with the legacy driver
+#[repr(C, packed)]
+struct AddPage<'a> {
+    addr: u64,
+    src: u64,
+    secinfo: u64,
+    /// Measured 256 byte chunks of the page
+    mrmask: u16,
+    phantom: PhantomData<&'a ()>,
+}
+
+/// Struct for initializing an enclave with a launch token with the legacy driver
+#[repr(C, packed)]
+struct Init<'a> {
+    addr: u64,
+    sigstruct: u64,
+    einittoken: u64,
+    phantom: PhantomData<&'a ()>,
+}
+
+/// Returns whether the CPU supports Flexible Launch Control
+fn flc() -> bool {
+    // Safety: the SGX backend is only built for x86_64, where CPUID is always available
+    unsafe { __cpuid_count(0x00000007, 0x00000000) }.ecx & (1 << 30) != 0
+}
+
+/// Returns whether enclaves are launched with launch tokens on the legacy driver
+pub fn required() -> bool {
+    cfg!(feature = "sgx-launch-token") && !flc() && Path::new(DEVICE).exists()
+}
+
+/// The data of the backend, if enclaves are launched with launch tokens
+///
+/// FLC is not required, but the legacy driver has to be accessible.
+pub fn data() -> Vec<Datum> {
+    let mut data = vec![Datum {
+        name: "Driver".into(),
+        ..dev_access(DEVICE)
+    }];
+    data.extend(
+        CPUIDS
+            .iter()
+            .filter(|c| c.name.trim() != "FLC Support")
+            .map(|c| c.into()),
+    );
+    data.push(Datum {
+        name: "  Launch Tokens".into(),
+        pass: true,
+        info: Some("weaker trust model, see `sgx-launch-token`".into()),
+        mesg: None,
+    });
+    data
+}
+
+/// Opens the legacy driver and maps it onto the reserved enclave memory of `size` bytes at `addr`
+///
+/// Unlike the in-kernel driver, the legacy driver requires the mapping to exist before ECREATE.
+pub fn open(addr: usize, size: usize) -> Result<File> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(DEVICE)
+        .with_context(|| format!("Failed to open '{DEVICE}'"))?;
+
+    let rwx = libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC;
+    // Safety: the mapping replaces the memory reserved for the enclave, which is not used yet.
+    let map = unsafe {
+        Map::bytes(size)
+            .onto(addr)
+            .from(&mut file, 0)
+            .with_kind(Shared)
+            .with(perms::Unknown(rwx))
+    }
+    .with_context(|| format!("Failed to map enclave memory of '{DEVICE}'"))?;
+    // The reservation owns the address range and unmaps it on drop.
+    std::mem::forget(map);
+    Ok(file)
+}
+
+/// Adds the `pages` at `addr` to the enclave page by page
+pub fn add_pages(
+    file: &mut File,
+    addr: usize,
+    pages: &[u8],
+    secinfo: &SecInfo,
+    measure: bool,
+) -> Result<()> {
+    for (i, page) in pages.chunks(Page::SIZE).enumerate() {
+        let ap = AddPage {
+            addr: (addr + i * Page::SIZE) as _,
+            src: page.as_ptr() as _,
+            secinfo: secinfo as *const _ as _,
+            mrmask: if measure { u16::MAX } else { 0 },
+            phantom: PhantomData,
+        };
+        ENCLAVE_ADD_PAGE
+            .ioctl(file, &ap)
+            .context("Failed to add pages to SGX enclave")?;
+    }
+    Ok(())
+}
+
+/// Initializes the enclave at `addr` with a launch token for `signature`
+pub fn init(
+    file: &mut File,
+    addr: usize,
+    signature: &Signature,
+    parameters: &Parameters,
+) -> Result<()> {
+    let mrenclave = signature.body().mrenclave();
+    // MRSIGNER is the hash of the modulus of the SIGSTRUCT
+    let mrsigner = Sha256::digest(&signature.as_bytes()[128..512]);
+    let attributes = parameters.attr.data & parameters.attr.mask;
+    // Safety: `Attributes` is the plain 16 byte `ATTRIBUTES` structure of SGX
+    let attributes: [u8; 16] = unsafe { transmute::<Attributes, _>(attributes) };
+
+    let token = get_launch_token(&mrenclave, &mrsigner, &attributes)
+        .context("Failed to get launch token from AESM")?;
+
+    let init = Init {
+        addr: addr as _,
+        sigstruct: signature as *const _ as _,
+        einittoken: token.as_ptr() as _,
+        phantom: PhantomData,
+    };
+    ENCLAVE_INIT
+        .ioctl(file, &init)
+        .context("Failed to initialize SGX enclave")?;
+    Ok(())
+}
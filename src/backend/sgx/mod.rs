@@ -2,11 +2,13 @@
 
 mod attestation;
 mod builder;
+mod cache;
 mod config;
 mod data;
 mod enarxcall;
 mod hasher;
 mod ioctls;
+mod legacy;
 mod thread;
 
 use super::Loader;
@@ -17,16 +19,26 @@ use mmarinus::{perms, Map};
 use crate::backend::Signatures;
 use std::arch::x86_64::__cpuid_count;
 use std::fs::File;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 
 pub const AESM_SOCKET: &str = "/var/run/aesmd/aesm.socket";
 
+/// Memory size of the workload in bytes, which the enclave is sized for, or 0 for the default
+static MEMORY_SIZE: AtomicU64 = AtomicU64::new(0);
+
+fn memory_size() -> u64 {
+    MEMORY_SIZE.load(Ordering::Relaxed)
+}
+
 pub type Tcs = usize;
 
 pub(crate) struct Keep {
     sallyport_block_size: u64,
     mem: Map<perms::Unknown>,
     tcs: RwLock<Vec<Tcs>>,
+    /// End of the heap added at build time, if the CPU lacks EDMM
+    static_heap: Option<usize>,
     enclave: Mutex<File>,
 }
 
@@ -55,9 +67,13 @@ impl crate::backend::Backend for Backend {
     }
 
     fn data(&self) -> Vec<super::Datum> {
-        let mut data = vec![data::dev_sgx_enclave()];
-
-        data.extend(data::CPUIDS.iter().map(|c| c.into()));
+        let mut data = if legacy::required() {
+            legacy::data()
+        } else {
+            let mut data = vec![data::dev_sgx_enclave()];
+            data.extend(data::CPUIDS.iter().map(|c| c.into()));
+            data
+        };
 
         let max = unsafe { __cpuid_count(0x00000000, 0x00000000) }.eax;
         data.push(data::epc_size(max));
@@ -69,6 +85,14 @@ impl crate::backend::Backend for Backend {
         vec![data::aesm_socket()]
     }
 
+    fn doctor(&self) -> Vec<super::Datum> {
+        vec![
+            data::kernel_version(),
+            data::aesmd_reachable(),
+            data::pccs_reachable(),
+        ]
+    }
+
     #[inline]
     fn keep(
         &self,
@@ -79,10 +103,20 @@ impl crate::backend::Backend for Backend {
         builder::Builder::load(shim, exec, signatures)
     }
 
+    #[inline]
+    fn capabilities(&self, shim: &[u8]) -> Result<super::Capabilities> {
+        builder::capabilities(shim)
+    }
+
     #[inline]
     fn hash(&self, shim: &[u8], exec: &[u8]) -> Result<Vec<u8>> {
         hasher::Hasher::load(shim, exec, None)
     }
+
+    #[inline]
+    fn set_memory_size(&self, size: u64) {
+        MEMORY_SIZE.store(size, Ordering::Relaxed)
+    }
 }
 
 #[cfg(test)]
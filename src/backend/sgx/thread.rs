@@ -13,7 +13,7 @@ use std::mem::{size_of, MaybeUninit};
 use std::net::TcpStream;
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use sallyport::item;
 use sallyport::item::{Block, Item};
 use sgx::enclu::{EENTER, EEXIT, ERESUME};
@@ -74,6 +74,8 @@ impl super::super::Thread for Thread {
         run.tcs = self.tcs as u64;
         let how = self.how;
         let exit_status: u64;
+        let panic_file: u64;
+        let panic_line: u64;
         use x86_64::registers::segmentation::Segment64;
         use x86_64::registers::segmentation::{FS, GS};
 
@@ -104,8 +106,8 @@ impl super::super::Thread for Thread {
                 lateout("rdx") _,
                 inout("rcx") how => _,
                 lateout("r8") exit_status,
-                lateout("r9") _,
-                inout("r10") &mut run => _,
+                lateout("r9") panic_file,
+                inout("r10") &mut run => panic_line,
                 inout("r11") self.vdso => _,
                 lateout("r12") _,
                 lateout("r13") _,
@@ -118,13 +120,31 @@ impl super::super::Thread for Thread {
         debug_assert_eq!(oldfs, FS::read_base().as_u64());
         debug_assert_eq!(oldgs, GS::read_base().as_u64());
 
+        // The shim panicked and exited the enclave for good
+        if run.function as usize == EEXIT && exit_status == sallyport::SGX_PANIC {
+            return Err(anyhow!(
+                "shim panic at {panic_file:016x}:{}:{}",
+                panic_line >> 32,
+                panic_line as u32
+            ));
+        }
+
         let exit_status = exit_status as i32;
 
         self.how = match run.function as usize {
             EENTER | ERESUME if run.vector == Vector::InvalidOpcode => EENTER,
 
-            #[cfg(feature = "gdb")]
-            EENTER | ERESUME if run.vector == Vector::Page => EENTER,
+            // The shim restores discarded pages on access
+            EENTER | ERESUME if run.vector == Vector::Page => {
+                trace!(
+                    "{:?}: address = {:>#016x}, error code = {:>#016b} cssa={}",
+                    run.vector,
+                    run.exception_addr,
+                    run.exception_error_code,
+                    self.cssa
+                );
+                EENTER
+            }
 
             EEXIT if self.cssa > 0 => ERESUME,
             EEXIT if self.cssa == 0 => {
@@ -173,6 +193,7 @@ impl super::super::Thread for Thread {
         // remove this logic.
         if self.cssa > 0 {
             if let (EENTER, ERESUME) = (how, self.how) {
+                let mut exit_group = None;
                 let block: Block = self.block.as_mut_slice().into();
                 for item in block {
                     match item {
@@ -223,7 +244,8 @@ impl super::super::Thread for Thread {
                             ..,
                         ) if (*num == libc::SYS_exit_group as usize) => {
                             trace!("exit_group({code})");
-                            std::process::exit(*code as _);
+                            exit_group = Some(*code);
+                            break;
                         }
 
                         Item::Syscall(ref _syscall, ..) => {
@@ -248,6 +270,14 @@ impl super::super::Thread for Thread {
                         }
                     }
                 }
+
+                // Verify the scrubbing of the block as far as observable by the host
+                if let Some(code) = exit_group {
+                    if !sallyport::host::is_scrubbed(&self.block) {
+                        error!("sallyport block was not scrubbed before exit_group({code})");
+                    }
+                    std::process::exit(code as _);
+                }
             }
         }
 
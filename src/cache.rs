@@ -0,0 +1,330 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host-managed cache of precompiled Wasm modules
+//!
+//! The cache is shared by concurrently launched Keeps. Entries are addressed by the digest
+//! of the Wasm module, the compilation target and the CPU fingerprint of the host, so that a
+//! cache directory shared by hosts with different CPUs never serves an artifact compiled for
+//! another CPU. Every entry is guarded by a lock file, so that concurrent launches of the same
+//! module compile it only once.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use enarx_exec_wasmtime::{cpu_fingerprint, precompile, verify_artifact};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+/// Extension of artifact files
+const ARTIFACT_EXT: &str = "bin";
+
+/// Extension of lock files
+const LOCK_EXT: &str = "lock";
+
+/// Name of the lock file guarding garbage collection
+const GC_LOCK: &str = "gc.lock";
+
+/// An exclusive lock on a file, which is released on drop
+struct Lock {
+    file: File,
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Acquires the lock on the file at `path`, creating it if necessary
+    ///
+    /// Returns `None`, if `block` is `false` and the lock is held elsewhere.
+    fn acquire(path: PathBuf, block: bool) -> Result<Option<Self>> {
+        loop {
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .open(&path)
+                .with_context(|| format!("failed to open lock file `{}`", path.display()))?;
+
+            let op = if block {
+                libc::LOCK_EX
+            } else {
+                libc::LOCK_EX | libc::LOCK_NB
+            };
+            if unsafe { libc::flock(file.as_raw_fd(), op) } != 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    return Ok(None);
+                }
+                return Err(err).with_context(|| format!("failed to lock `{}`", path.display()));
+            }
+
+            // The lock file might have been removed by the garbage collection, before
+            // the lock was acquired. Retry in that case.
+            let locked = file.metadata()?;
+            match fs::metadata(&path) {
+                Ok(md) if md.dev() == locked.dev() && md.ino() == locked.ino() => {
+                    return Ok(Some(Self { file, path }))
+                }
+                Ok(..) => continue,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to query `{}`", path.display()))
+                }
+            }
+        }
+    }
+
+    /// Removes the lock file, while the lock is still held
+    fn remove(self) -> io::Result<()> {
+        let res = fs::remove_file(&self.path);
+        drop(self.file);
+        res
+    }
+}
+
+/// Cache of precompiled Wasm modules
+pub struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+    max_age: Duration,
+}
+
+impl Cache {
+    /// Opens the cache at `dir`, creating it if necessary
+    pub fn new(dir: impl Into<PathBuf>, max_size: u64, max_age: Duration) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create cache directory `{}`", dir.display()))?;
+        Ok(Self {
+            dir,
+            max_size,
+            max_age,
+        })
+    }
+
+    fn path(&self, key: &str, ext: &str) -> PathBuf {
+        self.dir.join(key).with_extension(ext)
+    }
+
+    /// Returns the artifact of `webasm` compiled for `target`
+    ///
+    /// The module is compiled and inserted into the cache, if it is not present yet or
+    /// the cached artifact is corrupted. Returns `None`, if the module fails to compile.
+    pub fn get(&self, webasm: &[u8], target: Option<&str>) -> Result<Option<File>> {
+        let key = key(webasm, target);
+        let path = self.path(&key, ARTIFACT_EXT);
+
+        let _lock = Lock::acquire(self.path(&key, LOCK_EXT), true)?
+            .expect("blocking lock acquisition returned without the lock");
+
+        match open(&path, webasm) {
+            Ok(Some(file)) => {
+                debug!("using cached artifact `{}`", path.display());
+                return Ok(Some(file));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!("invalidating cached artifact `{}`: {e:#}", path.display());
+                fs::remove_file(&path).with_context(|| {
+                    format!("failed to remove cached artifact `{}`", path.display())
+                })?;
+            }
+        }
+
+        let artifact = match precompile(webasm, target) {
+            Ok(artifact) => artifact,
+            Err(e) => {
+                debug!("not caching Wasm module: {e:#}");
+                return Ok(None);
+            }
+        };
+
+        let tmp = path.with_extension("tmp");
+        let mut file =
+            File::create(&tmp).with_context(|| format!("failed to create `{}`", tmp.display()))?;
+        file.write_all(&artifact)
+            .and_then(|()| file.sync_all())
+            .with_context(|| format!("failed to write `{}`", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("failed to rename `{}`", tmp.display()))?;
+        debug!("cached artifact `{}`", path.display());
+
+        if let Err(e) = self.gc() {
+            warn!("failed to collect garbage in cache: {e:#}");
+        }
+
+        let mut file =
+            File::open(&path).with_context(|| format!("failed to open `{}`", path.display()))?;
+        file.rewind()?;
+        Ok(Some(file))
+    }
+
+    /// Removes entries, which have not been used for longer than the maximum age,
+    /// and the least recently used entries exceeding the maximum size of the cache
+    ///
+    /// Entries in use by concurrent launches are skipped.
+    pub fn gc(&self) -> Result<()> {
+        let _lock = match Lock::acquire(self.dir.join(GC_LOCK), false)? {
+            Some(lock) => lock,
+            // Garbage is being collected concurrently
+            None => return Ok(()),
+        };
+
+        let mut entries = vec![];
+        for entry in fs::read_dir(&self.dir)
+            .with_context(|| format!("failed to read `{}`", self.dir.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(ARTIFACT_EXT) {
+                continue;
+            }
+            let md = fs::metadata(&path)?;
+            entries.push((md.modified()?, md.len(), path));
+        }
+        // Least recently used first
+        entries.sort();
+
+        let now = SystemTime::now();
+        let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        for (used, len, path) in entries {
+            let expired = now.duration_since(used).unwrap_or_default() > self.max_age;
+            if !expired && size <= self.max_size {
+                break;
+            }
+            let lock = match Lock::acquire(path.with_extension(LOCK_EXT), false)? {
+                Some(lock) => lock,
+                // The entry is in use
+                None => continue,
+            };
+            debug!("removing cached artifact `{}`", path.display());
+            match fs::remove_file(&path) {
+                Ok(()) => size -= len,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => size -= len,
+                Err(e) => bail!("failed to remove `{}`: {e}", path.display()),
+            }
+            lock.remove()?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the key of `webasm` compiled for `target` on this host
+fn key(webasm: &[u8], target: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update([0]);
+    hasher.update(target.unwrap_or("host"));
+    hasher.update([0]);
+    hasher.update(cpu_fingerprint().to_le_bytes());
+    hasher.update(webasm);
+    hex::encode(hasher.finalize())
+}
+
+/// Opens the verified artifact at `path` and marks it as used
+fn open(path: &Path, webasm: &[u8]) -> Result<Option<File>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("failed to open"),
+    };
+    let mut artifact = vec![];
+    file.read_to_end(&mut artifact).context("failed to read")?;
+    verify_artifact(&artifact, Some(webasm))?;
+
+    if unsafe { libc::futimens(file.as_raw_fd(), std::ptr::null()) } != 0 {
+        debug!(
+            "failed to update the time of last use of `{}`: {}",
+            path.display(),
+            io::Error::last_os_error()
+        );
+    }
+    file.rewind()?;
+    Ok(Some(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RETURN_1_WAT: &str = r#"(module
+      (func (export "") (result i32) i32.const 1)
+    )"#;
+
+    fn artifacts(dir: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().and_then(|e| e.to_str()) == Some(ARTIFACT_EXT))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn get() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path(), u64::MAX, Duration::MAX).unwrap();
+        let webasm = wat::parse_str(RETURN_1_WAT).unwrap();
+
+        let mut artifact = vec![];
+        cache
+            .get(&webasm, None)
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut artifact)
+            .unwrap();
+        assert!(verify_artifact(&artifact, Some(&webasm)).is_ok());
+
+        let paths = artifacts(dir.path());
+        assert_eq!(paths.len(), 1);
+        let modified = fs::metadata(&paths[0]).unwrap().modified().unwrap();
+
+        // A cache hit does not recompile
+        assert!(cache.get(&webasm, None).unwrap().is_some());
+        assert_eq!(
+            fs::metadata(&paths[0]).unwrap().len(),
+            artifact.len() as u64
+        );
+        assert!(fs::metadata(&paths[0]).unwrap().modified().unwrap() >= modified);
+
+        // Corrupted entries are invalidated
+        fs::write(&paths[0], &artifact[..artifact.len() - 1]).unwrap();
+        let mut recompiled = vec![];
+        cache
+            .get(&webasm, None)
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut recompiled)
+            .unwrap();
+        assert!(verify_artifact(&recompiled, Some(&webasm)).is_ok());
+
+        // Invalid modules are not cached
+        assert!(cache.get(b"\0asm", None).unwrap().is_none());
+        assert_eq!(artifacts(dir.path()).len(), 1);
+    }
+
+    #[test]
+    fn gc() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let cache = Cache::new(dir.path(), u64::MAX, Duration::MAX).unwrap();
+        let webasm = wat::parse_str(RETURN_1_WAT).unwrap();
+        cache.get(&webasm, None).unwrap().unwrap();
+        assert_eq!(artifacts(dir.path()).len(), 1);
+
+        // Entries in use are kept
+        let path = artifacts(dir.path()).remove(0);
+        let lock = Lock::acquire(path.with_extension(LOCK_EXT), true)
+            .unwrap()
+            .unwrap();
+        let cache = Cache::new(dir.path(), 0, Duration::ZERO).unwrap();
+        cache.gc().unwrap();
+        assert_eq!(artifacts(dir.path()).len(), 1);
+        drop(lock);
+
+        cache.gc().unwrap();
+        assert!(artifacts(dir.path()).is_empty());
+        assert!(!path.with_extension(LOCK_EXT).exists());
+    }
+}
@@ -0,0 +1,67 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::backend::Backend;
+use crate::cache::Cache;
+use crate::exec::Exec;
+
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::time::Duration;
+
+use anyhow::Context;
+use camino::Utf8PathBuf;
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+
+/// Common compiled module cache options
+#[derive(Args, Debug)]
+pub struct CacheOptions {
+    /// Directory of the compiled Wasm module cache shared by concurrently launched Keeps.
+    ///
+    /// Modules are compiled on the host only once and the result is reused by subsequent
+    /// launches. Caching is disabled, if unset. Keeps of hardware-isolated backends always
+    /// compile the module themselves.
+    #[clap(long, env = "ENARX_CACHE_DIR", value_name = "DIR")]
+    cache_dir: Option<Utf8PathBuf>,
+
+    /// Maximum total size of the compiled Wasm module cache in bytes
+    #[clap(long, default_value_t = 1 << 30, value_name = "BYTES")]
+    cache_max_size: u64,
+
+    /// Maximum age in seconds of unused entries in the compiled Wasm module cache
+    #[clap(long, default_value_t = 30 * 24 * 60 * 60, value_name = "SECONDS")]
+    cache_max_age: u64,
+}
+
+impl CacheOptions {
+    /// Returns the cached artifact of the Wasm module read from `wasm` for `exec` on `backend`.
+    pub fn artifact(
+        &self,
+        backend: &dyn Backend,
+        exec: &dyn Exec,
+        wasm: &mut File,
+    ) -> anyhow::Result<Option<File>> {
+        let dir = match self.cache_dir {
+            Some(ref dir) => dir,
+            None => return Ok(None),
+        };
+        // Host-compiled code must not be trusted by hardware-isolated Keeps
+        if matches!(backend.name(), "sgx" | "sev") {
+            return Ok(None);
+        }
+
+        let cache = Cache::new(
+            dir,
+            self.cache_max_size,
+            Duration::from_secs(self.cache_max_age),
+        )
+        .classify(ErrorKind::Config)?;
+
+        let mut webasm = vec![];
+        wasm.read_to_end(&mut webasm)
+            .and_then(|_| wasm.rewind())
+            .context("failed to read WASM module")
+            .classify(ErrorKind::Io)?;
+        cache.get(&webasm, exec.target()).classify(ErrorKind::Io)
+    }
+}
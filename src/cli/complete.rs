@@ -0,0 +1,412 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Shell completion
+//!
+//! `enarx completions <SHELL>` prints a completion script, which calls the hidden
+//! `enarx __complete` for every completion. It walks the `enarx` command line up to the word
+//! being completed and prints the candidates for it: subcommands, options and values. Besides
+//! the possible values of options, backend names, the IDs of running Keeps and recently deployed
+//! Drawbridge slugs are completed. `enarx __complete --commands` prints all commands as JSON for
+//! tools driving `enarx`.
+
+use crate::backend::BACKENDS;
+use crate::drawbridge::recent_slugs;
+
+use std::ops::Deref;
+
+use clap::{Arg, Args, Command, CommandFactory, ValueEnum};
+use serde_json::{json, Value};
+
+/// Shell of a completion script
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    /// Bash, with one candidate per line
+    Bash,
+    /// Zsh, with `value:help` lines for `_describe`
+    Zsh,
+    /// Fish, with tab-separated `value` and `help`
+    Fish,
+}
+
+const BASH: &str = r#"_enarx() {
+    local IFS=$'\n'
+    COMPREPLY=($(enarx __complete --shell bash -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+}
+complete -o default -F _enarx enarx
+"#;
+
+const ZSH: &str = r#"#compdef enarx
+_enarx() {
+    local -a candidates
+    candidates=("${(@f)$(enarx __complete --shell zsh -- "${(@)words[2,CURRENT]}" 2>/dev/null)}")
+    _describe 'enarx' candidates
+}
+compdef _enarx enarx
+"#;
+
+const FISH: &str = r#"complete -c enarx -f -a '(enarx __complete --shell fish -- (commandline -opc)[2..-1] (commandline -ct))'
+"#;
+
+/// Print a shell completion script
+///
+/// For example, add `source <(enarx completions bash)` to `~/.bashrc`.
+#[derive(Args, Debug)]
+pub struct CompletionsOptions {
+    /// Shell to complete in
+    #[clap(value_enum)]
+    shell: Shell,
+}
+
+impl CompletionsOptions {
+    pub fn execute(self) -> anyhow::Result<()> {
+        match self.shell {
+            Shell::Bash => print!("{BASH}"),
+            Shell::Zsh => print!("{ZSH}"),
+            Shell::Fish => print!("{FISH}"),
+        }
+        Ok(())
+    }
+}
+
+/// Complete an `enarx` command line
+///
+/// Prints a JSON array of the candidates with their help, or one candidate per line in the
+/// format of `--shell`.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// Print the candidates in the format of the completion script of this shell
+    #[clap(long, value_enum)]
+    shell: Option<Shell>,
+
+    /// Print all commands with their arguments as JSON instead
+    #[clap(long)]
+    commands: bool,
+
+    /// The words following `enarx`, the last one being completed
+    #[clap(last = true, value_name = "WORDS")]
+    words: Vec<String>,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let mut cmd = super::Options::command();
+        cmd.build();
+
+        if self.commands {
+            println!("{}", serde_json::to_string_pretty(&describe(&cmd))?);
+            return Ok(());
+        }
+
+        let candidates = complete(&cmd, &self.words, &Sources::Local);
+        match self.shell {
+            None => println!("{}", serde_json::to_string_pretty(&candidates)?),
+            Some(shell) => {
+                for candidate in candidates {
+                    println!("{}", candidate.format(shell));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A completion candidate
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+struct Candidate {
+    value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    help: Option<String>,
+}
+
+impl Candidate {
+    fn new(value: impl Into<String>, help: Option<impl ToString>) -> Self {
+        let help = help
+            .map(|help| help.to_string())
+            .and_then(|help| help.lines().next().map(str::to_string))
+            .filter(|help| !help.is_empty());
+        Self {
+            value: value.into(),
+            help,
+        }
+    }
+
+    /// Returns the candidate in the format of the completion script of `shell`
+    fn format(&self, shell: Shell) -> String {
+        match (shell, &self.help) {
+            (Shell::Bash, _) | (_, None) => self.value.clone(),
+            (Shell::Zsh, Some(help)) => format!("{}:{help}", self.value.replace(':', "\\:")),
+            (Shell::Fish, Some(help)) => format!("{}\t{help}", self.value),
+        }
+    }
+}
+
+/// Sources of dynamic values, which are replaced in tests
+enum Sources {
+    Local,
+    #[cfg(test)]
+    Fixed {
+        keeps: Vec<(u32, String)>,
+        slugs: Vec<String>,
+    },
+}
+
+impl Sources {
+    /// Returns the IDs of the running Keeps with their workloads
+    fn keeps(&self) -> Vec<(u32, String)> {
+        match self {
+            #[cfg(unix)]
+            Self::Local => crate::control::list(&crate::control::dir())
+                .map(|keeps| {
+                    keeps
+                        .into_iter()
+                        .map(|keep| (keep.id, keep.workload))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            #[cfg(not(unix))]
+            Self::Local => vec![],
+            #[cfg(test)]
+            Self::Fixed { keeps, .. } => keeps.clone(),
+        }
+    }
+
+    /// Returns the recently deployed Drawbridge slugs
+    fn slugs(&self) -> Vec<String> {
+        match self {
+            Self::Local => recent_slugs(),
+            #[cfg(test)]
+            Self::Fixed { slugs, .. } => slugs.clone(),
+        }
+    }
+}
+
+/// Returns whether `arg` has the value name `name`
+fn named(arg: &Arg, name: &str) -> bool {
+    arg.get_value_names()
+        .map_or(false, |names| names.iter().any(|n| n.deref() == name))
+}
+
+/// Returns the candidates for a value of `arg`
+fn values(arg: &Arg, sources: &Sources) -> Vec<Candidate> {
+    if arg.get_id().as_str() == "backend" {
+        BACKENDS
+            .deref()
+            .iter()
+            .map(|backend| {
+                let help = match backend.have() {
+                    true => "available",
+                    false => "not available on this platform",
+                };
+                Candidate::new(backend.name(), Some(help))
+            })
+            .collect()
+    } else if named(arg, "KEEP") {
+        sources
+            .keeps()
+            .into_iter()
+            .map(|(id, workload)| Candidate::new(id.to_string(), Some(workload)))
+            .collect()
+    } else if named(arg, "PACKAGE") {
+        sources
+            .slugs()
+            .into_iter()
+            .map(|slug| Candidate::new(slug, None::<String>))
+            .collect()
+    } else {
+        arg.get_possible_values()
+            .into_iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| Candidate::new(value.get_name(), value.get_help()))
+            .collect()
+    }
+}
+
+/// Returns whether `arg` is an option or positional argument taking values
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+/// Returns the candidates completing the last of the `words` following `enarx`
+///
+/// `cmd` must be built.
+fn complete(mut cmd: &Command, words: &[String], sources: &Sources) -> Vec<Candidate> {
+    let (current, words) = match words.split_last() {
+        Some((current, words)) => (current.as_str(), words),
+        None => ("", words),
+    };
+
+    // Walk the command line up to the current word
+    let mut pending = None;
+    let mut positional = 0;
+    let mut options = true;
+    for word in words {
+        if pending.take().is_some() {
+            continue;
+        }
+        if options && word == "--" {
+            options = false;
+        } else if let Some(long) = word.strip_prefix("--").filter(|_| options) {
+            // An option followed by `=` and its value takes none from the next word
+            pending = cmd
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(long))
+                .filter(|arg| takes_value(arg));
+        } else if let Some(short) = word.strip_prefix('-').filter(|s| options && !s.is_empty()) {
+            // A short option followed by its value in the same word takes none from the next
+            pending = short
+                .chars()
+                .next()
+                .filter(|_| short.len() == 1)
+                .and_then(|short| {
+                    cmd.get_arguments()
+                        .find(|arg| arg.get_short() == Some(short))
+                })
+                .filter(|arg| takes_value(arg));
+        } else if let Some(sub) = cmd.find_subcommand(word).filter(|_| positional == 0) {
+            cmd = sub;
+        } else {
+            positional += 1;
+        }
+    }
+
+    let mut candidates = if let Some(arg) = pending {
+        values(arg, sources)
+    } else if let Some((long, value)) = current
+        .strip_prefix("--")
+        .filter(|_| options)
+        .and_then(|current| current.split_once('='))
+    {
+        return match cmd.get_arguments().find(|arg| arg.get_long() == Some(long)) {
+            Some(arg) => values(arg, sources)
+                .into_iter()
+                .filter(|candidate| candidate.value.starts_with(value))
+                .map(|candidate| Candidate {
+                    value: format!("--{long}={}", candidate.value),
+                    ..candidate
+                })
+                .collect(),
+            None => vec![],
+        };
+    } else if current.starts_with('-') && options {
+        cmd.get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .filter_map(|arg| {
+                let long = arg.get_long()?;
+                Some(Candidate::new(format!("--{long}"), arg.get_help()))
+            })
+            .collect()
+    } else {
+        let mut candidates = vec![];
+        if positional == 0 {
+            candidates.extend(
+                cmd.get_subcommands()
+                    .filter(|sub| !sub.is_hide_set())
+                    .map(|sub| Candidate::new(sub.get_name(), sub.get_about())),
+            );
+        }
+        if let Some(arg) = cmd.get_positionals().nth(positional) {
+            candidates.extend(values(arg, sources));
+        }
+        candidates
+    };
+
+    candidates.retain(|candidate| candidate.value.starts_with(current));
+    candidates
+}
+
+/// Returns `cmd` with its arguments and subcommands as JSON
+fn describe(cmd: &Command) -> Value {
+    let args: Vec<Value> = cmd
+        .get_arguments()
+        .filter(|arg| !["help", "version"].contains(&arg.get_id().as_str()))
+        .map(|arg| {
+            json!({
+                "id": arg.get_id().as_str(),
+                "long": arg.get_long(),
+                "short": arg.get_short(),
+                "positional": arg.is_positional(),
+                "required": arg.is_required_set(),
+                "takes_value": takes_value(arg),
+                "value_names": arg
+                    .get_value_names()
+                    .map(|names| names.iter().map(|name| name.deref().to_string()).collect::<Vec<_>>()),
+                "possible_values": arg
+                    .get_possible_values()
+                    .iter()
+                    .map(|value| value.get_name().to_string())
+                    .collect::<Vec<_>>(),
+                "hidden": arg.is_hide_set(),
+                "help": arg.get_help().map(|help| help.to_string()),
+            })
+        })
+        .collect();
+    let subcommands: Vec<Value> = cmd
+        .get_subcommands()
+        .filter(|sub| sub.get_name() != "help")
+        .map(describe)
+        .collect();
+    json!({
+        "name": cmd.get_name(),
+        "about": cmd.get_about().map(|about| about.to_string()),
+        "hidden": cmd.is_hide_set(),
+        "args": args,
+        "subcommands": subcommands,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(line: &str) -> Vec<String> {
+        let mut cmd = crate::cli::Options::command();
+        cmd.build();
+        let mut words: Vec<_> = line.split(' ').map(str::to_string).collect();
+        if line.is_empty() {
+            words.clear();
+        }
+        let sources = Sources::Fixed {
+            keeps: vec![(42, "sha256:00".into())],
+            slugs: vec!["example.com/user/repo:0.1.0".into()],
+        };
+        complete(&cmd, &words, &sources)
+            .into_iter()
+            .map(|candidate| candidate.value)
+            .collect()
+    }
+
+    #[test]
+    fn subcommands() {
+        assert!(candidates("").contains(&"run".to_string()));
+        assert!(candidates("ru").contains(&"run".to_string()));
+        assert!(!candidates("ru").contains(&"deploy".to_string()));
+        // Hidden subcommands are not completed
+        assert!(!candidates("").contains(&"__complete".to_string()));
+        assert!(candidates("package ").contains(&"fetch".to_string()));
+    }
+
+    #[test]
+    fn options() {
+        assert!(candidates("run --was").contains(&"--wasmcfgfile".to_string()));
+        assert!(candidates("deploy --backend ").contains(&"nil".to_string()));
+        assert!(candidates("deploy --backend=n").contains(&"--backend=nil".to_string()));
+        assert_eq!(candidates("completions z"), ["zsh"]);
+    }
+
+    #[test]
+    fn dynamic() {
+        assert_eq!(candidates("deploy ex"), ["example.com/user/repo:0.1.0"]);
+        assert!(candidates("deploy --backend nil ")
+            .contains(&"example.com/user/repo:0.1.0".to_string()));
+        #[cfg(unix)]
+        assert_eq!(candidates("kill "), ["42"]);
+    }
+
+    #[test]
+    fn format() {
+        let candidate = Candidate::new("a:b", Some("help\nmore"));
+        assert_eq!(candidate.format(Shell::Bash), "a:b");
+        assert_eq!(candidate.format(Shell::Zsh), "a\\:b:help");
+        assert_eq!(candidate.format(Shell::Fish), "a:b\thelp");
+    }
+}
@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::File;
+
+use anyhow::Context;
+use camino::Utf8PathBuf;
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+use enarx_exec_wasmtime::dataset_digest;
+
+/// Compute the digest of a read-only dataset shared by Keeps
+///
+/// Prints the digest, which must be added as `digest` to the `dataset` file of the Enarx.toml
+/// for Keeps to verify the dataset at `path`.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// Path of the dataset
+    #[clap(value_name = "PATH")]
+    pub path: Utf8PathBuf,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let digest = File::open(&self.path)
+            .and_then(dataset_digest)
+            .with_context(|| format!("failed to read dataset at `{}`", self.path))
+            .classify(ErrorKind::Io)?;
+        println!("{digest}");
+        Ok(())
+    }
+}
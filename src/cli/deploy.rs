@@ -1,8 +1,14 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::cli::BackendOptions;
-use crate::drawbridge::parse_tag;
-use crate::exec::{open_package, run_package, EXECS};
+#[cfg(unix)]
+use crate::cli::CacheOptions;
+use crate::cli::{BackendOptions, SecretOptions, ShimOptions};
+use crate::drawbridge::{parse_tag, remember_slug};
+#[cfg(target_os = "linux")]
+use crate::exec::host;
+use crate::exec::{
+    open_channel, open_datasets, open_package, open_provenance, read_config, run_package, EXECS,
+};
 
 use std::fmt::Debug;
 use std::fs;
@@ -13,7 +19,8 @@ use crate::backend::Signatures;
 use anyhow::{anyhow, bail, Context};
 use camino::Utf8PathBuf;
 use clap::Args;
-use enarx_exec_wasmtime::{Package, PACKAGE_CONFIG, PACKAGE_ENTRYPOINT};
+use enarx_error::{Classify, ErrorKind};
+use enarx_exec_wasmtime::{Package, PACKAGE_CONFIG, PACKAGE_ENTRYPOINT, PACKAGE_PROVENANCE};
 use url::Url;
 
 /// Deploy an Enarx package to an Enarx Keep.
@@ -22,6 +29,16 @@ pub struct Options {
     #[clap(flatten)]
     pub backend: BackendOptions,
 
+    #[clap(flatten)]
+    pub secrets: SecretOptions,
+
+    #[clap(flatten)]
+    pub shims: ShimOptions,
+
+    #[cfg(unix)]
+    #[clap(flatten)]
+    pub cache: CacheOptions,
+
     /// Package slug or a URL to deploy.
     #[clap(value_name = "PACKAGE")]
     pub package: String,
@@ -34,6 +51,11 @@ pub struct Options {
     #[clap(long, value_name = "SIGNATURES")]
     pub signatures: Option<Utf8PathBuf>,
 
+    /// Network namespace, in which the host opens the sockets of the Keep, e.g. `/run/netns/NAME`
+    #[cfg(target_os = "linux")]
+    #[clap(long, env = "ENARX_NETNS", value_name = "PATH")]
+    pub netns: Option<Utf8PathBuf>,
+
     /// gdb options
     #[cfg(feature = "gdb")]
     #[clap(long, default_value = "localhost:23456")]
@@ -44,21 +66,34 @@ impl Options {
     pub fn execute(self) -> anyhow::Result<()> {
         let Self {
             backend,
+            secrets,
+            shims,
+            #[cfg(unix)]
+            cache,
             package,
             unsigned,
             signatures,
+            #[cfg(target_os = "linux")]
+            netns,
             #[cfg(feature = "gdb")]
             gdblisten,
         } = self;
 
         let backend = backend.pick()?;
+        let secrets = secrets.resolve()?;
         // TODO: Only allow secure backends
         // https://github.com/enarx/enarx/issues/1850
         let exec = EXECS
             .iter()
             .find(|w| w.with_backend(backend))
             .ok_or_else(|| anyhow!("no supported exec found"))
-            .map(|b| b.exec())?;
+            .classify(ErrorKind::Platform)?;
+        let (shim, binary) = shims.load(backend, &**exec)?;
+
+        #[cfg(target_os = "linux")]
+        if let Some(netns) = &netns {
+            host::enter_netns(netns.as_ref())?;
+        }
 
         #[cfg(not(feature = "gdb"))]
         let gdblisten = None;
@@ -69,7 +104,7 @@ impl Options {
         let signatures = if unsigned {
             None
         } else {
-            Signatures::load(signatures)?
+            Signatures::load(signatures).classify(ErrorKind::Config)?
         };
 
         let package = match package
@@ -81,7 +116,9 @@ impl Options {
                 use drawbridge_client::API_VERSION;
 
                 let (host, user, repo, tag) = parse_tag(&package)
-                    .with_context(|| format!("failed to parse `{package}` as a Drawbridge slug"))?;
+                    .with_context(|| format!("failed to parse `{package}` as a Drawbridge slug"))
+                    .classify(ErrorKind::Config)?;
+                remember_slug(&package);
                 format!("https://{host}/api/v{API_VERSION}/{user}/{repo}/_tag/{tag}")
                     .parse()
                     .with_context(|| {
@@ -96,15 +133,19 @@ impl Options {
                 let path = package
                     .to_file_path()
                     .map_err(|()| anyhow!("failed to parse file path from URL `{}`", package))?;
-                let md = fs::metadata(&path).with_context(|| {
-                    format!("failed to get information about `{}`", path.display())
-                })?;
-                let (wasm, conf) = if md.is_file() {
-                    (path, None)
+                let md = fs::metadata(&path)
+                    .with_context(|| {
+                        format!("failed to get information about `{}`", path.display())
+                    })
+                    .classify(ErrorKind::Io)?;
+                let (wasm, conf, provenance) = if md.is_file() {
+                    (path, None, None)
                 } else if md.is_dir() {
+                    let provenance = path.join(PACKAGE_PROVENANCE.as_str());
                     (
                         path.join(PACKAGE_ENTRYPOINT.as_str()),
                         Some(path.join(PACKAGE_CONFIG.as_str())),
+                        Some(provenance).filter(|provenance| provenance.exists()),
                     )
                 } else {
                     bail!(
@@ -114,28 +155,65 @@ impl Options {
                 };
 
                 let get_pkg = || {
-                    let (wasm, conf) = open_package(wasm, conf)?;
+                    #[cfg_attr(windows, allow(unused_mut))]
+                    let (mut wasm, mut conf) = open_package(wasm, conf)?;
+                    let provenance = open_provenance(provenance)?;
+                    let config = read_config(conf.as_mut())?;
+                    let datasets = open_datasets(config.as_ref())?;
+                    let channel = open_channel(config.as_ref())?;
 
                     #[cfg(unix)]
                     let pkg = Package::Local {
+                        cache: cache
+                            .artifact(backend, &**exec, &mut wasm)?
+                            .map(|cache| cache.into_raw_fd()),
                         wasm: wasm.into_raw_fd(),
                         conf: conf.map(|conf| conf.into_raw_fd()),
+                        provenance: provenance.map(|provenance| provenance.into_raw_fd()),
+                        sidecar: None,
+                        datasets: datasets
+                            .into_iter()
+                            .map(|(path, dataset)| (path, dataset.into_raw_fd()))
+                            .collect(),
+                        tap: None,
+                        channel: channel.map(|channel| channel.into_raw_fd()),
+                        offload: Default::default(),
+                        helpers: Default::default(),
                     };
 
                     #[cfg(windows)]
-                    let pkg = Package::Local { wasm, conf };
+                    let pkg = Package::Local {
+                        wasm,
+                        conf,
+                        cache: None,
+                        provenance,
+                        sidecar: None,
+                        datasets,
+                        tap: None,
+                        channel,
+                        offload: Default::default(),
+                        helpers: Default::default(),
+                    };
 
                     Ok(pkg)
                 };
 
-                run_package(backend, exec, signatures, gdblisten, get_pkg)?
+                run_package(
+                    backend, shim, binary, signatures, gdblisten, get_pkg, secrets,
+                )?
             }
 
             // The WASM module and config will be downloaded from a remote by exec-wasmtime
             // TODO: Disallow `http` or guard by an `--insecure` flag
-            "http" | "https" => run_package(backend, exec, signatures, gdblisten, || {
-                Ok(Package::Remote(package))
-            })?,
+            "http" | "https" => run_package(
+                backend,
+                shim,
+                binary,
+                signatures,
+                gdblisten,
+                || Ok(Package::Remote(package)),
+                secrets,
+            )?,
 
             s => bail!("unsupported scheme: {}", s),
         };
@@ -0,0 +1,165 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::backend::{Backend, Datum, BACKENDS};
+
+use std::fmt::{self, Formatter};
+use std::ops::Deref;
+
+use anyhow::anyhow;
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+use serde::Serialize;
+
+/// Diagnose common setup failures of the Keep backends and suggest fixes
+///
+/// Exits with an error, if no hardware-backed Keep backend is usable.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// Only diagnose the given backend
+    #[clap(long)]
+    backend: Option<String>,
+
+    #[clap(short, long)]
+    /// Emit JSON rather than human-readable output
+    json: bool,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let reports: Vec<_> = BACKENDS
+            .deref()
+            .iter()
+            .map(|b| &**b)
+            // The nil backend does not need any setup
+            .filter(|b| b.name() != "nil")
+            .filter(|b| {
+                self.backend
+                    .as_deref()
+                    .map_or(true, |name| b.name() == name)
+            })
+            .map(Report::new)
+            .collect();
+
+        if reports.is_empty() {
+            return Err(anyhow!(
+                "Keep backend identifier {:?} is unknown.",
+                self.backend.unwrap()
+            ))
+            .classify(ErrorKind::Config);
+        }
+
+        let usable = reports.iter().any(|r| r.usable);
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        } else {
+            for report in &reports {
+                print!("{}", report);
+            }
+        }
+
+        if !usable {
+            return Err(anyhow!(
+                "No usable Keep backend found. Apply the suggested fixes and run `enarx doctor` again."
+            ))
+            .classify(ErrorKind::Platform);
+        }
+        Ok(())
+    }
+}
+
+/// The diagnosis of a backend
+#[derive(Serialize)]
+struct Report {
+    backend: &'static str,
+    usable: bool,
+    /// Whether the platform supports the backend at all
+    supported: bool,
+    problems: Vec<Datum>,
+}
+
+impl Report {
+    fn new(backend: &dyn Backend) -> Self {
+        let data = backend.data();
+        let supported = backend.have();
+
+        // Without platform support, the configuration of the backend is irrelevant and
+        // only the actionable problems or the first one are of interest
+        let problems: Vec<_> = if supported {
+            data.into_iter()
+                .chain(backend.config())
+                .chain(backend.doctor())
+                .filter(|d| !d.pass)
+                .collect()
+        } else {
+            let mut failed: Vec<_> = data.into_iter().filter(|d| !d.pass).collect();
+            if failed.iter().any(|d| d.mesg.is_some()) {
+                failed.retain(|d| d.mesg.is_some());
+            } else {
+                failed.truncate(1);
+            }
+            failed
+        };
+
+        Self {
+            backend: backend.name(),
+            usable: supported && problems.is_empty(),
+            supported,
+            problems,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use colorful::*;
+
+        let is_atty = atty::is(atty::Stream::Stdout);
+        let icon = match (is_atty, self.usable) {
+            (true, true) => "✔".green().to_string(),
+            (true, false) => "✗".red().to_string(),
+            (false, true) => "✔".into(),
+            (false, false) => "✗".into(),
+        };
+
+        write!(f, "{} Backend: {}", icon, self.backend)?;
+        match (self.supported, self.problems.len()) {
+            (true, 0) => writeln!(f, ": no problems found")?,
+            (true, n) => writeln!(f, ": {n} problem{} found", if n == 1 { "" } else { "s" })?,
+            (false, _) => writeln!(f, ": not supported by this machine")?,
+        }
+
+        for problem in &self.problems {
+            write!(f, "  - {}", problem.name.trim())?;
+            if let Some(ref info) = problem.info {
+                write!(f, ": {}", info)?;
+            }
+            writeln!(f)?;
+            if let Some(ref mesg) = problem.mesg {
+                writeln!(f, "    Fix: {}", mesg)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_doctor() {
+    // The result depends on the machine, but the diagnosis must not fail
+    let _ = Options {
+        backend: None,
+        json: true,
+    }
+    .execute();
+    let _ = Options {
+        backend: None,
+        json: false,
+    }
+    .execute();
+
+    assert!(Options {
+        backend: Some("unknown".into()),
+        json: false,
+    }
+    .execute()
+    .is_err());
+}
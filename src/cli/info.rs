@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::control;
+
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+
+/// Show the details of a running Keep
+#[derive(Args, Debug)]
+pub struct Options {
+    #[clap(short, long)]
+    /// Emit JSON rather than human-readable output
+    json: bool,
+
+    /// ID of the Keep as listed by `enarx ps`
+    #[clap(value_name = "KEEP")]
+    id: u32,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let info = control::info(&control::dir(), self.id).classify(ErrorKind::Io)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+        } else {
+            println!("ID:       {}", info.id);
+            println!("Backend:  {}", info.backend);
+            println!("Workload: {}", info.workload);
+            println!("Started:  {} (Unix time)", info.started);
+            println!("Uptime:   {}s", info.uptime);
+            if let Some(threads) = info.threads {
+                println!("Threads:  {threads}");
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::{self, OpenOptions};
+use std::io::prelude::*;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use anyhow::{anyhow, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args, ValueEnum};
+use enarx_error::{Classify, ErrorKind};
+
+/// Files of every project
+const COMMON: &[(&str, &str)] = &[
+    ("Enarx.toml", include_str!("templates/Enarx.toml")),
+    ("README.md", include_str!("templates/README.md")),
+];
+
+/// Usage of an application greeting on its listen socket
+const USAGE_LISTEN: &str = r#"Then greet the application:
+
+```sh
+echo "Enarx" | nc localhost 8080
+```"#;
+
+/// Usage of an application greeting on stdout
+const USAGE_STDIO: &str = r#"The application greets the name read from stdin:
+
+```sh
+echo "Enarx" | enarx run --wasmcfgfile Enarx.toml main.wasm
+```"#;
+
+/// Language of a project template
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Lang {
+    /// Rust, built with a nightly toolchain for `wasm32-wasi`
+    Rust,
+    /// Go 1.21 or later, built for `wasip1`
+    Go,
+    /// JavaScript, built with Javy
+    Js,
+    /// Python, built with py2wasm
+    Python,
+}
+
+impl Lang {
+    /// Returns the paths and contents of the language-specific files
+    fn files(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Rust => &[
+                ("Cargo.toml", include_str!("templates/rust/Cargo.toml.in")),
+                (
+                    "rust-toolchain.toml",
+                    include_str!("templates/rust/rust-toolchain.toml"),
+                ),
+                ("src/main.rs", include_str!("templates/rust/src/main.rs")),
+                ("build.sh", include_str!("templates/rust/build.sh")),
+                (".gitignore", include_str!("templates/rust/gitignore")),
+            ],
+            Self::Go => &[
+                ("go.mod", include_str!("templates/go/go.mod")),
+                ("main.go", include_str!("templates/go/main.go")),
+                ("build.sh", include_str!("templates/go/build.sh")),
+                (".gitignore", include_str!("templates/go/gitignore")),
+            ],
+            Self::Js => &[
+                ("index.js", include_str!("templates/js/index.js")),
+                ("build.sh", include_str!("templates/js/build.sh")),
+                (".gitignore", include_str!("templates/js/gitignore")),
+            ],
+            Self::Python => &[
+                ("main.py", include_str!("templates/python/main.py")),
+                ("build.sh", include_str!("templates/python/build.sh")),
+                (".gitignore", include_str!("templates/python/gitignore")),
+            ],
+        }
+    }
+
+    fn usage(self) -> &'static str {
+        match self {
+            // Javy does not provide sockets
+            Self::Js => USAGE_STDIO,
+            _ => USAGE_LISTEN,
+        }
+    }
+}
+
+/// Create a new project of a WebAssembly application for an Enarx Keep
+///
+/// The project consists of a minimal application, a `build.sh` script building `main.wasm`
+/// and an `Enarx.toml` with stdio and a listen socket on port 8080.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// Language of the project
+    #[clap(long, value_enum, default_value = "rust")]
+    lang: Lang,
+
+    /// Directory of the project, which is created, if it does not exist
+    #[clap(value_name = "DIR", default_value = ".")]
+    path: Utf8PathBuf,
+}
+
+/// Derives a package name, which is valid for all languages, from the directory `path`
+fn project_name(path: &Utf8Path) -> anyhow::Result<String> {
+    let path = path
+        .canonicalize_utf8()
+        .with_context(|| format!("failed to resolve `{path}`"))?;
+    let name: String = path
+        .file_name()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9') => c,
+            _ => '-',
+        })
+        .collect();
+    let name = name.trim_matches('-');
+
+    Ok(match name.chars().next() {
+        None => "app".into(),
+        Some('0'..='9') => format!("app-{name}"),
+        Some(_) => name.into(),
+    })
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let Self { lang, path } = self;
+
+        fs::create_dir_all(&path)
+            .with_context(|| format!("failed to create `{path}`"))
+            .classify(ErrorKind::Io)?;
+        let name = project_name(&path).classify(ErrorKind::Io)?;
+
+        let files: Vec<_> = COMMON.iter().chain(lang.files()).collect();
+        if let Some((file, _)) = files.iter().find(|(file, _)| path.join(file).exists()) {
+            return Err(anyhow!("`{}` does already exist.", path.join(file)))
+                .classify(ErrorKind::Config);
+        }
+
+        for (file, template) in files {
+            let file = path.join(file);
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create `{dir}`"))
+                    .classify(ErrorKind::Io)?;
+            }
+
+            let mut options = OpenOptions::new();
+            options.create_new(true).write(true);
+            #[cfg(unix)]
+            if file.extension() == Some("sh") {
+                options.mode(0o755);
+            }
+
+            let content = template
+                .replace("{{name}}", &name)
+                .replace("{{usage}}", lang.usage());
+            options
+                .open(&file)
+                .and_then(|mut f| f.write_all(content.as_bytes()))
+                .with_context(|| format!("failed to write `{file}`"))
+                .classify(ErrorKind::Io)?;
+        }
+
+        println!(
+            "Created {lang:?} project `{name}` in `{path}`, see its README.md to get started."
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use enarx_config::Config;
+
+    #[test]
+    fn init() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(tmp.path()).unwrap();
+
+        for lang in Lang::value_variants() {
+            let path = root.join(format!("My {lang:?} App"));
+            Options {
+                lang: *lang,
+                path: path.clone(),
+            }
+            .execute()
+            .unwrap();
+
+            for (file, _) in COMMON.iter().chain(lang.files()) {
+                let content = fs::read_to_string(path.join(file)).unwrap();
+                assert!(!content.contains("{{"), "{file} is not rendered");
+            }
+            let conf = fs::read_to_string(path.join("Enarx.toml")).unwrap();
+            let conf: Config = toml::from_str(&conf).unwrap();
+            assert_eq!(conf.files.len(), 4);
+
+            // Existing projects are not overwritten
+            assert!(Options { lang: *lang, path }.execute().is_err());
+        }
+
+        let cargo = fs::read_to_string(root.join("My Rust App/Cargo.toml")).unwrap();
+        assert!(cargo.contains(r#"name = "my-rust-app""#));
+    }
+
+    #[test]
+    fn name() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(tmp.path()).unwrap();
+        for (dir, name) in [
+            ("hello_world", "hello-world"),
+            ("42", "app-42"),
+            ("--", "app"),
+        ] {
+            fs::create_dir(root.join(dir)).unwrap();
+            assert_eq!(project_name(&root.join(dir)).unwrap(), name);
+        }
+    }
+}
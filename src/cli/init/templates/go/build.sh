@@ -0,0 +1,3 @@
+#!/bin/sh
+set -e
+GOOS=wasip1 GOARCH=wasm go build -o main.wasm .
@@ -0,0 +1,30 @@
+package main
+
+import (
+	"bufio"
+	"fmt"
+	"net"
+	"os"
+	"strings"
+)
+
+func main() {
+	// The `ingest` socket of the Enarx.toml follows stdin, stdout and stderr
+	listener, err := net.FileListener(os.NewFile(3, "ingest"))
+	if err != nil {
+		fmt.Fprintln(os.Stderr, err)
+		os.Exit(1)
+	}
+	fmt.Println("Listening on port 8080")
+
+	for {
+		conn, err := listener.Accept()
+		if err != nil {
+			fmt.Fprintln(os.Stderr, err)
+			os.Exit(1)
+		}
+		name, _ := bufio.NewReader(conn).ReadString('\n')
+		fmt.Fprintf(conn, "Hello, %s!\n", strings.TrimSpace(name))
+		conn.Close()
+	}
+}
@@ -0,0 +1,3 @@
+#!/bin/sh
+set -e
+javy compile index.js -o main.wasm
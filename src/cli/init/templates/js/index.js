@@ -0,0 +1,17 @@
+// Javy only provides stdio, so the application greets on stdout until it can use the
+// `ingest` socket of the Enarx.toml.
+function readStdin() {
+  const chunks = [];
+  for (;;) {
+    const chunk = new Uint8Array(1024);
+    const n = Javy.IO.readSync(0, chunk);
+    if (n === 0) {
+      break;
+    }
+    chunks.push(...chunk.subarray(0, n));
+  }
+  return new TextDecoder().decode(new Uint8Array(chunks));
+}
+
+const name = readStdin().trim() || "{{name}}";
+Javy.IO.writeSync(1, new TextEncoder().encode(`Hello, ${name}!\n`));
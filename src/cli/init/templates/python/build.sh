@@ -0,0 +1,3 @@
+#!/bin/sh
+set -e
+py2wasm main.py -o main.wasm
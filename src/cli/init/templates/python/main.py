@@ -0,0 +1,11 @@
+import socket
+
+# The `ingest` socket of the Enarx.toml follows stdin, stdout and stderr
+listener = socket.socket(fileno=3)
+print("Listening on port 8080", flush=True)
+
+while True:
+    conn, _ = listener.accept()
+    with conn:
+        name = conn.makefile().readline().strip()
+        conn.sendall(f"Hello, {name}!\n".encode())
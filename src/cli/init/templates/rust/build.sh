@@ -0,0 +1,4 @@
+#!/bin/sh
+set -e
+cargo build --release --target wasm32-wasi
+cp target/wasm32-wasi/release/{{name}}.wasm main.wasm
@@ -0,0 +1,19 @@
+#![feature(wasi_ext)]
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::os::wasi::io::FromRawFd;
+
+fn main() -> io::Result<()> {
+    // The `ingest` socket of the Enarx.toml follows stdin, stdout and stderr
+    let listener = unsafe { TcpListener::from_raw_fd(3) };
+    println!("Listening on port 8080");
+
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let mut name = String::new();
+        BufReader::new(&stream).read_line(&mut name)?;
+        writeln!(stream, "Hello, {}!", name.trim())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::control;
+
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+
+/// Request a running Keep to shut down
+///
+/// The launcher of the Keep exits with code 143, as if terminated by `SIGTERM`.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// ID of the Keep as listed by `enarx ps`
+    #[clap(value_name = "KEEP")]
+    id: u32,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        control::stop(&control::dir(), self.id).classify(ErrorKind::Io)
+    }
+}
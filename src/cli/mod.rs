@@ -1,26 +1,48 @@
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(unix)]
+mod cache;
+mod complete;
 mod config;
+mod dataset;
 mod deploy;
+mod doctor;
+#[cfg(unix)]
+mod info;
+mod init;
 #[cfg(enarx_with_shim)]
 mod key;
+#[cfg(unix)]
+mod kill;
 mod package;
 mod platform;
+mod precompile;
+#[cfg(unix)]
+mod ps;
 mod repo;
 mod run;
+mod schedule;
+mod shims;
 #[cfg(enarx_with_shim)]
 mod sign;
 mod tree;
 mod unstable;
 mod user;
 
+#[cfg(unix)]
+pub use cache::CacheOptions;
+pub use shims::ShimOptions;
+
 use crate::backend::{Backend, BACKENDS};
+use crate::secret::{self, SecretSpec};
 
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::str::FromStr;
 
 use anyhow::{anyhow, bail};
 use clap::{ArgAction, Args, Parser, Subcommand};
+use enarx_error::{Classify, ErrorKind};
 use tracing::info;
 use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*, EnvFilter};
@@ -34,17 +56,35 @@ use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*, EnvFilter};
 /// For more information about the project and the technology used
 /// visit the Enarx Project home page https://enarx.dev/.
 #[derive(Parser, Debug)]
-#[clap(version)]
+#[clap(version, after_help = EXIT_CODES)]
 pub struct Options {
     /// Logging options
     #[clap(flatten)]
     logger: LogOptions,
 
+    /// Print errors as a JSON object on stderr
+    #[clap(long, global = true)]
+    pub json: bool,
+
     /// Subcommands (with their own options)
     #[clap(subcommand)]
     cmd: Subcommands,
 }
 
+/// Exit codes of `enarx`, which are documented in the help output
+const EXIT_CODES: &str = "\
+Exit codes:
+  0   Success
+  1   Unclassified error
+  69  Platform unsupported
+  70  Workload trap
+  74  Host I/O error
+  75  TEE resources exhausted, retry later
+  77  Attestation failure
+  78  Configuration error
+
+Otherwise, the exit code of the workload is returned.";
+
 impl Options {
     pub fn execute(self) -> anyhow::Result<()> {
         self.logger.init();
@@ -60,14 +100,29 @@ impl Options {
 #[derive(Subcommand, Debug)]
 enum Subcommands {
     Run(run::Options),
+    BuildShims(shims::Options),
     Deploy(deploy::Options),
+    Doctor(doctor::Options),
+    Init(init::Options),
+    #[cfg(unix)]
+    Ps(ps::Options),
+    #[cfg(unix)]
+    Info(info::Options),
+    #[cfg(unix)]
+    Kill(kill::Options),
     #[clap(subcommand)]
     Config(config::Subcommands),
+    Completions(complete::CompletionsOptions),
+    #[clap(name = "__complete", hide = true)]
+    Complete(complete::Options),
+    Dataset(dataset::Options),
     #[cfg(enarx_with_shim)]
     #[clap(subcommand)]
     Key(key::Subcommands),
     #[clap(subcommand)]
     Platform(platform::Subcommands),
+    Precompile(precompile::Options),
+    Schedule(schedule::Options),
     #[clap(subcommand)]
     Package(package::Subcommands),
     #[clap(subcommand)]
@@ -87,11 +142,25 @@ impl Subcommands {
     fn dispatch(self) -> anyhow::Result<()> {
         match self {
             Self::Run(cmd) => cmd.execute(),
+            Self::BuildShims(cmd) => cmd.execute(),
             Self::Config(subcmd) => subcmd.dispatch(),
+            Self::Completions(cmd) => cmd.execute(),
+            Self::Complete(cmd) => cmd.execute(),
+            Self::Dataset(cmd) => cmd.execute(),
             Self::Deploy(cmd) => cmd.execute(),
+            Self::Doctor(cmd) => cmd.execute(),
+            Self::Init(cmd) => cmd.execute(),
+            #[cfg(unix)]
+            Self::Ps(cmd) => cmd.execute(),
+            #[cfg(unix)]
+            Self::Info(cmd) => cmd.execute(),
+            #[cfg(unix)]
+            Self::Kill(cmd) => cmd.execute(),
             #[cfg(enarx_with_shim)]
             Self::Key(subcmd) => subcmd.dispatch(),
             Self::Platform(subcmd) => subcmd.dispatch(),
+            Self::Precompile(cmd) => cmd.execute(),
+            Self::Schedule(cmd) => cmd.execute(),
             Self::Package(subcmd) => subcmd.dispatch(),
             Self::Repo(subcmd) => subcmd.dispatch(),
             #[cfg(enarx_with_shim)]
@@ -115,6 +184,10 @@ pub struct BackendOptions {
 
 impl BackendOptions {
     pub fn pick(&self) -> anyhow::Result<&dyn Backend> {
+        self.find().classify(ErrorKind::Platform)
+    }
+
+    fn find(&self) -> anyhow::Result<&dyn Backend> {
         if let Some(ref name) = self.backend {
             match BACKENDS.deref().iter().find(|b| b.name() == name) {
                 None => {
@@ -141,6 +214,25 @@ impl BackendOptions {
     }
 }
 
+/// Common secret injection options
+#[derive(Args, Debug)]
+pub struct SecretOptions {
+    /// Secret to resolve on the host and provide to the application as an environment variable.
+    ///
+    /// Specified as `NAME=URI`, where URI is one of `env://VARIABLE`, `file:///path`,
+    /// `vault://mount/path#key` or `aws://secret-id[#key]`. May be passed multiple times.
+    /// The value is passed to the Keep in plaintext, so the host can read it.
+    #[clap(long = "secret", value_name = "NAME=URI")]
+    secrets: Vec<SecretSpec>,
+}
+
+impl SecretOptions {
+    /// Resolve the secrets to be forwarded to the Keep.
+    pub fn resolve(&self) -> anyhow::Result<HashMap<String, String>> {
+        secret::resolve(&self.secrets).classify(ErrorKind::Io)
+    }
+}
+
 /// Common logging / output options
 #[derive(Args, Debug)]
 pub struct LogOptions {
@@ -3,11 +3,15 @@
 use crate::drawbridge::{client, TagSpec};
 
 use std::ffi::OsString;
-use std::fs::read_dir;
+use std::fs::{self, read_dir};
+use std::io::BufReader;
 
 use anyhow::{bail, Context};
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::Args;
+use enarx_exec_wasmtime::{
+    Provenance, Statement, PACKAGE_CONFIG, PACKAGE_ENTRYPOINT, PACKAGE_PROVENANCE,
+};
 use oauth2::url::Url;
 
 /// Publish a new package.
@@ -21,6 +25,18 @@ pub struct Options {
     insecure_auth_token: Option<String>,
     #[clap(long, env = "ENARX_CREDENTIAL_HELPER")]
     credential_helper: Option<OsString>,
+    /// Path of the ECDSA P-256 PKCS#8 key to sign the build provenance of the package with
+    #[clap(long, requires_all = ["builder", "repository", "commit"])]
+    provenance_key: Option<Utf8PathBuf>,
+    /// Identity of the builder recorded in the build provenance, e.g. the URL of a CI workflow
+    #[clap(long, requires = "provenance_key")]
+    builder: Option<String>,
+    /// Source repository recorded in the build provenance
+    #[clap(long, requires = "provenance_key")]
+    repository: Option<String>,
+    /// Source commit recorded in the build provenance
+    #[clap(long, requires = "provenance_key")]
+    commit: Option<String>,
     spec: TagSpec,
     path: Utf8PathBuf,
 }
@@ -46,7 +62,9 @@ impl Options {
                 let path = entry?.path();
                 if path.is_file() {
                     path.file_name()
-                        .filter(|&name| name == "main.wasm" || name == "Enarx.toml")
+                        .filter(|&name| {
+                            name == "main.wasm" || name == "Enarx.toml" || name == "provenance.json"
+                        })
                         .with_context(|| format!("Invalid file name: {}", path.display()))?;
                 } else {
                     bail!("Publishing nested directories is not supported")
@@ -55,10 +73,65 @@ impl Options {
         }
 
         let tag = cl.tag(&self.spec.ctx);
-        let (_tag_created, _tree_created) = tag
-            .create_from_path_unsigned(self.path)
-            .context("Failed to create tag and upload tree")?;
+        let key = match self.provenance_key {
+            Some(ref key) => key,
+            None => {
+                let (_tag_created, _tree_created) = tag
+                    .create_from_path_unsigned(self.path)
+                    .context("Failed to create tag and upload tree")?;
+                return Ok(());
+            }
+        };
+
+        // Publish a copy of the package with the signed build provenance attached
+        let statement = Statement::new(
+            self.builder.unwrap_or_default(),
+            self.repository.unwrap_or_default(),
+            self.commit.unwrap_or_default(),
+            &fs::read(entrypoint(&self.path))
+                .with_context(|| format!("Failed to read `{}`", entrypoint(&self.path)))?,
+        );
+        let provenance = Provenance::sign(&statement, &read_key(key)?)
+            .context("Failed to sign build provenance")?;
+
+        let dir = std::env::temp_dir().join(format!("enarx-publish-{}", std::process::id()));
+        let dir = Utf8PathBuf::try_from(dir).context("Invalid temporary directory")?;
+        let res = stage(&self.path, &dir, &provenance).and_then(|()| {
+            tag.create_from_path_unsigned(&dir)
+                .context("Failed to create tag and upload tree")
+        });
+        let _ = fs::remove_dir_all(&dir);
+        res.map(|_| ())
+    }
+}
+
+/// Returns the path of the Wasm module of the package at `path`
+fn entrypoint(path: &Utf8Path) -> Utf8PathBuf {
+    if path.is_file() {
+        path.to_owned()
+    } else {
+        path.join(PACKAGE_ENTRYPOINT.as_str())
+    }
+}
+
+/// Reads the PKCS#8 key at `path` in PEM or DER encoding
+fn read_key(path: &Utf8Path) -> anyhow::Result<Vec<u8>> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read `{path}`"))?;
+    let pem = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(bytes.as_slice()))
+        .with_context(|| format!("Failed to decode `{path}`"))?;
+    Ok(pem.into_iter().next().unwrap_or(bytes))
+}
 
-        Ok(())
+/// Copies the package at `path` into `dir` with `provenance` attached
+fn stage(path: &Utf8Path, dir: &Utf8Path, provenance: &Provenance) -> anyhow::Result<()> {
+    fs::create_dir(dir).with_context(|| format!("Failed to create `{dir}`"))?;
+    fs::copy(entrypoint(path), dir.join(PACKAGE_ENTRYPOINT.as_str()))
+        .context("Failed to copy Wasm module")?;
+    let config = path.join(PACKAGE_CONFIG.as_str());
+    if path.is_dir() && config.is_file() {
+        fs::copy(config, dir.join(PACKAGE_CONFIG.as_str())).context("Failed to copy config")?;
     }
+    let provenance = serde_json::to_vec(provenance).context("Failed to encode provenance")?;
+    fs::write(dir.join(PACKAGE_PROVENANCE.as_str()), provenance)
+        .context("Failed to write provenance")
 }
@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::cli::BackendOptions;
+use crate::exec::EXECS;
+
+use std::fs;
+
+use anyhow::{anyhow, Context};
+use camino::Utf8PathBuf;
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+use enarx_exec_wasmtime::precompile;
+use sha2::{Digest, Sha256};
+
+/// Compile a WebAssembly module ahead of time for faster Keep startup
+///
+/// Prints the digest of the artifact, which must be added to the `precompiled` section
+/// of the Enarx.toml for Keeps to use the artifact passed with `enarx run --precompiled`.
+/// The artifact is only used by Keeps on CPUs with the same features as this host.
+#[derive(Args, Debug)]
+pub struct Options {
+    #[clap(flatten)]
+    pub backend: BackendOptions,
+
+    /// Path of the compiled artifact
+    #[clap(short, long, value_name = "ARTIFACT")]
+    pub output: Utf8PathBuf,
+
+    /// Path of the WebAssembly module to compile
+    #[clap(value_name = "MODULE")]
+    pub module: Utf8PathBuf,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let backend = self.backend.pick()?;
+        let exec = EXECS
+            .iter()
+            .find(|w| w.with_backend(backend))
+            .ok_or_else(|| anyhow!("no supported exec found"))
+            .classify(ErrorKind::Platform)?;
+
+        let webasm = fs::read(&self.module)
+            .with_context(|| format!("failed to read WASM module at `{}`", self.module))
+            .classify(ErrorKind::Io)?;
+        let artifact = precompile(&webasm, exec.target()).classify(ErrorKind::Config)?;
+        fs::write(&self.output, &artifact)
+            .with_context(|| format!("failed to write artifact to `{}`", self.output))
+            .classify(ErrorKind::Io)?;
+
+        println!("sha256:{}", hex::encode(Sha256::digest(&artifact)));
+        Ok(())
+    }
+}
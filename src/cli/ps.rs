@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::control::{self, Info};
+
+use std::fmt::{self, Formatter};
+
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+
+/// List the running Keeps of the current user
+#[derive(Args, Debug)]
+pub struct Options {
+    #[clap(short, long)]
+    /// Emit JSON rather than human-readable output
+    json: bool,
+}
+
+/// A line of the Keep table
+struct Row<'a>(&'a Info);
+
+impl fmt::Display for Row<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Info {
+            id,
+            backend,
+            workload,
+            uptime,
+            threads,
+            ..
+        } = self.0;
+        let threads = threads.map_or_else(|| "-".into(), |threads| threads.to_string());
+        write!(
+            f,
+            "{id:<10} {backend:<8} {uptime:>9}s {threads:>7}  {workload}"
+        )
+    }
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let keeps = control::list(&control::dir()).classify(ErrorKind::Io)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&keeps)?);
+        } else {
+            println!(
+                "{:<10} {:<8} {:>10} {:>7}  WORKLOAD",
+                "ID", "BACKEND", "UPTIME", "THREADS"
+            );
+            for keep in &keeps {
+                println!("{}", Row(keep));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+// SPDX-License-Identifier: Apache-2.0
+
+mod watch;
+
+use crate::backend::Signatures;
+#[cfg(unix)]
+use crate::cli::CacheOptions;
+use crate::cli::{BackendOptions, SecretOptions, ShimOptions};
+#[cfg(target_os = "linux")]
+use crate::exec::host;
+#[cfg(unix)]
+use crate::exec::{helper, offload};
+use crate::exec::{
+    open_channel, open_datasets, open_package, open_precompiled, open_provenance, open_sidecar,
+    open_tap, preflight, read_config, run_package, EXECS,
+};
+
+use std::fmt::Debug;
+#[cfg(unix)]
+use std::os::unix::io::IntoRawFd;
+
+use anyhow::{anyhow, Context};
+use camino::Utf8PathBuf;
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+use enarx_exec_wasmtime::Package;
+
+/// Run a WebAssembly module inside an Enarx Keep.
+#[derive(Args, Debug)]
+pub struct Options {
+    #[clap(flatten)]
+    pub backend: BackendOptions,
+
+    #[clap(flatten)]
+    pub secrets: SecretOptions,
+
+    #[clap(flatten)]
+    pub shims: ShimOptions,
+
+    #[cfg(unix)]
+    #[clap(flatten)]
+    pub cache: CacheOptions,
+
+    #[clap(long, env = "ENARX_WASMCFGFILE")]
+    pub wasmcfgfile: Option<Utf8PathBuf>,
+
+    /// Path of the build provenance of the WebAssembly module
+    #[clap(long, value_name = "PROVENANCE")]
+    pub provenance: Option<Utf8PathBuf>,
+
+    /// Path of the artifact of the module compiled by `enarx precompile`
+    ///
+    /// The Keep only uses it instead of compiling the module, if its digest matches the
+    /// `precompiled` section of the package config.
+    #[clap(long, value_name = "ARTIFACT")]
+    pub precompiled: Option<Utf8PathBuf>,
+
+    /// Path of an observer WebAssembly module to run alongside the module, if its config permits it
+    #[clap(long, value_name = "MODULE")]
+    pub sidecar: Option<Utf8PathBuf>,
+
+    /// Path of a pcapng file, to which a Keep allowing debugging mirrors the decrypted data of the
+    /// streams selected by the `[tap]` of the package config
+    #[clap(long, value_name = "PCAPNG")]
+    pub tap: Option<Utf8PathBuf>,
+
+    /// Path of the PEM certificate chain, with which the host terminates TLS of the
+    /// non-confidential `listen` file descriptors of the package config
+    #[cfg(unix)]
+    #[clap(long, value_name = "CERT", requires = "offload_key")]
+    pub offload_cert: Option<Utf8PathBuf>,
+
+    /// Path of the PKCS#8 PEM private key of the certificate of `--offload-cert`
+    #[cfg(unix)]
+    #[clap(long, value_name = "KEY", requires = "offload_cert")]
+    pub offload_key: Option<Utf8PathBuf>,
+
+    /// Path of the WebAssembly module to run
+    #[clap(value_name = "MODULE")]
+    pub module: Utf8PathBuf,
+
+    /// Start an unsigned Keep
+    #[clap(long)]
+    pub unsigned: bool,
+
+    /// Path of the signature file to use.
+    #[clap(long, value_name = "SIGNATURES")]
+    pub signatures: Option<Utf8PathBuf>,
+
+    /// Rebuild and relaunch the Keep, whenever a file below the current directory changes
+    #[clap(long)]
+    pub watch: bool,
+
+    /// Command to rebuild the WebAssembly module before every launch in watch mode
+    #[clap(long, value_name = "COMMAND", requires = "watch")]
+    pub build: Option<String>,
+
+    /// Parent cgroup (v2) of the Keep enforcing the `[limits]` of the package config
+    /// [default: /sys/fs/cgroup/enarx, if it exists]
+    #[cfg(target_os = "linux")]
+    #[clap(long, env = "ENARX_CGROUP", value_name = "DIR")]
+    pub cgroup: Option<Utf8PathBuf>,
+
+    /// Network namespace, in which the host opens the sockets of the Keep, e.g. `/run/netns/NAME`
+    #[cfg(target_os = "linux")]
+    #[clap(long, env = "ENARX_NETNS", value_name = "PATH")]
+    pub netns: Option<Utf8PathBuf>,
+
+    /// gdb options
+    #[cfg(feature = "gdb")]
+    #[clap(long, default_value = "localhost:23456")]
+    pub gdblisten: String,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let Self {
+            backend,
+            secrets,
+            shims,
+            #[cfg(unix)]
+            cache,
+            wasmcfgfile,
+            provenance,
+            precompiled,
+            sidecar,
+            tap,
+            #[cfg(unix)]
+            offload_cert,
+            #[cfg(unix)]
+            offload_key,
+            module,
+            unsigned,
+            signatures,
+            watch,
+            build,
+            #[cfg(target_os = "linux")]
+            cgroup,
+            #[cfg(target_os = "linux")]
+            netns,
+            #[cfg(feature = "gdb")]
+            gdblisten,
+        } = self;
+        if watch {
+            return watch::watch(build.as_deref());
+        }
+
+        let backend = backend.pick()?;
+        let secrets = secrets.resolve()?;
+        let exec = EXECS
+            .iter()
+            .find(|w| w.with_backend(backend))
+            .ok_or_else(|| anyhow!("no supported exec found"))
+            .classify(ErrorKind::Platform)?;
+        let (shim, binary) = shims.load(backend, &**exec)?;
+        let capabilities = backend
+            .capabilities(&shim)
+            .context("failed to determine the capabilities of the backend")
+            .classify(ErrorKind::Platform)?;
+        #[cfg(unix)]
+        let can_offload = offload_cert.is_some();
+        #[cfg(windows)]
+        let can_offload = false;
+
+        #[cfg(target_os = "linux")]
+        if let Some(netns) = &netns {
+            host::enter_netns(netns.as_ref())?;
+        }
+
+        let signatures = if unsigned {
+            None
+        } else {
+            Signatures::load(signatures).classify(ErrorKind::Config)?
+        };
+
+        // Killed after the Keep exited
+        #[cfg(unix)]
+        let mut helpers = None;
+        let get_pkg = || {
+            #[cfg_attr(windows, allow(unused_mut))]
+            let (mut wasm, mut conf) = open_package(module, wasmcfgfile)?;
+            preflight::check(conf.as_mut(), backend, capabilities, can_offload)?;
+            let provenance = open_provenance(provenance)?;
+            let precompiled = open_precompiled(precompiled)?;
+            let sidecar = open_sidecar(sidecar)?;
+            let tap = open_tap(tap)?;
+            let config = read_config(conf.as_mut())?;
+            let datasets = open_datasets(config.as_ref())?;
+            let channel = open_channel(config.as_ref())?;
+            #[cfg(unix)]
+            let offload = offload::open(
+                conf.as_mut(),
+                offload_cert
+                    .as_deref()
+                    .map(|cert| cert.as_std_path())
+                    .zip(offload_key.as_deref().map(|key| key.as_std_path())),
+            )?;
+            #[cfg(unix)]
+            let streams = {
+                let (streams, spawned) = helper::spawn(conf.as_mut())?;
+                helpers = Some(spawned);
+                streams
+            };
+
+            #[cfg(target_os = "linux")]
+            let conf = match conf {
+                Some(mut conf) => {
+                    let limits = host::read_limits(&mut conf)?;
+                    if let Some(size) = limits.memory_size {
+                        backend.set_memory_size(size);
+                    }
+                    host::enter_cgroup(cgroup.as_deref().map(AsRef::as_ref), &limits)?;
+                    Some(conf)
+                }
+                None => None,
+            };
+
+            #[cfg(unix)]
+            let pkg = Package::Local {
+                cache: match precompiled {
+                    Some(precompiled) => Some(precompiled),
+                    None => cache.artifact(backend, &**exec, &mut wasm)?,
+                }
+                .map(|cache| cache.into_raw_fd()),
+                wasm: wasm.into_raw_fd(),
+                conf: conf.map(|conf| conf.into_raw_fd()),
+                provenance: provenance.map(|provenance| provenance.into_raw_fd()),
+                sidecar: sidecar.map(|sidecar| sidecar.into_raw_fd()),
+                datasets: datasets
+                    .into_iter()
+                    .map(|(path, dataset)| (path, dataset.into_raw_fd()))
+                    .collect(),
+                tap: tap.map(|tap| tap.into_raw_fd()),
+                channel: channel.map(|channel| channel.into_raw_fd()),
+                offload: offload
+                    .into_iter()
+                    .map(|(name, listener)| (name, listener.into_raw_fd()))
+                    .collect(),
+                helpers: streams
+                    .into_iter()
+                    .map(|(name, (wasm, stream))| {
+                        (name, (wasm.into_raw_fd(), stream.into_raw_fd()))
+                    })
+                    .collect(),
+            };
+
+            #[cfg(windows)]
+            let pkg = Package::Local {
+                wasm,
+                conf,
+                cache: precompiled,
+                provenance,
+                sidecar,
+                datasets,
+                tap,
+                channel,
+                offload: Default::default(),
+                helpers: Default::default(),
+            };
+
+            Ok(pkg)
+        };
+
+        let code = run_package(
+            backend,
+            shim,
+            binary,
+            signatures,
+            #[cfg(not(feature = "gdb"))]
+            None,
+            #[cfg(feature = "gdb")]
+            Some(gdblisten),
+            get_pkg,
+            secrets,
+        )?;
+        #[cfg(unix)]
+        drop(helpers);
+        std::process::exit(code);
+    }
+}
@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Development loop rebuilding and relaunching the Keep on changes
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, SystemTime};
+use std::{env, fs, thread};
+
+use anyhow::Context;
+use tracing::{info, warn};
+
+/// Interval of polling the files for changes
+const POLL: Duration = Duration::from_millis(500);
+
+/// Directories of build artifacts and dependencies, which are not watched
+const IGNORED: &[&str] = &["target", "node_modules", "__pycache__"];
+
+/// Modification times of the watched files
+type Snapshot = BTreeMap<PathBuf, SystemTime>;
+
+/// Records the modification times of the files below `dir`, skipping hidden files
+fn snapshot(dir: &Path, files: &mut Snapshot) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || IGNORED.contains(&&*name) {
+            continue;
+        }
+        match entry.metadata() {
+            Ok(meta) if meta.is_dir() => snapshot(&entry.path(), files),
+            Ok(meta) => {
+                if let Ok(modified) = meta.modified() {
+                    files.insert(entry.path(), modified);
+                }
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Strips the watch mode options from the arguments of `enarx`
+fn keep_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut args = args.into_iter();
+    let mut stripped = vec![];
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--watch") => {}
+            Some("--build") => {
+                args.next();
+            }
+            Some(arg) if arg.starts_with("--build=") => {}
+            _ => stripped.push(arg),
+        }
+    }
+    stripped
+}
+
+/// Runs the build command `cmd` in a shell and returns, whether it succeeded
+fn build(cmd: &str) -> bool {
+    info!("building with `{cmd}`");
+
+    #[cfg(unix)]
+    let status = Command::new("sh").arg("-c").arg(cmd).status();
+    #[cfg(windows)]
+    let status = Command::new("cmd").arg("/C").arg(cmd).status();
+
+    match status {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            warn!("build failed with {status}, waiting for changes");
+            false
+        }
+        Err(e) => {
+            warn!("failed to run `{cmd}`: {e}");
+            false
+        }
+    }
+}
+
+/// Launches the Keep with the arguments of `enarx` without the watch mode options and
+/// relaunches it, whenever a file below the current directory changes
+///
+/// If `cmd` is set, the module is rebuilt with it before every launch.
+pub fn watch(cmd: Option<&str>) -> anyhow::Result<()> {
+    let exe = env::current_exe().context("failed to locate the enarx executable")?;
+    let args = keep_args(env::args_os().skip(1));
+    let dir = env::current_dir().context("failed to get the current directory")?;
+
+    loop {
+        // The files written by the build are part of the snapshot, so they do not trigger
+        // another relaunch
+        let launch = cmd.map_or(true, build);
+        let mut before = Snapshot::new();
+        snapshot(&dir, &mut before);
+
+        let mut keep: Option<Child> = if launch {
+            info!("launching Keep");
+            Some(
+                Command::new(exe.as_os_str())
+                    .args(&args)
+                    .spawn()
+                    .context("failed to launch Keep")?,
+            )
+        } else {
+            None
+        };
+
+        loop {
+            thread::sleep(POLL);
+
+            if let Some(child) = keep.as_mut() {
+                if let Ok(Some(status)) = child.try_wait() {
+                    info!("Keep exited with {status}, waiting for changes");
+                    keep = None;
+                }
+            }
+
+            let mut now = Snapshot::new();
+            snapshot(&dir, &mut now);
+            if now != before {
+                break;
+            }
+        }
+
+        info!("change detected, relaunching");
+        if let Some(mut child) = keep {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args() {
+        let args = [
+            "run",
+            "--watch",
+            "--build",
+            "./build.sh",
+            "--backend=nil",
+            "--build=make",
+            "main.wasm",
+        ];
+        assert_eq!(
+            keep_args(args.map(OsString::from)),
+            ["run", "--backend=nil", "main.wasm"].map(OsString::from)
+        );
+    }
+
+    #[test]
+    fn changes() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("src")).unwrap();
+        fs::create_dir_all(tmp.path().join("target")).unwrap();
+        fs::write(tmp.path().join("src/main.rs"), "").unwrap();
+        fs::write(tmp.path().join("target/main.wasm"), "").unwrap();
+        fs::write(tmp.path().join(".hidden"), "").unwrap();
+
+        let mut files = Snapshot::new();
+        snapshot(tmp.path(), &mut files);
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            [&tmp.path().join("src/main.rs")]
+        );
+    }
+}
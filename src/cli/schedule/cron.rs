@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cron schedules evaluated in UTC
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context};
+
+/// Seconds of a day
+const DAY: u64 = 24 * 60 * 60;
+
+/// Days searched for the next run, covering the leap day of the next leap year
+const HORIZON: u64 = 8 * 366;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parses the value `s` of a field ranging from `min` to `max` with optional `names` from `min`
+fn value(s: &str, min: u32, max: u32, names: &[&str]) -> anyhow::Result<u32> {
+    let value = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+        Some(i) => i as u32 + min,
+        None => s.parse().with_context(|| format!("invalid value `{s}`"))?,
+    };
+    if value < min || value > max {
+        bail!("value {value} is out of range {min}-{max}");
+    }
+    Ok(value)
+}
+
+/// Parses a field ranging from `min` to `max` into a bitmask of its values
+///
+/// A field is a comma-separated list of `*`, values or ranges `a-b`, each with an optional step
+/// `/n`.
+fn field(s: &str, min: u32, max: u32, names: &[&str]) -> anyhow::Result<u64> {
+    let mut mask = 0;
+    for item in s.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (item, None),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (
+                value(first, min, max, names)?,
+                value(last, min, max, names)?,
+            ),
+            None if step.is_some() => (value(range, min, max, names)?, max),
+            None => {
+                let value = value(range, min, max, names)?;
+                (value, value)
+            }
+        };
+        let step = match step {
+            Some(step) => step
+                .parse()
+                .ok()
+                .filter(|&step: &u32| step > 0)
+                .ok_or_else(|| anyhow!("invalid step `{step}`"))?,
+            None => 1,
+        };
+        if first > last {
+            bail!("invalid range `{range}`");
+        }
+        for value in (first..=last).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+/// Returns the year, month and day of the day `days` since the Unix epoch
+fn civil(days: u64) -> (u64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A cron schedule of five fields, minute, hour, day of month, month and day of week
+///
+/// As with cron, a run is due, if either the day of month or the day of week matches, unless
+/// one of them is `*`. The macros `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are
+/// supported as well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expr = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expr => expr,
+        };
+        let fields: Vec<_> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday]: [&str; 5] = fields
+            .try_into()
+            .map_err(|_| anyhow!("expected 5 fields in schedule `{s}`"))?;
+
+        let parse = |name, s, min, max, names| {
+            field(s, min, max, names).with_context(|| format!("invalid {name} field `{s}`"))
+        };
+        let weekdays = parse("day of week", weekday, 0, 7, &WEEKDAYS)?;
+        let schedule = Self {
+            expr: s.trim().into(),
+            minutes: parse("minute", minute, 0, 59, &[])?,
+            hours: parse("hour", hour, 0, 23, &[])?,
+            days: parse("day of month", day, 1, 31, &[])?,
+            months: parse("month", month, 1, 12, &MONTHS)?,
+            // Sunday is both 0 and 7
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        };
+        if schedule.next(0).is_none() {
+            bail!("schedule `{s}` never runs");
+        }
+        Ok(schedule)
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+impl Schedule {
+    /// Returns, whether a run is due on the day `days` since the Unix epoch
+    fn day(&self, days: u64) -> bool {
+        let (_, month, day) = civil(days);
+        // The Unix epoch was a Thursday
+        let weekday = (days + 4) % 7;
+        let day = self.days & 1 << day != 0;
+        let weekday = self.weekdays & 1 << weekday != 0;
+        let day = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        self.months & 1 << month != 0 && day
+    }
+
+    /// Returns the time of the first run after `after` in seconds since the Unix epoch
+    pub fn next(&self, after: u64) -> Option<u64> {
+        let start = after / 60 + 1;
+        let first = start * 60 / DAY;
+        (first..first + HORIZON)
+            .filter(|&days| self.day(days))
+            .find_map(|days| {
+                let from = if days == first { start % (DAY / 60) } else { 0 };
+                (from..DAY / 60)
+                    .find(|minute| {
+                        self.hours & 1 << (minute / 60) != 0
+                            && self.minutes & 1 << (minute % 60) != 0
+                    })
+                    .map(|minute| days * DAY + minute * 60)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2023-01-01T00:00:00Z, a Sunday
+    const JAN1: u64 = 1672531200;
+
+    fn next(expr: &str, after: u64) -> u64 {
+        expr.parse::<Schedule>().unwrap().next(after).unwrap()
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil(0), (1970, 1, 1));
+        assert_eq!(civil(JAN1 / DAY), (2023, 1, 1));
+        assert_eq!(civil(JAN1 / DAY + 58), (2023, 2, 28));
+        assert_eq!(civil(JAN1 / DAY + 59), (2023, 3, 1));
+        assert_eq!(civil(11016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn next_runs() {
+        assert_eq!(next("* * * * *", JAN1), JAN1 + 60);
+        assert_eq!(next("* * * * *", JAN1 + 59), JAN1 + 60);
+        assert_eq!(next("0 * * * *", JAN1), JAN1 + 3600);
+        assert_eq!(next("@hourly", JAN1 + 1), JAN1 + 3600);
+        assert_eq!(next("*/15 * * * *", JAN1 + 60), JAN1 + 15 * 60);
+        assert_eq!(next("30 2 * * *", JAN1), JAN1 + 2 * 3600 + 30 * 60);
+        assert_eq!(next("0 0 * * *", JAN1), JAN1 + DAY);
+        // Monday
+        assert_eq!(next("0 9 * * mon", JAN1), JAN1 + DAY + 9 * 3600);
+        assert_eq!(next("0 0 * * 7", JAN1), JAN1 + 7 * DAY);
+        assert_eq!(next("0 0 1 feb *", JAN1), JAN1 + 31 * DAY);
+        // either the day of month or the day of week
+        assert_eq!(next("0 0 15 * 1", JAN1), JAN1 + DAY);
+        // the next leap day
+        assert_eq!(next("0 0 29 2 *", JAN1), 1709164800);
+    }
+
+    #[test]
+    fn invalid() {
+        for expr in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "x * * * *",
+            "0 0 31 2 *",
+        ] {
+            assert!(expr.parse::<Schedule>().is_err(), "{expr}");
+        }
+    }
+}
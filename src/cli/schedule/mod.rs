@@ -0,0 +1,263 @@
+// SPDX-License-Identifier: Apache-2.0
+
+mod cron;
+
+use self::cron::Schedule;
+
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{env, thread};
+
+use anyhow::Context;
+use camino::Utf8PathBuf;
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// Launch a fresh Keep on a cron schedule.
+///
+/// The schedule has five fields, minute, hour, day of month, month and day of week, and is
+/// evaluated in UTC, e.g. "0 * * * *" runs at the start of every hour. Every run launches a new
+/// Keep, which attests itself, with `enarx run` for a local WebAssembly module and with
+/// `enarx deploy` otherwise. Runs do not overlap, runs due while the previous one is still
+/// running are skipped. A JSON record of every run is written after it finished.
+///
+/// A Keep, which fails to launch, because the TEE resources of the host, e.g. EPC pages or SEV
+/// ASIDs, are exhausted by other Keeps, is queued and launched again after a delay, which doubles
+/// with every retry.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// Append the records of the runs to this file instead of writing them to stdout
+    #[clap(long, value_name = "FILE")]
+    pub records: Option<Utf8PathBuf>,
+
+    /// Stop after this number of runs instead of running forever
+    #[clap(long, value_name = "N")]
+    pub runs: Option<u64>,
+
+    /// Maximum number of retries of a run, whose Keep found the TEE resources exhausted
+    #[clap(long, default_value_t = 5, value_name = "N")]
+    pub retries: u32,
+
+    /// Delay before the first retry of a run in seconds
+    #[clap(long, default_value_t = 10, value_name = "SECONDS")]
+    pub retry_delay: u64,
+
+    /// Cron schedule in UTC, e.g. "0 * * * *" or "@daily"
+    #[clap(value_name = "SCHEDULE")]
+    pub schedule: Schedule,
+
+    /// Path of a WebAssembly module, package slug or URL to run
+    #[clap(value_name = "WORKLOAD")]
+    pub workload: String,
+
+    /// Options passed to `enarx run` or `enarx deploy`, e.g. `-- --wasmcfgfile Enarx.toml`
+    #[clap(last = true, value_name = "OPTIONS")]
+    pub args: Vec<OsString>,
+}
+
+/// Delay before a retry is capped at this duration
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(600);
+
+/// State of a scheduled run after it finished
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum State {
+    /// The Keep exited
+    Exited,
+    /// The Keep found the TEE resources of the host exhausted on every retry
+    ResourceExhausted,
+    /// The Keep could not be launched
+    Failed,
+}
+
+impl State {
+    /// Returns the state of a run, whose Keep exited with `exit_code`
+    fn of(exit_code: Option<i32>) -> Self {
+        if exit_code == Some(ErrorKind::Resource.exit_code()) {
+            Self::ResourceExhausted
+        } else {
+            Self::Exited
+        }
+    }
+}
+
+/// Returns the delay before the retry `retry` of a run, starting at 1
+fn retry_delay(delay: Duration, retry: u32) -> Duration {
+    delay
+        .checked_mul(1 << (retry - 1).min(16))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+}
+
+/// Record of a scheduled run
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    /// Number of the run, starting at 1
+    run: u64,
+    /// Schedule of the run
+    schedule: String,
+    /// Workload of the run
+    workload: &'a str,
+    /// Time, at which the run was due, in seconds since the Unix epoch
+    scheduled: u64,
+    /// Time, at which the Keep was launched for the last time, in milliseconds since the Unix
+    /// epoch
+    started: u128,
+    /// Time, at which the Keep exited, in milliseconds since the Unix epoch
+    finished: u128,
+    /// State of the run
+    state: State,
+    /// Number of retries of the run due to exhausted TEE resources
+    retries: u32,
+    /// Exit code of the Keep, if it exited normally
+    exit_code: Option<i32>,
+    /// Error launching the Keep
+    error: Option<String>,
+}
+
+/// Returns the time since the Unix epoch
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Returns the `enarx` subcommand launching `workload` with the options `args`
+fn launch_args(workload: &str, args: &[OsString]) -> Vec<OsString> {
+    let cmd = if Path::new(workload).is_file() {
+        "run"
+    } else {
+        "deploy"
+    };
+    let mut launch = vec![cmd.into()];
+    launch.extend(args.iter().cloned());
+    launch.push(workload.into());
+    launch
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let Self {
+            records,
+            runs,
+            retries: max_retries,
+            retry_delay: first_delay,
+            schedule,
+            workload,
+            args,
+        } = self;
+
+        let exe = env::current_exe().context("failed to locate the enarx executable")?;
+        let args = launch_args(&workload, &args);
+        let mut records: Box<dyn Write> = match &records {
+            Some(path) => Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("failed to open records file `{path}`"))
+                    .classify(ErrorKind::Io)?,
+            ),
+            None => Box::new(io::stdout()),
+        };
+
+        for run in 1.. {
+            if runs.map_or(false, |runs| run > runs) {
+                break;
+            }
+
+            // `Schedule::from_str` ensures, that there is a next run
+            let scheduled = schedule.next(now().as_secs()).unwrap();
+            info!("next run of `{workload}` at {scheduled}");
+            let wait = Duration::from_secs(scheduled).saturating_sub(now());
+            thread::sleep(wait);
+
+            let mut retries = 0;
+            let (started, finished, state, exit_code, error) = loop {
+                info!("launching Keep for run {run}");
+                let started = now().as_millis();
+                let status = Command::new(exe.as_os_str()).args(&args).status();
+                let finished = now().as_millis();
+                let (state, exit_code, error) = match status {
+                    Ok(status) => (State::of(status.code()), status.code(), None),
+                    Err(e) => {
+                        warn!("failed to launch Keep: {e}");
+                        (State::Failed, None, Some(e.to_string()))
+                    }
+                };
+                if state != State::ResourceExhausted || retries == max_retries {
+                    break (started, finished, state, exit_code, error);
+                }
+
+                retries += 1;
+                let delay = retry_delay(Duration::from_secs(first_delay), retries);
+                warn!(
+                    "TEE resources exhausted, retry {retries} of {max_retries} of run {run} in {}s",
+                    delay.as_secs()
+                );
+                thread::sleep(delay);
+            };
+
+            let record = Record {
+                run,
+                schedule: schedule.to_string(),
+                workload: &workload,
+                scheduled,
+                started,
+                finished,
+                state,
+                retries,
+                exit_code,
+                error,
+            };
+            writeln!(records, "{}", serde_json::to_string(&record)?)
+                .and_then(|()| records.flush())
+                .context("failed to write run record")
+                .classify(ErrorKind::Io)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launch() {
+        let args = [
+            OsString::from("--wasmcfgfile"),
+            OsString::from("Enarx.toml"),
+        ];
+        let module = env::current_exe().unwrap();
+        let module = module.to_str().unwrap();
+        assert_eq!(
+            launch_args(module, &args),
+            ["run", "--wasmcfgfile", "Enarx.toml", module]
+        );
+        assert_eq!(
+            launch_args("example.com/user/repo:0.1.0", &[]),
+            ["deploy", "example.com/user/repo:0.1.0"]
+        );
+    }
+
+    #[test]
+    fn retry() {
+        assert_eq!(State::of(Some(0)), State::Exited);
+        assert_eq!(State::of(Some(78)), State::Exited);
+        assert_eq!(State::of(None), State::Exited);
+        assert_eq!(State::of(Some(75)), State::ResourceExhausted);
+
+        let delay = Duration::from_secs(10);
+        assert_eq!(retry_delay(delay, 1), delay);
+        assert_eq!(retry_delay(delay, 2), Duration::from_secs(20));
+        assert_eq!(retry_delay(delay, 4), Duration::from_secs(80));
+        assert_eq!(retry_delay(delay, 10), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(delay, u32::MAX), MAX_RETRY_DELAY);
+    }
+}
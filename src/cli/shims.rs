@@ -0,0 +1,345 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Shims and execs built outside of the Enarx build
+//!
+//! The shims and the exec embedded into `enarx` are built with the nightly toolchain pinned by
+//! the Enarx repository. `enarx build-shims` instead compiles them from a source tree with its
+//! own pinned toolchain or fetches the pre-built binaries of a release, and writes them to a
+//! directory, which Keeps are launched with by `--shim-dir`.
+//!
+//! A release provides the binaries together with a `SHA256SUMS` file in the format of
+//! `sha256sum` and its hex-encoded ASN.1 ECDSA P-256 SHA-256 signature `SHA256SUMS.sig`.
+
+use crate::backend::Backend;
+use crate::exec::Exec;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, bail, ensure, Context};
+use camino::Utf8PathBuf;
+use clap::Args;
+use enarx_error::{Classify, ErrorKind};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+use sha2::{Digest, Sha256};
+use tracing::info;
+use url::Url;
+
+/// Name of the checksum file of a release
+const CHECKSUMS: &str = "SHA256SUMS";
+
+/// Name of the signature of the checksum file of a release
+const SIGNATURE: &str = "SHA256SUMS.sig";
+
+/// Maximum size of a fetched file
+const MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+/// The shim and the exec of a Keep
+type Binaries = (Cow<'static, [u8]>, Cow<'static, [u8]>);
+
+/// A binary of a Keep
+#[derive(Debug, PartialEq, Eq)]
+struct Artifact {
+    /// Name of the crate and its binary
+    name: &'static str,
+
+    /// Target triple the binary is compiled for
+    target: &'static str,
+}
+
+const EXEC_WASMTIME: Artifact = Artifact {
+    name: "enarx-exec-wasmtime",
+    target: "x86_64-unknown-linux-musl",
+};
+
+const SHIM_KVM: Artifact = Artifact {
+    name: "enarx-shim-kvm",
+    target: "x86_64-unknown-none",
+};
+
+const SHIM_SGX: Artifact = Artifact {
+    name: "enarx-shim-sgx",
+    target: "x86_64-unknown-none",
+};
+
+/// Returns the shim and the exec of Keeps of the backend `name`
+fn artifacts(name: &str) -> anyhow::Result<[&'static Artifact; 2]> {
+    match name {
+        "sgx" => Ok([&SHIM_SGX, &EXEC_WASMTIME]),
+        // SEV Keeps use the KVM shim
+        "kvm" | "sev" => Ok([&SHIM_KVM, &EXEC_WASMTIME]),
+        _ => bail!("Keep backend {name:?} does not use a shim."),
+    }
+}
+
+/// Parses the file names and SHA-256 digests of a checksum file in the format of `sha256sum`
+fn parse_checksums(checksums: &str) -> anyhow::Result<HashMap<&str, Vec<u8>>> {
+    checksums
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (digest, name) = line
+                .split_once(' ')
+                .with_context(|| format!("invalid checksum line `{line}`"))?;
+            // `sha256sum` marks files read in binary mode with `*`
+            let name = name.trim_start_matches(' ').trim_start_matches('*');
+            let digest =
+                hex::decode(digest).with_context(|| format!("invalid digest of `{name}`"))?;
+            ensure!(digest.len() == 32, "invalid digest of `{name}`");
+            Ok((name, digest))
+        })
+        .collect()
+}
+
+/// Verifies the hex-encoded `signature` of `checksums` by one of the trusted `keys`
+fn verify_checksums(checksums: &[u8], signature: &str, keys: &[String]) -> anyhow::Result<()> {
+    let signature = hex::decode(signature.trim()).context("failed to decode signature")?;
+    for key in keys {
+        let key = hex::decode(key).with_context(|| format!("failed to decode key `{key}`"))?;
+        if UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, key)
+            .verify(checksums, &signature)
+            .is_ok()
+        {
+            return Ok(());
+        }
+    }
+    bail!("`{CHECKSUMS}` is not signed by a trusted key")
+}
+
+/// Downloads the file `name` of the release at `url`
+fn download(url: &Url, name: &str) -> anyhow::Result<Vec<u8>> {
+    let url = url
+        .join(name)
+        .with_context(|| format!("invalid URL of `{name}`"))?;
+    let mut body = vec![];
+    ureq::get(url.as_str())
+        .call()
+        .with_context(|| format!("failed to fetch `{url}`"))?
+        .into_reader()
+        .take(MAX_SIZE)
+        .read_to_end(&mut body)
+        .with_context(|| format!("failed to read `{url}`"))?;
+    Ok(body)
+}
+
+/// Compiles `artifact` in the Enarx source tree `source` and returns the path of the binary
+fn compile(source: &Path, toolchain: Option<&str>, artifact: &Artifact) -> anyhow::Result<PathBuf> {
+    let target_dir = source.join("target");
+
+    let mut cmd = Command::new("cargo");
+    if let Some(toolchain) = toolchain {
+        cmd.arg(format!("+{toolchain}"));
+    }
+    // Building in the source tree picks up its pinned toolchain and cargo configuration
+    cmd.current_dir(source)
+        .args(["build", "--release", "--locked", "-p", artifact.name])
+        .args(["--bin", artifact.name, "--target", artifact.target])
+        .arg("--target-dir")
+        .arg(&target_dir);
+    info!("compiling `{}` with {cmd:?}", artifact.name);
+
+    let status = cmd
+        .status()
+        .context("failed to run `cargo`")
+        .classify(ErrorKind::Io)?;
+    if !status.success() {
+        return Err(anyhow!("failed to compile `{}`: {status}", artifact.name))
+            .classify(ErrorKind::Config);
+    }
+    Ok(target_dir
+        .join(artifact.target)
+        .join("release")
+        .join(artifact.name))
+}
+
+/// Build the shims and the exec of Keeps outside of the Enarx build.
+///
+/// The binaries are compiled from an Enarx source tree with the toolchain pinned by it or
+/// fetched from a release and verified against its signed `SHA256SUMS`. They are written to
+/// a directory, which Keeps are launched with by `enarx run --shim-dir`.
+#[derive(Args, Debug)]
+pub struct Options {
+    /// Keep backend to build the binaries for [default: all]
+    #[clap(long = "target", value_name = "BACKEND", value_parser = ["sgx", "kvm", "sev"])]
+    targets: Vec<String>,
+
+    /// Enarx source tree, e.g. a checkout of a release, to compile the binaries from
+    #[clap(long, value_name = "DIR", required_unless_present = "from")]
+    source: Option<Utf8PathBuf>,
+
+    /// Rust toolchain to compile the binaries with [default: the toolchain pinned by the source tree]
+    #[clap(long, requires = "source")]
+    toolchain: Option<String>,
+
+    /// URL of a release providing the pre-built binaries, `SHA256SUMS` and `SHA256SUMS.sig`
+    #[clap(long, value_name = "URL", conflicts_with = "source", requires = "keys")]
+    from: Option<Url>,
+
+    /// Hex-encoded uncompressed SEC1 ECDSA P-256 public key trusted to sign the release
+    #[clap(long = "key", value_name = "KEY", requires = "from")]
+    keys: Vec<String>,
+
+    /// Directory to write the binaries to
+    #[clap(long, value_name = "DIR")]
+    out: Utf8PathBuf,
+}
+
+impl Options {
+    pub fn execute(self) -> anyhow::Result<()> {
+        let Self {
+            targets,
+            source,
+            toolchain,
+            from,
+            keys,
+            out,
+        } = self;
+
+        let targets = if targets.is_empty() {
+            vec!["sgx".into(), "kvm".into()]
+        } else {
+            targets
+        };
+        let mut wanted: Vec<&Artifact> = vec![];
+        for target in &targets {
+            for artifact in artifacts(target).classify(ErrorKind::Config)? {
+                if !wanted.contains(&artifact) {
+                    wanted.push(artifact);
+                }
+            }
+        }
+
+        fs::create_dir_all(&out)
+            .with_context(|| format!("failed to create `{out}`"))
+            .classify(ErrorKind::Io)?;
+
+        if let Some(url) = from {
+            // Relative file names resolve below the release URL
+            let url = if url.path().ends_with('/') {
+                url
+            } else {
+                let mut url = url;
+                url.set_path(&format!("{}/", url.path()));
+                url
+            };
+
+            let checksums = download(&url, CHECKSUMS).classify(ErrorKind::Io)?;
+            let signature = download(&url, SIGNATURE).classify(ErrorKind::Io)?;
+            let signature = String::from_utf8_lossy(&signature);
+            verify_checksums(&checksums, &signature, &keys).classify(ErrorKind::Config)?;
+            let checksums = std::str::from_utf8(&checksums)
+                .context("invalid checksum file")
+                .and_then(parse_checksums)
+                .classify(ErrorKind::Config)?;
+
+            for artifact in wanted {
+                let digest = checksums
+                    .get(artifact.name)
+                    .with_context(|| format!("`{CHECKSUMS}` lacks `{}`", artifact.name))
+                    .classify(ErrorKind::Config)?;
+                let binary = download(&url, artifact.name).classify(ErrorKind::Io)?;
+                if Sha256::digest(&binary).as_slice() != digest.as_slice() {
+                    return Err(anyhow!("digest of `{}` does not match", artifact.name))
+                        .classify(ErrorKind::Config);
+                }
+                let path = out.join(artifact.name);
+                fs::write(&path, binary)
+                    .with_context(|| format!("failed to write `{path}`"))
+                    .classify(ErrorKind::Io)?;
+                println!("Fetched `{path}`");
+            }
+        } else if let Some(source) = source {
+            for artifact in wanted {
+                let binary = compile(source.as_std_path(), toolchain.as_deref(), artifact)?;
+                let path = out.join(artifact.name);
+                fs::copy(&binary, &path)
+                    .with_context(|| format!("failed to copy `{}`", binary.display()))
+                    .classify(ErrorKind::Io)?;
+                println!("Compiled `{path}`");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Options to launch Keeps with shims and execs built by `enarx build-shims`
+#[derive(Args, Debug)]
+pub struct ShimOptions {
+    /// Directory of the shims and the exec written by `enarx build-shims` to launch the Keep
+    /// with instead of the embedded ones
+    #[clap(long, env = "ENARX_SHIM_DIR", value_name = "DIR")]
+    shim_dir: Option<Utf8PathBuf>,
+}
+
+impl ShimOptions {
+    /// Returns the shim of `backend` and `exec`
+    pub fn load(&self, backend: &dyn Backend, exec: &dyn Exec) -> anyhow::Result<Binaries> {
+        let dir = match self.shim_dir {
+            // The `nil` backend runs the exec in the host process
+            Some(ref dir) if !backend.shim().is_empty() => dir,
+            _ => return Ok((backend.shim().into(), exec.exec().into())),
+        };
+
+        let [shim, exec] = artifacts(backend.name())
+            .classify(ErrorKind::Platform)?
+            .map(|artifact| dir.join(artifact.name));
+        let read = |path: Utf8PathBuf| {
+            fs::read(&path)
+                .with_context(|| format!("failed to read `{path}`, see `enarx build-shims`"))
+                .classify(ErrorKind::Config)
+        };
+        Ok((read(shim)?.into(), read(exec)?.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    #[test]
+    fn checksums() {
+        let a = hex::encode(Sha256::digest(b"a"));
+        let b = hex::encode(Sha256::digest(b"b"));
+        let checksums = format!("{a}  enarx-shim-sgx\n{b} *enarx-exec-wasmtime\n\n");
+        let checksums = parse_checksums(&checksums).unwrap();
+        assert_eq!(checksums.len(), 2);
+        assert_eq!(checksums["enarx-shim-sgx"], Sha256::digest(b"a").as_slice());
+        assert_eq!(
+            checksums["enarx-exec-wasmtime"],
+            Sha256::digest(b"b").as_slice()
+        );
+
+        assert!(parse_checksums("enarx-shim-sgx").is_err());
+        assert!(parse_checksums("abcd  enarx-shim-sgx").is_err());
+    }
+
+    #[test]
+    fn signature() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()).unwrap();
+        let trusted = hex::encode(key.public_key());
+
+        let checksums = b"checksums";
+        let signature = hex::encode(key.sign(&rng, checksums).unwrap());
+
+        verify_checksums(checksums, &signature, &["04abcd".into(), trusted.clone()]).unwrap();
+        assert!(verify_checksums(b"tampered", &signature, &[trusted]).is_err());
+        assert!(verify_checksums(checksums, &signature, &[]).is_err());
+    }
+
+    #[test]
+    fn backends() {
+        assert_eq!(artifacts("sev").unwrap(), artifacts("kvm").unwrap());
+        assert_eq!(artifacts("sgx").unwrap()[0], &SHIM_SGX);
+        assert!(artifacts("nil").is_err());
+    }
+}
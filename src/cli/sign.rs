@@ -4,6 +4,7 @@ use crate::backend::sev::snp::launch::{IdAuth, IdBlock};
 use crate::backend::sev::snp::sign::Signature as IdSignature;
 use crate::backend::ByteSized;
 use crate::backend::{Backend, SevSignature, Signatures, BACKENDS};
+use crate::cli::ShimOptions;
 use crate::exec::EXECS;
 
 use std::fmt::Debug;
@@ -37,6 +38,9 @@ pub struct Options {
     #[clap(value_name = "BINARY")]
     pub binpath: Option<Utf8PathBuf>,
 
+    #[clap(flatten)]
+    shims: ShimOptions,
+
     /// SGX RSA private key in PEM form
     #[clap(long)]
     sgx_key: Utf8PathBuf,
@@ -52,6 +56,12 @@ pub struct Options {
     /// File path to write the signature
     #[clap(long)]
     out: Option<Utf8PathBuf>,
+
+    /// Memory size in bytes of the workload, which SGX Keeps are sized for
+    ///
+    /// Must match the `memory_size` limit of the Enarx.toml of the signed Keeps.
+    #[clap(long, value_name = "BYTES")]
+    memory_size: Option<u64>,
 }
 
 fn sign_sgx(body_bytes: &[u8], sgx_key: &RS256PrivateKey) -> Result<Vec<u8>> {
@@ -147,23 +157,29 @@ impl Options {
         for backend in BACKENDS.deref().iter() {
             let backend: &dyn Backend = backend.deref();
 
-            if backend.shim().is_empty() {
+            // Only the Keeps of these backends are signed
+            if backend.shim().is_empty() || !matches!(backend.name(), "sgx" | "sev") {
                 continue;
             }
 
+            let (shim, exec) = match EXECS.iter().find(|w| w.with_backend(backend)) {
+                Some(e) => self.shims.load(backend, &**e)?,
+                None => continue,
+            };
             let exec = if let Some(ref e) = binary {
                 e.as_ref()
-            } else if let Some(e) = EXECS.iter().find(|w| w.with_backend(backend)) {
-                e.exec()
             } else {
-                continue;
+                exec.as_ref()
             };
 
             if exec.is_empty() {
                 continue;
             }
 
-            let blob = backend.hash(backend.shim().as_ref(), exec.as_ref())?;
+            if let Some(size) = self.memory_size {
+                backend.set_memory_size(size);
+            }
+            let blob = backend.hash(shim.as_ref(), exec)?;
 
             match backend.name() {
                 "sgx" => {
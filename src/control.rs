@@ -0,0 +1,313 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Control sockets of running Keeps
+//!
+//! Every launched Keep serves a Unix socket in the control directory, which is named after the
+//! ID of the Keep, i.e. the process ID of its launcher. A client sends a single command, `info`
+//! or `stop`, shuts down its write half and reads the JSON reply. `enarx ps`, `enarx info` and
+//! `enarx kill` are such clients.
+
+use std::fs::{self, DirBuilder, File};
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::net::Shutdown;
+use std::os::unix::fs::{DirBuilderExt, FileExt};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use enarx_exec_wasmtime::Package;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+/// Extension of control sockets
+const SOCKET_EXT: &str = "sock";
+
+/// Command querying the [`Info`] of a Keep
+const INFO: &str = "info";
+
+/// Command requesting a Keep to shut down
+const STOP: &str = "stop";
+
+/// Exit code of a Keep shut down on request, as if terminated by `SIGTERM`
+const STOPPED: i32 = 128 + libc::SIGTERM;
+
+/// Timeout of a client waiting for the reply of a Keep
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Details of a running Keep
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Info {
+    /// ID of the Keep, i.e. the process ID of its launcher
+    pub id: u32,
+    /// Name of the backend
+    pub backend: String,
+    /// SHA-256 digest of a local Wasm module or the URL of a remote package
+    pub workload: String,
+    /// Launch time in seconds since the Unix epoch
+    pub started: u64,
+    /// Seconds since the launch
+    pub uptime: u64,
+    /// Number of threads of the launcher, if known
+    pub threads: Option<u64>,
+}
+
+/// Returns the control directory, `$XDG_RUNTIME_DIR/enarx/keeps` or `/tmp/enarx-<uid>/keeps`
+pub fn dir() -> PathBuf {
+    dirs::runtime_dir()
+        .map(|dir| dir.join("enarx"))
+        .unwrap_or_else(|| {
+            std::env::temp_dir().join(format!("enarx-{}", unsafe { libc::getuid() }))
+        })
+        .join("keeps")
+}
+
+/// Returns the path of the control socket of Keep `id` in `dir`
+fn socket(dir: &Path, id: u32) -> PathBuf {
+    dir.join(id.to_string()).with_extension(SOCKET_EXT)
+}
+
+/// Returns the number of threads of the current process, if known
+fn threads() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .and_then(|threads| threads.trim().parse().ok())
+}
+
+/// Returns the SHA-256 digest of the Wasm module of a local `package` or the URL of a remote one
+pub fn workload(package: &Package) -> String {
+    match package {
+        Package::Remote(url) => url.to_string(),
+        Package::Local { wasm, .. } => digest(*wasm)
+            .map(|digest| format!("sha256:{}", hex::encode(digest)))
+            .unwrap_or_else(|e| {
+                debug!("failed to hash Wasm module: {e}");
+                "unknown".into()
+            }),
+    }
+}
+
+/// Returns the SHA-256 digest of the file open at `fd` without changing its offset
+fn digest(fd: RawFd) -> io::Result<[u8; 32]> {
+    // The file descriptor is owned by the package
+    let file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    let mut hasher = Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    let mut offset = 0;
+    loop {
+        match file.read_at(&mut buf, offset)? {
+            0 => return Ok(hasher.finalize().into()),
+            n => {
+                hasher.update(&buf[..n]);
+                offset += n as u64;
+            }
+        }
+    }
+}
+
+/// The control socket of the running Keep, which is removed on drop
+pub struct Control {
+    path: PathBuf,
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Serves the control socket of the running Keep of `backend` executing `workload` in `dir`
+pub fn serve(dir: &Path, backend: &str, workload: String) -> Result<Control> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("failed to create control directory `{}`", dir.display()))?;
+
+    let id = std::process::id();
+    let path = socket(dir, id);
+    // A socket left behind by a crashed launcher with the same process ID
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("failed to bind control socket `{}`", path.display()))?;
+
+    let started = SystemTime::now();
+    let info = Info {
+        id,
+        backend: backend.into(),
+        workload,
+        started: started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        uptime: 0,
+        threads: None,
+    };
+
+    let control = Control { path: path.clone() };
+    thread::Builder::new()
+        .name("control".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stop = stream.and_then(|stream| handle(stream, &info, started));
+                match stop {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        let _ = fs::remove_file(&path);
+                        std::process::exit(STOPPED);
+                    }
+                    Err(e) => warn!("failed to handle control request: {e}"),
+                }
+            }
+        })
+        .context("failed to spawn control thread")?;
+    Ok(control)
+}
+
+/// Handles a single control request on `stream` and returns, whether the Keep shall stop
+fn handle(mut stream: UnixStream, info: &Info, started: SystemTime) -> io::Result<bool> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut command = String::new();
+    (&mut stream).take(64).read_to_string(&mut command)?;
+
+    let (reply, stop) = match command.trim() {
+        INFO => {
+            let info = Info {
+                uptime: started.elapsed().unwrap_or_default().as_secs(),
+                threads: threads(),
+                ..info.clone()
+            };
+            (serde_json::to_string(&info)?, false)
+        }
+        STOP => ("{}".into(), true),
+        command => (
+            serde_json::json!({ "error": format!("unknown command `{command}`") }).to_string(),
+            false,
+        ),
+    };
+    stream.write_all(reply.as_bytes())?;
+    Ok(stop)
+}
+
+/// Sends `command` to the control socket at `path` and returns the reply
+fn request(path: &Path, command: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.write_all(command.as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// Parses the `reply` of Keep `id` to an `info` command
+fn parse(id: u32, reply: &str) -> Result<Info> {
+    serde_json::from_str(reply).with_context(|| format!("invalid reply of Keep {id}: {reply}"))
+}
+
+/// Returns the [`Info`] of Keep `id` in `dir`
+pub fn info(dir: &Path, id: u32) -> Result<Info> {
+    let path = socket(dir, id);
+    let reply = request(&path, INFO)
+        .with_context(|| format!("failed to query Keep {id}, is it running?"))?;
+    parse(id, &reply)
+}
+
+/// Returns the [`Info`] of all running Keeps in `dir` ordered by ID
+///
+/// Sockets of launchers, which exited without removing them, are removed.
+pub fn list(dir: &Path) -> Result<Vec<Info>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("failed to read control directory `{}`", dir.display()))
+        }
+    };
+
+    let mut keeps = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let id = match path.file_stem().and_then(|id| id.to_str()?.parse().ok()) {
+            Some(id) if path.extension().map_or(false, |ext| ext == SOCKET_EXT) => id,
+            _ => continue,
+        };
+        match request(&path, INFO) {
+            Ok(reply) => keeps.push(parse(id, &reply)?),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                debug!("removing stale control socket `{}`", path.display());
+                let _ = fs::remove_file(&path);
+            }
+            Err(e) => warn!("failed to query Keep {id}: {e}"),
+        }
+    }
+    keeps.sort_by_key(|keep| keep.id);
+    Ok(keeps)
+}
+
+/// Requests Keep `id` in `dir` to shut down
+pub fn stop(dir: &Path, id: u32) -> Result<()> {
+    let path = socket(dir, id);
+    let reply = request(&path, STOP)
+        .with_context(|| format!("failed to stop Keep {id}, is it running?"))?;
+    if reply.trim() != "{}" {
+        bail!("Keep {id} refused to stop: {reply}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn serve_and_list() {
+        let dir = tempdir().unwrap();
+        let keeps = dir.path().join("keeps");
+        assert_eq!(list(&keeps).unwrap(), vec![]);
+
+        let control = serve(&keeps, "nil", "sha256:00".into()).unwrap();
+        let id = std::process::id();
+
+        let info = info(&keeps, id).unwrap();
+        assert_eq!(info.id, id);
+        assert_eq!(info.backend, "nil");
+        assert_eq!(info.workload, "sha256:00");
+        assert!(info.threads.unwrap_or(1) >= 1);
+        assert_eq!(list(&keeps).unwrap(), vec![info]);
+
+        let reply = request(&socket(&keeps, id), "bogus").unwrap();
+        assert!(reply.contains("unknown command"));
+
+        // a stale socket without a listener is removed
+        let stale = socket(&keeps, u32::MAX);
+        drop(UnixListener::bind(&stale).unwrap());
+        assert_eq!(list(&keeps).unwrap().len(), 1);
+        assert!(!stale.exists());
+
+        drop(control);
+        assert_eq!(list(&keeps).unwrap(), vec![]);
+        assert!(super::info(&keeps, id).is_err());
+    }
+
+    #[test]
+    fn digest() {
+        use std::io::Seek;
+        use std::os::unix::io::AsRawFd;
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"abc").unwrap();
+        let expected: [u8; 32] = Sha256::digest(b"abc").into();
+        assert_eq!(super::digest(file.as_raw_fd()).unwrap(), expected);
+        assert_eq!(file.stream_position().unwrap(), 3);
+    }
+}
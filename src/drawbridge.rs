@@ -2,8 +2,9 @@
 
 use std::borrow::Borrow;
 use std::ffi::OsStr;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{stderr, Write};
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::str::FromStr;
 use std::thread::spawn;
@@ -19,9 +20,13 @@ use oauth2::ureq::http_client;
 use oauth2::url::Url;
 use oauth2::{AuthType, AuthUrl, ClientId, DeviceAuthorizationUrl, Scope, TokenResponse, TokenUrl};
 use rustls::{Certificate, RootCertStore};
+use tracing::debug;
 
 const DEFAULT_HOST: &str = "store.profian.com";
 
+/// Maximum number of recently deployed slugs remembered for shell completion
+const RECENT_SLUGS: usize = 100;
+
 #[derive(Clone, Debug)]
 pub struct UserSpec {
     pub host: String,
@@ -78,6 +83,44 @@ impl FromStr for TagSpec {
     }
 }
 
+/// Returns the file of recently deployed slugs, `$XDG_CACHE_HOME/enarx/slugs`
+fn recent_slugs_file() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("enarx").join("slugs"))
+}
+
+/// Returns the recently deployed slugs, the most recent first
+pub fn recent_slugs() -> Vec<String> {
+    recent_slugs_file()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|slugs| slugs.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Remembers the deployed `slug` for shell completion
+///
+/// Failing to remember it is not an error of the deployment.
+pub fn remember_slug(slug: &str) {
+    let path = match recent_slugs_file() {
+        Some(path) => path,
+        None => return,
+    };
+    let mut slugs = recent_slugs();
+    slugs.retain(|s| s != slug);
+    slugs.insert(0, slug.into());
+    slugs.truncate(RECENT_SLUGS);
+
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, slugs.join("\n") + "\n"));
+    if let Err(e) = written {
+        debug!(
+            "failed to remember slug `{slug}` in `{}`: {e}",
+            path.display()
+        );
+    }
+}
+
 pub fn parse_tag(slug: &str) -> anyhow::Result<(String, &str, &str, &str)> {
     let (head, tag) = slug
         .rsplit_once(':')
@@ -18,4 +18,9 @@ impl crate::exec::Exec for WasmExec {
     fn with_backend(&self, backend: &dyn Backend) -> bool {
         backend.name() != "nil"
     }
+
+    #[inline]
+    fn target(&self) -> Option<&'static str> {
+        Some("x86_64-unknown-linux-musl")
+    }
 }
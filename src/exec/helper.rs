@@ -0,0 +1,98 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helper Keeps of the `helper` file descriptors of a package config
+//!
+//! For every `helper` file descriptor, the host spawns `enarx run --backend nil` with the Wasm
+//! module at its `path` and connects the standard input and output of the helper Keep to one end
+//! of a Unix socket pair. The module and the other end are passed to the Keep, which verifies the
+//! module against the `digest` and claims it in its attestation evidence. The helper Keep runs
+//! with the default config, i.e. without network, so it only communicates through the Keep.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::os::unix::io::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::process::{Child, Command, Stdio};
+
+use anyhow::{Context, Result};
+use enarx_config::{Config, File as FileConf, HelperFile};
+use enarx_error::{Classify, ErrorKind};
+use tracing::{info, warn};
+
+/// The helper Keeps spawned for a Keep, which are killed, when they are dropped
+#[derive(Debug, Default)]
+pub struct Helpers(Vec<(String, Child)>);
+
+impl Drop for Helpers {
+    fn drop(&mut self) {
+        for (name, child) in &mut self.0 {
+            match child.try_wait() {
+                Ok(Some(status)) => info!("helper `{name}` exited with {status}"),
+                Ok(None) => {
+                    if let Err(e) = child.kill().and_then(|_| child.wait()) {
+                        warn!("failed to kill helper `{name}`: {e}");
+                    }
+                }
+                Err(e) => warn!("failed to query helper `{name}`: {e}"),
+            }
+        }
+    }
+}
+
+/// Spawns the helper Keep running the module at `path` connected to `stream`
+fn spawn_one(path: &str, stream: UnixStream) -> Result<Child> {
+    let exe = std::env::current_exe().context("failed to locate the enarx executable")?;
+    let stdout = stream
+        .try_clone()
+        .context("failed to duplicate helper stream")?;
+    Command::new(exe)
+        .args(["run", "--backend", "nil", path])
+        // The helper runs with the default config and is never relaunched
+        .env_remove("ENARX_WASMCFGFILE")
+        .env_remove(super::REEXEC_CONFIG)
+        .stdin(Stdio::from(OwnedFd::from(stream)))
+        .stdout(Stdio::from(OwnedFd::from(stdout)))
+        .spawn()
+        .context("failed to spawn helper Keep")
+}
+
+/// Spawns the helper Keeps of the package config `conf`, if any, and rewinds it for the Keep
+///
+/// Returns the modules of the helpers and the streams connected to them by the name of their
+/// `helper` file descriptor together with the spawned helpers.
+pub fn spawn(conf: Option<&mut File>) -> Result<(HashMap<String, (File, UnixStream)>, Helpers)> {
+    let conf = match conf {
+        Some(conf) => conf,
+        None => return Ok(Default::default()),
+    };
+    let mut buf = String::new();
+    conf.read_to_string(&mut buf)
+        .and_then(|_| conf.rewind())
+        .context("failed to read package config")
+        .classify(ErrorKind::Io)?;
+    let config: Config = toml::from_str(&buf)
+        .context("failed to parse package config")
+        .classify(ErrorKind::Config)?;
+
+    let mut streams = HashMap::new();
+    let mut helpers = Helpers::default();
+    for file in &config.files {
+        if let FileConf::Helper(HelperFile { path, .. }) = file {
+            let name = file.name().to_string();
+            let wasm = File::open(path)
+                .with_context(|| format!("failed to open helper module at `{path}`"))
+                .classify(ErrorKind::Io)?;
+            let (keep, helper) = UnixStream::pair()
+                .context("failed to create a Unix socket pair")
+                .classify(ErrorKind::Io)?;
+            let child = spawn_one(path, helper)
+                .with_context(|| format!("failed to start helper `{name}`"))
+                .classify(ErrorKind::Io)?;
+            info!(pid = child.id(), "spawned helper `{name}` running `{path}`");
+            helpers.0.push((name.clone(), child));
+            streams.insert(name, (wasm, keep));
+        }
+    }
+    Ok((streams, helpers))
+}
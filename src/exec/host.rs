@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resources of the host process of a Keep
+//!
+//! The rlimits needed by the backend are raised before the Keep is launched, so it does not fail
+//! midway with `ENOMEM` or `EMFILE`. The Keep is placed in a cgroup (v2) enforcing the `[limits]`
+//! of its configuration and optionally in a network namespace, in which the host opens its
+//! sockets.
+
+use crate::backend::Backend;
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process;
+
+use anyhow::{anyhow, Context};
+use enarx_config::{Config, Limits};
+use enarx_error::{Classify, ErrorKind};
+use tracing::{debug, warn};
+
+/// Default parent cgroup of the Keeps, which has to be delegated to the user by the administrator
+pub const CGROUP: &str = "/sys/fs/cgroup/enarx";
+
+/// Approximate memory footprint of a SEV Keep, which is locked in memory
+const SEV_MEMLOCK: u64 = 5 * 1024 * 1024;
+
+/// Memory of the host process and the runtime in addition to the memory of the workload
+const MEMORY_HEADROOM: u64 = 256 * 1024 * 1024;
+
+/// Period of the CPU quota in microseconds
+const CPU_PERIOD: u64 = 100_000;
+
+#[cfg(target_env = "gnu")]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+type Resource = libc::c_int;
+
+/// Raises the soft limit of `resource` to `want` and returns the new soft limit
+///
+/// Unprivileged processes cannot raise it above the hard limit.
+fn raise(resource: Resource, want: u64) -> io::Result<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid rlimit struct
+    if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if limit.rlim_cur >= want {
+        return Ok(limit.rlim_cur);
+    }
+
+    let raised = libc::rlimit {
+        rlim_cur: want,
+        rlim_max: limit.rlim_max.max(want),
+    };
+    // SAFETY: `raised` is a valid rlimit struct
+    if unsafe { libc::setrlimit(resource, &raised) } == 0 {
+        return Ok(want);
+    }
+
+    limit.rlim_cur = limit.rlim_max.min(want);
+    // SAFETY: `limit` is a valid rlimit struct
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(limit.rlim_cur)
+}
+
+/// Raises the rlimits of the host process as needed by the `backend`
+pub fn raise_rlimits(backend: &dyn Backend) -> anyhow::Result<()> {
+    // Every file descriptor of the workload is backed by one of the host process
+    match raise(libc::RLIMIT_NOFILE, libc::RLIM_INFINITY) {
+        Ok(nofile) => debug!("NOFILE rlimit is {nofile}"),
+        Err(e) => warn!("failed to raise NOFILE rlimit: {e}"),
+    }
+
+    // The memory of SEV Keeps is pinned
+    if backend.name() == "sev" {
+        let memlock = raise(libc::RLIMIT_MEMLOCK, libc::RLIM_INFINITY)
+            .context("failed to raise MEMLOCK rlimit")
+            .classify(ErrorKind::Platform)?;
+        if memlock < SEV_MEMLOCK {
+            return Err(anyhow!(
+                "MEMLOCK rlimit of `{memlock}` bytes is too small for a SEV Keep. \
+                 Raise it to at least `{SEV_MEMLOCK}` bytes in `/etc/security/limits.d`."
+            ))
+            .classify(ErrorKind::Platform);
+        }
+    }
+    Ok(())
+}
+
+/// Reads the `[limits]` of the package config `conf` and rewinds it for the Keep
+pub fn read_limits(conf: &mut File) -> anyhow::Result<Limits> {
+    let mut buf = String::new();
+    conf.read_to_string(&mut buf)
+        .and_then(|_| conf.rewind())
+        .context("failed to read package config")
+        .classify(ErrorKind::Io)?;
+    let config: Config = toml::from_str(&buf)
+        .context("failed to parse package config")
+        .classify(ErrorKind::Config)?;
+    Ok(config.limits)
+}
+
+/// Returns the `major:minor` numbers of all block devices, which are no partitions
+fn block_devices() -> Vec<String> {
+    let entries = match fs::read_dir("/sys/class/block") {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    entries
+        .flatten()
+        .filter(|entry| !entry.path().join("partition").exists())
+        .filter_map(|entry| fs::read_to_string(entry.path().join("dev")).ok())
+        .map(|dev| dev.trim().to_string())
+        .collect()
+}
+
+/// Returns the cgroup controllers and interface files with their values enforcing `limits`
+fn cgroup_limits(limits: &Limits, devices: &[String]) -> Vec<(&'static str, &'static str, String)> {
+    let mut values = vec![];
+    if let Some(size) = limits.memory_size {
+        let max = size.saturating_add(MEMORY_HEADROOM);
+        values.push(("memory", "memory.max", max.to_string()));
+    }
+    if let Some(quota) = limits.cpu_quota {
+        let max = u64::from(quota) * CPU_PERIOD / 100;
+        values.push(("cpu", "cpu.max", format!("{max} {CPU_PERIOD}")));
+    }
+    if let Some(bps) = limits.io_bandwidth {
+        for dev in devices {
+            values.push(("io", "io.max", format!("{dev} rbps={bps} wbps={bps}")));
+        }
+    }
+    values
+}
+
+/// Moves the host process into a new cgroup below `parent` enforcing `limits`
+///
+/// Without `parent`, the Keep is placed below [`CGROUP`], if it exists.
+pub fn enter_cgroup(parent: Option<&Path>, limits: &Limits) -> anyhow::Result<()> {
+    let values = cgroup_limits(limits, &block_devices());
+    if values.is_empty() {
+        return Ok(());
+    }
+
+    let parent = match parent {
+        Some(parent) => parent,
+        None if Path::new(CGROUP).is_dir() => Path::new(CGROUP),
+        None => {
+            warn!("`[limits]` are not enforced by the host without the cgroup `{CGROUP}`");
+            return Ok(());
+        }
+    };
+
+    // The cgroups of exited Keeps are empty and can be removed
+    if let Ok(entries) = fs::read_dir(parent) {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with("keep-") {
+                let _ = fs::remove_dir(entry.path());
+            }
+        }
+    }
+
+    let mut controllers: Vec<_> = values.iter().map(|(c, ..)| format!("+{c}")).collect();
+    controllers.dedup();
+    fs::write(parent.join("cgroup.subtree_control"), controllers.join(" "))
+        .with_context(|| {
+            format!(
+                "failed to enable the {} controllers of the cgroup `{}`",
+                controllers.join(" "),
+                parent.display()
+            )
+        })
+        .classify(ErrorKind::Platform)?;
+
+    let dir = parent.join(format!("keep-{}", process::id()));
+    fs::create_dir(&dir)
+        .with_context(|| format!("failed to create the cgroup `{}`", dir.display()))
+        .classify(ErrorKind::Platform)?;
+    for (_, file, value) in values {
+        fs::write(dir.join(file), &value)
+            .with_context(|| format!("failed to set `{file}` of the cgroup to `{value}`"))
+            .classify(ErrorKind::Platform)?;
+    }
+    fs::write(dir.join("cgroup.procs"), "0")
+        .with_context(|| {
+            format!(
+                "failed to move the Keep into the cgroup `{}`",
+                dir.display()
+            )
+        })
+        .classify(ErrorKind::Platform)?;
+    debug!("entered cgroup `{}`", dir.display());
+    Ok(())
+}
+
+/// Moves the host process into the network namespace `path`, e.g. `/run/netns/NAME` created by
+/// `ip netns add NAME`
+///
+/// Only the calling thread enters the namespace, so this has to be called before the threads of
+/// the Keep are spawned, which inherit it.
+pub fn enter_netns(path: &Path) -> anyhow::Result<()> {
+    let ns = File::open(path)
+        .with_context(|| format!("failed to open the network namespace `{}`", path.display()))
+        .classify(ErrorKind::Config)?;
+    // SAFETY: `ns` is an open file descriptor
+    if unsafe { libc::setns(ns.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("failed to enter the network namespace `{}`", path.display()))
+            .classify(ErrorKind::Platform);
+    }
+    debug!("entered network namespace `{}`", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        assert!(cgroup_limits(&Limits::default(), &["8:0".into()]).is_empty());
+
+        let limits = Limits {
+            memory_size: Some(1024),
+            cpu_quota: Some(150),
+            io_bandwidth: Some(1000),
+            ..Default::default()
+        };
+        assert_eq!(
+            cgroup_limits(&limits, &["8:0".into(), "259:0".into()]),
+            vec![
+                ("memory", "memory.max", (1024 + MEMORY_HEADROOM).to_string()),
+                ("cpu", "cpu.max", "150000 100000".into()),
+                ("io", "io.max", "8:0 rbps=1000 wbps=1000".into()),
+                ("io", "io.max", "259:0 rbps=1000 wbps=1000".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn netns() {
+        let err = enter_netns(Path::new("/nonexistent/netns")).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::Config));
+
+        // Not a namespace
+        let err = enter_netns(Path::new("/dev/null")).unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::Platform));
+    }
+}
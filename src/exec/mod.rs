@@ -23,25 +23,49 @@
 
 #[cfg(enarx_with_shim)]
 pub mod exec_wasmtime;
+#[cfg(unix)]
+pub mod helper;
+#[cfg(target_os = "linux")]
+pub mod host;
+#[cfg(unix)]
+pub mod offload;
+pub mod preflight;
+#[cfg(unix)]
+mod tty;
 
 use crate::backend::{Backend, Command, Signatures};
+#[cfg(unix)]
+use crate::control;
 
+use std::collections::HashMap;
 use std::convert::Into;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek};
 #[cfg(unix)]
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 #[cfg(unix)]
 use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use enarx_config::{Config, DatasetFile};
+use enarx_error::{Classify, ErrorKind};
+#[cfg(unix)]
+use enarx_exec_wasmtime::Reexec;
 use enarx_exec_wasmtime::{Args as ExecArgs, Package};
 use once_cell::sync::Lazy;
+#[cfg(unix)]
+use tracing::{info, warn};
 
 /// Write timeout for writing the arguments to exec-wasmtime.
 #[cfg(unix)]
 const ARG_WRITE_TIMEOUT: Duration = Duration::new(60, 0);
 
+/// Environment variable passing the config digest of the [`Reexec`] requested by the workload
+/// to the relaunched host
+#[cfg(unix)]
+const REEXEC_CONFIG: &str = "ENARX_REEXEC_CONFIG";
+
 /// A trait for the "Exec"
 ///
 /// (as in Backend::keep(shim, exec) [q.v.]) and formerly known as the "code"
@@ -62,6 +86,11 @@ pub trait Exec: Sync + Send {
     /// which calls into the `exec-wasmtime` crate directly, without
     /// loading any binary.
     fn with_backend(&self, backend: &dyn Backend) -> bool;
+
+    /// The target triple Wasm modules are compiled for, if it differs from the host
+    fn target(&self) -> Option<&'static str> {
+        None
+    }
 }
 
 pub struct NilExec;
@@ -95,7 +124,9 @@ pub fn keep_exec(
     signatures: Option<Signatures>,
     _gdblisten: Option<String>,
 ) -> anyhow::Result<libc::c_int> {
-    let keep = backend.keep(shim.as_ref(), exec.as_ref(), signatures)?;
+    let keep = backend
+        .keep(shim.as_ref(), exec.as_ref(), signatures)
+        .classify(ErrorKind::Platform)?;
     let mut thread = keep.clone().spawn()?.unwrap();
     loop {
         match thread.enter(&_gdblisten)? {
@@ -111,17 +142,121 @@ pub fn open_package(
 ) -> Result<(File, Option<File>)> {
     let wasm = wasm.into();
     let wasm = File::open(&wasm)
-        .with_context(|| format!("failed to open WASM module at `{}`", wasm.display()))?;
+        .with_context(|| format!("failed to open WASM module at `{}`", wasm.display()))
+        .classify(ErrorKind::Io)?;
     if let Some(conf) = conf {
         let conf = conf.into();
         let conf = File::open(&conf)
-            .with_context(|| format!("failed to open package config at `{}`", conf.display()))?;
+            .with_context(|| format!("failed to open package config at `{}`", conf.display()))
+            .classify(ErrorKind::Config)?;
         Ok((wasm, Some(conf)))
     } else {
         Ok((wasm, None))
     }
 }
 
+/// Opens the build provenance of a package, if any.
+pub fn open_provenance(path: Option<impl Into<PathBuf>>) -> Result<Option<File>> {
+    path.map(|path| {
+        let path = path.into();
+        File::open(&path)
+            .with_context(|| format!("failed to open build provenance at `{}`", path.display()))
+            .classify(ErrorKind::Io)
+    })
+    .transpose()
+}
+
+/// Opens the precompiled module artifact of a package, if any.
+pub fn open_precompiled(path: Option<impl Into<PathBuf>>) -> Result<Option<File>> {
+    path.map(|path| {
+        let path = path.into();
+        File::open(&path)
+            .with_context(|| {
+                format!(
+                    "failed to open precompiled artifact at `{}`",
+                    path.display()
+                )
+            })
+            .classify(ErrorKind::Io)
+    })
+    .transpose()
+}
+
+/// Opens the observer sidecar module of a package, if any.
+pub fn open_sidecar(path: Option<impl Into<PathBuf>>) -> Result<Option<File>> {
+    path.map(|path| {
+        let path = path.into();
+        File::open(&path)
+            .with_context(|| format!("failed to open sidecar module at `{}`", path.display()))
+            .classify(ErrorKind::Io)
+    })
+    .transpose()
+}
+
+/// Creates the pcapng file of the tap of a Keep, if any.
+pub fn open_tap(path: Option<impl Into<PathBuf>>) -> Result<Option<File>> {
+    path.map(|path| {
+        let path = path.into();
+        File::create(&path)
+            .with_context(|| format!("failed to create tap file at `{}`", path.display()))
+            .classify(ErrorKind::Io)
+    })
+    .transpose()
+}
+
+/// Reads and parses the package config `conf`, if any, and rewinds it for the Keep.
+pub fn read_config(conf: Option<&mut File>) -> Result<Option<Config>> {
+    let conf = match conf {
+        Some(conf) => conf,
+        None => return Ok(None),
+    };
+    let mut buf = String::new();
+    conf.read_to_string(&mut buf)
+        .and_then(|_| conf.rewind())
+        .context("failed to read package config")
+        .classify(ErrorKind::Io)?;
+    toml::from_str(&buf)
+        .context("failed to parse package config")
+        .classify(ErrorKind::Config)
+        .map(Some)
+}
+
+/// Opens the host files of the datasets of the package config `config`, if any.
+pub fn open_datasets(config: Option<&Config>) -> Result<HashMap<String, File>> {
+    let mut datasets = HashMap::new();
+    for file in config.iter().flat_map(|config| &config.files) {
+        if let enarx_config::File::Dataset(DatasetFile { path, .. }) = file {
+            if datasets.contains_key(path) {
+                continue;
+            }
+            let dataset = File::open(path)
+                .with_context(|| format!("failed to open dataset at `{path}`"))
+                .classify(ErrorKind::Io)?;
+            datasets.insert(path.clone(), dataset);
+        }
+    }
+    Ok(datasets)
+}
+
+/// Opens the attestation channel of a `file` Steward URL of the package config `config`, if any.
+pub fn open_channel(config: Option<&Config>) -> Result<Option<File>> {
+    let url = match config.and_then(|config| config.steward.as_ref()) {
+        Some(url) if url.scheme() == "file" => url,
+        _ => return Ok(None),
+    };
+    let path = url
+        .to_file_path()
+        .map_err(|()| anyhow!("invalid attestation channel path `{url}`"))
+        .classify(ErrorKind::Config)?;
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("failed to open attestation channel at `{}`", path.display()))
+        .classify(ErrorKind::Io)
+        .map(Some)
+}
+
 /// Runs a package.
 /// SAFETY: Panics if next free FD number is not equal to 3.
 /// In other words, callers must either close all files opened at runtime before calling this
@@ -129,15 +264,21 @@ pub fn open_package(
 #[cfg(windows)]
 pub fn run_package(
     backend: &dyn Backend,
+    shim: impl AsRef<[u8]>,
     exec: impl AsRef<[u8]>,
     _signatures: Option<Signatures>,
     gdblisten: Option<String>,
     package: impl FnOnce() -> Result<Package>,
+    secrets: HashMap<String, String>,
 ) -> Result<i32> {
     let package = package()?;
-    let args = ExecArgs { package };
+    let args = ExecArgs {
+        package,
+        secrets,
+        reexec: None,
+    };
     backend.set_args(args);
-    let exit_code = keep_exec(backend, backend.shim(), exec, None, gdblisten)?;
+    let exit_code = keep_exec(backend, shim, exec, None, gdblisten)?;
     Ok(exit_code)
 }
 
@@ -148,18 +289,25 @@ pub fn run_package(
 #[cfg(unix)]
 pub fn run_package(
     backend: &dyn Backend,
+    shim: impl AsRef<[u8]>,
     exec: impl AsRef<[u8]>,
     signatures: Option<Signatures>,
     gdblisten: Option<String>,
     package: impl FnOnce() -> Result<Package>,
+    secrets: HashMap<String, String>,
 ) -> Result<i32> {
     use std::io::Write;
     use std::net::Shutdown;
     use std::os::unix::net::UnixStream;
     use std::thread;
 
-    let (exec_sock, mut host_sock) =
-        UnixStream::pair().context("failed to create a Unix socket pair")?;
+    tty::restore_at_exit();
+    #[cfg(target_os = "linux")]
+    host::raise_rlimits(backend)?;
+
+    let (exec_sock, mut host_sock) = UnixStream::pair()
+        .context("failed to create a Unix socket pair")
+        .classify(ErrorKind::Io)?;
 
     assert_eq!(
         exec_sock.as_raw_fd(),
@@ -168,8 +316,23 @@ pub fn run_package(
     );
 
     let package = package()?;
-    let args =
-        toml::to_vec(&ExecArgs { package }).context("failed to encode exec-wasmtime arguments")?;
+    // Set by the host, which relaunched itself on request of the workload of the previous Keep
+    let reexec = std::env::var(REEXEC_CONFIG)
+        .ok()
+        .map(|config| Reexec { config });
+    std::env::remove_var(REEXEC_CONFIG);
+    // Opened after FD 3 is taken by the Unix socket pair
+    let control = control::serve(&control::dir(), backend.name(), control::workload(&package))
+        .map_err(|e| warn!("failed to serve Keep control socket: {e:#}"))
+        .ok();
+    // Secrets are forwarded to the Keep over this socket in plaintext
+    let args = toml::to_vec(&ExecArgs {
+        package,
+        secrets,
+        reexec,
+    })
+    .context("failed to encode exec-wasmtime arguments")
+    .classify(ErrorKind::Io)?;
 
     host_sock
         .set_nonblocking(true)
@@ -185,19 +348,63 @@ pub fn run_package(
         host_sock
             .shutdown(Shutdown::Write)
             .context("failed to shutdown write half of host's socket")?;
-        // TODO: Read exec-wasmtime output from the socket
-        host_sock
-            .shutdown(Shutdown::Read)
-            .context("failed to shutdown read half of host's socket")
+        Ok(host_sock)
     });
 
-    let exit_code = keep_exec(backend, backend.shim(), exec, signatures, gdblisten)?;
-    exec_io
+    let exit_code = keep_exec(backend, shim, exec, signatures, gdblisten)?;
+    let mut host_sock = exec_io
         .join()
-        .expect("failed to join exec-wasmtime I/O thread")?;
+        .expect("failed to join exec-wasmtime I/O thread")
+        .classify(ErrorKind::Io)?;
+    drop(control);
+    if let Some(reexec) = requested_reexec(&mut host_sock) {
+        relaunch(&reexec)?;
+    }
     Ok(exit_code)
 }
 
+/// Returns the relaunch requested by the workload of the exited Keep on `host_sock`, if any
+///
+/// The Keep writes the request before it exits, so it is read without blocking.
+#[cfg(unix)]
+fn requested_reexec(host_sock: &mut std::os::unix::net::UnixStream) -> Option<Reexec> {
+    let mut msg = vec![];
+    match host_sock.read_to_end(&mut msg) {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+        Err(e) => {
+            warn!("failed to read from the Keep: {e}");
+            return None;
+        }
+    }
+    if msg.is_empty() {
+        return None;
+    }
+    toml::from_slice(&msg)
+        .map_err(|e| warn!("ignoring invalid relaunch request of the Keep: {e}"))
+        .ok()
+}
+
+/// Relaunches the host with its own arguments for `reexec`, which only returns on failure
+///
+/// All files of the host are closed on exec, so the relaunched host takes FD 3 again.
+#[cfg(unix)]
+fn relaunch(reexec: &Reexec) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    info!(config = %reexec.config, "relaunching Keep on request of the workload");
+    let exe = std::env::current_exe()
+        .context("failed to locate the enarx executable")
+        .classify(ErrorKind::Io)?;
+    let e = std::process::Command::new(exe)
+        .args(std::env::args_os().skip(1))
+        .env(REEXEC_CONFIG, &reexec.config)
+        .exec();
+    Err(e)
+        .context("failed to relaunch Keep")
+        .classify(ErrorKind::Io)
+}
+
 #[cfg(test)]
 mod test {
     use super::{Exec, NilExec};
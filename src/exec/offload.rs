@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! TLS termination on the host for the non-confidential listen sockets of a Keep
+//!
+//! For every `listen` file descriptor with `prot = "tls"` and `confidential = false` in the
+//! package config, the host binds the configured address itself and an internal listen socket on
+//! its loopback interface, which is passed to the Keep instead. Every accepted connection is
+//! decrypted with the certificate of the operator and relayed to the internal listen socket in
+//! plaintext, so the Keep spends no cycles on TLS for public content.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+use enarx_config::{Config, File as FileConf, ListenFile};
+use enarx_error::{Classify, ErrorKind};
+use rustls::{Certificate, PrivateKey, ServerConfig, ServerConnection};
+use tracing::{debug, info};
+
+/// Size of the buffer of the plaintext read from the Keep
+const BUF_SIZE: usize = 16 * 1024;
+
+/// Loads the TLS config of the PEM certificate chain at `cert` and the PKCS#8 PEM key at `key`
+fn server_config(cert: &Path, key: &Path) -> Result<Arc<ServerConfig>> {
+    let certs = File::open(cert)
+        .and_then(|file| rustls_pemfile::certs(&mut BufReader::new(file)))
+        .with_context(|| format!("failed to read certificate chain at `{}`", cert.display()))?;
+    let der = File::open(key)
+        .and_then(|file| rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(file)))
+        .with_context(|| format!("failed to read private key at `{}`", key.display()))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no PKCS#8 private key found at `{}`", key.display()))?;
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            certs.into_iter().map(Certificate).collect(),
+            PrivateKey(der),
+        )
+        .context("invalid certificate chain or private key")?;
+    Ok(Arc::new(config))
+}
+
+/// Relays the data between the TLS connection `tls` of the client on `public` and the plaintext
+/// stream `keep` to the Keep, until either side closes its stream
+fn relay(mut tls: ServerConnection, mut public: TcpStream, mut keep: TcpStream) -> io::Result<()> {
+    let mut buf = vec![0; BUF_SIZE];
+    loop {
+        while tls.wants_write() {
+            tls.write_tls(&mut public)?;
+        }
+
+        let mut fds = [public.as_raw_fd(), keep.as_raw_fd()].map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        // SAFETY: `fds` is a valid array of two `pollfd`.
+        if unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+            match io::Error::last_os_error() {
+                e if e.kind() == io::ErrorKind::Interrupted => continue,
+                e => return Err(e),
+            }
+        }
+
+        if fds[0].revents != 0 {
+            if tls.read_tls(&mut public)? == 0 {
+                return Ok(());
+            }
+            let state = tls
+                .process_new_packets()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let mut data = vec![0; state.plaintext_bytes_to_read()];
+            tls.reader().read_exact(&mut data)?;
+            keep.write_all(&data)?;
+            if state.peer_has_closed() {
+                return Ok(());
+            }
+        }
+
+        if fds[1].revents != 0 {
+            match keep.read(&mut buf)? {
+                0 => {
+                    tls.send_close_notify();
+                    while tls.wants_write() {
+                        tls.write_tls(&mut public)?;
+                    }
+                    return Ok(());
+                }
+                n => tls.writer().write_all(&buf[..n])?,
+            }
+        }
+    }
+}
+
+/// Accepts the connections on `public` and relays each to the internal listen socket at `keep`
+fn serve(name: String, public: TcpListener, keep: SocketAddr, config: Arc<ServerConfig>) {
+    for stream in public.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                debug!("failed to accept connection on `{name}`: {e}");
+                continue;
+            }
+        };
+        let config = config.clone();
+        let name = name.clone();
+        thread::spawn(move || {
+            let res = ServerConnection::new(config)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                .and_then(|tls| Ok((tls, TcpStream::connect(keep)?)))
+                .and_then(|(tls, conn)| relay(tls, stream, conn));
+            if let Err(e) = res {
+                debug!("failed to relay connection on `{name}`: {e}");
+            }
+        });
+    }
+}
+
+/// Terminates TLS of the non-confidential listen sockets of the package config `conf`, if any,
+/// with the certificate chain and private key at `cert`, rewinds the config for the Keep and
+/// returns the internal listen sockets by name.
+pub fn open(
+    conf: Option<&mut File>,
+    cert: Option<(&Path, &Path)>,
+) -> Result<HashMap<String, TcpListener>> {
+    let conf = match conf {
+        Some(conf) => conf,
+        None => return Ok(HashMap::new()),
+    };
+    let mut buf = String::new();
+    conf.read_to_string(&mut buf)
+        .and_then(|_| conf.rewind())
+        .context("failed to read package config")
+        .classify(ErrorKind::Io)?;
+    let config: Config = toml::from_str(&buf)
+        .context("failed to parse package config")
+        .classify(ErrorKind::Config)?;
+
+    let mut listeners = HashMap::new();
+    let mut server = None;
+    for file in config.files {
+        let (name, addr, port) = match file {
+            FileConf::Listen(ListenFile::Tls {
+                name,
+                addr,
+                port,
+                confidential: false,
+            }) => (name.to_string(), addr, port),
+            _ => continue,
+        };
+        let config = match &server {
+            Some(config) => Arc::clone(config),
+            None => {
+                let (cert, key) = cert
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "`{name}` is not confidential, so the host terminates its TLS, \
+                             which requires `--offload-cert` and `--offload-key`"
+                        )
+                    })
+                    .classify(ErrorKind::Config)?;
+                let config = server_config(cert, key).classify(ErrorKind::Config)?;
+                server = Some(config.clone());
+                config
+            }
+        };
+
+        let public = TcpListener::bind((addr.as_str(), port))
+            .with_context(|| format!("failed to listen on `{addr}` port {port} for `{name}`"))
+            .classify(ErrorKind::Io)?;
+        let keep = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|keep| Ok((keep.local_addr()?, keep)))
+            .context("failed to create internal listen socket")
+            .classify(ErrorKind::Io)?;
+        info!("terminating TLS of `{name}` on the host and relaying the plaintext to the Keep");
+        listeners.insert(name.clone(), keep.1);
+        thread::Builder::new()
+            .name(format!("offload-{name}"))
+            .spawn(move || serve(name, public, keep.0, config))
+            .context("failed to spawn TLS offload thread")
+            .classify(ErrorKind::Io)?;
+    }
+    Ok(listeners)
+}
@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checks of the package config against the capabilities of the backend
+//!
+//! Before the Keep is built, the features requested by the package config are checked against
+//! the [`Capabilities`] of the backend and the options of the host. All requests, which cannot
+//! be satisfied, are reported at once, instead of the Keep failing on the first of them, possibly
+//! long after it started.
+
+use crate::backend::{Backend, Capabilities};
+
+use std::fs::File;
+use std::io::{Read, Seek};
+
+use anyhow::{bail, Context, Result};
+use enarx_config::{Config, File as FileConf, ListenFile};
+use enarx_error::{Classify, ErrorKind};
+
+/// Returns the problems of running `config` in a Keep of the backend `backend` with
+/// `capabilities`
+///
+/// `offload` is whether the host can terminate TLS of non-confidential listen sockets.
+fn problems(
+    config: &Config,
+    backend: &str,
+    capabilities: Capabilities,
+    offload: bool,
+) -> Vec<String> {
+    let mut problems = vec![];
+
+    match (config.limits.threads, capabilities.threads) {
+        (Some(threads), Some(0)) if threads > 0 => problems.push(format!(
+            "`threads = {threads}` in `[limits]`, but Keeps of the `{backend}` backend cannot spawn \
+             threads: remove it or pick another backend with `--backend`"
+        )),
+        (Some(threads), Some(max)) if threads > max => problems.push(format!(
+            "`threads = {threads}` in `[limits]`, but Keeps of the `{backend}` backend support at \
+             most {max} threads: lower it to {max}"
+        )),
+        _ => {}
+    }
+
+    if let (Some(size), Some(max)) = (config.limits.memory_size, capabilities.memory_size) {
+        if size > max {
+            problems.push(format!(
+                "`memory_size = {size}` in `[limits]`, but Keeps of the `{backend}` backend hold \
+                 at most {max} bytes of the workload: lower it to {max}"
+            ));
+        }
+    }
+
+    if !offload {
+        for file in &config.files {
+            if let FileConf::Listen(ListenFile::Tls {
+                name,
+                confidential: false,
+                ..
+            }) = file
+            {
+                problems.push(format!(
+                    "`{name}` is a listen socket with `confidential = false`, whose TLS the host \
+                     terminates: pass `--offload-cert` and `--offload-key` on a Unix host or \
+                     remove `confidential = false`"
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Checks the package config `conf`, if any, against the `capabilities` of `backend` and rewinds
+/// it for the Keep
+///
+/// `offload` is whether the host can terminate TLS of non-confidential listen sockets.
+pub fn check(
+    conf: Option<&mut File>,
+    backend: &dyn Backend,
+    capabilities: Capabilities,
+    offload: bool,
+) -> Result<()> {
+    let conf = match conf {
+        Some(conf) => conf,
+        None => return Ok(()),
+    };
+    let mut buf = String::new();
+    conf.read_to_string(&mut buf)
+        .and_then(|_| conf.rewind())
+        .context("failed to read package config")
+        .classify(ErrorKind::Io)?;
+    let config: Config = toml::from_str(&buf)
+        .context("failed to parse package config")
+        .classify(ErrorKind::Config)?;
+
+    let problems = problems(&config, backend.name(), capabilities, offload);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let report: String = problems
+        .iter()
+        .map(|problem| format!("\n  - {problem}"))
+        .collect();
+    bail!(
+        "the package config requests {} feature(s), which the `{}` backend or the host cannot \
+         provide:{report}",
+        problems.len(),
+        backend.name()
+    )
+    .classify(ErrorKind::Config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities() {
+        let config: Config = toml::from_str(
+            r#"
+            [limits]
+            memory_size = 4096
+            threads = 4
+
+            [[files]]
+            kind = "listen"
+            name = "public"
+            prot = "tls"
+            port = 443
+            confidential = false
+            "#,
+        )
+        .unwrap();
+
+        assert!(problems(&config, "nil", Capabilities::default(), true).is_empty());
+
+        let kvm = Capabilities {
+            threads: Some(0),
+            memory_size: None,
+        };
+        let kvm = problems(&config, "kvm", kvm, false);
+        assert_eq!(kvm.len(), 2);
+        assert!(kvm[0].contains("cannot spawn threads"));
+        assert!(kvm[1].contains("`public`"));
+
+        let sgx = Capabilities {
+            threads: Some(2),
+            memory_size: Some(1024),
+        };
+        let sgx = problems(&config, "sgx", sgx, true);
+        assert_eq!(sgx.len(), 2);
+        assert!(sgx[0].contains("at most 2 threads"));
+        assert!(sgx[1].contains("at most 1024 bytes"));
+    }
+}
@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Restoration of the settings of the controlling terminal
+//!
+//! The Keep changes the settings of the terminal connected to its standard I/O,
+//! e.g. to enable the raw mode for a TUI application. The settings are restored
+//! on exit, even if the Keep exits without restoring them itself.
+
+use std::mem::MaybeUninit;
+
+use once_cell::sync::OnceCell;
+use tracing::debug;
+
+/// The settings of the terminal on standard input before the Keep started
+static SAVED: OnceCell<libc::termios> = OnceCell::new();
+
+extern "C" fn restore() {
+    if let Some(termios) = SAVED.get() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) };
+    }
+}
+
+/// Saves the settings of the terminal on standard input, if any,
+/// and restores them on exit of the process
+pub fn restore_at_exit() {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+        return;
+    }
+
+    let mut termios = MaybeUninit::<libc::termios>::uninit();
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+        debug!(
+            "failed to get terminal settings: {}",
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+
+    if SAVED.set(unsafe { termios.assume_init() }).is_ok() {
+        unsafe { libc::atexit(restore) };
+    }
+}
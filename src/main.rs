@@ -8,15 +8,46 @@
 #![allow(elided_lifetimes_in_paths)]
 
 mod backend;
+#[cfg(unix)]
+mod cache;
 mod cli;
+#[cfg(unix)]
+mod control;
 mod drawbridge;
 mod exec;
 #[cfg(enarx_with_shim)]
 mod protobuf;
+mod secret;
 
 use clap::Parser;
+use enarx_error::{exit_code, ErrorKind};
+use serde_json::json;
 
-fn main() -> anyhow::Result<()> {
+/// Prints `err` in the requested format and returns the exit code for it
+fn report(err: &anyhow::Error, json: bool) -> i32 {
+    let code = exit_code(err);
+    if json {
+        let kind = ErrorKind::of(err).map(ErrorKind::name);
+        eprintln!(
+            "{}",
+            json!({
+                "error": {
+                    "kind": kind,
+                    "code": code,
+                    "message": format!("{err:#}"),
+                }
+            })
+        );
+    } else {
+        eprintln!("Error: {err:?}");
+    }
+    code
+}
+
+fn main() {
     let app = cli::Options::parse();
-    app.execute()
+    let json = app.json;
+    if let Err(e) = app.execute() {
+        std::process::exit(report(&e, json));
+    }
 }
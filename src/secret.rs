@@ -0,0 +1,440 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host-side secret providers
+//!
+//! Secrets are specified as `NAME=URI`, where the scheme of the URI selects the provider,
+//! which resolves the secret value at deploy time:
+//!
+//! - `env://VARIABLE` reads the environment variable `VARIABLE` of the host process
+//! - `file:///path/to/file` reads the contents of a file with trailing newlines stripped
+//! - `vault://mount/path#key` reads `key` from a HashiCorp Vault KV version 2 secret,
+//!   using `VAULT_ADDR` and `VAULT_TOKEN`
+//! - `aws://secret-id#key` reads a secret from AWS Secrets Manager, using the standard
+//!   `AWS_*` credential environment variables. If `key` is specified, the secret string
+//!   is parsed as a JSON object and the value of `key` is returned.
+//!
+//! The values are passed to the Keep in plaintext with the other arguments of the Keep, so they
+//! are not protected from the host, which resolves them anyway. Secrets, which must not be known
+//! to the host, are to be obtained by the workload after attestation, e.g. with a certificate of
+//! the Steward.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, ensure, Context};
+use once_cell::sync::Lazy;
+use ring::hmac;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// A secret to resolve on the host
+#[derive(Clone, Debug)]
+pub struct SecretSpec {
+    pub name: String,
+    pub uri: Url,
+}
+
+impl FromStr for SecretSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, uri) = s
+            .split_once('=')
+            .with_context(|| format!("Invalid secret specification `{s}`, expected `NAME=URI`"))?;
+        ensure!(!name.is_empty(), "Secret name must not be empty");
+        let uri = uri
+            .parse()
+            .with_context(|| format!("Failed to parse secret URI of `{name}`"))?;
+        Ok(Self {
+            name: name.into(),
+            uri,
+        })
+    }
+}
+
+/// A source of secret values
+pub trait Provider: Sync + Send {
+    /// The URI scheme handled by the provider
+    fn scheme(&self) -> &'static str;
+
+    /// Resolves the secret value referenced by `uri`
+    fn resolve(&self, uri: &Url) -> anyhow::Result<String>;
+}
+
+pub static PROVIDERS: Lazy<Vec<Box<dyn Provider>>> = Lazy::new(|| {
+    vec![
+        Box::new(EnvProvider),
+        Box::new(FileProvider),
+        Box::new(VaultProvider),
+        Box::new(AwsProvider),
+    ]
+});
+
+/// Resolves all secrets using the provider matching the scheme of their URI
+pub fn resolve(secrets: &[SecretSpec]) -> anyhow::Result<HashMap<String, String>> {
+    let mut values = HashMap::with_capacity(secrets.len());
+    for SecretSpec { name, uri } in secrets {
+        let provider = PROVIDERS
+            .iter()
+            .find(|p| p.scheme() == uri.scheme())
+            .ok_or_else(|| anyhow!("Unsupported secret provider `{}`", uri.scheme()))?;
+        let value = provider
+            .resolve(uri)
+            .with_context(|| format!("Failed to resolve secret `{name}`"))?;
+        ensure!(
+            values.insert(name.clone(), value).is_none(),
+            "Secret `{name}` specified more than once"
+        );
+    }
+    Ok(values)
+}
+
+/// Returns the location referenced by `uri` without the scheme and fragment
+fn location(uri: &Url) -> anyhow::Result<String> {
+    let location = format!("{}{}", uri.host_str().unwrap_or_default(), uri.path());
+    let location = location.trim_start_matches('/');
+    ensure!(!location.is_empty(), "Missing secret location in `{uri}`");
+    Ok(location.into())
+}
+
+/// Returns the key referenced by the fragment of `uri`
+fn key(uri: &Url) -> anyhow::Result<&str> {
+    uri.fragment()
+        .filter(|key| !key.is_empty())
+        .with_context(|| format!("Missing `#key` fragment in `{uri}`"))
+}
+
+/// Returns the string value of `key` in a JSON object
+fn json_key(obj: &Value, key: &str) -> anyhow::Result<String> {
+    match obj.get(key) {
+        Some(Value::String(value)) => Ok(value.clone()),
+        Some(..) => bail!("Value of key `{key}` is not a string"),
+        None => bail!("Key `{key}` not found"),
+    }
+}
+
+fn var(name: &str) -> anyhow::Result<String> {
+    env::var(name).with_context(|| format!("Failed to read environment variable `{name}`"))
+}
+
+struct EnvProvider;
+
+impl Provider for EnvProvider {
+    fn scheme(&self) -> &'static str {
+        "env"
+    }
+
+    fn resolve(&self, uri: &Url) -> anyhow::Result<String> {
+        var(&location(uri)?)
+    }
+}
+
+struct FileProvider;
+
+impl Provider for FileProvider {
+    fn scheme(&self) -> &'static str {
+        "file"
+    }
+
+    fn resolve(&self, uri: &Url) -> anyhow::Result<String> {
+        let path = uri
+            .to_file_path()
+            .map_err(|()| anyhow!("Failed to parse file path from URL `{uri}`"))?;
+        let value = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read `{}`", path.display()))?;
+        Ok(value.trim_end_matches(&['\r', '\n'][..]).into())
+    }
+}
+
+struct VaultProvider;
+
+impl Provider for VaultProvider {
+    fn scheme(&self) -> &'static str {
+        "vault"
+    }
+
+    fn resolve(&self, uri: &Url) -> anyhow::Result<String> {
+        let addr = var("VAULT_ADDR")?;
+        let token = var("VAULT_TOKEN")?;
+
+        let location = location(uri)?;
+        let (mount, path) = location
+            .split_once('/')
+            .with_context(|| format!("Expected `vault://mount/path#key`, got `{uri}`"))?;
+        let url = format!("{}/v1/{mount}/data/{path}", addr.trim_end_matches('/'));
+
+        let res: Value = ureq::get(&url)
+            .set("X-Vault-Token", &token)
+            .call()
+            .with_context(|| format!("Failed to query Vault at `{url}`"))
+            .and_then(|res| {
+                serde_json::from_reader(res.into_reader())
+                    .context("Failed to decode Vault response")
+            })?;
+        let data = res
+            .pointer("/data/data")
+            .context("Vault response does not contain secret data")?;
+        json_key(data, key(uri)?)
+    }
+}
+
+struct AwsProvider;
+
+impl AwsProvider {
+    const SERVICE: &'static str = "secretsmanager";
+    const TARGET: &'static str = "secretsmanager.GetSecretValue";
+    const CONTENT_TYPE: &'static str = "application/x-amz-json-1.1";
+}
+
+impl Provider for AwsProvider {
+    fn scheme(&self) -> &'static str {
+        "aws"
+    }
+
+    fn resolve(&self, uri: &Url) -> anyhow::Result<String> {
+        let access_key = var("AWS_ACCESS_KEY_ID")?;
+        let secret_key = var("AWS_SECRET_ACCESS_KEY")?;
+        let session_token = env::var("AWS_SESSION_TOKEN").ok();
+        let region = var("AWS_REGION").or_else(|_| var("AWS_DEFAULT_REGION"))?;
+
+        let host = format!("{}.{region}.amazonaws.com", Self::SERVICE);
+        let body = serde_json::json!({ "SecretId": location(uri)? }).to_string();
+        let time = aws_timestamp(SystemTime::now());
+        let authorization = aws_authorization(
+            &access_key,
+            &secret_key,
+            &region,
+            Self::SERVICE,
+            &time,
+            &AwsRequest {
+                method: "POST",
+                query: "",
+                headers: &[
+                    ("content-type", Self::CONTENT_TYPE),
+                    ("host", &host),
+                    ("x-amz-date", &time),
+                    ("x-amz-target", Self::TARGET),
+                ],
+                body: body.as_bytes(),
+            },
+        );
+
+        let mut req = ureq::post(&format!("https://{host}/"))
+            .set("Content-Type", Self::CONTENT_TYPE)
+            .set("X-Amz-Date", &time)
+            .set("X-Amz-Target", Self::TARGET)
+            .set("Authorization", &authorization);
+        if let Some(token) = session_token {
+            req = req.set("X-Amz-Security-Token", &token);
+        }
+        let res: Value = req
+            .send_string(&body)
+            .context("Failed to query AWS Secrets Manager")
+            .and_then(|res| {
+                serde_json::from_reader(res.into_reader())
+                    .context("Failed to decode AWS Secrets Manager response")
+            })?;
+
+        let secret = json_key(&res, "SecretString")?;
+        match uri.fragment().filter(|key| !key.is_empty()) {
+            None => Ok(secret),
+            Some(key) => {
+                let obj = serde_json::from_str(&secret)
+                    .context("Failed to parse secret string as JSON")?;
+                json_key(&obj, key)
+            }
+        }
+    }
+}
+
+/// Returns the AWS signature version 4 timestamp of `now`
+fn aws_timestamp(now: SystemTime) -> String {
+    let secs = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Convert days since the epoch to a civil date
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Request to the root path of an AWS service
+struct AwsRequest<'a> {
+    method: &'a str,
+    /// Canonical query string
+    query: &'a str,
+    /// Lowercase names and trimmed values of the signed headers, sorted by name
+    headers: &'a [(&'a str, &'a str)],
+    body: &'a [u8],
+}
+
+/// Returns the AWS signature version 4 `Authorization` header of `request` to `service` in
+/// `region` at the timestamp `time`
+fn aws_authorization(
+    access_key: &str,
+    secret_key: &str,
+    region: &str,
+    service: &str,
+    time: &str,
+    request: &AwsRequest<'_>,
+) -> String {
+    let date = &time[..8];
+    let headers: String = request
+        .headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{value}\n"))
+        .collect();
+    let signed_headers = request
+        .headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "{}\n/\n{}\n{headers}\n{signed_headers}\n{}",
+        request.method,
+        request.query,
+        hex::encode(Sha256::digest(request.body)),
+    );
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{time}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let sign = |key: &[u8], data: &str| {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+    };
+    let key = sign(format!("AWS4{secret_key}").as_bytes(), date);
+    let key = sign(key.as_ref(), region);
+    let key = sign(key.as_ref(), service);
+    let key = sign(key.as_ref(), "aws4_request");
+    let signature = hex::encode(sign(key.as_ref(), &string_to_sign));
+
+    format!("AWS4-HMAC-SHA256 Credential={access_key}/{scope}, SignedHeaders={signed_headers}, Signature={signature}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_spec() {
+        let spec: SecretSpec = "db_pass=vault://kv/app#password".parse().unwrap();
+        assert_eq!(spec.name, "db_pass");
+        assert_eq!(location(&spec.uri).unwrap(), "kv/app");
+        assert_eq!(key(&spec.uri).unwrap(), "password");
+
+        assert!("db_pass".parse::<SecretSpec>().is_err());
+        assert!("=env://FOO".parse::<SecretSpec>().is_err());
+        assert!("db_pass=not a uri".parse::<SecretSpec>().is_err());
+    }
+
+    #[test]
+    fn resolve_local() {
+        env::set_var("ENARX_TEST_SECRET", "s3cr3t");
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "from-file").unwrap();
+        let file_uri = Url::from_file_path(file.path()).unwrap();
+
+        let secrets = [
+            "a=env://ENARX_TEST_SECRET".parse().unwrap(),
+            format!("b={file_uri}").parse().unwrap(),
+        ];
+        let values = resolve(&secrets).unwrap();
+        assert_eq!(values["a"], "s3cr3t");
+        assert_eq!(values["b"], "from-file");
+
+        assert!(resolve(&["a=unknown://foo".parse().unwrap()]).is_err());
+        assert!(resolve(&[secrets[0].clone(), secrets[0].clone()]).is_err());
+    }
+
+    #[test]
+    fn aws_civil_date() {
+        let timestamp = |secs| aws_timestamp(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(timestamp(1_440_938_160), "20150830T123600Z");
+        assert_eq!(timestamp(0), "19700101T000000Z");
+        assert_eq!(timestamp(951_782_400), "20000229T000000Z");
+        assert_eq!(timestamp(1_483_228_799), "20161231T235959Z");
+        assert_eq!(timestamp(1_709_164_800), "20240229T000000Z");
+        assert_eq!(timestamp(4_107_542_400), "21000301T000000Z");
+    }
+
+    /// Test vectors of the AWS signature version 4 documentation and test suite
+    #[test]
+    fn aws_signature() {
+        const ACCESS_KEY: &str = "AKIDEXAMPLE";
+        const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+        const TIME: &str = "20150830T123600Z";
+
+        // get-vanilla
+        let authorization = aws_authorization(
+            ACCESS_KEY,
+            SECRET_KEY,
+            "us-east-1",
+            "service",
+            TIME,
+            &AwsRequest {
+                method: "GET",
+                query: "",
+                headers: &[("host", "example.amazonaws.com"), ("x-amz-date", TIME)],
+                body: b"",
+            },
+        );
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+
+        // IAM ListUsers
+        let authorization = aws_authorization(
+            ACCESS_KEY,
+            SECRET_KEY,
+            "us-east-1",
+            "iam",
+            TIME,
+            &AwsRequest {
+                method: "GET",
+                query: "Action=ListUsers&Version=2010-05-08",
+                headers: &[
+                    (
+                        "content-type",
+                        "application/x-www-form-urlencoded; charset=utf-8",
+                    ),
+                    ("host", "iam.amazonaws.com"),
+                    ("x-amz-date", TIME),
+                ],
+                body: b"",
+            },
+        );
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, SignedHeaders=content-type;host;x-amz-date, Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+}
@@ -1,6 +1,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{assert_eq_slices, enarx, is_nil, is_sev, is_sgx, run_test, run_test_signed};
+use super::{
+    assert_eq_slices, check_output, enarx, is_nil, is_sev, is_sgx, run_test, run_test_signed,
+};
 
 use std::ffi::OsStr;
 use std::fs;
@@ -243,6 +245,31 @@ fn memspike() {
     run_test(bin, 0, None, None, None);
 }
 
+#[test]
+#[serial]
+#[cfg_attr(not(host_can_test_sgx), ignore = "Backend does not support SGX")]
+fn memspike_static_heap() {
+    if !is_sgx() {
+        eprintln!("SGX backend is disabled, ignoring");
+        return;
+    }
+
+    // Force the static heap of CPUs without EDMM
+    let bin = env!("CARGO_BIN_FILE_ENARX_EXEC_TESTS_memspike");
+    let output = enarx(
+        |cmd| {
+            cmd.env("ENARX_SGX_EDMM", "0").args(vec![
+                OsStr::new("unstable"),
+                OsStr::new("exec"),
+                OsStr::new("--unsigned"),
+                OsStr::new(bin),
+            ])
+        },
+        None,
+    );
+    check_output(&output, 0, None, None);
+}
+
 #[test]
 #[serial]
 fn memory_stress_test() {